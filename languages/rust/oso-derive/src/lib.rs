@@ -8,6 +8,7 @@ use syn::{
 enum OsoAttribute {
     ClassName { name: String },
     Attribute,
+    Sensitive,
 }
 
 fn get_single_segment(path: &Path) -> Option<String> {
@@ -32,6 +33,9 @@ fn get_nested_attr(nested: NestedMeta, oso_attrs: &mut Vec<OsoAttribute>) {
                         Some(ref seg) if seg == "attribute" => {
                             oso_attrs.push(OsoAttribute::Attribute);
                         }
+                        Some(ref seg) if seg == "sensitive" => {
+                            oso_attrs.push(OsoAttribute::Sensitive);
+                        }
                         _ => (),
                     };
                 }
@@ -78,7 +82,7 @@ pub fn derive_polar_class_impl(ts: TokenStream) -> TokenStream {
     let input = syn::parse_macro_input!(ts as syn::DeriveInput);
 
     let type_name = input.ident;
-    let mut class_name = type_name.to_string();
+    let mut class_name = None;
 
     let attrs = input.attrs;
     let mut oso_attrs = vec![];
@@ -87,8 +91,32 @@ pub fn derive_polar_class_impl(ts: TokenStream) -> TokenStream {
     }
     for oso_attr in oso_attrs {
         if let OsoAttribute::ClassName { name } = oso_attr {
-            class_name = name;
+            class_name = Some(name);
+        }
+    }
+
+    // Generic types are named after their type parameters by the class builder, so that
+    // each instantiation gets a class of its own.
+    let mut generics = input.generics;
+    let is_generic = generics.type_params().next().is_some();
+    let name = match class_name {
+        Some(ref name) => quote! { .name(#name) },
+        None if is_generic => quote! {},
+        None => {
+            let name = type_name.to_string();
+            quote! { .name(#name) }
         }
+    };
+    let class_name = class_name.unwrap_or_else(|| type_name.to_string());
+    let params: Vec<_> = generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect();
+    let where_clause = generics.make_where_clause();
+    for param in params {
+        where_clause
+            .predicates
+            .push(syn::parse_quote! { #param: Send + Sync + 'static });
     }
 
     let mut getters = vec![];
@@ -102,12 +130,25 @@ pub fn derive_polar_class_impl(ts: TokenStream) -> TokenStream {
                     for attr in field.attrs {
                         get_oso_attrs(attr, &mut oso_attrs);
                     }
-                    if oso_attrs.contains(&OsoAttribute::Attribute) {
+                    // Sensitive fields are attributes too.
+                    let sensitive = oso_attrs.contains(&OsoAttribute::Sensitive);
+                    if sensitive || oso_attrs.contains(&OsoAttribute::Attribute) {
+                        if is_generic {
+                            let ty = field.ty;
+                            where_clause
+                                .predicates
+                                .push(syn::parse_quote! { #ty: Clone + oso::ToPolar });
+                        }
                         let attr = field.ident.unwrap();
                         let name = attr.to_string();
                         getters.push(quote! {
-                            .add_attribute_getter(#name, |recv: &#type_name| recv.#attr.clone())
+                            .add_attribute_getter(#name, |recv: &Self| recv.#attr.clone())
                         });
+                        if sensitive {
+                            getters.push(quote! {
+                                .with_sensitive_attr(#name)
+                            });
+                        }
                     }
                 }
             }
@@ -115,14 +156,27 @@ pub fn derive_polar_class_impl(ts: TokenStream) -> TokenStream {
             Fields::Unit => {}
         },
         Data::Enum(DataEnum { variants, .. }) => {
+            if is_generic {
+                return quote_spanned! { type_name.span() => compile_error!("#[derive(PolarClass)] is not supported on generic enums."); }.into();
+            }
             for variant in variants {
                 match variant.fields {
                     Fields::Unit => {
+                        let mut oso_attrs = vec![];
+                        for attr in variant.attrs {
+                            get_oso_attrs(attr, &mut oso_attrs);
+                        }
                         let vident = variant.ident;
                         let vname = format!("{}::{}", class_name, vident);
-                        constants.push(quote! {
-                            .add_constant(#type_name::#vident, #vname)
-                        });
+                        if oso_attrs.contains(&OsoAttribute::Sensitive) {
+                            constants.push(quote! {
+                                .add_sensitive_constant(#type_name::#vident, #vname)
+                            });
+                        } else {
+                            constants.push(quote! {
+                                .add_constant(#type_name::#vident, #vname)
+                            });
+                        }
                     }
                     _ => {
                         return quote_spanned! { variant.ident.span() => compile_error!("#[derive(PolarClass)] is currently only supported on enums with unit variants."); }.into();
@@ -135,17 +189,18 @@ pub fn derive_polar_class_impl(ts: TokenStream) -> TokenStream {
         }
     }
 
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
     let result = quote! {
-        impl oso::PolarClass for #type_name {
-            fn get_polar_class_builder() -> oso::ClassBuilder<#type_name> {
+        impl #impl_generics oso::PolarClass for #type_name #ty_generics #where_clause {
+            fn get_polar_class_builder() -> oso::ClassBuilder<Self> {
                 oso::Class::builder()
-                    .name(#class_name)
+                    #name
                     #(#getters)*
                     #(#constants)*
             }
 
             fn get_polar_class() -> oso::Class {
-                let builder = #type_name::get_polar_class_builder();
+                let builder = Self::get_polar_class_builder();
                 builder.build()
             }
         }
@@ -0,0 +1,268 @@
+//! Records of authorization decisions, and replaying them against a policy, e.g., to find out
+//! during an incident whether a request was allowed because of the policy at the time, or to
+//! check which past decisions a policy change would reverse.
+use std::time::SystemTime;
+
+use crate::oso::KnowledgeBaseRef;
+use crate::{AnnotatedRule, Decision, Oso, PolarValue, ToPolar};
+
+/// An authorization decision, as made by [`Oso::decide`], with the request it was made for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecisionRecord {
+    pub actor: PolarValue,
+    pub action: PolarValue,
+    pub resource: PolarValue,
+    pub decision: Decision,
+    pub decided_at: SystemTime,
+    /// The [fingerprint](Oso::policy_fingerprint) of the policy that made the decision, if
+    /// known.
+    pub policy_fingerprint: Option<u64>,
+    /// The annotated rules that made the decision, as for [`Oso::explain`], if known.
+    pub annotated_rules: Vec<AnnotatedRule>,
+}
+
+impl DecisionRecord {
+    /// Record a decision made now, by an unknown version of the policy. Prefer
+    /// [`Oso::decide_and_record`], which knows it.
+    pub fn new<Actor, Action, Resource>(
+        actor: Actor,
+        action: Action,
+        resource: Resource,
+        decision: Decision,
+    ) -> Self
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+        Resource: ToPolar,
+    {
+        Self {
+            actor: actor.to_polar(),
+            action: action.to_polar(),
+            resource: resource.to_polar(),
+            decision,
+            decided_at: SystemTime::now(),
+            policy_fingerprint: None,
+            annotated_rules: vec![],
+        }
+    }
+
+    /// The record as a JSON object for an audit log, e.g.,
+    /// `{"actor": "alice", "action": "read", "resource": {"id": 1}, "decision": "allow",
+    /// "decided_at_ms": 1700000000000, "policy_fingerprint": "5c0f4be1a2e6d9f3",
+    /// "annotated_rules": [{"rule": "allow", "annotations": {"owner": "secteam"}}]}`. The
+    /// fingerprint is a hexadecimal string, or `null` if it isn't known.
+    ///
+    /// Application instances can't be written to JSON, so record requests with them by
+    /// converting them to values first, e.g., [`DynObject`](crate::DynObject)s.
+    #[cfg(feature = "serde_json")]
+    pub fn to_json(&self) -> crate::Result<serde_json::Value> {
+        use crate::FromPolar;
+        use serde_json::Value;
+
+        let value = |value: &PolarValue| {
+            Value::from_polar(value.clone()).map_err(|_| crate::OsoError::InvalidDecisionRecord {
+                message: format!("{:?} has no JSON representation", value),
+            })
+        };
+        Ok(serde_json::json!({
+            "actor": value(&self.actor)?,
+            "action": value(&self.action)?,
+            "resource": value(&self.resource)?,
+            "decision": decision_name(self.decision),
+            "decided_at_ms": crate::oso::to_unix_ms(self.decided_at),
+            "policy_fingerprint": self.policy_fingerprint.map(|f| format!("{:016x}", f)),
+            "annotated_rules": self
+                .annotated_rules
+                .iter()
+                .map(|rule| serde_json::json!({
+                    "rule": rule.rule,
+                    "annotations": rule.annotations,
+                }))
+                .collect::<Vec<_>>(),
+        }))
+    }
+
+    /// Read a record written by [`DecisionRecord::to_json`].
+    #[cfg(feature = "serde_json")]
+    pub fn from_json(json: &serde_json::Value) -> crate::Result<Self> {
+        use serde_json::Value;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let invalid = |message: &str| crate::OsoError::InvalidDecisionRecord {
+            message: message.to_owned(),
+        };
+        let field = |name: &str| {
+            json.get(name)
+                .cloned()
+                .map(ToPolar::to_polar)
+                .ok_or_else(|| invalid(&format!("missing `{}`", name)))
+        };
+        let decision = json
+            .get("decision")
+            .and_then(|d| d.as_str())
+            .and_then(decision_from_name)
+            .ok_or_else(|| invalid("`decision` must be allow, deny, or not_applicable"))?;
+        let decided_at = json
+            .get("decided_at_ms")
+            .and_then(|ms| ms.as_u64())
+            .ok_or_else(|| invalid("`decided_at_ms` must be a timestamp in milliseconds"))?;
+        let policy_fingerprint = match json.get("policy_fingerprint") {
+            None | Some(Value::Null) => None,
+            Some(fingerprint) => fingerprint
+                .as_str()
+                .and_then(|f| u64::from_str_radix(f, 16).ok())
+                .map(Some)
+                .ok_or_else(|| invalid("`policy_fingerprint` must be a hexadecimal string"))?,
+        };
+        let annotated_rule = |rule: &Value| -> Option<AnnotatedRule> {
+            let annotations = rule.get("annotations")?.as_object()?;
+            Some(AnnotatedRule {
+                rule: rule.get("rule")?.as_str()?.to_owned(),
+                annotations: annotations
+                    .iter()
+                    .map(|(name, value)| Some((name.clone(), value.as_str()?.to_owned())))
+                    .collect::<Option<_>>()?,
+            })
+        };
+        let annotated_rules = match json.get("annotated_rules") {
+            None | Some(Value::Null) => vec![],
+            Some(rules) => rules
+                .as_array()
+                .and_then(|rules| rules.iter().map(annotated_rule).collect())
+                .ok_or_else(|| {
+                    invalid("`annotated_rules` must be a list of rules and their annotations")
+                })?,
+        };
+        Ok(Self {
+            actor: field("actor")?,
+            action: field("action")?,
+            resource: field("resource")?,
+            decision,
+            decided_at: UNIX_EPOCH + Duration::from_millis(decided_at),
+            policy_fingerprint,
+            annotated_rules,
+        })
+    }
+}
+
+/// The name of `decision` in JSON, e.g., `allow`.
+#[cfg(feature = "serde_json")]
+pub(crate) fn decision_name(decision: Decision) -> &'static str {
+    match decision {
+        Decision::Allow => "allow",
+        Decision::Deny => "deny",
+        Decision::NotApplicable => "not_applicable",
+    }
+}
+
+#[cfg(feature = "serde_json")]
+pub(crate) fn decision_from_name(name: &str) -> Option<Decision> {
+    match name {
+        "allow" => Some(Decision::Allow),
+        "deny" => Some(Decision::Deny),
+        "not_applicable" => Some(Decision::NotApplicable),
+        _ => None,
+    }
+}
+
+/// The outcome of replaying a [`DecisionRecord`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Replay {
+    /// The decision that was recorded.
+    pub recorded: Decision,
+    /// The decision of the policy it was replayed against.
+    pub replayed: Decision,
+    /// The fingerprint of the policy that made the recorded decision, if known.
+    pub recorded_policy: Option<u64>,
+    /// The fingerprint of the policy it was replayed against.
+    pub replayed_policy: u64,
+}
+
+impl Replay {
+    /// Whether the policy decides differently than it did when the decision was recorded.
+    pub fn differs(&self) -> bool {
+        self.recorded != self.replayed
+    }
+
+    /// Whether the decision was replayed against a different policy than the one that made
+    /// it, or `None` if the recorded policy isn't known.
+    pub fn policy_changed(&self) -> Option<bool> {
+        self.recorded_policy
+            .map(|recorded| recorded != self.replayed_policy)
+    }
+}
+
+impl Oso {
+    /// Like [`Oso::decide`], but return a record of the decision for an audit log, with the
+    /// fingerprint of the policy and the annotated rules that made it.
+    pub fn decide_and_record<Actor, Action, Resource>(
+        &self,
+        actor: Actor,
+        action: Action,
+        resource: Resource,
+    ) -> crate::Result<DecisionRecord>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+        Resource: ToPolar,
+    {
+        let (actor, action, resource) = (actor.to_polar(), action.to_polar(), resource.to_polar());
+        // Take the fingerprint first: a policy loaded while deciding would make it stale.
+        let policy_fingerprint = self.policy_fingerprint();
+        let explanation = self.explain(actor.clone(), action.clone(), resource.clone())?;
+        Ok(DecisionRecord {
+            policy_fingerprint: Some(policy_fingerprint),
+            annotated_rules: explanation.annotated_rules,
+            ..DecisionRecord::new(actor, action, resource, explanation.decision)
+        })
+    }
+
+    /// Make the decision of `record` again, against the current policy.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use oso::{Decision, Oso};
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.set_max_policy_epochs(10);
+    /// oso.load_str(r#"allow("alice", "read", "doc");"#).unwrap();
+    /// let record = oso.decide_and_record("alice", "read", "doc").unwrap();
+    ///
+    /// # std::thread::sleep(std::time::Duration::from_millis(5));
+    /// oso.clear_rules().unwrap();
+    /// oso.load_str(r#"allow("bob", "read", "doc");"#).unwrap();
+    /// let replay = oso.replay(&record).unwrap();
+    /// assert!(replay.differs());
+    /// assert_eq!(replay.replayed, Decision::NotApplicable);
+    /// assert_eq!(replay.policy_changed(), Some(true));
+    /// assert!(!oso.replay_at(&record, record.decided_at).unwrap().differs());
+    /// ```
+    pub fn replay(&self, record: &DecisionRecord) -> crate::Result<Replay> {
+        self.replay_in(&self.inner.kb.clone(), record)
+    }
+
+    /// Make the decision of `record` again, against the policy that was loaded at `at`, e.g.,
+    /// `record.decided_at` to check that the policy at the time explains the decision.
+    ///
+    /// As for [`Oso::is_allowed_at`], only rules are versioned, and asking about a time
+    /// before the oldest policy epoch is an error.
+    pub fn replay_at(&self, record: &DecisionRecord, at: SystemTime) -> crate::Result<Replay> {
+        self.replay_in(&self.kb_at(at)?, record)
+    }
+
+    fn replay_in(&self, kb: &KnowledgeBaseRef, record: &DecisionRecord) -> crate::Result<Replay> {
+        let args = (
+            record.actor.clone(),
+            record.action.clone(),
+            record.resource.clone(),
+        );
+        let replayed_policy = kb.read().unwrap().fingerprint();
+        Ok(Replay {
+            recorded: record.decision,
+            replayed: self.decide_in(kb, &args)?,
+            recorded_policy: record.policy_fingerprint,
+            replayed_policy,
+        })
+    }
+}
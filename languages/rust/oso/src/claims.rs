@@ -0,0 +1,268 @@
+//! Actors for token-based authentication: the claims of a validated JWT or OIDC ID token.
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{ClassBuilder, PolarClass, PolarValue};
+
+/// A point in time, e.g., the expiry of a token, registered as the Polar class `DateTime`.
+///
+/// Polar can't compare application instances with `<` or `>`, so compare them with
+/// `is_before` and `is_after`, or compare their `timestamp`s, in seconds since the Unix epoch.
+/// `new DateTime(seconds)` is the time `seconds` after the epoch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime(SystemTime);
+
+impl DateTime {
+    pub fn now() -> Self {
+        Self(SystemTime::now())
+    }
+
+    /// The time `seconds` after the Unix epoch, e.g., a JWT `NumericDate`, or `None` if
+    /// `seconds` isn't finite or the time is out of the range of [`SystemTime`].
+    pub fn from_timestamp(seconds: f64) -> Option<Self> {
+        let offset = Duration::try_from_secs_f64(seconds.abs()).ok()?;
+        let time = if seconds < 0.0 {
+            UNIX_EPOCH.checked_sub(offset)
+        } else {
+            UNIX_EPOCH.checked_add(offset)
+        };
+        time.map(Self)
+    }
+
+    /// Whole seconds since the Unix epoch.
+    pub fn timestamp(&self) -> i64 {
+        match self.0.duration_since(UNIX_EPOCH) {
+            Ok(since) => since.as_secs() as i64,
+            Err(before) => -(before.duration().as_secs_f64().ceil() as i64),
+        }
+    }
+
+    pub fn is_past(&self) -> bool {
+        self.0 <= SystemTime::now()
+    }
+}
+
+impl From<SystemTime> for DateTime {
+    fn from(time: SystemTime) -> Self {
+        Self(time)
+    }
+}
+
+impl From<DateTime> for SystemTime {
+    fn from(time: DateTime) -> Self {
+        time.0
+    }
+}
+
+impl PolarClass for DateTime {
+    fn get_polar_class_builder() -> ClassBuilder<DateTime> {
+        ClassBuilder::<DateTime>::with_fallible_constructor(|seconds: i64| {
+            match DateTime::from_timestamp(seconds as f64) {
+                Some(time) => Ok(time),
+                None => lazy_error!(
+                    "{} seconds since the epoch is out of range for a DateTime",
+                    seconds
+                ),
+            }
+        })
+        .name("DateTime")
+        .with_equality_check()
+        .add_class_method("now", DateTime::now)
+        .add_method("timestamp", |time: &DateTime| time.timestamp())
+        .add_method("is_past", |time: &DateTime| time.is_past())
+        .add_method("is_before", |time: &DateTime, other: DateTime| {
+            *time < other
+        })
+        .add_method("is_after", |time: &DateTime, other: DateTime| *time > other)
+    }
+}
+
+/// The claims of a validated JWT, registered as the Polar class `Claims`, so that a token can
+/// be passed to Polar as the actor without converting it to an application type first.
+///
+/// Validate the token's signature and expiry before converting its claims: `Claims` trusts
+/// them as they are.
+///
+/// In policies, the registered claims are attributes: `sub` and `iss` are optional strings,
+/// `aud` and `scopes` are lists of strings, and `expires_at`, `issued_at` and `not_before`
+/// are optional `DateTime`s. Other claims are read with `get`, which looks into nested claims
+/// with dotted paths, e.g., `claims.get("org.id")`, and returns an `Option`.
+///
+/// # Examples
+///
+/// ```
+/// use maplit::hashmap;
+/// use oso::{Claims, DateTime, Oso, PolarClass, PolarValue};
+///
+/// let mut oso = Oso::new();
+/// oso.register_class(Claims::get_polar_class()).unwrap();
+/// oso.register_class(DateTime::get_polar_class()).unwrap();
+/// oso.load_str(
+///     r#"allow(claims: Claims, "read", org) if
+///            claims.has_scope("read") and
+///            claims.get("org.id").unwrap() = org;"#,
+/// )
+/// .unwrap();
+///
+/// let claims = Claims::new(hashmap! {
+///     "sub".to_owned() => PolarValue::String("alice".to_owned()),
+///     "scope".to_owned() => PolarValue::String("read write".to_owned()),
+///     "org".to_owned() => PolarValue::Map(hashmap! {
+///         "id".to_owned() => PolarValue::String("acme".to_owned()),
+///     }),
+/// });
+/// assert!(oso.is_allowed(claims.clone(), "read", "acme").unwrap());
+/// assert!(!oso.is_allowed(claims, "read", "initech").unwrap());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Claims {
+    claims: HashMap<String, PolarValue>,
+}
+
+impl Claims {
+    pub fn new(claims: HashMap<String, PolarValue>) -> Self {
+        Self { claims }
+    }
+
+    /// The claim `path`, or, if there's no claim with that name, the claim nested in objects
+    /// at the dot-separated `path`, e.g., `org.id`. Claim names may have dots of their own,
+    /// e.g., `https://example.com/org.id`.
+    pub fn get(&self, path: &str) -> Option<&PolarValue> {
+        lookup(&self.claims, path)
+    }
+
+    fn string(&self, name: &str) -> Option<String> {
+        match self.claims.get(name) {
+            Some(PolarValue::String(s)) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    fn date(&self, name: &str) -> Option<DateTime> {
+        match self.claims.get(name) {
+            Some(PolarValue::Integer(i)) => DateTime::from_timestamp(*i as f64),
+            Some(PolarValue::Float(f)) => DateTime::from_timestamp(*f),
+            _ => None,
+        }
+    }
+
+    pub fn subject(&self) -> Option<String> {
+        self.string("sub")
+    }
+
+    pub fn issuer(&self) -> Option<String> {
+        self.string("iss")
+    }
+
+    /// The `aud` claim, which is either a string or a list of strings.
+    pub fn audiences(&self) -> Vec<String> {
+        strings(self.claims.get("aud"), false)
+    }
+
+    /// The OAuth 2.0 `scope` claim, a space-separated string, or else the `scp` claim that
+    /// some providers use instead, a list or a space-separated string.
+    pub fn scopes(&self) -> Vec<String> {
+        match self.claims.get("scope") {
+            Some(scope) => strings(Some(scope), true),
+            None => strings(self.claims.get("scp"), true),
+        }
+    }
+
+    pub fn has_audience(&self, audience: &str) -> bool {
+        self.audiences().iter().any(|aud| aud == audience)
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes().iter().any(|s| s == scope)
+    }
+
+    pub fn expires_at(&self) -> Option<DateTime> {
+        self.date("exp")
+    }
+
+    pub fn issued_at(&self) -> Option<DateTime> {
+        self.date("iat")
+    }
+
+    pub fn not_before(&self) -> Option<DateTime> {
+        self.date("nbf")
+    }
+
+    /// Whether the token has expired. Tokens without an `exp` claim never expire, nor do tokens
+    /// whose `exp` claim is out of range.
+    pub fn is_expired(&self) -> bool {
+        self.expires_at().is_some_and(|exp| exp.is_past())
+    }
+}
+
+fn lookup<'a>(fields: &'a HashMap<String, PolarValue>, path: &str) -> Option<&'a PolarValue> {
+    if let Some(value) = fields.get(path) {
+        return Some(value);
+    }
+    path.match_indices('.')
+        .find_map(|(i, _)| match fields.get(&path[..i]) {
+            Some(PolarValue::Map(nested)) => lookup(nested, &path[i + 1..]),
+            _ => None,
+        })
+}
+
+/// The strings of a string or list claim, splitting strings on spaces if `split`.
+fn strings(value: Option<&PolarValue>, split: bool) -> Vec<String> {
+    match value {
+        Some(PolarValue::String(s)) if split => s.split_whitespace().map(str::to_owned).collect(),
+        Some(PolarValue::String(s)) => vec![s.clone()],
+        Some(PolarValue::List(values)) => values
+            .iter()
+            .filter_map(|value| match value {
+                PolarValue::String(s) => Some(s.clone()),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+impl From<HashMap<String, PolarValue>> for Claims {
+    fn from(claims: HashMap<String, PolarValue>) -> Self {
+        Self::new(claims)
+    }
+}
+
+/// E.g., the claims decoded by the `jsonwebtoken` crate into a `serde_json::Map`.
+#[cfg(feature = "serde_json")]
+impl From<serde_json::Map<String, serde_json::Value>> for Claims {
+    fn from(claims: serde_json::Map<String, serde_json::Value>) -> Self {
+        use crate::ToPolar;
+
+        Self::new(
+            claims
+                .into_iter()
+                .map(|(name, value)| (name, value.to_polar()))
+                .collect(),
+        )
+    }
+}
+
+impl PolarClass for Claims {
+    fn get_polar_class_builder() -> ClassBuilder<Claims> {
+        ClassBuilder::<Claims>::with_default()
+            .name("Claims")
+            .add_attribute_getter("sub", Claims::subject)
+            .add_attribute_getter("iss", Claims::issuer)
+            .add_attribute_getter("aud", Claims::audiences)
+            .add_attribute_getter("scopes", Claims::scopes)
+            .add_attribute_getter("expires_at", Claims::expires_at)
+            .add_attribute_getter("issued_at", Claims::issued_at)
+            .add_attribute_getter("not_before", Claims::not_before)
+            .add_method("get", |claims: &Claims, path: String| {
+                claims.get(&path).cloned()
+            })
+            .add_method("has_audience", |claims: &Claims, audience: String| {
+                claims.has_audience(&audience)
+            })
+            .add_method("has_scope", |claims: &Claims, scope: String| {
+                claims.has_scope(&scope)
+            })
+            .add_method("is_expired", Claims::is_expired)
+    }
+}
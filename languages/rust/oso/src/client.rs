@@ -0,0 +1,264 @@
+//! Evaluate authorization requests with a remote [`Server`](crate::server::Server).
+use std::io::{self, BufReader, BufWriter};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use polar_core::filter::Filter;
+use serde_json::{json, Value};
+
+use crate::http::{read_response, write_request, DEADLINE_HEADER};
+use crate::{Enforcer, FromPolar, Oso, OsoError, PolarValue, ToPolar};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_MAX_IDLE_CONNECTIONS: usize = 8;
+
+type Connection = BufReader<TcpStream>;
+
+/// An [`Enforcer`] that asks the authorization [`Server`](crate::server::Server) for decisions.
+///
+/// Actors, actions, and resources are sent to the server as JSON, so they must be values
+/// with a JSON representation (not application instances). The server's resolvers turn them
+/// into the values passed to the policy.
+///
+/// Connections are reused between requests. Each request must complete within the client's
+/// timeout, which is also sent to the server so that it can skip requests the client has
+/// already given up on.
+///
+/// When the server can't be reached, requests may be answered by a local fallback [`Oso`]
+/// instead. The fallback is passed the values sent to the server as-is, like the server's
+/// default resolvers.
+///
+/// # Examples
+///
+/// ```no_run
+/// use oso::client::OsoClient;
+/// use oso::{Enforcer, ToPolar};
+///
+/// let client = OsoClient::new("127.0.0.1:8180").unwrap();
+/// let allowed = client
+///     .authorize("alice".to_polar(), "read".to_polar(), "repo".to_polar())
+///     .unwrap();
+/// ```
+pub struct OsoClient {
+    addrs: Vec<SocketAddr>,
+    idle: Mutex<Vec<Connection>>,
+    max_idle_connections: usize,
+    timeout: Duration,
+    fallback: Option<Oso>,
+}
+
+impl OsoClient {
+    /// Create a client for the server at `addr`. No connection is made until the first
+    /// request.
+    pub fn new<A: ToSocketAddrs>(addr: A) -> crate::Result<Self> {
+        Ok(Self {
+            addrs: addr.to_socket_addrs()?.collect(),
+            idle: Mutex::new(vec![]),
+            max_idle_connections: DEFAULT_MAX_IDLE_CONNECTIONS,
+            timeout: DEFAULT_TIMEOUT,
+            fallback: None,
+        })
+    }
+
+    /// Set how long each request may take, including connecting to the server.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set how many idle connections are kept open for reuse.
+    pub fn with_max_idle_connections(mut self, max_idle_connections: usize) -> Self {
+        self.max_idle_connections = max_idle_connections;
+        self
+    }
+
+    /// Answer requests with `oso` when the server can't be reached.
+    pub fn with_fallback(mut self, oso: Oso) -> Self {
+        self.fallback = Some(oso);
+        self
+    }
+
+    fn fallback(&self) -> &Oso {
+        self.fallback.as_ref().unwrap()
+    }
+
+    /// Send `body` to the server & parse its response.
+    fn post(&self, path: &str, body: Value) -> Result<Value, RequestError> {
+        let response = self
+            .exchange(path, &body.to_string())
+            .map_err(RequestError::Unreachable)?;
+
+        let status = response.status;
+        let mut body: Value = serde_json::from_slice(&response.body)
+            .map_err(|_| RequestError::Oso(Box::new(malformed_response(status))))?;
+        if status == 200 {
+            Ok(body)
+        } else {
+            let message = match body.get_mut("error").map(Value::take) {
+                Some(Value::String(message)) => message,
+                _ => body.to_string(),
+            };
+            Err(RequestError::Oso(Box::new(OsoError::ServerError {
+                status,
+                message,
+            })))
+        }
+    }
+
+    /// Send `body` to the server, reusing an idle connection if there is one.
+    fn exchange(&self, path: &str, body: &str) -> io::Result<crate::http::Response> {
+        let deadline = Instant::now() + self.timeout;
+        let idle = self.idle.lock().unwrap().pop();
+        match idle {
+            // The server may have closed an idle connection, so retry on a new one.
+            Some(connection) => self
+                .send(connection, path, body, deadline)
+                .or_else(|_| self.send(self.connect(deadline)?, path, body, deadline)),
+            None => self.send(self.connect(deadline)?, path, body, deadline),
+        }
+    }
+
+    fn connect(&self, deadline: Instant) -> io::Result<Connection> {
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no server address");
+        for addr in &self.addrs {
+            match TcpStream::connect_timeout(addr, remaining(deadline)?) {
+                Ok(stream) => return Ok(BufReader::new(stream)),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    fn send(
+        &self,
+        mut connection: Connection,
+        path: &str,
+        body: &str,
+        deadline: Instant,
+    ) -> io::Result<crate::http::Response> {
+        let remaining = remaining(deadline)?;
+        let stream = connection.get_ref();
+        stream.set_read_timeout(Some(remaining))?;
+        stream.set_write_timeout(Some(remaining))?;
+
+        let headers = [(DEADLINE_HEADER, remaining.as_millis().to_string())];
+        write_request(&mut BufWriter::new(stream), path, &headers, body.as_bytes())?;
+        let response = read_response(&mut connection)?;
+
+        if response.keep_alive() {
+            let mut idle = self.idle.lock().unwrap();
+            if idle.len() < self.max_idle_connections {
+                idle.push(connection);
+            }
+        }
+        Ok(response)
+    }
+}
+
+impl Enforcer for OsoClient {
+    fn authorize(
+        &self,
+        actor: PolarValue,
+        action: PolarValue,
+        resource: PolarValue,
+    ) -> crate::Result<bool> {
+        let body = json!({
+            "actor": to_json(&actor)?,
+            "action": to_json(&action)?,
+            "resource": to_json(&resource)?,
+        });
+        match self.post("/authorize", body) {
+            Err(RequestError::Unreachable(_)) if self.fallback.is_some() => {
+                self.fallback().authorize(actor, action, resource)
+            }
+            result => match result?.get("allowed") {
+                Some(Value::Bool(allowed)) => Ok(*allowed),
+                _ => Err(malformed_response(200)),
+            },
+        }
+    }
+
+    fn authorized_actions(
+        &self,
+        actor: PolarValue,
+        resource: PolarValue,
+    ) -> crate::Result<Vec<PolarValue>> {
+        let body = json!({ "actor": to_json(&actor)?, "resource": to_json(&resource)? });
+        match self.post("/authorized_actions", body) {
+            Err(RequestError::Unreachable(_)) if self.fallback.is_some() => {
+                self.fallback().authorized_actions(actor, resource)
+            }
+            result => match result?.get_mut("actions").map(Value::take) {
+                Some(Value::Array(actions)) => Ok(actions
+                    .into_iter()
+                    .map(|action| match action {
+                        Value::String(any) if any == "*" => {
+                            PolarValue::Variable("action".to_owned())
+                        }
+                        action => action.to_polar(),
+                    })
+                    .collect()),
+                _ => Err(malformed_response(200)),
+            },
+        }
+    }
+
+    fn authorized_query(
+        &self,
+        actor: PolarValue,
+        action: PolarValue,
+        resource_type: &str,
+    ) -> crate::Result<Filter> {
+        let body = json!({
+            "actor": to_json(&actor)?,
+            "action": to_json(&action)?,
+            "resource_type": resource_type,
+        });
+        match self.post("/authorized_query", body) {
+            Err(RequestError::Unreachable(_)) if self.fallback.is_some() => self
+                .fallback()
+                .authorized_query(actor, action, resource_type),
+            result => {
+                let filter = result?.get_mut("filter").map(Value::take);
+                filter
+                    .and_then(|filter| serde_json::from_value(filter).ok())
+                    .ok_or_else(|| malformed_response(200))
+            }
+        }
+    }
+}
+
+/// Return the time left until `deadline`, or a timeout error if it has passed.
+fn remaining(deadline: Instant) -> io::Result<Duration> {
+    match deadline.checked_duration_since(Instant::now()) {
+        Some(remaining) if !remaining.is_zero() => Ok(remaining),
+        _ => Err(io::Error::new(io::ErrorKind::TimedOut, "deadline exceeded")),
+    }
+}
+
+fn to_json(value: &PolarValue) -> crate::Result<Value> {
+    Value::from_polar(value.clone())
+}
+
+fn malformed_response(status: u16) -> OsoError {
+    OsoError::ServerError {
+        status,
+        message: "malformed response".to_owned(),
+    }
+}
+
+/// Whether a request failed to reach the server, or the server failed to answer it.
+enum RequestError {
+    Unreachable(io::Error),
+    Oso(Box<OsoError>),
+}
+
+impl From<RequestError> for OsoError {
+    fn from(err: RequestError) -> Self {
+        match err {
+            RequestError::Unreachable(err) => OsoError::Io(err),
+            RequestError::Oso(err) => *err,
+        }
+    }
+}
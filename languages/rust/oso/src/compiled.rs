@@ -0,0 +1,154 @@
+//! Authorization checks compiled ahead of time, for the hottest `(action, resource type)`
+//! pairs: the policy is partially evaluated once, and each check only evaluates what's left.
+use std::any::TypeId;
+use std::marker::PhantomData;
+
+use polar_core::events::ResultEvent;
+use polar_core::terms::{Operation, Operator, Symbol, Term, Value};
+
+use crate::query::Query;
+use crate::{Oso, PolarValue, ToPolar};
+
+/// A check of whether actors of type `Actor` may perform an action on resources of type
+/// `Resource`, made by [`Oso::compile_check`].
+///
+/// The check only evaluates the conditions that partially evaluating the policy left on the
+/// actor and the resource, so it doesn't resolve rules again. It reflects the policy as it was
+/// compiled: compile it again after loading a different policy, e.g., when
+/// [`CompiledCheck::policy_fingerprint`] no longer matches [`Oso::policy_fingerprint`].
+pub struct CompiledCheck<Actor, Resource> {
+    oso: Oso,
+    query: Term,
+    policy_fingerprint: u64,
+    types: PhantomData<fn(Actor, Resource)>,
+}
+
+impl<Actor, Resource> Clone for CompiledCheck<Actor, Resource> {
+    fn clone(&self) -> Self {
+        Self {
+            oso: self.oso.clone(),
+            query: self.query.clone(),
+            policy_fingerprint: self.policy_fingerprint,
+            types: PhantomData,
+        }
+    }
+}
+
+impl<Actor: ToPolar, Resource: ToPolar> CompiledCheck<Actor, Resource> {
+    /// Whether `actor` may perform the action on `resource`, i.e., whether [`Oso::decide`]
+    /// would allow it: an `allow` rule matches, and no `deny` rule does.
+    pub fn check(&self, actor: Actor, resource: Resource) -> crate::Result<bool> {
+        let mut host = self.oso.host.clone();
+        let mut query = self
+            .oso
+            .inner
+            .new_query_from_term(self.query.clone(), false);
+        query.bind(Symbol::new("actor"), actor.to_polar().to_term(&mut host))?;
+        query.bind(
+            Symbol::new("resource"),
+            resource.to_polar().to_term(&mut host),
+        )?;
+        Query::new(query, host)
+            .next()
+            .transpose()
+            .map(|result| result.is_some())
+    }
+
+    /// The [fingerprint](Oso::policy_fingerprint) of the policy the check was compiled from.
+    pub fn policy_fingerprint(&self) -> u64 {
+        self.policy_fingerprint
+    }
+}
+
+impl Oso {
+    /// Compile the check of whether actors of type `Actor` may perform `action` on resources
+    /// of type `Resource`, by partially evaluating the policy's `allow` and `deny` rules once.
+    /// Both types must be registered.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use oso::{Oso, PolarClass};
+    ///
+    /// #[derive(Clone, PolarClass)]
+    /// struct User {
+    ///     #[polar(attribute)]
+    ///     id: i64,
+    /// }
+    ///
+    /// #[derive(Clone, PolarClass)]
+    /// struct Repo {
+    ///     #[polar(attribute)]
+    ///     owner_id: i64,
+    /// }
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.register_class(User::get_polar_class()).unwrap();
+    /// oso.register_class(Repo::get_polar_class()).unwrap();
+    /// oso.load_str(r#"allow(user: User, "read", repo: Repo) if repo.owner_id = user.id;"#)
+    ///     .unwrap();
+    ///
+    /// let can_read = oso.compile_check::<User, Repo, _>("read").unwrap();
+    /// assert!(can_read.check(User { id: 1 }, Repo { owner_id: 1 }).unwrap());
+    /// assert!(!can_read.check(User { id: 2 }, Repo { owner_id: 1 }).unwrap());
+    /// ```
+    pub fn compile_check<Actor, Resource, A>(
+        &self,
+        action: A,
+    ) -> crate::Result<CompiledCheck<Actor, Resource>>
+    where
+        Actor: 'static,
+        Resource: 'static,
+        A: ToPolar,
+    {
+        let actor_type = self
+            .host
+            .get_class_by_type_id(TypeId::of::<Actor>())?
+            .name
+            .clone();
+        let resource_type = self
+            .host
+            .get_class_by_type_id(TypeId::of::<Resource>())?
+            .name
+            .clone();
+        let policy_fingerprint = self.policy_fingerprint();
+        let action = action.to_polar();
+        let residual = |rule: &str| -> crate::Result<Term> {
+            let (partials, _) = self.partial_query(
+                PolarValue::Variable("actor".to_owned()),
+                action.clone(),
+                &resource_type,
+                Some(&actor_type),
+                |call| call(rule),
+            )?;
+            let results = partials
+                .into_iter()
+                .map(|ResultEvent { bindings, .. }| bindings)
+                .collect::<Vec<_>>();
+            Ok(polar_core::residual_query(
+                &results,
+                &[Symbol::new("actor"), Symbol::new("resource")],
+            ))
+        };
+
+        // Partially evaluating `allow and not deny` together loses the disjunctions in
+        // negated `deny` rules, so each is evaluated on its own.
+        let mut query = residual("allow")?;
+        if self.has_deny_rules() {
+            let not_denied = Operation {
+                operator: Operator::Not,
+                args: vec![residual("deny")?],
+            };
+            query = Term::new_from_ffi(Value::Expression(Operation {
+                operator: Operator::And,
+                args: vec![query, Term::new_from_ffi(Value::Expression(not_denied))],
+            }));
+        }
+        Ok(CompiledCheck {
+            oso: self.clone(),
+            query,
+            policy_fingerprint,
+            types: PhantomData,
+        })
+    }
+}
@@ -0,0 +1,67 @@
+//! Answer the application calls of one query instead of the registered classes.
+//!
+//! A [`Dispatcher`] set with [`Query::set_dispatcher`](crate::Query::set_dispatcher) is asked
+//! about every method call, attribute lookup, and class check of the query before the host is,
+//! e.g., to stub out a class in tests, or to evaluate a policy against virtual data in a
+//! sandbox without touching the application.
+use crate::PolarValue;
+
+/// A method call or attribute lookup made by a query.
+#[derive(Clone, Debug)]
+pub struct ExternalCall {
+    /// The name of the class of the receiver, as registered, or its type name if it isn't.
+    pub class: String,
+    /// The value whose method or attribute is called.
+    pub receiver: PolarValue,
+    /// The name of the method or attribute.
+    pub name: String,
+    /// The arguments of a method call, or `None` for an attribute lookup.
+    pub args: Option<Vec<PolarValue>>,
+}
+
+/// Answers the application calls of a query before the host does. Anything it returns `None`
+/// for is answered by the registered classes as usual.
+///
+/// # Examples
+///
+/// Closures taking an [`ExternalCall`] implement `Dispatcher`:
+///
+/// ```
+/// use oso::{ExternalCall, Oso, PolarClass, PolarValue};
+///
+/// #[derive(Clone, PolarClass)]
+/// struct User {
+///     #[polar(attribute)]
+///     name: String,
+/// }
+///
+/// let mut oso = Oso::new();
+/// oso.register_class(User::get_polar_class()).unwrap();
+/// oso.load_str(r#"allow(user: User, "read", _) if user.name = "alice";"#).unwrap();
+///
+/// let user = User { name: "bob".to_owned() };
+/// let mut query = oso.query_rule("allow", (user, "read", "doc")).unwrap();
+/// query.set_dispatcher(|call: &ExternalCall| match (call.class.as_str(), call.name.as_str()) {
+///     ("User", "name") => Some(Ok(PolarValue::String("alice".to_owned()))),
+///     _ => None,
+/// });
+/// assert!(query.next().is_some());
+/// ```
+pub trait Dispatcher: Send + Sync {
+    /// The result of `call`, or `None` to call the application.
+    fn call(&self, call: &ExternalCall) -> Option<crate::Result<PolarValue>>;
+
+    /// Whether `instance` is an instance of the class `class`, or `None` to ask the host.
+    fn isa(&self, _instance: &PolarValue, _class: &str) -> Option<bool> {
+        None
+    }
+}
+
+impl<F> Dispatcher for F
+where
+    F: Fn(&ExternalCall) -> Option<crate::Result<PolarValue>> + Send + Sync,
+{
+    fn call(&self, call: &ExternalCall) -> Option<crate::Result<PolarValue>> {
+        self(call)
+    }
+}
@@ -0,0 +1,252 @@
+//! A common interface for authorization engines, so that application code doesn't depend on
+//! where decisions are made.
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use polar_core::filter::Filter;
+
+use crate::{Oso, PolarValue};
+
+/// Makes authorization decisions.
+///
+/// Implemented by [`Oso`], the remote `OsoClient`, and [`CachingEnforcer`]. Application code
+/// written against `Enforcer` (or `&dyn Enforcer`) can swap engines, e.g., to use a mock in
+/// tests. `Oso` lets `deny` rules override `allow` rules, as in [`Oso::decide`].
+///
+/// ```
+/// use oso::{Enforcer, Oso, ToPolar};
+///
+/// fn can_read(enforcer: &dyn Enforcer, user: &str, repo: &str) -> oso::Result<bool> {
+///     enforcer.authorize(user.to_polar(), "read".to_polar(), repo.to_polar())
+/// }
+///
+/// let mut oso = Oso::new();
+/// oso.load_str(r#"allow("alice", "read", "oso");"#).unwrap();
+/// assert!(can_read(&oso, "alice", "oso").unwrap());
+/// ```
+pub trait Enforcer {
+    /// Return true if `actor` may perform `action` on `resource`.
+    fn authorize(
+        &self,
+        actor: PolarValue,
+        action: PolarValue,
+        resource: PolarValue,
+    ) -> crate::Result<bool>;
+
+    /// Return the distinct actions that `actor` may perform on `resource`. If every action is
+    /// allowed, the result contains an unbound [`PolarValue::Variable`].
+    fn authorized_actions(
+        &self,
+        actor: PolarValue,
+        resource: PolarValue,
+    ) -> crate::Result<Vec<PolarValue>>;
+
+    /// Build a [`Filter`] describing the resources of type `resource_type` that `actor` may
+    /// perform `action` on.
+    fn authorized_query(
+        &self,
+        actor: PolarValue,
+        action: PolarValue,
+        resource_type: &str,
+    ) -> crate::Result<Filter>;
+
+    /// A fingerprint of the policy that decisions are made with, e.g.,
+    /// [`Oso::policy_fingerprint`], or `None` if the enforcer doesn't know it.
+    fn policy_fingerprint(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl Enforcer for Oso {
+    fn authorize(
+        &self,
+        actor: PolarValue,
+        action: PolarValue,
+        resource: PolarValue,
+    ) -> crate::Result<bool> {
+        self.decide(actor, action, resource)
+            .map(|decision| decision.is_allowed())
+    }
+
+    fn authorized_actions(
+        &self,
+        actor: PolarValue,
+        resource: PolarValue,
+    ) -> crate::Result<Vec<PolarValue>> {
+        let query = self.query_rule(
+            "allow",
+            (
+                actor.clone(),
+                PolarValue::Variable("action".to_owned()),
+                resource.clone(),
+            ),
+        )?;
+        let mut actions = vec![];
+        for result in query {
+            let action = result?.get("action").ok_or(crate::OsoError::FromPolar)?;
+            if !actions.contains(&action) {
+                actions.push(action);
+            }
+        }
+        // An unbound action is kept even if some actions are denied, since there's no way to
+        // say "every action except these".
+        let mut allowed = vec![];
+        for action in actions {
+            if matches!(action, PolarValue::Variable(_))
+                || !self.is_denied(actor.clone(), action.clone(), resource.clone())?
+            {
+                allowed.push(action);
+            }
+        }
+        Ok(allowed)
+    }
+
+    fn authorized_query(
+        &self,
+        actor: PolarValue,
+        action: PolarValue,
+        resource_type: &str,
+    ) -> crate::Result<Filter> {
+        Oso::authorized_query(self, actor, action, resource_type)
+    }
+
+    fn policy_fingerprint(&self) -> Option<u64> {
+        Some(Oso::policy_fingerprint(self))
+    }
+}
+
+const DEFAULT_MAX_ENTRIES: usize = 10_000;
+
+/// Remembers the decisions of another [`Enforcer`] for a while.
+///
+/// Only [`Enforcer::authorize`] decisions are cached, and only for actors, actions, and
+/// resources made of plain values: decisions involving application instances, whose
+/// attributes may change, are always passed through. Errors are not cached. Decisions are
+/// cached by [`Enforcer::policy_fingerprint`] too, so loading a new policy into `Oso` doesn't
+/// require clearing the cache.
+pub struct CachingEnforcer<E> {
+    inner: E,
+    ttl: Duration,
+    max_entries: usize,
+    decisions: Mutex<HashMap<String, (Instant, bool)>>,
+}
+
+impl<E: Enforcer> CachingEnforcer<E> {
+    /// Cache the decisions of `inner` for `ttl`.
+    pub fn new(inner: E, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            max_entries: DEFAULT_MAX_ENTRIES,
+            decisions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Set how many decisions may be cached at once.
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+
+    /// Forget all cached decisions, e.g., after reloading the policy.
+    pub fn clear(&self) {
+        self.decisions.lock().unwrap().clear();
+    }
+}
+
+impl<E: Enforcer> Enforcer for CachingEnforcer<E> {
+    fn authorize(
+        &self,
+        actor: PolarValue,
+        action: PolarValue,
+        resource: PolarValue,
+    ) -> crate::Result<bool> {
+        let key = match cache_key(&[&actor, &action, &resource]) {
+            // Decisions made with an earlier version of the policy are never reused.
+            Some(key) => match self.inner.policy_fingerprint() {
+                Some(fingerprint) => format!("{:016x};{}", fingerprint, key),
+                None => key,
+            },
+            None => return self.inner.authorize(actor, action, resource),
+        };
+        if let Some((expires, allowed)) = self.decisions.lock().unwrap().get(&key) {
+            if *expires > Instant::now() {
+                return Ok(*allowed);
+            }
+        }
+
+        let allowed = self.inner.authorize(actor, action, resource)?;
+        let mut decisions = self.decisions.lock().unwrap();
+        let now = Instant::now();
+        if decisions.len() >= self.max_entries {
+            decisions.retain(|_, (expires, _)| *expires > now);
+        }
+        if decisions.len() < self.max_entries {
+            decisions.insert(key, (now + self.ttl, allowed));
+        }
+        Ok(allowed)
+    }
+
+    fn authorized_actions(
+        &self,
+        actor: PolarValue,
+        resource: PolarValue,
+    ) -> crate::Result<Vec<PolarValue>> {
+        self.inner.authorized_actions(actor, resource)
+    }
+
+    fn authorized_query(
+        &self,
+        actor: PolarValue,
+        action: PolarValue,
+        resource_type: &str,
+    ) -> crate::Result<Filter> {
+        self.inner.authorized_query(actor, action, resource_type)
+    }
+
+    fn policy_fingerprint(&self) -> Option<u64> {
+        self.inner.policy_fingerprint()
+    }
+}
+
+/// Return a string that is equal for equal `values`, or `None` if any value contains an
+/// application instance or a variable.
+fn cache_key(values: &[&PolarValue]) -> Option<String> {
+    fn write(value: &PolarValue, key: &mut String) -> Option<()> {
+        match value {
+            PolarValue::Integer(i) => key.push_str(&format!("i{};", i)),
+            PolarValue::Float(f) => key.push_str(&format!("f{};", f.to_bits())),
+            PolarValue::String(s) => key.push_str(&format!("s{:?};", s)),
+            PolarValue::Boolean(b) => key.push_str(&format!("b{};", b)),
+            PolarValue::List(values) => {
+                key.push('[');
+                for value in values {
+                    write(value, key)?;
+                }
+                key.push(']');
+            }
+            PolarValue::Map(fields) => {
+                let fields = fields.iter().collect::<BTreeMap<_, _>>();
+                key.push('{');
+                for (k, v) in fields {
+                    key.push_str(&format!("{:?}:", k));
+                    write(v, key)?;
+                }
+                key.push('}');
+            }
+            PolarValue::Variable(_) | PolarValue::Instance(_) => return None,
+        }
+        Some(())
+    }
+
+    let mut key = String::new();
+    for value in values {
+        write(value, &mut key)?;
+    }
+    Some(key)
+}
@@ -43,6 +43,11 @@ pub enum OsoError {
     #[error("Inline query failed {location}")]
     InlineQueryFailedError { location: String },
 
+    /// An inline query in a policy loaded with
+    /// [`Oso::load_str_for_tenant`](crate::Oso::load_str_for_tenant) failed.
+    #[error("Inline query failed for tenant {tenant} {location}")]
+    TenantInlineQueryFailedError { tenant: String, location: String },
+
     #[error(transparent)]
     InvalidCallError(#[from] InvalidCallError),
 
@@ -58,6 +63,46 @@ pub enum OsoError {
     #[error("Tried to find an instance that doesn't exist -- internal error")]
     MissingInstanceError,
 
+    /// An error response from the authorization server.
+    #[error("Authorization server responded with status {status}: {message}")]
+    ServerError { status: u16, message: String },
+
+    /// Malformed input to one of the bulk fact loaders.
+    #[error("Invalid fact data: {message}")]
+    InvalidFactData { message: String },
+
+    /// [`Oso::query`](crate::Oso::query) was called after string queries were disabled.
+    #[error("String queries are disabled; use query_rule or query_with to pass values instead")]
+    StringQueriesDisabled,
+
+    /// A string that isn't a [`Cursor`](crate::Cursor) was parsed as one.
+    #[error("Invalid query cursor: {cursor}")]
+    InvalidCursor { cursor: String },
+
+    /// [`Query::sorted_by`](crate::Query::sorted_by) was asked to sort by values that can't be
+    /// compared.
+    #[error("Cannot sort query results: {message}")]
+    InvalidSortKey { message: String },
+
+    /// A decision record that [`DecisionRecord::from_json`](crate::DecisionRecord::from_json)
+    /// couldn't read.
+    #[error("Invalid decision record: {message}")]
+    InvalidDecisionRecord { message: String },
+
+    /// A [`VirtualHost`](crate::VirtualHost) defined by invalid data, or missing the result
+    /// of a method call.
+    #[error("Invalid virtual host: {message}")]
+    InvalidVirtualHost { message: String },
+
+    /// A [`Fixture`](crate::Fixture) or [`DecisionMatrix`](crate::DecisionMatrix) defined by
+    /// invalid data.
+    #[error("Invalid fixture: {message}")]
+    InvalidFixture { message: String },
+
+    /// A policy loaded with [`LoadOptions::strict`](crate::LoadOptions::strict) had warnings.
+    #[error("Policy has warnings:\n{}", warnings.join("\n"))]
+    LoadWarnings { warnings: Vec<String> },
+
     /// TODO: replace all these with proper variants
     #[error("{message}")]
     Custom { message: String },
@@ -24,3 +24,70 @@ impl crate::PolarClass for uuid_10::Uuid {
             .with_equality_check()
     }
 }
+
+/// JSON `null` is converted to `nil`, and numbers to integers when they fit in an `i64`.
+#[cfg(feature = "serde_json")]
+impl crate::ToPolar for serde_json::Value {
+    fn to_polar(self) -> crate::PolarValue {
+        use crate::PolarValue;
+        use serde_json::Value;
+
+        match self {
+            Value::Null => Option::<PolarValue>::None.to_polar(),
+            Value::Bool(b) => PolarValue::Boolean(b),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => PolarValue::Integer(i),
+                None => PolarValue::Float(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Value::String(s) => PolarValue::String(s),
+            Value::Array(values) => {
+                PolarValue::List(values.into_iter().map(|v| v.to_polar()).collect())
+            }
+            Value::Object(fields) => {
+                PolarValue::Map(fields.into_iter().map(|(k, v)| (k, v.to_polar())).collect())
+            }
+        }
+    }
+}
+
+/// `nil` is converted to JSON `null`, and dynamic objects to their values. Variables, other
+/// application instances, and non-finite floats have no JSON representation.
+#[cfg(feature = "serde_json")]
+impl crate::FromPolar for serde_json::Value {
+    fn from_polar(val: crate::PolarValue) -> crate::Result<Self> {
+        use crate::PolarValue;
+        use serde_json::Value;
+
+        Ok(match val {
+            PolarValue::Integer(i) => Value::from(i),
+            PolarValue::Float(f) => serde_json::Number::from_f64(f)
+                .map(Value::Number)
+                .ok_or(crate::OsoError::FromPolar)?,
+            PolarValue::String(s) => Value::String(s),
+            PolarValue::Boolean(b) => Value::Bool(b),
+            PolarValue::List(values) => Value::Array(
+                values
+                    .into_iter()
+                    .map(Value::from_polar)
+                    .collect::<crate::Result<_>>()?,
+            ),
+            PolarValue::Map(fields) => Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(k, v)| Ok((k, Value::from_polar(v)?)))
+                    .collect::<crate::Result<_>>()?,
+            ),
+            PolarValue::Instance(ref instance) => {
+                if let Ok(object) = instance.downcast::<crate::DynObject>(None) {
+                    return Ok(object.value().clone());
+                }
+                match instance.downcast::<Option<PolarValue>>(None) {
+                    Ok(None) => Value::Null,
+                    Ok(Some(value)) => Value::from_polar(value.clone())?,
+                    Err(_) => return Err(crate::OsoError::FromPolar),
+                }
+            }
+            PolarValue::Variable(_) => return Err(crate::OsoError::FromPolar),
+        })
+    }
+}
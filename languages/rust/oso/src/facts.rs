@@ -0,0 +1,392 @@
+//! Answer rules from data that lives outside of Polar policies.
+//!
+//! A [`FactSource`] registered with [`Oso::register_fact_source`](crate::Oso::register_fact_source)
+//! is consulted whenever a policy queries a rule that has no clauses, e.g., a `has_role` rule
+//! whose role assignments live in a database.
+//!
+//! Relationship data that fits in memory can instead be asserted as facts with
+//! [`Oso::insert_fact`](crate::Oso::insert_fact), or loaded in bulk from JSON or CSV with the
+//! `serde_json` and `csv` features.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use polar_core::terms::{Term, Value};
+
+use crate::PolarValue;
+
+/// The answers produced by a [`FactSource`] for a single call.
+///
+/// Each answer is a list of values with one entry per rule argument. Answers are unified with
+/// the arguments of the call, so a source may return answers that don't match the bound
+/// arguments; they are simply skipped.
+pub type Facts = Box<dyn Iterator<Item = crate::Result<Vec<PolarValue>>>>;
+
+/// How long the answers of a [`FactSource`] may be reused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheHint {
+    /// Ask the source every time the rule is called. Answers are streamed lazily.
+    NoCache,
+    /// Reuse the answers to identical calls for the rest of the current query.
+    Query,
+    /// Reuse the answers to identical calls across queries for the given duration.
+    Ttl(Duration),
+}
+
+/// An external source of answers for a rule.
+///
+/// # Examples
+///
+/// Closures that return all answers at once implement `FactSource`:
+///
+/// ```
+/// use oso::{Oso, PolarValue};
+///
+/// let mut oso = Oso::new();
+/// oso.register_fact_source("has_role", |_args: &[PolarValue]| {
+///     Ok(vec![vec![
+///         PolarValue::String("alice".to_owned()),
+///         PolarValue::String("member".to_owned()),
+///         PolarValue::String("repo".to_owned()),
+///     ]])
+/// })
+/// .unwrap();
+/// oso.load_str(r#"allow(actor, "read", repo) if has_role(actor, "member", repo);"#).unwrap();
+///
+/// assert!(oso.is_allowed("alice", "read", "repo").unwrap());
+/// ```
+pub trait FactSource: Send + Sync {
+    /// Return the answers for a call with `args`.
+    ///
+    /// Arguments that are unbound in the policy are passed as [`PolarValue::Variable`].
+    fn facts(&self, args: &[PolarValue]) -> crate::Result<Facts>;
+
+    /// Return how long the answers for a call with `args` may be reused.
+    fn cache_hint(&self, _args: &[PolarValue]) -> CacheHint {
+        CacheHint::NoCache
+    }
+}
+
+impl<F> FactSource for F
+where
+    F: Fn(&[PolarValue]) -> crate::Result<Vec<Vec<PolarValue>>> + Send + Sync,
+{
+    fn facts(&self, args: &[PolarValue]) -> crate::Result<Facts> {
+        Ok(Box::new(self(args)?.into_iter().map(Ok)))
+    }
+}
+
+/// Calls are cached by rule name and bound arguments. Unbound arguments are stored as `None`
+/// since the temporary names of variables differ between queries.
+type CacheKey = (String, Vec<Option<Term>>);
+type Answers = Vec<Vec<PolarValue>>;
+
+/// Answers cached for the duration of a single query.
+pub(crate) type QueryFactCache = HashMap<CacheKey, Answers>;
+
+fn cache_key(name: &str, args: &[Term]) -> CacheKey {
+    let args = args
+        .iter()
+        .map(|arg| match arg.value() {
+            Value::Variable(_) | Value::RestVariable(_) => None,
+            _ => Some(arg.clone()),
+        })
+        .collect();
+    (name.to_owned(), args)
+}
+
+/// Fact sources registered on a [`Host`](crate::host::Host).
+#[derive(Clone, Default)]
+pub(crate) struct FactSources {
+    sources: HashMap<String, Arc<dyn FactSource>>,
+    /// Answers cached across queries, with their expiration time.
+    shared_cache: Arc<Mutex<HashMap<CacheKey, (Instant, Answers)>>>,
+}
+
+impl FactSources {
+    pub fn insert(&mut self, name: String, source: Arc<dyn FactSource>) {
+        self.sources.insert(name, source);
+    }
+
+    /// Fetch the answers to the call `name(args)`, consulting the appropriate cache.
+    pub fn facts(
+        &self,
+        name: &str,
+        terms: &[Term],
+        args: &[PolarValue],
+        query_cache: &mut QueryFactCache,
+    ) -> crate::Result<Facts> {
+        let source = self
+            .sources
+            .get(name)
+            .ok_or_else(|| crate::OsoError::Custom {
+                message: format!("No fact source registered for rule {}", name),
+            })?;
+
+        let key = cache_key(name, terms);
+        let answers = match source.cache_hint(args) {
+            CacheHint::NoCache => return source.facts(args),
+            CacheHint::Query => match query_cache.get(&key) {
+                Some(answers) => answers.clone(),
+                None => {
+                    let answers = source.facts(args)?.collect::<crate::Result<Answers>>()?;
+                    query_cache.insert(key, answers.clone());
+                    answers
+                }
+            },
+            CacheHint::Ttl(ttl) => {
+                let mut shared_cache = self.shared_cache.lock().unwrap();
+                match shared_cache.get(&key) {
+                    Some((expires, answers)) if *expires > Instant::now() => answers.clone(),
+                    _ => {
+                        let answers = source.facts(args)?.collect::<crate::Result<Answers>>()?;
+                        shared_cache.insert(key, (Instant::now() + ttl, answers.clone()));
+                        answers
+                    }
+                }
+            }
+        };
+        Ok(Box::new(answers.into_iter().map(Ok)))
+    }
+}
+
+/// Number of facts converted & indexed at a time by the bulk loaders.
+#[cfg(any(feature = "csv", feature = "serde_json"))]
+pub(crate) const BATCH_SIZE: usize = 10_000;
+
+#[cfg(any(feature = "csv", feature = "serde_json"))]
+fn invalid_fact_data(message: String) -> crate::OsoError {
+    crate::OsoError::InvalidFactData { message }
+}
+
+/// Inserts a batch of facts for a rule, returning the number of facts that weren't already
+/// present.
+#[cfg(feature = "serde_json")]
+type InsertFacts<'a> = dyn FnMut(&str, Vec<Vec<PolarValue>>) -> crate::Result<usize> + 'a;
+
+/// Read facts from a JSON object mapping rule names to lists of argument lists, e.g.,
+/// `{"has_role": [["alice", "member", 1], ["bob", "owner", 2]]}`, passing them to `insert` in
+/// batches as they're parsed. Returns the sum of the counts returned by `insert`.
+#[cfg(feature = "serde_json")]
+pub(crate) fn read_json<R: std::io::Read>(
+    reader: R,
+    insert: &mut InsertFacts,
+) -> crate::Result<usize> {
+    use serde::de::DeserializeSeed;
+
+    let mut error = None;
+    let mut de = serde_json::Deserializer::from_reader(reader);
+    let tables = JsonTables {
+        insert,
+        error: &mut error,
+    };
+    let inserted = tables
+        .deserialize(&mut de)
+        .and_then(|n| de.end().map(|_| n));
+    // Errors raised while inserting facts are stashed, since serde can only carry a message.
+    inserted.map_err(|e| {
+        error
+            .take()
+            .unwrap_or_else(|| invalid_fact_data(e.to_string()))
+    })
+}
+
+#[cfg(feature = "serde_json")]
+fn json_to_polar(value: serde_json::Value) -> crate::Result<PolarValue> {
+    use serde_json::Value as Json;
+
+    Ok(match value {
+        Json::Bool(b) => PolarValue::Boolean(b),
+        Json::Number(n) => {
+            match n.as_i64() {
+                Some(i) => PolarValue::Integer(i),
+                None => PolarValue::Float(n.as_f64().ok_or_else(|| {
+                    invalid_fact_data(format!("{} is out of range for a fact", n))
+                })?),
+            }
+        }
+        Json::String(s) => PolarValue::String(s),
+        Json::Array(values) => PolarValue::List(
+            values
+                .into_iter()
+                .map(json_to_polar)
+                .collect::<crate::Result<_>>()?,
+        ),
+        Json::Object(fields) => PolarValue::Map(
+            fields
+                .into_iter()
+                .map(|(k, v)| Ok((k, json_to_polar(v)?)))
+                .collect::<crate::Result<_>>()?,
+        ),
+        Json::Null => return Err(invalid_fact_data("facts may not contain null".to_owned())),
+    })
+}
+
+/// Visits the top-level object, handing each rule's list of facts to [`JsonFacts`].
+#[cfg(feature = "serde_json")]
+struct JsonTables<'a> {
+    insert: &'a mut InsertFacts<'a>,
+    error: &'a mut Option<crate::OsoError>,
+}
+
+#[cfg(feature = "serde_json")]
+impl<'de, 'a> serde::de::DeserializeSeed<'de> for JsonTables<'a> {
+    type Value = usize;
+
+    fn deserialize<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<usize, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl<'de, 'a> serde::de::Visitor<'de> for JsonTables<'a> {
+    type Value = usize;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("an object mapping rule names to lists of facts")
+    }
+
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<usize, A::Error> {
+        let mut inserted = 0;
+        while let Some(name) = map.next_key::<String>()? {
+            inserted += map.next_value_seed(JsonFacts {
+                name: &name,
+                insert: &mut *self.insert,
+                error: &mut *self.error,
+            })?;
+        }
+        Ok(inserted)
+    }
+}
+
+/// Visits the list of facts for the rule `name`, inserting them every [`BATCH_SIZE`] facts.
+#[cfg(feature = "serde_json")]
+struct JsonFacts<'a> {
+    name: &'a str,
+    insert: &'a mut InsertFacts<'a>,
+    error: &'a mut Option<crate::OsoError>,
+}
+
+#[cfg(feature = "serde_json")]
+impl<'a> JsonFacts<'a> {
+    fn insert<E: serde::de::Error>(&mut self, batch: Vec<Vec<PolarValue>>) -> Result<usize, E> {
+        (self.insert)(self.name, batch).map_err(|e| self.fail(e))
+    }
+
+    fn fail<E: serde::de::Error>(&mut self, error: crate::OsoError) -> E {
+        let message = E::custom(&error);
+        *self.error = Some(error);
+        message
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl<'de, 'a> serde::de::DeserializeSeed<'de> for JsonFacts<'a> {
+    type Value = usize;
+
+    fn deserialize<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<usize, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl<'de, 'a> serde::de::Visitor<'de> for JsonFacts<'a> {
+    type Value = usize;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "a list of facts for `{}`", self.name)
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(mut self, mut seq: A) -> Result<usize, A::Error> {
+        let mut inserted = 0;
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        while let Some(args) = seq.next_element::<Vec<serde_json::Value>>()? {
+            match args.into_iter().map(json_to_polar).collect() {
+                Ok(fact) => batch.push(fact),
+                Err(e) => return Err(self.fail(e)),
+            }
+            if batch.len() == BATCH_SIZE {
+                inserted += self.insert(std::mem::take(&mut batch))?;
+            }
+        }
+        if !batch.is_empty() {
+            inserted += self.insert(batch)?;
+        }
+        Ok(inserted)
+    }
+}
+
+/// The type of a CSV column.
+#[cfg(feature = "csv")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    String,
+    Integer,
+    Float,
+    Boolean,
+}
+
+/// Describes the facts stored in a CSV file: each record is one fact for the rule `name`,
+/// with one argument per column.
+#[cfg(feature = "csv")]
+#[derive(Clone, Debug)]
+pub struct FactSchema {
+    pub(crate) name: String,
+    columns: Vec<Column>,
+    has_headers: bool,
+}
+
+#[cfg(feature = "csv")]
+impl FactSchema {
+    /// Create a schema for a CSV file with a header row.
+    pub fn new(name: &str, columns: Vec<Column>) -> Self {
+        Self {
+            name: name.to_owned(),
+            columns,
+            has_headers: true,
+        }
+    }
+
+    /// Set whether the first record of the file is a header row that should be skipped.
+    pub fn has_headers(mut self, has_headers: bool) -> Self {
+        self.has_headers = has_headers;
+        self
+    }
+}
+
+/// Read facts from CSV `reader` according to `schema`.
+#[cfg(feature = "csv")]
+pub(crate) fn read_csv<'a, R: std::io::Read + 'a>(
+    reader: R,
+    schema: &'a FactSchema,
+) -> impl Iterator<Item = crate::Result<Vec<PolarValue>>> + 'a {
+    let reader = csv::ReaderBuilder::new()
+        .has_headers(schema.has_headers)
+        .from_reader(reader);
+    reader.into_records().map(move |record| {
+        let record = record.map_err(|e| invalid_fact_data(e.to_string()))?;
+        let line = record.position().map_or(0, |p| p.line());
+        if record.len() != schema.columns.len() {
+            return Err(invalid_fact_data(format!(
+                "line {}: expected {} columns, got {}",
+                line,
+                schema.columns.len(),
+                record.len()
+            )));
+        }
+        record
+            .iter()
+            .zip(&schema.columns)
+            .map(|(field, column)| {
+                let invalid =
+                    || invalid_fact_data(format!("line {}: invalid {:?}: {}", line, column, field));
+                Ok(match column {
+                    Column::String => PolarValue::String(field.to_owned()),
+                    Column::Integer => PolarValue::Integer(field.parse().map_err(|_| invalid())?),
+                    Column::Float => PolarValue::Float(field.parse().map_err(|_| invalid())?),
+                    Column::Boolean => PolarValue::Boolean(field.parse().map_err(|_| invalid())?),
+                })
+            })
+            .collect()
+    })
+}
@@ -0,0 +1,205 @@
+//! Constants whose values change while the application runs, e.g., feature flags and kill
+//! switches.
+//!
+//! A [`FlagProvider`] reads the current flags, e.g., from environment variables or a feature
+//! flag service. [`FeatureFlags`] registers them as a dictionary constant, `Flags` by default,
+//! so that policies can use them as `Flags.new_billing`, and refreshes them on an interval.
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::{CachingEnforcer, Enforcer, Oso, PolarValue};
+
+/// A source of flag values.
+///
+/// Flags should be plain values: booleans, numbers, strings, and lists and dictionaries of
+/// them. Application instances are never equal to their previous values, so they're reported
+/// as changed on every refresh.
+pub trait FlagProvider: Send + Sync {
+    /// Return the current value of every flag.
+    fn flags(&self) -> crate::Result<HashMap<String, PolarValue>>;
+}
+
+impl<F> FlagProvider for F
+where
+    F: Fn() -> crate::Result<HashMap<String, PolarValue>> + Send + Sync,
+{
+    fn flags(&self) -> crate::Result<HashMap<String, PolarValue>> {
+        self()
+    }
+}
+
+type Listener = Box<dyn Fn(&[String]) + Send + Sync>;
+
+/// Flags from a [`FlagProvider`], registered as a constant.
+///
+/// Flags that the provider no longer returns are removed, so policies that use them fail
+/// rather than see stale values.
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::HashMap;
+/// use std::sync::{Arc, Mutex};
+/// use std::time::Duration;
+///
+/// use oso::{CachingEnforcer, Enforcer, FeatureFlags, Oso, PolarValue};
+///
+/// let billing = Arc::new(Mutex::new(false));
+/// let provider = {
+///     let billing = billing.clone();
+///     move || -> oso::Result<HashMap<String, PolarValue>> {
+///         let enabled = *billing.lock().unwrap();
+///         Ok(HashMap::from([("new_billing".to_owned(), PolarValue::Boolean(enabled))]))
+///     }
+/// };
+///
+/// let mut oso = Oso::new();
+/// let cache = Arc::new(CachingEnforcer::new(oso.clone(), Duration::from_secs(60)));
+/// let mut flags = FeatureFlags::new(provider).invalidates(cache.clone());
+/// flags.refresh(&mut oso).unwrap();
+/// oso.load_str(r#"allow(_, "pay", _) if Flags.new_billing;"#).unwrap();
+///
+/// let pay = |cache: &CachingEnforcer<Oso>| {
+///     let s = |s: &str| PolarValue::String(s.to_owned());
+///     cache.authorize(s("alice"), s("pay"), s("invoice")).unwrap()
+/// };
+/// assert!(!pay(&cache));
+///
+/// *billing.lock().unwrap() = true;
+/// assert_eq!(flags.refresh(&mut oso).unwrap(), vec!["new_billing"]);
+/// assert!(pay(&cache));
+/// ```
+pub struct FeatureFlags<P: FlagProvider> {
+    provider: P,
+    name: String,
+    values: Option<HashMap<String, PolarValue>>,
+    listeners: Vec<Listener>,
+}
+
+impl<P: FlagProvider + 'static> FeatureFlags<P> {
+    pub fn new(provider: P) -> Self {
+        Self {
+            provider,
+            name: "Flags".to_owned(),
+            values: None,
+            listeners: vec![],
+        }
+    }
+
+    /// Register the flags as the constant `name` instead of `Flags`.
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_owned();
+        self
+    }
+
+    /// Call `f` with the names of the flags that changed, after each refresh that changed any.
+    pub fn on_change<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&[String]) + Send + Sync + 'static,
+    {
+        self.listeners.push(Box::new(f));
+        self
+    }
+
+    /// Clear the decisions cached by `cache` whenever a flag changes.
+    pub fn invalidates<E>(self, cache: Arc<CachingEnforcer<E>>) -> Self
+    where
+        E: Enforcer + Send + Sync + 'static,
+    {
+        self.on_change(move |_| cache.clear())
+    }
+
+    /// The flags as of the last refresh, or `None` before the first.
+    pub fn values(&self) -> Option<&HashMap<String, PolarValue>> {
+        self.values.as_ref()
+    }
+
+    /// Read the flags from the provider and register them. Returns the names of the flags
+    /// that changed, in order. Every flag has changed on the first refresh, which must happen
+    /// before loading policies that use the flags.
+    pub fn refresh(&mut self, oso: &mut Oso) -> crate::Result<Vec<String>> {
+        let values = self.provider.flags()?;
+        let previous = self.values.as_ref();
+        let mut changed = values
+            .iter()
+            .filter(|(name, value)| previous.and_then(|p| p.get(*name)) != Some(*value))
+            .map(|(name, _)| name.clone())
+            .chain(
+                previous
+                    .into_iter()
+                    .flat_map(|p| p.keys())
+                    .filter(|name| !values.contains_key(*name))
+                    .cloned(),
+            )
+            .collect::<Vec<_>>();
+        changed.sort();
+        if self.values.is_some() && changed.is_empty() {
+            return Ok(changed);
+        }
+
+        oso.register_constant(values.clone(), &self.name)?;
+        self.values = Some(values);
+        for listener in &self.listeners {
+            listener(&changed);
+        }
+        Ok(changed)
+    }
+
+    /// Refresh the flags every `interval` on a thread of its own, until the returned handle
+    /// is stopped or dropped. The flags must have been refreshed once already. Failed
+    /// refreshes are logged and keep the previous flags.
+    pub fn spawn(mut self, mut oso: Oso, interval: Duration) -> FlagRefresher {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stopped = stopped.clone();
+            std::thread::spawn(move || {
+                let mut next = Instant::now() + interval;
+                while !stopped.load(Ordering::SeqCst) {
+                    let now = Instant::now();
+                    if now < next {
+                        std::thread::park_timeout(next - now);
+                        continue;
+                    }
+                    next = now + interval;
+                    if let Err(error) = self.refresh(&mut oso) {
+                        tracing::warn!(%error, "failed to refresh flags");
+                    }
+                }
+            })
+        };
+        FlagRefresher {
+            stopped,
+            thread: Some(thread),
+        }
+    }
+}
+
+/// Stops the refreshes started by [`FeatureFlags::spawn`] when stopped or dropped.
+pub struct FlagRefresher {
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FlagRefresher {
+    /// Stop refreshing, and wait for a refresh in progress to finish.
+    pub fn stop(mut self) {
+        self.join();
+    }
+
+    fn join(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for FlagRefresher {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
@@ -0,0 +1,85 @@
+//! Intercept the events of a query before they're handled.
+//!
+//! A [`QueryHook`] registered with [`Oso::add_query_hook`](crate::Oso::add_query_hook) or
+//! [`Query::add_hook`](crate::Query::add_hook) sees every [`QueryEvent`] of a query, and may
+//! answer or fail it instead of the default handler, e.g., to log application calls, deny calls
+//! to particular methods, or answer calls with fake results in tests.
+use std::sync::Arc;
+
+use polar_core::events::QueryEvent;
+
+use crate::{OsoError, PolarValue};
+
+/// What to do with a query event.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum HookAction {
+    /// Pass the event on to the next hook, or to the default handler after the last hook.
+    Continue,
+    /// Answer an application call (`QueryEvent::ExternalCall`, `NextExternal`, or
+    /// `ExternalFacts`) with a value, or with no value to end an iteration, without calling
+    /// the application.
+    CallResult(Option<PolarValue>),
+    /// Answer an application question (`QueryEvent::ExternalIsa`, `ExternalOp`,
+    /// `ExternalIsSubSpecializer`, or `ExternalIsSubclass`) without asking the application.
+    QuestionResult(bool),
+    /// Fail the event with an error, as if the default handler had returned it.
+    Fail(OsoError),
+}
+
+/// Intercepts the events of a query. Hooks run in the order they were added, and the first to
+/// return something other than [`HookAction::Continue`] decides how the event is handled.
+///
+/// # Examples
+///
+/// Closures taking a [`QueryEvent`] implement `QueryHook`:
+///
+/// ```
+/// use oso::{HookAction, Oso, OsoError, QueryEvent};
+///
+/// let mut oso = Oso::new();
+/// oso.add_query_hook(|event: &QueryEvent| match event {
+///     QueryEvent::ExternalCall { attribute, .. } if attribute == "delete" => {
+///         HookAction::Fail(OsoError::Custom {
+///             message: "policies may not call delete".to_owned(),
+///         })
+///     }
+///     _ => HookAction::Continue,
+/// });
+/// oso.load_str(r#"allow(actor, _action, _resource) if actor.delete();"#).unwrap();
+///
+/// assert!(oso.is_allowed("alice", "read", "doc").is_err());
+/// ```
+pub trait QueryHook: Send + Sync {
+    fn on_event(&self, event: &QueryEvent) -> HookAction;
+}
+
+impl<F> QueryHook for F
+where
+    F: Fn(&QueryEvent) -> HookAction + Send + Sync,
+{
+    fn on_event(&self, event: &QueryEvent) -> HookAction {
+        self(event)
+    }
+}
+
+/// Hooks registered on a [`Host`](crate::host::Host).
+#[derive(Clone, Default)]
+pub(crate) struct QueryHooks {
+    hooks: Vec<Arc<dyn QueryHook>>,
+}
+
+impl QueryHooks {
+    pub fn push(&mut self, hook: Arc<dyn QueryHook>) {
+        self.hooks.push(hook);
+    }
+
+    /// Return the action of the first hook that doesn't pass on `event`.
+    pub fn on_event(&self, event: &QueryEvent) -> HookAction {
+        self.hooks
+            .iter()
+            .map(|hook| hook.on_event(event))
+            .find(|action| !matches!(action, HookAction::Continue))
+            .unwrap_or(HookAction::Continue)
+    }
+}
@@ -1,14 +1,14 @@
 //! Support for dynamic class objects in Rust
 
 use std::any::TypeId;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::sync::Arc;
 
 use crate::errors::{InvalidCallError, OsoError};
 
 use super::class_method::{
-    AttributeGetter, ClassMethod, Constructor, InstanceMethod, RegisterHook,
+    AttributeGetter, BatchMethod, ClassMethod, Constructor, InstanceMethod, RegisterHook,
 };
 use super::from_polar::FromPolarList;
 use super::method::{Function, Method};
@@ -16,14 +16,17 @@ use super::to_polar::ToPolarResult;
 use super::Host;
 use super::PolarValue;
 
-type Attributes = HashMap<&'static str, AttributeGetter>;
+type Attributes = HashMap<String, AttributeGetter>;
 type RegisterHooks = Vec<RegisterHook>;
 type ClassMethods = HashMap<&'static str, ClassMethod>;
-type InstanceMethods = HashMap<&'static str, InstanceMethod>;
+type InstanceMethods = HashMap<String, InstanceMethod>;
+type BatchMethods = HashMap<String, BatchMethod>;
 
 type EqualityMethod = Arc<dyn Fn(&Host, &Instance, &Instance) -> crate::Result<bool> + Send + Sync>;
 type IteratorMethod =
     Arc<dyn Fn(&Host, &Instance) -> crate::Result<crate::host::PolarIterator> + Send + Sync>;
+type FilterKeyMethod = Arc<dyn Fn(&Host, &Instance) -> crate::Result<PolarValue> + Send + Sync>;
+type ReprMethod = Arc<dyn Fn(&Instance) -> Option<String> + Send + Sync>;
 
 fn equality_not_supported() -> EqualityMethod {
     let eq = move |host: &Host, lhs: &Instance, _: &Instance| -> crate::Result<bool> {
@@ -66,8 +69,14 @@ pub struct Class {
     constructor: Option<Constructor>,
     /// Methods that return simple attribute lookups on an instance of `T`
     attributes: Attributes,
+    /// Attributes whose values are redacted in traces, logs, and error messages
+    sensitive_attributes: HashSet<&'static str>,
+    /// Pure methods, whose results are reused for calls with the same arguments in a query
+    cached_methods: HashSet<&'static str>,
     /// Instance methods on `T` that expect a list of `PolarValue`s, and an instance of `&T`
     instance_methods: InstanceMethods,
+    /// Instance methods that can also be called on many instances of `T` at once
+    batch_methods: BatchMethods,
     /// Class methods on `T`
     class_methods: ClassMethods,
 
@@ -77,8 +86,18 @@ pub struct Class {
 
     into_iter: IteratorMethod,
 
+    /// A function that returns a key identifying instances of this class in data filters.
+    filter_key: Option<FilterKeyMethod>,
+
+    /// A function that shows instances of this class in traces and error messages.
+    repr: Option<ReprMethod>,
+
     /// Hooks to be called on the class once it's been registered with host.
     pub register_hooks: RegisterHooks,
+
+    /// Whether the class was defined at runtime, so that its instances are told apart from
+    /// those of other runtime classes by name rather than by type.
+    pub(crate) dynamic: bool,
 }
 
 impl Class {
@@ -116,6 +135,51 @@ impl Class {
         attr.clone().invoke(args)
     }
 
+    /// Create a class defined at runtime named `name`, whose instances are values of type `T`
+    /// created with [`Instance::new_dynamic`].
+    #[cfg(feature = "serde_json")]
+    pub(crate) fn new_dynamic<T: 'static>(name: String) -> Self {
+        let mut class = ClassBuilder::<T>::new().class;
+        class.name = name;
+        class.dynamic = true;
+        class
+    }
+
+    #[cfg(feature = "serde_json")]
+    pub(crate) fn insert_attribute(&mut self, name: String, getter: AttributeGetter) {
+        self.attributes.insert(name, getter);
+    }
+
+    #[cfg(feature = "serde_json")]
+    pub(crate) fn insert_method(&mut self, name: String, method: InstanceMethod) {
+        self.instance_methods.insert(name, method);
+    }
+
+    #[cfg(feature = "serde_json")]
+    pub(crate) fn set_dynamic_constructor(&mut self, constructor: Constructor) {
+        self.constructor = Some(constructor);
+    }
+
+    #[cfg(feature = "serde_json")]
+    pub(crate) fn set_dynamic_equality_check<F>(&mut self, f: F)
+    where
+        F: Fn(&Host, &Instance, &Instance) -> crate::Result<bool> + Send + Sync + 'static,
+    {
+        self.equality_check = Arc::new(f);
+    }
+
+    /// Return true if the attribute `name` was marked sensitive with
+    /// [`ClassBuilder::with_sensitive_attr`].
+    pub fn is_sensitive_attr(&self, name: &str) -> bool {
+        self.sensitive_attributes.contains(name)
+    }
+
+    /// Return true if the method `name` was marked pure with
+    /// [`ClassBuilder::with_cached_method`].
+    pub fn is_cached_method(&self, name: &str) -> bool {
+        self.cached_methods.contains(name)
+    }
+
     fn get_method(&self, name: &str) -> Option<InstanceMethod> {
         tracing::trace!({class=%self.name, name}, "get_method");
         if self.type_id == TypeId::of::<Class>() {
@@ -126,6 +190,11 @@ impl Class {
         }
     }
 
+    /// The method `name`, if it was added with [`ClassBuilder::add_batch_method`].
+    pub(crate) fn get_batch_method(&self, name: &str) -> Option<&BatchMethod> {
+        self.batch_methods.get(name)
+    }
+
     fn equals(&self, host: &Host, lhs: &Instance, rhs: &Instance) -> crate::Result<bool> {
         // equality checking is currently only supported for exactly matching types
         // TODO: support multiple dispatch for equality
@@ -135,6 +204,33 @@ impl Class {
             (self.equality_check)(host, lhs, rhs)
         }
     }
+
+    /// The key identifying `instance` in data filters, if the class has one.
+    pub(crate) fn filter_key(
+        &self,
+        host: &Host,
+        instance: &Instance,
+    ) -> Option<crate::Result<PolarValue>> {
+        self.filter_key.as_ref().map(|key| key(host, instance))
+    }
+
+    /// How `instance` is shown in traces and error messages, if the class has a repr.
+    pub(crate) fn repr(&self, instance: &Instance) -> Option<String> {
+        self.repr.as_ref().and_then(|repr| repr(instance))
+    }
+}
+
+/// The default name of the class of a type named `type_name`: its name without module paths,
+/// followed by those of its type parameters, if any, so that each instantiation of a generic
+/// type gets a class of its own. E.g., `Paginated<Repo>` is named `Paginated_Repo`, and
+/// `Paginated<Vec<Org>>` is named `Paginated_Vec_Org`.
+fn default_class_name(type_name: &str) -> String {
+    type_name
+        .split(|c: char| !(c.is_alphanumeric() || c == '_' || c == ':'))
+        .filter_map(|path| path.rsplit("::").next())
+        .filter(|name| !name.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
 }
 
 /// Builder for new Oso [`Class`].
@@ -160,19 +256,23 @@ where
 {
     /// Create a new class builder.
     fn new() -> Self {
-        let fq_name = std::any::type_name::<T>().to_string();
-        let short_name = fq_name.split("::").last().expect("type has invalid name");
         Self {
             class: Class {
-                name: short_name.to_string(),
+                name: default_class_name(std::any::type_name::<T>()),
                 constructor: None,
                 attributes: HashMap::new(),
+                sensitive_attributes: HashSet::new(),
+                cached_methods: HashSet::new(),
                 instance_methods: InstanceMethods::new(),
+                batch_methods: BatchMethods::new(),
                 class_methods: ClassMethods::new(),
                 equality_check: equality_not_supported(),
                 into_iter: iterator_not_supported(),
+                filter_key: None,
+                repr: None,
                 type_id: TypeId::of::<T>(),
                 register_hooks: RegisterHooks::new(),
+                dynamic: false,
             },
             ty: std::marker::PhantomData,
         }
@@ -228,6 +328,19 @@ where
         class
     }
 
+    /// Create a new class builder with a constructor that may fail, e.g., on arguments out of
+    /// range.
+    pub(crate) fn with_fallible_constructor<F, Args>(f: F) -> Self
+    where
+        F: Function<Args, Result = crate::Result<T>>,
+        T: Send + Sync,
+        Args: FromPolarList,
+    {
+        let mut class: ClassBuilder<T> = ClassBuilder::new();
+        class.class.constructor = Some(Constructor::new_fallible(f));
+        class
+    }
+
     /// Set the constructor function to use for polar `new` statements.
     ///
     /// # Examples
@@ -305,6 +418,71 @@ where
         self.set_into_iter(|t| t.clone().into_iter())
     }
 
+    /// Set a function returning a key that identifies instances in data filters.
+    ///
+    /// Filters built by [`Oso::authorized_query`](crate::Oso::authorized_query) compare
+    /// instances of this class by their keys instead of by handle, so that distinct instances
+    /// with the same key match the same records.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use oso::ClassBuilder;
+    ///
+    /// #[derive(Default)]
+    /// struct MyClass {
+    ///     id: i64,
+    /// }
+    ///
+    /// let class = ClassBuilder::<MyClass>::with_default()
+    ///     .with_filter_key(|t| t.id)
+    ///     .build();
+    /// ```
+    pub fn with_filter_key<F, K>(mut self, f: F) -> Self
+    where
+        F: Fn(&T) -> K + Send + Sync + 'static,
+        K: crate::ToPolar,
+    {
+        self.class.filter_key = Some(Arc::new(move |host, instance| {
+            let instance = instance.downcast(Some(host)).map_err(|e| e.user())?;
+            Ok(f(instance).to_polar())
+        }));
+
+        self
+    }
+
+    /// Set a function that shows instances in query traces, logs, and error messages, e.g., as
+    /// `User(alice)`, instead of by their type alone.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use oso::ClassBuilder;
+    ///
+    /// #[derive(Default)]
+    /// struct User {
+    ///     name: String,
+    /// }
+    ///
+    /// let class = ClassBuilder::<User>::with_default()
+    ///     .with_repr(|user| format!("User({})", user.name))
+    ///     .build();
+    /// ```
+    pub fn with_repr<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&T) -> String + Send + Sync + 'static,
+    {
+        self.class.repr = Some(Arc::new(move |instance| {
+            instance.downcast(None).ok().map(&f)
+        }));
+
+        self
+    }
+
     /// Use [`PartialEq`] as the equality check for Polar `==` statements.
     ///
     /// # Examples
@@ -360,7 +538,74 @@ where
         R: crate::ToPolar,
         T: 'static,
     {
-        self.class.attributes.insert(name, AttributeGetter::new(f));
+        self.class
+            .attributes
+            .insert(name.to_owned(), AttributeGetter::new(f));
+        self
+    }
+
+    /// Mark the attribute `name` as sensitive, e.g., a password or an API key. Its values are
+    /// shown as `[REDACTED]` in query traces, logs, and error messages, including the values
+    /// of variables bound to them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use oso::ClassBuilder;
+    ///
+    /// #[derive(Clone, Default)]
+    /// struct User {
+    ///     name: String,
+    ///     api_key: String,
+    /// }
+    ///
+    /// let class = ClassBuilder::<User>::with_default()
+    ///     .add_attribute_getter("name", |user| user.name.clone())
+    ///     .add_attribute_getter("api_key", |user| user.api_key.clone())
+    ///     .with_sensitive_attr("api_key")
+    ///     .build();
+    /// ```
+    pub fn with_sensitive_attr(mut self, name: &'static str) -> Self {
+        self.class.sensitive_attributes.insert(name);
+        self
+    }
+
+    /// Mark the method `name` as pure: its result depends only on the instance and the
+    /// arguments. Each query calls it once for each instance and arguments, and reuses the
+    /// result for repeated calls, e.g., when backtracking retries the same rule.
+    ///
+    /// Don't mark methods that return iterators, whose results can only be used once.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use oso::{Oso, PolarClass};
+    ///
+    /// #[derive(Clone, PolarClass)]
+    /// struct User {
+    ///     id: i64,
+    /// }
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.register_class(
+    ///     User::get_polar_class_builder()
+    ///         // An expensive lookup, made once per query for each user and organization.
+    ///         .add_method("role_in", |user: &User, org: String| {
+    ///             if user.id == 1 && org == "acme" { "admin" } else { "guest" }.to_owned()
+    ///         })
+    ///         .with_cached_method("role_in")
+    ///         .build(),
+    /// )
+    /// .unwrap();
+    /// oso.load_str(
+    ///     r#"allow(user, "read", org) if user.role_in(org) = "guest";
+    ///        allow(user, "read", org) if user.role_in(org) = "admin";"#,
+    /// )
+    /// .unwrap();
+    /// assert!(oso.is_allowed(User { id: 1 }, "read", "acme").unwrap());
+    /// ```
+    pub fn with_cached_method(mut self, name: &'static str) -> Self {
+        self.class.cached_methods.insert(name);
         self
     }
 
@@ -383,6 +628,21 @@ where
         self
     }
 
+    /// Like [`add_constant`](ClassBuilder::add_constant), but the constant is redacted in
+    /// traces, logs, and error messages, as for [`Oso::register_sensitive_constant`](crate::Oso::register_sensitive_constant).
+    pub fn add_sensitive_constant<V: crate::ToPolar + Clone + Send + Sync + 'static>(
+        mut self,
+        value: V,
+        name: &'static str,
+    ) -> Self {
+        let register_hook =
+            move |oso: &mut crate::Oso| oso.register_sensitive_constant(value.clone(), name);
+        self.class
+            .register_hooks
+            .push(RegisterHook::new(register_hook));
+        self
+    }
+
     /// Add a method for polar method calls like `foo.plus(1)
     /// `class.add_attribute_getter("bar", |instance, n| instance.foo + n)
     pub fn add_method<F, Args, R>(mut self, name: &'static str, f: F) -> Self
@@ -393,7 +653,7 @@ where
     {
         self.class
             .instance_methods
-            .insert(name, InstanceMethod::new(f));
+            .insert(name.to_owned(), InstanceMethod::new(f));
         self
     }
 
@@ -411,7 +671,69 @@ where
     {
         self.class
             .instance_methods
-            .insert(name, InstanceMethod::new_iterator(f));
+            .insert(name.to_owned(), InstanceMethod::new_iterator(f));
+        self
+    }
+
+    /// A method that can be called on many instances at once, e.g., to look up data for all of
+    /// them in one database query. `f` is called with the instances and the arguments as a
+    /// tuple, and returns a result for each instance, in order.
+    ///
+    /// When a policy iterates a list of instances and calls the method on each of them, the
+    /// method is called once for all the instances of the list that are of this class, and the
+    /// results are reused for the rest of the query. Other calls pass `f` a single instance.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use oso::{ClassBuilder, Oso, PolarClass};
+    ///
+    /// #[derive(Clone, PolarClass)]
+    /// struct Repo {
+    ///     id: i64,
+    /// }
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.register_class(
+    ///     Repo::get_polar_class_builder()
+    ///         .add_batch_method("is_public", |repos: &[&Repo], (): ()| {
+    ///             // One lookup for all the repos.
+    ///             repos.iter().map(|repo| repo.id % 2 == 0).collect()
+    ///         })
+    ///         .build(),
+    /// )
+    /// .unwrap();
+    /// oso.load_str("public(repos, repo) if repo in repos and repo.is_public();")
+    ///     .unwrap();
+    ///
+    /// let repos = vec![Repo { id: 1 }, Repo { id: 2 }];
+    /// let public: Vec<Repo> = oso
+    ///     .query_rule("public", (repos, oso::PolarValue::Variable("repo".to_owned())))
+    ///     .unwrap()
+    ///     .map(|result| result.unwrap().get_typed("repo").unwrap())
+    ///     .collect();
+    /// assert_eq!(public.len(), 1);
+    /// assert_eq!(public[0].id, 2);
+    /// ```
+    pub fn add_batch_method<F, Args, R>(mut self, name: &'static str, f: F) -> Self
+    where
+        Args: FromPolarList,
+        F: Fn(&[&T], Args) -> Vec<R> + Send + Sync + 'static,
+        R: ToPolarResult + 'static,
+    {
+        let method = BatchMethod::new(f);
+        self.class
+            .instance_methods
+            .insert(name.to_owned(), method.single());
+        self.class.batch_methods.insert(name.to_owned(), method);
+        let register_hook = move |oso: &mut crate::Oso| {
+            oso.inner
+                .register_batched_method(polar_core::terms::Symbol::new(name));
+            Ok(())
+        };
+        self.class
+            .register_hooks
+            .push(RegisterHook::new(register_hook));
         self
     }
 
@@ -446,6 +768,9 @@ where
 pub struct Instance {
     inner: Arc<dyn std::any::Any + Send + Sync>,
 
+    /// The name of the class of an instance of a class defined at runtime.
+    class_name: Option<Arc<str>>,
+
     /// The type name of the Instance, to be used for debugging purposes only.
     /// To get the registered name, use `Instance::name`.
     debug_type_name: &'static str,
@@ -462,13 +787,44 @@ impl Instance {
     pub fn new<T: Send + Sync + 'static>(instance: T) -> Self {
         Self {
             inner: Arc::new(instance),
+            class_name: None,
             debug_type_name: std::any::type_name::<T>(),
         }
     }
 
+    /// Create an instance of the class defined at runtime named `class_name`.
+    #[cfg(feature = "serde_json")]
+    pub(crate) fn new_dynamic<T: Send + Sync + 'static>(class_name: &str, instance: T) -> Self {
+        Self {
+            class_name: Some(class_name.into()),
+            ..Self::new(instance)
+        }
+    }
+
+    /// Create an instance that shares `instance` with the application. Converting the same
+    /// shared value more than once, e.g., through the back-references of an object graph,
+    /// gives the same Polar instance each time, so it's equal to itself without an equality
+    /// check, and conversion never walks the graph.
+    pub fn new_shared<T: Send + Sync + 'static>(instance: Arc<T>) -> Self {
+        Self {
+            inner: instance,
+            class_name: None,
+            debug_type_name: std::any::type_name::<T>(),
+        }
+    }
+
+    /// The address of the instance's value, which identifies the value while it's alive.
+    pub(crate) fn address(&self) -> usize {
+        Arc::as_ptr(&self.inner) as *const () as usize
+    }
+
     /// Check whether this is an instance of `class`
     pub fn instance_of(&self, class: &Class) -> bool {
         self.type_id() == class.type_id
+            && self
+                .class_name
+                .as_ref()
+                .map_or(!class.dynamic, |name| **name == class.name)
     }
 
     pub fn type_id(&self) -> std::any::TypeId {
@@ -477,6 +833,9 @@ impl Instance {
 
     /// Looks up the `Class` for this instance on the provided `host`
     pub fn class<'a>(&self, host: &'a Host) -> crate::Result<&'a Class> {
+        if let Some(name) = &self.class_name {
+            return host.get_class(name);
+        }
         host.get_class_by_type_id(self.inner.as_ref().type_id())
             .map_err(|_| OsoError::MissingClassError {
                 name: self.debug_type_name.to_string(),
@@ -571,6 +930,48 @@ impl Instance {
             .downcast_ref()
             .ok_or_else(|| crate::errors::TypeError::expected(expected_name).got(name))
     }
+
+    /// Like [`downcast`](Instance::downcast), but share the value instead of borrowing it.
+    pub(crate) fn downcast_shared<T: Send + Sync + 'static>(
+        &self,
+    ) -> Result<Arc<T>, crate::errors::TypeError> {
+        self.inner.clone().downcast().map_err(|_| {
+            crate::errors::TypeError::expected(std::any::type_name::<T>())
+                .got(self.debug_type_name.to_owned())
+        })
+    }
+}
+
+/// An opaque handle to an instance returned by a query, for passing it back into later
+/// queries unchanged.
+///
+/// Any instance converts to a handle with [`ResultSet::get_typed`](crate::ResultSet::get_typed),
+/// whether or not its class is registered or its type is known to the caller. This enables
+/// workflows like querying for resources, then checking an action on each of them.
+///
+/// Two handles are equal if they refer to the same instance.
+#[derive(Clone)]
+pub struct InstanceHandle(pub(crate) Instance);
+
+impl InstanceHandle {
+    /// The name of the instance's Rust type, for debugging purposes only.
+    pub fn type_name(&self) -> &'static str {
+        self.0.debug_type_name
+    }
+}
+
+impl PartialEq for InstanceHandle {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::as_ptr(&self.0.inner) as *const () == Arc::as_ptr(&other.0.inner) as *const ()
+    }
+}
+
+impl Eq for InstanceHandle {}
+
+impl fmt::Debug for InstanceHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "InstanceHandle<{}>", self.0.debug_type_name)
+    }
 }
 
 #[cfg(test)]
@@ -589,4 +990,21 @@ mod test {
         assert!(foo_instance.instance_of(&foo_class));
         assert!(!foo_instance.instance_of(&bar_class));
     }
+
+    #[test]
+    fn test_default_class_name() {
+        struct Page<T>(T);
+        struct Repo;
+
+        assert_eq!(Class::builder::<Repo>().build().name, "Repo");
+        assert_eq!(Class::builder::<Page<Repo>>().build().name, "Page_Repo");
+        assert_eq!(
+            Class::builder::<Page<Page<(Repo, i64)>>>().build().name,
+            "Page_Page_Repo_i64"
+        );
+        assert_eq!(
+            default_class_name("a::Map<alloc::string::String, b::Repo>"),
+            "Map_String_Repo"
+        );
+    }
 }
@@ -49,6 +49,27 @@ impl Constructor {
         }))
     }
 
+    /// A constructor that may fail, e.g., on arguments out of range.
+    pub(crate) fn new_fallible<Args, F, T>(f: F) -> Self
+    where
+        Args: FromPolarList,
+        F: Function<Args, Result = crate::Result<T>>,
+        T: Send + Sync + 'static,
+    {
+        Constructor(Arc::new(move |args: Vec<PolarValue>| {
+            Args::from_polar_list(&args).and_then(|args| f.invoke(args).map(Instance::new))
+        }))
+    }
+
+    /// A constructor taking any number of arguments.
+    #[cfg(feature = "serde_json")]
+    pub fn new_variadic<F>(f: F) -> Self
+    where
+        F: Fn(Vec<PolarValue>) -> crate::Result<Instance> + Send + Sync + 'static,
+    {
+        Constructor(Arc::new(f))
+    }
+
     pub fn invoke(&self, args: Vec<PolarValue>) -> crate::Result<Instance> {
         self.0(args)
     }
@@ -75,6 +96,21 @@ impl AttributeGetter {
         }))
     }
 
+    /// A getter whose errors are returned as they are.
+    #[cfg(feature = "serde_json")]
+    pub fn new_fallible<T, F>(f: F) -> Self
+    where
+        T: 'static,
+        F: Fn(&T) -> crate::Result<PolarValue> + Send + Sync + 'static,
+    {
+        Self(Arc::new(move |receiver, host: &mut Host| {
+            receiver
+                .downcast(Some(host))
+                .map_err(|e| e.invariant().into())
+                .and_then(&f)
+        }))
+    }
+
     pub fn invoke(&self, receiver: &Instance, host: &mut Host) -> crate::Result<PolarValue> {
         self.0(receiver, host)
     }
@@ -105,6 +141,26 @@ impl InstanceMethod {
         ))
     }
 
+    /// A method taking any number of arguments.
+    #[cfg(feature = "serde_json")]
+    pub fn new_variadic<T, F, R>(f: F) -> Self
+    where
+        F: Fn(&T, Vec<PolarValue>) -> crate::Result<R> + Send + Sync + 'static,
+        R: ToPolarResult,
+        T: 'static,
+    {
+        Self(Arc::new(
+            move |receiver: &Instance, args: Vec<PolarValue>, host: &mut Host| {
+                let receiver = receiver
+                    .downcast(Some(host))
+                    .map_err(|e| e.invariant().into());
+                receiver
+                    .and_then(|receiver| f(receiver, args))
+                    .and_then(|result| result.to_polar_result())
+            },
+        ))
+    }
+
     pub fn new_iterator<T, F, Args, I>(f: F) -> Self
     where
         Args: FromPolarList,
@@ -156,6 +212,70 @@ impl InstanceMethod {
     }
 }
 
+type TypeErasedBatchMethod = Arc<
+    dyn Fn(&[Instance], Vec<PolarValue>, &mut Host) -> crate::Result<Vec<PolarValue>> + Send + Sync,
+>;
+
+/// A method called on many instances at once, returning a result for each of them.
+#[derive(Clone)]
+pub struct BatchMethod(TypeErasedBatchMethod);
+
+impl BatchMethod {
+    pub fn new<T, F, Args, R>(f: F) -> Self
+    where
+        Args: FromPolarList,
+        F: Fn(&[&T], Args) -> Vec<R> + Send + Sync + 'static,
+        R: ToPolarResult,
+        T: 'static,
+    {
+        Self(Arc::new(
+            move |receivers: &[Instance], args: Vec<PolarValue>, host: &mut Host| {
+                let receivers = receivers
+                    .iter()
+                    .map(|receiver| {
+                        receiver
+                            .downcast(Some(host))
+                            .map_err(|e| e.invariant().into())
+                    })
+                    .collect::<crate::Result<Vec<&T>>>()?;
+                let args = Args::from_polar_list(&args)?;
+                let results = f(&receivers, args);
+                if results.len() != receivers.len() {
+                    return lazy_error!(
+                        "batch method returned {} results for {} instances",
+                        results.len(),
+                        receivers.len()
+                    );
+                }
+                results
+                    .into_iter()
+                    .map(|result| result.to_polar_result())
+                    .collect()
+            },
+        ))
+    }
+
+    pub fn invoke(
+        &self,
+        receivers: &[Instance],
+        args: Vec<PolarValue>,
+        host: &mut Host,
+    ) -> crate::Result<Vec<PolarValue>> {
+        self.0(receivers, args, host)
+    }
+
+    /// The method as an [`InstanceMethod`], for calls on one instance at a time.
+    pub fn single(&self) -> InstanceMethod {
+        let batch = self.clone();
+        InstanceMethod(Arc::new(
+            move |receiver: &Instance, args: Vec<PolarValue>, host: &mut Host| {
+                let mut results = batch.invoke(std::slice::from_ref(receiver), args, host)?;
+                Ok(results.remove(0))
+            },
+        ))
+    }
+}
+
 #[derive(Clone)]
 pub struct ClassMethod(TypeErasedFunction<PolarValue>);
 
@@ -0,0 +1,193 @@
+//! Classes defined at runtime, whose instances are JSON values, for exposing objects to Polar
+//! without a Rust type for each of them, e.g., from plugins or scripting layers.
+
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use super::class::{Class, Instance};
+use super::class_method::{AttributeGetter, Constructor, InstanceMethod};
+use super::from_polar::FromPolar;
+use super::to_polar::{ToPolar, ToPolarResult};
+use super::PolarValue;
+
+/// An instance of a class registered with [`Oso::register_dynamic_class`](crate::Oso::register_dynamic_class):
+/// a JSON value tagged with the name of its class.
+///
+/// Dynamic objects are equal if they're of the same class and have equal values.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DynObject {
+    class: String,
+    value: Value,
+}
+
+impl DynObject {
+    /// Create an instance of the dynamic class named `class`.
+    pub fn new<S: Into<String>>(class: S, value: Value) -> Self {
+        Self {
+            class: class.into(),
+            value,
+        }
+    }
+
+    /// The name of the object's class.
+    pub fn class(&self) -> &str {
+        &self.class
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    pub fn into_value(self) -> Value {
+        self.value
+    }
+}
+
+impl ToPolar for DynObject {
+    fn to_polar(self) -> PolarValue {
+        let class = self.class.clone();
+        PolarValue::Instance(Instance::new_dynamic(&class, self))
+    }
+}
+
+impl FromPolar for DynObject {
+    fn from_polar(val: PolarValue) -> crate::Result<Self> {
+        if let PolarValue::Instance(instance) = val {
+            Ok(instance
+                .downcast::<Self>(None)
+                .map_err(|e| e.user())?
+                .clone())
+        } else {
+            Err(crate::errors::TypeError::expected("Instance").user())
+        }
+    }
+}
+
+type DynAttribute = Arc<dyn Fn(&Value) -> crate::Result<PolarValue> + Send + Sync>;
+type DynMethod = Arc<dyn Fn(&Value, Vec<Value>) -> crate::Result<PolarValue> + Send + Sync>;
+type DynConstructor = Arc<dyn Fn(Vec<Value>) -> crate::Result<Value> + Send + Sync>;
+
+/// The definition of a class at runtime: its name, and closures over the JSON values of its
+/// instances for its attributes and methods.
+///
+/// Arguments are converted to JSON as by the `FromPolar` implementation for
+/// [`serde_json::Value`], and dynamic objects passed as arguments to their values. Results may
+/// be anything that converts to Polar, including JSON values and other [`DynObject`]s.
+///
+/// # Examples
+///
+/// ```
+/// use oso::{DynClassSpec, DynObject, Oso};
+/// use serde_json::json;
+///
+/// let mut oso = Oso::new();
+/// oso.register_dynamic_class(
+///     DynClassSpec::new("Plugin")
+///         .add_attribute_getter("name", |plugin| plugin["name"].clone())
+///         .add_method("has_scope", |plugin, args| {
+///             let scopes = plugin["scopes"].as_array().cloned().unwrap_or_default();
+///             Ok(scopes.contains(&args[0]))
+///         }),
+/// )
+/// .unwrap();
+/// oso.load_str(r#"allow(plugin: Plugin, "read", _) if plugin.has_scope("read");"#)
+///     .unwrap();
+///
+/// let plugin = DynObject::new("Plugin", json!({"name": "search", "scopes": ["read"]}));
+/// assert!(oso.is_allowed(plugin, "read", "docs").unwrap());
+/// ```
+#[derive(Clone)]
+pub struct DynClassSpec {
+    name: String,
+    attributes: Vec<(String, DynAttribute)>,
+    methods: Vec<(String, DynMethod)>,
+    constructor: Option<DynConstructor>,
+}
+
+impl DynClassSpec {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Self {
+            name: name.into(),
+            attributes: vec![],
+            methods: vec![],
+            constructor: None,
+        }
+    }
+
+    /// Add an attribute, looked up on an instance's value by `f`.
+    pub fn add_attribute_getter<S, F, R>(mut self, name: S, f: F) -> Self
+    where
+        S: Into<String>,
+        F: Fn(&Value) -> R + Send + Sync + 'static,
+        R: ToPolarResult,
+    {
+        self.attributes.push((
+            name.into(),
+            Arc::new(move |value| f(value).to_polar_result()),
+        ));
+        self
+    }
+
+    /// Add a method, called with an instance's value and any number of arguments.
+    pub fn add_method<S, F, R>(mut self, name: S, f: F) -> Self
+    where
+        S: Into<String>,
+        F: Fn(&Value, Vec<Value>) -> crate::Result<R> + Send + Sync + 'static,
+        R: ToPolarResult,
+    {
+        self.methods.push((
+            name.into(),
+            Arc::new(move |value, args| f(value, args).and_then(|r| r.to_polar_result())),
+        ));
+        self
+    }
+
+    /// Set a constructor, which makes the value of an instance from the arguments of `new`.
+    pub fn set_constructor<F>(mut self, f: F) -> Self
+    where
+        F: Fn(Vec<Value>) -> crate::Result<Value> + Send + Sync + 'static,
+    {
+        self.constructor = Some(Arc::new(f));
+        self
+    }
+
+    pub(crate) fn build(self) -> Class {
+        let mut class = Class::new_dynamic::<DynObject>(self.name.clone());
+        for (name, f) in self.attributes {
+            class.insert_attribute(
+                name,
+                AttributeGetter::new_fallible(move |object: &DynObject| f(&object.value)),
+            );
+        }
+        for (name, f) in self.methods {
+            class.insert_method(
+                name,
+                InstanceMethod::new_variadic(move |object: &DynObject, args| {
+                    f(&object.value, to_json(args)?)
+                }),
+            );
+        }
+        if let Some(f) = self.constructor {
+            let name = self.name;
+            class.set_dynamic_constructor(Constructor::new_variadic(move |args| {
+                let object = DynObject::new(name.clone(), f(to_json(args)?)?);
+                Ok(Instance::new_dynamic(&name, object))
+            }));
+        }
+        class.set_dynamic_equality_check(|host, left, right| {
+            let left = left
+                .downcast::<DynObject>(Some(host))
+                .map_err(|e| e.user())?;
+            let right = right
+                .downcast::<DynObject>(Some(host))
+                .map_err(|e| e.user())?;
+            Ok(left == right)
+        });
+        class
+    }
+}
+
+fn to_json(args: Vec<PolarValue>) -> crate::Result<Vec<Value>> {
+    args.into_iter().map(Value::from_polar).collect()
+}
@@ -4,10 +4,11 @@
 
 use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, LinkedList, VecDeque};
 use std::hash::Hash;
+use std::sync::Arc;
 
 use impl_trait_for_tuples::*;
 
-use super::class::Instance;
+use super::class::{Instance, InstanceHandle};
 use super::PolarValue;
 use crate::errors::TypeError;
 use crate::PolarClass;
@@ -76,6 +77,20 @@ where
     }
 }
 
+/// Shares the instance's value instead of copying it.
+impl<T> FromPolar for Arc<T>
+where
+    T: 'static + Send + Sync + PolarClass,
+{
+    fn from_polar(val: PolarValue) -> crate::Result<Self> {
+        if let PolarValue::Instance(instance) = val {
+            Ok(instance.downcast_shared::<T>().map_err(|e| e.user())?)
+        } else {
+            Err(TypeError::expected("Instance").user())
+        }
+    }
+}
+
 impl FromPolar for f64 {
     fn from_polar(val: PolarValue) -> crate::Result<Self> {
         if let PolarValue::Float(f) = val {
@@ -314,6 +329,16 @@ impl FromPolar for Instance {
     }
 }
 
+impl FromPolar for InstanceHandle {
+    fn from_polar(value: PolarValue) -> crate::Result<Self> {
+        if let PolarValue::Instance(instance) = value {
+            Ok(Self(instance))
+        } else {
+            Err(TypeError::expected("Instance").user())
+        }
+    }
+}
+
 #[impl_for_tuples(16)]
 #[tuple_types_custom_trait_bound(FromPolar)]
 impl FromPolarList for Tuple {
@@ -343,3 +368,16 @@ impl FromPolarList for Tuple {
 #[impl_for_tuples(16)]
 #[tuple_types_custom_trait_bound(FromPolar)]
 impl private::Sealed for Tuple {}
+
+/// Lists convert to tuples of the same length, e.g., a two-element list to a pair.
+#[impl_for_tuples(1, 16)]
+#[tuple_types_custom_trait_bound(FromPolar)]
+impl FromPolar for Tuple {
+    fn from_polar(val: PolarValue) -> crate::Result<Self> {
+        if let PolarValue::List(l) = val {
+            Self::from_polar_list(&l)
+        } else {
+            Err(TypeError::expected("List").user())
+        }
+    }
+}
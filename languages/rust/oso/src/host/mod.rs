@@ -1,17 +1,24 @@
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use crate::errors::OsoError;
+use crate::facts::FactSources;
+use crate::hooks::QueryHooks;
 use crate::Polar;
 
 mod class;
 mod class_method;
+#[cfg(feature = "serde_json")]
+mod dynamic;
 mod from_polar;
 mod method;
 mod to_polar;
 mod value;
 
-pub use class::{Class, ClassBuilder, Instance};
+pub use class::{Class, ClassBuilder, Instance, InstanceHandle};
+#[cfg(feature = "serde_json")]
+pub use dynamic::{DynClassSpec, DynObject};
 pub use from_polar::{FromPolar, FromPolarList};
 use polar_core::terms::{Operator, Symbol};
 pub use to_polar::{PolarIterator, ToPolar, ToPolarList};
@@ -30,6 +37,9 @@ fn metaclass() -> Class {
     Class::builder::<Class>().name("oso::host::Class").build()
 }
 
+/// A host predicate registered as a specializer, e.g., `@verified_email`.
+pub(crate) type SpecializerPredicate = Arc<dyn Fn(PolarValue) -> bool + Send + Sync>;
+
 /// Maintain mappings and caches for Rust classes & instances
 #[derive(Clone)]
 pub struct Host {
@@ -42,11 +52,28 @@ pub struct Host {
     /// Map of cached instances
     instances: HashMap<u64, class::Instance>,
 
+    /// Map from the addresses of cached instances to their IDs, so that the same value gets
+    /// the same ID each time it's cached
+    instance_ids: HashMap<usize, u64>,
+
     /// Map from type IDs, to class names
     /// This helps us go from a generic type `T` to the
     /// class name it is registered as
     class_names: HashMap<std::any::TypeId, String>,
 
+    /// Map from rule names to the fact sources that answer them
+    pub(crate) fact_sources: FactSources,
+
+    /// Map from names to the predicates registered as specializers
+    pub(crate) specializers: HashMap<String, SpecializerPredicate>,
+
+    /// Hooks that intercept the events of queries
+    pub(crate) hooks: QueryHooks,
+
+    /// How long queries may run, if not as long as the `POLAR_TIMEOUT_MS` environment
+    /// variable says
+    pub(crate) query_timeout: Option<Duration>,
+
     pub accept_expression: bool,
 }
 
@@ -56,6 +83,11 @@ impl Host {
             class_names: HashMap::new(),
             classes: HashMap::new(),
             instances: HashMap::new(),
+            instance_ids: HashMap::new(),
+            fact_sources: FactSources::default(),
+            specializers: HashMap::new(),
+            hooks: QueryHooks::default(),
+            query_timeout: None,
             accept_expression: false,
             polar,
         };
@@ -96,17 +128,22 @@ impl Host {
     /// Returns an instance of `Type` for this class.
     pub fn cache_class(&mut self, class: Class, name: String) -> crate::Result<String> {
         // Insert into default classes here so that we don't repeat this the first
-        // time we see an instance.
-        DEFAULT_CLASSES
-            .write()
-            .unwrap()
-            .entry(class.type_id)
-            .or_insert_with(|| class.clone());
+        // time we see an instance. Classes defined at runtime share a type, so they're
+        // looked up by name instead.
+        if !class.dynamic {
+            DEFAULT_CLASSES
+                .write()
+                .unwrap()
+                .entry(class.type_id)
+                .or_insert_with(|| class.clone());
+        }
 
         if self.classes.contains_key(&name) {
             Err(OsoError::DuplicateClassError { name })
         } else {
-            self.class_names.insert(class.type_id, name.clone());
+            if !class.dynamic {
+                self.class_names.insert(class.type_id, name.clone());
+            }
             self.classes.insert(name.clone(), class);
             Ok(name)
         }
@@ -117,7 +154,7 @@ impl Host {
     pub fn register_mros(&self) -> crate::Result<()> {
         for name in self.classes.keys() {
             if name != "oso::host::Class" {
-                self.polar.register_mro(Symbol(name.clone()), vec![])?;
+                self.polar.register_mro(Symbol::new(name), vec![])?;
             }
         }
         Ok(())
@@ -130,6 +167,15 @@ impl Host {
             .ok_or(OsoError::MissingInstanceError)
     }
 
+    /// The key identifying the instance `id` in data filters, if its class has one.
+    pub fn filter_key(&self, id: u64) -> crate::Result<Option<PolarValue>> {
+        let instance = self.get_instance(id)?;
+        match instance.class(self) {
+            Ok(class) => class.filter_key(self, instance).transpose(),
+            Err(_) => Ok(None),
+        }
+    }
+
     pub fn cache_instance(&mut self, instance: class::Instance, id: Option<u64>) -> u64 {
         // Lookup the class for this instance
         let type_id = instance.type_id();
@@ -143,6 +189,14 @@ impl Host {
             }
         }
 
+        // The cache keeps the values it holds alive, so their addresses aren't reused.
+        let address = instance.address();
+        if id.is_none() {
+            if let Some(&id) = self.instance_ids.get(&address) {
+                return id;
+            }
+        }
+
         let id = id.unwrap_or_else(|| self.polar.get_external_id());
         tracing::trace!(
             "insert instance {:?} {:?}, instances: {:?}",
@@ -151,6 +205,7 @@ impl Host {
             self.instances.keys().collect::<Vec<_>>()
         );
         self.instances.insert(id, instance);
+        self.instance_ids.entry(address).or_insert(id);
         id
     }
 
@@ -185,6 +240,17 @@ impl Host {
         Ok(res)
     }
 
+    /// Whether `value` satisfies the predicate registered as the specializer `@name`.
+    pub fn satisfies(&self, value: PolarValue, name: &str) -> crate::Result<bool> {
+        let predicate = self
+            .specializers
+            .get(name)
+            .ok_or_else(|| OsoError::Custom {
+                message: format!("No predicate registered for specializer @{}", name),
+            })?;
+        Ok(predicate(value))
+    }
+
     pub fn is_subspecializer(&self, _id: u64, _left_tag: &str, _right_tag: &str) -> bool {
         // Rust has no notion of inheritance, so there are no subspecializers.
         false
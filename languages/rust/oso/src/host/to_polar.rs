@@ -6,8 +6,9 @@
 use impl_trait_for_tuples::*;
 
 use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, LinkedList, VecDeque};
+use std::sync::{Arc, Weak};
 
-use super::DEFAULT_CLASSES;
+use super::{Instance, InstanceHandle, DEFAULT_CLASSES};
 use crate::PolarValue;
 
 /// Convert Rust types to Polar types.
@@ -57,26 +58,80 @@ pub trait ToPolar {
     fn to_polar(self) -> PolarValue;
 }
 
-impl<C: crate::PolarClass + Send + Sync> ToPolar for C {
-    fn to_polar(self) -> PolarValue {
-        let registered = DEFAULT_CLASSES
-            .read()
+/// Register the default class of `C`, for instances of classes that aren't registered.
+fn register_default_class<C: crate::PolarClass>() {
+    let registered = DEFAULT_CLASSES
+        .read()
+        .unwrap()
+        .get(&std::any::TypeId::of::<C>())
+        .is_some();
+
+    if !registered {
+        DEFAULT_CLASSES
+            .write()
             .unwrap()
-            .get(&std::any::TypeId::of::<C>())
-            .is_some();
-
-        if !registered {
-            DEFAULT_CLASSES
-                .write()
-                .unwrap()
-                .entry(std::any::TypeId::of::<C>())
-                .or_insert_with(C::get_polar_class);
-        }
+            .entry(std::any::TypeId::of::<C>())
+            .or_insert_with(C::get_polar_class);
+    }
+}
 
+impl<C: crate::PolarClass + Send + Sync> ToPolar for C {
+    fn to_polar(self) -> PolarValue {
+        register_default_class::<C>();
         PolarValue::new_from_instance(self)
     }
 }
 
+/// Shared values are passed to Polar as instances of their class without being copied, and
+/// the same value is the same instance however many times it's converted. Use `Arc`s for the
+/// fields of object graphs with back-references, e.g., from a team to its organization, so
+/// that `team.org = org` holds without an equality check.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::{Arc, Weak};
+/// use oso::{Oso, PolarClass};
+///
+/// #[derive(PolarClass)]
+/// struct Org {
+///     #[polar(attribute)]
+///     teams: Vec<Arc<Team>>,
+/// }
+///
+/// #[derive(PolarClass)]
+/// struct Team {
+///     #[polar(attribute)]
+///     org: Weak<Org>,
+/// }
+///
+/// let org = Arc::new_cyclic(|org| Org {
+///     teams: vec![Arc::new(Team { org: org.clone() })],
+/// });
+///
+/// let mut oso = Oso::new();
+/// oso.load_str("in_own_org(org) if team in org.teams and team.org = org;").unwrap();
+/// let mut query = oso.query_rule("in_own_org", (org,)).unwrap();
+/// assert!(query.next().unwrap().is_ok());
+/// ```
+impl<C: crate::PolarClass + Send + Sync> ToPolar for Arc<C> {
+    fn to_polar(self) -> PolarValue {
+        register_default_class::<C>();
+        PolarValue::Instance(Instance::new_shared(self))
+    }
+}
+
+/// Weak references are converted like [`Arc`]s while their value is alive, and to `nil`
+/// after it's dropped.
+impl<C: crate::PolarClass + Send + Sync> ToPolar for Weak<C> {
+    fn to_polar(self) -> PolarValue {
+        match self.upgrade() {
+            Some(shared) => shared.to_polar(),
+            None => Option::<PolarValue>::None.to_polar(),
+        }
+    }
+}
+
 pub trait ToPolarResult {
     fn to_polar_result(self) -> crate::Result<PolarValue>;
 }
@@ -296,6 +351,16 @@ impl ToPolar for PolarValue {
     }
 }
 
+impl ToPolar for InstanceHandle {
+    fn to_polar(self) -> PolarValue {
+        PolarValue::Instance(self.0)
+    }
+}
+
+/// Options are passed to Polar as instances of the `Option` class. `None` is equal to the
+/// `nil` constant, so `is_nil(x.field)` and `x.?field` treat a getter returning `None` as
+/// missing. `Some` values are not unwrapped automatically: use `x.unwrap()`, or iterate with
+/// `value in x`.
 impl<T: ToPolar> ToPolar for Option<T> {
     fn to_polar(self) -> PolarValue {
         PolarValue::new_from_instance(self.map(|t| t.to_polar()))
@@ -55,7 +55,7 @@ impl PolarValue {
             Value::Dictionary(dict) => {
                 let mut map = HashMap::new();
                 for (k, v) in &dict.fields {
-                    let key = k.0.clone();
+                    let key = k.to_string();
                     let value = PolarValue::from_term(v, host)?;
                     map.insert(key, value);
                 }
@@ -71,7 +71,7 @@ impl PolarValue {
                 }
                 PolarValue::List(list)
             }
-            Value::Variable(Symbol(sym)) => PolarValue::Variable(sym.clone()),
+            Value::Variable(sym) => PolarValue::Variable(sym.to_string()),
             Value::Expression(_) => {
                 return Err(crate::OsoError::Custom {
                     message: r#"
@@ -100,7 +100,7 @@ This may mean you performed an operation in your policy over an unbound variable
             PolarValue::Map(map) => {
                 let mut dict = Dictionary::new();
                 for (k, v) in map {
-                    let key = Symbol(k.clone());
+                    let key = Symbol::new(k);
                     let value = v.to_term(host);
                     dict.fields.insert(key, value);
                 }
@@ -108,11 +108,18 @@ This may mean you performed an operation in your policy over an unbound variable
             }
             PolarValue::Instance(instance) => {
                 let id = host.cache_instance(instance.clone(), None);
+                // Only registered classes have a class repr, which sandboxes use to check
+                // which methods an instance allows.
+                let class = instance.class(host).ok();
+                let class_repr = class.map(|class| class.name.clone());
+                let repr = class
+                    .and_then(|class| class.repr(instance))
+                    .unwrap_or_else(|| std::any::type_name::<Self>().to_owned());
                 Value::ExternalInstance(ExternalInstance {
                     constructor: None,
                     instance_id: id,
-                    repr: Some(std::any::type_name::<Self>().to_owned()),
-                    class_repr: Some(std::any::type_name::<Self>().to_owned()),
+                    repr: Some(repr),
+                    class_repr,
                     class_id: None,
                 })
             }
@@ -123,7 +130,7 @@ This may mean you performed an operation in your policy over an unbound variable
                 }
                 Value::List(list)
             }
-            PolarValue::Variable(s) => Value::Variable(Symbol(s.clone())),
+            PolarValue::Variable(s) => Value::Variable(Symbol::new(s)),
         };
         Term::new_from_ffi(value)
     }
@@ -0,0 +1,202 @@
+//! Just enough HTTP/1.1 to exchange JSON messages between the authorization server and its
+//! clients: requests & responses with a `Content-Length` body, over persistent connections.
+use std::io::{self, BufRead, Read, Write};
+
+/// Milliseconds remaining before the client gives up on a request.
+pub(crate) const DEADLINE_HEADER: &str = "Oso-Deadline-Ms";
+
+/// Bodies larger than this are rejected rather than buffered.
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+/// Start lines & header lines longer than this are rejected rather than buffered.
+const MAX_LINE_SIZE: usize = 8 * 1024;
+
+/// Messages with more headers than this are rejected.
+const MAX_HEADERS: usize = 100;
+
+#[cfg(feature = "server")]
+pub(crate) struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+#[cfg(feature = "server")]
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        header(&self.headers, name)
+    }
+
+    /// HTTP/1.1 connections are persistent unless either side asks to close them.
+    pub fn keep_alive(&self) -> bool {
+        !self
+            .header("connection")
+            .is_some_and(|value| value.eq_ignore_ascii_case("close"))
+    }
+}
+
+#[cfg(feature = "client")]
+pub(crate) struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+#[cfg(feature = "client")]
+impl Response {
+    pub fn keep_alive(&self) -> bool {
+        !header(&self.headers, "connection")
+            .is_some_and(|value| value.eq_ignore_ascii_case("close"))
+    }
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
+
+/// Read a line of at most `MAX_LINE_SIZE` bytes into `line`, returning its length.
+fn read_line<R: BufRead>(reader: &mut R, line: &mut String) -> io::Result<usize> {
+    let read = reader.by_ref().take(MAX_LINE_SIZE as u64).read_line(line)?;
+    if read == MAX_LINE_SIZE && !line.ends_with('\n') {
+        return Err(invalid("line too long"));
+    }
+    Ok(read)
+}
+
+/// A start line, headers, and body.
+type Message = (String, Vec<(String, String)>, Vec<u8>);
+
+/// Read a start line & headers, followed by the body they describe. Returns `None` if the
+/// connection was closed before a message began.
+fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Message>> {
+    let mut start = String::new();
+    if read_line(reader, &mut start)? == 0 {
+        return Ok(None);
+    }
+
+    let mut headers = vec![];
+    loop {
+        let mut line = String::new();
+        if read_line(reader, &mut line)? == 0 {
+            return Err(invalid("unexpected end of headers"));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(invalid("too many headers"));
+        }
+        let (key, value) = line
+            .split_once(':')
+            .ok_or_else(|| invalid("malformed header"))?;
+        headers.push((key.trim().to_owned(), value.trim().to_owned()));
+    }
+
+    let length = match header(&headers, "content-length") {
+        Some(length) => length
+            .parse()
+            .map_err(|_| invalid("malformed content-length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY_SIZE {
+        return Err(invalid("body too large"));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Some((start.trim_end().to_owned(), headers, body)))
+}
+
+fn write_message<W: Write>(
+    writer: &mut W,
+    start: &str,
+    headers: &[(&str, String)],
+    body: &[u8],
+) -> io::Result<()> {
+    let mut head = format!("{}\r\n", start);
+    for (key, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", key, value));
+    }
+    head.push_str(&format!(
+        "Content-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+        body.len()
+    ));
+    writer.write_all(head.as_bytes())?;
+    writer.write_all(body)?;
+    writer.flush()
+}
+
+#[cfg(feature = "server")]
+pub(crate) fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Option<Request>> {
+    let (start, headers, body) = match read_message(reader)? {
+        Some(message) => message,
+        None => return Ok(None),
+    };
+    let mut parts = start.split_whitespace();
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(path), Some(_version)) => Ok(Some(Request {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            headers,
+            body,
+        })),
+        _ => Err(invalid("malformed request line")),
+    }
+}
+
+#[cfg(feature = "client")]
+pub(crate) fn read_response<R: BufRead>(reader: &mut R) -> io::Result<Response> {
+    let (start, headers, body) =
+        read_message(reader)?.ok_or_else(|| invalid("connection closed"))?;
+    let status = start
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| invalid("malformed status line"))?;
+    Ok(Response {
+        status,
+        headers,
+        body,
+    })
+}
+
+#[cfg(feature = "client")]
+pub(crate) fn write_request<W: Write>(
+    writer: &mut W,
+    path: &str,
+    headers: &[(&str, String)],
+    body: &[u8],
+) -> io::Result<()> {
+    write_message(writer, &format!("POST {} HTTP/1.1", path), headers, body)
+}
+
+#[cfg(feature = "server")]
+pub(crate) fn write_response<W: Write>(
+    writer: &mut W,
+    status: u16,
+    headers: &[(&str, String)],
+    body: &[u8],
+) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        504 => "Gateway Timeout",
+        _ => "Internal Server Error",
+    };
+    write_message(
+        writer,
+        &format!("HTTP/1.1 {} {}", status, reason),
+        headers,
+        body,
+    )
+}
@@ -76,17 +76,78 @@
 #[macro_use]
 pub mod macros;
 
+mod audit;
 pub(crate) mod builtins;
+mod claims;
+#[cfg(feature = "client")]
+pub mod client;
+mod compiled;
+mod dispatch;
+mod enforcer;
 pub mod errors;
 mod extras;
+mod facts;
+mod flags;
+mod hooks;
 mod host;
+#[cfg(any(feature = "client", feature = "server"))]
+mod http;
+mod load_options;
+pub mod mock;
 mod oso;
 mod query;
+mod query_builder;
+mod relationships;
+#[cfg(feature = "server")]
+pub mod server;
+mod session;
+#[cfg(feature = "serde_json")]
+mod simulation;
+mod stdlib;
+#[cfg(feature = "serde_json")]
+mod virtual_host;
 
-pub use crate::oso::{Action, Oso};
+#[cfg(feature = "serde_json")]
+pub use crate::oso::parse_to_json;
+pub use crate::oso::{Action, Decision, Explanation, InlineQueryReport, Oso, ShadowDivergence};
+pub use audit::{DecisionRecord, Replay};
+pub use claims::{Claims, DateTime};
+pub use compiled::CompiledCheck;
+pub use dispatch::{Dispatcher, ExternalCall};
+pub use enforcer::{CachingEnforcer, Enforcer};
 pub use errors::{OsoError, Result};
-pub use host::{Class, ClassBuilder, FromPolar, FromPolarList, PolarValue, ToPolar, ToPolarList};
-pub use query::{Query, ResultSet};
+pub use facts::{CacheHint, FactSource, Facts};
+#[cfg(feature = "csv")]
+pub use facts::{Column, FactSchema};
+pub use flags::{FeatureFlags, FlagProvider, FlagRefresher};
+pub use hooks::{HookAction, QueryHook};
+pub use host::{
+    Class, ClassBuilder, FromPolar, FromPolarList, InstanceHandle, PolarValue, ToPolar, ToPolarList,
+};
+#[cfg(feature = "serde_json")]
+pub use host::{DynClassSpec, DynObject};
+pub use load_options::LoadOptions;
+pub use query::{AnnotatedRule, Cursor, Page, Query, ResultSet, SortOrder};
+pub use query_builder::{QueryBuilder, RuleCall, RuleSignature};
+pub use relationships::{
+    ObjectRef, RelationTuple, RelationshipStore, RelationshipSync, SubjectRef, TupleOperation,
+    WatchBatch,
+};
+pub use session::{ActorAttributeProvider, ActorSession};
+#[cfg(feature = "serde_json")]
+pub use simulation::{DecisionChange, DecisionMatrix, Fixture};
+#[cfg(feature = "serde_json")]
+pub use virtual_host::VirtualHost;
+
+pub use polar_core::constraints::{CmpOp, ConstraintExpr, Operand};
+pub use polar_core::events::QueryEvent;
+pub use polar_core::kb::DuplicateClauses;
+pub use polar_core::limits::Limits;
+pub use polar_core::quota::{RuleQuota, ScopeQuota};
+pub use polar_core::rego::RegoIssue;
+pub use polar_core::rules::TemplateInfo;
+pub use polar_core::sandbox::Sandbox;
+pub use polar_core::TermFormatter;
 
 use polar_core::polar::Polar;
 
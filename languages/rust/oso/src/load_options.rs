@@ -0,0 +1,101 @@
+//! Options for loading policies with [`Oso::load_str_with`](crate::Oso::load_str_with) and
+//! [`Oso::load_files_with`](crate::Oso::load_files_with).
+use polar_core::kb::DuplicateClauses;
+use polar_core::sandbox::Sandbox;
+
+/// How to load a policy. The default options load it the way [`Oso::load_str`] and
+/// [`Oso::load_files`] do.
+///
+/// [`Oso::load_str`]: crate::Oso::load_str
+/// [`Oso::load_files`]: crate::Oso::load_files
+///
+/// # Examples
+///
+/// ```
+/// use oso::{LoadOptions, Oso};
+///
+/// let mut oso = Oso::new();
+/// oso.load_str_with(
+///     r#"allow("alice", "read", "doc"); ?= allow("bob", "read", "doc");"#,
+///     &LoadOptions::new().tag("policy.polar").run_inline_queries(false),
+/// )
+/// .unwrap();
+/// assert!(oso.is_allowed("alice", "read", "doc").unwrap());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoadOptions {
+    /// The tenant to load the policy for, replacing its policy, as with
+    /// [`Oso::load_str_for_tenant`](crate::Oso::load_str_for_tenant), or `None` to load the
+    /// policy shared by all tenants.
+    pub scope: Option<String>,
+    /// Fail to load a policy with warnings, as if they were errors. The policy isn't loaded.
+    pub strict: bool,
+    /// Run the policy's inline queries once it's loaded, failing the load if one fails. If
+    /// not, they're discarded.
+    pub run_inline_queries: bool,
+    /// What loading a clause that's the same as one already loaded for its rule, but for the
+    /// names of its variables, does. Tenant policies keep every clause.
+    pub dedup: DuplicateClauses,
+    /// The name of policies loaded from strings, shown in error messages. Files are named by
+    /// their paths.
+    pub tag: Option<String>,
+    /// Restrict the application classes and methods the policy's rules may use, as with
+    /// [`Oso::load_str_sandboxed`](crate::Oso::load_str_sandboxed).
+    pub sandbox: Option<Sandbox>,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            scope: None,
+            strict: false,
+            run_inline_queries: true,
+            dedup: DuplicateClauses::Keep,
+            tag: None,
+            sandbox: None,
+        }
+    }
+}
+
+impl LoadOptions {
+    /// Create the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the policy for `tenant`.
+    pub fn scope(mut self, tenant: &str) -> Self {
+        self.scope = Some(tenant.to_owned());
+        self
+    }
+
+    /// Fail to load a policy with warnings.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Run the policy's inline queries, or discard them.
+    pub fn run_inline_queries(mut self, run: bool) -> Self {
+        self.run_inline_queries = run;
+        self
+    }
+
+    /// Add, warn about, or skip duplicated clauses.
+    pub fn dedup(mut self, mode: DuplicateClauses) -> Self {
+        self.dedup = mode;
+        self
+    }
+
+    /// Name policies loaded from strings `tag`.
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tag = Some(tag.to_owned());
+        self
+    }
+
+    /// Restrict the policy to the classes and methods allowed by `sandbox`.
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+}
@@ -0,0 +1,343 @@
+//! An [`Enforcer`] for application tests that doesn't evaluate a policy.
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use polar_core::filter::Filter;
+
+use crate::{Enforcer, OsoError, PolarValue, ToPolar};
+
+/// Matches one argument of a call to a [`MockOso`].
+///
+/// Any [`ToPolar`] value can be used as a matcher for equal values.
+///
+/// ```
+/// use oso::mock::Matcher;
+/// use oso::ToPolar;
+///
+/// assert!(Matcher::from("read").matches(&"read".to_polar()));
+/// assert!(Matcher::any().matches(&1.to_polar()));
+/// assert!(Matcher::with(|v| v != &"delete".to_polar()).matches(&"read".to_polar()));
+/// ```
+pub struct Matcher(MatcherKind);
+
+enum MatcherKind {
+    Any,
+    Eq(PolarValue),
+    Fn(Box<dyn Fn(&PolarValue) -> bool + Send + Sync>),
+}
+
+impl Matcher {
+    /// Match any value.
+    pub fn any() -> Self {
+        Self(MatcherKind::Any)
+    }
+
+    /// Match values for which `f` returns `true`.
+    pub fn with<F>(f: F) -> Self
+    where
+        F: Fn(&PolarValue) -> bool + Send + Sync + 'static,
+    {
+        Self(MatcherKind::Fn(Box::new(f)))
+    }
+
+    /// Match application instances of type `T` for which `f` returns `true`.
+    pub fn instance<T, F>(f: F) -> Self
+    where
+        T: 'static,
+        F: Fn(&T) -> bool + Send + Sync + 'static,
+    {
+        Self::with(move |value| match value {
+            PolarValue::Instance(instance) => instance.downcast::<T>(None).is_ok_and(&f),
+            _ => false,
+        })
+    }
+
+    pub fn matches(&self, value: &PolarValue) -> bool {
+        match &self.0 {
+            MatcherKind::Any => true,
+            MatcherKind::Eq(expected) => expected == value,
+            MatcherKind::Fn(f) => f(value),
+        }
+    }
+}
+
+impl<T: ToPolar> From<T> for Matcher {
+    fn from(value: T) -> Self {
+        Self(MatcherKind::Eq(value.to_polar()))
+    }
+}
+
+impl fmt::Debug for Matcher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.0 {
+            MatcherKind::Any => write!(f, "_"),
+            MatcherKind::Eq(value) => write!(f, "{:?}", value),
+            MatcherKind::Fn(_) => write!(f, "<fn>"),
+        }
+    }
+}
+
+/// A call made to a [`MockOso`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Call {
+    Authorize {
+        actor: PolarValue,
+        action: PolarValue,
+        resource: PolarValue,
+    },
+    AuthorizedActions {
+        actor: PolarValue,
+        resource: PolarValue,
+    },
+    AuthorizedQuery {
+        actor: PolarValue,
+        action: PolarValue,
+        resource_type: String,
+    },
+}
+
+struct Rule {
+    allow: bool,
+    actor: Matcher,
+    action: Matcher,
+    resource: Matcher,
+}
+
+impl Rule {
+    fn matches(&self, actor: &PolarValue, action: &PolarValue, resource: &PolarValue) -> bool {
+        self.actor.matches(actor) && self.action.matches(action) && self.resource.matches(resource)
+    }
+}
+
+/// An [`Enforcer`] whose decisions are programmed by the test using it, and which records
+/// the calls made to it.
+///
+/// A request is allowed if it matches an [`allow`](MockOso::allow) rule and no
+/// [`deny`](MockOso::deny) rule. Everything else is denied, unless the mock was created with
+/// [`allow_by_default`](MockOso::allow_by_default).
+///
+/// # Examples
+///
+/// ```
+/// use oso::mock::{Matcher, MockOso};
+/// use oso::{Enforcer, ToPolar};
+///
+/// let mock = MockOso::new()
+///     .allow("alice", Matcher::any(), Matcher::any())
+///     .deny(Matcher::any(), "delete", "prod");
+///
+/// assert!(mock.authorize("alice".to_polar(), "read".to_polar(), "prod".to_polar()).unwrap());
+/// assert!(!mock.authorize("alice".to_polar(), "delete".to_polar(), "prod".to_polar()).unwrap());
+/// mock.assert_authorize_called("alice", "delete", Matcher::any());
+/// ```
+pub struct MockOso {
+    default: bool,
+    rules: Vec<Rule>,
+    filters: HashMap<String, Filter>,
+    calls: Mutex<Vec<Call>>,
+}
+
+impl Default for MockOso {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockOso {
+    /// Create a mock that denies every request.
+    pub fn new() -> Self {
+        Self {
+            default: false,
+            rules: vec![],
+            filters: HashMap::new(),
+            calls: Mutex::new(vec![]),
+        }
+    }
+
+    /// Create a mock that allows every request that doesn't match a `deny` rule.
+    pub fn allow_by_default() -> Self {
+        Self {
+            default: true,
+            ..Self::new()
+        }
+    }
+
+    /// Allow requests matching `actor`, `action`, and `resource`.
+    pub fn allow(
+        mut self,
+        actor: impl Into<Matcher>,
+        action: impl Into<Matcher>,
+        resource: impl Into<Matcher>,
+    ) -> Self {
+        self.rules.push(Rule {
+            allow: true,
+            actor: actor.into(),
+            action: action.into(),
+            resource: resource.into(),
+        });
+        self
+    }
+
+    /// Deny requests matching `actor`, `action`, and `resource`, even if they match an
+    /// `allow` rule.
+    pub fn deny(
+        mut self,
+        actor: impl Into<Matcher>,
+        action: impl Into<Matcher>,
+        resource: impl Into<Matcher>,
+    ) -> Self {
+        self.rules.push(Rule {
+            allow: false,
+            actor: actor.into(),
+            action: action.into(),
+            resource: resource.into(),
+        });
+        self
+    }
+
+    /// Return `filter` from [`Enforcer::authorized_query`] calls for `resource_type`.
+    pub fn with_filter(mut self, resource_type: &str, filter: Filter) -> Self {
+        self.filters.insert(resource_type.to_owned(), filter);
+        self
+    }
+
+    /// Return the calls made to the mock, oldest first.
+    pub fn calls(&self) -> Vec<Call> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Forget the calls made so far.
+    pub fn clear_calls(&self) {
+        self.calls.lock().unwrap().clear();
+    }
+
+    /// Return how many `authorize` calls matched `actor`, `action`, and `resource`.
+    pub fn authorize_call_count(
+        &self,
+        actor: impl Into<Matcher>,
+        action: impl Into<Matcher>,
+        resource: impl Into<Matcher>,
+    ) -> usize {
+        let rule = Rule {
+            allow: true,
+            actor: actor.into(),
+            action: action.into(),
+            resource: resource.into(),
+        };
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|call| match call {
+                Call::Authorize {
+                    actor,
+                    action,
+                    resource,
+                } => rule.matches(actor, action, resource),
+                _ => false,
+            })
+            .count()
+    }
+
+    /// Panic unless an `authorize` call matched `actor`, `action`, and `resource`.
+    pub fn assert_authorize_called(
+        &self,
+        actor: impl Into<Matcher>,
+        action: impl Into<Matcher>,
+        resource: impl Into<Matcher>,
+    ) {
+        let (actor, action, resource) = (actor.into(), action.into(), resource.into());
+        let description = format!("({:?}, {:?}, {:?})", actor, action, resource);
+        if self.authorize_call_count(actor, action, resource) == 0 {
+            panic!(
+                "expected an authorize call matching {}, got: {:#?}",
+                description,
+                self.calls()
+            );
+        }
+    }
+
+    fn record(&self, call: Call) {
+        self.calls.lock().unwrap().push(call);
+    }
+
+    fn decide(&self, actor: &PolarValue, action: &PolarValue, resource: &PolarValue) -> bool {
+        let mut allowed = self.default;
+        for rule in &self.rules {
+            if rule.matches(actor, action, resource) {
+                if !rule.allow {
+                    return false;
+                }
+                allowed = true;
+            }
+        }
+        allowed
+    }
+}
+
+impl Enforcer for MockOso {
+    fn authorize(
+        &self,
+        actor: PolarValue,
+        action: PolarValue,
+        resource: PolarValue,
+    ) -> crate::Result<bool> {
+        let allowed = self.decide(&actor, &action, &resource);
+        self.record(Call::Authorize {
+            actor,
+            action,
+            resource,
+        });
+        Ok(allowed)
+    }
+
+    /// Return the actions given as values in matching `allow` rules that aren't denied. If a
+    /// matching `allow` rule matches any action, or the mock allows by default, the result is
+    /// an unbound [`PolarValue::Variable`] instead.
+    fn authorized_actions(
+        &self,
+        actor: PolarValue,
+        resource: PolarValue,
+    ) -> crate::Result<Vec<PolarValue>> {
+        let mut actions = vec![];
+        let mut any = self.default;
+        for rule in self.rules.iter().filter(|rule| rule.allow) {
+            if !(rule.actor.matches(&actor) && rule.resource.matches(&resource)) {
+                continue;
+            }
+            match &rule.action.0 {
+                MatcherKind::Eq(action) => {
+                    if !actions.contains(action) && self.decide(&actor, action, &resource) {
+                        actions.push(action.clone());
+                    }
+                }
+                _ => any = true,
+            }
+        }
+        if any {
+            actions = vec![PolarValue::Variable("action".to_owned())];
+        }
+        self.record(Call::AuthorizedActions { actor, resource });
+        Ok(actions)
+    }
+
+    fn authorized_query(
+        &self,
+        actor: PolarValue,
+        action: PolarValue,
+        resource_type: &str,
+    ) -> crate::Result<Filter> {
+        self.record(Call::AuthorizedQuery {
+            actor,
+            action,
+            resource_type: resource_type.to_owned(),
+        });
+        self.filters
+            .get(resource_type)
+            .cloned()
+            .ok_or_else(|| OsoError::Custom {
+                message: format!("MockOso has no filter for {}", resource_type),
+            })
+    }
+}
@@ -1,23 +1,56 @@
 //! Communicate with the Polar virtual machine: load rules, make queries, etc/
-use polar_core::sources::Source;
-use polar_core::terms::{Call, Symbol, Term, Value};
+use polar_core::constraints::ConstraintExpr;
+use polar_core::data_filtering::Types;
+use polar_core::error::RuntimeError;
+use polar_core::events::ResultEvent;
+use polar_core::filter::{Filter, PrefetchHint};
+use polar_core::kb::{DuplicateClauses, KnowledgeBase};
+use polar_core::limits::Limits;
+use polar_core::lint::LintRule;
+use polar_core::messages::MessageKind;
+use polar_core::quota::ScopeQuota;
+use polar_core::rego::{self, RegoIssue};
+use polar_core::rules::{DefaultDecision, TemplateInfo};
+use polar_core::sandbox::Sandbox;
+use polar_core::sources::{Source, SourceReader};
+use polar_core::sql::SqlTemplate;
+use polar_core::terms::{
+    Call, Dictionary, InstanceLiteral, IntegerOverflow, Operation, Operator, Pattern, Symbol, Term,
+    Value,
+};
+use polar_core::{RewritePass, TermFormatter};
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs::File;
 use std::hash::Hash;
 use std::io::Read;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use crate::host::Host;
-use crate::query::Query;
-use crate::{FromPolar, OsoError, PolarValue, ToPolar, ToPolarList};
+use crate::query::{AnnotatedRule, Query, ResultSet};
+use crate::stdlib;
+use crate::{
+    FactSource, FromPolar, LoadOptions, OsoError, PolarValue, QueryHook, ToPolar, ToPolarList,
+};
 
 /// Oso is the main struct you interact with. It is an instance of the Oso authorization library
 /// and contains the polar language knowledge base and query engine.
 #[derive(Clone)]
 pub struct Oso {
-    inner: Arc<polar_core::polar::Polar>,
-    host: Host,
+    pub(crate) inner: Arc<polar_core::polar::Polar>,
+    pub(crate) host: Host,
+    /// Fields of the types that may be filtered by [`Oso::authorized_query`].
+    filter_types: Arc<Types>,
+    /// Called with requests on which the shadow policy disagrees. See [`Oso::shadow_load`].
+    shadow_observer: Option<ShadowObserver>,
+    /// Whether [`Oso::query`] accepts Polar source. See [`Oso::set_string_queries`].
+    string_queries: bool,
+    /// Whether policies are loaded with the standard library. See [`Oso::load_stdlib`].
+    stdlib: bool,
+    /// The results of the inline queries of the last load. See [`Oso::inline_query_reports`].
+    inline_query_reports: Arc<RwLock<Vec<InlineQueryReport>>>,
 }
 
 impl Default for Oso {
@@ -49,13 +82,142 @@ impl<T: FromPolar> FromPolar for Action<T> {
     }
 }
 
+/// The outcome of an authorization request made with [`Oso::decide`].
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub enum Decision {
+    /// An `allow` rule matched, and no `deny` rule did, or neither matched and the policy's
+    /// default decision allowed the request.
+    Allow,
+    /// A `deny` rule matched, or no rule matched and the policy's default decision is
+    /// `default deny;`. Deny rules override allow rules.
+    Deny,
+    /// Neither an `allow` nor a `deny` rule matched, and the policy's default decision, if
+    /// any, didn't allow the request.
+    NotApplicable,
+}
+
+impl Decision {
+    pub fn is_allowed(self) -> bool {
+        self == Decision::Allow
+    }
+}
+
+/// A decision made by [`Oso::explain`], with the rules that made it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Explanation {
+    pub decision: Decision,
+    /// The annotated rules applied to derive the `deny` or `allow` result that made the
+    /// decision, outermost first. Empty for [`Decision::NotApplicable`].
+    pub annotated_rules: Vec<AnnotatedRule>,
+}
+
+/// A request on which the shadow policy loaded with [`Oso::shadow_load`] disagreed with the
+/// loaded policy.
+#[derive(Clone, Debug)]
+pub struct ShadowDivergence {
+    pub actor: PolarValue,
+    pub action: PolarValue,
+    pub resource: PolarValue,
+    /// The decision of the loaded policy, which was returned to the caller.
+    pub decision: Decision,
+    /// The decision of the shadow policy, or the error raised while evaluating it.
+    pub shadow: Result<Decision, String>,
+}
+
+type ShadowObserver = Arc<dyn Fn(&ShadowDivergence) + Send + Sync>;
+
+/// The results of an inline query, `?= query;`, run when its policy was loaded, so that inline
+/// queries can double as load-time reports, e.g., of how many admin role mappings there are.
+/// Formats as the query and where it was loaded from, then its results, e.g.,
+/// `has_role(user, "admin", org) at line 3, column 4: 2 results: {user: "alice", org: "acme"},
+/// {user: "bob", org: "acme"}`.
+#[derive(Clone)]
+pub struct InlineQueryReport {
+    /// The query and where it was loaded from.
+    pub location: String,
+    /// The tenant the query ran for, if it was loaded with [`Oso::load_str_for_tenant`].
+    pub tenant: Option<String>,
+    /// Every result of the query, with the bindings of its variables.
+    pub results: Vec<ResultSet>,
+}
+
+impl InlineQueryReport {
+    pub fn count(&self) -> usize {
+        self.results.len()
+    }
+}
+
+impl fmt::Display for InlineQueryReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.location)?;
+        if let Some(tenant) = &self.tenant {
+            write!(f, " for tenant {}", tenant)?;
+        }
+        let plural = if self.count() == 1 { "" } else { "s" };
+        write!(f, ": {} result{}", self.count(), plural)?;
+        for (i, result) in self.results.iter().enumerate() {
+            let mut bindings = result
+                .bindings()
+                .iter()
+                .map(|(name, value)| format!("{}: {}", name, value))
+                .collect::<Vec<_>>();
+            if bindings.is_empty() {
+                continue;
+            }
+            bindings.sort();
+            let separator = if i == 0 { ": " } else { ", " };
+            write!(f, "{}{{{}}}", separator, bindings.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) type KnowledgeBaseRef = Arc<RwLock<KnowledgeBase>>;
+
+/// Whether `kb` has rules `name` for requests in `scope`, i.e., rules loaded without a scope
+/// or in `scope` or the scopes it encloses.
+fn has_rule(kb: &KnowledgeBaseRef, scope: Option<&str>, name: &str) -> bool {
+    let kb = kb.read().unwrap();
+    let name = Symbol::new(name);
+    kb.get_generic_rule(&name).is_some()
+        || scope.is_some_and(|scope| {
+            kb.get_scope(scope)
+                .into_iter()
+                .chain(kb.enclosing_scopes(scope))
+                .any(|scope| scope.get_generic_rule(&name).is_some())
+        })
+}
+
+/// Whether to query the `allow` rules for a request in `scope`. Querying a rule that isn't
+/// defined is an error, unless there's a default decision to decide the request instead.
+pub(crate) fn should_query_allow(kb: &KnowledgeBaseRef, scope: Option<&str>) -> bool {
+    let kb = kb.read().unwrap();
+    let allow = Symbol::new("allow");
+    kb.default_decision(scope).is_none()
+        || kb.get_generic_rule(&allow).is_some()
+        || kb.is_fact_source(&allow)
+        || kb.has_facts(&allow)
+        || matches!(
+            scope.and_then(|scope| kb.get_scope(scope)),
+            Some(scope) if scope.get_generic_rule(&allow).is_some()
+        )
+}
+
 impl Oso {
     /// Create a new instance of Oso. Each instance is separate and can have different rules and classes loaded into it.
     pub fn new() -> Self {
         let inner = Arc::new(polar_core::polar::Polar::new());
         let host = Host::new(inner.clone());
 
-        let mut oso = Self { inner, host };
+        let mut oso = Self {
+            inner,
+            host,
+            filter_types: Arc::new(Types::new()),
+            shadow_observer: None,
+            string_queries: true,
+            stdlib: false,
+            inline_query_reports: Arc::new(RwLock::new(vec![])),
+        };
 
         for class in crate::builtins::classes() {
             oso.register_class(class)
@@ -67,6 +229,21 @@ impl Oso {
     }
 
     /// High level interface for authorization decisions. Makes an allow query with the given actor, action and resource and returns true or false.
+    ///
+    /// As for [`Oso::decide`], a matching `deny` rule denies the request even if an `allow`
+    /// rule matches. If no `allow` rule matches, the policy's default decision applies:
+    /// `default allow;` allows the request, `default allow rule;` allows it if
+    /// `rule(actor, action, resource)` matches, and `default deny;` or no default denies it.
+    ///
+    /// ```
+    /// use oso::Oso;
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.load_str(r#"default allow allow_readonly;
+    ///                 allow_readonly(_actor, "read", _resource);"#).unwrap();
+    /// assert!(oso.is_allowed("alice", "read", "doc").unwrap());
+    /// assert!(!oso.is_allowed("alice", "delete", "doc").unwrap());
+    /// ```
     pub fn is_allowed<Actor, Action, Resource>(
         &self,
         actor: Actor,
@@ -78,16 +255,339 @@ impl Oso {
         Action: ToPolar,
         Resource: ToPolar,
     {
-        let mut query = self.query_rule("allow", (actor, action, resource)).unwrap();
-        match query.next() {
-            Some(Ok(_)) => Ok(true),
-            Some(Err(e)) => Err(e),
-            None => Ok(false),
+        let args = (actor.to_polar(), action.to_polar(), resource.to_polar());
+        self.decide_with(args, false)
+            .map(|explanation| explanation.decision.is_allowed())
+    }
+
+    /// Like [`Oso::is_allowed`], but evaluated against the policy that was loaded at `at`
+    /// rather than the current one, e.g., to check whether a past request would have been
+    /// allowed under the policy at the time.
+    ///
+    /// Every load (and [`Oso::clear_rules`]) starts a new policy epoch. The most recent epochs
+    /// are kept, up to [`Oso::set_max_policy_epochs`], none by default; asking about a time
+    /// before the oldest of them is an error. Registered classes, constants, and facts
+    /// aren't versioned, so the current ones are used.
+    pub fn is_allowed_at<Actor, Action, Resource>(
+        &self,
+        actor: Actor,
+        action: Action,
+        resource: Resource,
+        at: SystemTime,
+    ) -> crate::Result<bool>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+        Resource: ToPolar,
+    {
+        let args = (actor.to_polar(), action.to_polar(), resource.to_polar());
+        self.decide_in(&self.kb_at(at)?, &args)
+            .map(Decision::is_allowed)
+    }
+
+    /// A snapshot of the knowledge base with the rules that were loaded at `at`.
+    pub(crate) fn kb_at(&self, at: SystemTime) -> crate::Result<KnowledgeBaseRef> {
+        let at = to_unix_ms(at);
+        let kb = self
+            .inner
+            .kb
+            .read()
+            .unwrap()
+            .snapshot_at(at)
+            .ok_or(RuntimeError::NoPolicyEpoch { at })
+            .map_err(polar_core::error::PolarError::from)?;
+        Ok(Arc::new(RwLock::new(kb)))
+    }
+
+    /// The times at which each policy epoch available to [`Oso::is_allowed_at`] began, oldest
+    /// first.
+    pub fn policy_epochs(&self) -> Vec<SystemTime> {
+        self.inner
+            .kb
+            .read()
+            .unwrap()
+            .epoch_timestamps()
+            .into_iter()
+            .map(|ms| UNIX_EPOCH + Duration::from_millis(ms))
+            .collect()
+    }
+
+    /// A hash of the loaded rules, to tell which version of the policy made a decision, as
+    /// for [`KnowledgeBase::fingerprint`]. Registered classes, constants, and facts aren't
+    /// part of it.
+    pub fn policy_fingerprint(&self) -> u64 {
+        self.inner.kb.read().unwrap().fingerprint()
+    }
+
+    /// Make an authorization decision in which `deny` rules override `allow` rules.
+    ///
+    /// If the policy defines `deny(actor, action, resource)` rules and one of them matches, the
+    /// request is denied regardless of any `allow` rules. This lets a policy contain
+    /// restrictions that can't be bypassed by adding another `allow` rule. [`Oso::is_allowed`]
+    /// and the [`Enforcer`](crate::Enforcer) implementation for `Oso` decide the same way.
+    ///
+    /// ```
+    /// use oso::{Decision, Oso};
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.load_str(r#"allow(_actor, _action, _resource);
+    ///                 deny(_actor, "delete", "prod");"#).unwrap();
+    /// assert_eq!(oso.decide("alice", "read", "prod").unwrap(), Decision::Allow);
+    /// assert_eq!(oso.decide("alice", "delete", "prod").unwrap(), Decision::Deny);
+    /// ```
+    pub fn decide<Actor, Action, Resource>(
+        &self,
+        actor: Actor,
+        action: Action,
+        resource: Resource,
+    ) -> crate::Result<Decision>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+        Resource: ToPolar,
+    {
+        let args = (actor.to_polar(), action.to_polar(), resource.to_polar());
+        self.decide_with(args, false)
+            .map(|explanation| explanation.decision)
+    }
+
+    /// Like [`Oso::decide`], but also return the annotated rules that made the decision, so
+    /// that it can be attributed, e.g., to the team that owns them. Tracing the queries makes
+    /// this slower than `decide`.
+    ///
+    /// ```
+    /// use oso::{Decision, Oso};
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.load_str(r#"@owner("secteam") deny(_actor, "delete", "prod");
+    ///                 @owner("platform") allow(_actor, _action, _resource);"#).unwrap();
+    /// let explanation = oso.explain("alice", "delete", "prod").unwrap();
+    /// assert_eq!(explanation.decision, Decision::Deny);
+    /// assert_eq!(explanation.annotated_rules[0].annotations["owner"], "secteam");
+    /// ```
+    pub fn explain<Actor, Action, Resource>(
+        &self,
+        actor: Actor,
+        action: Action,
+        resource: Resource,
+    ) -> crate::Result<Explanation>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+        Resource: ToPolar,
+    {
+        let args = (actor.to_polar(), action.to_polar(), resource.to_polar());
+        self.decide_with(args, true)
+    }
+
+    fn decide_with(
+        &self,
+        args: (PolarValue, PolarValue, PolarValue),
+        trace: bool,
+    ) -> crate::Result<Explanation> {
+        let explanation = self.explain_in(&self.inner.kb.clone(), None, &args, trace)?;
+        if self.shadow_observer.is_some() {
+            self.compare_shadow(explanation.decision, args, Oso::decide_in);
+        }
+        Ok(explanation)
+    }
+
+    pub(crate) fn decide_in(
+        &self,
+        kb: &KnowledgeBaseRef,
+        args: &(PolarValue, PolarValue, PolarValue),
+    ) -> crate::Result<Decision> {
+        self.explain_in(kb, None, args, false)
+            .map(|explanation| explanation.decision)
+    }
+
+    /// Decide in `kb` for a request in `scope`, tracing the queries for the annotated rules
+    /// that made the decision if `trace` is true.
+    fn explain_in(
+        &self,
+        kb: &KnowledgeBaseRef,
+        scope: Option<&str>,
+        args: &(PolarValue, PolarValue, PolarValue),
+        trace: bool,
+    ) -> crate::Result<Explanation> {
+        let explanation = |decision, result: ResultSet| Explanation {
+            decision,
+            annotated_rules: result.annotated_rules().to_vec(),
+        };
+        if has_rule(kb, scope, "deny") {
+            if let Some(result) = self.rule_result(kb, scope, "deny", args, trace)? {
+                return Ok(explanation(Decision::Deny, result));
+            }
+        }
+        let allowed = if should_query_allow(kb, scope) {
+            self.rule_result(kb, scope, "allow", args, trace)?
+        } else {
+            None
+        };
+        match allowed {
+            Some(result) => Ok(explanation(Decision::Allow, result)),
+            None => Ok(Explanation {
+                decision: self.default_decision(kb, scope, args)?,
+                annotated_rules: vec![],
+            }),
+        }
+    }
+
+    /// The decision of a request in `scope` that no `allow` rule in `kb` matched: the scope's
+    /// default decision if it declares one, or else the default decision loaded without a
+    /// scope, or else `Decision::NotApplicable`. `default allow rule;` queries `rule` in
+    /// `scope`.
+    pub(crate) fn default_decision(
+        &self,
+        kb: &KnowledgeBaseRef,
+        scope: Option<&str>,
+        args: &(PolarValue, PolarValue, PolarValue),
+    ) -> crate::Result<Decision> {
+        let decision = kb.read().unwrap().default_decision(scope).cloned();
+        let rule = match decision {
+            None => return Ok(Decision::NotApplicable),
+            Some(DefaultDecision::Deny) => return Ok(Decision::Deny),
+            Some(DefaultDecision::Allow) => return Ok(Decision::Allow),
+            Some(DefaultDecision::AllowIf(rule)) => rule,
+        };
+        let (term, host) = self.rule_call(rule.as_str(), args.clone());
+        let mut query = self.inner.new_query_from_term_in(kb.clone(), term, false);
+        query.set_scope(scope.map(str::to_owned));
+        check_messages!(self.inner);
+        match Query::new(query, host).next().transpose()? {
+            Some(_) => Ok(Decision::Allow),
+            None => Ok(Decision::NotApplicable),
+        }
+    }
+
+    /// Return true if the policy defines `deny` rules.
+    pub(crate) fn has_deny_rules(&self) -> bool {
+        has_rule(&self.inner.kb, None, "deny")
+    }
+
+    /// Return true if a `deny` rule matches.
+    pub(crate) fn is_denied(
+        &self,
+        actor: PolarValue,
+        action: PolarValue,
+        resource: PolarValue,
+    ) -> crate::Result<bool> {
+        if !self.has_deny_rules() {
+            return Ok(false);
+        }
+        let args = (actor, action, resource);
+        self.rule_result(&self.inner.kb.clone(), None, "deny", &args, false)
+            .map(|result| result.is_some())
+    }
+
+    /// Return the first result of a query for the rule `name` in `kb` and `scope`, traced if
+    /// `trace` is true.
+    fn rule_result(
+        &self,
+        kb: &KnowledgeBaseRef,
+        scope: Option<&str>,
+        name: &str,
+        args: &(PolarValue, PolarValue, PolarValue),
+        trace: bool,
+    ) -> crate::Result<Option<ResultSet>> {
+        let (term, host) = self.rule_call(name, args.clone());
+        let mut query = self.inner.new_query_from_term_in(kb.clone(), term, trace);
+        query.set_scope(scope.map(str::to_owned));
+        check_messages!(self.inner);
+        let mut query = Query::new(query, host);
+        query.next().transpose()
+    }
+
+    /// Load `src` as a shadow policy, to try out changes to the policy before rolling them out.
+    ///
+    /// While a shadow policy is loaded and a [`Oso::on_shadow_divergence`] callback is set,
+    /// [`Oso::is_allowed`] and [`Oso::decide`] also evaluate each request against the shadow
+    /// policy and report requests for which it disagrees with the loaded policy. The shadow
+    /// policy never affects the returned decision.
+    ///
+    /// The shadow policy sees the classes, constants, and facts registered before this call.
+    /// Loading another shadow policy replaces this one.
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    /// use oso::{Oso, ShadowDivergence};
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.load_str(r#"allow(_actor, "read", _resource);"#).unwrap();
+    /// oso.shadow_load(r#"allow("admin", "read", _resource);"#).unwrap();
+    ///
+    /// let divergences = Arc::new(Mutex::new(vec![]));
+    /// let seen = divergences.clone();
+    /// oso.on_shadow_divergence(move |divergence: &ShadowDivergence| {
+    ///     seen.lock().unwrap().push(divergence.actor.clone());
+    /// });
+    ///
+    /// assert!(oso.is_allowed("alice", "read", "doc").unwrap());
+    /// assert!(oso.is_allowed("admin", "read", "doc").unwrap());
+    /// assert_eq!(divergences.lock().unwrap().len(), 1);
+    /// ```
+    pub fn shadow_load(&mut self, src: &str) -> crate::Result<()> {
+        self.host.register_mros()?;
+        let mut sources = vec![Source::new(src)];
+        if self.stdlib {
+            sources.push(stdlib::source(None));
+        }
+        self.inner.shadow_load(sources)?;
+        check_messages!(self.inner);
+        Ok(())
+    }
+
+    /// Discard the shadow policy, if any.
+    pub fn clear_shadow(&mut self) {
+        self.inner.clear_shadow();
+    }
+
+    /// Call `observer` for every request on which the shadow policy loaded with
+    /// [`Oso::shadow_load`] disagrees with the loaded policy, including when evaluating the
+    /// shadow policy fails.
+    pub fn on_shadow_divergence<F>(&mut self, observer: F)
+    where
+        F: Fn(&ShadowDivergence) + Send + Sync + 'static,
+    {
+        self.shadow_observer = Some(Arc::new(observer));
+    }
+
+    /// Evaluate a request against the shadow policy with `decide`, and report it if the
+    /// outcome differs from `decision`. Errors are reported rather than returned.
+    fn compare_shadow<F>(
+        &self,
+        decision: Decision,
+        args: (PolarValue, PolarValue, PolarValue),
+        decide: F,
+    ) where
+        F: FnOnce(
+            &Self,
+            &KnowledgeBaseRef,
+            &(PolarValue, PolarValue, PolarValue),
+        ) -> crate::Result<Decision>,
+    {
+        let (observer, kb) = match (&self.shadow_observer, self.inner.shadow_kb()) {
+            (Some(observer), Some(kb)) => (observer, kb),
+            _ => return,
+        };
+        let shadow = decide(self, &kb, &args).map_err(|e| e.to_string());
+        if shadow.as_ref() != Ok(&decision) {
+            let (actor, action, resource) = args;
+            observer(&ShadowDivergence {
+                actor,
+                action,
+                resource,
+                decision,
+                shadow,
+            });
         }
     }
 
     /// Get the actions actor is allowed to take on resource.
     /// Returns a [std::collections::HashSet] of actions, typed according the return value.
+    /// Actions that a `deny` rule denies aren't included, and those that `default allow rule;`
+    /// allows are, as for [`Oso::decide`]. The actions `default allow;` allows can't be
+    /// listed, so it's an error to call this with a policy that declares it.
     /// # Examples
     /// ```ignore
     /// oso.load_str(r#"allow(actor: Actor{name: "sally"}, action, resource: Widget{id: 1}) if
@@ -109,29 +609,454 @@ impl Oso {
         Resource: ToPolar,
         T: FromPolar + Eq + Hash,
     {
-        let mut query = self
-            .query_rule(
-                "allow",
-                (actor, PolarValue::Variable("action".to_owned()), resource),
-            )
-            .unwrap();
+        let (actor, resource) = (actor.to_polar(), resource.to_polar());
+        let kb = self.inner.kb.clone();
+        let mut rules = vec![];
+        if should_query_allow(&kb, None) {
+            rules.push(Symbol::new("allow"));
+        }
+        match kb.read().unwrap().default_decision(None) {
+            Some(DefaultDecision::Allow) => {
+                return Err(OsoError::Custom {
+                    message: "the actions allowed by `default allow;` can't be listed".to_owned(),
+                })
+            }
+            Some(DefaultDecision::AllowIf(rule)) => rules.push(rule.clone()),
+            Some(DefaultDecision::Deny) | None => {}
+        }
 
         let mut set = HashSet::new();
-        loop {
-            match query.next() {
-                Some(Ok(result)) => {
-                    if let Some(action) = result.get("action") {
-                        set.insert(T::from_polar(action)?);
+        for rule in rules {
+            let mut query = self.query_rule(
+                rule.as_str(),
+                (
+                    actor.clone(),
+                    PolarValue::Variable("action".to_owned()),
+                    resource.clone(),
+                ),
+            )?;
+            loop {
+                match query.next() {
+                    Some(Ok(result)) => {
+                        if let Some(action) = result.get("action") {
+                            if !self.is_denied(actor.clone(), action.clone(), resource.clone())? {
+                                set.insert(T::from_polar(action)?);
+                            }
+                        }
                     }
-                }
-                Some(Err(e)) => return Err(e),
-                None => break,
-            };
+                    Some(Err(e)) => return Err(e),
+                    None => break,
+                };
+            }
         }
 
         Ok(set)
     }
 
+    /// Register the fields of types that may be filtered with [`Oso::authorized_query`].
+    ///
+    /// Each entry maps a class name to its fields, which are either other classes (e.g.,
+    /// `String`) or relations to other filterable types.
+    pub fn register_filter_types(&mut self, types: Types) {
+        Arc::make_mut(&mut self.filter_types).extend(types);
+    }
+
+    /// Build a [`Filter`] describing the resources of type `resource_type` that `actor` is
+    /// allowed to perform `action` on, by partially evaluating an allow query with an unbound
+    /// resource.
+    ///
+    /// The fields of `resource_type` and any types it is related to must be registered with
+    /// [`Oso::register_filter_types`]. If the policy defines `deny` rules, the filter excludes
+    /// resources they match, as in [`Oso::decide`]. Instances of classes with a
+    /// [filter key](crate::ClassBuilder::with_filter_key) are compared by their keys.
+    pub fn authorized_query<Actor, Action>(
+        &self,
+        actor: Actor,
+        action: Action,
+        resource_type: &str,
+    ) -> crate::Result<Filter>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+    {
+        let (partials, mut query) =
+            self.partial_allow_query(actor.to_polar(), action.to_polar(), resource_type, None)?;
+        let mut types = Types::clone(&self.filter_types);
+        types.entry(resource_type.to_owned()).or_default();
+        let filter = self
+            .inner
+            .build_data_filter(types, partials, "resource", resource_type)?;
+        Self::key_instances(filter, query.host_mut())
+    }
+
+    /// The constraints on resources of type `resource_type` that `actor` is allowed to perform
+    /// `action` on, by partially evaluating an allow query with an unbound resource: one
+    /// disjunct for each way of being allowed, constraining the resource `_this`. For adapters
+    /// that translate constraints themselves, instead of using [`Oso::authorized_query`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use oso::{ConstraintExpr, Oso, PolarClass};
+    ///
+    /// #[derive(Clone, PolarClass)]
+    /// struct Repo {
+    ///     #[polar(attribute)]
+    ///     public: bool,
+    /// }
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.register_class(Repo::get_polar_class()).unwrap();
+    /// oso.load_str(r#"allow(_actor, "read", repo: Repo) if repo.public = true;"#)
+    ///     .unwrap();
+    ///
+    /// match oso.authorized_constraints("alice", "read", "Repo").unwrap() {
+    ///     ConstraintExpr::Or(allowed) => assert_eq!(
+    ///         allowed[0].to_string(),
+    ///         "_this matches Repo{} and true = _this.public"
+    ///     ),
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
+    pub fn authorized_constraints<Actor, Action>(
+        &self,
+        actor: Actor,
+        action: Action,
+        resource_type: &str,
+    ) -> crate::Result<ConstraintExpr>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+    {
+        let (partials, _) =
+            self.partial_allow_query(actor.to_polar(), action.to_polar(), resource_type, None)?;
+        let resource = Symbol::new("resource");
+        let disjuncts = partials
+            .iter()
+            .filter_map(|result| result.bindings.get(&resource))
+            .map(ConstraintExpr::from_term)
+            .collect::<Result<_, _>>()?;
+        Ok(ConstraintExpr::Or(disjuncts))
+    }
+
+    /// Compile the rules that allow actors of type `actor_type` to perform `action` on
+    /// resources of type `resource_type` to a SQL predicate over the rows of the resources'
+    /// table, e.g., for a PostgreSQL row-level security policy, so that the database enforces
+    /// the same rules as the application.
+    ///
+    /// The predicate has a numbered placeholder for each attribute of the actor the rules use,
+    /// and for each value the rules compare fields with, listed in [`SqlTemplate::parameters`],
+    /// e.g., `actor.id` for `$1`. Types are as for
+    /// [`Oso::authorized_query`], and `tables` maps type names to table names; types without
+    /// one use their own name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use oso::{Oso, PolarClass};
+    /// use polar_core::data_filtering::Type;
+    /// use polar_core::sql::SqlParameter;
+    /// use std::collections::HashMap;
+    ///
+    /// #[derive(Clone, PolarClass)]
+    /// struct User {
+    ///     #[polar(attribute)]
+    ///     id: i64,
+    /// }
+    ///
+    /// #[derive(Clone, PolarClass)]
+    /// struct Repo {
+    ///     #[polar(attribute)]
+    ///     owner_id: i64,
+    /// }
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.register_class(User::get_polar_class()).unwrap();
+    /// oso.register_class(Repo::get_polar_class()).unwrap();
+    /// oso.register_filter_types(HashMap::from([(
+    ///     "Repo".to_owned(),
+    ///     HashMap::from([(
+    ///         "owner_id".to_owned(),
+    ///         Type::Base { class_tag: "Integer".to_owned() },
+    ///     )]),
+    /// )]));
+    /// oso.load_str(r#"allow(user: User, "read", repo: Repo) if repo.owner_id = user.id;"#)
+    ///     .unwrap();
+    ///
+    /// let tables = HashMap::from([("Repo".to_owned(), "repos".to_owned())]);
+    /// let template = oso.row_level_security("User", "read", "Repo", &tables).unwrap();
+    /// assert_eq!(template.predicate, r#"$1 = "repos"."owner_id""#);
+    /// assert_eq!(template.parameters, vec![SqlParameter::Path("actor.id".to_owned())]);
+    /// ```
+    pub fn row_level_security<Action: ToPolar>(
+        &self,
+        actor_type: &str,
+        action: Action,
+        resource_type: &str,
+        tables: &HashMap<String, String>,
+    ) -> crate::Result<SqlTemplate> {
+        let actor = PolarValue::Variable("actor".to_owned());
+        let (partials, mut query) =
+            self.partial_allow_query(actor, action.to_polar(), resource_type, Some(actor_type))?;
+        let mut types = Types::clone(&self.filter_types);
+        types.entry(resource_type.to_owned()).or_default();
+        let filter = Filter::build_with_parameters(
+            types.clone(),
+            partials,
+            "resource",
+            resource_type,
+            &[("actor", actor_type)],
+        )?;
+        let filter = Self::key_instances(filter, query.host_mut())?;
+        Ok(filter.to_sql(&types, tables)?)
+    }
+
+    /// Partially evaluate an allow query for resources of type `resource_type`, and for actors
+    /// of type `actor_type` if the actor is a variable, returning the partial results and the
+    /// query, whose host has the instances they refer to. If the policy defines `deny` rules,
+    /// the results exclude resources they match.
+    fn partial_allow_query(
+        &self,
+        actor: PolarValue,
+        action: PolarValue,
+        resource_type: &str,
+        actor_type: Option<&str>,
+    ) -> crate::Result<(Vec<ResultEvent>, Query)> {
+        let has_deny_rules = self.has_deny_rules();
+        self.partial_query(actor, action, resource_type, actor_type, |call| {
+            let allow = call("allow");
+            if !has_deny_rules {
+                return allow;
+            }
+            let not_denied = Operation {
+                operator: Operator::Not,
+                args: vec![call("deny")],
+            };
+            Term::new_from_ffi(Value::Expression(Operation {
+                operator: Operator::And,
+                args: vec![allow, Term::new_from_ffi(Value::Expression(not_denied))],
+            }))
+        })
+    }
+
+    /// Partially evaluate the query that `query_term` builds from calls to rules with the
+    /// arguments `(actor, action, resource)`, as for [`Oso::partial_allow_query`].
+    pub(crate) fn partial_query<F>(
+        &self,
+        actor: PolarValue,
+        action: PolarValue,
+        resource_type: &str,
+        actor_type: Option<&str>,
+        query_term: F,
+    ) -> crate::Result<(Vec<ResultEvent>, Query)>
+    where
+        F: FnOnce(&dyn Fn(&str) -> Term) -> Term,
+    {
+        let resource = Symbol::new("resource");
+        let constraint = |var: &Symbol, class: &str| {
+            let isa = Operation {
+                operator: Operator::Isa,
+                args: vec![
+                    Term::new_from_ffi(Value::Variable(var.clone())),
+                    Term::new_from_ffi(Value::Pattern(Pattern::Instance(InstanceLiteral {
+                        tag: Symbol::new(class),
+                        fields: Dictionary::new(),
+                    }))),
+                ],
+            };
+            Term::new_from_ffi(Value::Expression(Operation {
+                operator: Operator::And,
+                args: vec![Term::new_from_ffi(Value::Expression(isa))],
+            }))
+        };
+
+        let mut query_host = self.host.clone();
+        query_host.accept_expression = true;
+        let args: Vec<Term> = (actor, action, PolarValue::Variable(resource.to_string()))
+            .to_polar_list()
+            .iter()
+            .map(|value| value.to_term(&mut query_host))
+            .collect();
+        let call = |name: &str| {
+            Term::new_from_ffi(Value::Call(Call {
+                name: Symbol::new(name),
+                args: args.clone(),
+                kwargs: None,
+            }))
+        };
+        let mut query = self.inner.new_query_from_term(query_term(&call), false);
+        check_messages!(self.inner);
+        query.bind(resource.clone(), constraint(&resource, resource_type))?;
+        if let Some(actor_type) = actor_type {
+            let actor = Symbol::new("actor");
+            query.bind(actor.clone(), constraint(&actor, actor_type))?;
+        }
+
+        let mut query = Query::new(query, query_host);
+        let partials = query
+            .by_ref()
+            .map(|result| result.map(|result| result.into_event()))
+            .collect::<crate::Result<Vec<_>>>()?;
+        Ok((partials, query))
+    }
+
+    /// Compare instances whose classes have filter keys by key.
+    fn key_instances(filter: Filter, host: &mut Host) -> crate::Result<Filter> {
+        filter.map_instances(|instance| {
+            host.filter_key(instance.instance_id)?
+                .map(|key| Ok(key.to_term(host).value().clone()))
+                .transpose()
+        })
+    }
+
+    /// Export the policy's `allow` and `deny` rules as Cedar policies, e.g., for AWS Verified
+    /// Permissions. Rules must stay within the subset of Polar that Cedar expresses, described
+    /// in [`polar_core::cedar`]; otherwise, the export fails.
+    ///
+    /// ```
+    /// use oso::Oso;
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.load_str(r#"allow(actor, "read", resource) if actor.team = resource.team;"#)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(
+    ///     oso.export_cedar().unwrap(),
+    ///     "permit (\n    principal,\n    action == Action::\"read\",\n    resource\n)\nwhen { principal.team == resource.team };\n"
+    /// );
+    /// ```
+    pub fn export_cedar(&self) -> crate::Result<String> {
+        Ok(self.inner.export_cedar()?)
+    }
+
+    /// Hints for fetching the records of each relation that `filter`, built by
+    /// [`Oso::authorized_query`], traverses: their cardinality, from the kinds of relations
+    /// registered with [`Oso::register_filter_types`], and how much of the filter uses them, so
+    /// that an adapter can choose between joins and subqueries.
+    pub fn prefetch_hints(&self, filter: &Filter) -> Vec<PrefetchHint> {
+        filter.prefetch_hints(&self.filter_types)
+    }
+
+    /// Register a [`LintRule`] to check policies loaded after this call. Lints reported as
+    /// errors fail the load; warnings are printed like other policy warnings.
+    pub fn register_lint_rule<R: LintRule + 'static>(&mut self, rule: R) {
+        self.inner.register_lint_rule(Box::new(rule));
+    }
+
+    /// Register a [`RewritePass`] to transform the rules of policies loaded after this call,
+    /// e.g., to add a check to every `allow` rule. Passes run in the order they're registered,
+    /// after constant folding and before Polar's built-in rewrites.
+    pub fn register_rewrite_pass<P: RewritePass + 'static>(&mut self, pass: P) {
+        self.inner.register_rewrite_pass(Box::new(pass));
+    }
+
+    /// Set whether integer arithmetic in policies that overflows an `i64` raises an error, the
+    /// default, or saturates at the nearest representable value.
+    pub fn set_integer_overflow(&mut self, mode: IntegerOverflow) {
+        self.inner.set_integer_overflow(mode);
+    }
+
+    /// Set whether queries check that unification doesn't bind a variable to a term that
+    /// contains it, like `x = [x]`, failing with an occurs check error instead of making a
+    /// cyclic term. Off by default, since it slows down every binding; turn it on for
+    /// policies from untrusted sources.
+    pub fn set_occurs_check(&mut self, enabled: bool) {
+        self.inner.set_occurs_check(enabled);
+    }
+
+    /// Set what loading a clause that's the same as one already loaded for its rule, but for
+    /// the names of its variables, does: add it, the default; add it with a warning; or skip
+    /// it, e.g., when loading policy bundles that overlap.
+    pub fn set_duplicate_clauses(&mut self, mode: DuplicateClauses) {
+        self.inner.set_duplicate_clauses(mode);
+    }
+
+    /// Set limits on how deeply rule calls in queries, and terms in policies and queries, may
+    /// nest. Exceeding a limit is an error rather than a crash or a runaway query.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.inner.set_limits(limits);
+    }
+
+    /// Group the rules `name` of `arity` parameters by the constant value of parameter `param`
+    /// when they're loaded, so that queries with a constant argument there find their rules with
+    /// a single lookup, e.g., `oso.add_hot_entrypoint("allow", 3, 1)` for policies with an
+    /// `allow` rule per action.
+    pub fn add_hot_entrypoint(
+        &mut self,
+        name: &str,
+        arity: usize,
+        param: usize,
+    ) -> crate::Result<()> {
+        self.inner.add_hot_entrypoint(name, arity, param)?;
+        Ok(())
+    }
+
+    /// Have queries emit a [`QueryEvent::Heartbeat`](crate::QueryEvent::Heartbeat) every
+    /// `interval` goals run without another event, or none if `interval` is `None`, the default.
+    /// Heartbeats are ignored unless a [`QueryHook`] acts on them, e.g., by failing queries that
+    /// run past a deadline.
+    pub fn set_heartbeat_interval(&mut self, interval: Option<u64>) {
+        self.inner.set_heartbeat_interval(interval);
+    }
+
+    /// Keep the policies of the last `max` loads for [`Oso::is_allowed_at`]. Each one is a copy
+    /// of the rules, so none are kept by default.
+    pub fn set_max_policy_epochs(&mut self, max: usize) {
+        self.inner.set_max_policy_epochs(max);
+    }
+
+    /// Fail queries that run for longer than `timeout` with a `QueryTimeout` error, or for
+    /// longer than the `POLAR_TIMEOUT_MS` environment variable says if `timeout` is `None`,
+    /// the default. Time spent in application calls counts.
+    pub fn set_query_timeout(&mut self, timeout: Option<Duration>) {
+        self.host.query_timeout = timeout;
+    }
+
+    /// Format values in query traces, stack traces, and logs with `formatter`, e.g., to keep
+    /// long lists short, or to redact strings that look like secrets.
+    ///
+    /// ```
+    /// use oso::{Oso, TermFormatter};
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.set_term_formatter(TermFormatter::new().max_width(200).redact("sk_live_*"));
+    /// ```
+    pub fn set_term_formatter(&mut self, formatter: TermFormatter) {
+        self.inner.set_term_formatter(formatter);
+    }
+
+    /// Issue the IDs of instances passed to Polar with `partition` in their high `bits` bits,
+    /// so that they don't collide with IDs issued by engines in other partitions, e.g., other
+    /// processes of a cluster sharing state. `bits` may be at most 16, and `partition` must fit
+    /// in `bits` bits. Call this before registering classes: IDs issued before the call are
+    /// not affected.
+    ///
+    /// ```
+    /// use oso::Oso;
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.set_id_partition(3, 4).unwrap();
+    /// assert!(oso.set_id_partition(16, 4).is_err());
+    /// ```
+    pub fn set_id_partition(&mut self, partition: u64, bits: u32) -> crate::Result<()> {
+        self.inner.set_id_partition(partition, bits)?;
+        Ok(())
+    }
+
+    /// How many times instance IDs ran out and a new generation of IDs was started. IDs don't
+    /// repeat within a generation, so a growing count is only a concern for applications that
+    /// keep IDs for a very long time.
+    pub fn id_wraps(&self) -> u64 {
+        self.inner.id_wraps()
+    }
+
+    /// Set whether [`Oso::query`] accepts Polar source, which it does by default. Disabling it,
+    /// e.g., in production, ensures that untrusted input like an action name is never parsed as
+    /// Polar: [`Oso::query_rule`] and [`Oso::query_with`] pass their arguments as values.
+    pub fn set_string_queries(&mut self, enabled: bool) {
+        self.string_queries = enabled;
+    }
+
     /// Clear out all files and rules that have been loaded.
     pub fn clear_rules(&mut self) -> crate::Result<()> {
         self.inner.clear_rules();
@@ -139,14 +1064,53 @@ impl Oso {
         Ok(())
     }
 
+    /// The results of the inline queries of the policy loaded last, in the order they ran.
+    /// Each is also logged at the `info` level as it runs.
+    ///
+    /// ```
+    /// let mut oso = oso::Oso::new();
+    /// oso.load_str(r#"is_admin("alice"); is_admin("bob"); ?= is_admin(admin);"#)
+    ///     .unwrap();
+    /// let reports = oso.inline_query_reports();
+    /// assert_eq!(reports[0].count(), 2);
+    /// assert!(reports[0]
+    ///     .to_string()
+    ///     .ends_with(r#"2 results: {admin: "alice"}, {admin: "bob"}"#));
+    /// ```
+    pub fn inline_query_reports(&self) -> Vec<InlineQueryReport> {
+        self.inline_query_reports.read().unwrap().clone()
+    }
+
     fn check_inline_queries(&self) -> crate::Result<()> {
-        while let Some(q) = self.inner.next_inline_query(false) {
+        self.check_inline_queries_in(&self.inner.kb)
+    }
+
+    /// Run the inline queries of the policy loaded into `kb`, e.g., a staged policy.
+    fn check_inline_queries_in(&self, kb: &KnowledgeBaseRef) -> crate::Result<()> {
+        let mut reports = self.inline_query_reports.write().unwrap();
+        reports.clear();
+        while let Some(q) = self.inner.next_inline_query_in(kb, false) {
             let location = q.source_info();
+            let tenant = q.scope().map(str::to_owned);
             let query = Query::new(q, self.host.clone());
-            match query.collect::<crate::Result<Vec<_>>>() {
-                Ok(v) if !v.is_empty() => continue,
-                Ok(_) => return Err(OsoError::InlineQueryFailedError { location }),
-                Err(e) => return lazy_error!("error in inline query: {}", e),
+            match (query.collect::<crate::Result<Vec<_>>>(), tenant) {
+                (Ok(results), tenant) if !results.is_empty() => {
+                    let report = InlineQueryReport {
+                        location,
+                        tenant,
+                        results,
+                    };
+                    tracing::info!(%report, "inline query");
+                    reports.push(report);
+                }
+                (Ok(_), None) => return Err(OsoError::InlineQueryFailedError { location }),
+                (Ok(_), Some(tenant)) => {
+                    return Err(OsoError::TenantInlineQueryFailedError { tenant, location })
+                }
+                (Err(e), None) => return lazy_error!("error in inline query: {}", e),
+                (Err(e), Some(tenant)) => {
+                    return lazy_error!("error in inline query for tenant {}: {}", tenant, e)
+                }
             }
         }
         check_messages!(self.inner);
@@ -155,9 +1119,62 @@ impl Oso {
 
     // Register MROs, load Polar code, and check inline queries.
     fn load_sources(&mut self, sources: Vec<Source>) -> crate::Result<()> {
+        self.load_sources_with(sources, &LoadOptions::default())
+    }
+
+    fn load_sources_with(
+        &mut self,
+        mut sources: Vec<Source>,
+        options: &LoadOptions,
+    ) -> crate::Result<()> {
+        if let Some(sandbox) = &options.sandbox {
+            sources = sources
+                .into_iter()
+                .map(|source| source.with_sandbox(sandbox.clone()))
+                .collect();
+        }
         self.host.register_mros()?;
-        self.inner.load(sources)?;
-        self.check_inline_queries()
+        let loaded = match &options.scope {
+            Some(tenant) => self.inner.load_scope(tenant, sources),
+            None => {
+                if self.stdlib {
+                    let sandbox = sources.first().and_then(|source| source.sandbox.clone());
+                    sources.push(stdlib::source(sandbox));
+                }
+                let dedup = self.inner.duplicate_clauses();
+                self.inner.set_duplicate_clauses(options.dedup);
+                let loaded = self.inner.load(sources);
+                self.inner.set_duplicate_clauses(dedup);
+                loaded
+            }
+        };
+        loaded?;
+
+        if options.strict {
+            let mut warnings = vec![];
+            while let Some(message) = self.inner.next_message() {
+                match message.kind {
+                    MessageKind::Print => println!("{}", &message.msg),
+                    MessageKind::Warning => warnings.push(message.msg),
+                }
+            }
+            if !warnings.is_empty() {
+                match &options.scope {
+                    Some(tenant) => {
+                        self.inner.remove_scope(tenant, true)?;
+                    }
+                    None => self.inner.clear_rules(),
+                }
+                return Err(OsoError::LoadWarnings { warnings });
+            }
+        }
+        if options.run_inline_queries {
+            self.check_inline_queries()
+        } else {
+            while self.inner.next_inline_query(false).is_some() {}
+            check_messages!(self.inner);
+            Ok(())
+        }
     }
 
     /// Load a file containing Polar rules. All Polar files must end in `.polar`.
@@ -173,26 +1190,42 @@ impl Oso {
     pub fn load_files<P: AsRef<std::path::Path>>(
         &mut self,
         filenames: Vec<P>,
+    ) -> crate::Result<()> {
+        self.load_files_with(filenames, &LoadOptions::default())
+    }
+
+    /// Load files containing Polar rules as [`options`](LoadOptions) say. All Polar files must
+    /// end in `.polar`.
+    pub fn load_files_with<P: AsRef<std::path::Path>>(
+        &mut self,
+        filenames: Vec<P>,
+        options: &LoadOptions,
     ) -> crate::Result<()> {
         if filenames.is_empty() {
             return Ok(());
         }
+        let sources = read_sources(filenames)?;
+        self.load_sources_with(sources, options)
+    }
 
-        let mut sources = Vec::with_capacity(filenames.len());
-
-        for file in filenames {
-            let file = file.as_ref();
-            let filename = file.to_string_lossy().into_owned();
-            if !file.extension().map_or(false, |ext| ext == "polar") {
-                return Err(crate::OsoError::IncorrectFileType { filename });
-            }
-            let mut f = File::open(file)?;
-            let mut src = String::new();
-            f.read_to_string(&mut src)?;
-            sources.push(Source::new_with_name(filename, src));
+    /// Check that loading `filenames` in any order makes the same policy, e.g., in a policy's
+    /// tests, by loading them in several orders without loading them into this `Oso`. Clauses
+    /// of a rule defined in several files are tried in order of file name, however the files
+    /// are loaded.
+    ///
+    /// ```no_run
+    /// let oso = oso::Oso::new();
+    /// oso.verify_load_order(vec!["roles.polar", "documents.polar"]).unwrap();
+    /// ```
+    pub fn verify_load_order<P: AsRef<std::path::Path>>(
+        &self,
+        filenames: Vec<P>,
+    ) -> crate::Result<()> {
+        let mut sources = read_sources(filenames)?;
+        if self.stdlib {
+            sources.push(stdlib::source(None));
         }
-
-        self.load_sources(sources)
+        Ok(self.inner.verify_load_order(sources)?)
     }
 
     /// Load a string of polar source directly.
@@ -202,7 +1235,402 @@ impl Oso {
     /// ```
     pub fn load_str(&mut self, src: &str) -> crate::Result<()> {
         // TODO(gj): emit... some sort of warning?
-        self.load_sources(vec![Source::new(src)])
+        self.load_str_with(src, &LoadOptions::default())
+    }
+
+    /// Load a string of Polar source as [`options`](LoadOptions) say.
+    pub fn load_str_with(&mut self, src: &str, options: &LoadOptions) -> crate::Result<()> {
+        let source = match &options.tag {
+            Some(tag) => Source::new_with_name(tag, src),
+            None => Source::new(src),
+        };
+        self.load_sources_with(vec![source], options)
+    }
+
+    /// Replace the loaded policy with `src` as one change: if `src` fails to load, or one of
+    /// its inline queries fails, the loaded policy stays loaded. Unlike [`Oso::clear_rules`]
+    /// followed by [`Oso::load_str`], queries made meanwhile never see an empty policy.
+    ///
+    /// ```
+    /// let mut oso = oso::Oso::new();
+    /// oso.load_str(r#"allow("alice", "read", "doc");"#).unwrap();
+    /// assert!(oso.reload_str(r#"allow("bob", "read", "doc"); ?= false;"#).is_err());
+    /// assert!(oso.is_allowed("alice", "read", "doc").unwrap());
+    /// oso.reload_str(r#"allow("bob", "read", "doc");"#).unwrap();
+    /// assert!(oso.is_allowed("bob", "read", "doc").unwrap());
+    /// ```
+    pub fn reload_str(&mut self, src: &str) -> crate::Result<()> {
+        self.reload_sources(vec![Source::new(src)])
+    }
+
+    /// Replace the loaded policy with the Polar files `filenames` as one change, as
+    /// [`Oso::reload_str`] does.
+    pub fn reload_files<P: AsRef<std::path::Path>>(
+        &mut self,
+        filenames: Vec<P>,
+    ) -> crate::Result<()> {
+        let sources = read_sources(filenames)?;
+        self.reload_sources(sources)
+    }
+
+    fn reload_sources(&mut self, mut sources: Vec<Source>) -> crate::Result<()> {
+        self.host.register_mros()?;
+        if self.stdlib {
+            sources.push(stdlib::source(None));
+        }
+        let staged = self.inner.stage_policy(sources)?;
+        check_messages!(self.inner);
+        self.check_inline_queries_in(&staged)?;
+        self.inner.commit_policy(staged);
+        Ok(())
+    }
+
+    /// Load a policy from the JSON array of its lines, as written by [`parse_to_json`] or by a
+    /// tool that generates policies structurally. See [`polar_core::parser::Line`] for the
+    /// schema.
+    ///
+    /// ```
+    /// let mut oso = oso::Oso::new();
+    /// let json = oso::parse_to_json(r#"allow("alice", "read", "doc");"#).unwrap();
+    /// oso.load_ast_json(&json).unwrap();
+    /// assert!(oso.is_allowed("alice", "read", "doc").unwrap());
+    /// ```
+    #[cfg(feature = "serde_json")]
+    pub fn load_ast_json(&mut self, json: &str) -> crate::Result<()> {
+        let mut lines: Vec<polar_core::parser::Line> =
+            serde_json::from_str(json).map_err(serialization_error)?;
+        self.host.register_mros()?;
+        if self.stdlib {
+            lines.extend(polar_core::parser::parse_lines(stdlib::source(None))?);
+        }
+        self.inner.load_ast(lines)?;
+        self.check_inline_queries()
+    }
+
+    /// Translate `src`, a policy in a subset of Rego, to Polar, and load the translation,
+    /// returning the constructs that couldn't be translated. See [`polar_core::rego`] for what
+    /// the translation covers. Rules are prefixed with their package, and take the input
+    /// document as their first argument. The data document, if the policy uses one, should be
+    /// registered as the constant `data` before loading.
+    ///
+    /// ```
+    /// use oso::Oso;
+    /// use std::collections::HashMap;
+    ///
+    /// let mut oso = Oso::new();
+    /// let issues = oso
+    ///     .load_rego(
+    ///         r#"
+    ///         package authz
+    ///
+    ///         default allow := false
+    ///
+    ///         allow if input.role == "admin"
+    ///         "#,
+    ///     )
+    ///     .unwrap();
+    /// assert!(issues.is_empty());
+    ///
+    /// let input = HashMap::from([("role", "admin")]);
+    /// assert!(oso.query_rule("authz_allow", (input,)).unwrap().next().is_some());
+    /// ```
+    pub fn load_rego(&mut self, src: &str) -> crate::Result<Vec<RegoIssue>> {
+        let translation = rego::translate(src);
+        self.load_sources(vec![Source::new_with_name("rego", translation.polar)])?;
+        Ok(translation.issues)
+    }
+
+    /// Load Polar source from `reader`, parsing and loading it in chunks of whole statements as
+    /// it's read, rather than reading all of it into memory first. Use this for large policies,
+    /// like generated files of facts.
+    ///
+    /// If reading fails, no rules are loaded.
+    ///
+    /// ```
+    /// use oso::Oso;
+    ///
+    /// let mut oso = Oso::new();
+    /// let policy = "allow(\"alice\", \"read\", \"doc\");\n";
+    /// oso.load_reader(policy.as_bytes()).unwrap();
+    ///
+    /// assert!(oso.is_allowed("alice", "read", "doc").unwrap());
+    /// ```
+    pub fn load_reader<R: Read>(&mut self, reader: R) -> crate::Result<()> {
+        self.host.register_mros()?;
+        let mut error = None;
+        let sources = SourceReader::new(reader)
+            .map_while(|source| source.map_err(|e| error = Some(e)).ok())
+            .chain(self.stdlib.then(|| stdlib::source(None)));
+        let loaded = self.inner.load_iter(sources);
+        if let Some(e) = error {
+            self.inner.clear_rules();
+            return Err(e.into());
+        }
+        loaded?;
+        self.check_inline_queries()
+    }
+
+    /// Load the Oso standard library of Polar helpers along with the policies loaded after
+    /// this call, including shadow policies. The standard library is versioned with this
+    /// crate, and has helpers for lists, like `subset(xs, ys)`, role-based access control,
+    /// like `role_allows(roles, action, role_permissions)`, and attribute-based access control,
+    /// like `attributes_match(left, left_path, right, right_path)`. See `stdlib.polar` in the
+    /// crate source for all of them.
+    ///
+    /// ```
+    /// use oso::Oso;
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.load_stdlib();
+    /// oso.load_str(
+    ///     r#"allow(user, action, _resource) if
+    ///            role_allows(user.roles, action, {reader: ["read"], writer: ["read", "write"]});"#,
+    /// )
+    /// .unwrap();
+    ///
+    /// let reader = maplit::hashmap! { "roles" => vec!["reader"] };
+    /// assert!(oso.is_allowed(reader.clone(), "read", "doc").unwrap());
+    /// assert!(!oso.is_allowed(reader, "write", "doc").unwrap());
+    /// ```
+    pub fn load_stdlib(&mut self) {
+        self.stdlib = true;
+    }
+
+    /// Load a string of Polar source whose rules may only use the application classes and
+    /// methods allowed by `sandbox`; other lookups fail with a sandbox violation error. Use this
+    /// for policies written by third parties.
+    ///
+    /// ```
+    /// use oso::{Oso, PolarClass, Sandbox};
+    ///
+    /// #[derive(Clone, PolarClass)]
+    /// struct User {
+    ///     #[polar(attribute)]
+    ///     name: String,
+    ///     #[polar(attribute)]
+    ///     password: String,
+    /// }
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.register_class(User::get_polar_class()).unwrap();
+    /// oso.load_str_sandboxed(
+    ///     r#"allow(user: User, "read", _) if user.name = "alice";
+    ///        allow(user: User, "write", _) if user.password = "hunter2";"#,
+    ///     Sandbox::new().allow_method("User", "name"),
+    /// )
+    /// .unwrap();
+    ///
+    /// let alice = User { name: "alice".to_owned(), password: "hunter2".to_owned() };
+    /// assert!(oso.is_allowed(alice.clone(), "read", "doc").unwrap());
+    /// assert!(oso.is_allowed(alice, "write", "doc").is_err());
+    /// ```
+    pub fn load_str_sandboxed(&mut self, src: &str, sandbox: Sandbox) -> crate::Result<()> {
+        self.load_str_with(src, &LoadOptions::new().sandbox(sandbox))
+    }
+
+    /// Load a string of Polar rules for `tenant`, replacing any rules loaded for `tenant`
+    /// before. Tenant policies may only contain rules, templates, and inline queries. They're
+    /// only used by queries for the same tenant, like [`Oso::is_allowed_for_tenant`], which also
+    /// use the rules shared by all tenants that were loaded with [`Oso::load_str`] or
+    /// [`Oso::load_files`]. Inline queries run as queries for `tenant`, and fail with
+    /// [`OsoError::TenantInlineQueryFailedError`](crate::OsoError::TenantInlineQueryFailedError).
+    ///
+    /// ```
+    /// use oso::Oso;
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.load_str(r#"allow("admin", _action, _resource);"#).unwrap();
+    /// oso.load_str_for_tenant("acme", r#"allow("alice", "read", "doc");"#).unwrap();
+    ///
+    /// assert!(oso.is_allowed_for_tenant("acme", "alice", "read", "doc").unwrap());
+    /// assert!(oso.is_allowed_for_tenant("acme", "admin", "read", "doc").unwrap());
+    /// assert!(!oso.is_allowed_for_tenant("globex", "alice", "read", "doc").unwrap());
+    /// assert!(!oso.is_allowed("alice", "read", "doc").unwrap());
+    /// ```
+    pub fn load_str_for_tenant(&mut self, tenant: &str, src: &str) -> crate::Result<()> {
+        self.load_str_with(src, &LoadOptions::new().scope(tenant))
+    }
+
+    /// Let queries for `tenant` use the rules of the policies loaded for the tenants
+    /// `includes`, e.g., those of a parent organization, as well as its own.
+    pub fn set_tenant_includes(&mut self, tenant: &str, includes: &[&str]) -> crate::Result<()> {
+        let includes = includes.iter().map(|&name| name.to_owned()).collect();
+        self.inner.set_scope_includes(tenant, includes)?;
+        Ok(())
+    }
+
+    /// Remove the policy loaded for `tenant` with [`Oso::load_str_for_tenant`], its quota, and
+    /// its includes. Queries for `tenant` then only use the rules shared by all tenants. Fails
+    /// if other tenants [include](Oso::set_tenant_includes) `tenant`, unless `force` is set.
+    /// Return `false` if no policy was loaded for `tenant`.
+    pub fn remove_tenant(&mut self, tenant: &str, force: bool) -> crate::Result<bool> {
+        Ok(self.inner.remove_scope(tenant, force)?)
+    }
+
+    /// Limit the size of the policies loaded for `tenant` with [`Oso::load_str_for_tenant`],
+    /// and the number of goals each query for `tenant` may run. Policies or queries exceeding
+    /// the quota fail with a quota exceeded error. The quota is checked the next time a policy
+    /// is loaded for `tenant`, not against the policy it has now.
+    ///
+    /// A [`RuleQuota`](crate::RuleQuota) limits the calls of one rule, e.g., a helper rule in
+    /// the tenant's policy, so that it can't use up the goals each query may run before the
+    /// rest of the policy is evaluated.
+    ///
+    /// ```
+    /// use oso::{Oso, ScopeQuota};
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.set_tenant_quota("acme", ScopeQuota::new().max_rules(1));
+    /// assert!(oso.load_str_for_tenant("acme", "f(1); f(2);").is_err());
+    /// ```
+    pub fn set_tenant_quota(&mut self, tenant: &str, quota: ScopeQuota) {
+        self.inner.set_scope_quota(tenant, quota);
+    }
+
+    /// Limit the tenants that don't have a quota set with [`Oso::set_tenant_quota`].
+    pub fn set_default_tenant_quota(&mut self, quota: ScopeQuota) {
+        self.inner.set_default_scope_quota(quota);
+    }
+
+    /// Like [`Oso::query_rule`], but also matching the rules loaded for `tenant` with
+    /// [`Oso::load_str_for_tenant`].
+    pub fn query_rule_for_tenant(
+        &self,
+        tenant: &str,
+        name: &str,
+        args: impl ToPolarList,
+    ) -> crate::Result<Query> {
+        let (query_term, query_host) = self.rule_call(name, args);
+        let mut query = self.inner.new_query_from_term(query_term, false);
+        query.set_scope(Some(tenant.to_owned()));
+        check_messages!(self.inner);
+        let query = Query::new(query, query_host);
+        Ok(query)
+    }
+
+    /// Like [`Oso::is_allowed`], but also matching the rules loaded for `tenant` with
+    /// [`Oso::load_str_for_tenant`].
+    ///
+    /// Rules take precedence over default decisions, and `deny` rules over `allow` rules: if a
+    /// `deny` rule of the tenant or one shared by all tenants matches, the request is denied,
+    /// and otherwise if such an `allow` rule matches, it's allowed. Otherwise the tenant's default
+    /// decision applies if its policy declares one, or else the default decision shared by all
+    /// tenants, if any. `default allow rule;` queries `rule` for the tenant.
+    ///
+    /// ```
+    /// use oso::Oso;
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.load_str(r#"default allow;
+    ///                 allow("admin", _action, _resource);"#).unwrap();
+    /// oso.load_str_for_tenant("acme", "default deny;").unwrap();
+    /// assert!(oso.is_allowed_for_tenant("globex", "alice", "read", "doc").unwrap());
+    /// assert!(!oso.is_allowed_for_tenant("acme", "alice", "read", "doc").unwrap());
+    /// assert!(oso.is_allowed_for_tenant("acme", "admin", "read", "doc").unwrap());
+    /// ```
+    pub fn is_allowed_for_tenant<Actor, Action, Resource>(
+        &self,
+        tenant: &str,
+        actor: Actor,
+        action: Action,
+        resource: Resource,
+    ) -> crate::Result<bool>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+        Resource: ToPolar,
+    {
+        let args = (actor.to_polar(), action.to_polar(), resource.to_polar());
+        self.explain_in(&self.inner.kb.clone(), Some(tenant), &args, false)
+            .map(|explanation| explanation.decision.is_allowed())
+    }
+
+    /// Make the source of a rule from the template `name`, a rule annotated with
+    /// `@template(name)`, with placeholders like `:role` in it replaced by the bindings of the
+    /// same name. Bindings must be plain values like strings, numbers, and lists and
+    /// dictionaries of them, so rules made from untrusted input only take the forms of the
+    /// templates in the policy. Templates aren't loaded as rules themselves.
+    ///
+    /// ```
+    /// use oso::{Oso, ToPolar};
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.load_str(
+    ///     r#"@template("allow_role")
+    ///        allow(actor, :action, _resource) if :role in actor.roles;"#,
+    /// )
+    /// .unwrap();
+    /// let bindings = [("action", "read".to_polar()), ("role", "admin".to_polar())];
+    /// let rule = oso.instantiate_template("allow_role", bindings).unwrap();
+    /// assert_eq!(rule, r#"allow(actor, "read", _resource) if "admin" in actor.roles;"#);
+    /// oso.load_str_for_tenant("acme", &rule).unwrap();
+    /// ```
+    pub fn instantiate_template<K, V>(
+        &self,
+        name: &str,
+        bindings: impl IntoIterator<Item = (K, V)>,
+    ) -> crate::Result<String>
+    where
+        K: Into<String>,
+        V: ToPolar,
+    {
+        self.instantiate_template_in(None, name, bindings)
+    }
+
+    /// Like [`Oso::instantiate_template`], but also using the templates loaded for `tenant`
+    /// with [`Oso::load_str_for_tenant`].
+    pub fn instantiate_template_for_tenant<K, V>(
+        &self,
+        tenant: &str,
+        name: &str,
+        bindings: impl IntoIterator<Item = (K, V)>,
+    ) -> crate::Result<String>
+    where
+        K: Into<String>,
+        V: ToPolar,
+    {
+        self.instantiate_template_in(Some(tenant), name, bindings)
+    }
+
+    fn instantiate_template_in<K, V>(
+        &self,
+        tenant: Option<&str>,
+        name: &str,
+        bindings: impl IntoIterator<Item = (K, V)>,
+    ) -> crate::Result<String>
+    where
+        K: Into<String>,
+        V: ToPolar,
+    {
+        let mut host = self.host.clone();
+        let bindings = bindings
+            .into_iter()
+            .map(|(name, value)| (name.into(), value.to_polar().to_term(&mut host)))
+            .collect();
+        let rule = self.inner.instantiate_template(tenant, name, bindings)?;
+        Ok(rule.to_string())
+    }
+
+    /// The templates that [`Oso::instantiate_template_for_tenant`] may instantiate for
+    /// `tenant`, or that [`Oso::instantiate_template`] may instantiate if `tenant` is `None`,
+    /// with their placeholders and `@doc` strings, sorted by name.
+    ///
+    /// ```
+    /// use oso::Oso;
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.load_str(
+    ///     r#"@doc("Allow actors with a role to take an action.")
+    ///        @template("allow_role")
+    ///        allow(actor, :action, _resource) if :role in actor.roles;"#,
+    /// )
+    /// .unwrap();
+    /// let templates = oso.list_templates(None);
+    /// assert_eq!(templates[0].name, "allow_role");
+    /// assert_eq!(templates[0].placeholders, ["action", "role"]);
+    /// ```
+    pub fn list_templates(&self, tenant: Option<&str>) -> Vec<TemplateInfo> {
+        self.inner.list_templates(tenant)
     }
 
     /// Query the knowledge base. This can be an allow query or any other polar expression.
@@ -211,12 +1639,48 @@ impl Oso {
     /// oso.query("x = 1 or x = 2");
     /// ```
     pub fn query(&self, s: &str) -> crate::Result<Query> {
+        if !self.string_queries {
+            return Err(OsoError::StringQueriesDisabled);
+        }
         let query = self.inner.new_query(s, false)?;
         check_messages!(self.inner);
         let query = Query::new(query, self.host.clone());
         Ok(query)
     }
 
+    /// Query the knowledge base with placeholders like `:actor` in `s` replaced by the
+    /// parameters of the same name. Parameters are passed to Polar as values, like the
+    /// arguments of [`Oso::query_rule`], so untrusted input can't change the query.
+    /// # Examples
+    /// ```
+    /// use oso::{Oso, ToPolar};
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.load_str(r#"allow("alice", "read", 1);"#).unwrap();
+    /// let params = [("actor", "alice".to_polar()), ("resource", 1.to_polar())];
+    /// let mut query = oso.query_with(r#"allow(:actor, "read", :resource)"#, params).unwrap();
+    /// assert!(query.next().is_some());
+    /// ```
+    #[must_use = "Query that is not consumed does nothing."]
+    pub fn query_with<K, V>(
+        &self,
+        s: &str,
+        params: impl IntoIterator<Item = (K, V)>,
+    ) -> crate::Result<Query>
+    where
+        K: Into<String>,
+        V: ToPolar,
+    {
+        let mut query_host = self.host.clone();
+        let params = params
+            .into_iter()
+            .map(|(name, value)| (name.into(), value.to_polar().to_term(&mut query_host)))
+            .collect();
+        let query = self.inner.new_query_with_params(s, params, false)?;
+        check_messages!(self.inner);
+        Ok(Query::new(query, query_host))
+    }
+
     /// Query the knowledge base but with a rule name and argument list.
     /// This allows you to pass in rust values.
     /// # Examples
@@ -225,22 +1689,30 @@ impl Oso {
     /// ```
     #[must_use = "Query that is not consumed does nothing."]
     pub fn query_rule(&self, name: &str, args: impl ToPolarList) -> crate::Result<Query> {
+        let (query_term, query_host) = self.rule_call(name, args);
+        let query = self.inner.new_query_from_term(query_term, false);
+        check_messages!(self.inner);
+        let query = Query::new(query, query_host);
+        Ok(query)
+    }
+
+    /// Build a call to the rule `name`, along with the host that `args` were registered with.
+    fn rule_call(&self, name: &str, args: impl ToPolarList) -> (Term, Host) {
+        self.rule_call_values(name, args.to_polar_list())
+    }
+
+    pub(crate) fn rule_call_values(&self, name: &str, args: Vec<PolarValue>) -> (Term, Host) {
         let mut query_host = self.host.clone();
         let args = args
-            .to_polar_list()
             .iter()
             .map(|value| value.to_term(&mut query_host))
             .collect();
         let query_value = Value::Call(Call {
-            name: Symbol(name.to_string()),
+            name: Symbol::new(name),
             args,
             kwargs: None,
         });
-        let query_term = Term::new_from_ffi(query_value);
-        let query = self.inner.new_query_from_term(query_term, false);
-        check_messages!(self.inner);
-        let query = Query::new(query, query_host);
-        Ok(query)
+        (Term::new_from_ffi(query_value), query_host)
     }
 
     /// Register a rust type as a Polar class.
@@ -255,6 +1727,165 @@ impl Oso {
         self.register_constant(class, &class_name)
     }
 
+    /// Register a class defined at runtime, whose instances are [`DynObject`](crate::DynObject)s.
+    /// See [`DynClassSpec`](crate::DynClassSpec).
+    #[cfg(feature = "serde_json")]
+    pub fn register_dynamic_class(&mut self, spec: crate::DynClassSpec) -> crate::Result<()> {
+        self.register_class(spec.build())
+    }
+
+    /// Register a [`FactSource`] that answers queries for the rule `name` when the policy
+    /// contains no clauses for it.
+    ///
+    /// Like classes, fact sources must be registered before loading policies that call them.
+    pub fn register_fact_source<S: FactSource + 'static>(
+        &mut self,
+        name: &str,
+        source: S,
+    ) -> crate::Result<()> {
+        self.inner.register_fact_source(Symbol::new(name))?;
+        self.host
+            .fact_sources
+            .insert(name.to_string(), Arc::new(source));
+        Ok(())
+    }
+
+    /// Register `predicate` as the specializer `@name`, so that rules can require arguments
+    /// to satisfy it, e.g., `allow(actor: @verified_email, "read", _)`. Arguments that don't
+    /// convert to `T` don't match.
+    ///
+    /// Like classes, specializers must be registered before loading policies that use them.
+    ///
+    /// ```
+    /// # use oso::Oso;
+    /// let mut oso = Oso::new();
+    /// oso.register_specializer("verified_email", |email: String| email.ends_with("@example.com"));
+    /// oso.load_str(r#"allow(_actor: @verified_email, "read", _);"#).unwrap();
+    /// assert!(oso.is_allowed("alice@example.com", "read", "doc").unwrap());
+    /// assert!(!oso.is_allowed("mallory@example.org", "read", "doc").unwrap());
+    /// ```
+    pub fn register_specializer<T, F>(&mut self, name: &str, predicate: F)
+    where
+        T: FromPolar,
+        F: Fn(T) -> bool + Send + Sync + 'static,
+    {
+        self.inner.register_specializer_predicate(Symbol::new(name));
+        self.host.specializers.insert(
+            name.to_string(),
+            Arc::new(move |value| T::from_polar(value).is_ok_and(&predicate)),
+        );
+    }
+
+    /// Intercept the events of every query with `hook`, after any hooks added before. See
+    /// [`QueryHook`].
+    pub fn add_query_hook<H: QueryHook + 'static>(&mut self, hook: H) {
+        self.host.hooks.push(Arc::new(hook));
+    }
+
+    /// Assert the fact `name(args)`. Queries for the rule `name` match asserted facts before
+    /// trying any rules in the policy, and facts persist across policy reloads.
+    ///
+    /// Returns `false` if the fact was already present. Like fact sources, a rule's first fact
+    /// must be asserted before loading policies that call the rule without defining it.
+    ///
+    /// ```
+    /// use oso::Oso;
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.insert_fact("has_role", ("alice", "member", "repo")).unwrap();
+    /// oso.load_str(r#"allow(actor, "read", repo) if has_role(actor, "member", repo);"#).unwrap();
+    /// assert!(oso.is_allowed("alice", "read", "repo").unwrap());
+    ///
+    /// oso.delete_fact("has_role", ("alice", "member", "repo")).unwrap();
+    /// assert!(!oso.is_allowed("alice", "read", "repo").unwrap());
+    /// ```
+    pub fn insert_fact(&mut self, name: &str, args: impl ToPolarList) -> crate::Result<bool> {
+        let args = self.fact_args(args);
+        Ok(self.inner.insert_fact(Symbol::new(name), args)?)
+    }
+
+    /// Retract the fact `name(args)`. Returns `false` if there was no such fact.
+    ///
+    /// Application instances are matched by identity, so retracting a fact that contains an
+    /// instance requires a fact inserted with that same instance.
+    pub fn delete_fact(&mut self, name: &str, args: impl ToPolarList) -> crate::Result<bool> {
+        let args = self.fact_args(args);
+        Ok(self.inner.delete_fact(Symbol::new(name), args)?)
+    }
+
+    /// Assert many facts for the rule `name` at once. Returns the number of facts that weren't
+    /// already present.
+    pub fn insert_facts(
+        &mut self,
+        name: &str,
+        facts: Vec<Vec<PolarValue>>,
+    ) -> crate::Result<usize> {
+        let facts = facts
+            .iter()
+            .map(|args| args.iter().map(|arg| arg.to_term(&mut self.host)).collect())
+            .collect();
+        Ok(self.inner.insert_facts(Symbol::new(name), facts)?)
+    }
+
+    /// Load facts from a JSON object mapping rule names to lists of facts, where each fact is
+    /// a list of arguments. Returns the number of facts that weren't already present.
+    ///
+    /// The input is parsed incrementally and facts are indexed in batches as they're read, so
+    /// facts from batches preceding invalid data remain asserted.
+    ///
+    /// ```
+    /// use oso::Oso;
+    ///
+    /// let mut oso = Oso::new();
+    /// let data = r#"{"has_role": [["alice", "member", 1], ["bob", "owner", 2]]}"#;
+    /// assert_eq!(oso.load_facts_json(data.as_bytes()).unwrap(), 2);
+    /// ```
+    #[cfg(feature = "serde_json")]
+    pub fn load_facts_json<R: Read>(&mut self, reader: R) -> crate::Result<usize> {
+        crate::facts::read_json(reader, &mut |name, batch| self.insert_facts(name, batch))
+    }
+
+    /// Load facts for the rule described by `schema` from CSV, one fact per record. Returns
+    /// the number of facts that weren't already present.
+    ///
+    /// Facts are indexed in batches as the input is read, and facts from batches preceding an
+    /// invalid record remain asserted.
+    ///
+    /// ```
+    /// use oso::{Column, FactSchema, Oso};
+    ///
+    /// let mut oso = Oso::new();
+    /// let schema = FactSchema::new("has_role", vec![Column::String, Column::String, Column::Integer]);
+    /// let data = "user,role,repo\nalice,member,1\nbob,owner,2\n";
+    /// assert_eq!(oso.load_facts_csv(data.as_bytes(), &schema).unwrap(), 2);
+    /// ```
+    #[cfg(feature = "csv")]
+    pub fn load_facts_csv<R: Read>(
+        &mut self,
+        reader: R,
+        schema: &crate::FactSchema,
+    ) -> crate::Result<usize> {
+        let mut inserted = 0;
+        let mut batch = Vec::with_capacity(crate::facts::BATCH_SIZE);
+        for fact in crate::facts::read_csv(reader, schema) {
+            batch.push(fact?);
+            if batch.len() == crate::facts::BATCH_SIZE {
+                inserted += self.insert_facts(&schema.name, std::mem::take(&mut batch))?;
+            }
+        }
+        if !batch.is_empty() {
+            inserted += self.insert_facts(&schema.name, batch)?;
+        }
+        Ok(inserted)
+    }
+
+    fn fact_args(&mut self, args: impl ToPolarList) -> Vec<Term> {
+        args.to_polar_list()
+            .iter()
+            .map(|value| value.to_term(&mut self.host))
+            .collect()
+    }
+
     /// Register a rust type as a Polar constant.
     /// See [`oso::Class`] docs.
     pub fn register_constant<V: crate::host::ToPolar + Send + Sync>(
@@ -262,14 +1893,76 @@ impl Oso {
         value: V,
         name: &str,
     ) -> crate::Result<()> {
-        self.inner.register_constant(
-            Symbol(name.to_string()),
-            value.to_polar().to_term(&mut self.host),
-        )?;
+        self.inner
+            .register_constant(Symbol::new(name), value.to_polar().to_term(&mut self.host))?;
+        Ok(())
+    }
+
+    /// Register a rust type as a Polar constant whose value is sensitive, e.g., a secret. Its
+    /// value is shown as `[REDACTED]` in query traces, logs, and error messages, including the
+    /// values of variables bound to it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use oso::Oso;
+    /// let mut oso = Oso::new();
+    /// oso.register_sensitive_constant("hunter2", "ADMIN_PASSWORD").unwrap();
+    /// oso.load_str(r#"allow(_, "login", password) if password = ADMIN_PASSWORD;"#).unwrap();
+    /// assert!(oso.is_allowed("alice", "login", "hunter2").unwrap());
+    /// ```
+    pub fn register_sensitive_constant<V: crate::host::ToPolar + Send + Sync>(
+        &mut self,
+        value: V,
+        name: &str,
+    ) -> crate::Result<()> {
+        let term = value.to_polar().to_term(&mut self.host);
+        self.inner
+            .register_constant(Symbol::new(name), Term::new_sensitive(term.value().clone()))?;
         Ok(())
     }
 }
 
+/// Read the Polar files `filenames`, which must end in `.polar`.
+fn read_sources<P: AsRef<std::path::Path>>(filenames: Vec<P>) -> crate::Result<Vec<Source>> {
+    let mut sources = Vec::with_capacity(filenames.len());
+    for file in filenames {
+        let file = file.as_ref();
+        let filename = file.to_string_lossy().into_owned();
+        if !file.extension().map_or(false, |ext| ext == "polar") {
+            return Err(crate::OsoError::IncorrectFileType { filename });
+        }
+        let mut f = File::open(file)?;
+        let mut src = String::new();
+        f.read_to_string(&mut src)?;
+        sources.push(Source::new_with_name(filename, src));
+    }
+    Ok(sources)
+}
+
+/// Parse the policy `src` to the JSON array of its lines, for tools that edit policies
+/// structurally. [`Oso::load_ast_json`] loads the JSON. See [`polar_core::parser::Line`] for
+/// the schema.
+#[cfg(feature = "serde_json")]
+pub fn parse_to_json(src: &str) -> crate::Result<String> {
+    let lines = polar_core::parser::parse_lines(Source::new(src))?;
+    serde_json::to_string(&lines).map_err(serialization_error)
+}
+
+#[cfg(feature = "serde_json")]
+fn serialization_error(e: serde_json::Error) -> crate::OsoError {
+    polar_core::error::PolarError::from(polar_core::error::OperationalError::Serialization {
+        msg: e.to_string(),
+    })
+    .into()
+}
+
+/// Milliseconds since the Unix epoch, or 0 for times before it.
+pub(crate) fn to_unix_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
 // Make sure the `Oso` object is threadsafe
 #[cfg(test)]
 static_assertions::assert_impl_all!(Oso: Send, Sync);
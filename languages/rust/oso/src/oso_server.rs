@@ -0,0 +1,50 @@
+//! Serve authorization decisions for Polar policies over HTTP.
+//! See [`oso::server`] for the endpoints.
+
+use clap::{Arg, Command};
+
+use oso::server::Server;
+use oso::Oso;
+
+/// Build the App for handling command line parameters
+fn build_app() -> Command<'static> {
+    Command::new("oso-server")
+        .version(env!("CARGO_PKG_VERSION"))
+        .author(env!("CARGO_PKG_AUTHORS"))
+        .about("Oso authorization server")
+        .arg(
+            Arg::with_name("address")
+                .long("address")
+                .takes_value(true)
+                .default_value("127.0.0.1:8180")
+                .help("Address to listen on"),
+        )
+        .arg(
+            Arg::with_name("allow-reload")
+                .long("allow-reload")
+                .help("Allow clients to replace the policy with the /reload endpoint"),
+        )
+        .arg(
+            Arg::with_name("FILES")
+                .multiple(true)
+                .multiple_values(true)
+                .help("Specify one or more .polar files to load"),
+        )
+}
+
+pub fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::init();
+    let mut oso = Oso::new();
+
+    let matches = build_app().get_matches();
+    if matches.is_present("FILES") {
+        oso.load_files(matches.values_of("FILES").unwrap().collect())?;
+    }
+
+    let address = matches.value_of("address").unwrap();
+    tracing::info!("listening on {}", address);
+    Server::new(oso)
+        .allow_reload(matches.is_present("allow-reload"))
+        .listen(address)?;
+    Ok(())
+}
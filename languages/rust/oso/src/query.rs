@@ -1,13 +1,37 @@
+use std::cell::Cell;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
-
-use crate::errors::OsoError;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::dispatch::{Dispatcher, ExternalCall};
+use crate::errors::{InvalidCallError, OsoError};
+use crate::facts::{Facts, QueryFactCache};
+use crate::hooks::{HookAction, QueryHook};
 use crate::host::{Host, Instance, PolarIterator};
+use crate::session::ActorAttributes;
 use crate::{FromPolar, PolarValue};
 
+use polar_core::error::{PolarError, RuntimeError};
 use polar_core::events::*;
 use polar_core::terms::*;
 
+thread_local! {
+    /// When queries created on this thread must finish by, if they must. See [`with_deadline`].
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Run `f` with the queries it creates on this thread timing out at `deadline`, if they
+/// wouldn't time out sooner, e.g., to evaluate a request only as long as its client waits.
+#[cfg(feature = "server")]
+pub(crate) fn with_deadline<T>(deadline: Option<Instant>, f: impl FnOnce() -> T) -> T {
+    let outer = DEADLINE.with(|d| d.replace(deadline));
+    let result = f();
+    DEADLINE.with(|d| d.set(outer));
+    result
+}
+
 impl Iterator for Query {
     type Item = crate::Result<ResultSet>;
     fn next(&mut self) -> Option<Self::Item> {
@@ -19,23 +43,88 @@ pub struct Query {
     inner: polar_core::query::Query,
     /// Stores a map from call_id to the iterator the call iterates through
     iterators: HashMap<u64, PolarIterator>,
+    /// Stores a map from call_id to the answers of a fact source
+    facts: HashMap<u64, Facts>,
+    /// Fact source answers that may be reused for the rest of the query
+    fact_cache: QueryFactCache,
+    /// Results of calls of cached methods, by instance, method name, and arguments
+    method_cache: HashMap<(Term, Symbol, Vec<Term>), Term>,
+    /// The provider of the attributes of the actor of an `ActorSession`
+    pub(crate) actor_attributes: Option<Arc<ActorAttributes>>,
+    /// Answers application calls before the host does
+    dispatcher: Option<Arc<dyn Dispatcher>>,
     host: Host,
+    /// Number of results returned so far
+    returned: usize,
+    /// Whether a hook or application code panicked
+    panicked: bool,
 }
 
 impl Query {
-    pub fn new(inner: polar_core::query::Query, host: Host) -> Self {
+    pub fn new(mut inner: polar_core::query::Query, host: Host) -> Self {
+        let until_deadline = DEADLINE
+            .with(Cell::get)
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        if let Some(timeout) = host.query_timeout.into_iter().chain(until_deadline).min() {
+            // A timeout of 0 would disable timeouts.
+            inner.set_query_timeout(
+                u64::try_from(timeout.as_millis())
+                    .unwrap_or(u64::MAX)
+                    .max(1),
+            );
+        }
         Self {
             iterators: HashMap::new(),
+            facts: HashMap::new(),
+            fact_cache: QueryFactCache::new(),
+            method_cache: HashMap::new(),
+            actor_attributes: None,
+            dispatcher: None,
             inner,
             host,
+            returned: 0,
+            panicked: false,
         }
     }
 
+    /// The host the query caches instances on.
+    pub(crate) fn host_mut(&mut self) -> &mut Host {
+        &mut self.host
+    }
+
     pub fn source(&self) -> String {
         self.inner.source_info()
     }
 
+    /// The rule calls in progress when an error last aborted the query, if one has.
+    pub fn backtrace(&self) -> Option<&polar_core::traces::Backtrace> {
+        self.inner.backtrace()
+    }
+
+    /// Intercept the events of this query with `hook`, after the hooks added with
+    /// [`Oso::add_query_hook`](crate::Oso::add_query_hook).
+    pub fn add_hook<H: QueryHook + 'static>(&mut self, hook: H) {
+        self.host.hooks.push(Arc::new(hook));
+    }
+
+    /// Set whether this query checks that unification doesn't make cyclic terms, overriding
+    /// [`Oso::set_occurs_check`](crate::Oso::set_occurs_check).
+    pub fn set_occurs_check(&mut self, enabled: bool) {
+        self.inner.set_occurs_check(enabled);
+    }
+
+    /// Answer the application calls and class checks of this query with `dispatcher` before
+    /// the registered classes, replacing any dispatcher set before. See [`Dispatcher`].
+    pub fn set_dispatcher<D: Dispatcher + 'static>(&mut self, dispatcher: D) {
+        self.dispatcher = Some(Arc::new(dispatcher));
+    }
+
+    /// Return the next result, or `None` once the query is done. A panic in a hook or in
+    /// application code is returned as an `EnginePanic` error, after which the query is done.
     pub fn next_result(&mut self) -> Option<crate::Result<ResultSet>> {
+        if self.panicked {
+            return None;
+        }
         loop {
             let event = self.inner.next()?;
             check_messages!(self.inner);
@@ -44,54 +133,26 @@ impl Query {
             }
             let event = event.unwrap();
             tracing::debug!(event=?event);
-            let result = match event {
-                QueryEvent::None => Ok(()),
-                QueryEvent::Done { .. } => return None,
-                QueryEvent::Result { bindings, .. } => {
-                    return Some(ResultSet::from_bindings(bindings, self.host.clone()));
+            let action = match self.catch_panics(|query| Ok(query.host.hooks.on_event(&event))) {
+                Ok(action) => action,
+                Err(e) => return Some(Err(e)),
+            };
+            let result = match (action, event) {
+                (HookAction::Continue, QueryEvent::None | QueryEvent::Heartbeat { .. }) => Ok(()),
+                (HookAction::Continue, QueryEvent::Done { .. }) => return None,
+                (HookAction::Continue, QueryEvent::Result { bindings, trace }) => {
+                    self.returned += 1;
+                    return Some(
+                        ResultSet::from_bindings(bindings, self.host.clone())
+                            .map(|result| result.with_trace(trace)),
+                    );
                 }
-                QueryEvent::MakeExternal {
-                    instance_id,
-                    constructor,
-                } => self.handle_make_external(instance_id, constructor),
-                QueryEvent::NextExternal { call_id, iterable } => {
-                    self.handle_next_external(call_id, iterable)
+                (HookAction::Continue, event) => {
+                    self.catch_panics(|query| query.handle_event(event))
+                }
+                (action, event) => {
+                    self.catch_panics(|query| query.handle_hook_action(event, action))
                 }
-                QueryEvent::ExternalCall {
-                    call_id,
-                    instance,
-                    attribute,
-                    args,
-                    kwargs,
-                } => self.handle_external_call(call_id, instance, attribute, args, kwargs),
-                QueryEvent::ExternalOp {
-                    call_id,
-                    operator,
-                    args,
-                } => self.handle_external_op(call_id, operator, args),
-                QueryEvent::ExternalIsa {
-                    call_id,
-                    instance,
-                    class_tag,
-                } => self.handle_external_isa(call_id, instance, class_tag),
-                QueryEvent::ExternalIsSubSpecializer {
-                    call_id,
-                    instance_id,
-                    left_class_tag,
-                    right_class_tag,
-                } => self.handle_external_is_subspecializer(
-                    call_id,
-                    instance_id,
-                    left_class_tag,
-                    right_class_tag,
-                ),
-                QueryEvent::Debug { message } => self.handle_debug(message),
-                QueryEvent::ExternalIsSubclass {
-                    call_id,
-                    left_class_tag,
-                    right_class_tag,
-                } => self.handle_external_is_subclass(call_id, left_class_tag, right_class_tag),
-                event => unimplemented!("Unhandled event {:?}", event),
             };
 
             match result {
@@ -110,6 +171,223 @@ impl Query {
         }
     }
 
+    /// Return the next `n` results, and a cursor for the page after them if there may be more.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use oso::Oso;
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.load_str("f(1); f(2); f(3);").unwrap();
+    ///
+    /// let page = oso.query("f(x)")?.next_page(2)?;
+    /// assert_eq!(page.results.len(), 2);
+    ///
+    /// // Later, maybe in another request, run the query again to get the next page.
+    /// let cursor: oso::Cursor = page.next.unwrap().to_string().parse()?;
+    /// let mut query = oso.query("f(x)")?;
+    /// query.skip_to(&cursor)?;
+    /// let page = query.next_page(2)?;
+    /// assert_eq!(page.results[0].get_typed::<i64>("x")?, 3);
+    /// assert!(page.next.is_none());
+    /// # Ok::<(), oso::OsoError>(())
+    /// ```
+    pub fn next_page(&mut self, n: usize) -> crate::Result<Page> {
+        let results = self.take(n).collect::<crate::Result<Vec<_>>>()?;
+        let next = (results.len() == n).then_some(Cursor {
+            offset: self.returned,
+        });
+        Ok(Page { results, next })
+    }
+
+    /// Skip the results before `cursor`, which was returned by [`Query::next_page`] for the
+    /// same query. The skipped results are found again, so a page far into the results takes
+    /// as long to fetch as all the pages before it.
+    pub fn skip_to(&mut self, cursor: &Cursor) -> crate::Result<()> {
+        while self.returned < cursor.offset {
+            match self.next_result() {
+                Some(result) => drop(result?),
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Return all the results, sorted by the value bound to `var` in each of them. Results with
+    /// equal values stay in the order the query found them.
+    ///
+    /// Values must be all numbers, all strings, or all booleans. Application instances can't be
+    /// sorted, so bind an attribute of them to sort by instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use oso::{Oso, SortOrder};
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.load_str(r#"repo("oso", 3); repo("api", 1); repo("docs", 2);"#).unwrap();
+    ///
+    /// let names = oso
+    ///     .query("repo(name, stars)")?
+    ///     .sorted_by("name", SortOrder::Ascending)?
+    ///     .iter()
+    ///     .map(|result| result.get_typed("name"))
+    ///     .collect::<oso::Result<Vec<String>>>()?;
+    /// assert_eq!(names, vec!["api", "docs", "oso"]);
+    /// # Ok::<(), oso::OsoError>(())
+    /// ```
+    pub fn sorted_by(self, var: &str, order: SortOrder) -> crate::Result<Vec<ResultSet>> {
+        let mut results = self.collect::<crate::Result<Vec<_>>>()?;
+        let mut first: Option<&Term> = None;
+        for result in &results {
+            let key = result.sort_key(var)?;
+            if !matches!(
+                key.value(),
+                Value::Number(_) | Value::String(_) | Value::Boolean(_)
+            ) {
+                return Err(OsoError::InvalidSortKey {
+                    message: format!("{} is bound to {}, which can't be sorted", var, key),
+                });
+            }
+            match first {
+                Some(first)
+                    if std::mem::discriminant(first.value())
+                        != std::mem::discriminant(key.value()) =>
+                {
+                    return Err(OsoError::InvalidSortKey {
+                        message: format!("{} is bound to both {} and {}", var, first, key),
+                    });
+                }
+                Some(_) => (),
+                None => first = Some(key),
+            }
+        }
+
+        let compare = |a: &ResultSet, b: &ResultSet| {
+            match (
+                a.sort_key(var).unwrap().value(),
+                b.sort_key(var).unwrap().value(),
+            ) {
+                (Value::Number(a), Value::Number(b)) => a.partial_cmp(b),
+                (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+                (Value::Boolean(a), Value::Boolean(b)) => Some(a.cmp(b)),
+                _ => None,
+            }
+            .unwrap_or(std::cmp::Ordering::Equal)
+        };
+        match order {
+            SortOrder::Ascending => results.sort_by(compare),
+            SortOrder::Descending => results.sort_by(|a, b| compare(b, a)),
+        }
+        Ok(results)
+    }
+
+    /// Handle `event` with the default handler.
+    fn handle_event(&mut self, event: QueryEvent) -> crate::Result<()> {
+        match event {
+            QueryEvent::MakeExternal {
+                instance_id,
+                constructor,
+            } => self.handle_make_external(instance_id, constructor),
+            QueryEvent::NextExternal { call_id, iterable } => {
+                self.handle_next_external(call_id, iterable)
+            }
+            QueryEvent::ExternalCall {
+                call_id,
+                instance,
+                attribute,
+                args,
+                kwargs,
+            } => self.handle_external_call(call_id, instance, attribute, args, kwargs),
+            QueryEvent::ExternalCallBatch {
+                call_id,
+                instances,
+                attribute,
+                args,
+            } => self.handle_external_call_batch(call_id, instances, attribute, args),
+            QueryEvent::ExternalOp {
+                call_id,
+                operator,
+                args,
+            } => self.handle_external_op(call_id, operator, args),
+            QueryEvent::ExternalIsa {
+                call_id,
+                instance,
+                class_tag,
+            } => self.handle_external_isa(call_id, instance, class_tag),
+            QueryEvent::ExternalSpecializer {
+                call_id,
+                name,
+                instance,
+            } => self.handle_external_specializer(call_id, name, instance),
+            QueryEvent::ExternalIsSubSpecializer {
+                call_id,
+                instance_id,
+                left_class_tag,
+                right_class_tag,
+            } => self.handle_external_is_subspecializer(
+                call_id,
+                instance_id,
+                left_class_tag,
+                right_class_tag,
+            ),
+            QueryEvent::Debug { message } => self.handle_debug(message),
+            QueryEvent::ExternalIsSubclass {
+                call_id,
+                left_class_tag,
+                right_class_tag,
+            } => self.handle_external_is_subclass(call_id, left_class_tag, right_class_tag),
+            QueryEvent::ExternalFacts {
+                call_id,
+                name,
+                args,
+            } => self.handle_external_facts(call_id, name, args),
+            event => unimplemented!("Unhandled event {:?}", event),
+        }
+    }
+
+    /// Run `f`, returning a panic in a hook or in application code as an `EnginePanic` error
+    /// rather than unwinding into the caller.
+    fn catch_panics<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> crate::Result<T>,
+    ) -> crate::Result<T> {
+        // Handling an event at most caches one new instance on the host, so a panic can't leave
+        // the host half-updated. The VM may be left waiting for the result of the event, so the
+        // query isn't resumed after the error.
+        std::panic::catch_unwind(AssertUnwindSafe(|| f(self))).unwrap_or_else(|payload| {
+            self.panicked = true;
+            let error: PolarError = RuntimeError::engine_panic(payload).into();
+            Err(error.into())
+        })
+    }
+
+    /// Handle `event` as decided by a hook instead of the default handler.
+    fn handle_hook_action(&mut self, event: QueryEvent, action: HookAction) -> crate::Result<()> {
+        match (action, event) {
+            (HookAction::Fail(error), _) => Err(error),
+            (
+                HookAction::CallResult(result),
+                QueryEvent::ExternalCall { call_id, .. }
+                | QueryEvent::NextExternal { call_id, .. }
+                | QueryEvent::ExternalFacts { call_id, .. },
+            ) => match result {
+                Some(result) => self.call_result(call_id, result),
+                None => self.call_result_none(call_id),
+            },
+            (
+                HookAction::QuestionResult(result),
+                QueryEvent::ExternalIsa { call_id, .. }
+                | QueryEvent::ExternalSpecializer { call_id, .. }
+                | QueryEvent::ExternalOp { call_id, .. }
+                | QueryEvent::ExternalIsSubSpecializer { call_id, .. }
+                | QueryEvent::ExternalIsSubclass { call_id, .. },
+            ) => self.question_result(call_id, result),
+            (action, event) => lazy_error!("query hook can't answer {:?} with {:?}", event, action),
+        }
+    }
+
     fn question_result(&mut self, call_id: u64, result: bool) -> crate::Result<()> {
         Ok(self.inner.question_result(call_id, result)?)
     }
@@ -144,7 +422,7 @@ impl Query {
                         .iter()
                         .map(|term| PolarValue::from_term(term, &self.host))
                         .collect::<crate::Result<Vec<PolarValue>>>()?;
-                    self.host.make_instance(&name.0, args, instance_id)
+                    self.host.make_instance(name, args, instance_id)
                 }
             }
             _ => lazy_error!("invalid type for constructing an instance -- internal error"),
@@ -173,6 +451,38 @@ impl Query {
         }
     }
 
+    fn handle_external_facts(
+        &mut self,
+        call_id: u64,
+        name: Symbol,
+        args: Vec<Term>,
+    ) -> crate::Result<()> {
+        if !self.facts.contains_key(&call_id) {
+            tracing::trace!(call_id, name = %name, args = ?args, "facts");
+            let values = args
+                .iter()
+                .map(|arg| PolarValue::from_term(arg, &self.host))
+                .collect::<crate::Result<Vec<PolarValue>>>()?;
+            let facts =
+                self.host
+                    .fact_sources
+                    .facts(&name, &args, &values, &mut self.fact_cache)?;
+            self.facts.insert(call_id, facts);
+        }
+
+        match self.facts.get_mut(&call_id).and_then(|facts| facts.next()) {
+            Some(Ok(answer)) => self.call_result(call_id, PolarValue::List(answer)),
+            Some(Err(e)) => {
+                self.call_result_none(call_id)?;
+                Err(e)
+            }
+            None => {
+                self.facts.remove(&call_id);
+                self.call_result_none(call_id)
+            }
+        }
+    }
+
     fn handle_external_call(
         &mut self,
         call_id: u64,
@@ -185,25 +495,186 @@ impl Query {
             return lazy_error!("Invalid call error: kwargs not supported in Rust.");
         }
         tracing::trace!(call_id, name = %name, args = ?args, "call");
-        let instance = Instance::from_polar(PolarValue::from_term(&instance, &self.host)?)?;
+        let instance_term = instance;
+        let receiver = PolarValue::from_term(&instance_term, &self.host)?;
+        if let Some(dispatcher) = self.dispatcher.clone() {
+            let args = args
+                .as_ref()
+                .map(|args| {
+                    args.iter()
+                        .map(|v| PolarValue::from_term(v, &self.host))
+                        .collect::<crate::Result<Vec<PolarValue>>>()
+                })
+                .transpose()?;
+            let call = ExternalCall {
+                class: Instance::from_polar(receiver.clone())?
+                    .name(&self.host)
+                    .to_owned(),
+                receiver: receiver.clone(),
+                name: name.to_string(),
+                args,
+            };
+            if let Some(result) = dispatcher.call(&call) {
+                tracing::trace!(call_id, name = %name, "dispatched call");
+                return self.answer_call(call_id, result, false, None);
+            }
+        }
+        let instance = Instance::from_polar(receiver)?;
+        let actor_attributes = self
+            .actor_attributes
+            .clone()
+            .filter(|attributes| args.is_none() && attributes.is_actor(&instance_term));
+        let cache_key = args
+            .as_ref()
+            .filter(|_| {
+                instance
+                    .class(&self.host)
+                    .is_ok_and(|class| class.is_cached_method(&name))
+            })
+            .map(|args| (instance_term, name.clone(), args.clone()));
+        if let Some(result) = cache_key
+            .as_ref()
+            .and_then(|key| self.method_cache.get(key))
+        {
+            tracing::trace!(call_id, name = %name, "cached call");
+            return Ok(self.inner.call_result(call_id, Some(result.clone()))?);
+        }
+
+        let mut sensitive = false;
         let result = if let Some(args) = args {
             let args = args
                 .iter()
                 .map(|v| PolarValue::from_term(v, &self.host))
                 .collect::<crate::Result<Vec<PolarValue>>>()?;
-            instance.call(&name.0, args, &mut self.host)
+            instance.call(&name, args, &mut self.host)
         } else {
-            instance.get_attr(&name.0, &mut self.host)
+            sensitive = instance
+                .class(&self.host)
+                .is_ok_and(|class| class.is_sensitive_attr(&name));
+            let result = instance.get_attr(&name, &mut self.host);
+            let not_found = matches!(
+                result,
+                Err(OsoError::InvalidCallError(
+                    InvalidCallError::AttributeNotFound { .. }
+                ))
+            );
+            match actor_attributes {
+                Some(attributes) if not_found => attributes
+                    .get(&name, &self.host)
+                    .transpose()
+                    .unwrap_or(result),
+                _ => result,
+            }
         };
+        self.answer_call(call_id, result, sensitive, cache_key)
+    }
+
+    /// Answer the call `call_id` with `result`, hiding it from traces and logs if it's
+    /// `sensitive`, and caching it by `cache_key`, if any.
+    fn answer_call(
+        &mut self,
+        call_id: u64,
+        result: crate::Result<PolarValue>,
+        sensitive: bool,
+        cache_key: Option<(Term, Symbol, Vec<Term>)>,
+    ) -> crate::Result<()> {
         match result {
-            Ok(t) => self.call_result(call_id, t),
+            Ok(t) if sensitive => {
+                let term = Term::new_sensitive(t.to_term(&mut self.host).value().clone());
+                Ok(self.inner.call_result(call_id, Some(term))?)
+            }
+            Ok(t) => {
+                let term = t.to_term(&mut self.host);
+                if let Some(key) = cache_key {
+                    self.method_cache.insert(key, term.clone());
+                }
+                Ok(self.inner.call_result(call_id, Some(term))?)
+            }
+            Err(e) => {
+                self.call_result_none(call_id)?;
+                // Application errors are only passed to Polar when a `try` can catch them, so
+                // that uncaught errors keep their source.
+                if matches!(e, OsoError::ApplicationError { .. }) && self.inner.catches_errors() {
+                    return self.application_error(e);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Call the method `name` on all of `instances` at once: with the batch method of each of
+    /// their classes, or one instance at a time for classes without one.
+    fn handle_external_call_batch(
+        &mut self,
+        call_id: u64,
+        instances: Vec<Term>,
+        name: Symbol,
+        args: Vec<Term>,
+    ) -> crate::Result<()> {
+        tracing::trace!(call_id, name = %name, args = ?args, instances = instances.len(), "batch call");
+        match self.call_batch(&instances, &name, &args) {
+            Ok(results) => {
+                let results = results
+                    .into_iter()
+                    .map(|result| Some(result.to_term(&mut self.host)))
+                    .collect();
+                Ok(self.inner.call_results(call_id, results)?)
+            }
             Err(e) => {
                 self.call_result_none(call_id)?;
+                if matches!(e, OsoError::ApplicationError { .. }) && self.inner.catches_errors() {
+                    return self.application_error(e);
+                }
                 Err(e)
             }
         }
     }
 
+    fn call_batch(
+        &mut self,
+        instances: &[Term],
+        name: &Symbol,
+        args: &[Term],
+    ) -> crate::Result<Vec<PolarValue>> {
+        let instances = instances
+            .iter()
+            .map(|term| Instance::from_polar(PolarValue::from_term(term, &self.host)?))
+            .collect::<crate::Result<Vec<_>>>()?;
+        let args = args
+            .iter()
+            .map(|v| PolarValue::from_term(v, &self.host))
+            .collect::<crate::Result<Vec<PolarValue>>>()?;
+
+        // Call the batch method of each class once for all its instances, and call the method
+        // on instances of classes without one one at a time.
+        let mut results = vec![None; instances.len()];
+        let mut batches: Vec<(String, _, Vec<usize>)> = vec![];
+        for (i, instance) in instances.iter().enumerate() {
+            let batch = instance.class(&self.host).ok().and_then(|class| {
+                let method = class.get_batch_method(name)?.clone();
+                Some((class.name.clone(), method))
+            });
+            match batch {
+                Some((class, method)) => match batches.iter_mut().find(|(c, ..)| c == &class) {
+                    Some((_, _, group)) => group.push(i),
+                    None => batches.push((class, method, vec![i])),
+                },
+                None => results[i] = Some(instance.call(name, args.clone(), &mut self.host)?),
+            }
+        }
+        for (_, method, group) in batches {
+            let receivers = group
+                .iter()
+                .map(|&i| instances[i].clone())
+                .collect::<Vec<_>>();
+            let group_results = method.invoke(&receivers, args.clone(), &mut self.host)?;
+            for (i, result) in group.into_iter().zip(group_results) {
+                results[i] = Some(result);
+            }
+        }
+        Ok(results.into_iter().map(Option::unwrap).collect())
+    }
+
     fn handle_external_op(
         &mut self,
         call_id: u64,
@@ -229,9 +700,29 @@ impl Query {
         class_tag: Symbol,
     ) -> crate::Result<()> {
         tracing::debug!(instance = ?instance, class = %class_tag, "isa");
+        let instance = PolarValue::from_term(&instance, &self.host)?;
+        let dispatched = self
+            .dispatcher
+            .as_ref()
+            .and_then(|dispatcher| dispatcher.isa(&instance, &class_tag));
+        let res = match dispatched {
+            Some(res) => res,
+            None => self.host.isa(instance, &class_tag)?,
+        };
+        self.question_result(call_id, res)?;
+        Ok(())
+    }
+
+    fn handle_external_specializer(
+        &mut self,
+        call_id: u64,
+        name: Symbol,
+        instance: Term,
+    ) -> crate::Result<()> {
+        tracing::debug!(instance = ?instance, specializer = %name, "specializer");
         let res = self
             .host
-            .isa(PolarValue::from_term(&instance, &self.host)?, &class_tag.0)?;
+            .satisfies(PolarValue::from_term(&instance, &self.host)?, &name)?;
         self.question_result(call_id, res)?;
         Ok(())
     }
@@ -245,7 +736,7 @@ impl Query {
     ) -> crate::Result<()> {
         let res = self
             .host
-            .is_subspecializer(instance_id, &left_class_tag.0, &right_class_tag.0);
+            .is_subspecializer(instance_id, &left_class_tag, &right_class_tag);
         self.question_result(call_id, res)?;
         Ok(())
     }
@@ -269,10 +760,70 @@ impl Query {
     }
 }
 
+/// The order of the results returned by [`Query::sorted_by`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+/// A page of the results of a query, returned by [`Query::next_page`].
+#[derive(Debug)]
+pub struct Page {
+    pub results: Vec<ResultSet>,
+    /// Where the next page starts, if there may be more results.
+    pub next: Option<Cursor>,
+}
+
+/// The position of a page in the results of a query.
+///
+/// Cursors format as opaque strings that can be passed to clients and parsed back, so that
+/// later requests can fetch the following pages with [`Query::skip_to`]. A cursor is only
+/// meaningful for the query it came from, and only while the policy and data it reads are
+/// unchanged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cursor {
+    offset: usize,
+}
+
+impl Cursor {
+    /// The number of results before the cursor.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl std::fmt::Display for Cursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.offset)
+    }
+}
+
+impl std::str::FromStr for Cursor {
+    type Err = OsoError;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        s.parse()
+            .map(|offset| Self { offset })
+            .map_err(|_| OsoError::InvalidCursor {
+                cursor: s.to_owned(),
+            })
+    }
+}
+
+/// A rule with annotations other than `@doc`, `@deprecated`, and `@template`, e.g.,
+/// `@owner("secteam") allow(...) if ...;`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnnotatedRule {
+    pub rule: String,
+    pub annotations: BTreeMap<String, String>,
+}
+
 #[derive(Clone)]
 pub struct ResultSet {
     bindings: polar_core::kb::Bindings,
     host: crate::host::Host,
+    annotated_rules: Vec<AnnotatedRule>,
 }
 
 impl ResultSet {
@@ -294,34 +845,100 @@ This may mean you performed an operation in your policy over an unbound variable
             }
         }
 
-        Ok(Self { bindings, host })
+        Ok(Self {
+            bindings,
+            host,
+            annotated_rules: vec![],
+        })
+    }
+
+    /// Record the annotated rules applied in `trace`, the derivation of the result.
+    fn with_trace(mut self, trace: Option<polar_core::traces::TraceResult>) -> Self {
+        if let Some(trace) = trace {
+            self.annotated_rules = trace
+                .trace
+                .rules()
+                .into_iter()
+                .filter(|rule| !rule.metadata.annotations.is_empty())
+                .map(|rule| AnnotatedRule {
+                    rule: rule.name.to_string(),
+                    annotations: rule.metadata.annotations.clone(),
+                })
+                .collect();
+        }
+        self
+    }
+
+    /// The annotated rules applied to derive the result, outermost first, if the query was
+    /// traced. Otherwise, empty.
+    pub fn annotated_rules(&self) -> &[AnnotatedRule] {
+        &self.annotated_rules
     }
 
     /// Return the keys in bindings.
     pub fn keys(&self) -> Box<dyn std::iter::Iterator<Item = &str> + '_> {
-        Box::new(self.bindings.keys().map(|sym| sym.0.as_ref()))
+        Box::new(self.bindings.keys().map(|sym| sym.as_str()))
     }
 
     pub fn iter_bindings(&self) -> Box<dyn std::iter::Iterator<Item = (&str, &Value)> + '_> {
-        Box::new(self.bindings.iter().map(|(k, v)| (k.0.as_ref(), v.value())))
+        Box::new(self.bindings.iter().map(|(k, v)| (k.as_str(), v.value())))
     }
 
     pub fn is_empty(&self) -> bool {
         self.bindings.is_empty()
     }
 
+    pub(crate) fn bindings(&self) -> &polar_core::kb::Bindings {
+        &self.bindings
+    }
+
     pub fn get(&self, name: &str) -> Option<crate::PolarValue> {
         self.bindings
-            .get(&Symbol(name.to_string()))
+            .get(&Symbol::new(name))
             .map(|t| PolarValue::from_term(t, &self.host).unwrap())
     }
 
+    fn sort_key(&self, var: &str) -> crate::Result<&Term> {
+        self.bindings
+            .get(&Symbol::new(var))
+            .ok_or_else(|| OsoError::InvalidSortKey {
+                message: format!("{} is unbound", var),
+            })
+    }
+
+    /// Return the binding of `name` converted to `T`. Lists convert to collections, such as
+    /// `Vec<T>` and `HashSet<T>`, and to tuples of the same length.
+    ///
+    /// ```
+    /// use std::collections::HashSet;
+    /// use oso::Oso;
+    ///
+    /// let oso = Oso::new();
+    /// let mut query = oso.query(r#"x = ["a", "b", "a"] and y = ["a", 1]"#).unwrap();
+    /// let result = query.next().unwrap().unwrap();
+    ///
+    /// let x: Vec<String> = result.get_typed("x").unwrap();
+    /// assert_eq!(x, vec!["a", "b", "a"]);
+    /// let x: HashSet<String> = result.get_typed("x").unwrap();
+    /// assert_eq!(x.len(), 2);
+    /// let y: (String, i64) = result.get_typed("y").unwrap();
+    /// assert_eq!(y, ("a".to_owned(), 1));
+    /// ```
     pub fn get_typed<T: crate::host::FromPolar>(&self, name: &str) -> crate::Result<T> {
         self.get(name)
             .ok_or(crate::OsoError::FromPolar)
             .and_then(T::from_polar)
     }
 
+    /// Return every binding by variable name, e.g., to encode results as JSON without knowing
+    /// the names or types of their variables in advance.
+    pub fn into_map(self) -> HashMap<String, PolarValue> {
+        self.bindings
+            .iter()
+            .map(|(k, v)| (k.to_string(), PolarValue::from_term(v, &self.host).unwrap()))
+            .collect()
+    }
+
     pub fn into_event(self) -> ResultEvent {
         ResultEvent::new(self.bindings)
     }
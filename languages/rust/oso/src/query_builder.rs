@@ -0,0 +1,127 @@
+//! Queries for rules built from Rust values, without writing Polar.
+use crate::query::Query;
+use crate::{Oso, PolarValue, ToPolar};
+
+/// A builder for a query of one rule. Arguments are passed to Polar as values, like those of
+/// [`Oso::query_rule`], so untrusted input can't change the query.
+///
+/// ```
+/// use oso::{Oso, QueryBuilder};
+///
+/// let mut oso = Oso::new();
+/// oso.load_str(r#"allow("alice", "read", 1); allow("alice", "read", 2);"#).unwrap();
+///
+/// let call = QueryBuilder::rule("allow")
+///     .arg("alice")
+///     .arg("read")
+///     .var("resource")
+///     .build();
+/// let resources: Vec<i64> = oso
+///     .query_call(call)
+///     .unwrap()
+///     .map(|result| result.unwrap().get_typed("resource").unwrap())
+///     .collect();
+/// assert_eq!(resources, vec![1, 2]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct QueryBuilder {
+    name: String,
+    args: Vec<PolarValue>,
+}
+
+impl QueryBuilder {
+    /// Start a query of the rule `name`.
+    pub fn rule(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            args: vec![],
+        }
+    }
+
+    /// Add an argument.
+    pub fn arg<T: ToPolar>(mut self, value: T) -> Self {
+        self.args.push(value.to_polar());
+        self
+    }
+
+    /// Add a variable as an argument, whose values are bound in the query's results.
+    pub fn var(mut self, name: &str) -> Self {
+        self.args.push(PolarValue::Variable(name.to_owned()));
+        self
+    }
+
+    pub fn build(self) -> RuleCall {
+        RuleCall {
+            name: self.name,
+            args: self.args,
+        }
+    }
+}
+
+/// A call of a rule, built with a [`QueryBuilder`] or a [`RuleSignature`], for querying with
+/// [`Oso::query_call`].
+#[derive(Clone, Debug)]
+pub struct RuleCall {
+    name: String,
+    args: Vec<PolarValue>,
+}
+
+impl RuleCall {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn args(&self) -> &[PolarValue] {
+        &self.args
+    }
+}
+
+/// A rule of `N` parameters, for building calls with the right number of arguments, checked
+/// at compile time.
+///
+/// ```
+/// use oso::{Oso, PolarValue, RuleSignature, ToPolar};
+///
+/// const ALLOW: RuleSignature<3> = RuleSignature::new("allow");
+///
+/// let mut oso = Oso::new();
+/// oso.load_str(r#"allow("alice", "read", 1);"#).unwrap();
+///
+/// let x = PolarValue::Variable("x".to_owned());
+/// let call = ALLOW.call(["alice".to_polar(), "read".to_polar(), x]);
+/// let mut query = oso.query_call(call).unwrap();
+/// assert_eq!(query.next().unwrap().unwrap().get_typed::<i64>("x").unwrap(), 1);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct RuleSignature<const N: usize> {
+    name: &'static str,
+}
+
+impl<const N: usize> RuleSignature<N> {
+    pub const fn new(name: &'static str) -> Self {
+        Self { name }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Build a call of the rule with `args`.
+    pub fn call(&self, args: [PolarValue; N]) -> RuleCall {
+        RuleCall {
+            name: self.name.to_owned(),
+            args: args.into(),
+        }
+    }
+}
+
+impl Oso {
+    /// Query for the rule call `call`, built with a [`QueryBuilder`] or a [`RuleSignature`].
+    #[must_use = "Query that is not consumed does nothing."]
+    pub fn query_call(&self, call: RuleCall) -> crate::Result<Query> {
+        let (query_term, query_host) = self.rule_call_values(&call.name, call.args);
+        let query = self.inner.new_query_from_term(query_term, false);
+        check_messages!(self.inner);
+        Ok(Query::new(query, query_host))
+    }
+}
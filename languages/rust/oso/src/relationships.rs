@@ -0,0 +1,307 @@
+//! Keep facts in sync with a Zanzibar-style relationship store, like SpiceDB or OpenFGA, so
+//! that local rules can use relationships that live in the store.
+//!
+//! A [`RelationshipStore`] wraps a client of the store's snapshot and watch APIs. A
+//! [`RelationshipSync`] loads a snapshot of the store's relation tuples as facts, and then
+//! applies the changes it watches, so that each tuple `resource#relation@subject` is the fact
+//! `has_relation(subject, relation, resource)`.
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use polar_core::terms::{Symbol, Term};
+
+use crate::{Oso, OsoError, PolarValue};
+
+/// An object in a relationship store, e.g., `document:readme`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ObjectRef {
+    pub object_type: String,
+    pub object_id: String,
+}
+
+impl ObjectRef {
+    pub fn new<T: Into<String>, I: Into<String>>(object_type: T, object_id: I) -> Self {
+        Self {
+            object_type: object_type.into(),
+            object_id: object_id.into(),
+        }
+    }
+}
+
+impl fmt::Display for ObjectRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.object_type, self.object_id)
+    }
+}
+
+/// The subject of a relation tuple: an object, or the objects with a relation to it, e.g.,
+/// `group:eng#member`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SubjectRef {
+    pub object: ObjectRef,
+    pub relation: Option<String>,
+}
+
+impl fmt::Display for SubjectRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.object)?;
+        if let Some(relation) = &self.relation {
+            write!(f, "#{}", relation)?;
+        }
+        Ok(())
+    }
+}
+
+/// A relation tuple, written `resource#relation@subject`, e.g.,
+/// `document:readme#viewer@user:alice`.
+///
+/// ```
+/// use oso::RelationTuple;
+///
+/// let tuple: RelationTuple = "document:readme#viewer@group:eng#member".parse().unwrap();
+/// assert_eq!(tuple.relation, "viewer");
+/// assert_eq!(tuple.subject.to_string(), "group:eng#member");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RelationTuple {
+    pub resource: ObjectRef,
+    pub relation: String,
+    pub subject: SubjectRef,
+}
+
+impl fmt::Display for RelationTuple {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}#{}@{}", self.resource, self.relation, self.subject)
+    }
+}
+
+impl FromStr for RelationTuple {
+    type Err = OsoError;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        let invalid = || OsoError::InvalidFactData {
+            message: format!("invalid relation tuple `{}`", s),
+        };
+        let object = |s: &str| match s.split_once(':') {
+            Some((object_type, object_id)) if !object_type.is_empty() && !object_id.is_empty() => {
+                Some(ObjectRef::new(object_type, object_id))
+            }
+            _ => None,
+        };
+        let (resource, rest) = s.split_once('#').ok_or_else(invalid)?;
+        let (relation, subject) = rest.split_once('@').ok_or_else(invalid)?;
+        let (subject, subject_relation) = match subject.split_once('#') {
+            Some((subject, relation)) => (subject, Some(relation.to_owned())),
+            None => (subject, None),
+        };
+        if relation.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            resource: object(resource).ok_or_else(invalid)?,
+            relation: relation.to_owned(),
+            subject: SubjectRef {
+                object: object(subject).ok_or_else(invalid)?,
+                relation: subject_relation,
+            },
+        })
+    }
+}
+
+/// Whether a watched change wrote or deleted a tuple.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TupleOperation {
+    /// The tuple was created, or touched, i.e., written whether or not it existed.
+    Write,
+    Delete,
+}
+
+/// The changes to a store up to `revision`, e.g., a SpiceDB `WatchResponse` or a page of
+/// OpenFGA `ReadChanges`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WatchBatch {
+    pub updates: Vec<(TupleOperation, RelationTuple)>,
+    /// The revision the store is at after the updates, e.g., a SpiceDB `ZedToken` or an
+    /// OpenFGA continuation token.
+    pub revision: String,
+}
+
+/// A client of a relationship store.
+pub trait RelationshipStore: Send {
+    /// Read all of the store's tuples, and the revision they were read at.
+    fn snapshot(&mut self) -> crate::Result<(Vec<RelationTuple>, String)>;
+
+    /// Wait for the changes after `revision`. Returns `None` once the store stops sending
+    /// changes, e.g., when its watch stream closes.
+    fn watch(&mut self, revision: &str) -> crate::Result<Option<WatchBatch>>;
+}
+
+type Mapper = Box<dyn Fn(&SubjectRef) -> PolarValue + Send + Sync>;
+
+/// Keeps facts for the relation tuples of a [`RelationshipStore`].
+///
+/// Objects are passed to Polar as strings, e.g., `"user:alice"` or `"group:eng#member"`, unless
+/// they're mapped to other values with [`RelationshipSync::map_objects`]. Mapped values must
+/// be compared by value, not by identity like application instances, so that facts for
+/// deleted tuples can be retracted.
+///
+/// # Examples
+///
+/// ```
+/// use oso::{Oso, RelationTuple, RelationshipStore, RelationshipSync, WatchBatch};
+///
+/// struct Store(Vec<RelationTuple>);
+///
+/// impl RelationshipStore for Store {
+///     fn snapshot(&mut self) -> oso::Result<(Vec<RelationTuple>, String)> {
+///         Ok((self.0.clone(), "1".to_owned()))
+///     }
+///
+///     fn watch(&mut self, _revision: &str) -> oso::Result<Option<WatchBatch>> {
+///         Ok(None)
+///     }
+/// }
+///
+/// let mut oso = Oso::new();
+/// let tuple = "document:readme#viewer@user:alice".parse().unwrap();
+/// let mut sync = RelationshipSync::new(Store(vec![tuple]));
+/// sync.snapshot(&mut oso).unwrap();
+///
+/// oso.load_str(
+///     r#"allow(actor, "read", resource) if has_relation(actor, "viewer", resource);"#,
+/// )
+/// .unwrap();
+/// assert!(oso.is_allowed("user:alice", "read", "document:readme").unwrap());
+/// ```
+pub struct RelationshipSync<S: RelationshipStore> {
+    store: S,
+    rule: String,
+    mapper: Mapper,
+    /// The facts of the tuples in the store, by tuple.
+    facts: HashMap<RelationTuple, Vec<PolarValue>>,
+    revision: Option<String>,
+}
+
+impl<S: RelationshipStore> RelationshipSync<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            rule: "has_relation".to_owned(),
+            mapper: Box::new(|subject| PolarValue::String(subject.to_string())),
+            facts: HashMap::new(),
+            revision: None,
+        }
+    }
+
+    /// Keep facts for the rule `rule` instead of `has_relation`.
+    pub fn with_rule(mut self, rule: &str) -> Self {
+        self.rule = rule.to_owned();
+        self
+    }
+
+    /// Pass objects to Polar as the values `f` maps them to. Resources are passed as subjects
+    /// without relations.
+    pub fn map_objects<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&SubjectRef) -> PolarValue + Send + Sync + 'static,
+    {
+        self.mapper = Box::new(f);
+        self
+    }
+
+    /// The revision of the store that the facts are in sync with, if any.
+    pub fn revision(&self) -> Option<&str> {
+        self.revision.as_deref()
+    }
+
+    /// Replace the facts with those of a snapshot of the store, e.g., to start syncing, or to
+    /// resume after the watch failed. Returns the number of tuples in the snapshot.
+    ///
+    /// Take the first snapshot before loading policies that call the rule: it defines the rule
+    /// even if the store is empty.
+    pub fn snapshot(&mut self, oso: &mut Oso) -> crate::Result<usize> {
+        let (tuples, revision) = self.store.snapshot()?;
+        oso.inner.insert_facts(Symbol::new(&self.rule), vec![])?;
+        let mut stale = std::mem::take(&mut self.facts);
+        for tuple in tuples {
+            match stale.remove_entry(&tuple) {
+                Some((tuple, fact)) => {
+                    self.facts.insert(tuple, fact);
+                }
+                None => self.write(oso, tuple)?,
+            }
+        }
+        for (_, fact) in stale {
+            self.delete(oso, &fact)?;
+        }
+        self.revision = Some(revision);
+        Ok(self.facts.len())
+    }
+
+    /// Wait for the next changes to the store and apply them. Takes a snapshot first if there
+    /// isn't one yet. Returns `false` once the store stops sending changes.
+    pub fn poll(&mut self, oso: &mut Oso) -> crate::Result<bool> {
+        let revision = match &self.revision {
+            Some(revision) => revision.clone(),
+            None => {
+                self.snapshot(oso)?;
+                return Ok(true);
+            }
+        };
+        let batch = match self.store.watch(&revision)? {
+            Some(batch) => batch,
+            None => return Ok(false),
+        };
+        for (operation, tuple) in batch.updates {
+            match operation {
+                TupleOperation::Write if !self.facts.contains_key(&tuple) => {
+                    self.write(oso, tuple)?
+                }
+                TupleOperation::Write => (),
+                TupleOperation::Delete => {
+                    if let Some(fact) = self.facts.remove(&tuple) {
+                        self.delete(oso, &fact)?;
+                    }
+                }
+            }
+        }
+        self.revision = Some(batch.revision);
+        Ok(true)
+    }
+
+    /// Apply changes until the store stops sending them. Run this on a thread of its own with
+    /// a clone of `Oso`, which shares its facts.
+    pub fn run(&mut self, oso: &mut Oso) -> crate::Result<()> {
+        while self.poll(oso)? {}
+        Ok(())
+    }
+
+    fn write(&mut self, oso: &mut Oso, tuple: RelationTuple) -> crate::Result<()> {
+        let resource = SubjectRef {
+            object: tuple.resource.clone(),
+            relation: None,
+        };
+        let fact = vec![
+            (self.mapper)(&tuple.subject),
+            PolarValue::String(tuple.relation.clone()),
+            (self.mapper)(&resource),
+        ];
+        let args = fact_terms(oso, &fact);
+        oso.inner.insert_fact(Symbol::new(&self.rule), args)?;
+        self.facts.insert(tuple, fact);
+        Ok(())
+    }
+
+    fn delete(&self, oso: &mut Oso, fact: &[PolarValue]) -> crate::Result<()> {
+        let args = fact_terms(oso, fact);
+        oso.inner.delete_fact(Symbol::new(&self.rule), args)?;
+        Ok(())
+    }
+}
+
+fn fact_terms(oso: &mut Oso, fact: &[PolarValue]) -> Vec<Term> {
+    fact.iter()
+        .map(|value| value.to_term(&mut oso.host))
+        .collect()
+}
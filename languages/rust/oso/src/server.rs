@@ -0,0 +1,318 @@
+//! Serve authorization decisions over HTTP, so that services written in any language can
+//! share one policy engine.
+//!
+//! Every endpoint accepts a `POST` with a JSON object body and responds with a JSON object:
+//!
+//! | Path                  | Request                                | Response             |
+//! |-----------------------|----------------------------------------|----------------------|
+//! | `/authorize`          | `{"actor", "action", "resource"}`      | `{"allowed": bool}`  |
+//! | `/authorized_actions` | `{"actor", "resource"}`                | `{"actions": [...]}` |
+//! | `/authorized_query`   | `{"actor", "action", "resource_type"}` | `{"filter": Filter}` |
+//! | `/reload`             | `{"policy": "..."}`                    | `{}`                 |
+//!
+//! Decisions are made as by the [`Enforcer`] implementation for [`Oso`], so `deny` rules
+//! override `allow` rules. Actions are listed as `"*"` when every action is allowed. Failed
+//! requests respond with an error status and `{"error": "..."}`. Requests with an
+//! `Oso-Deadline-Ms` header, the milliseconds left until the client's deadline, fail with
+//! status 504 if their queries run past it, and immediately if it's 0.
+//!
+//! `/reload` replaces the policy with the one sent, or with the Polar files named by
+//! `{"files": [...]}`, if the server [allows it](Server::allow_reload) (and [allows
+//! files](Server::allow_reload_files)). Otherwise it responds with status 403.
+//!
+//! Actors and resources are sent as JSON *descriptors*, which [`Resolver`]s turn into the
+//! values passed to the policy. By default, descriptors are passed to the policy as-is, e.g.,
+//! `{"id": "alice"}` becomes the dictionary `{id: "alice"}`.
+use std::io::{BufReader, BufWriter};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::http::{read_request, write_response, Request, DEADLINE_HEADER};
+use crate::query::with_deadline;
+use crate::{Enforcer, FromPolar, Oso, OsoError, PolarValue, ToPolar};
+
+/// Turns a JSON descriptor of an actor or resource into the value passed to the policy.
+///
+/// Closures implement `Resolver`, e.g., to look up an application instance by ID:
+///
+/// ```
+/// use oso::server::Server;
+/// use oso::{Oso, PolarValue, ToPolar};
+///
+/// let server = Server::new(Oso::new()).with_actor_resolver(|actor: serde_json::Value| {
+///     Ok(actor["id"].as_str().unwrap_or_default().to_owned().to_polar())
+/// });
+/// ```
+pub trait Resolver: Send + Sync {
+    fn resolve(&self, descriptor: Value) -> crate::Result<PolarValue>;
+}
+
+impl<F> Resolver for F
+where
+    F: Fn(Value) -> crate::Result<PolarValue> + Send + Sync,
+{
+    fn resolve(&self, descriptor: Value) -> crate::Result<PolarValue> {
+        self(descriptor)
+    }
+}
+
+fn resolve_as_is(descriptor: Value) -> crate::Result<PolarValue> {
+    Ok(descriptor.to_polar())
+}
+
+/// Connections beyond this many at once are refused, unless set with
+/// [`Server::with_max_connections`].
+const DEFAULT_MAX_CONNECTIONS: usize = 256;
+
+/// Connections that send no request for this long are closed.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// An error response: the status code & message.
+type Failure = (u16, String);
+
+fn bad_request(message: impl ToString) -> Failure {
+    (400, message.to_string())
+}
+
+fn internal_error(err: OsoError) -> Failure {
+    match &err {
+        OsoError::Polar(e) if e.kind() == "RuntimeError::QueryTimeout" => {
+            (504, "deadline exceeded".to_owned())
+        }
+        _ => (500, err.to_string()),
+    }
+}
+
+fn field(body: &mut Value, name: &str) -> Result<Value, Failure> {
+    match body.get_mut(name).map(Value::take) {
+        None | Some(Value::Null) => Err(bad_request(format!("missing field \"{}\"", name))),
+        Some(value) => Ok(value),
+    }
+}
+
+fn string_field(body: &mut Value, name: &str) -> Result<String, Failure> {
+    match field(body, name)? {
+        Value::String(s) => Ok(s),
+        _ => Err(bad_request(format!("field \"{}\" must be a string", name))),
+    }
+}
+
+/// An HTTP server answering authorization requests with an [`Oso`] instance.
+///
+/// Register classes and load policies on the instance before creating the server. If the
+/// server [allows it](Server::allow_reload), policies may be replaced while serving with the
+/// `/reload` endpoint, as by [`Oso::reload_str`]; requests made while a reload is in progress
+/// wait for it to finish. If a reload fails, the previous policy stays loaded.
+#[derive(Clone)]
+pub struct Server {
+    oso: Arc<RwLock<Oso>>,
+    actors: Arc<dyn Resolver>,
+    resources: Arc<dyn Resolver>,
+    reload: bool,
+    reload_files: bool,
+    max_connections: usize,
+    connections: Arc<AtomicUsize>,
+}
+
+/// Counts a connection as open until dropped.
+struct ConnectionGuard(Arc<AtomicUsize>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Server {
+    pub fn new(oso: Oso) -> Self {
+        Self {
+            oso: Arc::new(RwLock::new(oso)),
+            actors: Arc::new(resolve_as_is),
+            resources: Arc::new(resolve_as_is),
+            reload: false,
+            reload_files: false,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            connections: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Allow `/reload` to replace the policy. Requests aren't authenticated, so only allow this
+    /// if every client that can reach the server may change the policy.
+    pub fn allow_reload(mut self, allow: bool) -> Self {
+        self.reload = allow;
+        self
+    }
+
+    /// Allow `/reload` to load the Polar files named in a request, as paths on the server, if
+    /// it [allows reloads](Server::allow_reload) at all. Only allow this if every client that
+    /// can reach the server may read those files.
+    pub fn allow_reload_files(mut self, allow: bool) -> Self {
+        self.reload_files = allow;
+        self
+    }
+
+    /// Refuse connections beyond `max` at once, each of which is handled on its own thread.
+    pub fn with_max_connections(mut self, max: usize) -> Self {
+        self.max_connections = max;
+        self
+    }
+
+    /// Set the resolver for actor descriptors.
+    pub fn with_actor_resolver<R: Resolver + 'static>(mut self, resolver: R) -> Self {
+        self.actors = Arc::new(resolver);
+        self
+    }
+
+    /// Set the resolver for resource descriptors.
+    pub fn with_resource_resolver<R: Resolver + 'static>(mut self, resolver: R) -> Self {
+        self.resources = Arc::new(resolver);
+        self
+    }
+
+    /// Listen on `addr` and serve requests.
+    pub fn listen<A: ToSocketAddrs>(&self, addr: A) -> crate::Result<()> {
+        self.serve(TcpListener::bind(addr)?)
+    }
+
+    /// Serve requests on `listener`, handling each connection on its own thread. Connections
+    /// beyond the [maximum](Server::with_max_connections) are closed as they're accepted, and
+    /// errors accepting connections, e.g., running out of file descriptors, are logged.
+    pub fn serve(&self, listener: TcpListener) -> crate::Result<()> {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::warn!("failed to accept a connection: {}", e);
+                    continue;
+                }
+            };
+            if self.connections.fetch_add(1, Ordering::SeqCst) >= self.max_connections {
+                self.connections.fetch_sub(1, Ordering::SeqCst);
+                tracing::debug!("refusing connection: too many connections");
+                continue;
+            }
+            let guard = ConnectionGuard(self.connections.clone());
+            let server = self.clone();
+            std::thread::spawn(move || {
+                let _guard = guard;
+                if let Err(e) = server.serve_connection(stream) {
+                    tracing::debug!("closing connection: {}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    fn serve_connection(&self, stream: TcpStream) -> std::io::Result<()> {
+        stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = BufWriter::new(stream);
+        while let Some(request) = read_request(&mut reader)? {
+            let (status, body) = match self.handle(&request) {
+                Ok(body) => (200, body),
+                Err((status, message)) => (status, json!({ "error": message })),
+            };
+            let headers = if request.keep_alive() {
+                vec![]
+            } else {
+                vec![("Connection", "close".to_owned())]
+            };
+            write_response(&mut writer, status, &headers, body.to_string().as_bytes())?;
+            if !request.keep_alive() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&self, request: &Request) -> Result<Value, Failure> {
+        if request.method != "POST" {
+            return Err((405, format!("{} is not supported", request.method)));
+        }
+        // Don't evaluate requests for longer than the client will wait for them.
+        let deadline = match request.header(DEADLINE_HEADER).map(str::parse::<u64>) {
+            None => None,
+            Some(Ok(0)) => return Err((504, "deadline exceeded".to_owned())),
+            Some(Ok(ms)) => Some(Instant::now() + Duration::from_millis(ms)),
+            Some(Err(_)) => return Err(bad_request(format!("invalid {} header", DEADLINE_HEADER))),
+        };
+        let body: Value = serde_json::from_slice(&request.body).map_err(bad_request)?;
+        if !body.is_object() {
+            return Err(bad_request("request body must be an object"));
+        }
+        with_deadline(deadline, || self.route(&request.path, body))
+    }
+
+    fn route(&self, path: &str, mut body: Value) -> Result<Value, Failure> {
+        match path {
+            "/authorize" => {
+                let (actor, resource) = self.resolve(&mut body)?;
+                let action = field(&mut body, "action")?.to_polar();
+                let oso = self.oso.read().unwrap();
+                let allowed =
+                    Enforcer::authorize(&*oso, actor, action, resource).map_err(internal_error)?;
+                Ok(json!({ "allowed": allowed }))
+            }
+            "/authorized_actions" => {
+                let (actor, resource) = self.resolve(&mut body)?;
+                let oso = self.oso.read().unwrap();
+                let actions = Enforcer::authorized_actions(&*oso, actor, resource)
+                    .map_err(internal_error)?
+                    .into_iter()
+                    // An unbound action means that every action is allowed.
+                    .map(|action| match action {
+                        PolarValue::Variable(_) => Ok(Value::String("*".to_owned())),
+                        action => Value::from_polar(action).map_err(internal_error),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(json!({ "actions": actions }))
+            }
+            "/authorized_query" => {
+                let actor = self
+                    .actors
+                    .resolve(field(&mut body, "actor")?)
+                    .map_err(bad_request)?;
+                let action = field(&mut body, "action")?.to_polar();
+                let resource_type = string_field(&mut body, "resource_type")?;
+                let oso = self.oso.read().unwrap();
+                let filter = oso
+                    .authorized_query(actor, action, &resource_type)
+                    .map_err(internal_error)?;
+                Ok(json!({ "filter": filter }))
+            }
+            "/reload" if !self.reload => Err((403, "reloading policies is disabled".to_owned())),
+            "/reload" => {
+                if let Ok(policy) = string_field(&mut body, "policy") {
+                    let mut oso = self.oso.write().unwrap();
+                    oso.reload_str(&policy).map_err(bad_request)?;
+                } else if self.reload_files {
+                    let files = field(&mut body, "files")?;
+                    let files = Vec::<String>::from_polar(files.to_polar())
+                        .map_err(|_| bad_request("field \"files\" must be a list of strings"))?;
+                    let mut oso = self.oso.write().unwrap();
+                    oso.reload_files(files).map_err(bad_request)?;
+                } else {
+                    return Err(bad_request("missing field \"policy\""));
+                }
+                Ok(json!({}))
+            }
+            path => Err((404, format!("no endpoint at {}", path))),
+        }
+    }
+
+    fn resolve(&self, body: &mut Value) -> Result<(PolarValue, PolarValue), Failure> {
+        let actor = self
+            .actors
+            .resolve(field(body, "actor")?)
+            .map_err(bad_request)?;
+        let resource = self
+            .resources
+            .resolve(field(body, "resource")?)
+            .map_err(bad_request)?;
+        Ok((actor, resource))
+    }
+}
@@ -0,0 +1,237 @@
+//! Authorization requests made on behalf of a single actor.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use polar_core::terms::{Call, Symbol, Term, Value};
+use polar_core::Precomputed;
+
+use crate::host::Host;
+use crate::oso::should_query_allow;
+use crate::query::Query;
+use crate::{Decision, Oso, PolarValue, ToPolar, ToPolarList};
+
+/// An actor bound to an [`Oso`] instance, created by [`Oso::for_actor`].
+///
+/// The actor is converted to a Polar value once, when the session is created, instead of once
+/// per query. Sessions are cheap to create and meant to be short-lived, e.g., one per request.
+///
+/// ```
+/// use oso::Oso;
+///
+/// let mut oso = Oso::new();
+/// oso.load_str(r#"allow(actor, "read", _doc) if is_member(actor);
+///                 is_member("alice");"#).unwrap();
+///
+/// let mut session = oso.for_actor("alice");
+/// session.precompute("is_member", 1).unwrap();
+/// assert!(session.is_allowed("read", "doc").unwrap());
+/// assert!(!session.is_allowed("write", "doc").unwrap());
+/// ```
+#[derive(Clone)]
+pub struct ActorSession {
+    oso: Oso,
+    host: Host,
+    actor: Term,
+    /// The results of the rules precomputed for the actor, if any.
+    precomputed: Option<Arc<Precomputed>>,
+    attributes: Option<Arc<ActorAttributes>>,
+}
+
+/// Looks up attributes of an actor that its class doesn't have, e.g., the groups of a user
+/// from an LDAP directory or a SCIM service, for an [`ActorSession`].
+///
+/// The provider is only asked for an attribute when a policy uses it, e.g., `actor.groups`,
+/// and then at most once per session.
+///
+/// # Examples
+///
+/// ```
+/// use oso::{Oso, PolarClass, PolarValue};
+///
+/// #[derive(Clone, PolarClass)]
+/// struct User {
+///     #[polar(attribute)]
+///     name: String,
+/// }
+///
+/// let mut oso = Oso::new();
+/// oso.register_class(User::get_polar_class()).unwrap();
+/// oso.load_str(r#"allow(user: User, "read", doc) if doc.group in user.groups;"#).unwrap();
+///
+/// let user = User { name: "alice".to_owned() };
+/// let session = oso.for_actor(user).with_attribute_provider(|_: &PolarValue, name: &str| {
+///     Ok(match name {
+///         "groups" => Some(PolarValue::List(vec![PolarValue::String("eng".to_owned())])),
+///         _ => None,
+///     })
+/// });
+/// let doc = maplit::hashmap! { "group" => "eng" };
+/// assert!(session.is_allowed("read", doc).unwrap());
+/// ```
+pub trait ActorAttributeProvider: Send + Sync {
+    /// Return the attribute `name` of `actor`, or `None` if the actor has no such attribute.
+    fn attribute(&self, actor: &PolarValue, name: &str) -> crate::Result<Option<PolarValue>>;
+}
+
+impl<F> ActorAttributeProvider for F
+where
+    F: Fn(&PolarValue, &str) -> crate::Result<Option<PolarValue>> + Send + Sync,
+{
+    fn attribute(&self, actor: &PolarValue, name: &str) -> crate::Result<Option<PolarValue>> {
+        self(actor, name)
+    }
+}
+
+/// The attributes an [`ActorAttributeProvider`] has provided for a session's actor.
+pub(crate) struct ActorAttributes {
+    actor: Term,
+    provider: Box<dyn ActorAttributeProvider>,
+    cache: Mutex<HashMap<String, Option<PolarValue>>>,
+}
+
+impl ActorAttributes {
+    pub fn is_actor(&self, instance: &Term) -> bool {
+        &self.actor == instance
+    }
+
+    /// Return the attribute `name` of the actor, asking the provider the first time.
+    pub fn get(&self, name: &str, host: &Host) -> crate::Result<Option<PolarValue>> {
+        if let Some(value) = self.cache.lock().unwrap().get(name) {
+            return Ok(value.clone());
+        }
+        let actor = PolarValue::from_term(&self.actor, host)?;
+        let value = self.provider.attribute(&actor, name)?;
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(name.to_owned(), value.clone());
+        Ok(value)
+    }
+}
+
+impl Oso {
+    /// Start a session for making many authorization requests on behalf of `actor`.
+    pub fn for_actor<Actor: ToPolar>(&self, actor: Actor) -> ActorSession {
+        let mut host = self.host.clone();
+        let actor = actor.to_polar().to_term(&mut host);
+        ActorSession {
+            oso: self.clone(),
+            host,
+            actor,
+            precomputed: None,
+            attributes: None,
+        }
+    }
+}
+
+impl ActorSession {
+    /// Look up the attributes that the actor's class doesn't have with `provider`. Only
+    /// actors that are application instances have attributes to look up.
+    pub fn with_attribute_provider<P>(mut self, provider: P) -> Self
+    where
+        P: ActorAttributeProvider + 'static,
+    {
+        self.attributes = Some(Arc::new(ActorAttributes {
+            actor: self.actor.clone(),
+            provider: Box::new(provider),
+            cache: Mutex::new(HashMap::new()),
+        }));
+        self
+    }
+
+    /// Like [`Oso::is_allowed`], with the session's actor. A matching `deny` rule denies the
+    /// request even if an `allow` rule matches, and the policy's default decision applies if
+    /// no `allow` rule does.
+    pub fn is_allowed<Action, Resource>(
+        &self,
+        action: Action,
+        resource: Resource,
+    ) -> crate::Result<bool>
+    where
+        Action: ToPolar,
+        Resource: ToPolar,
+    {
+        let (action, resource) = (action.to_polar(), resource.to_polar());
+        let matches = |name| {
+            let mut query = self.query_rule_with(name, vec![action.clone(), resource.clone()]);
+            query.next().transpose().map(|result| result.is_some())
+        };
+        if self.oso.has_deny_rules() && matches("deny")? {
+            return Ok(false);
+        }
+        let kb = &self.oso.inner.kb;
+        if should_query_allow(kb, None) && matches("allow")? {
+            return Ok(true);
+        }
+        let actor = PolarValue::from_term(&self.actor, &self.host)?;
+        self.oso
+            .default_decision(kb, None, &(actor, action, resource))
+            .map(Decision::is_allowed)
+    }
+
+    /// Query the rule `name` with the session's actor as the first argument, followed by
+    /// `args`.
+    #[must_use = "Query that is not consumed does nothing."]
+    pub fn query_rule(&self, name: &str, args: impl ToPolarList) -> Query {
+        self.query_rule_with(name, args.to_polar_list())
+    }
+
+    fn query_rule_with(&self, name: &str, args: Vec<PolarValue>) -> Query {
+        let mut host = self.host.clone();
+        let mut call_args = vec![self.actor.clone()];
+        call_args.extend(args.iter().map(|value| value.to_term(&mut host)));
+        let term = Term::new_from_ffi(Value::Call(Call {
+            name: Symbol::new(name),
+            args: call_args,
+            kwargs: None,
+        }));
+        let mut query = self.oso.inner.new_query_from_term(term, false);
+        query.set_precomputed(self.precomputed.clone());
+        check_messages!(self.oso.inner);
+        let mut query = Query::new(query, host);
+        query.actor_attributes = self.attributes.clone();
+        query
+    }
+
+    /// Evaluate the rule `name`, which takes the actor followed by `arity - 1` other
+    /// arguments, for the session's actor up front, e.g., to look up the actor's roles or
+    /// groups once rather than in every query. Returns the number of results.
+    ///
+    /// Later calls of the rule in the session's queries with the session's actor as the first
+    /// argument are answered from the results instead of evaluating the rule. Every result
+    /// must be fully bound. The results aren't recomputed if the policy changes.
+    pub fn precompute(&mut self, name: &str, arity: usize) -> crate::Result<usize> {
+        let variables = (1..arity).map(|i| format!("arg{}", i)).collect::<Vec<_>>();
+        let args = variables
+            .iter()
+            .cloned()
+            .map(PolarValue::Variable)
+            .collect();
+        let mut facts = vec![];
+        for result in self.query_rule_with(name, args) {
+            let result = result?;
+            let mut fact = vec![self.actor.clone()];
+            for (i, variable) in variables.iter().enumerate() {
+                let value = match result.get(variable) {
+                    Some(PolarValue::Variable(_)) | None => {
+                        return Err(crate::OsoError::Custom {
+                            message: format!(
+                                "cannot precompute `{}`: argument {} of a result is unbound",
+                                name,
+                                i + 2
+                            ),
+                        })
+                    }
+                    Some(value) => value,
+                };
+                fact.push(value.to_term(&mut self.host));
+            }
+            facts.push(fact);
+        }
+        let count = facts.len();
+
+        let precomputed = Arc::make_mut(self.precomputed.get_or_insert_with(Default::default));
+        precomputed.insert(Symbol::new(name), arity, self.actor.clone(), facts)?;
+        Ok(count)
+    }
+}
@@ -0,0 +1,289 @@
+//! Simulating a policy over a fixture dataset: the decisions of every check in a matrix of
+//! actors, actions, and resources, as a matrix that can be diffed across versions of the
+//! policy, e.g., to validate a refactoring of its roles.
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde_json::{Map, Value};
+
+use crate::audit::{decision_from_name, decision_name};
+use crate::{Decision, Oso, VirtualHost};
+
+fn invalid(message: String) -> crate::OsoError {
+    crate::OsoError::InvalidFixture { message }
+}
+
+/// A fixture dataset of actors, resources, and their relations, and the checks to run against
+/// it, read from a JSON object like:
+///
+/// ```json
+/// {
+///   "classes": {"User": {}, "Org": {}},
+///   "instances": {
+///     "alice": {"class": "User", "fields": {"orgs": [{"$ref": "acme"}]}},
+///     "bob": {"class": "User", "fields": {"orgs": []}},
+///     "acme": {"class": "Org"}
+///   },
+///   "actors": ["alice", "bob"],
+///   "actions": ["read", "delete"],
+///   "resources": ["acme"]
+/// }
+/// ```
+///
+/// `classes` and `instances` define a [`VirtualHost`], whose fields and method calls relate
+/// instances to each other. `actors` and `resources` name instances, and `actions` are
+/// strings. Every combination of an actor, an action, and a resource is checked.
+///
+/// # Examples
+///
+/// ```
+/// use oso::{Decision, Fixture};
+/// use serde_json::json;
+///
+/// let fixture = Fixture::from_json(&json!({
+///     "classes": {"User": {}, "Org": {}},
+///     "instances": {
+///         "alice": {"class": "User", "fields": {"orgs": [{"$ref": "acme"}]}},
+///         "bob": {"class": "User", "fields": {"orgs": []}},
+///         "acme": {"class": "Org"}
+///     },
+///     "actors": ["alice", "bob"],
+///     "actions": ["read", "delete"],
+///     "resources": ["acme"]
+/// }))
+/// .unwrap();
+///
+/// let mut before = fixture.oso().unwrap();
+/// before.load_str(r#"allow(user: User, _action, org: Org) if org in user.orgs;"#)
+///     .unwrap();
+/// let mut after = fixture.oso().unwrap();
+/// after.load_str(r#"allow(user: User, "read", org: Org) if org in user.orgs;"#)
+///     .unwrap();
+///
+/// let before = fixture.simulate(&before).unwrap();
+/// let after = fixture.simulate(&after).unwrap();
+/// assert_eq!(after.get("alice", "read", "acme"), Some(Decision::Allow));
+/// let changes = before.diff(&after);
+/// assert_eq!(changes.len(), 1);
+/// assert_eq!(changes[0].to_string(), "alice delete acme: allow -> not_applicable");
+/// ```
+#[derive(Clone, Debug)]
+pub struct Fixture {
+    host: VirtualHost,
+    actors: Vec<String>,
+    actions: Vec<String>,
+    resources: Vec<String>,
+}
+
+/// The names listed under `key` of a fixture.
+fn names(json: &Value, key: &str) -> crate::Result<Vec<String>> {
+    json.get(key)
+        .and_then(Value::as_array)
+        .and_then(|names| {
+            names
+                .iter()
+                .map(|name| name.as_str().map(str::to_owned))
+                .collect()
+        })
+        .ok_or_else(|| invalid(format!("`{}` must be a list of strings", key)))
+}
+
+impl Fixture {
+    /// Read a fixture from JSON, as described [above](Fixture).
+    pub fn from_json(json: &Value) -> crate::Result<Self> {
+        let host = VirtualHost::from_json(json)?;
+        let fixture = Self {
+            host,
+            actors: names(json, "actors")?,
+            actions: names(json, "actions")?,
+            resources: names(json, "resources")?,
+        };
+        for name in fixture.actors.iter().chain(&fixture.resources) {
+            if fixture.host.instance(name).is_none() {
+                return Err(invalid(format!("undefined instance `{}`", name)));
+            }
+        }
+        Ok(fixture)
+    }
+
+    /// The dataset of the fixture.
+    pub fn host(&self) -> &VirtualHost {
+        &self.host
+    }
+
+    /// A new `Oso` with the dataset of the fixture registered, to load a version of a policy
+    /// into.
+    pub fn oso(&self) -> crate::Result<Oso> {
+        let mut oso = Oso::new();
+        oso.register_virtual_host(&self.host)?;
+        Ok(oso)
+    }
+
+    /// Decide every check of the fixture with [`Oso::decide`]. `oso` must have the dataset of
+    /// the fixture registered, as by [`Fixture::oso`].
+    pub fn simulate(&self, oso: &Oso) -> crate::Result<DecisionMatrix> {
+        let mut decisions = BTreeMap::new();
+        for actor in &self.actors {
+            for action in &self.actions {
+                for resource in &self.resources {
+                    let decision = oso.decide(
+                        self.host.instance(actor).unwrap(),
+                        action.clone(),
+                        self.host.instance(resource).unwrap(),
+                    )?;
+                    decisions.insert((actor.clone(), action.clone(), resource.clone()), decision);
+                }
+            }
+        }
+        Ok(DecisionMatrix { decisions })
+    }
+}
+
+/// The decisions of the checks of a [`Fixture`], by actor, action, and resource.
+///
+/// Its [`Display`](fmt::Display) form has a line for each check, in order, e.g.,
+/// `alice read acme: allow`, and its JSON form nests decisions by actor, action, and
+/// resource, so that both diff well as text, e.g., to check a matrix into version control.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DecisionMatrix {
+    decisions: BTreeMap<(String, String, String), Decision>,
+}
+
+/// A check that two [`DecisionMatrix`]es decide differently. A check missing from a matrix
+/// has no decision in it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecisionChange {
+    pub actor: String,
+    pub action: String,
+    pub resource: String,
+    pub before: Option<Decision>,
+    pub after: Option<Decision>,
+}
+
+impl DecisionMatrix {
+    pub fn get(&self, actor: &str, action: &str, resource: &str) -> Option<Decision> {
+        self.decisions
+            .get(&(actor.to_owned(), action.to_owned(), resource.to_owned()))
+            .copied()
+    }
+
+    /// The checks and their decisions, in order of actor, action, and resource.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str, &str, Decision)> {
+        self.decisions
+            .iter()
+            .map(|((actor, action, resource), decision)| {
+                (
+                    actor.as_str(),
+                    action.as_str(),
+                    resource.as_str(),
+                    *decision,
+                )
+            })
+    }
+
+    /// The checks that `after` decides differently, in order.
+    pub fn diff(&self, after: &DecisionMatrix) -> Vec<DecisionChange> {
+        let mut checks = self.decisions.keys().collect::<Vec<_>>();
+        checks.extend(after.decisions.keys());
+        checks.sort();
+        checks.dedup();
+        checks
+            .into_iter()
+            .filter_map(|check| {
+                let (before, after) = (self.decisions.get(check), after.decisions.get(check));
+                (before != after).then(|| {
+                    let (actor, action, resource) = check.clone();
+                    DecisionChange {
+                        actor,
+                        action,
+                        resource,
+                        before: before.copied(),
+                        after: after.copied(),
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// The matrix as a JSON object, e.g.,
+    /// `{"alice": {"read": {"acme": "allow"}, "delete": {"acme": "not_applicable"}}}`.
+    pub fn to_json(&self) -> Value {
+        let mut json = Map::new();
+        for (actor, action, resource, decision) in self.iter() {
+            let actions = json
+                .entry(actor)
+                .or_insert_with(|| Value::Object(Map::new()));
+            let resources = actions
+                .as_object_mut()
+                .unwrap()
+                .entry(action)
+                .or_insert_with(|| Value::Object(Map::new()));
+            resources
+                .as_object_mut()
+                .unwrap()
+                .insert(resource.to_owned(), decision_name(decision).into());
+        }
+        Value::Object(json)
+    }
+
+    /// Read a matrix written by [`DecisionMatrix::to_json`], e.g., the matrix of an earlier
+    /// version of a policy.
+    pub fn from_json(json: &Value) -> crate::Result<Self> {
+        let object = |value: &Value, what: &str| {
+            value
+                .as_object()
+                .cloned()
+                .ok_or_else(|| invalid(format!("{} must be an object", what)))
+        };
+        let mut decisions = BTreeMap::new();
+        for (actor, actions) in object(json, "a decision matrix")? {
+            for (action, resources) in object(&actions, &format!("the checks of `{}`", actor))? {
+                let what = format!("the checks of `{}` for `{}`", actor, action);
+                for (resource, decision) in object(&resources, &what)? {
+                    let decision = decision
+                        .as_str()
+                        .and_then(decision_from_name)
+                        .ok_or_else(|| {
+                            invalid(format!(
+                                "the decision for `{} {} {}` must be allow, deny, or not_applicable",
+                                actor, action, resource
+                            ))
+                        })?;
+                    decisions.insert((actor.clone(), action.clone(), resource), decision);
+                }
+            }
+        }
+        Ok(Self { decisions })
+    }
+}
+
+impl fmt::Display for DecisionMatrix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (actor, action, resource, decision) in self.iter() {
+            writeln!(
+                f,
+                "{} {} {}: {}",
+                actor,
+                action,
+                resource,
+                decision_name(decision)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DecisionChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = |decision: Option<Decision>| decision.map_or("none", decision_name);
+        write!(
+            f,
+            "{} {} {}: {} -> {}",
+            self.actor,
+            self.action,
+            self.resource,
+            name(self.before),
+            name(self.after)
+        )
+    }
+}
@@ -0,0 +1,21 @@
+//! The standard library of Polar helpers loaded with policies after
+//! [`Oso::load_stdlib`](crate::Oso::load_stdlib).
+
+use polar_core::sandbox::Sandbox;
+use polar_core::sources::Source;
+
+/// The standard library shipped with this version of the crate.
+const STDLIB: &str = include_str!("stdlib.polar");
+
+/// The standard library as a source to load along with a policy. It gets the policy's
+/// sandbox, if any, so that sandboxed rules can't use it to make calls their sandbox forbids.
+pub(crate) fn source(sandbox: Option<Sandbox>) -> Source {
+    let source = Source::new_with_name(
+        concat!("oso-stdlib-", env!("CARGO_PKG_VERSION"), ".polar"),
+        STDLIB,
+    );
+    match sandbox {
+        Some(sandbox) => source.with_sandbox(sandbox),
+        None => source,
+    }
+}
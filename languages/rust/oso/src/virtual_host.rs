@@ -0,0 +1,300 @@
+//! Classes and instances defined as data, for evaluating policies without application code,
+//! e.g., in a policy playground or in CI.
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use serde_json::{Map, Value};
+
+use crate::{DynClassSpec, DynObject, Oso, PolarValue, ToPolar};
+
+/// The key of a reference to an instance, e.g., `{"$ref": "alice"}`.
+const REF: &str = "$ref";
+
+#[derive(Clone, Debug, Default)]
+struct VirtualClass {
+    attributes: BTreeSet<String>,
+    /// The default result of each method, if it has one.
+    methods: BTreeMap<String, Option<Value>>,
+}
+
+#[derive(Clone, Debug)]
+struct VirtualInstance {
+    class: String,
+    fields: Map<String, Value>,
+    /// The arguments and result of each call of a method.
+    calls: BTreeMap<String, Vec<(Vec<Value>, Value)>>,
+}
+
+type Instances = Arc<BTreeMap<String, VirtualInstance>>;
+
+/// Classes and instances defined as data, registered with [`Oso::register_virtual_host`].
+///
+/// A virtual host is read from a JSON object like:
+///
+/// ```json
+/// {
+///   "classes": {
+///     "User": {"attributes": ["email"], "methods": {"has_role": {"default": false}}},
+///     "Org": {}
+///   },
+///   "instances": {
+///     "alice": {
+///       "class": "User",
+///       "fields": {"name": "alice", "org": {"$ref": "acme"}},
+///       "calls": {"has_role": [{"args": ["admin", {"$ref": "acme"}], "result": true}]}
+///     },
+///     "acme": {"class": "Org", "fields": {"name": "Acme"}}
+///   }
+/// }
+/// ```
+///
+/// `{"$ref": name}` refers to the instance `name` wherever a value may appear. The attributes
+/// of a class are the ones it lists and the fields of its instances; an instance without one of
+/// them has `nil` for it. A method returns the result of the instance's first call with equal
+/// arguments, or the method's default, and is an error if it has neither.
+///
+/// # Examples
+///
+/// ```
+/// use oso::{Oso, VirtualHost};
+/// use serde_json::json;
+///
+/// let host = VirtualHost::from_json(&json!({
+///     "classes": {"User": {}, "Repo": {}},
+///     "instances": {
+///         "alice": {"class": "User", "fields": {"name": "alice"}},
+///         "oso": {"class": "Repo", "fields": {"owner": {"$ref": "alice"}}}
+///     }
+/// }))
+/// .unwrap();
+///
+/// let mut oso = Oso::new();
+/// oso.register_virtual_host(&host).unwrap();
+/// oso.load_str(r#"allow(user: User, "read", repo: Repo) if repo.owner = user;"#)
+///     .unwrap();
+///
+/// assert!(oso.query(r#"allow(alice, "read", oso)"#).unwrap().next().is_some());
+/// let alice = host.instance("alice").unwrap();
+/// let repo = host.instance("oso").unwrap();
+/// assert!(oso.is_allowed(alice, "read", repo).unwrap());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct VirtualHost {
+    classes: BTreeMap<String, VirtualClass>,
+    instances: Instances,
+}
+
+fn invalid(message: String) -> crate::OsoError {
+    crate::OsoError::InvalidVirtualHost { message }
+}
+
+/// The fields of an optional JSON object, e.g., `fields` of an instance.
+fn object(value: Option<&Value>, what: &str) -> crate::Result<Map<String, Value>> {
+    match value {
+        None => Ok(Map::new()),
+        Some(Value::Object(fields)) => Ok(fields.clone()),
+        Some(_) => Err(invalid(format!("{} must be an object", what))),
+    }
+}
+
+/// The name of the instance `value` refers to, if it's a reference.
+fn ref_name(value: &Value) -> Option<&str> {
+    value
+        .as_object()
+        .filter(|fields| fields.len() == 1)
+        .and_then(|fields| fields.get(REF))
+        .and_then(Value::as_str)
+}
+
+fn check_refs(value: &Value, instances: &BTreeMap<String, VirtualInstance>) -> crate::Result<()> {
+    match (ref_name(value), value) {
+        (Some(name), _) if !instances.contains_key(name) => {
+            Err(invalid(format!("undefined instance `{}`", name)))
+        }
+        (Some(_), _) => Ok(()),
+        (None, Value::Array(values)) => values.iter().try_for_each(|v| check_refs(v, instances)),
+        (None, Value::Object(fields)) => fields.values().try_for_each(|v| check_refs(v, instances)),
+        _ => Ok(()),
+    }
+}
+
+/// Convert `value` to Polar, with references as instances.
+fn resolve(value: &Value, instances: &Instances) -> PolarValue {
+    match (ref_name(value), value) {
+        (Some(name), _) => DynObject::new(instances[name].class.clone(), value.clone()).to_polar(),
+        (None, Value::Array(values)) => {
+            PolarValue::List(values.iter().map(|v| resolve(v, instances)).collect())
+        }
+        (None, Value::Object(fields)) => PolarValue::Map(
+            fields
+                .iter()
+                .map(|(k, v)| (k.clone(), resolve(v, instances)))
+                .collect(),
+        ),
+        (None, value) => value.clone().to_polar(),
+    }
+}
+
+impl VirtualHost {
+    /// Read a virtual host from JSON, as described [above](VirtualHost).
+    pub fn from_json(json: &Value) -> crate::Result<Self> {
+        let mut classes = BTreeMap::new();
+        for (name, spec) in object(json.get("classes"), "`classes`")? {
+            let what = format!("class `{}`", name);
+            let attributes = match spec.get("attributes") {
+                None => Some(BTreeSet::new()),
+                Some(Value::Array(names)) => names
+                    .iter()
+                    .map(|name| name.as_str().map(str::to_owned))
+                    .collect(),
+                Some(_) => None,
+            }
+            .ok_or_else(|| invalid(format!("the attributes of {} must be strings", what)))?;
+            let methods = object(spec.get("methods"), &format!("the methods of {}", what))?
+                .into_iter()
+                .map(|(name, method)| (name, method.get("default").cloned()))
+                .collect();
+            classes.insert(
+                name,
+                VirtualClass {
+                    attributes,
+                    methods,
+                },
+            );
+        }
+
+        let mut instances = BTreeMap::new();
+        for (name, spec) in object(json.get("instances"), "`instances`")? {
+            let what = format!("instance `{}`", name);
+            let class_name = spec
+                .get("class")
+                .and_then(Value::as_str)
+                .ok_or_else(|| invalid(format!("{} must have a class", what)))?;
+            let class = classes
+                .get_mut(class_name)
+                .ok_or_else(|| invalid(format!("{} has undefined class `{}`", what, class_name)))?;
+            let fields = object(spec.get("fields"), &format!("the fields of {}", what))?;
+            class.attributes.extend(fields.keys().cloned());
+            let mut calls = BTreeMap::new();
+            for (method, cases) in object(spec.get("calls"), &format!("the calls of {}", what))? {
+                if !class.methods.contains_key(&method) {
+                    return Err(invalid(format!(
+                        "{} calls undefined method `{}`",
+                        what, method
+                    )));
+                }
+                let cases = cases
+                    .as_array()
+                    .and_then(|cases| {
+                        cases
+                            .iter()
+                            .map(|case| {
+                                let args = case.get("args")?.as_array()?.clone();
+                                Some((args, case.get("result")?.clone()))
+                            })
+                            .collect::<Option<Vec<_>>>()
+                    })
+                    .ok_or_else(|| {
+                        invalid(format!(
+                            "the calls of `{}` by {} must be a list of args and results",
+                            method, what
+                        ))
+                    })?;
+                calls.insert(method, cases);
+            }
+            instances.insert(
+                name,
+                VirtualInstance {
+                    class: class_name.to_owned(),
+                    fields,
+                    calls,
+                },
+            );
+        }
+
+        let values = classes
+            .values()
+            .flat_map(|class| class.methods.values().flatten())
+            .chain(instances.values().flat_map(|instance| {
+                instance.fields.values().chain(
+                    instance
+                        .calls
+                        .values()
+                        .flatten()
+                        .flat_map(|(args, result)| args.iter().chain(Some(result))),
+                )
+            }));
+        for value in values {
+            check_refs(value, &instances)?;
+        }
+        Ok(Self {
+            classes,
+            instances: Arc::new(instances),
+        })
+    }
+
+    /// The instance `name`, e.g., to pass to [`Oso::is_allowed`].
+    pub fn instance(&self, name: &str) -> Option<DynObject> {
+        let instance = self.instances.get(name)?;
+        Some(DynObject::new(
+            instance.class.clone(),
+            serde_json::json!({ REF: name }),
+        ))
+    }
+
+    fn class_spec(&self, name: &str, class: &VirtualClass) -> DynClassSpec {
+        let mut spec = DynClassSpec::new(name);
+        for attribute in &class.attributes {
+            let (instances, attribute_name) = (self.instances.clone(), attribute.clone());
+            spec = spec.add_attribute_getter(attribute.clone(), move |value| {
+                // Objects made by the application, rather than referring to an instance, are
+                // their own fields.
+                let fields = match ref_name(value) {
+                    Some(name) => Some(&instances[name].fields),
+                    None => value.as_object(),
+                };
+                fields
+                    .and_then(|fields| fields.get(&attribute_name))
+                    .map_or_else(|| Value::Null.to_polar(), |v| resolve(v, &instances))
+            });
+        }
+        for (method, default) in &class.methods {
+            let (instances, method_name) = (self.instances.clone(), method.clone());
+            let default = default.clone();
+            spec = spec.add_method(method.clone(), move |value, args| {
+                let instance = ref_name(value).map(|name| (name, &instances[name]));
+                let result = instance
+                    .and_then(|(_, instance)| instance.calls.get(&method_name))
+                    .and_then(|cases| cases.iter().find(|(case_args, _)| *case_args == args))
+                    .map(|(_, result)| result)
+                    .or(default.as_ref())
+                    .ok_or_else(|| {
+                        invalid(format!(
+                            "instance `{}` has no result for `{}` with arguments {}",
+                            instance.map_or("<unnamed>", |(name, _)| name),
+                            method_name,
+                            Value::Array(args.clone())
+                        ))
+                    })?;
+                Ok(resolve(result, &instances))
+            });
+        }
+        spec
+    }
+}
+
+impl Oso {
+    /// Register the classes of `host` as dynamic classes, and its instances as constants named
+    /// after them, so that queries can refer to them, e.g., `allow(alice, "read", repo)`.
+    ///
+    /// Like classes, virtual hosts must be registered before loading policies that use them.
+    pub fn register_virtual_host(&mut self, host: &VirtualHost) -> crate::Result<()> {
+        for (name, class) in &host.classes {
+            self.register_dynamic_class(host.class_spec(name, class))?;
+        }
+        for name in host.instances.keys() {
+            self.register_constant(host.instance(name).unwrap(), name)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+#![cfg(all(feature = "client", feature = "server"))]
+mod common;
+
+use std::net::{SocketAddr, TcpListener};
+use std::time::{Duration, Instant};
+
+use maplit::hashmap;
+use polar_core::data_filtering::Type;
+
+use oso::client::OsoClient;
+use oso::server::Server;
+use oso::{Enforcer, Oso, OsoError, PolarValue, ToPolar};
+
+const POLICY: &str = r#"
+    allow(actor, "read", resource) if actor = resource.owner;
+    allow("admin", _action, _resource);
+"#;
+
+fn oso() -> Oso {
+    let mut oso = Oso::new();
+    oso.load_str(POLICY).unwrap();
+    oso
+}
+
+fn start(server: Server) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || server.serve(listener));
+    addr
+}
+
+/// An address that refuses connections.
+fn unreachable() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
+
+fn resource(owner: &str) -> PolarValue {
+    hashmap! { "owner".to_owned() => owner.to_owned() }.to_polar()
+}
+
+#[test]
+fn test_client() -> oso::Result<()> {
+    common::setup();
+
+    let client = OsoClient::new(start(Server::new(oso())))?;
+    for _ in 0..3 {
+        assert!(client.authorize("alice".to_polar(), "read".to_polar(), resource("alice"))?);
+        assert!(!client.authorize("bob".to_polar(), "read".to_polar(), resource("alice"))?);
+    }
+    assert_eq!(
+        client.authorized_actions("alice".to_polar(), resource("alice"))?,
+        vec!["read".to_polar()]
+    );
+    assert!(matches!(
+        client.authorized_actions("admin".to_polar(), resource("alice"))?[..],
+        [PolarValue::Variable(_)]
+    ));
+
+    // Errors from the server are not answered by a fallback.
+    let client = OsoClient::new(start(Server::new(Oso::new())))?.with_fallback(oso());
+    let err = client
+        .authorize("admin".to_polar(), "read".to_polar(), resource("alice"))
+        .unwrap_err();
+    assert!(matches!(err, OsoError::ServerError { status: 500, .. }));
+    Ok(())
+}
+
+#[test]
+fn test_client_authorized_query() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = Oso::new();
+    oso.register_filter_types(hashmap! {
+        "Dictionary".to_owned() => hashmap! {
+            "owner".to_owned() => Type::Base { class_tag: "String".to_owned() },
+        },
+    });
+    oso.load_str(r#"allow(actor, "read", resource: Dictionary) if resource.owner = actor;"#)?;
+    let local = oso.authorized_query("alice", "read", "Dictionary")?;
+
+    let client = OsoClient::new(start(Server::new(oso.clone())))?;
+    let remote = client.authorized_query("alice".to_polar(), "read".to_polar(), "Dictionary")?;
+    assert_eq!(remote, local);
+
+    let client = OsoClient::new(unreachable())?.with_fallback(oso);
+    let fallback = client.authorized_query("alice".to_polar(), "read".to_polar(), "Dictionary")?;
+    assert_eq!(fallback, local);
+    Ok(())
+}
+
+#[test]
+fn test_client_fallback() -> oso::Result<()> {
+    common::setup();
+
+    let client = OsoClient::new(unreachable())?;
+    let err = client
+        .authorize("alice".to_polar(), "read".to_polar(), resource("alice"))
+        .unwrap_err();
+    assert!(matches!(err, OsoError::Io(_)));
+
+    let client = client.with_fallback(oso());
+    assert!(client.authorize("alice".to_polar(), "read".to_polar(), resource("alice"))?);
+    assert_eq!(
+        client.authorized_actions("alice".to_polar(), resource("alice"))?,
+        vec!["read".to_polar()]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_client_timeout() -> oso::Result<()> {
+    common::setup();
+
+    // Accepts connections but never responds.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client =
+        OsoClient::new(listener.local_addr().unwrap())?.with_timeout(Duration::from_millis(100));
+
+    let start = Instant::now();
+    let err = client
+        .authorize("alice".to_polar(), "read".to_polar(), resource("alice"))
+        .unwrap_err();
+    assert!(matches!(err, OsoError::Io(_)));
+    assert!(start.elapsed() < Duration::from_secs(2));
+
+    let client = client.with_fallback(oso());
+    assert!(client.authorize("alice".to_polar(), "read".to_polar(), resource("alice"))?);
+    drop(listener);
+    Ok(())
+}
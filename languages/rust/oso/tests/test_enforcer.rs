@@ -0,0 +1,97 @@
+mod common;
+
+use std::time::Duration;
+
+use maplit::hashmap;
+
+use oso::{CachingEnforcer, Enforcer, Oso, PolarClass, PolarValue, ToPolar};
+
+fn can_read(enforcer: &dyn Enforcer, actor: &str) -> bool {
+    enforcer
+        .authorize(actor.to_polar(), "read".to_polar(), "repo".to_polar())
+        .unwrap()
+}
+
+#[test]
+fn test_oso_enforcer() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = Oso::new();
+    oso.load_str(
+        r#"allow("alice", action, "repo") if action in ["read", "write", "read"];
+           allow("admin", _action, "repo");"#,
+    )?;
+    assert!(can_read(&oso, "alice"));
+    assert!(!can_read(&oso, "bob"));
+    assert_eq!(
+        oso.authorized_actions("alice".to_polar(), "repo".to_polar())?,
+        vec!["read".to_polar(), "write".to_polar()]
+    );
+    assert!(matches!(
+        oso.authorized_actions("admin".to_polar(), "repo".to_polar())?[..],
+        [PolarValue::Variable(_)]
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_caching_enforcer() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = Oso::new();
+    oso.load_str(r#"allow("alice", "read", "repo");"#)?;
+    let cached = CachingEnforcer::new(oso.clone(), Duration::from_secs(60));
+    assert!(can_read(&cached, "alice"));
+    assert!(!can_read(&cached, "bob"));
+
+    // Decisions are cached by policy fingerprint, so policy changes take effect at once.
+    oso.clear_rules()?;
+    oso.load_str(r#"allow("bob", "read", "repo");"#)?;
+    assert!(!can_read(&cached, "alice"));
+    assert!(can_read(&cached, "bob"));
+
+    // Other changes, e.g., to facts, don't change the fingerprint, so they need the cache
+    // cleared.
+    oso.insert_fact("blocked", ("bob",))?;
+    oso.clear_rules()?;
+    oso.load_str(r#"allow(actor, "read", "repo") if actor = "bob" and not blocked(actor);"#)?;
+    assert!(!can_read(&cached, "bob"));
+    oso.delete_fact("blocked", ("bob",))?;
+    assert!(!can_read(&cached, "bob"));
+    cached.clear();
+    assert!(can_read(&cached, "bob"));
+
+    // Expired decisions are re-evaluated.
+    let cached = CachingEnforcer::new(oso.clone(), Duration::from_millis(0));
+    assert!(can_read(&cached, "bob"));
+    oso.clear_rules()?;
+    oso.load_str(r#"allow("alice", "read", "repo");"#)?;
+    assert!(!can_read(&cached, "bob"));
+    Ok(())
+}
+
+#[test]
+fn test_caching_enforcer_skips_instances() -> oso::Result<()> {
+    common::setup();
+
+    #[derive(Clone, PolarClass)]
+    struct User {
+        #[polar(attribute)]
+        name: String,
+    }
+
+    let mut oso = Oso::new();
+    oso.register_class(User::get_polar_class())?;
+    oso.load_str(r#"allow(user: User, "read", repo: Dictionary) if user.name = repo.owner;"#)?;
+    let cached = CachingEnforcer::new(oso.clone(), Duration::from_secs(60));
+    let repo = hashmap! { "owner".to_owned() => "alice".to_owned() }.to_polar();
+    let user = |name: &str| {
+        User {
+            name: name.to_owned(),
+        }
+        .to_polar()
+    };
+    assert!(cached.authorize(user("alice"), "read".to_polar(), repo.clone())?);
+    assert!(!cached.authorize(user("bob"), "read".to_polar(), repo)?);
+    Ok(())
+}
@@ -638,3 +638,99 @@ fn test_operator_unimplemented() -> oso::Result<()> {
 // This would raise a type error (if we did one-sided external unification,
 // but we want the matches to just fail.  This wouldn't be caught by the
 // current application error implementation.
+
+/// Test that `try` catches errors from application methods and lookups, but not errors from
+/// the policy itself.
+#[test]
+fn test_try_catches_application_errors() -> oso::Result<()> {
+    common::setup();
+
+    #[derive(Clone, PolarClass)]
+    struct Profile {
+        name: Option<String>,
+    }
+
+    impl Profile {
+        fn name(&self) -> Result<String, std::io::Error> {
+            self.name
+                .clone()
+                .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no name"))
+        }
+    }
+
+    let mut oso = OsoTest::new();
+    oso.oso.register_class(
+        Profile::get_polar_class_builder()
+            .add_method("name", Profile::name)
+            .build(),
+    )?;
+    oso.load_str(
+        r#"display_name(profile: Profile, name) if
+             try name = profile.name() else name = "anonymous";"#,
+    );
+
+    oso.oso.register_constant(
+        Profile {
+            name: Some("alice".to_owned()),
+        },
+        "alice",
+    )?;
+    oso.oso
+        .register_constant(Profile { name: None }, "nobody")?;
+    oso.qvar_one("display_name(alice, name)", "name", "alice".to_owned());
+    oso.qvar_one("display_name(nobody, name)", "name", "anonymous".to_owned());
+    oso.qvar_one("try x = alice.missing else x = 0", "x", 0);
+
+    oso.query_err("x = nobody.name()");
+    oso.query_err("try keys(alice, _x) else true");
+    Ok(())
+}
+
+#[test]
+fn test_panic_in_application_call() -> oso::Result<()> {
+    common::setup();
+
+    #[derive(Clone, PolarClass)]
+    struct Account;
+
+    impl Account {
+        fn balance(&self) -> i64 {
+            panic!("balance unavailable")
+        }
+    }
+
+    let mut oso = Oso::new();
+    oso.register_class(
+        Account::get_polar_class_builder()
+            .add_method("balance", Account::balance)
+            .build(),
+    )?;
+    oso.load_str(
+        r#"allow(_actor, "withdraw", account: Account) if account.balance() > 0;
+           allow(_actor, "view", _account: Account);"#,
+    )?;
+
+    let error = oso.is_allowed("alice", "withdraw", Account).unwrap_err();
+    assert!(
+        matches!(
+            &error,
+            OsoError::Polar(PolarError(ErrorKind::Runtime(RuntimeError::EnginePanic { msg })))
+                if msg == "balance unavailable"
+        ),
+        "{} doesn't match expected error",
+        error
+    );
+    assert!(oso.is_allowed("alice", "view", Account)?);
+
+    // The query is done after a panic, rather than resumed from an inconsistent state.
+    let action = oso::PolarValue::Variable("action".to_owned());
+    let mut query = oso.query_rule("allow", ("alice", action, Account))?;
+    assert!(matches!(
+        query.next(),
+        Some(Err(OsoError::Polar(PolarError(ErrorKind::Runtime(
+            RuntimeError::EnginePanic { .. }
+        )))))
+    ));
+    assert!(query.next().is_none());
+    Ok(())
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use polar_core::filter::Filter;
+
+use oso::mock::{Call, Matcher, MockOso};
+use oso::{Enforcer, PolarValue, ToPolar};
+
+#[derive(Clone)]
+struct User {
+    name: String,
+}
+
+fn user(name: &str) -> PolarValue {
+    PolarValue::new_from_instance(User {
+        name: name.to_owned(),
+    })
+}
+
+#[test]
+fn test_mock_decisions() -> oso::Result<()> {
+    common::setup();
+
+    let alice = Matcher::instance(|user: &User| user.name == "alice");
+    let mock = MockOso::new().allow(alice, Matcher::any(), "repo").deny(
+        Matcher::any(),
+        "delete",
+        Matcher::any(),
+    );
+
+    assert!(mock.authorize(user("alice"), "read".to_polar(), "repo".to_polar())?);
+    assert!(!mock.authorize(user("alice"), "delete".to_polar(), "repo".to_polar())?);
+    assert!(!mock.authorize(user("alice"), "read".to_polar(), "other".to_polar())?);
+    assert!(!mock.authorize(user("bob"), "read".to_polar(), "repo".to_polar())?);
+    assert!(!mock.authorize("alice".to_polar(), "read".to_polar(), "repo".to_polar())?);
+
+    let mock = MockOso::allow_by_default().deny("bob", Matcher::any(), Matcher::any());
+    assert!(mock.authorize("alice".to_polar(), "read".to_polar(), 1.to_polar())?);
+    assert!(!mock.authorize("bob".to_polar(), "read".to_polar(), 1.to_polar())?);
+    Ok(())
+}
+
+#[test]
+fn test_mock_authorized_actions() -> oso::Result<()> {
+    common::setup();
+
+    let mock = MockOso::new()
+        .allow("alice", "read", "repo")
+        .allow("alice", "write", "repo")
+        .allow("alice", "read", Matcher::any())
+        .deny("alice", "write", "repo")
+        .allow("admin", Matcher::any(), "repo");
+
+    assert_eq!(
+        mock.authorized_actions("alice".to_polar(), "repo".to_polar())?,
+        vec!["read".to_polar()]
+    );
+    assert!(mock
+        .authorized_actions("bob".to_polar(), "repo".to_polar())?
+        .is_empty());
+    assert!(matches!(
+        mock.authorized_actions("admin".to_polar(), "repo".to_polar())?[..],
+        [PolarValue::Variable(_)]
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_mock_authorized_query() -> oso::Result<()> {
+    common::setup();
+
+    let filter: Filter = serde_json::from_value(serde_json::json!({
+        "root": "Repo",
+        "relations": [],
+        "conditions": [],
+    }))
+    .unwrap();
+    let mock = MockOso::new().with_filter("Repo", filter.clone());
+    assert_eq!(
+        mock.authorized_query("alice".to_polar(), "read".to_polar(), "Repo")?,
+        filter
+    );
+    assert!(mock
+        .authorized_query("alice".to_polar(), "read".to_polar(), "Issue")
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_mock_records_calls() -> oso::Result<()> {
+    common::setup();
+
+    let mock = MockOso::new();
+    mock.authorize("alice".to_polar(), "read".to_polar(), "repo".to_polar())?;
+    mock.authorize("alice".to_polar(), "read".to_polar(), "repo".to_polar())?;
+    mock.authorize(user("bob"), "write".to_polar(), "repo".to_polar())?;
+    mock.authorized_actions("alice".to_polar(), "repo".to_polar())?;
+
+    assert_eq!(mock.calls().len(), 4);
+    assert_eq!(
+        mock.calls()[3],
+        Call::AuthorizedActions {
+            actor: "alice".to_polar(),
+            resource: "repo".to_polar(),
+        }
+    );
+    assert_eq!(mock.authorize_call_count("alice", "read", "repo"), 2);
+    assert_eq!(
+        mock.authorize_call_count(Matcher::any(), Matcher::any(), Matcher::any()),
+        3
+    );
+    mock.assert_authorize_called(
+        Matcher::instance(|user: &User| user.name == "bob"),
+        "write",
+        Matcher::any(),
+    );
+
+    mock.clear_calls();
+    assert!(mock.calls().is_empty());
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "expected an authorize call matching")]
+fn test_mock_assert_authorize_called() {
+    let mock = MockOso::new();
+    mock.assert_authorize_called("alice", "read", Matcher::any());
+}
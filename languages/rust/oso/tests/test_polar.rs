@@ -3,7 +3,13 @@ use std::collections::HashMap;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
-use oso::{Class, FromPolar, Oso, OsoError, PolarClass, PolarValue};
+use std::sync::{Arc, Mutex};
+
+use oso::{
+    Class, Decision, Dispatcher, ExternalCall, FromPolar, HookAction, InstanceHandle, Limits, Oso,
+    OsoError, PolarClass, PolarValue, QueryEvent, RuleQuota, Sandbox, ScopeQuota, SortOrder,
+    ToPolar,
+};
 use polar_core::error as polar_error;
 
 use maplit::hashmap;
@@ -287,6 +293,232 @@ fn test_load_multiple_files_same_name_different_path() -> oso::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_load_order_independence() -> oso::Result<()> {
+    common::setup();
+
+    let dir = tempfile::tempdir()?;
+    let a = dir.path().join("a.polar");
+    let b = dir.path().join("b.polar");
+    std::fs::write(&a, "f(1); f(2); g(x) if f(x);")?;
+    std::fs::write(&b, "f(3);")?;
+
+    let mut oso = test_oso();
+    oso.oso.verify_load_order(vec![&b, &a])?;
+    oso.oso.load_files(vec![&b, &a])?;
+    assert_eq!(oso.qvar::<i64>("g(x)", "x"), vec![1, 2, 3]);
+
+    Ok(())
+}
+
+#[test]
+fn test_load_reader() -> oso::Result<()> {
+    common::setup();
+
+    // Large enough to be read in several chunks.
+    let facts: String = (0..20_000).map(|i| format!("f({});\n", i)).collect();
+    let mut oso = test_oso();
+    oso.oso.load_reader(facts.as_bytes())?;
+    assert_eq!(oso.oso.query_rule("f", (0,))?.count(), 1);
+    assert_eq!(oso.oso.query_rule("f", (19_999,))?.count(), 1);
+
+    // Errors report lines of the whole source.
+    let mut oso = test_oso();
+    let err = oso
+        .oso
+        .load_reader(format!("{}f(;\n", facts).as_bytes())
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("at line 20001, column 3"),
+        "Error was {}",
+        err
+    );
+    // Nothing is loaded.
+    assert!(matches!(
+        oso.oso.query_rule("f", (1,))?.next(),
+        Some(Err(OsoError::Polar(polar_error::PolarError(
+            polar_error::ErrorKind::Runtime(
+                polar_error::RuntimeError::QueryForUndefinedRule { .. }
+            )
+        ))))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_pagination() -> oso::Result<()> {
+    common::setup();
+    let mut oso = Oso::new();
+    oso.load_str("f(1); f(2); f(3); f(4); f(5);")?;
+
+    let mut pages = vec![];
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut query = oso.query("f(x)")?;
+        if let Some(cursor) = cursor {
+            query.skip_to(&cursor.parse()?)?;
+        }
+        let page = query.next_page(2)?;
+        pages.push(
+            page.results
+                .iter()
+                .map(|result| result.get_typed("x"))
+                .collect::<oso::Result<Vec<i64>>>()?,
+        );
+        match page.next {
+            Some(next) => cursor = Some(next.to_string()),
+            None => break,
+        }
+    }
+    assert_eq!(pages, vec![vec![1, 2], vec![3, 4], vec![5]]);
+
+    // A full last page has a cursor to an empty page.
+    let mut query = oso.query("f(x)")?;
+    let page = query.next_page(5)?;
+    assert_eq!(page.next.map(|cursor| cursor.offset()), Some(5));
+    assert!(query.next_page(5)?.results.is_empty());
+
+    assert!(matches!(
+        "next".parse::<oso::Cursor>(),
+        Err(OsoError::InvalidCursor { .. })
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_sorted_by() -> oso::Result<()> {
+    common::setup();
+    let mut oso = test_oso();
+    oso.oso.register_constant(Widget::new(1), "widget")?;
+    oso.load_str(
+        r#"f(2, "b"); f(1.5, "c"); f(3, "a"); f(1.5, "d");
+           g(1); g("one");
+           h(widget);"#,
+    );
+
+    let sorted = |query: &str, var: &str, order: SortOrder| -> oso::Result<Vec<String>> {
+        oso.oso
+            .query(query)?
+            .sorted_by(var, order)?
+            .iter()
+            .map(|result| result.get_typed("y"))
+            .collect()
+    };
+    assert_eq!(
+        sorted("f(x, y)", "x", SortOrder::Ascending)?,
+        vec!["c", "d", "b", "a"]
+    );
+    assert_eq!(
+        sorted("f(x, y)", "x", SortOrder::Descending)?,
+        vec!["a", "b", "c", "d"]
+    );
+    assert_eq!(
+        sorted("f(_, y)", "y", SortOrder::Descending)?,
+        vec!["d", "c", "b", "a"]
+    );
+
+    for (query, var) in [("g(y)", "y"), ("h(y)", "y"), ("f(_, y)", "z")] {
+        assert!(matches!(
+            oso.oso.query(query)?.sorted_by(var, SortOrder::Ascending),
+            Err(OsoError::InvalidSortKey { .. })
+        ));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_instance_handles() -> oso::Result<()> {
+    common::setup();
+    let mut oso = test_oso();
+    oso.load_str(
+        r#"widget(w) if w = new Widget(1) or w = new Widget(2);
+           editable(w: Widget) if w.id = 2;"#,
+    );
+
+    let handles = oso
+        .oso
+        .query("widget(w)")?
+        .map(|result| result?.get_typed::<InstanceHandle>("w"))
+        .collect::<oso::Result<Vec<_>>>()?;
+    assert_eq!(handles.len(), 2);
+    assert!(handles[0].type_name().ends_with("Widget"));
+    assert_eq!(handles[0], handles[0].clone());
+    assert_ne!(handles[0], handles[1]);
+
+    // Handles pass back into later queries unchanged.
+    let editable = handles
+        .iter()
+        .filter(|&handle| {
+            let mut query = oso.oso.query_rule("editable", (handle.clone(),)).unwrap();
+            query.next().is_some()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(editable, vec![&handles[1]]);
+
+    // Only instances convert to handles.
+    let mut query = oso.oso.query("x = 1")?;
+    assert!(query
+        .next()
+        .unwrap()?
+        .get_typed::<InstanceHandle>("x")
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_stdlib() -> oso::Result<()> {
+    common::setup();
+    let mut oso = test_oso();
+    oso.oso.load_stdlib();
+    oso.load_str("f(1);");
+
+    // Lists
+    assert_eq!(oso.query("member(2, [1, 2, 2])").len(), 1);
+    oso.qnull("member(3, [1, 2])");
+    oso.qeval("subset([1, 2], [2, 3, 1])");
+    oso.qnull("subset([1, 4], [2, 3, 1])");
+    oso.qeval("intersects([1, 4], [4, 5])");
+    oso.qnull("intersects([1], [])");
+    oso.qvar_one("length([1, 2, 3], n)", "n", 3);
+    oso.qvar_one("last([1, 2, 3], x)", "x", 3);
+
+    // Role-based access control
+    let permissions = r#"{reader: ["read"], writer: ["read", "write"]}"#;
+    let query = format!(
+        r#"role_allows(["reader", "writer"], "read", {})"#,
+        permissions
+    );
+    assert_eq!(oso.query(&query).len(), 1);
+    oso.qnull(&format!(
+        r#"role_allows(["reader"], "write", {})"#,
+        permissions
+    ));
+    let implications = r#"{owner: ["writer"], writer: ["reader"]}"#;
+    assert_eq!(
+        oso.qvar::<String>(
+            &format!(r#"role_implies("owner", role, {})"#, implications),
+            "role"
+        ),
+        vec!["owner", "writer", "reader"]
+    );
+
+    // Attribute-based access control
+    oso.qeval(r#"attributes_match({org: {id: 1}}, "org.id", {org_id: 1}, "org_id")"#);
+    oso.qnull(r#"attributes_match({org: {id: 1}}, "org.id", {org_id: 2}, "org_id")"#);
+    oso.qnull(r#"attributes_match({}, "org.id", {}, "org_id")"#);
+    oso.qeval(r#"attribute_in({status: "open"}, "status", ["open", "pending"])"#);
+    oso.qnull(r#"attribute_in({}, "status", ["open", "pending"])"#);
+
+    // The standard library is loaded again with the next policy.
+    oso.clear_rules();
+    oso.load_str("g(1);");
+    oso.qeval("subset([1], [1])");
+    Ok(())
+}
+
 #[test]
 fn test_clear_rules() -> oso::Result<()> {
     common::setup();
@@ -739,6 +971,63 @@ fn test_inline_queries() {
     oso.oso.load_str("g(1); ?= g(2);").unwrap_err();
 }
 
+#[test]
+fn test_tenant_inline_queries() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = test_oso();
+    oso.load_str("f(1);");
+    oso.oso
+        .load_str_for_tenant("acme", "f(2); ?= f(1); ?= f(2);")?;
+
+    // A failing inline query identifies the tenant it was loaded for.
+    let err = oso
+        .oso
+        .load_str_for_tenant("globex", "f(3); ?= f(2);")
+        .unwrap_err();
+    assert!(
+        matches!(&err, OsoError::TenantInlineQueryFailedError { tenant, .. } if tenant == "globex"),
+        "{}",
+        err
+    );
+    Ok(())
+}
+
+#[test]
+fn test_inline_query_reports() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = test_oso();
+    oso.load_str(r#"role("alice", "admin"); role("bob", "admin"); role("carol", "member");"#);
+    oso.oso
+        .load_str_for_tenant("acme", r#"?= role(user, "admin"); ?= role("carol", _);"#)?;
+
+    let reports = oso.oso.inline_query_reports();
+    assert_eq!(
+        reports.iter().map(|r| r.count()).collect::<Vec<_>>(),
+        vec![2, 1]
+    );
+    let admins = &reports[0];
+    assert_eq!(admins.tenant.as_deref(), Some("acme"));
+    let users = admins
+        .results
+        .iter()
+        .map(|result| result.get_typed::<String>("user"))
+        .collect::<oso::Result<Vec<_>>>()?;
+    assert_eq!(users, vec!["alice", "bob"]);
+    assert!(admins
+        .to_string()
+        .ends_with(r#"for tenant acme: 2 results: {user: "alice"}, {user: "bob"}"#));
+    assert!(reports[1]
+        .to_string()
+        .ends_with("for tenant acme: 1 result"));
+
+    // Each load replaces the reports.
+    oso.oso.load_str_for_tenant("acme", "")?;
+    assert!(oso.oso.inline_query_reports().is_empty());
+    Ok(())
+}
+
 // Skipped parse error tests.
 
 #[test]
@@ -829,6 +1118,519 @@ fn test_returns_unbound_variable() -> oso::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_result_set_into_map() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = test_oso();
+    let result = oso
+        .query(r#"x = 1 and y = ["a", {b: true}] and w = new Widget(2) and z = _"#)
+        .pop()
+        .unwrap();
+
+    let values = result.into_map();
+    assert_eq!(values.len(), 4);
+    assert_eq!(values["x"], PolarValue::Integer(1));
+    assert_eq!(
+        values["y"],
+        PolarValue::List(vec![
+            PolarValue::String("a".to_owned()),
+            PolarValue::Map(hashmap! {"b".to_owned() => PolarValue::Boolean(true)}),
+        ])
+    );
+    match &values["w"] {
+        PolarValue::Instance(instance) => {
+            assert_eq!(instance.downcast::<Widget>(None).unwrap().id, 2)
+        }
+        value => panic!("expected an instance, got: {:?}", value),
+    }
+    assert!(matches!(values["z"], PolarValue::Variable(_)));
+
+    Ok(())
+}
+
+#[test]
+fn test_query_with_params() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = test_oso();
+    oso.load_str("owns(id, widget: Widget) if widget.id = id;");
+
+    let params = [("id", 1.to_polar()), ("widget", Widget::new(1).to_polar())];
+    let mut query = oso.oso.query_with("owns(:id, :widget)", params)?;
+    assert!(query.next().is_some());
+
+    let params = [
+        ("id", "1 or true".to_polar()),
+        ("widget", Widget::new(1).to_polar()),
+    ];
+    let mut query = oso.oso.query_with("owns(:id, :widget)", params)?;
+    assert!(query.next().is_none());
+
+    assert!(oso
+        .oso
+        .query_with("owns(:id, :widget)", [("id", 1)])
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_disable_string_queries() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = test_oso();
+    oso.load_str(r#"allow("alice", "read", 1);"#);
+    oso.oso.set_string_queries(false);
+    assert!(matches!(
+        oso.oso.query(r#"allow("alice", "read", 1)"#),
+        Err(OsoError::StringQueriesDisabled)
+    ));
+
+    // Queries that pass values are still allowed.
+    assert!(oso.oso.is_allowed("alice", "read", 1)?);
+    assert!(!oso.oso.is_allowed("alice", r#"read" or true or "x"#, 1)?);
+    let params = [("action", "read".to_polar())];
+    assert!(oso
+        .oso
+        .query_with(r#"allow("alice", :action, 1)"#, params)?
+        .next()
+        .is_some());
+    Ok(())
+}
+
+#[test]
+fn test_sandboxed_policy() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = test_oso();
+    oso.oso.load_str_sandboxed(
+        r#"named(user: User, name) if user.name = name;
+           widget_id(user: User, id) if user.widget().id = id;
+           make(id, w) if w = new Widget(id);"#,
+        Sandbox::new()
+            .allow_method("User", "name")
+            .allow_class("Widget"),
+    )?;
+
+    let user = User::new("alice".to_owned());
+    assert!(oso
+        .oso
+        .query_rule("named", (user.clone(), "alice"))?
+        .next()
+        .is_some());
+    assert!(oso
+        .oso
+        .query_rule("make", (1, PolarValue::Variable("w".to_owned())))?
+        .next()
+        .is_some());
+    let err = oso
+        .oso
+        .query_rule("widget_id", (user, 1))?
+        .next()
+        .unwrap()
+        .unwrap_err();
+    assert!(matches!(
+        &err,
+        OsoError::Polar(polar_error::PolarError(polar_error::ErrorKind::Runtime(
+            polar_error::RuntimeError::SandboxViolation { class, name, .. }
+        ))) if class == "User" && name == "widget"
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_tenant_policies() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = test_oso();
+    oso.load_str(
+        r#"allow(user: User, "read", widget: Widget) if can_read(user, widget.id);
+           can_read(user: User, _id) if user.name = "admin";"#,
+    );
+    oso.oso
+        .load_str_for_tenant("acme", r#"can_read(user: User, 1) if user.name = "alice";"#)?;
+    oso.oso
+        .load_str_for_tenant("globex", r#"can_read(_user: User, 2);"#)?;
+
+    let alice = User::new("alice".to_owned());
+    assert!(oso
+        .oso
+        .is_allowed_for_tenant("acme", alice.clone(), "read", Widget::new(1))?);
+    assert!(!oso
+        .oso
+        .is_allowed_for_tenant("acme", alice.clone(), "read", Widget::new(2))?);
+    assert!(oso
+        .oso
+        .is_allowed_for_tenant("globex", alice.clone(), "read", Widget::new(2))?);
+    assert!(!oso
+        .oso
+        .is_allowed_for_tenant("globex", alice, "read", Widget::new(1))?);
+
+    let mut query =
+        oso.oso
+            .query_rule_for_tenant("acme", "can_read", (User::new("bob".to_owned()), 1))?;
+    assert!(query.next().is_none());
+    assert!(oso.oso.is_allowed_for_tenant(
+        "globex",
+        User::new("admin".to_owned()),
+        "read",
+        Widget::new(1)
+    )?);
+
+    assert!(oso.oso.remove_tenant("acme", false)?);
+    assert!(!oso.oso.is_allowed_for_tenant(
+        "acme",
+        User::new("alice".to_owned()),
+        "read",
+        Widget::new(1)
+    )?);
+    assert!(!oso.oso.remove_tenant("acme", false)?);
+
+    // Tenants use the policies of the tenants they include, which may then only be removed by
+    // force.
+    let bob = User::new("bob".to_owned());
+    oso.oso.set_tenant_includes("initech", &["globex"])?;
+    assert!(oso
+        .oso
+        .is_allowed_for_tenant("initech", bob.clone(), "read", Widget::new(2))?);
+    assert!(oso.oso.remove_tenant("globex", false).is_err());
+    assert!(oso.oso.remove_tenant("globex", true)?);
+    assert!(!oso
+        .oso
+        .is_allowed_for_tenant("initech", bob, "read", Widget::new(2))?);
+    Ok(())
+}
+
+#[test]
+fn test_tenant_quotas() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = Oso::new();
+    oso.load_str("allow(actor, _action, _resource) if actor = 1 or actor = 2;")?;
+    oso.set_default_tenant_quota(ScopeQuota::new().max_rules(1));
+    oso.set_tenant_quota("acme", ScopeQuota::new().max_query_goals(10));
+
+    let err = oso
+        .load_str_for_tenant("globex", "allow(3, _, _); allow(4, _, _);")
+        .unwrap_err();
+    assert!(matches!(
+        &err,
+        OsoError::Polar(polar_error::PolarError(polar_error::ErrorKind::Runtime(
+            polar_error::RuntimeError::QuotaExceeded { scope, limit: 1, .. }
+        ))) if scope == "globex"
+    ));
+    oso.load_str_for_tenant("globex", "allow(3, _, _);")?;
+    assert!(oso.is_allowed_for_tenant("globex", 3, "read", "doc")?);
+
+    let err = oso
+        .is_allowed_for_tenant("acme", 2, "read", "doc")
+        .unwrap_err();
+    assert!(matches!(
+        &err,
+        OsoError::Polar(polar_error::PolarError(polar_error::ErrorKind::Runtime(
+            polar_error::RuntimeError::QuotaExceeded { scope, limit: 10, .. }
+        ))) if scope == "acme"
+    ));
+    assert!(oso.is_allowed(2, "read", "doc")?);
+    Ok(())
+}
+
+#[test]
+fn test_tenant_rule_quotas() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = Oso::new();
+    oso.load_str("allow(actor, _action, _resource) if actor = 1;")?;
+    oso.set_tenant_quota(
+        "acme",
+        ScopeQuota::new().rule("below", RuleQuota::new().max_calls(3)),
+    );
+    oso.load_str_for_tenant(
+        "acme",
+        "allow(actor, _action, _resource) if below(actor);
+         below(2); below(x) if x > 2 and below(x - 1);",
+    )?;
+
+    assert!(oso.is_allowed_for_tenant("acme", 1, "read", "doc")?);
+    assert!(oso.is_allowed_for_tenant("acme", 2, "read", "doc")?);
+    let err = oso
+        .is_allowed_for_tenant("acme", 5, "read", "doc")
+        .unwrap_err();
+    assert!(matches!(
+        &err,
+        OsoError::Polar(polar_error::PolarError(polar_error::ErrorKind::Runtime(
+            polar_error::RuntimeError::QuotaExceeded { scope, limit: 3, .. }
+        ))) if scope == "acme"
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_tenant_default_decisions() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = Oso::new();
+    oso.load_str(
+        r#"default allow allow_readonly;
+           allow("admin", _action, _resource);
+           deny(_actor, "delete", "prod");
+           allow_readonly(_actor, "read", _resource);"#,
+    )?;
+    oso.load_str_for_tenant("acme", "default deny;")?;
+    oso.load_str_for_tenant(
+        "globex",
+        r#"default allow allow_guest;
+           allow_guest("guest", _action, _resource);"#,
+    )?;
+    oso.load_str_for_tenant("initech", r#"allow("bob", _action, _resource);"#)?;
+
+    // Without a scope, the shared default applies when no rule matches.
+    assert!(oso.is_allowed("alice", "read", "doc")?);
+    assert!(!oso.is_allowed("alice", "write", "doc")?);
+    assert_eq!(oso.decide("alice", "read", "doc")?, Decision::Allow);
+    assert_eq!(
+        oso.decide("alice", "write", "doc")?,
+        Decision::NotApplicable
+    );
+    assert_eq!(oso.decide("alice", "delete", "prod")?, Decision::Deny);
+
+    // Rules, shared or the tenant's, take precedence over any default.
+    assert!(oso.is_allowed_for_tenant("acme", "admin", "write", "doc")?);
+    assert!(oso.is_allowed_for_tenant("initech", "bob", "write", "doc")?);
+    // A tenant's default overrides the shared default.
+    assert!(!oso.is_allowed_for_tenant("acme", "alice", "read", "doc")?);
+    assert!(oso.is_allowed_for_tenant("globex", "guest", "write", "doc")?);
+    assert!(!oso.is_allowed_for_tenant("globex", "alice", "read", "doc")?);
+    // Tenants without a default of their own use the shared default.
+    assert!(oso.is_allowed_for_tenant("initech", "alice", "read", "doc")?);
+    assert!(!oso.is_allowed_for_tenant("initech", "alice", "write", "doc")?);
+
+    let err = oso
+        .load_str_for_tenant("acme", "default deny; default allow;")
+        .unwrap_err();
+    assert!(matches!(
+        &err,
+        OsoError::Polar(polar_error::PolarError(polar_error::ErrorKind::Validation(
+            polar_error::ValidationError::InvalidDefaultDecision { .. }
+        )))
+    ));
+    let err = oso
+        .load_str_for_tenant("acme", "default allow no_such_rule;")
+        .unwrap_err();
+    assert!(matches!(
+        &err,
+        OsoError::Polar(polar_error::PolarError(polar_error::ErrorKind::Validation(
+            polar_error::ValidationError::InvalidDefaultDecision { .. }
+        )))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_default_decisions_without_allow_rules() -> oso::Result<()> {
+    use oso::LoadOptions;
+    use std::collections::HashSet;
+
+    common::setup();
+
+    // A default decision stands in for the `allow` rules, so there's no warning about them.
+    let mut oso = Oso::new();
+    oso.set_max_policy_epochs(1);
+    oso.load_str_with("default allow;", &LoadOptions::new().strict(true))?;
+    assert!(oso.is_allowed("alice", "read", "doc")?);
+    assert!(oso.for_actor("alice").is_allowed("read", "doc")?);
+    let now = std::time::SystemTime::now();
+    assert!(oso.is_allowed_at("alice", "read", "doc", now)?);
+    assert!(oso
+        .get_allowed_actions::<_, _, String>("alice", "doc")
+        .is_err());
+
+    let mut oso = Oso::new();
+    oso.load_str(
+        r#"default allow allow_readonly;
+           allow_readonly(_actor, "read", _resource);
+           allow_readonly(_actor, "list", _resource);
+           deny(_actor, "list", "secrets");"#,
+    )?;
+    assert!(oso.for_actor("alice").is_allowed("read", "doc")?);
+    assert!(!oso.for_actor("alice").is_allowed("write", "doc")?);
+    assert!(!oso.for_actor("alice").is_allowed("list", "secrets")?);
+    let actions: HashSet<String> = oso.get_allowed_actions("alice", "secrets")?;
+    assert_eq!(actions, HashSet::from(["read".to_owned()]));
+    Ok(())
+}
+
+#[test]
+fn test_limits() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = Oso::new();
+    oso.set_limits(Limits::new().max_rule_depth(5));
+    oso.load_str(
+        "allow(actor, _action, _resource) if depth(actor);
+         depth(0);
+         depth(n) if n > 0 and depth(n - 1);",
+    )?;
+    assert!(oso.is_allowed(3, "read", "doc")?);
+    let err = oso.is_allowed(4, "read", "doc").unwrap_err();
+    assert!(matches!(
+        &err,
+        OsoError::Polar(polar_error::PolarError(polar_error::ErrorKind::Runtime(
+            polar_error::RuntimeError::RuleDepthExceeded { limit: 5, .. }
+        )))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_heartbeats() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = Oso::new();
+    oso.load_str(
+        "allow(actor, _action, _resource) if depth(actor);
+         depth(0);
+         depth(n) if n > 0 and depth(n - 1);",
+    )?;
+    oso.set_heartbeat_interval(Some(100));
+    assert!(oso.is_allowed(100, "read", "doc")?);
+
+    // Abandon queries after a few heartbeats.
+    let heartbeats = std::sync::atomic::AtomicUsize::new(0);
+    oso.add_query_hook(move |event: &QueryEvent| match event {
+        QueryEvent::Heartbeat { .. }
+            if heartbeats.fetch_add(1, std::sync::atomic::Ordering::SeqCst) >= 3 =>
+        {
+            HookAction::Fail(OsoError::Custom {
+                message: "deadline exceeded".to_owned(),
+            })
+        }
+        _ => HookAction::Continue,
+    });
+    let err = oso.is_allowed(100, "read", "doc").unwrap_err();
+    assert!(matches!(err, OsoError::Custom { message } if message == "deadline exceeded"));
+    Ok(())
+}
+
+#[test]
+fn test_hot_entrypoints() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = Oso::new();
+    oso.add_hot_entrypoint("allow", 3, 1)?;
+    assert!(oso.add_hot_entrypoint("allow", 3, 3).is_err());
+    oso.load_str(
+        r#"allow(actor, "read", _resource) if actor = "alice";
+           allow(actor, "write", _resource) if actor = "bob";
+           allow(actor, _action, resource) if actor = resource;"#,
+    )?;
+    assert!(oso.is_allowed("alice", "read", "doc")?);
+    assert!(!oso.is_allowed("alice", "write", "doc")?);
+    assert!(oso.is_allowed("bob", "write", "doc")?);
+    assert!(oso.is_allowed("carol", "delete", "carol")?);
+    assert!(!oso.is_allowed("carol", "delete", "doc")?);
+    Ok(())
+}
+
+#[test]
+fn test_query_hooks() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = test_oso();
+    oso.load_str(
+        r#"allow(user: User, "read", widget: Widget) if user.name = "alice" and widget.id = 1;
+           allow(user: User, "write", _: Widget) if user.widget().id = 2;"#,
+    );
+    let calls = Arc::new(Mutex::new(vec![]));
+    let logged = calls.clone();
+    oso.oso.add_query_hook(move |event: &QueryEvent| {
+        if let QueryEvent::ExternalCall { attribute, .. } = event {
+            logged.lock().unwrap().push(attribute.to_string());
+        }
+        HookAction::Continue
+    });
+    let alice = User::new("alice".to_owned());
+    assert!(oso.oso.is_allowed(alice.clone(), "read", Widget::new(1))?);
+    assert_eq!(*calls.lock().unwrap(), vec!["name", "id"]);
+
+    // Hooks may answer calls in place of the application.
+    oso.oso.add_query_hook(|event: &QueryEvent| match event {
+        QueryEvent::ExternalCall { attribute, .. } if attribute == "id" => {
+            HookAction::CallResult(Some(PolarValue::Integer(2)))
+        }
+        _ => HookAction::Continue,
+    });
+    assert!(oso.oso.is_allowed(alice.clone(), "write", Widget::new(1))?);
+    assert!(!oso.oso.is_allowed(alice.clone(), "read", Widget::new(1))?);
+
+    // Hooks added to a query run after those added to `Oso`.
+    let mut query = oso
+        .oso
+        .query_rule("allow", (alice, "write", Widget::new(1)))?;
+    query.add_hook(|event: &QueryEvent| match event {
+        QueryEvent::ExternalCall { attribute, .. } if attribute == "widget" => {
+            HookAction::Fail(OsoError::Custom {
+                message: "denied".to_owned(),
+            })
+        }
+        _ => HookAction::Continue,
+    });
+    let err = query.next().unwrap().unwrap_err();
+    assert!(matches!(&err, OsoError::Custom { message } if message == "denied"));
+    Ok(())
+}
+
+#[test]
+fn test_query_dispatcher() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = test_oso();
+    oso.load_str(
+        r#"allow(user: User, "read", widget: Widget) if user.name = "alice" and widget.id = 1;"#,
+    );
+    let bob = User::new("bob".to_owned());
+
+    // A dispatcher stubs out calls for one query only.
+    let mut query = oso
+        .oso
+        .query_rule("allow", (bob.clone(), "read", Widget::new(1)))?;
+    query.set_dispatcher(
+        |call: &ExternalCall| match (call.class.as_str(), call.name.as_str()) {
+            ("User", "name") => Some(Ok(PolarValue::String("alice".to_owned()))),
+            _ => None,
+        },
+    );
+    assert!(query.next().transpose()?.is_some());
+    assert!(!oso.oso.is_allowed(bob.clone(), "read", Widget::new(1))?);
+
+    // Dispatchers may also answer class checks, to stand in virtual data for instances.
+    struct VirtualWidgets;
+    impl Dispatcher for VirtualWidgets {
+        fn call(&self, call: &ExternalCall) -> Option<oso::Result<PolarValue>> {
+            match (&call.receiver, call.name.as_str()) {
+                (PolarValue::Map(fields), "id") => fields.get("id").cloned().map(Ok),
+                (_, "name") => Some(Ok(PolarValue::String("alice".to_owned()))),
+                _ => None,
+            }
+        }
+
+        fn isa(&self, instance: &PolarValue, class: &str) -> Option<bool> {
+            match instance {
+                PolarValue::Map(_) => Some(class == "Widget"),
+                _ => None,
+            }
+        }
+    }
+    let widget = hashmap! { "id".to_owned() => 1 };
+    let mut query = oso
+        .oso
+        .query_rule("allow", (bob.clone(), "read", widget.clone()))?;
+    query.set_dispatcher(VirtualWidgets);
+    assert!(query.next().transpose()?.is_some());
+    assert!(!oso.oso.is_allowed(bob, "read", widget)?);
+    Ok(())
+}
+
 #[test]
 fn test_nan_inf() -> oso::Result<()> {
     common::setup();
@@ -57,14 +57,55 @@ fn test_data_conversions() {
 
     use oso::PolarValue;
 
-    // TODO: do we want to handle hlists better?
-    // e.g. https://docs.rs/hlist/0.1.2/hlist/
     let mut results = test.query("d(x)");
     let first = results.pop().unwrap();
     let mut x = first.get_typed::<Vec<PolarValue>>("x").unwrap();
     assert_eq!(i64::try_from(x.remove(0)).unwrap(), 1);
     assert_eq!(String::try_from(x.remove(0)).unwrap(), "two");
     assert!(bool::try_from(x.remove(0)).unwrap());
+
+    // Lists of mixed types convert to tuples of the same length.
+    let x = first.get_typed::<(i64, String, bool)>("x").unwrap();
+    assert_eq!(x, (1, "two".to_string(), true));
+    assert!(first.get_typed::<(i64, String)>("x").is_err());
+    assert!(first.get_typed::<(String, i64, bool)>("x").is_err());
+}
+
+#[test]
+fn test_list_conversions() {
+    common::setup();
+    use std::collections::{BTreeSet, HashSet};
+
+    let mut test = OsoTest::new();
+    test.load_str(
+        r#"roles(["admin", "reader", "admin"]);
+           grants([["alice", 1], ["bob", 2]]);"#,
+    );
+    test.qvar_one(
+        "roles(x)",
+        "x",
+        vec![
+            "admin".to_string(),
+            "reader".to_string(),
+            "admin".to_string(),
+        ],
+    );
+    test.qvar_one(
+        "roles(x)",
+        "x",
+        HashSet::from(["admin".to_string(), "reader".to_string()]),
+    );
+    test.qvar_one(
+        "roles(x)",
+        "x",
+        BTreeSet::from(["admin".to_string(), "reader".to_string()]),
+    );
+    test.qvar_one(
+        "grants(x)",
+        "x",
+        vec![("alice".to_string(), 1), ("bob".to_string(), 2)],
+    );
+    test.qvar_one("grants([x, *_])", "x", ("alice".to_string(), 1));
 }
 
 // This logic is changing. Updated when fixed
@@ -753,38 +794,2144 @@ fn test_is_subclass() {
     test.qnull("x matches Foo and x matches Bar");
 }
 
-#[cfg(feature = "uuid-06")]
 #[test]
-fn test_uuid_06() -> Result<(), Box<dyn std::error::Error>> {
-    use uuid_06::Uuid;
+fn test_fact_source() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use oso::{CacheHint, FactSource, Facts, PolarValue};
+
+    common::setup();
+
+    struct Roles {
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl FactSource for Roles {
+        fn facts(&self, args: &[PolarValue]) -> oso::Result<Facts> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let roles = vec![
+                ("alice", "member", 1),
+                ("alice", "owner", 2),
+                ("bob", "member", 2),
+            ];
+            let actor = args[0].clone();
+            Ok(Box::new(
+                roles
+                    .into_iter()
+                    .filter(move |(name, _, _)| match &actor {
+                        PolarValue::String(actor) => actor == name,
+                        _ => true,
+                    })
+                    .map(|(name, role, repo)| {
+                        Ok(vec![
+                            PolarValue::String(name.to_owned()),
+                            PolarValue::String(role.to_owned()),
+                            PolarValue::Integer(repo),
+                        ])
+                    }),
+            ))
+        }
+
+        fn cache_hint(&self, _args: &[PolarValue]) -> CacheHint {
+            CacheHint::Query
+        }
+    }
+
+    let calls = Arc::new(AtomicUsize::new(0));
     let mut test = OsoTest::new();
-    test.oso.register_class(Uuid::get_polar_class())?;
-    test.load_str("f(x: Uuid, y: Uuid) if x = y;");
-    let (x, y) = (Uuid::nil(), Uuid::nil());
-    test.oso.query_rule("f", (x, y))?.next().unwrap()?;
+    test.oso
+        .register_fact_source(
+            "has_role",
+            Roles {
+                calls: calls.clone(),
+            },
+        )
+        .unwrap();
+    test.load_str(
+        r#"allow(actor, "read", repo) if has_role(actor, _, repo);
+           allow(actor, "write", repo) if has_role(actor, "owner", repo);"#,
+    );
+
+    assert!(test.oso.is_allowed("alice", "read", 1).unwrap());
+    assert!(test.oso.is_allowed("alice", "write", 2).unwrap());
+    assert!(!test.oso.is_allowed("alice", "write", 1).unwrap());
+    assert!(!test.oso.is_allowed("bob", "write", 2).unwrap());
+    assert_eq!(
+        test.qvar::<i64>(r#"has_role("alice", _, repo)"#, "repo"),
+        [1, 2]
+    );
+
+    // Identical calls within one query are answered from the cache.
+    calls.store(0, Ordering::SeqCst);
+    test.qeval(r#"has_role("alice", "member", 1) and has_role("alice", "member", 1)"#);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_insert_and_delete_facts() -> oso::Result<()> {
+    common::setup();
+
+    let mut test = OsoTest::new();
+    assert!(test.oso.insert_fact("has_role", ("alice", "member", 1))?);
+    assert!(!test.oso.insert_fact("has_role", ("alice", "member", 1))?);
+    test.load_str(r#"allow(actor, "read", repo) if has_role(actor, "member", repo);"#);
+    assert!(test.oso.insert_fact("has_role", ("bob", "member", 2))?);
+
+    assert!(test.oso.is_allowed("alice", "read", 1)?);
+    assert!(!test.oso.is_allowed("alice", "read", 2)?);
+    assert_eq!(test.qvar::<i64>(r#"has_role(_, _, repo)"#, "repo"), [1, 2]);
+
+    assert!(test.oso.delete_fact("has_role", ("alice", "member", 1))?);
+    assert!(!test.oso.delete_fact("has_role", ("alice", "member", 1))?);
+    assert!(!test.oso.is_allowed("alice", "read", 1)?);
+    assert!(test.oso.is_allowed("bob", "read", 2)?);
     Ok(())
 }
 
-#[cfg(feature = "uuid-07")]
 #[test]
-fn test_uuid_07() -> Result<(), Box<dyn std::error::Error>> {
-    use uuid_07::Uuid;
+fn test_authorized_query() -> oso::Result<()> {
+    use polar_core::data_filtering::Type;
+    use serde_json::json;
+
+    common::setup();
+
+    #[derive(Clone, PolarClass)]
+    struct Repo {
+        #[polar(attribute)]
+        public: bool,
+    }
+
     let mut test = OsoTest::new();
-    test.oso.register_class(Uuid::get_polar_class())?;
-    test.load_str("f(x: Uuid, y: Uuid) if x = y;");
-    let (x, y) = (Uuid::nil(), Uuid::nil());
-    test.oso.query_rule("f", (x, y))?.next().unwrap()?;
+    test.oso.register_class(Repo::get_polar_class())?;
+    test.oso.register_filter_types(hashmap! {
+        "Repo".to_owned() => hashmap! {
+            "public".to_owned() => Type::Base { class_tag: "Boolean".to_owned() },
+        },
+    });
+    test.load_str(
+        r#"allow(_actor, "read", repo: Repo) if repo.public = true;
+           allow("admin", _action, _repo: Repo);"#,
+    );
+
+    let filter = test.oso.authorized_query("alice", "read", "Repo")?;
+    assert_eq!(
+        filter.explain(),
+        r#"Boolean(true) = Repo.public
+    <unnamed source>:1: repo.public in allow(_actor, "read", repo: Repo{})"#
+    );
+    assert_eq!(
+        serde_json::to_value(filter).unwrap(),
+        json!({
+            "root": "Repo",
+            "relations": [],
+            "conditions": [[[{"Immediate": {"Boolean": true}}, "Eq", {"Field": ["Repo", "public"]}]]],
+        })
+    );
+    let filter = test.oso.authorized_query("admin", "write", "Repo")?;
+    assert_eq!(
+        serde_json::to_value(filter).unwrap(),
+        json!({"root": "Repo", "relations": [], "conditions": [[]]})
+    );
+    // Nothing is allowed.
+    let filter = test.oso.authorized_query("alice", "write", "Repo")?;
+    assert_eq!(
+        serde_json::to_value(filter).unwrap(),
+        json!({
+            "root": "Repo",
+            "relations": [],
+            "conditions": [[[{"Immediate": {"Boolean": true}}, "Eq", {"Immediate": {"Boolean": false}}]]],
+        })
+    );
     Ok(())
 }
 
-#[cfg(feature = "uuid-10")]
 #[test]
-fn test_uuid_10() -> Result<(), Box<dyn std::error::Error>> {
-    use uuid_10::Uuid;
+fn test_prefetch_hints() -> oso::Result<()> {
+    use polar_core::data_filtering::Type;
+    use polar_core::filter::Cardinality;
+
+    common::setup();
+
+    #[derive(Clone, PolarClass)]
+    struct Org {
+        #[polar(attribute)]
+        name: String,
+    }
+
+    #[derive(Clone, PolarClass)]
+    struct Repo {
+        #[polar(attribute)]
+        org: Org,
+        #[polar(attribute)]
+        public: bool,
+    }
+
     let mut test = OsoTest::new();
-    test.oso.register_class(Uuid::get_polar_class())?;
-    test.load_str("f(x: Uuid, y: Uuid) if x = y;");
-    let (x, y) = (Uuid::nil(), Uuid::nil());
-    test.oso.query_rule("f", (x, y))?.next().unwrap()?;
+    test.oso.register_class(Org::get_polar_class())?;
+    test.oso.register_class(Repo::get_polar_class())?;
+    test.oso.register_filter_types(hashmap! {
+        "Repo".to_owned() => hashmap! {
+            "org".to_owned() => Type::Relation {
+                kind: "one".to_owned(),
+                other_class_tag: "Org".to_owned(),
+                my_field: "org_id".to_owned(),
+                other_field: "id".to_owned(),
+            },
+            "public".to_owned() => Type::Base { class_tag: "Boolean".to_owned() },
+        },
+        "Org".to_owned() => hashmap! {
+            "name".to_owned() => Type::Base { class_tag: "String".to_owned() },
+        },
+    });
+    test.load_str(
+        r#"allow(_actor, "read", repo: Repo) if repo.public = true;
+           allow(_actor, "read", repo: Repo) if repo.org.name = "acme";"#,
+    );
+
+    let filter = test.oso.authorized_query("alice", "read", "Repo")?;
+    let hints = test.oso.prefetch_hints(&filter);
+    assert_eq!(hints.len(), 1);
+    assert_eq!(
+        (hints[0].from.as_str(), hints[0].to.as_str()),
+        ("Repo", "Org")
+    );
+    assert_eq!(hints[0].cardinality, Cardinality::One);
+    assert_eq!((hints[0].depth, hints[0].uses), (0, 1));
+    Ok(())
+}
+
+#[test]
+fn test_load_rego() -> oso::Result<()> {
+    use maplit::hashmap;
+    use oso::PolarValue;
+
+    common::setup();
+
+    let mut test = OsoTest::new();
+    let grants = PolarValue::List(vec![PolarValue::Map(hashmap! {
+        "action".to_owned() => PolarValue::String("read".to_owned()),
+    })]);
+    let data = hashmap! {
+        "admins".to_owned() => PolarValue::List(vec![PolarValue::String("alice".to_owned())]),
+        "grants".to_owned() => PolarValue::Map(hashmap! { "viewer".to_owned() => grants }),
+    };
+    test.oso.register_constant(data, "data")?;
+    let policy = r#"
+        package authz
+
+        default allow := false
+
+        allow if is_admin
+
+        allow if {
+            some role in input.roles
+            some grant in data.grants[role]
+            grant.action == input.action
+            not input.locked
+        }
+
+        is_admin if input.user == data.admins[_]
+    "#;
+    assert!(test.oso.load_rego(policy)?.is_empty());
+
+    test.qeval(r#"authz_allow({user: "alice", action: "write"})"#);
+    test.qeval(r#"authz_allow({user: "bob", roles: ["viewer"], action: "read"})"#);
+    test.qnull(r#"authz_allow({user: "bob", roles: ["viewer"], action: "write"})"#);
+    test.qnull(r#"authz_allow({user: "bob", roles: ["viewer"], action: "read", locked: true})"#);
+    test.qeval(r#"authz_allow({user: "bob", roles: ["viewer"], action: "read", locked: false})"#);
+    test.qnull(r#"authz_allow({user: "bob", roles: ["editor"], action: "read"})"#);
+
+    test.oso.clear_rules()?;
+    let policy = format!(
+        "{}
+        allow if startswith(input.action, \"list\")",
+        policy
+    );
+    let issues = test.oso.load_rego(&policy)?;
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].line, 17);
+    assert_eq!(issues[0].rule.as_deref(), Some("allow"));
+
+    // No definition of `allow` is loaded, as one of them couldn't be translated.
+    let err = test.query_err(r#"authz_allow({user: "alice", action: "write"})"#);
+    assert!(err.contains("undefined rule"), "{}", err);
+    test.qeval(r#"authz_is_admin({user: "alice"})"#);
+    Ok(())
+}
+
+#[test]
+fn test_relationship_sync() -> oso::Result<()> {
+    use oso::{RelationTuple, RelationshipStore, RelationshipSync, TupleOperation, WatchBatch};
+    use std::collections::VecDeque;
+
+    common::setup();
+
+    struct Store {
+        snapshots: VecDeque<Vec<&'static str>>,
+        batches: VecDeque<Vec<(TupleOperation, &'static str)>>,
+        watched: Vec<String>,
+    }
+
+    impl RelationshipStore for Store {
+        fn snapshot(&mut self) -> oso::Result<(Vec<RelationTuple>, String)> {
+            let tuples = self.snapshots.pop_front().unwrap_or_default();
+            let tuples = tuples
+                .iter()
+                .map(|t| t.parse())
+                .collect::<oso::Result<_>>()?;
+            Ok((tuples, "snapshot".to_owned()))
+        }
+
+        fn watch(&mut self, revision: &str) -> oso::Result<Option<WatchBatch>> {
+            self.watched.push(revision.to_owned());
+            let updates = match self.batches.pop_front() {
+                Some(updates) => updates,
+                None => return Ok(None),
+            };
+            Ok(Some(WatchBatch {
+                updates: updates
+                    .into_iter()
+                    .map(|(op, t)| Ok((op, t.parse()?)))
+                    .collect::<oso::Result<_>>()?,
+                revision: format!("rev{}", self.watched.len()),
+            }))
+        }
+    }
+
+    let store = Store {
+        snapshots: VecDeque::from([
+            vec!["doc:1#viewer@user:alice", "doc:2#viewer@group:eng#member"],
+            vec!["doc:1#viewer@user:carol", "doc:2#viewer@group:eng#member"],
+        ]),
+        batches: VecDeque::from([
+            vec![
+                (TupleOperation::Write, "doc:1#viewer@user:bob"),
+                (TupleOperation::Write, "doc:1#viewer@user:bob"),
+                (TupleOperation::Delete, "doc:1#viewer@user:alice"),
+            ],
+            vec![(TupleOperation::Delete, "doc:9#viewer@user:nobody")],
+        ]),
+        watched: vec![],
+    };
+    let mut test = OsoTest::new();
+    let mut sync = RelationshipSync::new(store);
+    let is_allowed = |test: &OsoTest, actor: &str, doc: &str| {
+        test.oso
+            .is_allowed(actor.to_owned(), "read", doc.to_owned())
+    };
+
+    // The first poll takes a snapshot, which defines the rule for the policy.
+    assert!(sync.poll(&mut test.oso)?);
+    assert_eq!(sync.revision(), Some("snapshot"));
+    test.load_str(r#"allow(actor, "read", doc) if has_relation(actor, "viewer", doc);"#);
+    assert!(is_allowed(&test, "user:alice", "doc:1")?);
+    assert!(is_allowed(&test, "group:eng#member", "doc:2")?);
+
+    assert!(sync.poll(&mut test.oso)?);
+    assert_eq!(sync.revision(), Some("rev1"));
+    assert!(is_allowed(&test, "user:bob", "doc:1")?);
+    assert!(!is_allowed(&test, "user:alice", "doc:1")?);
+
+    sync.run(&mut test.oso)?;
+    assert_eq!(sync.revision(), Some("rev2"));
+
+    // A new snapshot replaces the facts.
+    assert_eq!(sync.snapshot(&mut test.oso)?, 2);
+    assert!(!is_allowed(&test, "user:bob", "doc:1")?);
+    assert!(is_allowed(&test, "user:carol", "doc:1")?);
+    assert!(is_allowed(&test, "group:eng#member", "doc:2")?);
+    Ok(())
+}
+
+#[test]
+fn test_row_level_security() -> oso::Result<()> {
+    use polar_core::data_filtering::Type;
+    use polar_core::sql::SqlParameter;
+    use polar_core::terms::Value;
+
+    common::setup();
+
+    #[derive(Clone, PolarClass)]
+    struct User {
+        #[polar(attribute)]
+        id: i64,
+        #[polar(attribute)]
+        org_ids: Vec<i64>,
+    }
+
+    #[derive(Clone, PolarClass)]
+    struct Org {
+        #[polar(attribute)]
+        id: i64,
+    }
+
+    #[derive(Clone, PolarClass)]
+    struct Repo {
+        #[polar(attribute)]
+        owner_id: i64,
+        #[polar(attribute)]
+        org: Org,
+        #[polar(attribute)]
+        archived: bool,
+    }
+
+    let mut test = OsoTest::new();
+    test.oso.register_class(User::get_polar_class())?;
+    test.oso.register_class(Org::get_polar_class())?;
+    test.oso.register_class(Repo::get_polar_class())?;
+    test.oso.register_filter_types(hashmap! {
+        "Repo".to_owned() => hashmap! {
+            "owner_id".to_owned() => Type::Base { class_tag: "Integer".to_owned() },
+            "archived".to_owned() => Type::Base { class_tag: "Boolean".to_owned() },
+            "org".to_owned() => Type::Relation {
+                kind: "one".to_owned(),
+                other_class_tag: "Org".to_owned(),
+                my_field: "org_id".to_owned(),
+                other_field: "id".to_owned(),
+            },
+        },
+        "Org".to_owned() => hashmap! {
+            "id".to_owned() => Type::Base { class_tag: "Integer".to_owned() },
+        },
+    });
+    test.load_str(
+        r#"allow(user: User, "read", repo: Repo) if repo.owner_id = user.id;
+           allow(user: User, "read", repo: Repo) if repo.org.id in user.org_ids;
+           deny(_user: User, _action, repo: Repo) if repo.archived = true;"#,
+    );
+
+    let tables = hashmap! {
+        "Repo".to_owned() => "repos".to_owned(),
+        "Org".to_owned() => "orgs".to_owned(),
+    };
+    let template = test
+        .oso
+        .row_level_security("User", "read", "Repo", &tables)?;
+    assert_eq!(
+        template.predicate,
+        r#"($1 = "repos"."owner_id" AND $2 <> "repos"."archived") OR (EXISTS (SELECT 1 FROM "orgs" WHERE "repos"."org_id" = "orgs"."id" AND $2 <> "repos"."archived" AND "orgs"."id" = ANY($3)))"#
+    );
+    assert_eq!(
+        template.parameters,
+        vec![
+            SqlParameter::Path("actor.id".to_owned()),
+            SqlParameter::Value(Value::Boolean(true)),
+            SqlParameter::Path("actor.org_ids".to_owned()),
+        ]
+    );
+
+    // Nothing is allowed.
+    let template = test
+        .oso
+        .row_level_security("User", "write", "Repo", &tables)?;
+    assert_eq!(template.predicate, "FALSE");
+    assert!(template.parameters.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_authorized_query_filter_keys() -> oso::Result<()> {
+    use polar_core::data_filtering::Type;
+    use serde_json::json;
+
+    common::setup();
+
+    #[derive(Clone, PolarClass)]
+    struct User {
+        id: i64,
+    }
+
+    #[derive(Clone, PolarClass)]
+    struct Repo {
+        #[polar(attribute)]
+        owner: User,
+    }
+
+    let mut test = OsoTest::new();
+    test.oso.register_class(
+        User::get_polar_class_builder()
+            .with_filter_key(|user| user.id)
+            .build(),
+    )?;
+    test.oso.register_class(Repo::get_polar_class())?;
+    test.oso.register_filter_types(hashmap! {
+        "Repo".to_owned() => hashmap! {
+            "owner".to_owned() => Type::Base { class_tag: "User".to_owned() },
+        },
+    });
+    test.load_str(r#"allow(user: User, "read", repo: Repo) if repo.owner = user;"#);
+
+    // Distinct users with the same key get the same filter.
+    let expected = json!({
+        "root": "Repo",
+        "relations": [],
+        "conditions": [[[{"Immediate": {"Number": {"Integer": 7}}}, "Eq", {"Field": ["Repo", "owner"]}]]],
+    });
+    for user in [User { id: 7 }, User { id: 7 }] {
+        let filter = test.oso.authorized_query(user, "read", "Repo")?;
+        assert_eq!(serde_json::to_value(filter).unwrap(), expected);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_deny_rules() -> oso::Result<()> {
+    use oso::{Decision, Enforcer, ToPolar};
+    use polar_core::data_filtering::Type;
+    use serde_json::json;
+
+    common::setup();
+
+    #[derive(Clone, PolarClass)]
+    struct Repo {
+        #[polar(attribute)]
+        archived: bool,
+    }
+
+    let mut test = OsoTest::new();
+    test.oso.register_class(Repo::get_polar_class())?;
+    test.oso.register_filter_types(hashmap! {
+        "Repo".to_owned() => hashmap! {
+            "archived".to_owned() => Type::Base { class_tag: "Boolean".to_owned() },
+        },
+    });
+    // Without deny rules, decisions match `is_allowed`.
+    test.load_str(r#"allow("alice", action, _repo) if action in ["read", "write"];"#);
+    let repo = Repo { archived: true };
+    assert_eq!(
+        test.oso.decide("alice", "write", repo.clone())?,
+        Decision::Allow
+    );
+    assert_eq!(
+        test.oso.decide("bob", "write", repo.clone())?,
+        Decision::NotApplicable
+    );
+
+    test.clear_rules();
+    test.load_str(
+        r#"allow("alice", action, _repo) if action in ["read", "write"];
+           allow("admin", _action, _repo);
+           deny(_actor, "write", repo: Repo) if repo.archived = true;"#,
+    );
+    assert_eq!(
+        test.oso.decide("alice", "read", repo.clone())?,
+        Decision::Allow
+    );
+    assert_eq!(
+        test.oso.decide("alice", "write", repo.clone())?,
+        Decision::Deny
+    );
+    assert_eq!(
+        test.oso.decide("admin", "write", repo.clone())?,
+        Decision::Deny
+    );
+    assert_eq!(
+        test.oso
+            .decide("alice", "write", Repo { archived: false })?,
+        Decision::Allow
+    );
+    assert!(!test.oso.is_allowed("alice", "write", repo.clone())?);
+
+    assert!(!test.oso.authorize(
+        "alice".to_polar(),
+        "write".to_polar(),
+        repo.clone().to_polar()
+    )?);
+    assert_eq!(
+        test.oso
+            .authorized_actions("alice".to_polar(), repo.clone().to_polar())?,
+        vec!["read".to_polar()]
+    );
+
+    let filter = test.oso.authorized_query("alice", "write", "Repo")?;
+    assert_eq!(
+        serde_json::to_value(filter).unwrap(),
+        json!({
+            "root": "Repo",
+            "relations": [],
+            // Repos that fail to match the deny rule's `Repo` specializer are unsatisfiable.
+            "conditions": [
+                [[{"Immediate": {"Boolean": true}}, "Eq", {"Immediate": {"Boolean": false}}]],
+                [[{"Immediate": {"Boolean": true}}, "Neq", {"Field": ["Repo", "archived"]}]],
+            ],
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn test_deny_rules_override_allow_everywhere() -> oso::Result<()> {
+    use oso::{Decision, Enforcer, ToPolar};
+    use std::collections::HashSet;
+
+    common::setup();
+    let mut test = OsoTest::new();
+    test.oso.set_max_policy_epochs(1);
+    test.load_str(
+        r#"allow(_actor, action, _resource) if action in ["read", "delete"];
+           deny(_actor, "delete", "prod");"#,
+    );
+    test.oso
+        .load_str_for_tenant("acme", r#"deny(_actor, "read", "prod");"#)?;
+    let oso = &test.oso;
+
+    assert_eq!(oso.decide("alice", "delete", "prod")?, Decision::Deny);
+    assert!(!oso.authorize("alice".to_polar(), "delete".to_polar(), "prod".to_polar())?);
+    assert!(!oso.is_allowed("alice", "delete", "prod")?);
+    assert!(oso.is_allowed("alice", "delete", "dev")?);
+    let now = std::time::SystemTime::now();
+    assert!(!oso.is_allowed_at("alice", "delete", "prod", now)?);
+    assert!(!oso.is_allowed_for_tenant("acme", "alice", "delete", "prod")?);
+    assert!(!oso.is_allowed_for_tenant("acme", "alice", "read", "prod")?);
+    assert!(oso.is_allowed_for_tenant("globex", "alice", "read", "prod")?);
+    assert!(!oso.for_actor("alice").is_allowed("delete", "prod")?);
+    assert!(oso.for_actor("alice").is_allowed("read", "prod")?);
+
+    let actions: HashSet<String> = oso.get_allowed_actions("alice", "prod")?;
+    assert_eq!(actions, HashSet::from(["read".to_owned()]));
+    Ok(())
+}
+
+#[test]
+fn test_lint_rules() {
+    use polar_core::kb::KnowledgeBase;
+    use polar_core::lint::{Lint, LintRule};
+    use polar_core::terms::Symbol;
+
+    struct NoTestOnlyRules;
+
+    impl LintRule for NoTestOnlyRules {
+        fn name(&self) -> &str {
+            "no-test-only"
+        }
+
+        fn check(&self, kb: &KnowledgeBase) -> Vec<Lint> {
+            match kb.get_generic_rule(&Symbol::new("test_only")) {
+                Some(_) => vec![Lint::error("test_only rules must not be deployed", None)],
+                None => vec![],
+            }
+        }
+    }
+
+    common::setup();
+
+    let mut test = OsoTest::new();
+    test.oso.register_lint_rule(NoTestOnlyRules);
+    let err = test
+        .oso
+        .load_str("allow(_, _, _) if test_only(); test_only();")
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "no-test-only: test_only rules must not be deployed"
+    );
+    test.load_str("allow(_, _, _);");
+}
+
+#[test]
+fn test_rewrite_passes() -> oso::Result<()> {
+    use polar_core::kb::KnowledgeBase;
+    use polar_core::rules::Rule;
+    use polar_core::terms::{Call, Operation, Operator, Symbol, Term, Value};
+    use polar_core::RewritePass;
+
+    /// Require `is_active(actor)` in the body of every `allow` rule.
+    struct ActiveActors;
+
+    impl RewritePass for ActiveActors {
+        fn name(&self) -> &str {
+            "active_actors"
+        }
+
+        fn rewrite_rule(&self, mut rule: Rule, _: &KnowledgeBase) -> Rule {
+            if rule.name.as_str() != "allow" {
+                return rule;
+            }
+            let check = Term::new_temporary(Value::Call(Call {
+                name: Symbol::new("is_active"),
+                args: vec![rule.params[0].parameter.clone()],
+                kwargs: None,
+            }));
+            if let Value::Expression(Operation { args, .. }) = rule.body.value() {
+                let mut args = args.clone();
+                args.insert(0, check);
+                let body = Value::Expression(Operation {
+                    operator: Operator::And,
+                    args,
+                });
+                rule.body = rule.body.clone_with_value(body);
+            }
+            rule
+        }
+    }
+
+    common::setup();
+
+    let mut test = OsoTest::new();
+    test.oso.register_rewrite_pass(ActiveActors);
+    test.load_str(
+        r#"is_active("alice");
+           allow(_actor, "read", _resource);"#,
+    );
+    assert!(test.oso.is_allowed("alice", "read", 1)?);
+    assert!(!test.oso.is_allowed("bob", "read", 1)?);
+    Ok(())
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn test_load_facts_json() -> oso::Result<()> {
+    common::setup();
+
+    let mut test = OsoTest::new();
+    let data = r#"{
+        "has_role": [["alice", "member", 1], ["alice", "member", 1], ["bob", "owner", 2]],
+        "parent": [[{"id": 1}, {"id": 2}]]
+    }"#;
+    assert_eq!(test.oso.load_facts_json(data.as_bytes())?, 3);
+    test.load_str(r#"allow(actor, "read", repo) if has_role(actor, _, repo);"#);
+
+    assert!(test.oso.is_allowed("alice", "read", 1)?);
+    assert!(test.oso.is_allowed("bob", "read", 2)?);
+    test.qvar_one("parent({id: 1}, {id: id})", "id", 2);
+    test.qnull("parent({id: 2}, _)");
+
+    let err = test.oso.load_facts_json(r#"{"f": [[null]]}"#.as_bytes());
+    assert!(matches!(err, Err(oso::OsoError::InvalidFactData { .. })));
+
+    // Facts are inserted as they're read, so the facts before invalid data remain.
+    let err = test
+        .oso
+        .load_facts_json(r#"{"g": [[1]], "h": [[1], 2]}"#.as_bytes());
+    assert!(matches!(err, Err(oso::OsoError::InvalidFactData { .. })));
+    test.qvar_one("g(x)", "x", 1);
+    let err = test
+        .oso
+        .load_facts_json(r#"{"g": [[2]]} trailing"#.as_bytes());
+    assert!(matches!(err, Err(oso::OsoError::InvalidFactData { .. })));
+    Ok(())
+}
+
+#[cfg(feature = "csv")]
+#[test]
+fn test_load_facts_csv() -> oso::Result<()> {
+    use oso::{Column, FactSchema};
+
+    common::setup();
+
+    let mut test = OsoTest::new();
+    let schema = FactSchema::new(
+        "has_role",
+        vec![Column::String, Column::String, Column::Integer],
+    );
+    let data = "user,role,repo\nalice,member,1\nbob,owner,2\n";
+    assert_eq!(test.oso.load_facts_csv(data.as_bytes(), &schema)?, 2);
+    test.load_str(r#"allow(actor, "read", repo) if has_role(actor, _, repo);"#);
+    assert!(test.oso.is_allowed("alice", "read", 1)?);
+    assert!(!test.oso.is_allowed("alice", "read", 2)?);
+
+    let schema = schema.has_headers(false);
+    let err = test
+        .oso
+        .load_facts_csv("carol,member,three\n".as_bytes(), &schema);
+    assert!(matches!(err, Err(oso::OsoError::InvalidFactData { .. })));
+    let err = test
+        .oso
+        .load_facts_csv("carol,member\n".as_bytes(), &schema);
+    assert!(matches!(err, Err(oso::OsoError::InvalidFactData { .. })));
+
+    assert_eq!(
+        test.oso
+            .load_facts_csv("carol,member,3\n".as_bytes(), &schema)?,
+        1
+    );
+    assert!(test.oso.is_allowed("carol", "read", 3)?);
+    Ok(())
+}
+
+#[cfg(feature = "uuid-06")]
+#[test]
+fn test_uuid_06() -> Result<(), Box<dyn std::error::Error>> {
+    use uuid_06::Uuid;
+    let mut test = OsoTest::new();
+    test.oso.register_class(Uuid::get_polar_class())?;
+    test.load_str("f(x: Uuid, y: Uuid) if x = y;");
+    let (x, y) = (Uuid::nil(), Uuid::nil());
+    test.oso.query_rule("f", (x, y))?.next().unwrap()?;
+    Ok(())
+}
+
+#[cfg(feature = "uuid-07")]
+#[test]
+fn test_uuid_07() -> Result<(), Box<dyn std::error::Error>> {
+    use uuid_07::Uuid;
+    let mut test = OsoTest::new();
+    test.oso.register_class(Uuid::get_polar_class())?;
+    test.load_str("f(x: Uuid, y: Uuid) if x = y;");
+    let (x, y) = (Uuid::nil(), Uuid::nil());
+    test.oso.query_rule("f", (x, y))?.next().unwrap()?;
+    Ok(())
+}
+
+#[cfg(feature = "uuid-10")]
+#[test]
+fn test_uuid_10() -> Result<(), Box<dyn std::error::Error>> {
+    use uuid_10::Uuid;
+    let mut test = OsoTest::new();
+    test.oso.register_class(Uuid::get_polar_class())?;
+    test.load_str("f(x: Uuid, y: Uuid) if x = y;");
+    let (x, y) = (Uuid::nil(), Uuid::nil());
+    test.oso.query_rule("f", (x, y))?.next().unwrap()?;
+    Ok(())
+}
+
+#[test]
+fn test_is_allowed_at() -> oso::Result<()> {
+    common::setup();
+    let mut test = OsoTest::new();
+    test.oso.set_max_policy_epochs(3);
+
+    let before_load = std::time::SystemTime::now() - std::time::Duration::from_secs(1);
+    test.load_str(r#"allow("alice", "read", "repo");"#);
+    let first = *test.oso.policy_epochs().last().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    test.clear_rules();
+    test.load_str(r#"allow("bob", "read", "repo");"#);
+
+    assert!(!test.oso.is_allowed("alice", "read", "repo")?);
+    assert!(test.oso.is_allowed("bob", "read", "repo")?);
+    assert!(test.oso.is_allowed_at("alice", "read", "repo", first)?);
+    assert!(!test.oso.is_allowed_at("bob", "read", "repo", first)?);
+    assert!(test
+        .oso
+        .is_allowed_at("bob", "read", "repo", std::time::SystemTime::now())?);
+    assert_eq!(test.oso.policy_epochs().len(), 3);
+
+    let err = test
+        .oso
+        .is_allowed_at("alice", "read", "repo", before_load)
+        .unwrap_err();
+    assert!(
+        err.to_string().starts_with("No policy was recorded"),
+        "{}",
+        err
+    );
+
+    test.oso.set_max_policy_epochs(2);
+    assert_eq!(test.oso.policy_epochs().len(), 2);
+    assert!(test
+        .oso
+        .is_allowed_at("alice", "read", "repo", first)
+        .is_err());
+
+    // No epochs are kept by default.
+    let mut oso = oso::Oso::new();
+    oso.load_str(r#"allow("alice", "read", "repo");"#)?;
+    assert!(oso.policy_epochs().is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_shadow_load() -> oso::Result<()> {
+    use oso::{Decision, ShadowDivergence, ToPolar};
+    use std::sync::{Arc, Mutex};
+
+    common::setup();
+    let mut test = OsoTest::new();
+    test.load_str(
+        r#"allow(_actor, "read", _resource);
+           allow("alice", "write", _resource);"#,
+    );
+    test.oso.shadow_load(
+        r#"allow(_actor, "read", _resource);
+           allow("alice", "write", _resource);
+           deny(_actor, "write", "prod");"#,
+    )?;
+
+    let divergences = Arc::new(Mutex::new(vec![]));
+    // Without an observer the shadow policy isn't evaluated.
+    assert!(test.oso.is_allowed("alice", "write", "prod")?);
+
+    let seen = divergences.clone();
+    test.oso
+        .on_shadow_divergence(move |divergence: &ShadowDivergence| {
+            seen.lock().unwrap().push(divergence.clone())
+        });
+    assert_eq!(test.oso.decide("bob", "read", "prod")?, Decision::Allow);
+    assert_eq!(test.oso.decide("alice", "write", "dev")?, Decision::Allow);
+    assert!(divergences.lock().unwrap().is_empty());
+
+    assert_eq!(test.oso.decide("alice", "write", "prod")?, Decision::Allow);
+    {
+        let divergences = divergences.lock().unwrap();
+        assert_eq!(divergences.len(), 1);
+        assert_eq!(divergences[0].actor, "alice".to_polar());
+        assert_eq!(divergences[0].resource, "prod".to_polar());
+        assert_eq!(divergences[0].decision, Decision::Allow);
+        assert_eq!(divergences[0].shadow, Ok(Decision::Deny));
+    }
+
+    // Errors in the shadow policy are reported, not returned.
+    test.oso
+        .shadow_load(r#"allow(_actor, _action, resource) if resource.missing;"#)?;
+    assert!(test.oso.is_allowed("alice", "read", "prod")?);
+    assert!(divergences.lock().unwrap()[1].shadow.is_err());
+
+    test.oso.clear_shadow();
+    assert!(test.oso.is_allowed("alice", "read", "prod")?);
+    assert_eq!(divergences.lock().unwrap().len(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_integer_overflow() -> oso::Result<()> {
+    use polar_core::terms::IntegerOverflow;
+
+    common::setup();
+    let mut test = OsoTest::new();
+    test.load_str("add(x, y, x + y);");
+    let mut query = test.oso.query("add(9223372036854775807, 1, z)")?;
+    assert!(query.next().unwrap().is_err());
+
+    test.oso.set_integer_overflow(IntegerOverflow::Saturate);
+    test.qvar_one("add(9223372036854775807, 1, z)", "z", i64::MAX);
+    Ok(())
+}
+
+#[test]
+fn test_safe_navigation() -> oso::Result<()> {
+    common::setup();
+
+    #[derive(Clone, PolarClass)]
+    struct Doc {
+        owner: Option<String>,
+    }
+
+    let mut test = OsoTest::new();
+    test.oso.register_class(
+        Doc::get_polar_class_builder()
+            .add_attribute_getter("owner", |doc: &Doc| doc.owner.clone())
+            .build(),
+    )?;
+    test.load_str(r#"owner_name(doc: Doc, name) if get(doc, "owner.name", "nobody", name);"#);
+
+    test.oso.register_constant(Doc { owner: None }, "orphan")?;
+    test.qeval("orphan.?owner.?name = nil");
+    test.qvar_one("owner_name(orphan, name)", "name", "nobody".to_owned());
+    Ok(())
+}
+
+#[test]
+fn test_nil_checks() -> oso::Result<()> {
+    common::setup();
+
+    #[derive(Clone, PolarClass)]
+    struct Doc {
+        owner: Option<String>,
+    }
+
+    let mut test = OsoTest::new();
+    test.oso.register_class(
+        Doc::get_polar_class_builder()
+            .add_attribute_getter("owner", |doc: &Doc| doc.owner.clone())
+            .build(),
+    )?;
+    test.load_str(
+        "owner(doc: Doc, owner) if is_defined(doc.owner) and owner = doc.owner.unwrap();",
+    );
+
+    test.oso.register_constant(Doc { owner: None }, "orphan")?;
+    test.oso.register_constant(
+        Doc {
+            owner: Some("alice".to_owned()),
+        },
+        "owned",
+    )?;
+    test.qeval("is_nil(orphan.owner)");
+    test.qnull("is_defined(orphan.owner)");
+    test.qnull("is_nil(owned.owner)");
+    test.qvar_one("owner(owned, owner)", "owner", "alice".to_owned());
+    test.qnull("owner(orphan, _owner)");
+    Ok(())
+}
+
+#[test]
+fn test_sensitive_values() -> oso::Result<()> {
+    common::setup();
+
+    #[derive(Clone, PolarClass)]
+    struct User {
+        #[polar(attribute)]
+        name: String,
+        #[polar(sensitive)]
+        api_key: String,
+    }
+
+    #[derive(Clone, PolarClass)]
+    enum Secret {
+        #[polar(sensitive)]
+        Master,
+    }
+
+    let mut test = OsoTest::new();
+    test.oso.register_class(User::get_polar_class())?;
+    test.oso.register_class(Secret::get_polar_class())?;
+    test.oso
+        .register_sensitive_constant("hunter2", "PASSWORD")?;
+    test.load_str(
+        r#"key_error(user: User) if key = user.api_key and _x = key + 1;
+           password_error() if _x = PASSWORD + 1;
+           name_error(user: User) if _x = user.name + 1;"#,
+    );
+
+    let user = User {
+        name: "alice".to_owned(),
+        api_key: "sk-12345".to_owned(),
+    };
+    // Sensitive values still work as usual in policies...
+    test.qvar_one("PASSWORD = x", "x", "hunter2".to_owned());
+    test.qeval("x = Secret::Master and x = Secret::Master");
+
+    // ...but are redacted in error messages.
+    let error = test
+        .oso
+        .query_rule("key_error", (user.clone(),))
+        .and_then(|mut query| query.next().unwrap())
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("[REDACTED]"), "{}", error);
+    assert!(!error.contains("sk-12345"), "{}", error);
+
+    let error = test
+        .oso
+        .query_rule("password_error", ())
+        .and_then(|mut query| query.next().unwrap())
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("[REDACTED]"), "{}", error);
+    assert!(!error.contains("hunter2"), "{}", error);
+
+    let error = test
+        .oso
+        .query_rule("name_error", (user,))
+        .and_then(|mut query| query.next().unwrap())
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("alice"), "{}", error);
+    Ok(())
+}
+
+#[test]
+fn test_shared_object_graphs() -> oso::Result<()> {
+    common::setup();
+    use std::sync::{Arc, Weak};
+
+    #[derive(PolarClass)]
+    struct Org {
+        #[polar(attribute)]
+        name: String,
+        #[polar(attribute)]
+        teams: Vec<Arc<Team>>,
+    }
+
+    #[derive(PolarClass)]
+    struct Team {
+        #[polar(attribute)]
+        name: String,
+        #[polar(attribute)]
+        org: Weak<Org>,
+    }
+
+    let org = Arc::new_cyclic(|org| Org {
+        name: "acme".to_owned(),
+        teams: ["eng", "ops"]
+            .iter()
+            .map(|name| {
+                Arc::new(Team {
+                    name: name.to_string(),
+                    org: org.clone(),
+                })
+            })
+            .collect(),
+    });
+
+    let mut test = OsoTest::new();
+    test.oso.register_class(Org::get_polar_class())?;
+    test.oso.register_class(
+        Team::get_polar_class_builder()
+            .add_method("same_org", |team: &Team, org: Arc<Org>| {
+                team.org
+                    .upgrade()
+                    .is_some_and(|own| Arc::ptr_eq(&own, &org))
+            })
+            .build(),
+    )?;
+    test.oso.register_constant(org.clone(), "acme")?;
+
+    // Back-references lead to the same instance, which is equal to itself without an
+    // equality check, however many times the graph is walked.
+    test.qeval("team in acme.teams and team.org = acme");
+    test.qeval("team in acme.teams and team.org.teams = acme.teams");
+    test.qeval("team in acme.teams and other in team.org.teams and other.org.name = \"acme\"");
+    test.qvar_one(
+        "[team, *_] = acme.teams and [first, *_] = team.org.teams and first = team and name = first.name",
+        "name",
+        "eng".to_owned(),
+    );
+
+    // Shared values are passed back to the application without copies.
+    test.qeval("team in acme.teams and team.same_org(acme)");
+    let results: Vec<Arc<Org>> = test.qvar("acme = org", "org");
+    assert!(Arc::ptr_eq(&results[0], &org));
+
+    // Dropped back-references are nil.
+    let team = org.teams[0].clone();
+    drop((test, results, org));
+    let mut test = OsoTest::new();
+    test.oso.register_class(Team::get_polar_class())?;
+    test.oso.register_constant(team, "orphan")?;
+    test.qeval("is_nil(orphan.org)");
+    Ok(())
+}
+
+#[test]
+fn test_class_repr() -> oso::Result<()> {
+    common::setup();
+
+    #[derive(Clone, PolarClass)]
+    struct User {
+        name: String,
+    }
+
+    #[derive(Clone, PolarClass)]
+    struct Repo;
+
+    let mut test = OsoTest::new();
+    test.oso.register_class(
+        User::get_polar_class_builder()
+            .with_repr(|user| format!("User({})", user.name))
+            .build(),
+    )?;
+    test.oso.register_class(Repo::get_polar_class())?;
+    test.load_str("f(x) if _y = x + 1;");
+
+    let user = User {
+        name: "alice".to_owned(),
+    };
+    let error = test
+        .oso
+        .query_rule("f", (user,))
+        .and_then(|mut query| query.next().unwrap())
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("User(alice) TYPE `User`"), "{}", error);
+
+    // Classes without a repr are shown by their type.
+    let error = test
+        .oso
+        .query_rule("f", (Repo,))
+        .and_then(|mut query| query.next().unwrap())
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("TYPE `Repo`"), "{}", error);
+    Ok(())
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn test_dynamic_classes() -> oso::Result<()> {
+    common::setup();
+    use oso::{DynClassSpec, DynObject};
+    use serde_json::{json, Value};
+
+    let mut test = OsoTest::new();
+    test.oso.register_dynamic_class(
+        DynClassSpec::new("Plugin")
+            .add_attribute_getter("name", |plugin| plugin["name"].clone())
+            .add_attribute_getter("owner", |plugin| {
+                DynObject::new("Account", plugin["owner"].clone())
+            })
+            .add_method("has_scope", |plugin, args| {
+                let scopes = plugin["scopes"].as_array().cloned().unwrap_or_default();
+                Ok(args.iter().all(|scope| scopes.contains(scope)))
+            }),
+    )?;
+    test.oso.register_dynamic_class(
+        DynClassSpec::new("Account")
+            .add_attribute_getter("id", |account| account["id"].clone())
+            .set_constructor(|args| Ok(json!({ "id": args[0] }))),
+    )?;
+    test.load_str(
+        r#"allow(plugin: Plugin, action, _) if plugin.has_scope(action);
+           owned_by(plugin: Plugin, id) if plugin.owner = new Account(id);"#,
+    );
+
+    let plugin = DynObject::new(
+        "Plugin",
+        json!({"name": "search", "scopes": ["read"], "owner": {"id": 1}}),
+    );
+    assert!(test.oso.is_allowed(plugin.clone(), "read", "docs")?);
+    assert!(!test.oso.is_allowed(plugin.clone(), "write", "docs")?);
+
+    // Dynamic classes are told apart by name, though their instances share a Rust type.
+    test.oso.register_constant(plugin.clone(), "search")?;
+    test.qeval("search matches Plugin");
+    test.qnull("search matches Account");
+    test.qeval("search.owner matches Account and search.owner.id = 1");
+    test.qeval("owned_by(search, 1)");
+    test.qnull("owned_by(search, 2)");
+    test.qvar_one("x = search.name", "x", "search".to_owned());
+
+    // Instances convert back to their objects, or to their values.
+    let results: Vec<DynObject> = test.qvar("x = search", "x");
+    assert_eq!(results, vec![plugin]);
+    let results: Vec<Value> = test.qvar("x = search.owner", "x");
+    assert_eq!(results, vec![json!({"id": 1})]);
+
+    let error = test.query_err("search.missing = 1");
+    assert!(error.contains("missing"), "{}", error);
+    Ok(())
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn test_virtual_host() -> oso::Result<()> {
+    common::setup();
+    use oso::{OsoError, VirtualHost};
+    use serde_json::json;
+
+    let host = VirtualHost::from_json(&json!({
+        "classes": {
+            "User": {"attributes": ["email"], "methods": {"has_role": {"default": false}}},
+            "Org": {"methods": {"members": {}}}
+        },
+        "instances": {
+            "alice": {
+                "class": "User",
+                "fields": {"name": "alice", "orgs": [{"$ref": "acme"}]},
+                "calls": {"has_role": [{"args": ["admin", {"$ref": "acme"}], "result": true}]}
+            },
+            "bob": {"class": "User", "fields": {"name": "bob"}},
+            "acme": {
+                "class": "Org",
+                "calls": {"members": [{"args": [], "result": [{"$ref": "alice"}, {"$ref": "bob"}]}]}
+            }
+        }
+    }))?;
+    let mut test = OsoTest::new();
+    test.oso.register_virtual_host(&host)?;
+    test.load_str(
+        r#"allow(user: User, "admin", org: Org) if user.has_role("admin", org);
+           allow(user: User, "read", org: Org) if org in user.orgs;"#,
+    );
+
+    test.qeval(r#"allow(alice, "admin", acme)"#);
+    test.qnull(r#"allow(bob, "admin", acme)"#);
+    test.qnull(r#"allow(bob, "read", acme)"#);
+    test.qvar_one("alice.email = x", "x", Option::<String>::None);
+    let names: Vec<String> = test.qvar("user in acme.members() and x = user.name", "x");
+    assert_eq!(names, vec!["alice".to_owned(), "bob".to_owned()]);
+    assert!(test.oso.is_allowed(
+        host.instance("alice").unwrap(),
+        "read",
+        host.instance("acme").unwrap()
+    )?);
+
+    // Calls without a result or a default are errors.
+    let error = test.query_err("bob.orgs = nil and acme.members(1) = x");
+    assert!(error.contains("no result for `members`"), "{}", error);
+
+    let invalid = VirtualHost::from_json(&json!({
+        "classes": {"User": {}},
+        "instances": {"alice": {"class": "User", "fields": {"org": {"$ref": "acme"}}}}
+    }));
+    assert!(matches!(invalid, Err(OsoError::InvalidVirtualHost { .. })));
+    Ok(())
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn test_policy_simulation() -> oso::Result<()> {
+    common::setup();
+    use oso::{Decision, DecisionMatrix, Fixture, OsoError};
+    use serde_json::json;
+
+    let dataset = json!({
+        "classes": {"User": {}, "Repo": {}},
+        "instances": {
+            "alice": {"class": "User", "fields": {"roles": [{"role": "admin", "repo": {"$ref": "oso"}}]}},
+            "bob": {"class": "User", "fields": {"roles": [{"role": "reader", "repo": {"$ref": "oso"}}]}},
+            "oso": {"class": "Repo", "fields": {"archived": false}},
+            "old": {"class": "Repo", "fields": {"archived": true}}
+        },
+        "actors": ["alice", "bob"],
+        "actions": ["read", "write"],
+        "resources": ["oso", "old"]
+    });
+    let fixture = Fixture::from_json(&dataset)?;
+
+    let mut v1 = fixture.oso()?;
+    v1.load_str(
+        r#"allow(user: User, "read", repo: Repo) if role in user.roles and role.repo = repo;
+           allow(user: User, "write", repo: Repo) if
+               role in user.roles and role.repo = repo and role.role = "admin";"#,
+    )?;
+    let mut v2 = fixture.oso()?;
+    v2.load_str(
+        r#"has_role(user: User, name: String, repo: Repo) if
+               role in user.roles and role.role = name and role.repo = repo;
+           allow(user: User, "read", repo: Repo) if has_role(user, _, repo);
+           allow(user: User, "write", repo: Repo) if has_role(user, "admin", repo);
+           deny(_: User, "write", repo: Repo) if repo.archived;"#,
+    )?;
+    let before = fixture.simulate(&v1)?;
+    let after = fixture.simulate(&v2)?;
+
+    assert_eq!(
+        before.to_string(),
+        "alice read old: not_applicable
+alice read oso: allow
+alice write old: not_applicable
+alice write oso: allow
+bob read old: not_applicable
+bob read oso: allow
+bob write old: not_applicable
+bob write oso: not_applicable
+"
+    );
+    let changes = before.diff(&after);
+    let changes = changes.iter().map(ToString::to_string).collect::<Vec<_>>();
+    assert_eq!(
+        changes,
+        vec![
+            "alice write old: not_applicable -> deny",
+            "bob write old: not_applicable -> deny",
+        ]
+    );
+
+    // Matrices written to JSON are read back unchanged, e.g., to diff against a saved baseline.
+    let json = before.to_json();
+    assert_eq!(json["bob"]["read"]["oso"], json!("allow"));
+    assert_eq!(DecisionMatrix::from_json(&json)?, before);
+    assert!(DecisionMatrix::from_json(&json!({"bob": {"read": {"oso": "maybe"}}})).is_err());
+    let mut partial = json;
+    partial["alice"].as_object_mut().unwrap().remove("write");
+    let changes = DecisionMatrix::from_json(&partial)?.diff(&before);
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[0].before, None);
+    assert_eq!(changes[0].after, Some(Decision::NotApplicable));
+
+    let mut invalid = dataset;
+    invalid["resources"] = json!(["missing"]);
+    assert!(matches!(
+        Fixture::from_json(&invalid),
+        Err(OsoError::InvalidFixture { .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_generic_classes() -> oso::Result<()> {
+    common::setup();
+
+    #[derive(Clone, PolarClass)]
+    struct Repo {
+        #[polar(attribute)]
+        name: String,
+    }
+
+    #[derive(Clone, PolarClass)]
+    struct Org;
+
+    #[derive(Clone, PolarClass)]
+    struct Paginated<T> {
+        #[polar(attribute)]
+        items: Vec<T>,
+        #[polar(attribute)]
+        page: i64,
+    }
+
+    #[derive(Clone, PolarClass)]
+    #[polar(class_name = "OrgPage")]
+    struct Page<T> {
+        #[polar(attribute)]
+        items: Vec<T>,
+    }
+
+    let mut test = OsoTest::new();
+    test.oso
+        .register_class(Paginated::<Repo>::get_polar_class())?;
+    test.oso
+        .register_class(Paginated::<Org>::get_polar_class())?;
+    test.oso.register_class(Page::<Org>::get_polar_class())?;
+    test.load_str(
+        r#"kind(_: Paginated_Repo, "repos");
+           kind(_: Paginated_Org, "orgs");
+           kind(_: OrgPage, "org page");
+           first_name(page: Paginated_Repo, name) if [first, *_] = page.items and name = first.name;"#,
+    );
+
+    let repos = Paginated {
+        items: vec![Repo {
+            name: "oso".to_owned(),
+        }],
+        page: 1,
+    };
+    let orgs = Paginated {
+        items: vec![Org],
+        page: 1,
+    };
+    test.oso.register_constant(repos, "repos")?;
+    test.oso.register_constant(orgs, "orgs")?;
+    test.oso
+        .register_constant(Page { items: vec![Org] }, "org_page")?;
+    test.qvar_one("kind(repos, kind)", "kind", "repos".to_owned());
+    test.qvar_one("kind(orgs, kind)", "kind", "orgs".to_owned());
+    test.qvar_one("kind(org_page, kind)", "kind", "org page".to_owned());
+    test.qvar_one("first_name(repos, name)", "name", "oso".to_owned());
+    test.qnull("first_name(orgs, _)");
+    test.qeval("orgs.page = 1");
+    Ok(())
+}
+
+#[test]
+fn test_query_builder() -> oso::Result<()> {
+    common::setup();
+    use oso::{PolarValue, QueryBuilder, RuleSignature, ToPolar};
+
+    #[derive(Clone, PolarClass)]
+    struct User {
+        #[polar(attribute)]
+        name: String,
+    }
+
+    let mut test = OsoTest::new();
+    test.oso.register_class(User::get_polar_class())?;
+    test.load_str(
+        r#"allow(user: User, "read", doc) if doc in ["a", "b"] and user.name = "alice";
+           allow(_: User, "read", "public");"#,
+    );
+
+    let alice = User {
+        name: "alice".to_owned(),
+    };
+    let call = QueryBuilder::rule("allow")
+        .arg(alice.clone())
+        .arg("read")
+        .var("doc")
+        .build();
+    assert_eq!(call.name(), "allow");
+    assert_eq!(call.args().len(), 3);
+    let docs = test
+        .oso
+        .query_call(call)?
+        .map(|result| result?.get_typed::<String>("doc"))
+        .collect::<oso::Result<Vec<_>>>()?;
+    assert_eq!(docs, vec!["a", "b", "public"]);
+
+    // Arguments are values, not Polar source.
+    let injected = QueryBuilder::rule("allow")
+        .arg(alice.clone())
+        .arg(r#"read", _) or (1 = 1"#)
+        .var("doc")
+        .build();
+    assert_eq!(test.oso.query_call(injected)?.count(), 0);
+
+    const ALLOW: RuleSignature<3> = RuleSignature::new("allow");
+    let call = ALLOW.call([
+        alice.to_polar(),
+        "read".to_polar(),
+        PolarValue::Variable("doc".to_owned()),
+    ]);
+    assert_eq!(test.oso.query_call(call)?.count(), 3);
+    Ok(())
+}
+
+#[test]
+fn test_batch_methods() -> oso::Result<()> {
+    use oso::{PolarValue, ToPolar};
+    use std::sync::{Arc, Mutex};
+
+    common::setup();
+
+    #[derive(Clone, PolarClass)]
+    struct Repo {
+        #[polar(attribute)]
+        name: String,
+        members: Vec<String>,
+    }
+
+    #[derive(Clone, PolarClass)]
+    struct Org {
+        members: Vec<String>,
+    }
+
+    let batches = Arc::new(Mutex::new(vec![]));
+    let mut test = OsoTest::new();
+    let seen = batches.clone();
+    test.oso.register_class(
+        Repo::get_polar_class_builder()
+            .add_batch_method("has_member", move |repos: &[&Repo], (user,): (String,)| {
+                seen.lock().unwrap().push(repos.len());
+                repos
+                    .iter()
+                    .map(|repo| repo.members.contains(&user))
+                    .collect()
+            })
+            .build(),
+    )?;
+    test.oso.register_class(
+        Org::get_polar_class_builder()
+            .add_method("has_member", |org: &Org, user: String| {
+                org.members.contains(&user)
+            })
+            .build(),
+    )?;
+    test.load_str(
+        r#"member_of(user, resources, resource) if
+               resource in resources and resource.has_member(user);
+           member(user, repo) if repo.has_member(user);"#,
+    );
+
+    let repo = |name: &str, members: &[&str]| Repo {
+        name: name.to_owned(),
+        members: members.iter().map(|m| m.to_string()).collect(),
+    };
+    let repos = vec![
+        repo("api", &["alice"]),
+        repo("web", &["bob"]),
+        repo("docs", &["alice", "bob"]),
+    ];
+    let names = test
+        .oso
+        .query_rule(
+            "member_of",
+            (
+                "alice",
+                repos.clone(),
+                PolarValue::Variable("repo".to_owned()),
+            ),
+        )?
+        .map(|result| result?.get_typed::<Repo>("repo").map(|repo| repo.name))
+        .collect::<oso::Result<Vec<_>>>()?;
+    assert_eq!(names, vec!["api", "docs"]);
+    assert_eq!(*batches.lock().unwrap(), vec![3]);
+
+    // Instances of classes without a batch method are called one at a time.
+    batches.lock().unwrap().clear();
+    let org = Org {
+        members: vec!["alice".to_owned()],
+    };
+    let resources = vec![
+        repos[0].clone().to_polar(),
+        org.to_polar(),
+        repos[1].clone().to_polar(),
+    ];
+    let results = test
+        .oso
+        .query_rule(
+            "member_of",
+            ("alice", resources, PolarValue::Variable("r".to_owned())),
+        )?
+        .count();
+    assert_eq!(results, 2);
+    assert_eq!(*batches.lock().unwrap(), vec![2]);
+
+    // Calls outside of lists are batches of one.
+    batches.lock().unwrap().clear();
+    assert!(test
+        .oso
+        .query_rule("member", ("bob", repos[1].clone()))?
+        .next()
+        .is_some());
+    assert_eq!(*batches.lock().unwrap(), vec![1]);
+    Ok(())
+}
+
+#[test]
+fn test_cached_methods() -> oso::Result<()> {
+    use oso::PolarValue;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    common::setup();
+
+    #[derive(Clone, PolarClass)]
+    struct User {
+        id: i64,
+    }
+
+    let cached_calls = Arc::new(AtomicUsize::new(0));
+    let uncached_calls = Arc::new(AtomicUsize::new(0));
+    let mut test = OsoTest::new();
+    let (cached, uncached) = (cached_calls.clone(), uncached_calls.clone());
+    test.oso.register_class(
+        User::get_polar_class_builder()
+            .add_method("level", move |user: &User, org: String| {
+                cached.fetch_add(1, Ordering::SeqCst);
+                user.id * org.len() as i64
+            })
+            .with_cached_method("level")
+            .add_method("uncached_level", move |user: &User, org: String| {
+                uncached.fetch_add(1, Ordering::SeqCst);
+                user.id * org.len() as i64
+            })
+            .build(),
+    )?;
+    test.load_str(
+        r#"f(user, org, x) if user.level(org) = 1 and x = 1;
+           f(user, org, x) if user.level(org) = 3 and x = 2;
+           f(user, org, x) if user.level(org) > 2 and x = 3;
+           g(user, org, x) if user.uncached_level(org) = 1 and x = 1;
+           g(user, org, x) if user.uncached_level(org) = 3 and x = 2;
+           g(user, org, x) if user.uncached_level(org) > 2 and x = 3;"#,
+    );
+
+    let user = User { id: 1 };
+    let results = test
+        .oso
+        .query_rule(
+            "f",
+            (user.clone(), "abc", PolarValue::Variable("x".to_owned())),
+        )?
+        .count();
+    assert_eq!(results, 2);
+    assert_eq!(cached_calls.load(Ordering::SeqCst), 1);
+    let results = test
+        .oso
+        .query_rule(
+            "g",
+            (user.clone(), "abc", PolarValue::Variable("x".to_owned())),
+        )?
+        .count();
+    assert_eq!(results, 2);
+    assert_eq!(uncached_calls.load(Ordering::SeqCst), 3);
+
+    // Results aren't reused by other queries.
+    let results = test
+        .oso
+        .query_rule("f", (user, "a", PolarValue::Variable("x".to_owned())))?
+        .count();
+    assert_eq!(results, 1);
+    assert_eq!(cached_calls.load(Ordering::SeqCst), 2);
+    Ok(())
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn test_claims() -> oso::Result<()> {
+    common::setup();
+    use oso::{Claims, DateTime};
+    use serde_json::json;
+
+    let mut test = OsoTest::new();
+    test.oso.register_class(Claims::get_polar_class())?;
+    test.oso.register_class(DateTime::get_polar_class())?;
+    test.load_str(
+        r#"allow(claims: Claims, action, org) if
+               not claims.is_expired() and
+               claims.has_audience("api") and
+               claims.has_scope(action) and
+               claims.get("https://example.com/org.id").unwrap() = org;"#,
+    );
+
+    let claims = |exp: i64| -> Claims {
+        let claims = json!({
+            "sub": "alice",
+            "aud": ["api", "web"],
+            "scp": ["read"],
+            "iat": 1_600_000_000,
+            "exp": exp,
+            "https://example.com/org": { "id": "acme" },
+        });
+        claims.as_object().cloned().unwrap().into()
+    };
+    let now = DateTime::now().timestamp();
+    let valid = claims(now + 60);
+    assert!(test.oso.is_allowed(valid.clone(), "read", "acme")?);
+    assert!(!test.oso.is_allowed(valid.clone(), "write", "acme")?);
+    assert!(!test.oso.is_allowed(valid.clone(), "read", "initech")?);
+    assert!(!test.oso.is_allowed(claims(now - 60), "read", "acme")?);
+
+    test.oso.register_constant(valid, "claims")?;
+    test.qvar_one("x = claims.sub.unwrap()", "x", "alice".to_owned());
+    test.qvar_one("x = claims.scopes", "x", vec!["read".to_owned()]);
+    test.qvar_one(
+        "x = claims.issued_at.unwrap().timestamp()",
+        "x",
+        1_600_000_000,
+    );
+    test.qeval("claims.issued_at.unwrap().is_before(DateTime.now())");
+    test.qeval("claims.expires_at.unwrap() = new DateTime(claims.get(\"exp\").unwrap())");
+    test.qeval("is_nil(claims.iss) and is_nil(claims.get(\"https://example.com/org.name\"))");
+
+    // Times out of range are missing.
+    let mut far = json!({"exp": 1e300}).as_object().cloned().unwrap();
+    assert!(Claims::from(far.clone()).expires_at().is_none());
+    far.insert("exp".to_owned(), json!(i64::MAX));
+    assert!(!Claims::from(far).is_expired());
+    assert!(test
+        .query_err("x = new DateTime(9223372036854775807)")
+        .contains("out of range"));
+    Ok(())
+}
+
+#[test]
+fn test_feature_flags() -> oso::Result<()> {
+    common::setup();
+    use oso::{FeatureFlags, PolarValue};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::time::Duration;
+
+    let kill_switch = Arc::new(AtomicBool::new(false));
+    let provider = {
+        let kill_switch = kill_switch.clone();
+        move || {
+            let mut flags = hashmap! {
+                "max_seats".to_owned() => PolarValue::Integer(10),
+            };
+            if kill_switch.load(Ordering::SeqCst) {
+                flags.insert("kill_switch".to_owned(), PolarValue::Boolean(true));
+            }
+            Ok(flags)
+        }
+    };
+    let (changes, changed) = mpsc::channel();
+    let mut flags = FeatureFlags::new(provider)
+        .with_name("Env")
+        .on_change(move |names| changes.send(names.to_vec()).unwrap());
+
+    let mut test = OsoTest::new();
+    assert_eq!(flags.refresh(&mut test.oso)?, vec!["max_seats"]);
+    assert_eq!(changed.recv().unwrap(), vec!["max_seats"]);
+    test.load_str(
+        r#"allow(_, "invite", seats) if seats < Env.max_seats and not Env.kill_switch = true;"#,
+    );
+    assert!(test.oso.is_allowed("alice", "invite", 5)?);
+    assert!(!test.oso.is_allowed("alice", "invite", 10)?);
+
+    // Refreshes without changes don't notify.
+    assert!(flags.refresh(&mut test.oso)?.is_empty());
+    assert!(changed.try_recv().is_err());
+
+    // Refreshes in the background update policies that use the flags.
+    let refresher = flags.spawn(test.oso.clone(), Duration::from_millis(1));
+    kill_switch.store(true, Ordering::SeqCst);
+    assert_eq!(
+        changed.recv_timeout(Duration::from_secs(10)).unwrap(),
+        vec!["kill_switch"]
+    );
+    refresher.stop();
+    assert!(!test.oso.is_allowed("alice", "invite", 5)?);
+    Ok(())
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn test_replay() -> oso::Result<()> {
+    common::setup();
+    use oso::{Decision, DecisionRecord};
+    use serde_json::json;
+    use std::time::{Duration, SystemTime};
+
+    let mut test = OsoTest::new();
+    test.oso.set_max_policy_epochs(10);
+    test.load_str(r#"allow(actor, "read", doc) if doc.owner = actor;"#);
+    let record = |doc: std::collections::HashMap<&str, &str>| {
+        let record = test.oso.decide_and_record("alice", "read", doc).unwrap();
+        DecisionRecord::from_json(&record.to_json().unwrap()).unwrap()
+    };
+    let allowed = record(hashmap! { "owner" => "alice", "status" => "draft" });
+    assert_eq!(allowed.decision, Decision::Allow);
+    assert_eq!(
+        allowed.policy_fingerprint,
+        Some(test.oso.policy_fingerprint())
+    );
+    let not_allowed = record(hashmap! { "owner" => "bob", "status" => "published" });
+    let decided_at = allowed.decided_at;
+
+    std::thread::sleep(Duration::from_millis(5));
+    test.clear_rules();
+    test.load_str(
+        r#"allow(actor, "read", doc) if doc.owner = actor;
+           deny(_actor, "read", doc) if doc.status = "draft";"#,
+    );
+    let replay = test.oso.replay(&allowed)?;
+    assert!(replay.differs());
+    assert_eq!(replay.replayed, Decision::Deny);
+    assert_eq!(replay.policy_changed(), Some(true));
+    assert!(!test.oso.replay(&not_allowed)?.differs());
+
+    // Replaying against the policy at the time of the decision reproduces it.
+    let replay = test.oso.replay_at(&allowed, decided_at)?;
+    assert!(!replay.differs());
+    assert_eq!(replay.policy_changed(), Some(false));
+    let error = test
+        .oso
+        .replay_at(&allowed, SystemTime::now() - Duration::from_secs(60))
+        .unwrap_err();
+    assert!(
+        error.to_string().starts_with("No policy was recorded"),
+        "{}",
+        error
+    );
+
+    let error =
+        DecisionRecord::from_json(&json!({"actor": "alice", "decision": "maybe"})).unwrap_err();
+    assert!(error.to_string().contains("`decision`"), "{}", error);
+    Ok(())
+}
+
+#[test]
+fn test_compiled_check() -> oso::Result<()> {
+    common::setup();
+
+    #[derive(Clone, PolarClass)]
+    struct User {
+        #[polar(attribute)]
+        id: i64,
+        #[polar(attribute)]
+        role: String,
+    }
+
+    #[derive(Clone, PolarClass)]
+    struct Repo {
+        #[polar(attribute)]
+        owner_id: i64,
+        #[polar(attribute)]
+        public: bool,
+    }
+
+    let mut test = OsoTest::new();
+    test.oso.register_class(User::get_polar_class())?;
+    test.oso.register_class(Repo::get_polar_class())?;
+    test.load_str(
+        r#"allow(user: User, "read", repo: Repo) if repo.owner_id = user.id;
+           allow(_user: User, "read", repo: Repo) if repo.public;
+           allow(user: User, "read", _repo: Repo) if user.role = "admin" and senior(user);
+           senior(user: User) if user.id > 3;
+           deny(user: User, "read", repo: Repo) if user.id = 7 and repo.owner_id != 7;"#,
+    );
+
+    // The compiled check decides like the policy, deny rules included.
+    let can_read = test.oso.compile_check::<User, Repo, _>("read")?;
+    for id in 0..9 {
+        for role in ["admin", "member"] {
+            for owner_id in [1, 4, 7] {
+                for public in [true, false] {
+                    let user = User {
+                        id,
+                        role: role.to_owned(),
+                    };
+                    let repo = Repo { owner_id, public };
+                    let expected = test.oso.decide(user.clone(), "read", repo.clone())?;
+                    assert_eq!(can_read.check(user, repo)?, expected.is_allowed());
+                }
+            }
+        }
+    }
+    assert_eq!(can_read.policy_fingerprint(), test.oso.policy_fingerprint());
+
+    let can_write = test.oso.compile_check::<User, Repo, _>("write")?;
+    let admin = User {
+        id: 5,
+        role: "admin".to_owned(),
+    };
+    let repo = Repo {
+        owner_id: 5,
+        public: true,
+    };
+    assert!(!can_write.check(admin, repo)?);
+
+    // Both types must be registered.
+    #[derive(Clone, PolarClass)]
+    struct Team;
+    assert!(test.oso.compile_check::<User, Team, _>("read").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_type_aliases() -> oso::Result<()> {
+    common::setup();
+
+    #[derive(Clone, PolarClass)]
+    struct Repo;
+    #[derive(Clone, PolarClass)]
+    struct Org;
+    #[derive(Clone, PolarClass)]
+    struct Issue {
+        #[polar(attribute)]
+        open: bool,
+    }
+
+    let mut test = OsoTest::new();
+    test.oso.register_class(Repo::get_polar_class())?;
+    test.oso.register_class(Org::get_polar_class())?;
+    test.oso.register_class(Issue::get_polar_class())?;
+    test.oso.register_constant(Repo, "repo")?;
+    // Aliases may be used before they're declared, and may include other aliases.
+    test.load_str(
+        r#"allow(_actor, "read", _resource: Readable);
+           allow(_actor, "list", resource) if resource matches Anything;
+           allow(_actor, "close", _issue: Anything{open: true});
+           type Readable = Repo | Org;
+           type Anything = Readable | Issue;"#,
+    );
+
+    let issue = |open| Issue { open };
+    assert!(test.oso.is_allowed("alice", "read", Repo)?);
+    assert!(test.oso.is_allowed("alice", "read", Org)?);
+    assert!(!test.oso.is_allowed("alice", "read", issue(true))?);
+    assert!(test.oso.is_allowed("alice", "list", Repo)?);
+    assert!(test.oso.is_allowed("alice", "list", issue(false))?);
+    assert!(!test.oso.is_allowed("alice", "list", "repo")?);
+    assert!(test.oso.is_allowed("alice", "close", issue(true))?);
+    assert!(!test.oso.is_allowed("alice", "close", issue(false))?);
+
+    // Queries expand aliases too.
+    let mut query = test.oso.query("repo matches Readable")?;
+    assert!(query.next().transpose()?.is_some());
+
+    let load_err = |policy: &str| {
+        let mut oso = oso::Oso::new();
+        oso.register_class(Repo::get_polar_class()).unwrap();
+        oso.load_str(policy).unwrap_err().to_string()
+    };
+    let err = load_err("type Resource = Repo;");
+    assert!(err.contains("built-in union"), "{}", err);
+    let err = load_err("type Repo = Repo;");
+    assert!(err.contains("registered class"), "{}", err);
+    let err = load_err("type A = Repo; type A = Repo;");
+    assert!(err.contains("only be declared once"), "{}", err);
+    let err = load_err("type A = B | Repo; type B = A; f(_: A);");
+    assert!(err.contains("member of itself"), "{}", err);
+    Ok(())
+}
+
+#[test]
+fn test_specializer_predicates() -> oso::Result<()> {
+    common::setup();
+
+    #[derive(Clone, PolarClass)]
+    struct User {
+        #[polar(attribute)]
+        email: String,
+        #[polar(attribute)]
+        verified: bool,
+    }
+
+    let mut test = OsoTest::new();
+    test.oso.register_class(User::get_polar_class())?;
+    test.oso
+        .register_specializer("verified_email", |user: User| user.verified);
+    test.oso
+        .register_specializer("staff", |email: String| email.ends_with("@example.com"));
+    test.load_str(
+        r#"allow(_actor: @verified_email, "read", _resource);
+           allow(actor: User, "write", _resource) if actor.email matches @staff;"#,
+    );
+
+    let user = |email: &str, verified| User {
+        email: email.to_owned(),
+        verified,
+    };
+    assert!(test
+        .oso
+        .is_allowed(user("alice@example.com", true), "read", "doc")?);
+    assert!(!test
+        .oso
+        .is_allowed(user("alice@example.com", false), "read", "doc")?);
+    // Arguments that the predicate can't take don't match it.
+    assert!(!test.oso.is_allowed("alice@example.com", "read", "doc")?);
+    assert!(test
+        .oso
+        .is_allowed(user("alice@example.com", false), "write", "doc")?);
+    assert!(!test
+        .oso
+        .is_allowed(user("bob@example.org", true), "write", "doc")?);
+
+    // Predicates that aren't registered are warned about when loading, and are errors when
+    // used.
+    let mut oso = oso::Oso::new();
+    oso.load_str(r#"allow(_actor: @unknown, "read", _resource);"#)?;
+    let err = oso.is_allowed("alice", "read", "doc").unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("Unregistered specializer predicate: @unknown"),
+        "{}",
+        err
+    );
+    Ok(())
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn test_load_ast_json() -> oso::Result<()> {
+    common::setup();
+
+    #[derive(Clone, PolarClass)]
+    struct User {
+        #[polar(attribute)]
+        name: String,
+    }
+    #[derive(Clone, PolarClass)]
+    struct Repo;
+
+    let policy = r#"actor User {}
+                    resource Repo { permissions = ["read"]; roles = ["reader"]; "read" if "reader"; }
+                    has_role(user: User, "reader", _: Repo) if user.name = "alice";
+                    allow(actor, action, resource) if has_permission(actor, action, resource);"#;
+    let json = oso::parse_to_json(policy)?;
+    let lines: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(lines.as_array().map(Vec::len), Some(4));
+    assert!(lines[1].get("ResourceBlock").is_some());
+
+    let mut test = OsoTest::new();
+    test.oso.register_class(User::get_polar_class())?;
+    test.oso.register_class(Repo::get_polar_class())?;
+    test.oso.load_ast_json(&json)?;
+    let user = |name: &str| User {
+        name: name.to_owned(),
+    };
+    assert!(test.oso.is_allowed(user("alice"), "read", Repo)?);
+    assert!(!test.oso.is_allowed(user("bob"), "read", Repo)?);
+
+    let err = test.oso.load_ast_json("[{\"Rule\": 1}]").unwrap_err();
+    assert!(err.to_string().contains("Serialization error"), "{}", err);
+    Ok(())
+}
+
+#[test]
+fn test_rule_annotations() -> oso::Result<()> {
+    common::setup();
+    use oso::{AnnotatedRule, Decision};
+
+    let mut test = OsoTest::new();
+    test.load_str(
+        r#"@severity("high") @owner("secteam")
+           deny(_actor, "delete", "prod");
+           @owner("platform")
+           allow(actor, action, resource) if can(actor, action, resource);
+           @owner("docs") @doc("Anyone may read docs.")
+           can(_actor, "read", "docs");
+           can(_actor, "read", "prod");"#,
+    );
+    let annotated = |rule: &str, annotations: &[(&str, &str)]| AnnotatedRule {
+        rule: rule.to_owned(),
+        annotations: annotations
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+    };
+
+    let explanation = test.oso.explain("alice", "delete", "prod")?;
+    assert_eq!(explanation.decision, Decision::Deny);
+    assert_eq!(
+        explanation.annotated_rules,
+        vec![annotated(
+            "deny",
+            &[("severity", "high"), ("owner", "secteam")]
+        )]
+    );
+    // Rules without annotations other than `@doc` are left out.
+    let explanation = test.oso.explain("alice", "read", "docs")?;
+    assert_eq!(explanation.decision, Decision::Allow);
+    assert_eq!(
+        explanation.annotated_rules,
+        vec![
+            annotated("allow", &[("owner", "platform")]),
+            annotated("can", &[("owner", "docs")]),
+        ]
+    );
+    let explanation = test.oso.explain("alice", "read", "prod")?;
+    assert_eq!(
+        explanation.annotated_rules,
+        vec![annotated("allow", &[("owner", "platform")])]
+    );
+    let explanation = test.oso.explain("alice", "write", "docs")?;
+    assert_eq!(explanation.decision, Decision::NotApplicable);
+    assert!(explanation.annotated_rules.is_empty());
+
+    let record = test.oso.decide_and_record("alice", "delete", "prod")?;
+    assert_eq!(record.annotated_rules[0].annotations["owner"], "secteam");
+    #[cfg(feature = "serde_json")]
+    {
+        let json = record.to_json()?;
+        assert_eq!(
+            json["annotated_rules"][0]["annotations"]["severity"],
+            serde_json::json!("high")
+        );
+        let read = oso::DecisionRecord::from_json(&json)?;
+        assert_eq!(read.annotated_rules, record.annotated_rules);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_load_options() -> oso::Result<()> {
+    use oso::{DuplicateClauses, LoadOptions, OsoError};
+
+    common::setup();
+
+    // Strict loads fail on warnings, and load nothing.
+    let ambiguous = r#"allow(x, "read", "doc") if x = "alice" and x = "bob" or x = "carol";"#;
+    let mut oso = oso::Oso::new();
+    let err = oso
+        .load_str_with(ambiguous, &LoadOptions::new().strict(true))
+        .unwrap_err();
+    assert!(matches!(err, OsoError::LoadWarnings { ref warnings } if warnings.len() == 1));
+    assert!(oso.is_allowed("carol", "read", "doc").is_err());
+    oso.load_str_with(ambiguous, &LoadOptions::new())?;
+    assert!(oso.is_allowed("carol", "read", "doc")?);
+
+    // Inline queries may be skipped.
+    let mut oso = oso::Oso::new();
+    let policy = r#"allow("alice", "read", "doc"); ?= allow("bob", "read", "doc");"#;
+    assert!(oso.load_str_with(policy, &LoadOptions::new()).is_err());
+    let mut oso = oso::Oso::new();
+    oso.load_str_with(policy, &LoadOptions::new().run_inline_queries(false))?;
+    assert!(oso.is_allowed("alice", "read", "doc")?);
+
+    // Tagged sources are named in errors.
+    let mut oso = oso::Oso::new();
+    let err = oso
+        .load_str_with("allow(", &LoadOptions::new().tag("policy.polar"))
+        .unwrap_err();
+    assert!(err.to_string().contains("policy.polar"));
+
+    // Duplicated clauses may be skipped.
+    let mut oso = oso::Oso::new();
+    let policy = "f(x) if x = 1; f(y) if y = 1;";
+    oso.load_str_with(policy, &LoadOptions::new().dedup(DuplicateClauses::Skip))?;
+    assert_eq!(oso.query("f(x)")?.count(), 1);
+
+    // Policies may be loaded for a tenant.
+    let mut oso = oso::Oso::new();
+    oso.load_str(r#"allow("admin", _action, _resource);"#)?;
+    oso.load_str_with(
+        r#"allow("alice", "read", "doc");"#,
+        &LoadOptions::new().scope("acme"),
+    )?;
+    assert!(oso.is_allowed_for_tenant("acme", "alice", "read", "doc")?);
+    assert!(!oso.is_allowed("alice", "read", "doc")?);
+    Ok(())
+}
+
+#[test]
+fn test_occurs_check() -> oso::Result<()> {
+    common::setup();
+    let mut test = OsoTest::new();
+    test.load_str("f(x, [x]);");
+    test.qeval("f(y, y)");
+
+    test.oso.set_occurs_check(true);
+    let err = test.oso.query("f(y, y)")?.next().unwrap().unwrap_err();
+    assert!(err.to_string().starts_with("Occurs check failed"));
+
+    let mut query = test.oso.query("f(y, y)")?;
+    query.set_occurs_check(false);
+    assert!(query.next().transpose()?.is_some());
     Ok(())
 }
@@ -0,0 +1,231 @@
+#![cfg(feature = "server")]
+mod common;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use oso::server::Server;
+use oso::{Oso, PolarClass, ToPolar};
+
+fn start(server: Server) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || server.serve(listener));
+    addr
+}
+
+/// Send `body` to `path` over `stream`, returning the status & body of the response.
+fn post(stream: &mut BufReader<TcpStream>, path: &str, body: Value) -> (u16, Value) {
+    post_with_headers(stream, path, "", body)
+}
+
+/// Send `body` to `path` over `stream` with `headers`, each ending in `\r\n`, returning the
+/// status & body of the response.
+fn post_with_headers(
+    stream: &mut BufReader<TcpStream>,
+    path: &str,
+    headers: &str,
+    body: Value,
+) -> (u16, Value) {
+    let body = body.to_string();
+    write!(
+        stream.get_mut(),
+        "POST {} HTTP/1.1\r\n{}Content-Length: {}\r\n\r\n{}",
+        path,
+        headers,
+        body.len(),
+        body
+    )
+    .unwrap();
+
+    let mut status = String::new();
+    stream.read_line(&mut status).unwrap();
+    let status = status.split_whitespace().nth(1).unwrap().parse().unwrap();
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        stream.read_line(&mut line).unwrap();
+        match line.trim_end().split_once(": ") {
+            Some(("Content-Length", value)) => length = value.parse().unwrap(),
+            None => break,
+            _ => (),
+        }
+    }
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[test]
+fn test_server() {
+    common::setup();
+
+    let mut oso = Oso::new();
+    oso.load_str(
+        r#"allow(actor: Dictionary, "read", repo: Dictionary) if actor.id = repo.owner;
+           allow("admin", action, _repo) if action in ["read", "delete"];"#,
+    )
+    .unwrap();
+    let addr = start(Server::new(oso).allow_reload(true));
+    // Requests share a connection.
+    let mut stream = BufReader::new(TcpStream::connect(addr).unwrap());
+
+    let request =
+        json!({"actor": {"id": "alice"}, "action": "read", "resource": {"owner": "alice"}});
+    assert_eq!(
+        post(&mut stream, "/authorize", request),
+        (200, json!({"allowed": true}))
+    );
+    let request = json!({"actor": {"id": "bob"}, "action": "read", "resource": {"owner": "alice"}});
+    assert_eq!(
+        post(&mut stream, "/authorize", request),
+        (200, json!({"allowed": false}))
+    );
+    let request = json!({"actor": "admin", "resource": {"owner": "alice"}});
+    assert_eq!(
+        post(&mut stream, "/authorized_actions", request),
+        (200, json!({"actions": ["read", "delete"]}))
+    );
+
+    let (status, _) = post(&mut stream, "/authorize", json!({"actor": "admin"}));
+    assert_eq!(status, 400);
+    let (status, _) = post(&mut stream, "/nope", json!({}));
+    assert_eq!(status, 404);
+
+    let reload = json!({"policy": "allow(_actor, _action, _resource);"});
+    assert_eq!(post(&mut stream, "/reload", reload), (200, json!({})));
+    let request = json!({"actor": "bob", "action": "write", "resource": 1});
+    assert_eq!(
+        post(&mut stream, "/authorize", request),
+        (200, json!({"allowed": true}))
+    );
+
+    // A failed reload leaves the previous policy loaded.
+    let (status, _) = post(&mut stream, "/reload", json!({"policy": "allow("}));
+    assert_eq!(status, 400);
+    let request = json!({"actor": "bob", "action": "write", "resource": 1});
+    assert_eq!(
+        post(&mut stream, "/authorize", request),
+        (200, json!({"allowed": true}))
+    );
+
+    // Files may only be reloaded if the server allows it.
+    let reload = json!({"files": ["tests/test_file.polar"]});
+    let (status, _) = post(&mut stream, "/reload", reload);
+    assert_eq!(status, 400);
+
+    // Policies may only be reloaded if the server allows it.
+    let mut stream = BufReader::new(TcpStream::connect(start(Server::new(Oso::new()))).unwrap());
+    let reload = json!({"policy": "allow(_actor, _action, _resource);"});
+    let (status, _) = post(&mut stream, "/reload", reload);
+    assert_eq!(status, 403);
+}
+
+#[test]
+fn test_server_limits() {
+    common::setup();
+
+    let addr = start(Server::new(Oso::new()).with_max_connections(1));
+    let mut first = BufReader::new(TcpStream::connect(addr).unwrap());
+    let (status, _) = post(&mut first, "/nope", json!({}));
+    assert_eq!(status, 404);
+
+    // The second connection is closed without a response.
+    let mut second = TcpStream::connect(addr).unwrap();
+    let mut response = vec![];
+    second.read_to_end(&mut response).unwrap();
+    assert!(response.is_empty());
+    drop(second);
+
+    // Headers that are too long close the connection.
+    write!(
+        first.get_mut(),
+        "POST /nope HTTP/1.1\r\nX-Padding: {}\r\n\r\n",
+        "x".repeat(16 * 1024)
+    )
+    .unwrap();
+    let mut response = vec![];
+    let _ = first.read_to_end(&mut response);
+    assert!(response.is_empty());
+}
+
+#[derive(Clone, PolarClass)]
+struct Clock;
+
+#[test]
+fn test_server_deadline() {
+    common::setup();
+
+    let mut oso = Oso::new();
+    oso.register_class(
+        Clock::get_polar_class_builder()
+            .add_class_method("sleep", || {
+                std::thread::sleep(Duration::from_millis(200));
+                true
+            })
+            .build(),
+    )
+    .unwrap();
+    oso.load_str(
+        r#"allow(_actor, "read", _resource);
+           allow(_actor, "wait", _resource) if Clock.sleep();"#,
+    )
+    .unwrap();
+    let mut stream = BufReader::new(TcpStream::connect(start(Server::new(oso))).unwrap());
+
+    let read = json!({"actor": "alice", "action": "read", "resource": 1});
+    let wait = json!({"actor": "alice", "action": "wait", "resource": 1});
+    assert_eq!(
+        post_with_headers(
+            &mut stream,
+            "/authorize",
+            "Oso-Deadline-Ms: 1000\r\n",
+            read.clone()
+        ),
+        (200, json!({"allowed": true}))
+    );
+    let (status, _) = post_with_headers(&mut stream, "/authorize", "Oso-Deadline-Ms: 0\r\n", read);
+    assert_eq!(status, 504);
+
+    // Queries that run past the deadline fail.
+    let (status, _) = post_with_headers(
+        &mut stream,
+        "/authorize",
+        "Oso-Deadline-Ms: 50\r\n",
+        wait.clone(),
+    );
+    assert_eq!(status, 504);
+    let (status, _) = post_with_headers(
+        &mut stream,
+        "/authorize",
+        "Oso-Deadline-Ms: soon\r\n",
+        wait.clone(),
+    );
+    assert_eq!(status, 400);
+    assert_eq!(
+        post(&mut stream, "/authorize", wait),
+        (200, json!({"allowed": true}))
+    );
+}
+
+#[test]
+fn test_server_resolvers() {
+    common::setup();
+
+    let mut oso = Oso::new();
+    oso.load_str(r#"allow(actor: String, "read", 1) if actor = "alice";"#)
+        .unwrap();
+    let server = Server::new(oso)
+        .with_actor_resolver(|actor: Value| Ok(actor["name"].as_str().unwrap().to_polar()))
+        .with_resource_resolver(|resource: Value| Ok(resource["id"].as_i64().unwrap().to_polar()));
+    let mut stream = BufReader::new(TcpStream::connect(start(server)).unwrap());
+
+    let request = json!({"actor": {"name": "alice"}, "action": "read", "resource": {"id": 1}});
+    assert_eq!(
+        post(&mut stream, "/authorize", request),
+        (200, json!({"allowed": true}))
+    );
+}
@@ -0,0 +1,174 @@
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use oso::{Oso, PolarClass};
+
+#[derive(Clone, PolarClass)]
+struct User {
+    #[polar(attribute)]
+    name: String,
+    lookups: Arc<AtomicUsize>,
+}
+
+impl User {
+    fn groups(&self) -> Vec<String> {
+        self.lookups.fetch_add(1, Ordering::SeqCst);
+        match self.name.as_str() {
+            "alice" => vec!["eng".to_owned(), "ops".to_owned()],
+            _ => vec![],
+        }
+    }
+}
+
+fn oso() -> oso::Result<Oso> {
+    let mut oso = Oso::new();
+    oso.register_class(
+        User::get_polar_class_builder()
+            .add_method("groups", User::groups)
+            .build(),
+    )?;
+    oso.load_str(
+        r#"allow(user: User, "read", group) if in_group(user, group);
+           allow(_user: User, "read", "public");
+           in_group(user: User, group) if group in user.groups();"#,
+    )?;
+    Ok(oso)
+}
+
+fn user(name: &str) -> User {
+    User {
+        name: name.to_owned(),
+        lookups: Arc::new(AtomicUsize::new(0)),
+    }
+}
+
+#[test]
+fn test_actor_session() -> oso::Result<()> {
+    common::setup();
+    let oso = oso()?;
+
+    let session = oso.for_actor(user("alice"));
+    assert!(session.is_allowed("read", "eng")?);
+    assert!(session.is_allowed("read", "public")?);
+    assert!(!session.is_allowed("read", "sales")?);
+    assert!(!session.is_allowed("write", "eng")?);
+    assert!(oso.for_actor(user("bob")).is_allowed("read", "public")?);
+    assert!(!oso.for_actor(user("bob")).is_allowed("read", "eng")?);
+    Ok(())
+}
+
+#[test]
+fn test_actor_session_precompute() -> oso::Result<()> {
+    common::setup();
+    let oso = oso()?;
+
+    let alice = user("alice");
+    let mut session = oso.for_actor(alice.clone());
+    assert_eq!(session.precompute("in_group", 2)?, 2);
+    assert_eq!(alice.lookups.load(Ordering::SeqCst), 1);
+
+    // Precomputed results answer the query without looking up groups again.
+    assert!(session.is_allowed("read", "eng")?);
+    assert!(session.is_allowed("read", "ops")?);
+    assert_eq!(alice.lookups.load(Ordering::SeqCst), 1);
+    assert!(!session.is_allowed("read", "sales")?);
+
+    // Each precomputed result is returned once, instead of the rule being evaluated as well.
+    let group = oso::PolarValue::Variable("group".to_owned());
+    let results = session.query_rule("in_group", (group,));
+    assert_eq!(results.count(), 2);
+    assert_eq!(alice.lookups.load(Ordering::SeqCst), 1);
+
+    // Precomputed results are private to the session.
+    assert!(!oso.for_actor(user("bob")).is_allowed("read", "eng")?);
+    assert!(oso
+        .query_rule("in_group", (user("bob"), "eng"))?
+        .next()
+        .is_none());
+    Ok(())
+}
+
+#[test]
+fn test_actor_session_precompute_unbound() -> oso::Result<()> {
+    common::setup();
+    let mut oso = Oso::new();
+    oso.register_class(User::get_polar_class())?;
+    oso.load_str("in_group(_user: User, _group);")?;
+
+    let err = oso
+        .for_actor(user("alice"))
+        .precompute("in_group", 2)
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "cannot precompute `in_group`: argument 2 of a result is unbound"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_actor_session_attribute_provider() -> oso::Result<()> {
+    common::setup();
+    use oso::{FromPolar, OsoError, PolarValue, ToPolar};
+
+    let mut oso = Oso::new();
+    oso.register_class(User::get_polar_class())?;
+    oso.load_str(
+        r#"allow(_user: User, "read", "public");
+           allow(user: User, "write", group) if group in user.admin_of;
+           allow(user: User, "delete", _) if user.missing;
+           allow(user: User, "audit", _) if user.name = "bob" and user.unknown;"#,
+    )?;
+    let lookups = Arc::new(AtomicUsize::new(0));
+    let provider = {
+        let lookups = lookups.clone();
+        move |actor: &PolarValue, name: &str| {
+            lookups.fetch_add(1, Ordering::SeqCst);
+            let user = User::from_polar(actor.clone())?;
+            match name {
+                "admin_of" if user.name == "alice" => Ok(Some(vec!["eng"].to_polar())),
+                "admin_of" => Ok(Some(Vec::<String>::new().to_polar())),
+                "missing" => Err(OsoError::Custom {
+                    message: "directory unavailable".to_owned(),
+                }),
+                _ => Ok(None),
+            }
+        }
+    };
+
+    // Attributes are only looked up when a rule needs them, and then once per session.
+    let session = oso
+        .for_actor(user("alice"))
+        .with_attribute_provider(provider.clone());
+    assert!(session.is_allowed("read", "public")?);
+    assert_eq!(lookups.load(Ordering::SeqCst), 0);
+    assert!(session.is_allowed("write", "eng")?);
+    assert!(!session.is_allowed("write", "ops")?);
+    assert_eq!(lookups.load(Ordering::SeqCst), 1);
+
+    // The class's own attributes aren't looked up, and attributes the provider doesn't have
+    // are still errors.
+    let session = oso.for_actor(user("bob")).with_attribute_provider(provider);
+    assert!(!session.is_allowed("write", "eng")?);
+    assert_eq!(lookups.load(Ordering::SeqCst), 2);
+    let error = session.is_allowed("audit", "eng").unwrap_err();
+    assert!(error.to_string().contains("unknown"), "{}", error);
+    assert_eq!(lookups.load(Ordering::SeqCst), 3);
+
+    // Errors from the provider are returned from the query.
+    let error = session.is_allowed("delete", "eng").unwrap_err();
+    assert!(
+        error.to_string().contains("directory unavailable"),
+        "{}",
+        error
+    );
+
+    // Without a provider, the attribute doesn't exist.
+    assert!(oso
+        .for_actor(user("alice"))
+        .is_allowed("write", "eng")
+        .is_err());
+    Ok(())
+}
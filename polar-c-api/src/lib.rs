@@ -4,7 +4,7 @@
 use polar_core::error::PolarError;
 pub use polar_core::polar::Polar;
 pub use polar_core::query::Query;
-use polar_core::{error, terms};
+use polar_core::{error, parser, sources, terms};
 
 use std::ffi::{c_void, CStr, CString};
 use std::os::raw::c_char;
@@ -110,6 +110,33 @@ pub extern "C" fn polar_load(
     })
 }
 
+/// Load a policy from the JSON array of its lines, as written by `polar_parse_to_json`. See
+/// `polar_core::parser::Line` for the schema.
+#[no_mangle]
+pub extern "C" fn polar_load_ast_json(
+    polar_ptr: *mut Polar,
+    lines: *const c_char,
+) -> *mut CResult<c_void> {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        from_json(lines).and_then(|lines| polar.load_ast(lines))
+    })
+}
+
+/// Parse the policy `src` to the JSON array of its lines.
+#[no_mangle]
+pub extern "C" fn polar_parse_to_json(src: *const c_char) -> *mut CResult<c_char> {
+    ffi_try!({
+        let src = unsafe { ffi_string!(src) };
+        parser::parse_lines(sources::Source::new(src.as_ref())).map(|lines| {
+            let lines_json = serde_json::to_string(&lines).unwrap();
+            CString::new(lines_json)
+                .expect("JSON should not contain any 0 bytes")
+                .into_raw()
+        })
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn polar_clear_rules(polar_ptr: *mut Polar) -> *mut CResult<c_void> {
     ffi_try!({
@@ -304,6 +331,32 @@ pub extern "C" fn polar_query_source_info(query_ptr: *mut Query) -> *mut CResult
     })
 }
 
+#[no_mangle]
+pub extern "C" fn polar_query_suspend(query_ptr: *mut Query) -> *mut CResult<c_char> {
+    ffi_try!({
+        let query = unsafe { ffi_ref!(query_ptr) };
+        query.suspend().map(|suspended| {
+            let suspended_json = serde_json::to_string(&suspended).unwrap();
+            CString::new(suspended_json)
+                .expect("JSON should not contain any 0 bytes")
+                .into_raw()
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn polar_resume_query(
+    polar_ptr: *mut Polar,
+    suspended: *const c_char,
+) -> *mut CResult<Query> {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        from_json(suspended)
+            .and_then(|suspended| polar.resume_query(suspended))
+            .map(|query| box_ptr!(query))
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn polar_bind(
     query_ptr: *mut Query,
@@ -1,7 +1,11 @@
 /// Manage binding state in the VM.
 ///
 /// Bindings associate variables in the VM with constraints or values.
+use std::cell::Cell;
 use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
 
 use crate::{
     error::{PolarResult, RuntimeError},
@@ -10,7 +14,7 @@ use crate::{
     vm::Goal,
 };
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Binding(pub Symbol, pub Term);
 
 // TODO This is only public for debugger and inverter.
@@ -21,8 +25,143 @@ pub type Bindings = HashMap<Symbol, Term>;
 pub type Bsp = Bsps;
 pub type FollowerId = usize;
 
+/// Counts of binding operations, shared by a binding manager and the snapshots taken of it, for
+/// measuring the cost of backtracking.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct BindingStats {
+    /// Bindings recorded.
+    pub bindings: u64,
+    /// Times the bindings were reset to an earlier state.
+    pub backtracks: u64,
+    /// Snapshots taken for child VMs. Snapshots share the bindings instead of copying them.
+    pub snapshots: u64,
+    /// Bindings copied, when backtracking into bindings shared with a snapshot.
+    pub copied: u64,
+}
+
+/// Bindings frozen when a snapshot was taken, shared by the binding managers that took or were
+/// made from the snapshot.
+#[derive(Debug)]
+struct Chunk {
+    bindings: BindingStack,
+    /// Position in the trail of the first binding in the chunk.
+    start: usize,
+    /// The chunk before this one.
+    parent: Option<Rc<Chunk>>,
+}
+
+impl Chunk {
+    fn end(&self) -> usize {
+        self.start + self.bindings.len()
+    }
+}
+
+/// The bindings of a binding manager in the order they were made. Bindings from before the last
+/// snapshot are frozen in chunks that snapshots share; later ones are in `tail`.
+#[derive(Clone, Debug, Default)]
+struct Trail {
+    /// The newest frozen chunk.
+    frozen: Option<Rc<Chunk>>,
+    tail: BindingStack,
+}
+
+impl Trail {
+    fn frozen_len(&self) -> usize {
+        self.frozen.as_ref().map_or(0, |chunk| chunk.end())
+    }
+
+    fn len(&self) -> usize {
+        self.frozen_len() + self.tail.len()
+    }
+
+    fn push(&mut self, binding: Binding) {
+        self.tail.push(binding);
+    }
+
+    /// Move the tail into a new frozen chunk, so that clones share it instead of copying it.
+    fn freeze(&mut self) {
+        if !self.tail.is_empty() {
+            let start = self.frozen_len();
+            self.frozen = Some(Rc::new(Chunk {
+                bindings: std::mem::take(&mut self.tail),
+                start,
+                parent: self.frozen.take(),
+            }));
+        }
+    }
+
+    /// Drop the bindings from position `len` on. Returns the number of bindings copied out of a
+    /// chunk shared with another trail.
+    fn truncate(&mut self, len: usize) -> usize {
+        let frozen_len = self.frozen_len();
+        if len >= frozen_len {
+            self.tail.truncate(len - frozen_len);
+            return 0;
+        }
+        self.tail.clear();
+        while let Some(chunk) = self.frozen.take() {
+            if chunk.end() <= len {
+                self.frozen = Some(chunk);
+                break;
+            } else if chunk.start < len {
+                // Keep the start of the chunk as the tail, copying it only if it's shared.
+                self.frozen = chunk.parent.clone();
+                let keep = len - chunk.start;
+                return match Rc::try_unwrap(chunk) {
+                    Ok(mut chunk) => {
+                        chunk.bindings.truncate(keep);
+                        self.tail = chunk.bindings;
+                        0
+                    }
+                    Err(chunk) => {
+                        self.tail = chunk.bindings[..keep].to_vec();
+                        keep
+                    }
+                };
+            } else {
+                self.frozen = chunk.parent.clone();
+            }
+        }
+        0
+    }
+
+    fn chunks(&self) -> impl Iterator<Item = &Chunk> {
+        std::iter::successors(self.frozen.as_deref(), |chunk| chunk.parent.as_deref())
+    }
+
+    /// The bindings with their positions, newest first.
+    fn iter_rev(&self) -> impl Iterator<Item = (usize, &Binding)> {
+        let frozen_len = self.frozen_len();
+        let tail = self
+            .tail
+            .iter()
+            .enumerate()
+            .rev()
+            .map(move |(i, binding)| (frozen_len + i, binding));
+        let frozen = self.chunks().flat_map(|chunk| {
+            chunk
+                .bindings
+                .iter()
+                .enumerate()
+                .rev()
+                .map(move |(i, binding)| (chunk.start + i, binding))
+        });
+        tail.chain(frozen)
+    }
+
+    /// The bindings, oldest first.
+    fn iter(&self) -> impl Iterator<Item = &Binding> {
+        let mut chunks = self.chunks().collect::<Vec<_>>();
+        chunks.reverse();
+        chunks
+            .into_iter()
+            .flat_map(|chunk| chunk.bindings.iter())
+            .chain(self.tail.iter())
+    }
+}
+
 /// Bsps represents bsps of a binding manager and its followers as a tree.
-#[derive(Clone, Debug, Default, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Bsps {
     /// Index into `bindings` array
     bindings_index: usize,
@@ -145,9 +284,10 @@ enum BindingManagerVariableState<'a> {
 /// The constraints or value associated with a variable is retrieved with `variable_state`.
 #[derive(Clone, Debug, Default)]
 pub struct BindingManager {
-    bindings: BindingStack,
+    bindings: Trail,
     followers: HashMap<FollowerId, BindingManager>,
     next_follower_id: FollowerId,
+    stats: Rc<Cell<BindingStats>>,
 }
 
 // Public interface.
@@ -164,6 +304,7 @@ impl BindingManager {
         match partial.ground(var, val.clone()) {
             None => Err(RuntimeError::IncompatibleBindings {
                 msg: "Grounding failed A".into(),
+                term: None,
             }
             .into()),
             Some(grounded) => {
@@ -219,6 +360,7 @@ impl BindingManager {
                 Bound(_) => {
                     return Err(RuntimeError::IncompatibleBindings {
                         msg: format!("Cannot rebind {:?}", var),
+                        term: None,
                     }
                     .into())
                 }
@@ -286,6 +428,7 @@ impl BindingManager {
                     None => {
                         return Err(RuntimeError::IncompatibleBindings {
                             msg: "Grounding failed B".into(),
+                            term: None,
                         }
                         .into())
                     }
@@ -312,7 +455,47 @@ impl BindingManager {
         })
         .unwrap();
 
-        self.bindings.truncate(to.bindings_index)
+        let copied = self.bindings.truncate(to.bindings_index) as u64;
+        self.count(|stats| {
+            stats.backtracks += 1;
+            stats.copied += copied;
+        });
+    }
+
+    /// Return a binding manager with the same bindings as this one. The bindings are shared
+    /// between the two rather than copied, until either backtracks into them.
+    pub fn snapshot(&mut self) -> BindingManager {
+        self.bindings.freeze();
+        self.count(|stats| stats.snapshots += 1);
+        self.clone()
+    }
+
+    /// The bindings, oldest first, to restore with `from_bindings` after suspending a query.
+    /// Binding managers with followers can't be restored.
+    pub fn suspend(&self) -> PolarResult<BindingStack> {
+        if !self.followers.is_empty() {
+            return Err(RuntimeError::Suspension {
+                msg: "the query is evaluating a negation or a partial".to_owned(),
+            }
+            .into());
+        }
+        Ok(self.bindings.iter().cloned().collect())
+    }
+
+    /// A binding manager with `bindings`, as returned by `suspend`.
+    pub fn from_bindings(bindings: BindingStack) -> Self {
+        Self {
+            bindings: Trail {
+                frozen: None,
+                tail: bindings,
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Counts of the binding operations of this binding manager and its snapshots.
+    pub fn stats(&self) -> BindingStats {
+        self.stats.get()
     }
 
     // *** Binding Inspection ***
@@ -322,6 +505,18 @@ impl BindingManager {
         Derefer::new(self).fold_term(term.clone())
     }
 
+    /// Whether `variable`, or a variable bound to it, occurs in `term` once its variables are
+    /// dereferenced, so that binding `variable` to `term` would make a cyclic term.
+    pub fn occurs(&self, variable: &Symbol, term: &Term) -> bool {
+        let aliases = match self._variable_state(variable) {
+            BindingManagerVariableState::Cycle(cycle) => cycle,
+            _ => vec![variable.clone()],
+        };
+        let mut vars = HashSet::new();
+        self.deep_deref(term).variables(&mut vars);
+        aliases.iter().any(|alias| vars.contains(alias))
+    }
+
     /// Get constraints on variable `variable`. If the variable is in a cycle,
     /// the cycle is expressed as a partial.
     pub fn get_constraints(&self, variable: &Symbol) -> Operation {
@@ -386,7 +581,7 @@ impl BindingManager {
 
     pub fn bindings_after(&self, include_temps: bool, after: &Bsp) -> Bindings {
         let mut bindings = HashMap::new();
-        for Binding(var, value) in &self.bindings[after.bindings_index..] {
+        for Binding(var, value) in self.bindings.iter().skip(after.bindings_index) {
             if !include_temps && var.is_temporary_var() {
                 continue;
             }
@@ -407,8 +602,8 @@ impl BindingManager {
     }
 
     /// Get the bindings stack *for debugging purposes only*.
-    pub fn bindings_debug(&self) -> &BindingStack {
-        &self.bindings
+    pub fn bindings_debug(&self) -> BindingStack {
+        self.bindings.iter().cloned().collect()
     }
 
     // *** Followers ***
@@ -510,6 +705,7 @@ impl BindingManager {
                 } else {
                     Err(RuntimeError::IncompatibleBindings {
                         msg: format!("{} and {} are both bound", left, right),
+                        term: None,
                     }
                     .into())
                 }
@@ -546,6 +742,13 @@ impl BindingManager {
 
     fn add_binding(&mut self, var: &Symbol, val: Term) {
         self.bindings.push(Binding(var.clone(), val));
+        self.count(|stats| stats.bindings += 1);
+    }
+
+    fn count(&self, f: impl FnOnce(&mut BindingStats)) {
+        let mut stats = self.stats.get();
+        f(&mut stats);
+        self.stats.set(stats);
     }
 
     fn lookup(&self, var: &Symbol) -> Option<Term> {
@@ -558,11 +761,11 @@ impl BindingManager {
     /// Look up a variable in the bindings stack and return
     /// a reference to its value if it's bound.
     fn value(&self, variable: &Symbol, bsp: usize) -> Option<&Term> {
-        self.bindings[..bsp]
-            .iter()
-            .rev()
-            .find(|Binding(var, _)| var == variable)
-            .map(|Binding(_, val)| val)
+        self.bindings
+            .iter_rev()
+            .skip_while(|(i, _)| *i >= bsp)
+            .find(|(_, Binding(var, _))| var == variable)
+            .map(|(_, Binding(_, val))| val)
     }
 
     fn _variable_state(&self, variable: &Symbol) -> BindingManagerVariableState {
@@ -897,4 +1100,59 @@ mod test {
             VariableState::Unbound
         ));
     }
+
+    #[test]
+    fn test_snapshots() {
+        let mut b1 = BindingManager::new();
+        b1.bind(&sym!("x"), term!(1)).unwrap();
+        b1.bind(&sym!("y"), term!(2)).unwrap();
+        let bsp = b1.bsp();
+
+        // Snapshots share the bindings made before them.
+        let mut b2 = b1.snapshot();
+        b2.bind(&sym!("z"), term!(3)).unwrap();
+        b1.bind(&sym!("w"), term!(4)).unwrap();
+        assert_eq!(
+            b2.variable_state(&sym!("x")),
+            VariableState::Bound(term!(1))
+        );
+        assert_eq!(b2.variable_state(&sym!("w")), VariableState::Unbound);
+        assert_eq!(b1.variable_state(&sym!("z")), VariableState::Unbound);
+        assert_eq!(
+            b1.variable_state_at_point(&sym!("w"), &bsp),
+            VariableState::Unbound
+        );
+        assert_eq!(b1.stats().copied, 0);
+
+        // Backtracking into shared bindings copies the ones kept.
+        let mut bsp = bsp;
+        bsp.bindings_index = 1;
+        b1.backtrack(&bsp);
+        assert_eq!(
+            b1.variable_state(&sym!("x")),
+            VariableState::Bound(term!(1))
+        );
+        assert_eq!(b1.variable_state(&sym!("y")), VariableState::Unbound);
+        assert_eq!(
+            b2.variable_state(&sym!("y")),
+            VariableState::Bound(term!(2))
+        );
+        assert_eq!(b1.bindings(true).len(), 1);
+        assert_eq!(b2.bindings(true).len(), 3);
+
+        let stats = b2.stats();
+        assert_eq!(stats.bindings, 4);
+        assert_eq!(stats.snapshots, 1);
+        assert_eq!(stats.backtracks, 1);
+        assert_eq!(stats.copied, 1);
+
+        // Unshared bindings are truncated in place.
+        drop(b2);
+        let mut b3 = b1.snapshot();
+        b3.bind(&sym!("v"), term!(5)).unwrap();
+        drop(b3);
+        b1.backtrack(&Bsp::default());
+        assert_eq!(b1.stats().copied, 1);
+        assert!(b1.bindings(true).is_empty());
+    }
 }
@@ -0,0 +1,382 @@
+//! Builtin predicates over lists and dictionaries, evaluated by the VM without calling out to
+//! the host.
+//!
+//! Each builtin's last argument is its result:
+//!
+//! - `append(list, element, result)`
+//! - `concat(list, other, result)`
+//! - `slice(list, start, end, result)`: elements from `start` up to but not including `end`.
+//!   Negative indices count from the end of the list, and indices are clamped to its bounds.
+//! - `keys(dict, result)` and `values(dict, result)`, both ordered by key.
+//! - `get_or(dict, key, default, result)`: the value of `key`, or `default` if it's missing.
+//! - `merge(dict, other, result)`: the fields of both, preferring those of `other`.
+//!
+//! Conversions between strings and numbers take the same form:
+//!
+//! - `to_string(value, result)`: a string, number, or boolean as a string.
+//! - `to_int(value, result)`: an integer, a float without a fractional part, or a string of an
+//!   integer as an integer.
+//! - `to_float(value, result)`: a number, or a string of one, as a float.
+//! - `parse_datetime(string, result)`: an RFC 3339 timestamp like `"2021-06-01T12:00:00Z"`, or a
+//!   date like `"2021-06-01"` at midnight UTC, as the number of seconds since the Unix epoch.
+//!   The result is an integer unless the timestamp has fractional seconds.
+//!
+//! Conversions of strings that don't parse fail, so `to_int(id, n) or n = 0` can supply a
+//! default. Conversions of values of any other type are type errors.
+//!
+//! `get(value, path, default, result)` looks up each field of `path`, either a list of field
+//! names or a string of them separated by dots like `"owner.id"`, in turn. If a value along the
+//! way is `nil`, or a dictionary without the field, the result is `default`. `value.?field` is
+//! the same lookup of a single field with `nil` as the default; without a registered `nil`
+//! constant, it fails instead.
+//!
+//! `is_nil(value)` succeeds if `value` is the registered `nil` constant, or a host value the
+//! host considers equal to it, like `None` in Rust. `is_defined(value)` succeeds if `value` is
+//! bound and not `nil`. An unbound variable is neither.
+//!
+//! Higher-order builtins take a lambda, e.g., `|x| x.owner = actor`, which is applied to each
+//! element of a list. Each succeeds at most once:
+//!
+//! - `filter(list, lambda, result)`: the elements for which the lambda succeeds.
+//! - `any(list, lambda)`: succeeds if the lambda succeeds for some element.
+//! - `all(list, lambda)`: succeeds if the lambda succeeds for every element.
+//!
+//! Rules defined by the policy take precedence over builtins with the same name.
+use std::collections::{BTreeMap, HashMap};
+
+use super::folder::{fold_term, Folder};
+use super::terms::*;
+
+const BUILTINS: &[(&str, usize)] = &[
+    ("append", 3),
+    ("concat", 3),
+    ("slice", 4),
+    ("keys", 2),
+    ("values", 2),
+    ("get_or", 4),
+    ("merge", 3),
+    ("to_string", 2),
+    ("to_int", 2),
+    ("to_float", 2),
+    ("parse_datetime", 2),
+];
+
+const HIGHER_ORDER_BUILTINS: &[(&str, usize)] = &[("filter", 3), ("any", 2), ("all", 2)];
+
+fn matches_any(builtins: &[(&str, usize)], call: &Call) -> bool {
+    builtins
+        .iter()
+        .any(|(name, arity)| call.name == *name && call.args.len() == *arity)
+}
+
+/// Return true if `call` is a call to a builtin predicate with the right number of arguments.
+pub(crate) fn is_builtin(call: &Call) -> bool {
+    matches_any(BUILTINS, call)
+        || is_higher_order(call)
+        || is_path_lookup(call)
+        || is_nil_check(call)
+}
+
+/// Return true if `call` is a call to `is_nil/1` or `is_defined/1`.
+pub(crate) fn is_nil_check(call: &Call) -> bool {
+    matches!(call.name.as_str(), "is_nil" | "is_defined") && call.args.len() == 1
+}
+
+/// Return true if `call` is a call to `get/4`.
+pub(crate) fn is_path_lookup(call: &Call) -> bool {
+    call.name == "get" && call.args.len() == 4
+}
+
+/// Return the fields of the `path` argument to `get`.
+pub(crate) fn path_fields(path: &Term) -> Result<TermList, String> {
+    let field = |name: &str| path.clone_with_value(Value::String(name.to_owned()));
+    match path.value() {
+        Value::String(path) => Ok(path.split('.').map(field).collect()),
+        Value::List(fields) if !has_rest_var(fields) => fields
+            .iter()
+            .map(|f| match f.value() {
+                Value::String(name) => Ok(field(name)),
+                _ => Err(format!("get expects field names to be strings, got: {}", f)),
+            })
+            .collect(),
+        _ => Err(format!("get expects a string or list path, got: {}", path)),
+    }
+}
+
+/// Return true if `call` is a call to a builtin that takes a lambda.
+pub(crate) fn is_higher_order(call: &Call) -> bool {
+    matches_any(HIGHER_ORDER_BUILTINS, call)
+}
+
+/// Build the goal for the higher-order builtin `name` from the applications of its lambda to
+/// each element of `list`, in order. `result` is the result argument of `filter`, and
+/// `gensym` makes fresh variables.
+pub(crate) fn higher_order_body(
+    term: &Term,
+    name: &Symbol,
+    list: &[Term],
+    applications: TermList,
+    result: Option<&Term>,
+    mut gensym: impl FnMut() -> Symbol,
+) -> Term {
+    let op =
+        |operator, args| term.clone_with_value(Value::Expression(Operation { operator, args }));
+    match (name.as_str(), result) {
+        ("any", _) => op(Operator::Or, applications),
+        ("filter", Some(result)) => {
+            // With the result as `rest_0`, element `i` is kept by unifying `rest_{i-1}` with
+            // `[element_i, *rest_i]`, or skipped by unifying it with `rest_i`.
+            let mut steps = vec![];
+            let mut rest = result.clone();
+            for (element, application) in list.iter().zip(applications) {
+                let next = gensym();
+                let kept = term.clone_with_value(Value::List(vec![
+                    element.clone(),
+                    term.clone_with_value(Value::RestVariable(next.clone())),
+                ]));
+                let next = term.clone_with_value(Value::Variable(next));
+                let keep = op(
+                    Operator::And,
+                    vec![
+                        application.clone(),
+                        op(Operator::Unify, vec![rest.clone(), kept]),
+                    ],
+                );
+                let skip = op(
+                    Operator::And,
+                    vec![
+                        op(Operator::Not, vec![application]),
+                        op(Operator::Unify, vec![rest, next.clone()]),
+                    ],
+                );
+                steps.push(op(Operator::Or, vec![keep, skip]));
+                rest = next;
+            }
+            let empty = term.clone_with_value(Value::List(vec![]));
+            steps.push(op(Operator::Unify, vec![rest, empty]));
+            op(Operator::And, steps)
+        }
+        _ => op(Operator::And, applications),
+    }
+}
+
+/// Apply `lambda` by replacing the variables in `substitutions` throughout its body.
+pub(crate) fn apply(lambda: &Lambda, substitutions: HashMap<Symbol, Term>) -> Term {
+    struct Substituter(HashMap<Symbol, Term>);
+
+    impl Folder for Substituter {
+        fn fold_term(&mut self, t: Term) -> Term {
+            match t.value() {
+                Value::Variable(v) if self.0.contains_key(v) => self.0[v].clone(),
+                _ => fold_term(t, self),
+            }
+        }
+    }
+
+    Substituter(substitutions).fold_term(lambda.body.clone())
+}
+
+/// Compute the result of the builtin called by `term` from the (dereferenced) arguments
+/// preceding the result argument, or describe why the arguments are invalid. The result is
+/// `None` if a conversion's string doesn't parse.
+pub(crate) fn evaluate(term: &Term, name: &Symbol, args: &[Term]) -> Result<Option<Term>, String> {
+    let value = match (name.as_str(), args) {
+        ("append", [list, element]) => {
+            let mut list = list_arg(name, list)?.clone();
+            list.push(element.clone());
+            Value::List(list)
+        }
+        ("concat", [list, other]) => {
+            let mut list = list_arg(name, list)?.clone();
+            list.extend(list_arg(name, other)?.iter().cloned());
+            Value::List(list)
+        }
+        ("slice", [list, start, end]) => {
+            let list = list_arg(name, list)?;
+            let index = |arg: &Term| -> Result<usize, String> {
+                let len = list.len() as i64;
+                let i = match arg.value() {
+                    Value::Number(Numeric::Integer(i)) => *i,
+                    _ => return Err(format!("{} expects integer indices, got: {}", name, arg)),
+                };
+                Ok((if i < 0 { len.saturating_add(i) } else { i }).clamp(0, len) as usize)
+            };
+            let (start, end) = (index(start)?, index(end)?);
+            Value::List(list[start..end.max(start)].to_vec())
+        }
+        ("keys", [dict]) => Value::List(
+            dict_arg(name, dict)?
+                .keys()
+                .map(|key| term.clone_with_value(Value::String(key.to_string())))
+                .collect(),
+        ),
+        ("values", [dict]) => Value::List(dict_arg(name, dict)?.values().cloned().collect()),
+        ("get_or", [dict, key, default]) => {
+            let fields = dict_arg(name, dict)?;
+            let key = match key.value() {
+                Value::String(key) => Symbol::new(key),
+                _ => return Err(format!("{} expects a string key, got: {}", name, key)),
+            };
+            return Ok(Some(fields.get(&key).unwrap_or(default).clone()));
+        }
+        ("merge", [dict, other]) => {
+            let mut fields = dict_arg(name, dict)?.clone();
+            fields.extend(dict_arg(name, other)?.clone());
+            Value::Dictionary(Dictionary { fields })
+        }
+        ("to_string", [value]) => Value::String(match value.value() {
+            Value::String(s) => s.clone(),
+            Value::Number(n) => n.to_string(),
+            Value::Boolean(b) => b.to_string(),
+            _ => {
+                return Err(format!(
+                    "{} expects a string, number, or boolean, got: {}",
+                    name, value
+                ))
+            }
+        }),
+        ("to_int", [value]) => match value.value() {
+            Value::Number(Numeric::Integer(_)) => value.value().clone(),
+            Value::Number(Numeric::Float(f)) if f.fract() == 0.0 && f.abs() < i64::MAX as f64 => {
+                Value::Number(Numeric::Integer(*f as i64))
+            }
+            Value::String(s) => match s.parse() {
+                Ok(i) => Value::Number(Numeric::Integer(i)),
+                Err(_) => return Ok(None),
+            },
+            _ => {
+                return Err(format!(
+                    "{} expects an integer, a whole float, or a string, got: {}",
+                    name, value
+                ))
+            }
+        },
+        ("to_float", [value]) => match value.value() {
+            Value::Number(Numeric::Integer(i)) => Value::Number(Numeric::Float(*i as f64)),
+            Value::Number(Numeric::Float(_)) => value.value().clone(),
+            Value::String(s) => match s.parse() {
+                Ok(f) => Value::Number(Numeric::Float(f)),
+                Err(_) => return Ok(None),
+            },
+            _ => {
+                return Err(format!(
+                    "{} expects a number or a string, got: {}",
+                    name, value
+                ))
+            }
+        },
+        ("parse_datetime", [value]) => match value.value() {
+            Value::String(s) => match parse_datetime(s) {
+                Some(seconds) => Value::Number(seconds),
+                None => return Ok(None),
+            },
+            _ => return Err(format!("{} expects a string, got: {}", name, value)),
+        },
+        _ => return Err(format!("no builtin {}/{}", name, args.len() + 1)),
+    };
+    Ok(Some(term.clone_with_value(value)))
+}
+
+/// Parse an RFC 3339 timestamp, or a date, into seconds since the Unix epoch.
+fn parse_datetime(s: &str) -> Option<Numeric> {
+    fn number(digits: &str) -> Option<i64> {
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
+    }
+
+    let (date, time) = match s.find(['T', 't', ' ']) {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    };
+    let mut parts = date.splitn(3, '-');
+    let (year, month, day) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(y), Some(m), Some(d)) if y.len() == 4 && m.len() == 2 && d.len() == 2 => {
+            (number(y)?, number(m)?, number(d)?)
+        }
+        _ => return None,
+    };
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return None,
+    };
+    if day < 1 || day > days_in_month {
+        return None;
+    }
+
+    // Days since the epoch of the proleptic Gregorian date, counting years from March.
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = y.div_euclid(400);
+    let day_of_era = (y - era * 400) * 365 + (y - era * 400) / 4 - (y - era * 400) / 100
+        + (153 * m + 2) / 5
+        + day
+        - 1;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    let time = match time {
+        Some(time) => time,
+        None => return Some(Numeric::Integer(days * 86_400)),
+    };
+    let offset_start = time.find(['Z', 'z', '+', '-'])?;
+    let (clock, offset) = time.split_at(offset_start);
+    let (clock, fraction) = match clock.split_once('.') {
+        Some((clock, fraction)) => (clock, Some(fraction)),
+        None => (clock, None),
+    };
+    let mut parts = clock.splitn(3, ':');
+    let (hour, minute, second) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(h), Some(m), Some(s)) if h.len() == 2 && m.len() == 2 && s.len() == 2 => {
+            (number(h)?, number(m)?, number(s)?)
+        }
+        _ => return None,
+    };
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let offset = match offset {
+        "Z" | "z" => 0,
+        _ => match offset[1..].split_once(':') {
+            Some((h, m)) if h.len() == 2 && m.len() == 2 && number(h)? < 24 && number(m)? < 60 => {
+                let minutes = number(h)? * 60 + number(m)?;
+                if offset.starts_with('-') {
+                    -minutes * 60
+                } else {
+                    minutes * 60
+                }
+            }
+            _ => return None,
+        },
+    };
+    let seconds = days * 86_400 + hour * 3_600 + minute * 60 + second - offset;
+    match fraction {
+        None => Some(Numeric::Integer(seconds)),
+        Some(fraction) => {
+            number(fraction)?;
+            let fraction: f64 = format!("0.{}", fraction).parse().ok()?;
+            Some(Numeric::Float(seconds as f64 + fraction))
+        }
+    }
+}
+
+fn list_arg<'a>(name: &Symbol, arg: &'a Term) -> Result<&'a TermList, String> {
+    match arg.value() {
+        Value::List(list) if !has_rest_var(list) => Ok(list),
+        _ => Err(format!("{} expects a list, got: {}", name, arg)),
+    }
+}
+
+fn dict_arg<'a>(name: &Symbol, arg: &'a Term) -> Result<&'a BTreeMap<Symbol, Term>, String> {
+    match arg.value() {
+        Value::Dictionary(Dictionary { fields }) => Ok(fields),
+        _ => Err(format!("{} expects a dictionary, got: {}", name, arg)),
+    }
+}
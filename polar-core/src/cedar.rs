@@ -0,0 +1,372 @@
+//! Export of Polar policies to Cedar, the policy language of AWS Verified Permissions.
+//!
+//! The export covers an RBAC/ABAC subset of Polar: `allow` rules become `permit` policies and
+//! `deny` rules become `forbid` policies. Their parameters are the principal, the action, and
+//! the resource; a parameter may be specialized on a class, which becomes its entity type, and
+//! an action may be a string, which becomes an `Action` entity. Their bodies may compare the
+//! attributes of the principal and the resource with each other and with literals, check
+//! membership with `in`, check types with `matches`, and combine those with `and`, `or`, and
+//! `not`. Roles are attributes, e.g., `"admin" in actor.roles`. Policies that use anything else,
+//! like calls of other rules or local variables, can't be exported.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::error::{PolarResult, RuntimeError};
+use crate::kb::KnowledgeBase;
+use crate::rules::Rule;
+use crate::terms::*;
+
+/// The Cedar policy scope variables, in the order of the parameters of `allow` and `deny`.
+const SCOPE: [&str; 3] = ["principal", "action", "resource"];
+
+/// Export the `allow` and `deny` rules of `kb` as Cedar policies.
+pub fn export(kb: &KnowledgeBase) -> PolarResult<String> {
+    let mut policies = vec![];
+    for (name, effect) in [("allow", "permit"), ("deny", "forbid")] {
+        let generic_rule = match kb.get_rules().get(&Symbol::new(name)) {
+            Some(generic_rule) => generic_rule,
+            None => continue,
+        };
+        let mut rules = generic_rule.rules.iter().collect::<Vec<_>>();
+        rules.sort_by_key(|(id, _)| **id);
+        for (_, rule) in rules {
+            let policy = Exporter::default().policy(effect, rule).map_err(|msg| {
+                match rule.parsed_context() {
+                    Some(context) => cedar_unsupported(msg + &context.source_position()),
+                    None => cedar_unsupported(format!("{} in the rule {}", msg, rule)),
+                }
+            })?;
+            policies.push(policy);
+        }
+    }
+    Ok(policies.join("\n"))
+}
+
+#[derive(Default)]
+struct Exporter {
+    /// The scope variable of each parameter variable.
+    scope: HashMap<Symbol, &'static str>,
+    /// The attribute lookups that the rewriter bound to temporary variables.
+    lookups: HashMap<Symbol, (Term, Term)>,
+}
+
+impl Exporter {
+    fn policy(mut self, effect: &str, rule: &Rule) -> Result<String, String> {
+        if rule.params.len() != SCOPE.len() {
+            return Err(format!(
+                "`{}` rules with {} parameters",
+                rule.name,
+                rule.params.len()
+            ));
+        }
+        self.find_lookups(&rule.body);
+
+        let mut scope = vec![];
+        let mut conditions = vec![];
+        for (param, var) in rule.params.iter().zip(SCOPE) {
+            match (param.parameter.value(), var) {
+                (Value::Variable(name), _) => {
+                    self.scope.insert(name.clone(), var);
+                }
+                (Value::String(action), "action") => {
+                    scope.push(format!("action == {}", action_entity(action)));
+                    if param.specializer.is_some() {
+                        return Err("specializers on actions".to_owned());
+                    }
+                    continue;
+                }
+                _ => return Err(format!("{} that isn't a variable", var)),
+            }
+            let specializer = match &param.specializer {
+                Some(specializer) => specializer,
+                None => {
+                    scope.push(var.to_owned());
+                    continue;
+                }
+            };
+            match specializer.value() {
+                Value::Pattern(Pattern::Instance(InstanceLiteral { tag, fields })) => {
+                    scope.push(format!("{} is {}", var, tag));
+                    for (field, value) in &fields.fields {
+                        conditions.push(format!(
+                            "{}{} == {}",
+                            var,
+                            attribute(field.as_str()),
+                            self.literal(value)?
+                        ));
+                    }
+                }
+                _ => return Err(format!("the specializer {}", specializer)),
+            }
+        }
+
+        if let Some(condition) = self.condition(&rule.body)? {
+            conditions.push(condition);
+        }
+        let mut policy = format!("{} (\n    {}\n)", effect, scope.join(",\n    "));
+        if !conditions.is_empty() {
+            write!(policy, "\nwhen {{ {} }}", conditions.join(" && ")).unwrap();
+        }
+        policy.push_str(";\n");
+        Ok(policy)
+    }
+
+    /// Record the lookups `base.field = _value_n` that the rewriter hoisted out of the body.
+    fn find_lookups(&mut self, term: &Term) {
+        if let Value::Expression(Operation { operator, args }) = term.value() {
+            match (operator, &args[..]) {
+                (Operator::Dot, [base, field, result]) => {
+                    if let Value::Variable(var) = result.value() {
+                        if var.is_temporary_var() {
+                            self.lookups
+                                .insert(var.clone(), (base.clone(), field.clone()));
+                        }
+                    }
+                }
+                _ => args.iter().for_each(|arg| self.find_lookups(arg)),
+            }
+        }
+    }
+
+    /// Translate a condition, or `None` for one that always holds.
+    fn condition(&self, term: &Term) -> Result<Option<String>, String> {
+        use Operator::*;
+        let Operation { operator, args } = match term.value() {
+            Value::Expression(op) => op,
+            Value::Boolean(true) => return Ok(None),
+            _ => return Err(format!("the condition {}", term)),
+        };
+        Ok(Some(match (operator, &args[..]) {
+            (And, args) => {
+                let conditions = args
+                    .iter()
+                    .map(|arg| self.condition(arg))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<_>>();
+                match conditions.len() {
+                    0 => return Ok(None),
+                    1 => conditions.into_iter().next().unwrap(),
+                    _ => conditions.join(" && "),
+                }
+            }
+            (Or, args) => {
+                let conditions = args
+                    .iter()
+                    .map(|arg| Ok(self.condition(arg)?.unwrap_or_else(|| "true".to_owned())))
+                    .collect::<Result<Vec<_>, String>>()?;
+                format!("({})", conditions.join(" || "))
+            }
+            (Not, [arg]) => match self.condition(arg)? {
+                Some(condition) => format!("!({})", condition),
+                None => "false".to_owned(),
+            },
+            // Lookups are inlined where their results are used.
+            (Dot, [_, _, result]) if self.is_lookup(result) => return Ok(None),
+            (Unify | Eq | Neq | Lt | Leq | Gt | Geq, [left, right]) => {
+                let op = match operator {
+                    Unify | Eq => "==",
+                    Neq => "!=",
+                    Lt => "<",
+                    Leq => "<=",
+                    Gt => ">",
+                    _ => ">=",
+                };
+                let (left, right) = match (self.is_action(left), self.is_action(right)) {
+                    (true, false) => ("action".to_owned(), self.action(right)?),
+                    (false, true) => (self.action(left)?, "action".to_owned()),
+                    _ => (self.expr(left)?, self.expr(right)?),
+                };
+                format!("{} {} {}", left, op, right)
+            }
+            (In, [item, collection]) if self.is_action(item) => {
+                format!("action in {}", self.action(collection)?)
+            }
+            (In, [item, collection]) => {
+                format!("{}.contains({})", self.expr(collection)?, self.expr(item)?)
+            }
+            (Isa, [value, pattern]) => match pattern.value() {
+                Value::Pattern(Pattern::Instance(InstanceLiteral { tag, fields }))
+                    if fields.fields.is_empty() =>
+                {
+                    format!("{} is {}", self.expr(value)?, tag)
+                }
+                _ => return Err(format!("matching the pattern {}", pattern)),
+            },
+            _ => return Err(format!("the condition {}", term)),
+        }))
+    }
+
+    fn is_lookup(&self, term: &Term) -> bool {
+        matches!(term.value(), Value::Variable(var) if self.lookups.contains_key(var))
+    }
+
+    fn is_action(&self, term: &Term) -> bool {
+        matches!(term.value(), Value::Variable(var) if self.scope.get(var) == Some(&"action"))
+    }
+
+    /// Translate a term compared with the action, whose strings are actions.
+    fn action(&self, term: &Term) -> Result<String, String> {
+        match term.value() {
+            Value::String(action) => Ok(action_entity(action)),
+            Value::List(actions) => Ok(format!(
+                "[{}]",
+                actions
+                    .iter()
+                    .map(|action| self.action(action))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .join(", ")
+            )),
+            _ => Err(format!("comparing the action with {}", term)),
+        }
+    }
+
+    fn expr(&self, term: &Term) -> Result<String, String> {
+        match term.value() {
+            Value::Variable(var) => {
+                if let Some(var) = self.scope.get(var) {
+                    return Ok(var.to_string());
+                }
+                match self.lookups.get(var) {
+                    Some((base, field)) => match field.value() {
+                        Value::String(field) => {
+                            Ok(format!("{}{}", self.expr(base)?, attribute(field)))
+                        }
+                        _ => Err(format!("the lookup of {}", field)),
+                    },
+                    None => Err(format!("the variable `{}`", var)),
+                }
+            }
+            Value::List(list) => Ok(format!(
+                "[{}]",
+                list.iter()
+                    .map(|element| self.expr(element))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .join(", ")
+            )),
+            _ => self.literal(term),
+        }
+    }
+
+    fn literal(&self, term: &Term) -> Result<String, String> {
+        match term.value() {
+            Value::String(s) => Ok(cedar_string(s)),
+            Value::Number(crate::numerics::Numeric::Integer(i)) => Ok(i.to_string()),
+            Value::Boolean(b) => Ok(b.to_string()),
+            Value::Dictionary(dict) => Ok(format!(
+                "{{{}}}",
+                dict.fields
+                    .iter()
+                    .map(|(k, v)| Ok(format!("{}: {}", cedar_string(k.as_str()), self.expr(v)?)))
+                    .collect::<Result<Vec<_>, String>>()?
+                    .join(", ")
+            )),
+            _ => Err(format!("the value {}", term)),
+        }
+    }
+}
+
+fn action_entity(action: &str) -> String {
+    format!("Action::{}", cedar_string(action))
+}
+
+/// An attribute access, with a dot if the attribute's name is an identifier.
+fn attribute(name: &str) -> String {
+    let mut chars = name.chars();
+    let identifier = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if identifier {
+        format!(".{}", name)
+    } else {
+        format!("[{}]", cedar_string(name))
+    }
+}
+
+fn cedar_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn cedar_unsupported(msg: String) -> crate::error::PolarError {
+    RuntimeError::CedarUnsupported { msg }.into()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::polar::Polar;
+
+    #[test]
+    fn test_export() {
+        let polar = Polar::new();
+        polar
+            .load_str(
+                r#"allow(_actor: User, action, _resource: Doc{public: true}) if
+                       action in ["read", "list"];
+                   allow(actor: User, "edit", resource: Doc) if
+                       actor.team = resource.team and "editor" in actor.roles and
+                       (resource.level < 3 or actor.clearance.level >= resource.level);
+                   deny(actor, _action, resource) if
+                       not actor matches Admin and resource.attributes.("read-only") = true;"#,
+            )
+            .unwrap();
+        assert_eq!(
+            polar.export_cedar().unwrap(),
+            r#"permit (
+    principal is User,
+    action,
+    resource is Doc
+)
+when { resource.public == true && action in [Action::"read", Action::"list"] };
+
+permit (
+    principal is User,
+    action == Action::"edit",
+    resource is Doc
+)
+when { principal.team == resource.team && principal.roles.contains("editor") && (resource.level < 3 || principal.clearance.level >= resource.level) };
+
+forbid (
+    principal,
+    action,
+    resource
+)
+when { !(principal is Admin) && resource.attributes["read-only"] == true };
+"#
+        );
+    }
+
+    #[test]
+    fn test_export_unsupported() {
+        let polar = Polar::new();
+        polar
+            .load_str(
+                r#"allow(actor, "read", resource) if is_owner(actor, resource);
+                   is_owner(actor, resource) if actor.id = resource.owner_id;"#,
+            )
+            .unwrap();
+        let error = polar.export_cedar().unwrap_err().to_string();
+        assert!(
+            error.starts_with(
+                "Unsupported in Cedar: the condition is_owner(actor, resource) at line 1"
+            ),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! Evaluate ground expressions in rule bodies once, when rules are loaded, instead of every
+//! time a query calls the rule.
+
+use super::folder::*;
+use super::numerics::IntegerOverflow;
+use super::rules::*;
+use super::terms::*;
+use super::visitor::{walk_call, walk_operation, Visitor};
+use super::vm::compare;
+
+/// Fold the ground arithmetic and comparisons in the body of `rule` into their values, and
+/// prune the parts of the body that always fail:
+///
+/// - `1 + 2` becomes `3`, and `"a" < "b"` becomes `true`.
+/// - `true` conjuncts are dropped, and a conjunction with a `false` conjunct becomes `false`.
+/// - `false` disjuncts are dropped.
+///
+/// Arithmetic that would raise an error, like integer overflow, is left for the query to raise.
+/// A conjunction isn't pruned if a conjunct before its `false` one cuts, prints, or debugs.
+pub fn fold_constants(mut rule: Rule) -> Rule {
+    rule.body = ConstantFolder.fold_term(rule.body);
+    rule
+}
+
+struct ConstantFolder;
+
+impl Folder for ConstantFolder {
+    fn fold_term(&mut self, t: Term) -> Term {
+        let t = fold_term(t, self);
+        let folded = match t.value() {
+            Value::Expression(Operation { operator, args }) => match operator {
+                Operator::Add
+                | Operator::Sub
+                | Operator::Mul
+                | Operator::Div
+                | Operator::Mod
+                | Operator::Rem => fold_arithmetic(*operator, args),
+                Operator::And => Some(fold_and(args)),
+                Operator::Or => Some(fold_or(args)),
+                Operator::Not => fold_not(args),
+                _ => None,
+            },
+            _ => None,
+        };
+        folded.map_or(t.clone(), |value| t.clone_with_value(value))
+    }
+
+    // Patterns match their fields structurally, so expressions in them aren't evaluated.
+    fn fold_pattern(&mut self, p: Pattern) -> Pattern {
+        p
+    }
+}
+
+fn fold_arithmetic(operator: Operator, args: &[Term]) -> Option<Value> {
+    match args {
+        [left, right] => match (left.value(), right.value()) {
+            (Value::Number(left), Value::Number(right)) => left
+                .arithmetic(operator, *right, IntegerOverflow::Error)
+                .map(Value::Number),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Fold a ground comparison in goal position into `true` or `false`.
+fn fold_condition(t: &Term) -> Term {
+    use Value::*;
+
+    if let Expression(Operation { operator, args }) = t.value() {
+        if let (
+            Operator::Eq
+            | Operator::Neq
+            | Operator::Lt
+            | Operator::Leq
+            | Operator::Gt
+            | Operator::Geq,
+            [left, right],
+        ) = (operator, &args[..])
+        {
+            if matches!(
+                (left.value(), right.value()),
+                (Number(_) | Boolean(_), Number(_) | Boolean(_)) | (String(_), String(_))
+            ) {
+                if let Ok(result) = compare(*operator, left, right, Some(t)) {
+                    return t.clone_with_value(Boolean(result));
+                }
+            }
+        }
+    }
+    t.clone()
+}
+
+/// The value of a goal that always succeeds once or always fails, if `t` is one.
+fn constant(t: &Term) -> Option<bool> {
+    match t.value() {
+        Value::Boolean(b) => Some(*b),
+        Value::Expression(Operation {
+            operator: Operator::And,
+            args,
+        }) => match &args[..] {
+            [] => Some(true),
+            [arg] => constant(arg),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn fold_and(args: &[Term]) -> Value {
+    let args: TermList = args.iter().map(fold_condition).collect();
+    if let Some(index) = args.iter().position(|arg| constant(arg) == Some(false)) {
+        if args[..index].iter().all(|arg| !has_effects(arg)) {
+            return Value::Expression(Operation {
+                operator: Operator::And,
+                args: vec![args[index].clone()],
+            });
+        }
+    }
+    Value::Expression(Operation {
+        operator: Operator::And,
+        args: args
+            .into_iter()
+            .filter(|arg| constant(arg) != Some(true))
+            .collect(),
+    })
+}
+
+fn fold_or(args: &[Term]) -> Value {
+    let args: TermList = args
+        .iter()
+        .map(fold_condition)
+        .filter(|arg| constant(arg) != Some(false))
+        .collect();
+    if args.is_empty() {
+        Value::Boolean(false)
+    } else {
+        Value::Expression(Operation {
+            operator: Operator::Or,
+            args,
+        })
+    }
+}
+
+fn fold_not(args: &[Term]) -> Option<Value> {
+    match args {
+        [arg] => {
+            let arg = fold_condition(arg);
+            Some(match constant(&arg) {
+                Some(b) => Value::Boolean(!b),
+                None => Value::Expression(Operation {
+                    operator: Operator::Not,
+                    args: vec![arg],
+                }),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Whether querying `t` may do more than succeed or fail, so that it can't be pruned. Calls of
+/// rules and lookups, calls, and constructors of application instances may have effects.
+fn has_effects(t: &Term) -> bool {
+    struct EffectVisitor(bool);
+
+    impl Visitor for EffectVisitor {
+        fn visit_call(&mut self, c: &Call) {
+            self.0 = true;
+            walk_call(self, c);
+        }
+
+        fn visit_operation(&mut self, o: &Operation) {
+            self.0 |= matches!(
+                o.operator,
+                Operator::Cut
+                    | Operator::Print
+                    | Operator::Debug
+                    | Operator::Dot
+                    | Operator::SafeDot
+                    | Operator::New
+            );
+            walk_operation(self, o);
+        }
+    }
+
+    let mut visitor = EffectVisitor(false);
+    visitor.visit_term(t);
+    visitor.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_rules;
+
+    fn fold(src: &str) -> String {
+        let rule = parse_rules(src).unwrap().pop().unwrap();
+        fold_constants(rule).to_string()
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        assert_eq!(fold("f(x) if x = 1 + 2 * 3;"), "f(x) if x = 7;");
+        assert_eq!(fold(r#"f(x) if "a" < "b" and x = 1;"#), "f(x) if x = 1;");
+        assert_eq!(fold("f(x) if x = 1 and 2 < 1 and x = 2;"), "f(x) if false;");
+        assert_eq!(
+            fold("f(x) if x = 1 or x = 2 or 1 > 2;"),
+            "f(x) if (x = 1 or x = 2);"
+        );
+        assert_eq!(fold("f(x) if not 1 > 2 and x = 1;"), "f(x) if x = 1;");
+        // Arithmetic errors are raised by queries.
+        assert_eq!(
+            fold("f(x) if x = 9223372036854775807 + 1;"),
+            "f(x) if x = 9223372036854775807 + 1;"
+        );
+        // Conjunctions that cut aren't pruned.
+        assert_eq!(fold("f(x) if cut and 2 < 1;"), "f(x) if cut and false;");
+        // Nor are conjunctions that may call into the application or other rules.
+        assert_eq!(
+            fold("f(x) if x.audit() and 1 > 2;"),
+            "f(x) if x.audit() and false;"
+        );
+        assert_eq!(
+            fold("f(x) if x = new Log() and 1 > 2;"),
+            "f(x) if x = new Log() and false;"
+        );
+        assert_eq!(fold("f(x) if g(x) and 1 > 2;"), "f(x) if g(x) and false;");
+    }
+}
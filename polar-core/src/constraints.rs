@@ -0,0 +1,337 @@
+//! Partial results as typed constraint expressions, so that adapters can translate the
+//! constraints on unbound variables without matching on nested operations.
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::Bindings;
+use crate::error::{PolarResult, RuntimeError};
+use crate::terms::*;
+
+/// A constraint on the values of variables, e.g., the binding of `resource` in the result of
+/// a query with `resource` unbound.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConstraintExpr {
+    And(Vec<ConstraintExpr>),
+    Or(Vec<ConstraintExpr>),
+    Not(Box<ConstraintExpr>),
+    Cmp {
+        op: CmpOp,
+        left: Operand,
+        right: Operand,
+    },
+    /// `value matches pattern`.
+    Isa {
+        value: Operand,
+        pattern: Pattern,
+    },
+    /// `item in collection`.
+    In {
+        item: Operand,
+        collection: Operand,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CmpOp {
+    /// Unification or equality. Partial results say "equal" with unification.
+    Eq,
+    Neq,
+    Lt,
+    Leq,
+    Gt,
+    Geq,
+}
+
+/// An operand of a constraint.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Operand {
+    /// A value, e.g., a string or an application instance.
+    Value(Term),
+    Var(Symbol),
+    /// A chain of attribute lookups on a variable, e.g., `_this.org.name`.
+    Path {
+        root: Symbol,
+        path: Vec<String>,
+    },
+}
+
+impl CmpOp {
+    fn from_operator(operator: Operator) -> Option<Self> {
+        match operator {
+            Operator::Unify | Operator::Eq => Some(Self::Eq),
+            Operator::Neq => Some(Self::Neq),
+            Operator::Lt => Some(Self::Lt),
+            Operator::Leq => Some(Self::Leq),
+            Operator::Gt => Some(Self::Gt),
+            Operator::Geq => Some(Self::Geq),
+            _ => None,
+        }
+    }
+
+    fn operator(self) -> Operator {
+        match self {
+            Self::Eq => Operator::Unify,
+            Self::Neq => Operator::Neq,
+            Self::Lt => Operator::Lt,
+            Self::Leq => Operator::Leq,
+            Self::Gt => Operator::Gt,
+            Self::Geq => Operator::Geq,
+        }
+    }
+}
+
+fn unsupported(msg: String, term: &Term) -> crate::error::PolarError {
+    RuntimeError::Unsupported {
+        msg,
+        term: term.clone(),
+    }
+    .into()
+}
+
+impl Operand {
+    pub fn from_term(term: &Term) -> PolarResult<Self> {
+        match term.value() {
+            Value::Variable(name) => Ok(Self::Var(name.clone())),
+            Value::Expression(Operation {
+                operator: Operator::Dot,
+                args,
+            }) if args.len() == 2 => {
+                let field = match args[1].value() {
+                    Value::String(field) => field.clone(),
+                    _ => {
+                        return Err(unsupported(
+                            format!("only attribute lookups are supported in paths: {}", term),
+                            term,
+                        ))
+                    }
+                };
+                match Self::from_term(&args[0])? {
+                    Self::Var(root) => Ok(Self::Path {
+                        root,
+                        path: vec![field],
+                    }),
+                    Self::Path { root, mut path } => {
+                        path.push(field);
+                        Ok(Self::Path { root, path })
+                    }
+                    Self::Value(_) => Err(unsupported(
+                        format!("paths must start with a variable: {}", term),
+                        term,
+                    )),
+                }
+            }
+            Value::Expression(_) => Err(unsupported(
+                format!("not an operand of a constraint: {}", term),
+                term,
+            )),
+            _ => Ok(Self::Value(term.clone())),
+        }
+    }
+
+    pub fn to_term(&self) -> Term {
+        match self {
+            Self::Value(term) => term.clone(),
+            Self::Var(name) => Term::from(Value::Variable(name.clone())),
+            Self::Path { root, path } => {
+                path.iter()
+                    .fold(Term::from(Value::Variable(root.clone())), |term, field| {
+                        Term::from(Value::Expression(Operation {
+                            operator: Operator::Dot,
+                            args: vec![term, Term::from(Value::String(field.clone()))],
+                        }))
+                    })
+            }
+        }
+    }
+}
+
+impl ConstraintExpr {
+    /// The constraint `term` expresses, e.g., the binding of a variable in a partial result.
+    /// Terms that aren't expressions constrain nothing, and are `And([])`.
+    pub fn from_term(term: &Term) -> PolarResult<Self> {
+        let Operation { operator, args } = match term.value() {
+            Value::Expression(operation) => operation,
+            _ => return Ok(Self::And(vec![])),
+        };
+        let binary = |args: &TermList| match args.as_slice() {
+            [left, right] => Ok((Operand::from_term(left)?, Operand::from_term(right)?)),
+            _ => Err(unsupported(
+                format!("wrong number of arguments: {}", term),
+                term,
+            )),
+        };
+        let all = |args: &TermList| args.iter().map(Self::from_term).collect::<PolarResult<_>>();
+        match operator {
+            Operator::And => Ok(Self::And(all(args)?)),
+            Operator::Or => Ok(Self::Or(all(args)?)),
+            Operator::Not if args.len() == 1 => Ok(Self::Not(Box::new(Self::from_term(&args[0])?))),
+            Operator::Isa if args.len() == 2 => {
+                let value = Operand::from_term(&args[0])?;
+                match args[1].value() {
+                    Value::Pattern(pattern) => Ok(Self::Isa {
+                        value,
+                        pattern: pattern.clone(),
+                    }),
+                    _ => Err(unsupported(
+                        format!("`matches` needs a pattern: {}", term),
+                        term,
+                    )),
+                }
+            }
+            Operator::In => {
+                let (item, collection) = binary(args)?;
+                Ok(Self::In { item, collection })
+            }
+            operator => match CmpOp::from_operator(*operator) {
+                Some(op) => {
+                    let (left, right) = binary(args)?;
+                    Ok(Self::Cmp { op, left, right })
+                }
+                None => Err(unsupported(format!("not a constraint: {}", term), term)),
+            },
+        }
+    }
+
+    /// The expression of the constraint, in the form partial results use.
+    pub fn to_term(&self) -> Term {
+        let operation =
+            |operator, args| Term::from(Value::Expression(Operation { operator, args }));
+        match self {
+            Self::And(constraints) => operation(
+                Operator::And,
+                constraints.iter().map(Self::to_term).collect(),
+            ),
+            Self::Or(constraints) => operation(
+                Operator::Or,
+                constraints.iter().map(Self::to_term).collect(),
+            ),
+            Self::Not(constraint) => operation(Operator::Not, vec![constraint.to_term()]),
+            Self::Cmp { op, left, right } => {
+                operation(op.operator(), vec![left.to_term(), right.to_term()])
+            }
+            Self::Isa { value, pattern } => operation(
+                Operator::Isa,
+                vec![value.to_term(), Term::from(Value::Pattern(pattern.clone()))],
+            ),
+            Self::In { item, collection } => {
+                operation(Operator::In, vec![item.to_term(), collection.to_term()])
+            }
+        }
+    }
+}
+
+impl fmt::Display for ConstraintExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_term())
+    }
+}
+
+/// The constraints on the variables of a partial result that are bound to expressions.
+pub fn constraints(bindings: &Bindings) -> PolarResult<BTreeMap<Symbol, ConstraintExpr>> {
+    bindings
+        .iter()
+        .filter(|(_, value)| matches!(value.value(), Value::Expression(_)))
+        .map(|(name, value)| Ok((name.clone(), ConstraintExpr::from_term(value)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::events::QueryEvent;
+    use crate::polar::Polar;
+
+    #[test]
+    fn test_constraint_round_trip() -> PolarResult<()> {
+        let dot = |term: Term, field: &str| term!(op!(Dot, term, str!(field)));
+        let term = term!(op!(
+            And,
+            term!(op!(Isa, var!("_this"), term!(pattern!(instance!("Repo"))))),
+            term!(op!(
+                Unify,
+                str!("acme"),
+                dot(dot(var!("_this"), "org"), "name")
+            )),
+            term!(op!(Not, term!(op!(In, var!("_this"), var!("banned"))))),
+            term!(op!(
+                Or,
+                term!(op!(Gt, dot(var!("_this"), "stars"), term!(10))),
+                term!(op!(Unify, dot(var!("_this"), "public"), term!(true)))
+            ))
+        ));
+        let constraint = ConstraintExpr::from_term(&term)?;
+        let this = sym!("_this");
+        let path = |fields: &[&str]| Operand::Path {
+            root: this.clone(),
+            path: fields.iter().map(|&f| f.to_owned()).collect(),
+        };
+        assert_eq!(
+            constraint,
+            ConstraintExpr::And(vec![
+                ConstraintExpr::Isa {
+                    value: Operand::Var(this.clone()),
+                    pattern: pattern!(instance!("Repo")),
+                },
+                ConstraintExpr::Cmp {
+                    op: CmpOp::Eq,
+                    left: Operand::Value(str!("acme")),
+                    right: path(&["org", "name"]),
+                },
+                ConstraintExpr::Not(Box::new(ConstraintExpr::In {
+                    item: Operand::Var(this.clone()),
+                    collection: Operand::Var(sym!("banned")),
+                })),
+                ConstraintExpr::Or(vec![
+                    ConstraintExpr::Cmp {
+                        op: CmpOp::Gt,
+                        left: path(&["stars"]),
+                        right: Operand::Value(term!(10)),
+                    },
+                    ConstraintExpr::Cmp {
+                        op: CmpOp::Eq,
+                        left: path(&["public"]),
+                        right: Operand::Value(term!(true)),
+                    },
+                ]),
+            ])
+        );
+        assert_eq!(constraint.to_term(), term);
+
+        let call = term!(op!(
+            Unify,
+            term!(op!(Dot, var!("_this"), term!(call!("f")))),
+            term!(1)
+        ));
+        assert!(ConstraintExpr::from_term(&call).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_partial_result_constraints() -> PolarResult<()> {
+        let polar = Polar::new();
+        polar.load_str(r#"f(x, y) if x.a > 1 and y = 2;"#)?;
+        let mut query = polar.new_query_from_term(term!(call!("f", [sym!("x"), sym!("y")])), false);
+        let bindings = match query.next_event()? {
+            QueryEvent::Result { bindings, .. } => bindings,
+            event => panic!("not bindings, {:?}", event),
+        };
+        let constraints = constraints(&bindings)?;
+        assert_eq!(constraints.len(), 1);
+        let (name, constraint) = constraints.iter().next().unwrap();
+        assert_eq!(name, &sym!("x"));
+        assert_eq!(
+            constraint,
+            &ConstraintExpr::And(vec![ConstraintExpr::Cmp {
+                op: CmpOp::Gt,
+                left: Operand::Path {
+                    root: sym!("_this"),
+                    path: vec!["a".to_owned()],
+                },
+                right: Operand::Value(term!(1)),
+            }])
+        );
+        Ok(())
+    }
+}
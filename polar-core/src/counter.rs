@@ -5,15 +5,54 @@ use crate::numerics::MOST_POSITIVE_EXACT_FLOAT;
 
 const MAX_ID: u64 = (MOST_POSITIVE_EXACT_FLOAT - 1) as u64;
 
+/// Generational IDs keep their index in the low bits, and their generation in the rest.
+const INDEX_BITS: u32 = 32;
+const INDEX_MASK: u64 = (1 << INDEX_BITS) - 1;
+const MAX_GENERATION: u64 = MAX_ID >> INDEX_BITS;
+const GENERATION_BITS: u32 = u64::BITS - MAX_GENERATION.leading_zeros();
+
+/// The most high bits of the generation that a partition may take, leaving the rest for the
+/// generations within the partition.
+pub const MAX_PARTITION_BITS: u32 = 16;
+
+/// Generations handed out to generational counters. Generation 0 is never handed out, so
+/// that IDs from plain counters can't pass for generational ones.
+static GENERATIONS: AtomicU64 = AtomicU64::new(0);
+
+/// A share of the generations, for counters whose IDs must not collide with those of
+/// counters in other processes, e.g., one per engine in a cluster. The partition is kept in
+/// the high `bits` bits of the generation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Partition {
+    id: u64,
+    bits: u32,
+}
+
+impl Partition {
+    fn new_generation(&self) -> u64 {
+        let local_bits = GENERATION_BITS - self.bits;
+        let local_max = (1 << local_bits) - 1;
+        let local = GENERATIONS.fetch_add(1, Ordering::SeqCst) % local_max + 1;
+        self.id << local_bits | local
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Counter {
     next: Arc<AtomicU64>,
+    generational: bool,
+    partition: Partition,
+    /// How many times the counter ran out of IDs.
+    wraps: Arc<AtomicU64>,
 }
 
 impl Default for Counter {
     fn default() -> Self {
         Self {
             next: Arc::new(AtomicU64::new(1)),
+            generational: false,
+            partition: Partition::default(),
+            wraps: Arc::new(AtomicU64::new(0)),
         }
     }
 }
@@ -24,19 +63,85 @@ impl Counter {
     pub fn with_start(start: u64) -> Self {
         Self {
             next: Arc::new(AtomicU64::new(start)),
+            ..Self::default()
+        }
+    }
+
+    /// Create a counter of generational IDs: each ID carries the generation of the counter
+    /// that issued it, so that IDs from other counters are told apart from its own. Every
+    /// generational counter starts a new generation, and starts another one when it runs out
+    /// of indices instead of wrapping around and reusing them.
+    pub fn generational() -> Self {
+        Self::partitioned(0, 0).expect("the whole ID space is a partition")
+    }
+
+    /// Create a counter of generational IDs whose generations all have `partition` in their
+    /// high `bits` bits, so that its IDs don't collide with those of counters in other
+    /// partitions, even in other processes. `bits` may be at most `MAX_PARTITION_BITS`, and
+    /// `partition` must fit in `bits` bits.
+    pub fn partitioned(partition: u64, bits: u32) -> Option<Self> {
+        if bits > MAX_PARTITION_BITS || partition >> bits != 0 {
+            return None;
+        }
+        let partition = Partition {
+            id: partition,
+            bits,
+        };
+        Some(Self {
+            next: Arc::new(AtomicU64::new(partition.new_generation() << INDEX_BITS | 1)),
+            generational: true,
+            partition,
+            wraps: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    /// The generation of the IDs this counter issues next. Always 0 for plain counters.
+    pub fn generation(&self) -> u64 {
+        if self.generational {
+            Self::generation_of(self.next.load(Ordering::SeqCst))
+        } else {
+            0
         }
     }
 
+    /// The generation of an ID issued by a generational counter.
+    pub fn generation_of(id: u64) -> u64 {
+        id >> INDEX_BITS
+    }
+
+    /// How many times the counter ran out of IDs: plain counters then wrap around and reuse
+    /// IDs, and generational counters start a new generation.
+    pub fn wraps(&self) -> u64 {
+        self.wraps.load(Ordering::SeqCst)
+    }
+
     /// Return a monotonically increasing integer ID.
     ///
     /// Wraps around at 52 bits of precision so that it can be safely
-    /// coerced to an IEEE-754 double-float (f64).
+    /// coerced to an IEEE-754 double-float (f64). Wrapping is counted by `wraps`.
     pub fn next(&self) -> u64 {
+        if self.generational {
+            let id = self
+                .next
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |id| {
+                    Some(if id & INDEX_MASK == INDEX_MASK {
+                        self.partition.new_generation() << INDEX_BITS | 1
+                    } else {
+                        id + 1
+                    })
+                })
+                .expect("the update always succeeds");
+            if id & INDEX_MASK == INDEX_MASK {
+                self.wraps.fetch_add(1, Ordering::SeqCst);
+            }
+            return id;
+        }
         if self
             .next
             .compare_exchange(MAX_ID, 1, Ordering::SeqCst, Ordering::SeqCst)
             == Ok(MAX_ID)
         {
+            self.wraps.fetch_add(1, Ordering::SeqCst);
             MAX_ID
         } else {
             self.next.fetch_add(1, Ordering::SeqCst)
@@ -52,4 +157,55 @@ fn test_id_wrapping() {
     assert_eq!(MAX_ID, counter.next());
     assert_eq!(1, counter.next());
     assert_eq!(2, counter.next());
+    assert_eq!(counter.wraps(), 1);
+}
+
+#[test]
+fn test_generational_ids() {
+    let counter = Counter::generational();
+    let other = Counter::generational();
+    let generation = counter.generation();
+    assert_ne!(generation, 0);
+    assert_ne!(generation, other.generation());
+
+    let id = counter.next();
+    assert_eq!(Counter::generation_of(id), generation);
+    assert_eq!(counter.next(), id + 1);
+    assert_ne!(other.next(), id);
+
+    // Running out of indices starts a new generation instead of reusing IDs.
+    counter
+        .next
+        .store(generation << INDEX_BITS | INDEX_MASK, Ordering::SeqCst);
+    assert_eq!(counter.next() & INDEX_MASK, INDEX_MASK);
+    assert_ne!(counter.generation(), generation);
+    assert_eq!(counter.next() & INDEX_MASK, 1);
+    assert!(counter.next() <= MAX_ID);
+    assert_eq!(counter.wraps(), 1);
+}
+
+#[test]
+fn test_partitioned_ids() {
+    assert!(Counter::partitioned(4, 2).is_none());
+    assert!(Counter::partitioned(0, MAX_PARTITION_BITS + 1).is_none());
+
+    let counter = Counter::partitioned(3, 2).unwrap();
+    let other = Counter::partitioned(1, 2).unwrap();
+    let partition = |generation: u64| generation >> (GENERATION_BITS - 2);
+    assert_eq!(partition(counter.generation()), 3);
+    assert_eq!(partition(other.generation()), 1);
+    assert!(counter.next() <= MAX_ID);
+
+    // New generations stay in the partition.
+    counter.next.store(
+        counter.generation() << INDEX_BITS | INDEX_MASK,
+        Ordering::SeqCst,
+    );
+    counter.next();
+    assert_eq!(partition(counter.generation()), 3);
+    assert_eq!(counter.wraps(), 1);
+
+    let widest = Counter::partitioned((1 << MAX_PARTITION_BITS) - 1, MAX_PARTITION_BITS).unwrap();
+    assert!(widest.next() <= MAX_ID);
+    assert_ne!(widest.generation(), 0);
 }
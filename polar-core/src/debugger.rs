@@ -14,7 +14,7 @@ impl PolarVirtualMachine {
         let relevant_bindings = self.relevant_bindings(&[query]);
         let bindings_str = relevant_bindings
             .iter()
-            .map(|(var, val)| format!("{} = {}", var.0, val))
+            .map(|(var, val)| format!("{} = {}", var, val))
             .collect::<Vec<_>>()
             .join(", ");
         format!("QUERY: {}, BINDINGS: {{{}}}", query, bindings_str)
@@ -254,7 +254,7 @@ impl Debugger {
             }
             "goals" => return Some(show(&vm.goals)),
             "bindings" => {
-                return Some(show(vm.bindings_debug().as_slice()))
+                return Some(show(&vm.bindings_debug()))
             }
             "var" => {
                 if parts.len() > 1 {
@@ -269,7 +269,7 @@ impl Debugger {
                     let mut vars = vm
                         .bindings(true)
                         .keys()
-                        .map(|k| k.0.as_ref())
+                        .map(|k| k.as_str())
                         .collect::<Vec<_>>()
                         .join(", ");
                     if vars.is_empty() {
@@ -319,7 +319,7 @@ pub fn get_binding_for_var(name: &str, vm: &PolarVirtualMachine) -> Binding {
             bindings
                 .keys()
                 .filter_map(|k| {
-                    k.0.strip_prefix(&prefix)
+                    k.strip_prefix(&prefix)
                         .and_then(|i| i.parse::<i64>().map_or(None, |i| Some((k, i))))
                 })
                 .max_by(|a, b| a.1.cmp(&b.1))
@@ -327,7 +327,7 @@ pub fn get_binding_for_var(name: &str, vm: &PolarVirtualMachine) -> Binding {
                     || Binding(sym!(name), Term::from(sym!("<unbound>"))),
                     |b| {
                         Binding(
-                            sym!(format!("{}@{}", name, b.0 .0).as_str()),
+                            sym!(format!("{}@{}", name, b.0).as_str()),
                             bindings.get(b.0).unwrap().clone(),
                         )
                     },
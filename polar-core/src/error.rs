@@ -1,10 +1,13 @@
-use std::{borrow::Borrow, fmt, sync::Arc};
+use std::{any::Any, borrow::Borrow, fmt, sync::Arc};
 
 use indoc::formatdoc;
 use serde::Serialize;
 use strum_macros::AsRefStr;
 
 use super::{
+    counter::Counter,
+    fixes::SuggestedFix,
+    quota::Quota,
     resource_block::Declaration,
     rules::Rule,
     sources::{Context, Source},
@@ -91,27 +94,48 @@ impl PolarError {
                 | UnrecognizedEOF { loc } => Some(Context::new(e.source.clone(), *loc, *loc)),
 
                 // These errors track `term`, from which we calculate the context.
-                WrongValueType { term, .. } => term.parsed_context().cloned(),
+                WrongValueType { term, .. } | TermTooDeep { term, .. } => {
+                    term.parsed_context().cloned()
+                }
             },
 
             Runtime(e) => match e {
                 // These errors sometimes track `term`, from which we derive context.
-                Application { term, .. } => term.as_ref().and_then(Term::parsed_context).cloned(),
+                Application { term, .. }
+                | StackOverflow { term, .. }
+                | RuleDepthExceeded { term, .. }
+                | QueryTimeout { term, .. }
+                | IncompatibleBindings { term, .. }
+                | QueryForUndefinedRule { term, .. }
+                | QuotaExceeded { term, .. } => {
+                    term.as_ref().and_then(Term::parsed_context).cloned()
+                }
 
                 // These errors track `term`, from which we derive the context.
                 ArithmeticError { term }
                 | TypeError { term, .. }
                 | UnhandledPartial { term, .. }
+                | SandboxViolation { term, .. }
+                | UnregisteredSpecializer { term, .. }
+                | OccursCheck { term, .. }
                 | Unsupported { term, .. } => term.parsed_context().cloned(),
 
                 // These errors never have context.
-                StackOverflow { .. }
-                | QueryTimeout { .. }
-                | IncompatibleBindings { .. }
-                | DataFilteringFieldMissing { .. }
+                DataFilteringFieldMissing { .. }
                 | DataFilteringUnsupportedOp { .. }
                 | InvalidRegistration { .. }
-                | QueryForUndefinedRule { .. }
+                | NoPolicyEpoch { .. }
+                | QueryParameter { .. }
+                | InvalidScope { .. }
+                | InvalidTemplate { .. }
+                | SqlUnsupported { .. }
+                | CedarUnsupported { .. }
+                | InvalidIdPartition { .. }
+                | Suspension { .. }
+                | EnginePanic { .. }
+                | UnknownCallId { .. }
+                | LoadOrderDependent { .. }
+                | MergeConflict { .. }
                 | MultipleLoadError => None,
             },
 
@@ -120,10 +144,13 @@ impl PolarError {
                 ResourceBlock { term, .. }
                 | SingletonVariable { term, .. }
                 | UndefinedRuleCall { term }
+                | DisallowedRuleCall { term, .. }
                 | DuplicateResourceBlockDeclaration {
                     declaration: term, ..
                 }
-                | UnregisteredClass { term, .. } => term.parsed_context().cloned(),
+                | UnregisteredClass { term, .. }
+                | InvalidTypeAlias { alias: term, .. }
+                | InvalidDefaultDecision { term, .. } => term.parsed_context().cloned(),
 
                 // These errors track `rule`, from which we calculate the context.
                 InvalidRule { rule, .. }
@@ -131,9 +158,12 @@ impl PolarError {
                     rule_type: rule, ..
                 } => rule.parsed_context().cloned(),
 
+                // These errors sometimes track `term`, from which we derive context.
+                Lint { term, .. } => term.as_ref().and_then(Term::parsed_context).cloned(),
+
                 // These errors track `rule_type`, from which we sometimes calculate the context.
                 MissingRequiredRule { rule_type } => {
-                    if rule_type.name.0 == "has_relation" {
+                    if rule_type.name == "has_relation" {
                         rule_type.parsed_context().cloned()
                     } else {
                         // TODO(gj): copy source info from the appropriate resource block term for
@@ -161,6 +191,32 @@ impl PolarError {
     }
 }
 
+impl PolarError {
+    /// Attach the context of `term`, where the error arose, if the error doesn't already have
+    /// context from the policy.
+    pub(crate) fn with_context_term(mut self, context_term: &Term) -> Self {
+        use RuntimeError::*;
+
+        if self.get_context().is_some() {
+            return self;
+        }
+        if let ErrorKind::Runtime(e) = &mut self.0 {
+            match e {
+                Application { term, .. }
+                | StackOverflow { term, .. }
+                | RuleDepthExceeded { term, .. }
+                | QueryTimeout { term, .. }
+                | IncompatibleBindings { term, .. }
+                | QueryForUndefinedRule { term, .. }
+                | QuotaExceeded { term, .. } => *term = Some(context_term.clone()),
+                TypeError { term, .. } | Unsupported { term, .. } => *term = context_term.clone(),
+                _ => {}
+            }
+        }
+        self
+    }
+}
+
 #[cfg(test)]
 impl PolarError {
     pub fn unwrap_parse(self) -> ParseErrorKind {
@@ -272,6 +328,11 @@ pub enum ParseErrorKind {
         loc: usize,
         key: String,
     },
+    /// A term nested deeper than the configured limit.
+    TermTooDeep {
+        term: Term,
+        limit: usize,
+    },
 }
 
 impl fmt::Display for ParseErrorKind {
@@ -317,6 +378,9 @@ impl fmt::Display for ParseErrorKind {
             Self::DuplicateKey { key, .. } => {
                 write!(f, "Duplicate key: {}", key)
             }
+            Self::TermTooDeep { limit, .. } => {
+                write!(f, "Term is nested more than {} deep", limit)
+            }
         }
     }
 }
@@ -340,10 +404,20 @@ pub enum RuntimeError {
     },
     StackOverflow {
         msg: String,
+        /// Option<Term> of the query that was running, tracked for lexical context.
+        term: Option<Term>,
+    },
+    /// A query had more rule calls in progress at once than the configured limit.
+    RuleDepthExceeded {
+        limit: usize,
+        /// Option<Term> of the query that was running, tracked for lexical context.
+        term: Option<Term>,
     },
     QueryTimeout {
         elapsed: u64,
         timeout: u64,
+        /// Option<Term> of the query that was running, tracked for lexical context.
+        term: Option<Term>,
     },
     Application {
         msg: String,
@@ -353,6 +427,8 @@ pub enum RuntimeError {
     },
     IncompatibleBindings {
         msg: String,
+        /// Option<Term> of the query that was running, tracked for lexical context.
+        term: Option<Term>,
     },
     UnhandledPartial {
         var: Symbol,
@@ -376,6 +452,101 @@ pub enum RuntimeError {
     /// `ValidationError::UndefinedRuleCall`.
     QueryForUndefinedRule {
         name: String,
+        /// Option<Term> where the error arose, tracked for lexical context.
+        term: Option<Term>,
+    },
+    /// A time-travel query asked about a time before the oldest recorded policy epoch.
+    NoPolicyEpoch {
+        /// Milliseconds since the Unix epoch.
+        at: u64,
+    },
+    /// A parameterized query has a placeholder without a parameter, or a parameter without a
+    /// placeholder.
+    QueryParameter {
+        name: String,
+        msg: String,
+    },
+    InvalidScope {
+        scope: String,
+        msg: String,
+    },
+    /// An ID partition doesn't fit in the bits set aside for it.
+    InvalidIdPartition {
+        msg: String,
+    },
+    /// A query couldn't be suspended, or a suspended query couldn't be resumed.
+    Suspension {
+        msg: String,
+    },
+    /// A rule template was defined twice, or instantiated with a missing template or bindings
+    /// that aren't plain values.
+    InvalidTemplate {
+        template: String,
+        msg: String,
+    },
+    /// A data filter can't be compiled to SQL, e.g., because it compares whole records.
+    SqlUnsupported {
+        msg: String,
+    },
+    /// A rule can't be exported to Cedar, because it's outside the subset that Cedar expresses.
+    CedarUnsupported {
+        msg: String,
+    },
+    /// A scope's policy, or a query run in the scope, exceeded one of the scope's quotas.
+    QuotaExceeded {
+        scope: String,
+        quota: Quota,
+        limit: u64,
+        /// Option<Term> of the query that was running, tracked for lexical context.
+        term: Option<Term>,
+    },
+    /// A query panicked, either inside the VM or in an application call. The query can't be
+    /// resumed.
+    EnginePanic {
+        msg: String,
+    },
+    /// A rule from a sandboxed source used an attribute or method its sandbox doesn't allow.
+    SandboxViolation {
+        class: String,
+        name: String,
+        /// Term where the error arose, tracked for lexical context.
+        term: Term,
+    },
+    /// A rule used `@name` as a specializer, but no host predicate is registered as `name`.
+    UnregisteredSpecializer {
+        name: Symbol,
+        /// Term where the error arose, tracked for lexical context.
+        term: Term,
+    },
+    /// With the occurs check on, a unification would have bound a variable to a term that
+    /// contains it, like `x = [x]`, making a cyclic term.
+    OccursCheck {
+        var: Symbol,
+        /// Term the variable would have been bound to, tracked for lexical context.
+        term: Term,
+    },
+    /// The application answered a call ID that the query isn't waiting on, e.g., a stale ID
+    /// from a query that has finished.
+    UnknownCallId {
+        call_id: u64,
+        msg: String,
+    },
+    /// Both knowledge bases given to `KnowledgeBase::merge` define something of the same name
+    /// differently.
+    MergeConflict {
+        /// What is defined, e.g., "template".
+        kind: String,
+        name: String,
+    },
+    /// Loading the same sources in two orders made a rule try its clauses in different orders.
+    LoadOrderDependent {
+        /// The name of the rule.
+        rule: String,
+        /// The sources, by filename or by position for unnamed sources, in the order that
+        /// loaded first.
+        first: Vec<String>,
+        /// The sources in the order that tried the clauses differently.
+        second: Vec<String>,
     },
 }
 
@@ -396,17 +567,22 @@ impl fmt::Display for RuntimeError {
                 writeln!(f, "{}", stack_trace)?;
                 write!(f, "Type error: {}", msg)
             }
-            Self::StackOverflow { msg } => {
+            Self::StackOverflow { msg, .. } => {
                 write!(f, "{}", msg)
             }
-            Self::QueryTimeout { elapsed, timeout } => write!(f, "Query timeout: Query running for {}ms, which exceeds the timeout of {}ms. To disable timeouts, set the POLAR_TIMEOUT_MS environment variable to 0.", elapsed, timeout),
+            Self::RuleDepthExceeded { limit, .. } => {
+                write!(f, "Rule calls nested more than {} deep", limit)
+            }
+            Self::QueryTimeout {
+                elapsed, timeout, ..
+            } => write!(f, "Query timeout: Query running for {}ms, which exceeds the timeout of {}ms. To disable timeouts, set the POLAR_TIMEOUT_MS environment variable to 0.", elapsed, timeout),
             Self::Application {
                 msg, stack_trace, ..
             } => {
                 writeln!(f, "{}", stack_trace)?;
                 write!(f, "Application error: {}", msg)
             }
-            Self::IncompatibleBindings { msg } => {
+            Self::IncompatibleBindings { msg, .. } => {
                 write!(f, "Attempted binding was incompatible: {}", msg)
             }
             Self::UnhandledPartial { var, term } => {
@@ -470,7 +646,64 @@ The expression is: {expr}
                 write!(f, "Invalid attempt to register '{}': {}", sym, msg)
             }
             Self::MultipleLoadError => write!(f, "Cannot load additional Polar code -- all Polar code must be loaded at the same time."),
-            Self::QueryForUndefinedRule { name } => write!(f, "Query for undefined rule `{}`", name),
+            Self::QueryForUndefinedRule { name, .. } => write!(f, "Query for undefined rule `{}`", name),
+            Self::NoPolicyEpoch { at } => write!(
+                f,
+                "No policy was recorded as loaded at {} ms since the Unix epoch",
+                at
+            ),
+            Self::QueryParameter { msg, .. } => write!(f, "{}", msg),
+            Self::InvalidScope { scope, msg } => write!(f, "Invalid scope '{}': {}", scope, msg),
+            Self::InvalidIdPartition { msg } => write!(f, "Invalid ID partition: {}", msg),
+            Self::Suspension { msg } => write!(f, "Query suspension failed: {}", msg),
+            Self::InvalidTemplate { template, msg } => {
+                write!(f, "Invalid template '{}': {}", template, msg)
+            }
+            Self::SqlUnsupported { msg } => write!(f, "Unsupported in SQL: {}", msg),
+            Self::CedarUnsupported { msg } => write!(f, "Unsupported in Cedar: {}", msg),
+            Self::QuotaExceeded {
+                scope,
+                quota,
+                limit,
+                ..
+            } => write!(
+                f,
+                "Quota exceeded: scope '{}' is limited to {} {}",
+                scope, limit, quota
+            ),
+            Self::EnginePanic { msg } => write!(f, "Query panicked: {}", msg),
+            Self::SandboxViolation { class, name, .. } => write!(
+                f,
+                "Sandbox violation: this policy may not use `{}` on `{}`",
+                name, class
+            ),
+            Self::UnregisteredSpecializer { name, .. } => {
+                write!(f, "Unregistered specializer predicate: @{}", name)
+            }
+            Self::OccursCheck { var, term } => write!(
+                f,
+                "Occurs check failed: binding `{}` to `{}` would make a cyclic term",
+                var, term
+            ),
+            Self::UnknownCallId { call_id, msg } => {
+                write!(f, "Unknown call ID {}: {}", call_id, msg)
+            }
+            Self::MergeConflict { kind, name } => write!(
+                f,
+                "Cannot merge knowledge bases that define the {} `{}` differently",
+                kind, name
+            ),
+            Self::LoadOrderDependent {
+                rule,
+                first,
+                second,
+            } => write!(
+                f,
+                "Loading [{}] instead of [{}] changes the order the clauses of `{}` are tried in",
+                second.join(", "),
+                first.join(", "),
+                rule
+            ),
         }
     }
 }
@@ -531,6 +764,8 @@ pub enum ValidationError {
         /// Rule where the error arose, tracked for lexical context.
         rule: Rule,
         msg: String,
+        /// Edits that would make the rule match one of its rule types, if any would.
+        fix: Option<Box<SuggestedFix>>,
     },
     InvalidRuleType {
         /// Rule type where the error arose, tracked for lexical context.
@@ -543,6 +778,16 @@ pub enum ValidationError {
         /// Term<Call> where the error arose, tracked for lexical context.
         term: Term,
     },
+    /// A rule calls a rule that the `@calls` annotations of the templates of its name and
+    /// arity don't allow.
+    DisallowedRuleCall {
+        /// Term<Call> where the error arose, tracked for lexical context.
+        term: Term,
+        /// The names of the templates.
+        templates: Vec<String>,
+        /// The rules they allow calls to.
+        allowed: Vec<String>,
+    },
     ResourceBlock {
         /// Term where the error arose, tracked for lexical context.
         term: Term,
@@ -560,6 +805,16 @@ pub enum ValidationError {
         /// Term<Symbol> where the error arose, tracked for lexical context.
         term: Term,
     },
+    InvalidTypeAlias {
+        /// Term<Symbol> naming the alias, tracked for lexical context.
+        alias: Term,
+        msg: String,
+    },
+    InvalidDefaultDecision {
+        /// Term<Symbol> `default` of the declaration, tracked for lexical context.
+        term: Term,
+        msg: String,
+    },
     DuplicateResourceBlockDeclaration {
         /// Term<Symbol> where the error arose.
         resource: Term,
@@ -568,6 +823,13 @@ pub enum ValidationError {
         existing: Declaration,
         new: Declaration,
     },
+    /// A violation reported by a user-defined `LintRule`.
+    Lint {
+        rule: String,
+        message: String,
+        /// Term where the violation arose, tracked for lexical context.
+        term: Option<Term>,
+    },
 }
 
 impl From<ValidationError> for PolarError {
@@ -580,7 +842,7 @@ impl fmt::Display for ValidationError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Self::FileLoading { msg, .. } => write!(f, "Problem loading file: {}", msg),
-            Self::InvalidRule { rule, msg } => {
+            Self::InvalidRule { rule, msg, .. } => {
                 write!(f, "Invalid rule: {} {}", rule, msg)
             }
             Self::InvalidRuleType { rule_type, msg } => {
@@ -589,6 +851,27 @@ impl fmt::Display for ValidationError {
             Self::UndefinedRuleCall { term } => {
                 write!(f, "Call to undefined rule: {}", term)
             }
+            Self::DisallowedRuleCall {
+                term,
+                templates,
+                allowed,
+            } => {
+                write!(
+                    f,
+                    "Call to {} is not allowed: rules like template(s) {} may only call {}",
+                    term,
+                    templates
+                        .iter()
+                        .map(|name| format!("`{}`", name))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    if allowed.is_empty() {
+                        "built-in rules".to_owned()
+                    } else {
+                        allowed.join(", ")
+                    }
+                )
+            }
             Self::MissingRequiredRule { rule_type } => {
                 write!(f, "Missing implementation for required rule {}", rule_type)
             }
@@ -601,6 +884,12 @@ impl fmt::Display for ValidationError {
             Self::UnregisteredClass { term } => {
                 write!(f, "Unregistered class: {}", term)
             }
+            Self::InvalidTypeAlias { alias, msg } => {
+                write!(f, "Invalid type alias {}: {}", alias, msg)
+            }
+            Self::InvalidDefaultDecision { msg, .. } => {
+                write!(f, "Invalid default decision: {}", msg)
+            }
             Self::DuplicateResourceBlockDeclaration {
                 resource,
                 declaration,
@@ -613,6 +902,35 @@ impl fmt::Display for ValidationError {
                     existing, declaration, resource, new
                 )
             }
+            Self::Lint { rule, message, .. } => write!(f, "{}: {}", rule, message),
+        }
+    }
+}
+
+impl RuntimeError {
+    /// Build an error from the payload of a panic caught with `std::panic::catch_unwind`.
+    pub fn engine_panic(payload: Box<dyn Any + Send>) -> Self {
+        let msg = match payload.downcast::<String>() {
+            Ok(msg) => *msg,
+            Err(payload) => match payload.downcast::<&'static str>() {
+                Ok(msg) => (*msg).to_owned(),
+                Err(_) => "unknown panic".to_owned(),
+            },
+        };
+        Self::EnginePanic { msg }
+    }
+
+    /// Build an error for a call ID that a query isn't waiting on. `generation` is the
+    /// generation of the IDs the query's knowledge base issues.
+    pub fn unknown_call_id(call_id: u64, generation: u64) -> Self {
+        let msg = if Counter::generation_of(call_id) != generation {
+            "it was issued by another Polar instance"
+        } else {
+            "it isn't pending in this query, and may be from a query that has finished"
+        };
+        Self::UnknownCallId {
+            call_id,
+            msg: msg.to_owned(),
         }
     }
 }
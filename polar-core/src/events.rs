@@ -49,6 +49,19 @@ pub enum QueryEvent {
         kwargs: Option<BTreeMap<Symbol, Term>>,
     },
 
+    /// Call the batched method `attribute` with `args` on each of `instances`, the instances
+    /// of a list that the query iterates, at once.
+    ///
+    /// The host responds with `call_results(call_id, results)`, where `results` has the result
+    /// of the call on each instance, in order, or `None` for instances without one. The results
+    /// for the other instances answer their calls later in the query without another event.
+    ExternalCallBatch {
+        call_id: u64,
+        instances: TermList,
+        attribute: Symbol,
+        args: TermList,
+    },
+
     /// Checks if the instance is an instance of (a subclass of) the class_tag.
     ExternalIsa {
         call_id: u64,
@@ -56,6 +69,14 @@ pub enum QueryEvent {
         class_tag: Symbol,
     },
 
+    /// Checks if the instance satisfies the host predicate registered as the specializer
+    /// `@name`. The host responds with `question_result(call_id, answer)`.
+    ExternalSpecializer {
+        call_id: u64,
+        name: Symbol,
+        instance: Term,
+    },
+
     /// Starting from `base_tag`, traverse `path` fields and check if the result is an instance of
     /// `class_tag`.
     ExternalIsaWithPath {
@@ -95,6 +116,25 @@ pub enum QueryEvent {
         call_id: u64,
         iterable: Term,
     },
+
+    /// Ask a host fact source for the next answer to the rule `name`.
+    ///
+    /// The host responds with `call_result(call_id, Some(answer))`, where `answer` is a list
+    /// of terms that will be unified with `args`, or with `None` once the source has no more
+    /// answers. The event is re-emitted with the same `call_id` on backtracking so that the host
+    /// can stream multiple answers.
+    ExternalFacts {
+        call_id: u64,
+        name: Symbol,
+        args: TermList,
+    },
+
+    /// The query has run `goals` goals since its last event. Emitted when heartbeats are
+    /// enabled, so that a host can stay responsive during long stretches without other events,
+    /// e.g., to enforce its own deadline by abandoning the query. The host needs no response.
+    Heartbeat {
+        goals: u64,
+    },
 }
 
 // A struct for just Result Events. Used to pass data back into
@@ -0,0 +1,312 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use super::error::{unsupported, PolarResult};
+use super::terms::*;
+
+/// Ground facts asserted at runtime, indexed by argument position.
+///
+/// Facts live alongside rules in the knowledge base but are managed separately: they are not
+/// cleared along with rules, and queries for a predicate try matching facts before rules.
+#[derive(Clone, Default)]
+pub struct FactStore {
+    tables: HashMap<Symbol, FactTable>,
+}
+
+#[derive(Clone, Default)]
+struct FactTable {
+    /// Fact ID -> arguments. IDs increase monotonically so that facts are returned in insertion
+    /// order.
+    rows: BTreeMap<u64, TermList>,
+    /// Arguments -> fact ID, for deduplication & retraction.
+    ids: HashMap<TermList, u64>,
+    /// Argument position -> argument value -> IDs of facts with that value at that position.
+    index: Vec<HashMap<Term, BTreeSet<u64>>>,
+    next_id: u64,
+}
+
+/// Only atoms are indexed. External instances may be equal to one another according to the
+/// host even if they have different instance IDs, so they can't be looked up by value.
+fn is_indexable(term: &Term) -> bool {
+    matches!(
+        term.value(),
+        Value::Number(_) | Value::String(_) | Value::Boolean(_)
+    )
+}
+
+/// Facts may contain any value except variables, expressions, and patterns.
+fn check_ground(name: &Symbol, arg: &Term) -> PolarResult<()> {
+    struct GroundChecker(bool);
+
+    impl crate::visitor::Visitor for GroundChecker {
+        fn visit_term(&mut self, t: &Term) {
+            match t.value() {
+                Value::Variable(_)
+                | Value::RestVariable(_)
+                | Value::Expression(_)
+                | Value::Pattern(_)
+                | Value::Call(_) => self.0 = false,
+                _ => crate::visitor::walk_term(self, t),
+            }
+        }
+    }
+
+    let mut checker = GroundChecker(true);
+    crate::visitor::Visitor::visit_term(&mut checker, arg);
+    if checker.0 {
+        Ok(())
+    } else {
+        unsupported(
+            format!("facts for {} must be ground, but got {}", name, arg),
+            arg,
+        )
+    }
+}
+
+impl FactTable {
+    fn reserve(&mut self, additional: usize) {
+        self.ids.reserve(additional);
+    }
+
+    fn insert(&mut self, args: TermList) -> bool {
+        if self.ids.contains_key(&args) {
+            return false;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        if self.index.len() < args.len() {
+            self.index.resize_with(args.len(), HashMap::new);
+        }
+        for (position, arg) in args.iter().enumerate().filter(|(_, a)| is_indexable(a)) {
+            self.index[position]
+                .entry(arg.clone())
+                .or_default()
+                .insert(id);
+        }
+        self.ids.insert(args.clone(), id);
+        self.rows.insert(id, args);
+        true
+    }
+
+    fn delete(&mut self, args: &TermList) -> bool {
+        if let Some(id) = self.ids.remove(args) {
+            for (position, arg) in args.iter().enumerate().filter(|(_, a)| is_indexable(a)) {
+                if let Some(ids) = self.index[position].get_mut(arg) {
+                    ids.remove(&id);
+                    if ids.is_empty() {
+                        self.index[position].remove(arg);
+                    }
+                }
+            }
+            self.rows.remove(&id);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Return facts that may unify with `args`, using the most selective index available.
+    fn candidates(&self, args: &[Term]) -> Vec<TermList> {
+        let mut best: Option<&BTreeSet<u64>> = None;
+        for (position, arg) in args.iter().enumerate() {
+            if !is_indexable(arg) {
+                continue;
+            }
+            match self.index.get(position).and_then(|index| index.get(arg)) {
+                // No fact has this value at this position.
+                None => return vec![],
+                Some(ids) if !matches!(best, Some(best) if best.len() <= ids.len()) => {
+                    best = Some(ids)
+                }
+                _ => (),
+            }
+        }
+
+        let matches = |row: &&TermList| {
+            row.len() == args.len()
+                && args
+                    .iter()
+                    .zip(row.iter())
+                    .all(|(arg, value)| !is_indexable(arg) || arg == value)
+        };
+        match best {
+            Some(ids) => ids
+                .iter()
+                .map(|id| &self.rows[id])
+                .filter(matches)
+                .cloned()
+                .collect(),
+            None => self.rows.values().filter(matches).cloned().collect(),
+        }
+    }
+}
+
+impl FactStore {
+    /// Assert `name(args)`. Returns `false` if the fact was already present.
+    pub fn insert(&mut self, name: Symbol, args: TermList) -> PolarResult<bool> {
+        for arg in &args {
+            check_ground(&name, arg)?;
+        }
+        Ok(self.tables.entry(name).or_default().insert(args))
+    }
+
+    /// Assert many facts for `name` at once. Returns the number of facts that weren't already
+    /// present.
+    ///
+    /// Either all facts are asserted or, if any fact isn't ground, none are.
+    pub fn insert_all(&mut self, name: Symbol, facts: Vec<TermList>) -> PolarResult<usize> {
+        for arg in facts.iter().flatten() {
+            check_ground(&name, arg)?;
+        }
+        let table = self.tables.entry(name).or_default();
+        table.reserve(facts.len());
+        Ok(facts
+            .into_iter()
+            .filter(|args| table.insert(args.clone()))
+            .count())
+    }
+
+    /// Retract `name(args)`. Returns `false` if there was no such fact.
+    pub fn delete(&mut self, name: &Symbol, args: &TermList) -> bool {
+        self.tables
+            .get_mut(name)
+            .is_some_and(|table| table.delete(args))
+    }
+
+    /// Return true if facts have ever been asserted for `name`.
+    pub fn contains(&self, name: &Symbol) -> bool {
+        self.tables.contains_key(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &Symbol> {
+        self.tables.keys()
+    }
+
+    /// Return the facts for `name` that may unify with `args`, or `None` if no facts have been
+    /// asserted for `name`.
+    pub fn get(&self, name: &Symbol, args: &[Term]) -> Option<Vec<TermList>> {
+        self.tables.get(name).map(|table| table.candidates(args))
+    }
+}
+
+/// The answers of rules evaluated up front for one value of their first argument, e.g., the
+/// roles of an actor. Calls of those rules with that first argument are answered from here
+/// instead of from the rules and facts of the knowledge base.
+#[derive(Clone, Debug, Default)]
+pub struct Precomputed {
+    /// (first argument, argument lists that succeed) by rule name & arity.
+    answers: HashMap<(Symbol, usize), (Term, Vec<TermList>)>,
+}
+
+impl Precomputed {
+    /// Answer calls of `name` with `arity` arguments, the first of which is `first`, with
+    /// `answers`, the argument lists of the calls that succeed. Replaces any earlier answers
+    /// for `name`. Every answer must be ground.
+    pub fn insert(
+        &mut self,
+        name: Symbol,
+        arity: usize,
+        first: Term,
+        answers: Vec<TermList>,
+    ) -> PolarResult<()> {
+        for arg in answers.iter().flatten() {
+            check_ground(&name, arg)?;
+        }
+        self.answers.insert((name, arity), (first, answers));
+        Ok(())
+    }
+
+    /// The answers to a call of `name` whose arguments are `args`, or `None` if the call isn't
+    /// precomputed.
+    pub fn get(&self, name: &Symbol, args: &[Term]) -> Option<&[TermList]> {
+        let (first, answers) = self.answers.get(&(name.clone(), args.len()))?;
+        (args.first() == Some(first)).then_some(answers.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fact_store_index() {
+        let mut facts = FactStore::default();
+        let has_role = sym!("has_role");
+        assert!(facts
+            .insert(has_role.clone(), vec![term!(1), term!("member"), term!(10)])
+            .unwrap());
+        assert!(facts
+            .insert(has_role.clone(), vec![term!(1), term!("owner"), term!(11)])
+            .unwrap());
+        assert!(facts
+            .insert(has_role.clone(), vec![term!(2), term!("member"), term!(11)])
+            .unwrap());
+        // Duplicates are ignored.
+        assert!(!facts
+            .insert(has_role.clone(), vec![term!(2), term!("member"), term!(11)])
+            .unwrap());
+
+        let var = term!(sym!("x"));
+        let get = |facts: &FactStore, args: Vec<Term>| facts.get(&has_role, &args).unwrap();
+        assert_eq!(
+            get(&facts, vec![var.clone(), var.clone(), var.clone()]).len(),
+            3
+        );
+        assert_eq!(
+            get(&facts, vec![term!(1), var.clone(), var.clone()]),
+            vec![
+                vec![term!(1), term!("member"), term!(10)],
+                vec![term!(1), term!("owner"), term!(11)]
+            ]
+        );
+        assert_eq!(
+            get(&facts, vec![var.clone(), term!("member"), term!(11)]),
+            vec![vec![term!(2), term!("member"), term!(11)]]
+        );
+        assert!(get(&facts, vec![term!(3), var.clone(), var.clone()]).is_empty());
+        assert!(get(&facts, vec![var.clone(), var.clone()]).is_empty());
+
+        assert!(facts.delete(&has_role, &vec![term!(1), term!("member"), term!(10)]));
+        assert!(!facts.delete(&has_role, &vec![term!(1), term!("member"), term!(10)]));
+        assert_eq!(
+            get(&facts, vec![term!(1), var.clone(), var]),
+            vec![vec![term!(1), term!("owner"), term!(11)]]
+        );
+        assert!(facts.get(&sym!("other"), &[]).is_none());
+    }
+
+    #[test]
+    fn test_fact_store_insert_all() {
+        let mut facts = FactStore::default();
+        let f = sym!("f");
+        facts.insert(f.clone(), vec![term!(1)]).unwrap();
+        let inserted = facts
+            .insert_all(
+                f.clone(),
+                vec![vec![term!(1)], vec![term!(2)], vec![term!(2)]],
+            )
+            .unwrap();
+        assert_eq!(inserted, 1);
+        assert!(facts
+            .insert_all(f.clone(), vec![vec![term!(3)], vec![term!(sym!("x"))]])
+            .is_err());
+        assert_eq!(
+            facts.get(&f, &[term!(sym!("x"))]).unwrap(),
+            vec![vec![term!(1)], vec![term!(2)]]
+        );
+    }
+
+    #[test]
+    fn test_facts_must_be_ground() {
+        let mut facts = FactStore::default();
+        assert!(facts.insert(sym!("f"), vec![term!(sym!("x"))]).is_err());
+        assert!(facts
+            .insert(sym!("f"), vec![term!([1, sym!("x")])])
+            .is_err());
+        assert!(facts
+            .insert(
+                sym!("f"),
+                vec![term!(btreemap! {sym!("a") => term!([1, 2])})]
+            )
+            .unwrap());
+    }
+}
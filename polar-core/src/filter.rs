@@ -7,11 +7,14 @@ use std::{
 use crate::{
     data_filtering::{PartialResults, Type},
     error::{df_field_missing, df_unsupported_op, invalid_state, PolarResult},
+    kb::KnowledgeBase,
+    lexer::loc_to_pos,
     normalize::*,
+    sources::Context,
     terms::*,
 };
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 type TypeName = String;
 type FieldName = String;
@@ -31,11 +34,17 @@ type Set<A> = HashSet<A>;
 /// hold over the data source: for every record in the data source, if for some
 /// top-level set in `conditions` every inner condition holds on the record, then
 /// the record passes through the filter.
-#[derive(Clone, Eq, Debug, Serialize, PartialEq)]
+#[derive(Clone, Eq, Debug, Serialize, Deserialize)]
 pub struct Filter {
-    root: TypeName,                  // the host already has this, so we could leave it off
-    relations: Vec<Relation>,        // this & root determine the "joins" (or whatever)
-    conditions: Vec<Set<Condition>>, // disjunctive normal form
+    pub(crate) root: TypeName, // the host already has this, so we could leave it off
+    pub(crate) relations: Vec<Relation>, // this & root determine the "joins" (or whatever)
+    pub(crate) conditions: Vec<Set<Condition>>, // disjunctive normal form
+    /// The policy expressions the conditions of each disjunct were made from, in the order of
+    /// `conditions`, for the conditions that were made from expressions of a loaded policy.
+    /// Hosts don't need them to apply the filter, so they aren't serialized, and filters
+    /// that differ only in them are equal.
+    #[serde(skip)]
+    pub(crate) provenance: Vec<Vec<(Condition, Provenance)>>,
 }
 
 /// A named logical extension of a data set. Corresponds to a "join" in relational
@@ -46,22 +55,29 @@ pub struct Filter {
 /// For example, Relation("Foo", "bar", "Bar") represents a Relation
 /// from the `Foo` type to the `Bar` type, accessed using the `bar` field
 /// on `Foo`.
-#[derive(PartialEq, Eq, Debug, Serialize, Clone, Hash)]
-pub struct Relation(TypeName, FieldName, TypeName);
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Hash)]
+pub struct Relation(
+    pub(crate) TypeName,
+    pub(crate) FieldName,
+    pub(crate) TypeName,
+);
 
 /// A constraint that must hold for a record in the data source.
-#[derive(PartialEq, Eq, Debug, Serialize, Clone, Hash)]
-pub struct Condition(Datum, Comparison, Datum);
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Hash)]
+pub struct Condition(pub(crate) Datum, pub(crate) Comparison, pub(crate) Datum);
 
 /// The left or right side of a Condition.
-#[derive(PartialEq, Eq, Debug, Serialize, Clone, Hash)]
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Hash)]
 pub enum Datum {
     Field(Projection),
     Immediate(Value),
+    /// A value supplied when the filter is used, named by a variable and the path of fields
+    /// looked up on it, e.g., `actor.org.id`. Only filters built with parameters have them.
+    Parameter(String),
 }
 
 /// The comparison operation applied by a Condition.
-#[derive(PartialEq, Debug, Serialize, Copy, Clone, Eq, Hash)]
+#[derive(PartialEq, Debug, Serialize, Deserialize, Copy, Clone, Eq, Hash)]
 pub enum Comparison {
     Eq,
     Neq,
@@ -73,9 +89,50 @@ pub enum Comparison {
     Geq,
 }
 
+/// Where in a policy a condition came from.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Hash)]
+pub struct Provenance {
+    /// The head of the rule whose body has the expression, e.g., `allow(actor, "read", repo)`,
+    /// if the filter was built from the policy's knowledge base.
+    pub rule: Option<String>,
+    /// The file the expression was loaded from, if it has a name.
+    pub filename: Option<String>,
+    /// The line the expression starts on, from 1.
+    pub line: usize,
+    /// The start and end of the expression within its source.
+    pub span: (usize, usize),
+    /// The expression, e.g., `repo.public`.
+    pub expression: String,
+}
+
 /// An abstract "field reference" on a record from a named data source.
-#[derive(PartialEq, Eq, Debug, Serialize, Clone, Hash)]
-pub struct Projection(TypeName, Option<FieldName>);
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Hash)]
+pub struct Projection(pub(crate) TypeName, pub(crate) Option<FieldName>);
+
+/// A hint for fetching the records of a relation that a filter traverses, so that a host can
+/// choose how to fetch them, e.g., with a join or a subquery.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+pub struct PrefetchHint {
+    /// The type the relation is from, the field it's accessed with, and the type it's to.
+    pub from: TypeName,
+    pub field: FieldName,
+    pub to: TypeName,
+    pub cardinality: Cardinality,
+    /// How many relations the filter traverses from its root to reach `from`.
+    pub depth: usize,
+    /// How many of the filter's disjuncts have conditions on `to`. A relation that every
+    /// disjunct uses suits a join, and one that few of them use suits a subquery.
+    pub uses: usize,
+}
+
+/// How many records of the type a relation is to are related to each record of the type it's
+/// from, from the relation's kind.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Copy, Clone, Hash)]
+pub enum Cardinality {
+    One,
+    Many,
+    Unknown,
+}
 
 type TypeInfo = Map<TypeName, Map<FieldName, Type>>;
 type VarTypes = Map<PathVar, TypeName>;
@@ -87,6 +144,11 @@ struct FilterInfo {
     entities: VarTypes,
     conditions: Set<Condition>,
     relations: Set<Relation>,
+    /// Variables that stand for parameters instead of records, and their types.
+    parameters: Map<VarName, TypeName>,
+    /// Where the constraint being added came from, and where each condition came from.
+    sources: Vec<Provenance>,
+    provenance: Vec<(Condition, Provenance)>,
 }
 
 /// A variable with zero or more "dot lookups"
@@ -123,18 +185,39 @@ impl PathVar {
                 pv.path.push(dot);
                 Ok(pv)
             }
-            Variable(Symbol(var)) => Ok(var.clone().into()),
+            Variable(var) => Ok(var.to_string().into()),
             _ => invalid_state(format!("PathVar::from_term({})", t)),
         }
     }
 }
 
+impl PartialEq for Filter {
+    fn eq(&self, other: &Self) -> bool {
+        self.root == other.root
+            && self.relations == other.relations
+            && self.conditions == other.conditions
+    }
+}
+
 impl Filter {
     pub fn build(
         types: TypeInfo,
         partials: PartialResults,
         var: &str,
         class: &str,
+    ) -> PolarResult<Self> {
+        Self::build_with_parameters(types, partials, var, class, &[])
+    }
+
+    /// Build a filter whose conditions may compare records with `parameters`, variables of the
+    /// given types that were left unbound in the partial query, e.g., the actor of an allow
+    /// query. Lookups on them become `Datum::Parameter`s, to be supplied when the filter is used.
+    pub fn build_with_parameters(
+        types: TypeInfo,
+        partials: PartialResults,
+        var: &str,
+        class: &str,
+        parameters: &[(&str, &str)],
     ) -> PolarResult<Self> {
         let explain = std::env::var("POLAR_EXPLAIN").is_ok();
 
@@ -143,14 +226,14 @@ impl Filter {
             eprintln!("\n==Bindings==")
         }
 
-        let sym = Symbol(var.to_string());
+        let sym = Symbol::new(var);
         let filter = partials
             .into_iter()
             .filter_map(|opt| opt.bindings.get(&sym).cloned())
             .reduce(or_)
             .into_iter()
             .flat_map(vec_of_ands)
-            .map(|ands| Self::from_partial(&types, ands, var, class))
+            .map(|ands| Self::from_partial(&types, ands, var, class, parameters))
             .reduce(|l, r| Ok(l?.union(r?)))
             .unwrap_or_else(|| Ok(Self::empty(class)))?;
 
@@ -161,16 +244,26 @@ impl Filter {
         Ok(filter)
     }
 
-    fn from_partial(types: &TypeInfo, ands: Term, var: &str, class: &str) -> PolarResult<Self> {
+    fn from_partial(
+        types: &TypeInfo,
+        ands: Term,
+        var: &str,
+        class: &str,
+        parameters: &[(&str, &str)],
+    ) -> PolarResult<Self> {
         use {Operator::*, Value::*};
 
         if std::env::var("POLAR_EXPLAIN").is_ok() {
             eprintln!("{}", ands);
         }
 
-        let term2expr = |i: Term| match i.as_expression() {
-            Ok(x) => x.clone(),
-            _ => op!(Unify, var!(var), i),
+        // Keep where each conjunct came from, to trace the conditions made from it.
+        let term2expr = |i: Term| {
+            let source = i.parsed_context().map(Provenance::from_context);
+            match i.as_expression() {
+                Ok(x) => (x.clone(), source),
+                _ => (op!(Unify, var!(var), i), source),
+            }
         };
 
         match ands.value() {
@@ -183,12 +276,15 @@ impl Filter {
                 .iter()
                 .map(|and| Ok(term2expr(and.clone())))
                 .collect::<PolarResult<Vec<_>>>()
-                .and_then(|ands| FilterInfo::build_filter(types.clone(), ands, var, class)),
+                .and_then(|ands| {
+                    FilterInfo::build_filter(types.clone(), ands, var, class, parameters)
+                }),
 
             // sometimes we get an instance back. that means the variable
             // is exactly this instance, so return a filter that matches it.
             ExternalInstance(_) => {
-                FilterInfo::build_filter(types.clone(), vec![term2expr(ands.clone())], var, class)
+                let ands = vec![term2expr(ands.clone())];
+                FilterInfo::build_filter(types.clone(), ands, var, class, parameters)
             }
 
             // oops, we don't know how to handle this!
@@ -206,11 +302,14 @@ impl Filter {
                 Comparison::Eq,
                 Immediate(Boolean(false)),
             ))],
+            provenance: vec![],
         }
     }
 
     fn union(mut self, other: Self) -> Self {
+        self.provenance.resize(self.conditions.len(), vec![]);
         self.conditions.extend(other.conditions);
+        self.provenance.extend(other.provenance);
         for rel in other.relations {
             if !self.relations.iter().any(|r| r == &rel) {
                 self.relations.push(rel);
@@ -218,6 +317,205 @@ impl Filter {
         }
         self
     }
+
+    /// Hints for fetching the records of each relation the filter traverses, in the order they
+    /// are traversed from the root. `types` are the types the filter was built with.
+    pub fn prefetch_hints(&self, types: &TypeInfo) -> Vec<PrefetchHint> {
+        let mut depths = Map::new();
+        depths.insert(self.root.as_str(), 0);
+        self.relations
+            .iter()
+            .map(|Relation(from, field, to)| {
+                let depth = depths.get(from.as_str()).copied().unwrap_or_default();
+                depths.entry(to.as_str()).or_insert(depth + 1);
+                let cardinality = match types.get(from).and_then(|fields| fields.get(field)) {
+                    Some(Type::Relation { kind, .. }) if kind == "one" => Cardinality::One,
+                    Some(Type::Relation { kind, .. }) if kind == "many" => Cardinality::Many,
+                    _ => Cardinality::Unknown,
+                };
+                let uses = self
+                    .conditions
+                    .iter()
+                    .filter(|conditions| {
+                        conditions.iter().any(|Condition(left, _, right)| {
+                            left.is_field_of(to) || right.is_field_of(to)
+                        })
+                    })
+                    .count();
+                PrefetchHint {
+                    from: from.clone(),
+                    field: field.clone(),
+                    to: to.clone(),
+                    cardinality,
+                    depth,
+                    uses,
+                }
+            })
+            .collect()
+    }
+
+    /// Replace the external instances that conditions compare with other values, e.g., with
+    /// keys that identify them, so that the host compares instances by key instead of by
+    /// handle. `f` returns `None` to leave an instance as it is.
+    pub fn map_instances<F, E>(mut self, mut f: F) -> Result<Self, E>
+    where
+        F: FnMut(&ExternalInstance) -> Result<Option<Value>, E>,
+    {
+        self.conditions = self
+            .conditions
+            .into_iter()
+            .map(|conditions| {
+                conditions
+                    .into_iter()
+                    .map(|condition| condition.map_instances(&mut f))
+                    .collect()
+            })
+            .collect::<Result<_, E>>()?;
+        self.provenance = self
+            .provenance
+            .into_iter()
+            .map(|sources| {
+                sources
+                    .into_iter()
+                    .map(|(condition, source)| Ok((condition.map_instances(&mut f)?, source)))
+                    .collect()
+            })
+            .collect::<Result<_, E>>()?;
+        Ok(self)
+    }
+
+    /// The policy expressions `condition` was made from in the disjunct at `index`.
+    pub fn provenance(&self, index: usize, condition: &Condition) -> Vec<&Provenance> {
+        self.provenance
+            .get(index)
+            .into_iter()
+            .flatten()
+            .filter(|(c, _)| c == condition)
+            .map(|(_, source)| source)
+            .collect()
+    }
+
+    /// Name the rules of `kb` that the conditions came from.
+    pub(crate) fn with_rules(mut self, kb: &KnowledgeBase) -> Self {
+        let rules = kb
+            .get_rules()
+            .values()
+            .flat_map(|generic_rule| generic_rule.rules.values())
+            .filter_map(|rule| Some((rule.parsed_context()?, rule)))
+            .collect::<Vec<_>>();
+        for (_, source) in self.provenance.iter_mut().flatten() {
+            let (left, right) = source.span;
+            // Rules don't overlap, so the expression is in the last rule that starts before it.
+            source.rule = rules
+                .iter()
+                .filter(|(context, _)| {
+                    context.source.filename == source.filename
+                        && context.left <= left
+                        && context.source.src.get(left..right) == Some(&source.expression)
+                })
+                .max_by_key(|(context, _)| context.left)
+                .map(|(_, rule)| rule.head_as_string());
+        }
+        self
+    }
+
+    /// Explain where each condition came from, e.g.,
+    ///
+    /// ```text
+    /// Repo.public = true
+    ///     policy.polar:3: repo.public in allow(actor, "read", repo: Repo)
+    /// ```
+    ///
+    /// with the disjuncts separated by `OR`.
+    pub fn explain(&self) -> String {
+        let mut lines = vec![];
+        for (i, conditions) in self.conditions.iter().enumerate() {
+            if i > 0 {
+                lines.push("OR".to_owned());
+            }
+            // Conditions are a set, so sort them for the same explanation every time.
+            let mut conditions = conditions.iter().collect::<Vec<_>>();
+            conditions.sort_by_cached_key(|condition| condition.to_string());
+            for condition in conditions {
+                lines.push(condition.to_string());
+                for source in self.provenance(i, condition) {
+                    let rule = source
+                        .rule
+                        .as_ref()
+                        .map_or_else(String::new, |rule| format!(" in {}", rule));
+                    lines.push(format!(
+                        "    {}:{}: {}{}",
+                        source.filename.as_deref().unwrap_or("<unnamed source>"),
+                        source.line,
+                        source.expression,
+                        rule
+                    ));
+                }
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+impl Provenance {
+    fn from_context(context: &Context) -> Self {
+        let (row, _) = loc_to_pos(&context.source.src, context.left);
+        Self {
+            rule: None,
+            filename: context.source.filename.clone(),
+            line: context.source.first_line + row + 1,
+            span: (context.left, context.right),
+            expression: context
+                .source
+                .src
+                .get(context.left..context.right)
+                .unwrap_or_default()
+                .to_owned(),
+        }
+    }
+}
+
+impl Condition {
+    fn map_instances<F, E>(self, f: &mut F) -> Result<Self, E>
+    where
+        F: FnMut(&ExternalInstance) -> Result<Option<Value>, E>,
+    {
+        let Condition(left, op, right) = self;
+        Ok(Condition(
+            left.map_instances(f)?,
+            op,
+            right.map_instances(f)?,
+        ))
+    }
+}
+
+impl Datum {
+    fn is_field_of(&self, typ: &str) -> bool {
+        matches!(self, Datum::Field(Projection(t, _)) if t == typ)
+    }
+
+    fn map_instances<F, E>(self, f: &mut F) -> Result<Self, E>
+    where
+        F: FnMut(&ExternalInstance) -> Result<Option<Value>, E>,
+    {
+        let mut map_value = |value: &Value| match value {
+            Value::ExternalInstance(instance) => f(instance),
+            _ => Ok(None),
+        };
+        Ok(match self {
+            Datum::Immediate(Value::List(terms)) => Datum::Immediate(Value::List(
+                terms
+                    .into_iter()
+                    .map(|term| {
+                        Ok(map_value(term.value())?
+                            .map_or(term.clone(), |value| term.clone_with_value(value)))
+                    })
+                    .collect::<Result<_, E>>()?,
+            )),
+            Datum::Immediate(value) => Datum::Immediate(map_value(&value)?.unwrap_or(value)),
+            field => field,
+        })
+    }
 }
 
 impl FilterInfo {
@@ -289,6 +587,12 @@ impl FilterInfo {
     fn term2datum(&mut self, x: &Term) -> PolarResult<Datum> {
         use Datum::*;
         match PathVar::from_term(x) {
+            Ok(PathVar { var, path }) if self.parameters.contains_key(&var) => Ok(Parameter(
+                std::iter::once(var)
+                    .chain(path)
+                    .collect::<Vec<_>>()
+                    .join("."),
+            )),
             Ok(pv) => Ok(Field(self.pathvar2proj(pv)?)),
             _ => Ok(Immediate(x.value().clone())),
         }
@@ -325,7 +629,7 @@ impl FilterInfo {
     /// Handle a unary operation from the simplifier
     fn add_constraint_1(&mut self, op: Operation) -> PolarResult<()> {
         use Operator::*;
-        // The only case this currently handles is `not in`.
+        // The only cases this currently handles are `not in` and `not isa`.
         match op.operator {
             Not => match op.args[0].as_expression() {
                 Ok(Operation { operator: In, args }) if args.len() == 2 => {
@@ -333,12 +637,60 @@ impl FilterInfo {
                     self.add_condition(left, Comparison::Nin, right);
                     Ok(())
                 }
+                // Negation inverts the constraints on each variable separately and conjoins
+                // them, so negating a rule that specializes on a parameter's type leaves this
+                // conjunct where it meant a disjunction. The parameter always matches its own
+                // type, so the rest of the conjunction is what's left of the negated rule.
+                Ok(Operation {
+                    operator: Isa,
+                    args,
+                }) if args.len() == 2
+                    && self.is_parameter(&args[0])
+                    && self.is_own_type(&args[0], &args[1]) =>
+                {
+                    Ok(())
+                }
+                // A variable always matches its own type, so this conjunct can't be satisfied.
+                // This arises from negating rules that specialize on the root type.
+                Ok(Operation {
+                    operator: Isa,
+                    args,
+                }) if args.len() == 2 && self.is_own_type(&args[0], &args[1]) => {
+                    use {Datum::Immediate, Value::Boolean};
+                    self.add_condition(
+                        Immediate(Boolean(true)),
+                        Comparison::Eq,
+                        Immediate(Boolean(false)),
+                    );
+                    Ok(())
+                }
                 _ => df_unsupported_op(op),
             },
             _ => df_unsupported_op(op),
         }
     }
 
+    fn is_parameter(&self, term: &Term) -> bool {
+        PathVar::from_term(term)
+            .is_ok_and(|pv| pv.path.is_empty() && self.parameters.contains_key(&pv.var))
+    }
+
+    /// Return true if `pattern` is exactly the known type of the variable `term`.
+    fn is_own_type(&mut self, term: &Term, pattern: &Term) -> bool {
+        let typ = PathVar::from_term(term)
+            .ok()
+            .and_then(|pv| match self.parameters.get(&pv.var) {
+                Some(typ) if pv.path.is_empty() => Some(typ.clone()),
+                _ => self.get_type(pv),
+            });
+        match (typ, pattern.value()) {
+            (Some(typ), Value::Pattern(Pattern::Instance(InstanceLiteral { tag, fields }))) => {
+                fields.fields.is_empty() && tag.as_str() == typ
+            }
+            _ => false,
+        }
+    }
+
     /// Handle a binary expression from the simplifier
     fn add_constraint_2(&mut self, op: Operation) -> PolarResult<()> {
         use {Datum::*, Operator::*};
@@ -366,7 +718,14 @@ impl FilterInfo {
         match op {
             Eq | Leq | Geq if left == right => (),
             _ => {
-                self.conditions.insert(Condition(left, op, right));
+                let condition = Condition(left, op, right);
+                for source in &self.sources {
+                    let source = (condition.clone(), source.clone());
+                    if !self.provenance.contains(&source) {
+                        self.provenance.push(source);
+                    }
+                }
+                self.conditions.insert(condition);
             }
         }
     }
@@ -388,7 +747,12 @@ impl FilterInfo {
     }
 
     /// populate conditions and relations on an initialized FilterInfo
-    fn with_constraints(mut self, ops: Set<Operation>, class: &str) -> PolarResult<Self> {
+    fn with_constraints(
+        mut self,
+        ops: Set<Operation>,
+        sources: &Map<Operation, Vec<Provenance>>,
+        class: &str,
+    ) -> PolarResult<Self> {
         // find pairs of implicitly equal variables
         let equivs = ops.iter().filter_map(|Operation { operator, args }| {
             use Operator::*;
@@ -435,6 +799,7 @@ impl FilterInfo {
         // now add a condition for each partial.
         // this also populates the relations.
         for op in ops {
+            self.sources = sources.get(&op).cloned().unwrap_or_default();
             self.add_constraint(op)?;
         }
 
@@ -443,9 +808,10 @@ impl FilterInfo {
 
     fn build_filter(
         type_info: TypeInfo,
-        parts: Vec<Operation>,
+        parts: Vec<(Operation, Option<Provenance>)>,
         var: &str,
         class: &str,
+        parameters: &[(&str, &str)],
     ) -> PolarResult<Filter> {
         fn sort_relations(
             relations: HashSet<Relation>,
@@ -467,9 +833,17 @@ impl FilterInfo {
             sort_relations(rest, types, out)
         }
 
+        let mut sources: Map<Operation, Vec<Provenance>> = Map::new();
+        for (op, source) in &parts {
+            if let Some(source) = source {
+                sources.entry(op.clone()).or_default().push(source.clone());
+            }
+        }
+
         // TODO(gw) check more isas in host -- rn we only check external instances
         let (_isas, othas): (Set<_>, Set<_>) = parts
             .into_iter()
+            .map(|(op, _)| op)
             .partition(|op| op.operator == Operator::Isa);
 
         let mut entities = HashMap::new();
@@ -479,13 +853,18 @@ impl FilterInfo {
         let Self {
             conditions,
             relations,
+            provenance,
             ..
         } = Self {
             type_info,
             entities,
+            parameters: parameters
+                .iter()
+                .map(|(var, typ)| (var.to_string(), typ.to_string()))
+                .collect(),
             ..Default::default()
         }
-        .with_constraints(othas, class)?;
+        .with_constraints(othas, &sources, class)?;
 
         let relations = sort_relations(relations, singleton(class.to_string()), vec![]);
 
@@ -493,6 +872,7 @@ impl FilterInfo {
             relations,
             conditions: vec![conditions],
             root: class.to_string(),
+            provenance: vec![provenance],
         })
     }
 }
@@ -562,6 +942,7 @@ impl Display for Datum {
             Immediate(val) => write!(f, "{}", val),
             Field(Projection(typ, None)) => write!(f, "{}", typ),
             Field(Projection(typ, Some(field))) => write!(f, "{}.{}", typ, field),
+            Parameter(path) => write!(f, "${}", path),
         }
     }
 }
@@ -750,6 +1131,7 @@ mod test {
             root,
             relations,
             conditions,
+            ..
         } = Filter::build(types, ors, "resource", "Resource")?;
 
         assert_eq!(&root, "Resource");
@@ -845,4 +1227,166 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_prefetch_hints() -> PolarResult<()> {
+        let s = String::from;
+        let mut types = types_2();
+        types.get_mut("Resource").unwrap().insert(
+            s("bars"),
+            Type::Relation {
+                kind: s("many"),
+                my_field: s("_"),
+                other_field: s("_"),
+                other_class_tag: s("Bar"),
+            },
+        );
+        let path = |fields: &[&str]| {
+            fields.iter().fold(var!("_this"), |term, field| {
+                term!(op!(Dot, term, str!(*field)))
+            })
+        };
+        let isa = term!(op!(
+            Isa,
+            var!("_this"),
+            term!(pattern!(instance!("Resource")))
+        ));
+        let ors = vec![
+            ResultEvent::new(hashmap! {
+                sym!("resource") => term!(op!(And,
+                    isa.clone(),
+                    term!(op!(Unify, term!(1), path(&["foo", "boo", "goo", "id"])))
+                ))
+            }),
+            ResultEvent::new(hashmap! {
+                sym!("resource") => term!(op!(And,
+                    isa,
+                    term!(op!(In, var!("x"), path(&["bars"]))),
+                    term!(op!(Unify, term!(2), term!(op!(Dot, var!("x"), str!("id")))))
+                ))
+            }),
+        ];
+
+        let filter = Filter::build(types.clone(), ors, "resource", "Resource")?;
+        let hints = filter
+            .prefetch_hints(&types)
+            .into_iter()
+            .map(|hint| (hint.to, hint.cardinality, hint.depth, hint.uses))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            hints,
+            vec![
+                (s("Foo"), Cardinality::One, 0, 0),
+                (s("Boo"), Cardinality::One, 1, 0),
+                (s("Goo"), Cardinality::One, 2, 1),
+                (s("Bar"), Cardinality::Many, 0, 1),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_map_instances() -> PolarResult<()> {
+        let instance = |id| term!(Value::ExternalInstance(ExternalInstance::from(id)));
+        let ors = vec![ResultEvent::new(hashmap! {
+            sym!("resource") => term!(op!(And,
+                term!(op!(Isa, var!("_this"), term!(pattern!(instance!("Foo"))))),
+                term!(op!(Unify, var!("_this"), instance(1))),
+                term!(op!(In, var!("_this"), term!(Value::List(vec![instance(2), instance(3)]))))
+            ))
+        })];
+        let filter = Filter::build(types_0(), ors, "resource", "Foo")?;
+        let keyed = filter.map_instances(|instance| -> PolarResult<_> {
+            Ok((instance.instance_id != 3).then(|| value!(instance.instance_id as i64 * 10)))
+        })?;
+
+        let proj = Datum::Field(Projection("Foo".to_owned(), None));
+        assert_eq!(
+            keyed.conditions,
+            vec![hashset! {
+                Condition(proj.clone(), Comparison::Eq, Datum::Immediate(value!(10))),
+                Condition(
+                    proj,
+                    Comparison::In,
+                    Datum::Immediate(Value::List(vec![term!(20), instance(3)]))
+                ),
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_provenance() -> PolarResult<()> {
+        use crate::events::QueryEvent;
+        use crate::polar::Polar;
+        use crate::sources::Source;
+
+        let s = String::from;
+        let types = hashmap! {
+            s("Repo") => hashmap! {
+                s("org") => Type::Relation {
+                    kind: s("one"),
+                    my_field: s("org_id"),
+                    other_field: s("id"),
+                    other_class_tag: s("Org"),
+                },
+            },
+            s("Org") => hashmap! {
+                s("stars") => Type::Base { class_tag: s("Integer") },
+            },
+        };
+        let polar = Polar::new();
+        polar.load(vec![Source::new_with_name(
+            "policy.polar",
+            r#"allow(_actor, "read", repo: Repo) if repo.org.stars = 1;
+               allow(_actor, "read", repo: Repo) if
+                   repo.org.stars = 1 and 10 < repo.org.stars;"#,
+        )])?;
+        let mut query = polar.new_query_from_term(
+            term!(call!("allow", ["alice", "read", sym!("repo")])),
+            false,
+        );
+        let mut ors = vec![];
+        loop {
+            match query.next_event()? {
+                QueryEvent::Result { bindings, .. } => ors.push(ResultEvent::new(bindings)),
+                QueryEvent::Done { .. } => break,
+                event => panic!("unexpected event: {:?}", event),
+            }
+        }
+
+        let filter = polar.build_data_filter(types, ors, "repo", "Repo")?;
+        let eq = Condition(
+            Datum::Immediate(value!(1)),
+            Comparison::Eq,
+            Datum::Field(Projection(s("Org"), Some(s("stars")))),
+        );
+        let sources = filter.provenance(0, &eq);
+        assert_eq!(sources.len(), 1);
+        assert_eq!(sources[0].filename.as_deref(), Some("policy.polar"));
+        assert_eq!(sources[0].line, 1);
+        assert_eq!(sources[0].span, (37, 51));
+        assert_eq!(sources[0].expression, "repo.org.stars");
+        assert_eq!(
+            sources[0].rule.as_deref(),
+            Some(r#"allow(_actor, "read", repo: Repo{})"#)
+        );
+        assert_eq!(filter.provenance(1, &eq)[0].line, 3);
+        assert_eq!(
+            filter.explain(),
+            r#"Number(Integer(1)) = Org.stars
+    policy.polar:1: repo.org.stars in allow(_actor, "read", repo: Repo{})
+OR
+Number(Integer(1)) = Org.stars
+    policy.polar:3: repo.org.stars in allow(_actor, "read", repo: Repo{})
+Number(Integer(10)) < Org.stars
+    policy.polar:3: 10 < repo.org.stars in allow(_actor, "read", repo: Repo{})"#
+        );
+
+        // Hosts get filters without provenance, which are still the same filters.
+        let sent: Filter = serde_json::from_value(serde_json::to_value(&filter).unwrap()).unwrap();
+        assert!(sent.provenance.is_empty());
+        assert_eq!(sent, filter);
+        Ok(())
+    }
 }
@@ -0,0 +1,211 @@
+//! Suggested fixes for rules that don't match any rule type of the same name: the fewest
+//! parameter edits that would make a rule match one, for editors to offer as quick fixes.
+use serde::Serialize;
+
+use super::rules::Parameter;
+use super::terms::*;
+
+/// The edits that make a rule match a rule type.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SuggestedFix {
+    /// The rule type the fixed rule matches, e.g., `allow(actor: Actor, action: String, resource: Resource)`.
+    pub rule_type: String,
+    /// The head of the fixed rule.
+    pub head: String,
+    /// The edits, one for each parameter that doesn't match, in parameter order.
+    pub params: Vec<ParamFix>,
+}
+
+/// The edit of one parameter.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ParamFix {
+    /// The position of the parameter, from 1.
+    pub index: usize,
+    pub edit: ParamEdit,
+    /// The fixed parameter, e.g., `resource: Repo`.
+    pub replacement: String,
+    /// The span of the parameter and its specializer in their source, if they were parsed,
+    /// which `replacement` replaces.
+    pub span: Option<(usize, usize)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum ParamEdit {
+    /// Specialize a parameter, or change its specializer.
+    Specializer { from: Option<String>, to: String },
+    /// Add fields to a parameter's specializer, or change their values.
+    Fields { fields: Vec<String> },
+    /// Replace a parameter that isn't a variable, or whose specializer isn't a pattern.
+    Parameter { from: String, to: String },
+}
+
+impl SuggestedFix {
+    /// Describe the fix, e.g., "change the specializer of parameter 3 to `Repo`".
+    pub fn describe(&self) -> String {
+        self.params
+            .iter()
+            .map(|fix| match &fix.edit {
+                ParamEdit::Specializer { from: None, to } => {
+                    format!("specialize parameter {} on `{}`", fix.index, to)
+                }
+                ParamEdit::Specializer { from: Some(_), to } => {
+                    format!(
+                        "change the specializer of parameter {} to `{}`",
+                        fix.index, to
+                    )
+                }
+                ParamEdit::Fields { fields } => format!(
+                    "set field(s) {} of parameter {}",
+                    fields
+                        .iter()
+                        .map(|field| format!("`{}`", field))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    fix.index
+                ),
+                ParamEdit::Parameter { to, .. } => {
+                    format!("replace parameter {} with `{}`", fix.index, to)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+fn pattern(term: Option<&Term>) -> Option<&Pattern> {
+    match term.map(Term::value) {
+        Some(Value::Pattern(pattern)) => Some(pattern),
+        _ => None,
+    }
+}
+
+/// The fields of `rule`, with the values of `rule_type` and the names of those that differ.
+fn merge_fields(rule: &Dictionary, rule_type: &Dictionary) -> (Dictionary, Vec<String>) {
+    let mut fields = rule.clone();
+    let mut changed = vec![];
+    for (name, value) in &rule_type.fields {
+        if fields.fields.get(name) != Some(value) {
+            fields.fields.insert(name.clone(), value.clone());
+            changed.push(name.to_string());
+        }
+    }
+    (fields, changed)
+}
+
+/// The span of a parameter and its specializer in their source.
+fn span(param: &Parameter) -> Option<(usize, usize)> {
+    let left = param.parameter.parsed_context()?.left;
+    let right = param
+        .specializer
+        .as_ref()
+        .unwrap_or(&param.parameter)
+        .parsed_context()?
+        .right;
+    Some((left, right))
+}
+
+/// The smallest edit of `param` that could make it match `type_param`, and the edited
+/// parameter, keeping the parameter's name and the fields of its specializer.
+pub(crate) fn fix_param(
+    index: usize,
+    param: &Parameter,
+    type_param: &Parameter,
+) -> (Parameter, ParamFix) {
+    let is_variable = matches!(param.parameter.value(), Value::Variable(_));
+    let specialized = |pattern: Pattern| Parameter {
+        parameter: param.parameter.clone(),
+        specializer: Some(Term::new_temporary(Value::Pattern(pattern))),
+        guard: param.guard.clone(),
+    };
+    let (fixed, edit) = match (
+        pattern(type_param.specializer.as_ref()),
+        pattern(param.specializer.as_ref()),
+    ) {
+        (Some(Pattern::Instance(type_instance)), Some(Pattern::Instance(instance)))
+            if is_variable && type_instance.tag == instance.tag =>
+        {
+            let (fields, changed) = merge_fields(&instance.fields, &type_instance.fields);
+            let tag = instance.tag.clone();
+            (
+                specialized(Pattern::Instance(InstanceLiteral { tag, fields })),
+                ParamEdit::Fields { fields: changed },
+            )
+        }
+        (Some(Pattern::Dictionary(type_fields)), Some(Pattern::Dictionary(fields)))
+            if is_variable =>
+        {
+            let (fields, changed) = merge_fields(fields, type_fields);
+            (
+                specialized(Pattern::Dictionary(fields)),
+                ParamEdit::Fields { fields: changed },
+            )
+        }
+        (Some(Pattern::Dictionary(type_fields)), Some(Pattern::Instance(instance)))
+            if is_variable =>
+        {
+            let (fields, changed) = merge_fields(&instance.fields, type_fields);
+            let tag = instance.tag.clone();
+            (
+                specialized(Pattern::Instance(InstanceLiteral { tag, fields })),
+                ParamEdit::Fields { fields: changed },
+            )
+        }
+        (Some(type_pattern), rule_pattern)
+            if is_variable && (rule_pattern.is_some() || param.specializer.is_none()) =>
+        {
+            // Keep the fields the parameter was specialized on.
+            let fields = match rule_pattern {
+                Some(Pattern::Instance(InstanceLiteral { fields, .. }))
+                | Some(Pattern::Dictionary(fields)) => fields.clone(),
+                _ => Dictionary::new(),
+            };
+            let type_pattern = match type_pattern {
+                Pattern::Instance(InstanceLiteral {
+                    tag,
+                    fields: type_fields,
+                }) => {
+                    let fields = merge_fields(&fields, type_fields).0;
+                    Pattern::Instance(InstanceLiteral {
+                        tag: tag.clone(),
+                        fields,
+                    })
+                }
+                Pattern::Dictionary(type_fields) => {
+                    Pattern::Dictionary(merge_fields(&fields, type_fields).0)
+                }
+                predicate => predicate.clone(),
+            };
+            let fixed = specialized(type_pattern);
+            let to = fixed.specializer.as_ref().unwrap().to_string();
+            let from = param.specializer.as_ref().map(ToString::to_string);
+            (fixed, ParamEdit::Specializer { from, to })
+        }
+        _ => {
+            let fixed = Parameter {
+                parameter: if is_variable {
+                    param.parameter.clone()
+                } else {
+                    type_param.parameter.clone()
+                },
+                specializer: type_param.specializer.clone(),
+                guard: param.guard.clone(),
+            };
+            let edit = ParamEdit::Parameter {
+                from: param.to_string(),
+                to: fixed.to_string(),
+            };
+            (fixed, edit)
+        }
+    };
+    let fix = ParamFix {
+        index,
+        edit,
+        replacement: Parameter {
+            guard: None,
+            ..fixed.clone()
+        }
+        .to_string(),
+        span: span(param),
+    };
+    (fixed, fix)
+}
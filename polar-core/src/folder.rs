@@ -81,6 +81,9 @@ pub trait Folder: Sized {
     fn fold_operation(&mut self, o: Operation) -> Operation {
         fold_operation(o, self)
     }
+    fn fold_lambda(&mut self, l: Lambda) -> Lambda {
+        fold_lambda(l, self)
+    }
     fn fold_param(&mut self, p: Parameter) -> Parameter {
         fold_param(p, self)
     }
@@ -93,6 +96,7 @@ pub fn fold_rule<T: Folder>(
         body,
         source_info,
         required,
+        metadata,
     }: Rule,
     fld: &mut T,
 ) -> Rule {
@@ -102,6 +106,7 @@ pub fn fold_rule<T: Folder>(
         body: fld.fold_term(body),
         source_info,
         required,
+        metadata,
     }
 }
 
@@ -122,6 +127,7 @@ pub fn fold_value<T: Folder>(v: Value, fld: &mut T) -> Value {
         Value::Variable(v) => Value::Variable(fld.fold_variable(v)),
         Value::RestVariable(r) => Value::RestVariable(fld.fold_rest_variable(r)),
         Value::Expression(o) => Value::Expression(fld.fold_operation(o)),
+        Value::Lambda(l) => Value::Lambda(fld.fold_lambda(l)),
     }
 }
 
@@ -183,6 +189,7 @@ pub fn fold_pattern<T: Folder>(p: Pattern, fld: &mut T) -> Pattern {
     match p {
         Pattern::Dictionary(d) => Pattern::Dictionary(fld.fold_dictionary(d)),
         Pattern::Instance(i) => Pattern::Instance(fld.fold_instance_literal(i)),
+        Pattern::Predicate(name) => Pattern::Predicate(fld.fold_name(name)),
     }
 }
 
@@ -223,6 +230,13 @@ pub fn fold_operation<T: Folder>(
     }
 }
 
+pub fn fold_lambda<T: Folder>(Lambda { params, body }: Lambda, fld: &mut T) -> Lambda {
+    Lambda {
+        params: fld.fold_list(params),
+        body: fld.fold_term(body),
+    }
+}
+
 pub fn fold_name<T: Folder>(n: Symbol, _fld: &mut T) -> Symbol {
     n
 }
@@ -231,12 +245,14 @@ pub fn fold_param<T: Folder>(
     Parameter {
         parameter,
         specializer,
+        guard,
     }: Parameter,
     fld: &mut T,
 ) -> Parameter {
     Parameter {
         parameter: fld.fold_term(parameter),
         specializer: specializer.map(|t| fld.fold_term(t)),
+        guard: guard.map(|t| fld.fold_term(t)),
     }
 }
 
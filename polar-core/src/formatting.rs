@@ -11,6 +11,7 @@
 
 use std::fmt::Write;
 
+use super::folder::{fold_term, Folder};
 use super::{lexer::loc_to_pos, rules::*, sources::*, terms::*, traces::*};
 
 impl Trace {
@@ -54,6 +55,146 @@ impl Trace {
     }
 }
 
+/// Formats terms for traces, stack traces, and logs, which may need to stay short, or to keep
+/// secrets like API keys out. By default, terms are formatted in full, as by `to_polar`.
+///
+/// ```
+/// use polar_core::{parser::parse_query, TermFormatter};
+///
+/// let formatter = TermFormatter::new().max_list_len(2).redact("sk_*");
+/// let term = parse_query(r#"["sk_live_123", 2, 3]"#).unwrap();
+/// assert_eq!(formatter.format(&term), "[<redacted>, 2, ... 1 more]");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TermFormatter {
+    max_depth: Option<usize>,
+    max_width: Option<usize>,
+    max_list_len: Option<usize>,
+    redactions: Vec<String>,
+}
+
+impl TermFormatter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Elide lists, dictionaries, calls, and expressions nested in more than `depth` others
+    /// as `...`.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Cut formatted terms longer than `width` characters short, ending them with `...`.
+    pub fn max_width(mut self, width: usize) -> Self {
+        self.max_width = Some(width);
+        self
+    }
+
+    /// Show only the first `len` elements of longer lists, followed by how many were elided.
+    pub fn max_list_len(mut self, len: usize) -> Self {
+        self.max_list_len = Some(len);
+        self
+    }
+
+    /// Replace strings, and representations of application instances, that match `pattern`
+    /// with `<redacted>`. A `*` in `pattern` matches any characters, e.g., `sk_live_*`.
+    pub fn redact<T: Into<String>>(mut self, pattern: T) -> Self {
+        self.redactions.push(pattern.into());
+        self
+    }
+
+    pub fn format(&self, term: &Term) -> String {
+        let term = Elider {
+            formatter: self,
+            depth: 0,
+        }
+        .fold_term(term.clone());
+        let formatted = term.to_string();
+        match self.max_width {
+            Some(width) if formatted.chars().count() > width => {
+                formatted.chars().take(width).chain("...".chars()).collect()
+            }
+            _ => formatted,
+        }
+    }
+
+    fn redacts(&self, s: &str) -> bool {
+        self.redactions.iter().any(|pattern| glob_match(pattern, s))
+    }
+}
+
+/// Match `s` against `pattern`, in which `*` matches any characters.
+fn glob_match(pattern: &str, s: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match s.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let mut parts = parts.collect::<Vec<_>>();
+    let last = match parts.pop() {
+        Some(last) => last,
+        // No `*`, so the pattern must match all of `s`.
+        None => return rest.is_empty(),
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Replace the parts of a term that a `TermFormatter` leaves out with bare symbols, which
+/// format as themselves.
+struct Elider<'a> {
+    formatter: &'a TermFormatter,
+    depth: usize,
+}
+
+fn elided(s: String) -> Value {
+    Value::Variable(Symbol::new(&s))
+}
+
+impl<'a> Folder for Elider<'a> {
+    fn fold_term(&mut self, t: Term) -> Term {
+        match t.value() {
+            Value::String(s) if self.formatter.redacts(s) => {
+                return t.clone_with_value(elided("<redacted>".to_owned()))
+            }
+            Value::ExternalInstance(ExternalInstance {
+                repr: Some(repr), ..
+            }) if self.formatter.redacts(repr) => {
+                return t.clone_with_value(elided("<redacted>".to_owned()))
+            }
+            Value::List(_)
+            | Value::Dictionary(_)
+            | Value::Call(_)
+            | Value::Expression(_)
+            | Value::Pattern(_)
+            | Value::Lambda(_) => (),
+            _ => return t,
+        }
+        if matches!(self.formatter.max_depth, Some(depth) if self.depth >= depth) {
+            return t.clone_with_value(elided("...".to_owned()));
+        }
+        self.depth += 1;
+        let mut t = fold_term(t, self);
+        self.depth -= 1;
+        if let (Value::List(list), Some(len)) = (t.value(), self.formatter.max_list_len) {
+            if list.len() > len {
+                let mut shown = list[..len].to_vec();
+                let more = elided(format!("... {} more", list.len() - len));
+                shown.push(t.clone_with_value(more));
+                t.replace_value(Value::List(shown));
+            }
+        }
+        t
+    }
+}
+
 /// Traverse a [`Source`](../types/struct.Source.html) line by line until `offset` is reached and
 /// return the source line containing the `offset` character as well as `context_lines` lines above
 /// and below it.
@@ -81,7 +222,8 @@ pub(crate) fn source_lines(source: &Source, offset: usize, context_lines: usize)
     let lines = prefix.chain(target).chain(suffix);
 
     // Format each line with its line number.
-    let format_line = |(i, line): (usize, &str)| format!("{:03}: {}", i + 1, line);
+    let format_line =
+        |(i, line): (usize, &str)| format!("{:03}: {}", source.first_line + i + 1, line);
     let mut lines: Vec<_> = lines.map(format_line).collect();
 
     // Insert 'indicator' line pointing at `target_column`.
@@ -120,6 +262,7 @@ fn precedence(o: &Operator) -> i32 {
         Operator::Cut => 10,
         Operator::ForAll => 10,
         Operator::Dot => 9,
+        Operator::SafeDot => 9,
         Operator::In => 8,
         Operator::Isa => 8,
         Operator::Mul => 7,
@@ -137,6 +280,7 @@ fn precedence(o: &Operator) -> i32 {
         Operator::Unify => 4,
         Operator::Assign => 4,
         Operator::Not => 3,
+        Operator::Try => 3,
         Operator::And => 2,
         Operator::Or => 1,
     }
@@ -171,6 +315,7 @@ mod display {
     use crate::resource_block::Declaration;
     use crate::rules::{Parameter, Rule};
     use crate::terms::{Call, Dictionary, InstanceLiteral, Operation, Operator, Symbol, Term};
+    use crate::traces::Counterexample;
     use crate::vm::*;
 
     impl fmt::Display for Binding {
@@ -181,7 +326,7 @@ mod display {
 
     impl fmt::Display for Symbol {
         fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-            write!(fmt, "{}", self.0)
+            write!(fmt, "{}", self.as_str())
         }
     }
 
@@ -319,6 +464,22 @@ mod display {
         }
     }
 
+    impl fmt::Display for Counterexample {
+        fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+            let bindings = self
+                .bindings
+                .iter()
+                .map(|(var, value)| format!("{} = {}", var, value.to_polar()))
+                .collect::<Vec<_>>();
+            write!(
+                fmt,
+                "{} for {}",
+                self.forall.to_polar(),
+                bindings.join(", ")
+            )
+        }
+    }
+
     impl fmt::Display for Rule {
         fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
             write!(fmt, "{}", self.to_polar())
@@ -431,6 +592,7 @@ mod to_polar {
             use Operator::*;
             match self {
                 Not => "not",
+                Try => "try",
                 Mul => "*",
                 Div => "/",
                 Mod => "mod",
@@ -447,6 +609,7 @@ mod to_polar {
                 And => "and",
                 New => "new",
                 Dot => ".",
+                SafeDot => ".?",
                 Unify => "=",
                 Assign => ":=",
                 In => "in",
@@ -485,18 +648,20 @@ mod to_polar {
                         )
                     }
                 }
-                // Lookup operator
-                Dot => {
+                // Lookup operators
+                Dot | SafeDot => {
                     let call_term = if let Value::String(s) = self.args[1].value() {
                         s.to_string()
                     } else {
                         self.args[1].to_polar()
                     };
+                    let dot = self.operator.to_polar();
                     match self.args.len() {
-                        2 => format!("{}.{}", self.args[0].to_polar(), call_term),
+                        2 => format!("{}{}{}", self.args[0].to_polar(), dot, call_term),
                         3 => format!(
-                            "{}.{} = {}",
+                            "{}{}{} = {}",
                             self.args[0].to_polar(),
+                            dot,
                             call_term,
                             self.args[2].to_polar()
                         ),
@@ -510,6 +675,11 @@ mod to_polar {
                     self.operator.to_polar(),
                     to_polar_parens(self.operator, &self.args[0])
                 ),
+                Try => format!(
+                    "try {} else {}",
+                    to_polar_parens(self.operator, &self.args[0]),
+                    to_polar_parens(self.operator, &self.args[1])
+                ),
                 // Binary operators
                 Mul | Div | Mod | Rem | Add | Sub | Eq | Geq | Leq | Neq | Gt | Lt | Unify
                 | Isa | In | Assign => match self.args.len() {
@@ -552,11 +722,15 @@ mod to_polar {
 
     impl ToPolarString for Parameter {
         fn to_polar(&self) -> String {
-            match &self.specializer {
+            let param = match &self.specializer {
                 None => self.parameter.to_polar(),
                 Some(specializer) => {
                     format!("{}: {}", self.parameter.to_polar(), specializer.to_polar())
                 }
+            };
+            match &self.guard {
+                None => param,
+                Some(guard) => format!("{} where {}", param, guard.to_polar()),
             }
         }
     }
@@ -613,13 +787,17 @@ mod to_polar {
 
     impl ToPolarString for Symbol {
         fn to_polar(&self) -> String {
-            self.0.to_string()
+            self.to_string()
         }
     }
 
     impl ToPolarString for Term {
         fn to_polar(&self) -> String {
-            self.value().to_polar()
+            if self.is_sensitive() {
+                REDACTED.to_owned()
+            } else {
+                self.value().to_polar()
+            }
         }
     }
 
@@ -628,6 +806,7 @@ mod to_polar {
             match self {
                 Pattern::Dictionary(d) => d.to_polar(),
                 Pattern::Instance(i) => i.to_polar(),
+                Pattern::Predicate(name) => format!("@{}", name),
             }
         }
     }
@@ -652,10 +831,21 @@ mod to_polar {
                 Value::Variable(s) => s.to_polar(),
                 Value::RestVariable(s) => format!("*{}", s.to_polar()),
                 Value::Expression(e) => e.to_polar(),
+                Value::Lambda(l) => l.to_polar(),
             }
         }
     }
 
+    impl ToPolarString for Lambda {
+        fn to_polar(&self) -> String {
+            format!(
+                "|{}| {}",
+                format_args(Operator::And, &self.params, ", "),
+                self.body.to_polar()
+            )
+        }
+    }
+
     impl ToPolarString for ShorthandRule {
         fn to_polar(&self) -> String {
             let Self {
@@ -716,6 +906,35 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_term_formatter() {
+        let term = crate::parser::parse_query(
+            r#"f([1, 2, 3, 4], {key: "sk_live_abc", nested: [[1]]}, "not secret")"#,
+        )
+        .unwrap();
+        assert_eq!(TermFormatter::new().format(&term), term.to_string());
+
+        let formatter = TermFormatter::new().max_list_len(2).redact("sk_*");
+        assert_eq!(
+            formatter.format(&term),
+            r#"f([1, 2, ... 2 more], {key: <redacted>, nested: [[1]]}, "not secret")"#
+        );
+        let formatter = TermFormatter::new().max_depth(2);
+        assert_eq!(
+            formatter.format(&term),
+            r#"f([1, 2, 3, 4], {key: "sk_live_abc", nested: ...}, "not secret")"#
+        );
+        assert_eq!(TermFormatter::new().max_depth(0).format(&term), "...");
+        assert_eq!(TermFormatter::new().max_width(4).format(&term), "f([1...");
+
+        assert!(glob_match("sk_*", "sk_live"));
+        assert!(glob_match("*secret*", "a secret!"));
+        assert!(glob_match("a*b*c", "abc"));
+        assert!(!glob_match("a*b*c", "acb"));
+        assert!(!glob_match("ab*ba", "aba"));
+        assert!(!glob_match("sk", "sk_live"));
+    }
+
     #[test]
     fn test_source_lines() {
         let source = Source::new("hi");
@@ -0,0 +1,115 @@
+//! A process-wide table of symbol names, so that symbols compare and hash as integers.
+//!
+//! Each distinct name gets a `u32` id, shared by every symbol with that name. Names are
+//! reference counted: once no symbol uses a name, its entry is dropped from the table by the
+//! next sweep and its id is reused, so that gensym'd variables don't grow the table forever.
+//!
+//! The table is split into shards by the hash of the name, each with its own lock, so that
+//! threads making symbols, e.g., renaming the variables of each rule they call, rarely wait
+//! for each other. The low bits of an id are the shard of its name.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+
+/// Don't sweep tables smaller than this.
+const MIN_SWEEP_SIZE: usize = 1024;
+
+/// The number of shards of the table, a power of two.
+const SHARDS: usize = 64;
+const SHARD_BITS: u32 = SHARDS.trailing_zeros();
+
+struct Interner {
+    ids: HashMap<Arc<str>, u32>,
+    free: Vec<u32>,
+    next: u32,
+    sweep_at: usize,
+}
+
+impl Interner {
+    fn new() -> Self {
+        Self {
+            ids: HashMap::new(),
+            free: vec![],
+            next: 0,
+            sweep_at: MIN_SWEEP_SIZE,
+        }
+    }
+
+    fn intern(&mut self, name: &str) -> (u32, Arc<str>) {
+        if let Some((name, id)) = self.ids.get_key_value(name) {
+            return (*id, name.clone());
+        }
+        if self.ids.len() >= self.sweep_at {
+            self.sweep();
+        }
+        let id = self.free.pop().unwrap_or_else(|| {
+            let id = self.next;
+            assert!(id < u32::MAX >> SHARD_BITS, "too many symbols");
+            self.next += 1;
+            id
+        });
+        let name: Arc<str> = Arc::from(name);
+        self.ids.insert(name.clone(), id);
+        (id, name)
+    }
+
+    /// Drop the names that only the table still holds, and free their ids.
+    fn sweep(&mut self) {
+        let free = &mut self.free;
+        self.ids.retain(|name, id| {
+            let used = Arc::strong_count(name) > 1;
+            if !used {
+                free.push(*id);
+            }
+            used
+        });
+        self.sweep_at = MIN_SWEEP_SIZE.max(2 * self.ids.len());
+    }
+}
+
+/// Return the id of `name` and the shared copy of it.
+pub fn intern(name: &str) -> (u32, Arc<str>) {
+    static INTERNERS: OnceLock<[Mutex<Interner>; SHARDS]> = OnceLock::new();
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    let shard = hasher.finish() as usize % SHARDS;
+    let (id, name) = INTERNERS.get_or_init(|| std::array::from_fn(|_| Mutex::new(Interner::new())))
+        [shard]
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .intern(name);
+    ((id << SHARD_BITS) | shard as u32, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern() {
+        let mut interner = Interner::new();
+        let (a, a_name) = interner.intern("a");
+        let (b, _) = interner.intern("b");
+        assert_ne!(a, b);
+        assert_eq!(interner.intern("a").0, a);
+        assert_eq!(&*a_name, "a");
+
+        // Unused names are swept, and their ids reused.
+        interner.sweep();
+        assert_eq!(interner.ids.len(), 1);
+        assert_eq!(interner.intern("c").0, b);
+        assert_eq!(interner.intern("a").0, a);
+    }
+
+    #[test]
+    fn test_sharded_ids() {
+        let names = (0..1000).map(|i| format!("name{}", i)).collect::<Vec<_>>();
+        let mut ids = names.iter().map(|name| intern(name).0).collect::<Vec<_>>();
+        assert_eq!(intern("name0").0, ids[0]);
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), names.len());
+    }
+}
@@ -11,6 +11,7 @@ use crate::kb::Bindings;
 use crate::partial::simplify_bindings;
 use crate::runnable::Runnable;
 use crate::terms::{Operation, Operator, Term, Value};
+use crate::traces::Backtrace;
 use crate::vm::{Goals, PolarVirtualMachine};
 
 /// The inverter implements the `not` operation in Polar.
@@ -46,7 +47,7 @@ static ID: AtomicU64 = AtomicU64::new(0);
 
 impl Inverter {
     pub fn new(
-        vm: &PolarVirtualMachine,
+        vm: &mut PolarVirtualMachine,
         goals: Goals,
         add_constraints: Rc<RefCell<Bindings>>,
         bsp: Bsp,
@@ -241,6 +242,10 @@ impl Runnable for Inverter {
         self.vm.external_call_result(call_id, term)
     }
 
+    fn external_call_results(&mut self, call_id: u64, terms: Vec<Option<Term>>) -> PolarResult<()> {
+        self.vm.external_call_results(call_id, terms)
+    }
+
     fn debug_command(&mut self, command: &str) -> PolarResult<()> {
         self.vm.debug_command(command)
     }
@@ -252,4 +257,8 @@ impl Runnable for Inverter {
     fn handle_error(&mut self, error: PolarError) -> PolarResult<QueryEvent> {
         self.vm.handle_error(error)
     }
+
+    fn error_backtrace(&self) -> Option<&Backtrace> {
+        self.vm.error_backtrace()
+    }
 }
@@ -1,16 +1,172 @@
-use std::collections::{HashMap, HashSet};
-use std::fmt::Write;
-use std::sync::Arc;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt::{self, Write};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
 
 pub use super::bindings::Bindings;
 use super::constants::Constants;
-use super::counter::Counter;
+use super::counter::{Counter, MAX_PARTITION_BITS};
 use super::diagnostic::Diagnostic;
 use super::error::{invalid_state, PolarError, PolarResult, RuntimeError, ValidationError};
+use super::facts::FactStore;
+use super::fixes::{fix_param, SuggestedFix};
+use super::folder::Folder;
+use super::formatting::TermFormatter;
+use super::limits::Limits;
+use super::quota::ScopeQuota;
 use super::resource_block::{ResourceBlocks, ACTOR_UNION_NAME, RESOURCE_UNION_NAME};
+use super::rewrites::{
+    expand_rule_type_aliases, expand_type_aliases, fill_placeholders, TypeAlias,
+};
 use super::rules::*;
+use super::slices::{slice, ActionSlices, RuleSlice, SLICED_RULE};
 use super::terms::*;
-use super::validations::check_undefined_rule_calls;
+use super::validations::{
+    check_template_rule_calls, check_undefined_rule_calls, check_undefined_scope_rule_calls,
+};
+use super::warning::ValidationWarning;
+
+/// How often a warning is emitted about calls to each deprecated rule.
+const DEPRECATION_WARNING_INTERVAL_MS: u64 = 60_000;
+
+/// The rules that were in effect from `loaded_at` until the next epoch began.
+struct PolicyEpoch {
+    /// Milliseconds since the Unix epoch.
+    loaded_at: u64,
+    rules: HashMap<Symbol, GenericRule>,
+    resource_blocks: ResourceBlocks,
+    default_decision: Option<(Term, DefaultDecision)>,
+}
+
+/// A query written `?= query;` in a policy, to be run once the policy is loaded.
+#[derive(Clone, Debug)]
+pub struct InlineQuery {
+    pub term: Term,
+    /// The scope the query was loaded into with `Polar::load_scope`, which it runs in.
+    pub scope: Option<String>,
+}
+
+/// What adding a clause to the knowledge base does if its rule already has a clause that's the
+/// same but for the names of its variables, e.g., when overlapping policies are loaded.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateClauses {
+    /// Add it, so that its rule succeeds once more for each copy.
+    #[default]
+    Keep,
+    /// Add it, and warn about it.
+    Warn,
+    /// Don't add it.
+    Skip,
+}
+
+/// What `KnowledgeBase::merge` does when both knowledge bases define a scope, template,
+/// constant, type alias, or default decision of the same name differently.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum MergeConflicts {
+    /// Fail the merge, leaving the knowledge base unchanged.
+    #[default]
+    Error,
+    /// Keep the definition of the knowledge base merged into.
+    KeepOurs,
+    /// Replace it with the definition of the knowledge base merged from.
+    TakeTheirs,
+}
+
+/// Rules loaded under a name, e.g., one tenant's policy. Queries run in a scope see its rules
+/// in addition to the rules loaded without a scope.
+#[derive(Clone, Default)]
+pub struct Scope {
+    rules: HashMap<Symbol, GenericRule>,
+    templates: HashMap<String, Rule>,
+    /// The declared default decision, with the term `default` of its declaration.
+    default_decision: Option<(Term, DefaultDecision)>,
+}
+
+impl Scope {
+    /// Add a rule annotated with `@template(name)` as the template `name`.
+    pub fn add_template(&mut self, rule: Rule) -> PolarResult<()> {
+        add_template(&mut self.templates, rule)
+    }
+
+    /// Declare the default decision of the scope, e.g., from `default deny;`.
+    pub fn set_default_decision(
+        &mut self,
+        keyword: Term,
+        decision: DefaultDecision,
+    ) -> PolarResult<()> {
+        set_default_decision(&mut self.default_decision, keyword, decision)
+    }
+
+    pub fn add_rule(&mut self, rule: Rule) {
+        let generic_rule = self
+            .rules
+            .entry(rule.name.clone())
+            .or_insert_with(|| GenericRule::new(rule.name.clone(), vec![]));
+        generic_rule.add_rule(Arc::new(rule));
+    }
+
+    pub fn get_rules(&self) -> &HashMap<Symbol, GenericRule> {
+        &self.rules
+    }
+
+    pub fn get_generic_rule(&self, name: &Symbol) -> Option<&GenericRule> {
+        self.rules.get(name)
+    }
+}
+
+fn add_template(templates: &mut HashMap<String, Rule>, rule: Rule) -> PolarResult<()> {
+    let name = match rule.metadata.template.clone() {
+        Some(name) => name,
+        None => return invalid_state(format!("{} is not a template", rule.head_as_string())),
+    };
+    if templates.contains_key(&name) {
+        return Err(RuntimeError::InvalidTemplate {
+            template: name,
+            msg: "templates may only be defined once".to_owned(),
+        }
+        .into());
+    }
+    templates.insert(name, rule);
+    Ok(())
+}
+
+fn set_default_decision(
+    default_decision: &mut Option<(Term, DefaultDecision)>,
+    keyword: Term,
+    decision: DefaultDecision,
+) -> PolarResult<()> {
+    if default_decision.is_some() {
+        return Err(ValidationError::InvalidDefaultDecision {
+            term: keyword,
+            msg: "default decisions may only be declared once".to_owned(),
+        }
+        .into());
+    }
+    *default_decision = Some((keyword, decision));
+    Ok(())
+}
+
+/// Check that the rule a default decision allows by is defined, by one of `defined_rules`.
+fn check_default_decision<'a>(
+    default_decision: &Option<(Term, DefaultDecision)>,
+    mut defined_rules: impl Iterator<Item = &'a Symbol>,
+) -> Option<Diagnostic> {
+    match default_decision {
+        Some((term, DefaultDecision::AllowIf(rule)))
+            if !defined_rules.any(|defined| defined == rule) =>
+        {
+            Some(
+                PolarError::from(ValidationError::InvalidDefaultDecision {
+                    term: term.clone(),
+                    msg: format!("call to undefined rule {}", rule),
+                })
+                .into(),
+            )
+        }
+        _ => None,
+    }
+}
 
 enum RuleParamMatch {
     True,
@@ -24,6 +180,30 @@ impl RuleParamMatch {
     }
 }
 
+/// Error on attempts to assert facts for the "union" types (Actor & Resource) for the same
+/// reason as `register_constant`.
+fn check_fact_name(name: &Symbol) -> PolarResult<()> {
+    if name == ACTOR_UNION_NAME || name == RESOURCE_UNION_NAME {
+        return Err(RuntimeError::InvalidRegistration {
+            msg: format!("'{}' is a built-in specializer.", name),
+            sym: name.clone(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// IDs handed to the application are generational, so that stale IDs from another knowledge
+/// base are told apart from this one's.
+#[derive(Clone)]
+struct IdCounter(Counter);
+
+impl Default for IdCounter {
+    fn default() -> Self {
+        Self(Counter::generational())
+    }
+}
+
 #[derive(Default)]
 pub struct KnowledgeBase {
     /// A map of bindings: variable name → value. The VM uses a stack internally,
@@ -37,14 +217,77 @@ pub struct KnowledgeBase {
 
     rules: HashMap<Symbol, GenericRule>,
     rule_types: RuleTypes,
+    /// Rule templates by name, as parsed.
+    templates: HashMap<String, Rule>,
+    /// The default decision declared without a scope, with the term `default` of its
+    /// declaration.
+    default_decision: Option<(Term, DefaultDecision)>,
     /// For symbols returned from gensym.
     gensym_counter: Counter,
     /// For call IDs, instance IDs, symbols, etc.
-    id_counter: Counter,
-    pub inline_queries: Vec<Term>,
+    id_counter: IdCounter,
+    /// Inline queries waiting to be run, last first.
+    pub inline_queries: Vec<InlineQuery>,
 
     /// Resource block bookkeeping.
     pub resource_blocks: ResourceBlocks,
+
+    /// Type aliases by name, as declared.
+    type_aliases: HashMap<Symbol, TypeAlias>,
+
+    /// Names of rules that may be answered by a host fact source when the policy
+    /// contains no clauses for them.
+    fact_sources: HashSet<Symbol>,
+
+    /// Names of host predicates that may be used as specializers, written `@name`.
+    specializer_predicates: HashSet<Symbol>,
+
+    /// Names of methods that the host can call on many instances at once.
+    batched_methods: HashSet<Symbol>,
+
+    /// Ground facts asserted at runtime. Unlike rules, facts are not cleared when policies are
+    /// reloaded.
+    facts: FactStore,
+
+    /// When a warning about calling each deprecated rule was last emitted, in milliseconds
+    /// since the Unix epoch.
+    deprecation_warnings: Mutex<HashMap<Symbol, u64>>,
+
+    /// Rules specialized on the constant value of one parameter, as (arity, parameter) by rule
+    /// name. Unlike rules, these are not cleared when policies are reloaded.
+    hot_entrypoints: HashMap<Symbol, (usize, usize)>,
+
+    /// Rules loaded into named scopes. Unlike rules, scopes are not cleared when policies are
+    /// reloaded.
+    scopes: HashMap<String, Scope>,
+
+    /// The scopes whose rules each named scope may use. Like quotas, these are kept when a
+    /// scope is loaded again.
+    scope_includes: HashMap<String, Vec<String>>,
+
+    /// Quotas of named scopes, and the quota of scopes without their own.
+    scope_quotas: HashMap<String, ScopeQuota>,
+    default_scope_quota: ScopeQuota,
+
+    /// Snapshots of the rules each time a policy was loaded or cleared, oldest first. Unlike
+    /// rules, epochs are not cleared when policies are reloaded.
+    epochs: VecDeque<PolicyEpoch>,
+    /// How many epochs are kept for time-travel queries; none by default, since each one is a
+    /// copy of the rules.
+    max_policy_epochs: usize,
+
+    integer_overflow: IntegerOverflow,
+    duplicate_clauses: DuplicateClauses,
+    occurs_check: bool,
+    limits: Limits,
+    heartbeat_interval: Option<u64>,
+    term_formatter: TermFormatter,
+
+    /// The fingerprint of the rules and templates, until they change.
+    fingerprint: Mutex<Option<u64>>,
+    /// The slices of the rules for each action that `allow` rules name, and for other actions
+    /// by `None`, until the rules change.
+    action_slices: Mutex<Option<Arc<ActionSlices>>>,
 }
 
 impl KnowledgeBase {
@@ -52,16 +295,104 @@ impl KnowledgeBase {
         Self::default()
     }
 
-    /// Return a monotonically increasing integer ID.
+    /// Return a monotonically increasing integer ID, tagged with this knowledge base's
+    /// generation of IDs.
     ///
-    /// Wraps around at 52 bits of precision so that it can be safely
+    /// Fits in 53 bits of precision so that it can be safely
     /// coerced to an IEEE-754 double-float (f64).
     pub fn new_id(&self) -> u64 {
-        self.id_counter.next()
+        self.id_counter.0.next()
+    }
+
+    /// How queries handle integer arithmetic that overflows. Defaults to raising an error.
+    pub fn integer_overflow(&self) -> IntegerOverflow {
+        self.integer_overflow
+    }
+
+    pub fn set_integer_overflow(&mut self, mode: IntegerOverflow) {
+        self.integer_overflow = mode;
+    }
+
+    /// What adding a clause identical to one of its rule's clauses does. Defaults to adding it.
+    pub fn duplicate_clauses(&self) -> DuplicateClauses {
+        self.duplicate_clauses
+    }
+
+    pub fn set_duplicate_clauses(&mut self, mode: DuplicateClauses) {
+        self.duplicate_clauses = mode;
+    }
+
+    /// Whether unification checks that variables aren't bound to terms that contain them.
+    /// Defaults to `false`.
+    pub fn occurs_check(&self) -> bool {
+        self.occurs_check
+    }
+
+    pub fn set_occurs_check(&mut self, enabled: bool) {
+        self.occurs_check = enabled;
+    }
+
+    /// Limits on the nesting of policies and queries.
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// How many goals queries run between heartbeat events, if they emit them. Off by default.
+    pub fn heartbeat_interval(&self) -> Option<u64> {
+        self.heartbeat_interval
+    }
+
+    pub fn set_heartbeat_interval(&mut self, interval: Option<u64>) {
+        self.heartbeat_interval = interval;
+    }
+
+    pub fn set_max_policy_epochs(&mut self, max: usize) {
+        self.max_policy_epochs = max;
+        while self.epochs.len() > max {
+            self.epochs.pop_front();
+        }
+    }
+
+    /// How traces, stack traces, and logs format terms that aren't shown as policy source.
+    pub fn term_formatter(&self) -> &TermFormatter {
+        &self.term_formatter
+    }
+
+    pub fn set_term_formatter(&mut self, formatter: TermFormatter) {
+        self.term_formatter = formatter;
     }
 
     pub fn id_counter(&self) -> Counter {
-        self.id_counter.clone()
+        self.id_counter.0.clone()
+    }
+
+    /// Issue IDs from now on with `partition` in the high `bits` bits of their generation, so
+    /// that they don't collide with the IDs of knowledge bases in other partitions, e.g., in
+    /// other engines of a cluster that share application state. `bits` may be at most
+    /// `MAX_PARTITION_BITS`, and `partition` must fit in `bits` bits.
+    pub fn set_id_partition(&mut self, partition: u64, bits: u32) -> PolarResult<()> {
+        match Counter::partitioned(partition, bits) {
+            Some(counter) => {
+                self.id_counter = IdCounter(counter);
+                Ok(())
+            }
+            None => Err(RuntimeError::InvalidIdPartition {
+                msg: format!(
+                    "partition {} doesn't fit in {} bits, or more than {} bits were asked for",
+                    partition, bits, MAX_PARTITION_BITS
+                ),
+            }
+            .into()),
+        }
+    }
+
+    /// How many times IDs ran out, and a new generation of IDs was started.
+    pub fn id_wraps(&self) -> u64 {
+        self.id_counter.0.wraps()
     }
 
     /// Generate a temporary variable prefix from a variable name.
@@ -75,23 +406,220 @@ impl KnowledgeBase {
     /// Generate a new symbol.
     pub fn gensym(&self, prefix: &str) -> Symbol {
         let next = self.gensym_counter.next();
-        Symbol(format!("{}{}", Self::temp_prefix(prefix), next))
+        Symbol::from(format!("{}{}", Self::temp_prefix(prefix), next))
     }
 
     /// Add a generic rule to the knowledge base.
     #[cfg(test)]
     pub fn add_generic_rule(&mut self, rule: GenericRule) {
+        self.rules_changed();
         self.rules.insert(rule.name.clone(), rule);
     }
 
-    pub fn add_rule(&mut self, rule: Rule) {
-        let generic_rule = self
+    /// Add `rule`, unless it duplicates a clause of its rule and duplicates are skipped.
+    /// Returns a warning about the duplicate if they're warned about.
+    pub fn add_rule(&mut self, rule: Rule) -> Option<ValidationWarning> {
+        let duplicate = match self.duplicate_clauses {
+            DuplicateClauses::Keep => false,
+            _ => self.is_duplicate_clause(&rule),
+        };
+        match self.duplicate_clauses {
+            DuplicateClauses::Warn if duplicate => {
+                self.insert_rule(rule.clone());
+                Some(ValidationWarning::DuplicateClause { rule })
+            }
+            DuplicateClauses::Skip if duplicate => None,
+            _ => {
+                self.insert_rule(rule);
+                None
+            }
+        }
+    }
+
+    /// Whether a clause of the rule `rule.name` is the same as `rule` but for the names of
+    /// its variables.
+    fn is_duplicate_clause(&self, rule: &Rule) -> bool {
+        let generic_rule = match self.rules.get(&rule.name) {
+            Some(generic_rule) => generic_rule,
+            None => return false,
+        };
+        let mut candidates = generic_rule
             .rules
-            .entry(rule.name.clone())
-            .or_insert_with(|| GenericRule::new(rule.name.clone(), vec![]));
+            .values()
+            .filter(|clause| clause.params.len() == rule.params.len())
+            .peekable();
+        if candidates.peek().is_none() {
+            return false;
+        }
+        let canonical = self.canonical_rule(rule);
+        candidates.any(|clause| self.canonical_rule(clause) == canonical)
+    }
+
+    fn insert_rule(&mut self, rule: Rule) {
+        self.rules_changed();
+        let hot_entrypoint = self.hot_entrypoints.get(&rule.name);
+        let generic_rule = self.rules.entry(rule.name.clone()).or_insert_with(|| {
+            let mut generic_rule = GenericRule::new(rule.name.clone(), vec![]);
+            if let Some(&(arity, param)) = hot_entrypoint {
+                generic_rule.specialize(arity, param);
+            }
+            generic_rule
+        });
         generic_rule.add_rule(Arc::new(rule));
     }
 
+    /// Add a rule annotated with `@template(name)` as the template `name`.
+    pub fn add_template(&mut self, rule: Rule) -> PolarResult<()> {
+        self.rules_changed();
+        add_template(&mut self.templates, rule)
+    }
+
+    /// Declare the default decision of requests that no `allow` rule matches, e.g., from
+    /// `default deny;`.
+    pub fn set_default_decision(
+        &mut self,
+        keyword: Term,
+        decision: DefaultDecision,
+    ) -> PolarResult<()> {
+        set_default_decision(&mut self.default_decision, keyword, decision)
+    }
+
+    /// The decision of requests in `scope`, or without a scope if `scope` is `None`, that no
+    /// `allow` rule matches: the scope's default decision if it declares one, or else the
+    /// default decision loaded without a scope, if there is one.
+    pub fn default_decision(&self, scope: Option<&str>) -> Option<&DefaultDecision> {
+        scope
+            .and_then(|name| self.get_scope(name)?.default_decision.as_ref())
+            .or(self.default_decision.as_ref())
+            .map(|(_, decision)| decision)
+    }
+
+    /// The templates that may be instantiated in `scope`, or without a scope if `scope` is
+    /// `None`, sorted by name. A scope's templates hide those of the same name loaded without
+    /// a scope.
+    pub fn list_templates(&self, scope: Option<&str>) -> Vec<TemplateInfo> {
+        let scoped = scope.and_then(|name| Some((name, self.get_scope(name)?)));
+        let mut templates = self
+            .templates
+            .iter()
+            .filter(|(name, _)| !matches!(scoped, Some((_, s)) if s.templates.contains_key(*name)))
+            .map(|(name, rule)| (name, None, rule))
+            .chain(scoped.into_iter().flat_map(|(scope, s)| {
+                s.templates
+                    .iter()
+                    .map(move |(name, rule)| (name, Some(scope.to_owned()), rule))
+            }))
+            .map(|(name, scope, rule)| TemplateInfo {
+                name: name.clone(),
+                scope,
+                head: rule.head_as_string(),
+                placeholders: rule.placeholders(),
+                doc: rule.metadata.doc.clone(),
+                calls: rule.metadata.calls.clone(),
+            })
+            .collect::<Vec<_>>();
+        templates.sort_by(|a, b| a.name.cmp(&b.name));
+        templates
+    }
+
+    /// Make a rule from the template `name` by replacing each of its placeholders, like
+    /// `:role`, with the binding of the same name. Bindings must be plain values, such as
+    /// strings, numbers, and lists and dictionaries of them, so that they can't change the
+    /// structure of the rule. Every placeholder must have a binding, and every binding a
+    /// placeholder.
+    ///
+    /// The rule isn't added to the knowledge base; load it, e.g., into a scope, to use it.
+    pub fn instantiate_template(
+        &self,
+        name: &str,
+        bindings: HashMap<String, Term>,
+    ) -> PolarResult<Rule> {
+        self.instantiate_template_in(None, name, bindings)
+    }
+
+    /// Like `instantiate_template`, but also using the templates loaded into `scope`.
+    pub fn instantiate_template_in(
+        &self,
+        scope: Option<&str>,
+        name: &str,
+        bindings: HashMap<String, Term>,
+    ) -> PolarResult<Rule> {
+        let template = scope
+            .and_then(|scope| self.get_scope(scope)?.templates.get(name))
+            .or_else(|| self.templates.get(name))
+            .ok_or_else(|| RuntimeError::InvalidTemplate {
+                template: name.to_owned(),
+                msg: "no such template".to_owned(),
+            })?;
+        let mut names = bindings.keys().collect::<Vec<_>>();
+        names.sort();
+        if let Some(binding) = names.into_iter().find(|b| !bindings[*b].is_ground()) {
+            return Err(RuntimeError::InvalidTemplate {
+                template: name.to_owned(),
+                msg: format!("the binding for :{} is not a plain value", binding),
+            }
+            .into());
+        }
+
+        // Fill the head and body together, so that a placeholder only in the head counts.
+        let mut terms = vec![];
+        for param in &template.params {
+            terms.push(param.parameter.clone());
+            terms.extend(param.specializer.clone());
+            terms.extend(param.guard.clone());
+        }
+        terms.push(template.body.clone());
+        let filled =
+            fill_placeholders(template.body.clone_with_value(Value::List(terms)), bindings)?;
+        let mut filled = match filled.value() {
+            Value::List(terms) => terms.clone().into_iter(),
+            _ => return invalid_state("filled template is not a list"),
+        };
+        let mut rule = template.clone();
+        for param in &mut rule.params {
+            param.parameter = filled.next().unwrap();
+            if param.specializer.is_some() {
+                param.specializer = filled.next();
+            }
+            if param.guard.is_some() {
+                param.guard = filled.next();
+            }
+        }
+        rule.body = filled.next().unwrap();
+        rule.metadata.template = None;
+        Ok(rule)
+    }
+
+    /// Specialize the rule `name` of `arity` parameters on the constant value of parameter
+    /// `param`, e.g., `allow/3` on its action, for the rules loaded now and later.
+    pub fn add_hot_entrypoint(
+        &mut self,
+        name: Symbol,
+        arity: usize,
+        param: usize,
+    ) -> PolarResult<()> {
+        if param >= arity {
+            return Err(RuntimeError::InvalidRegistration {
+                msg: format!(
+                    "cannot specialize {}/{} on parameter {}: rules of {} parameters are numbered from 0 to {}",
+                    name,
+                    arity,
+                    param,
+                    arity,
+                    arity.saturating_sub(1)
+                ),
+                sym: name,
+            }
+            .into());
+        }
+        if let Some(generic_rule) = self.rules.get_mut(&name) {
+            generic_rule.specialize(arity, param);
+        }
+        self.hot_entrypoints.insert(name, (arity, param));
+        self.rules_changed();
+        Ok(())
+    }
+
     pub fn validate_rules(&self) -> Vec<Diagnostic> {
         // Prior to #1310 these validations were not order dependent due to the
         // use of static default rule types.
@@ -106,50 +634,70 @@ impl KnowledgeBase {
         }
 
         diagnostics.append(&mut check_undefined_rule_calls(self));
+        diagnostics.append(&mut self.check_template_calls(None, &self.rules));
+        diagnostics.extend(check_default_decision(
+            &self.default_decision,
+            self.rules
+                .keys()
+                .chain(self.get_fact_sources().iter())
+                .chain(self.get_fact_names()),
+        ));
+
+        diagnostics
+    }
 
+    /// Check that each of `rules` only calls the rules allowed by the `@calls` annotations of
+    /// the templates of its name and arity, if any have one. The templates are those loaded
+    /// into `scope` and those of other names loaded without a scope.
+    fn check_template_calls(
+        &self,
+        scope: Option<&Scope>,
+        rules: &HashMap<Symbol, GenericRule>,
+    ) -> Vec<Diagnostic> {
+        let scoped = scope.map(|scope| &scope.templates);
+        let templates = self
+            .templates
+            .iter()
+            .filter(|(name, _)| !matches!(scoped, Some(scoped) if scoped.contains_key(*name)))
+            .chain(scoped.into_iter().flatten())
+            .filter(|(_, template)| template.metadata.calls.is_some())
+            .collect::<Vec<_>>();
+        let mut diagnostics = vec![];
+        for generic_rule in rules.values() {
+            for rule in generic_rule.rules.values() {
+                let mut names = vec![];
+                let mut allowed = HashSet::new();
+                for (name, template) in &templates {
+                    if template.name == rule.name && template.params.len() == rule.params.len() {
+                        names.push((*name).clone());
+                        allowed.extend(template.metadata.calls.iter().flatten());
+                    }
+                }
+                if !names.is_empty() {
+                    names.sort();
+                    diagnostics.append(&mut check_template_rule_calls(rule, &allowed, &names));
+                }
+            }
+        }
         diagnostics
     }
 
     /// Validate that all rules loaded into the knowledge base are valid based on rule types.
     fn validate_rule_types(&self) -> PolarResult<()> {
         // For every rule, if there *is* a rule type, check that the rule matches the rule type.
-        for (rule_name, generic_rule) in &self.rules {
-            if let Some(types) = self.rule_types.get(rule_name) {
-                // If a type with the same name exists, then the parameters must match for each rule
-                for rule in generic_rule.rules.values() {
-                    let mut msg = "Must match one of the following rule types:\n".to_owned();
-
-                    let results = types
-                        .iter()
-                        .map(|rule_type| {
-                            self.rule_params_match(rule.as_ref(), rule_type)
-                                .map(|result| (result, rule_type))
-                        })
-                        .collect::<PolarResult<Vec<_>>>()?;
-                    let found_match = results.iter().any(|(result, rule_type)| match result {
-                        RuleParamMatch::True => true,
-                        RuleParamMatch::False(message) => {
-                            write!(
-                                msg,
-                                "\n{}\n\tFailed to match because: {}\n",
-                                rule_type, message
-                            )
-                            .unwrap();
-                            false
-                        }
-                    });
-                    if !found_match {
-                        let rule = Rule::clone(rule);
-                        return Err(ValidationError::InvalidRule { rule, msg }.into());
-                    }
-                }
+        for generic_rule in self.rules.values() {
+            for rule in generic_rule.rules.values() {
+                self.validate_rule_type(rule)?;
             }
         }
 
         // For every rule type that is *required*, see that there is at least one corresponding
-        // implementation.
+        // implementation. Rules answered by a host fact source are implemented outside the
+        // policy, as are rules with facts asserted at runtime.
         for rule_type in self.rule_types.required_rule_types() {
-            if let Some(GenericRule { rules, .. }) = self.rules.get(&rule_type.name) {
+            if self.is_fact_source(&rule_type.name) || self.has_facts(&rule_type.name) {
+                continue;
+            } else if let Some(GenericRule { rules, .. }) = self.rules.get(&rule_type.name) {
                 let mut found_match = false;
                 for rule in rules.values() {
                     found_match = self
@@ -172,6 +720,189 @@ impl KnowledgeBase {
         Ok(())
     }
 
+    /// If there are rule types with the same name as `rule`, check that its parameters match
+    /// one of them.
+    fn validate_rule_type(&self, rule: &Rule) -> PolarResult<()> {
+        let types = match self.rule_types.get(&rule.name) {
+            Some(types) => types,
+            None => return Ok(()),
+        };
+        let mut msg = "Must match one of the following rule types:\n".to_owned();
+
+        let results = types
+            .iter()
+            .map(|rule_type| {
+                self.rule_params_match(rule, rule_type)
+                    .map(|result| (result, rule_type))
+            })
+            .collect::<PolarResult<Vec<_>>>()?;
+        let found_match = results.iter().any(|(result, rule_type)| match result {
+            RuleParamMatch::True => true,
+            RuleParamMatch::False(message) => {
+                write!(
+                    msg,
+                    "\n{}\n\tFailed to match because: {}\n",
+                    rule_type, message
+                )
+                .unwrap();
+                false
+            }
+        });
+        if !found_match {
+            let fix = self.suggest_fix(rule, types);
+            if let Some(fix) = &fix {
+                write!(msg, "\nPerhaps you meant:\n\n\t{}\n", fix.head).unwrap();
+            }
+            let (rule, fix) = (rule.clone(), fix.map(Box::new));
+            return Err(ValidationError::InvalidRule { rule, msg, fix }.into());
+        }
+        Ok(())
+    }
+
+    /// The fewest parameter edits that make `rule` match one of `types`, if any do.
+    fn suggest_fix(&self, rule: &Rule, types: &[Rule]) -> Option<SuggestedFix> {
+        let mut best: Option<SuggestedFix> = None;
+        for rule_type in types {
+            if rule_type.params.len() != rule.params.len() {
+                continue;
+            }
+            let mut fixed = rule.clone();
+            let mut params = vec![];
+            for (i, (param, type_param)) in rule.params.iter().zip(&rule_type.params).enumerate() {
+                let matched = self.check_param(i + 1, param, type_param, rule_type);
+                if !matches!(matched, Ok(RuleParamMatch::True)) {
+                    let (fixed_param, fix) = fix_param(i + 1, param, type_param);
+                    fixed.params[i] = fixed_param;
+                    params.push(fix);
+                }
+            }
+            // Only suggest edits that make the rule match.
+            let matched = self.rule_params_match(&fixed, rule_type);
+            if matches!(matched, Ok(RuleParamMatch::True))
+                && !matches!(&best, Some(best) if best.params.len() <= params.len())
+            {
+                best = Some(SuggestedFix {
+                    rule_type: rule_type.head_as_string(),
+                    head: fixed.head_as_string(),
+                    params,
+                });
+            }
+        }
+        best
+    }
+
+    /// Validate the rules of `scope` against the rule types of this knowledge base, and check
+    /// that they only call rules defined in this knowledge base or in `scope`.
+    pub fn validate_scope(&self, name: &str, scope: &Scope) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        for generic_rule in scope.rules.values() {
+            for rule in generic_rule.rules.values() {
+                if let Err(e) = self.validate_rule_type(rule) {
+                    diagnostics.push(e.into());
+                    return diagnostics;
+                }
+            }
+        }
+        diagnostics.append(&mut check_undefined_scope_rule_calls(self, name, scope));
+        diagnostics.append(&mut self.check_template_calls(Some(scope), &scope.rules));
+        diagnostics.extend(check_default_decision(
+            &scope.default_decision,
+            scope
+                .rules
+                .keys()
+                .chain(self.rules.keys())
+                .chain(self.get_fact_sources().iter())
+                .chain(self.get_fact_names()),
+        ));
+        diagnostics
+    }
+
+    /// Add the scope `name`, replacing any existing scope with that name.
+    pub fn set_scope(&mut self, name: &str, scope: Scope) {
+        self.scopes.insert(name.to_owned(), scope);
+    }
+
+    pub fn get_scope(&self, name: &str) -> Option<&Scope> {
+        self.scopes.get(name)
+    }
+
+    /// Let queries in the scope `name` use the rules of the scopes `includes` as well as its
+    /// own. Only the rules of `includes` themselves are used, not those of the scopes they
+    /// include.
+    pub fn set_scope_includes(&mut self, name: &str, includes: Vec<String>) -> PolarResult<()> {
+        if includes.iter().any(|included| included == name) {
+            return Err(RuntimeError::InvalidScope {
+                scope: name.to_owned(),
+                msg: "scopes may not include themselves".to_owned(),
+            }
+            .into());
+        }
+        self.scope_includes.insert(name.to_owned(), includes);
+        Ok(())
+    }
+
+    /// The loaded scopes whose rules queries in the scope `name` use besides its own: the
+    /// scopes it's nested in, innermost first, e.g., `a::b` and `a` for `a::b::c`, followed by
+    /// the scopes it includes.
+    pub fn enclosing_scopes(&self, name: &str) -> Vec<&Scope> {
+        let mut scopes = vec![];
+        let mut path = name;
+        while let Some((parent, _)) = path.rsplit_once("::") {
+            scopes.extend(self.get_scope(parent));
+            path = parent;
+        }
+        let included = self.scope_includes.get(name).into_iter().flatten();
+        for scope in included.filter_map(|included| self.get_scope(included)) {
+            if !scopes.iter().any(|s| std::ptr::eq(*s, scope)) {
+                scopes.push(scope);
+            }
+        }
+        scopes
+    }
+
+    /// Remove the scope `name` with its rules, templates, and default decision, along with its
+    /// quota and includes, so that queries in the scope only see the rules loaded without a
+    /// scope. Fails if other scopes include it, unless `force` is set, in which case they
+    /// include it again if it's loaded again. Return `false` if there was no such scope.
+    pub fn remove_scope(&mut self, name: &str, force: bool) -> PolarResult<bool> {
+        let mut includers = self
+            .scope_includes
+            .iter()
+            .filter(|(_, includes)| includes.iter().any(|included| included == name))
+            .map(|(includer, _)| includer.as_str())
+            .collect::<Vec<_>>();
+        if !force && !includers.is_empty() {
+            includers.sort_unstable();
+            return Err(RuntimeError::InvalidScope {
+                scope: name.to_owned(),
+                msg: format!("still included by {}", includers.join(", ")),
+            }
+            .into());
+        }
+        self.scope_quotas.remove(name);
+        self.scope_includes.remove(name);
+        self.inline_queries
+            .retain(|query| query.scope.as_deref() != Some(name));
+        Ok(self.scopes.remove(name).is_some())
+    }
+
+    /// Limit the policy loaded into the scope `name` and the queries run in it. The quota is
+    /// checked the next time the scope is loaded, not against the rules it has now.
+    pub fn set_scope_quota(&mut self, name: &str, quota: ScopeQuota) {
+        self.scope_quotas.insert(name.to_owned(), quota);
+    }
+
+    /// Limit the scopes without a quota of their own.
+    pub fn set_default_scope_quota(&mut self, quota: ScopeQuota) {
+        self.default_scope_quota = quota;
+    }
+
+    pub fn scope_quota(&self, name: &str) -> &ScopeQuota {
+        self.scope_quotas
+            .get(name)
+            .unwrap_or(&self.default_scope_quota)
+    }
+
     /// Determine whether the fields of a rule parameter specializer match the fields of a type parameter specializer.
     /// Rule fields match if they are a superset of type fields and all field values are equal.
     // TODO: once field-level specializers are working this should be updated so
@@ -251,8 +982,8 @@ impl KnowledgeBase {
                     } else {
                         RuleParamMatch::False(format!("Rule specializer {} on parameter {} did not match rule type specializer {} because the specializer fields did not match.", rule_instance, index, rule_type_instance))
                     }
-                } else if self.is_union(&term!(sym!(&rule_type_instance.tag.0))) {
-                    if self.is_union(&term!(sym!(&rule_instance.tag.0))) {
+                } else if self.is_union(&term!(sym!(rule_type_instance.tag.as_str()))) {
+                    if self.is_union(&term!(sym!(rule_instance.tag.as_str()))) {
                         // If both specializers are the same union, check fields.
                         if rule_instance.tag == rule_type_instance.tag {
                             if self.param_fields_match(
@@ -270,10 +1001,10 @@ impl KnowledgeBase {
                         }
                     }
 
-                    let members = self.get_union_members(&term!(sym!(&rule_type_instance.tag.0)));
+                    let members = self.get_union_members(&term!(sym!(rule_type_instance.tag.as_str())));
                     // If the rule specializer is not a direct member of the union, we still need
                     // to check if it's a subclass of any member of the union.
-                    if !members.contains(&term!(sym!(&rule_instance.tag.0))) {
+                    if !members.contains(&term!(sym!(rule_instance.tag.as_str()))) {
                         let mut success = false;
                         for member in members {
                             // Turn `member` into an `InstanceLiteral` by copying fields from
@@ -290,13 +1021,13 @@ impl KnowledgeBase {
                         }
                         if !success {
                             let mut err = format!("Rule specializer {} on parameter {} must be a member of rule type specializer {}", rule_instance.tag,index, rule_type_instance.tag);
-                            if rule_type_instance.tag.0 == ACTOR_UNION_NAME {
+                            if rule_type_instance.tag == ACTOR_UNION_NAME {
                                 write!(err, "
 
 \tPerhaps you meant to add an actor block to the top of your policy, like this:
 
 \t  actor {} {{}}", rule_instance.tag).unwrap();
-                            } else if rule_type_instance.tag.0 == RESOURCE_UNION_NAME {
+                            } else if rule_type_instance.tag == RESOURCE_UNION_NAME {
                                 write!(err, "
 
 \tPerhaps you meant to add a resource block to your policy, like this:
@@ -339,6 +1070,7 @@ impl KnowledgeBase {
                     RuleParamMatch::False(format!("Specializer mismatch on parameter {}. Rule specializer fields {:#?} do not match rule type specializer fields {:#?}.", index, rule_fields, rule_type_fields))
                 }
             }
+            (Pattern::Predicate(rule_type_name), Pattern::Predicate(rule_name)) if rule_type_name == rule_name => RuleParamMatch::True,
             (_, _) => {
                 RuleParamMatch::False(format!("Mismatch on parameter {}. Rule parameter {:#?} does not match rule type parameter {:#?}.", index, rule_type_pattern, rule_pattern))
             }
@@ -486,6 +1218,12 @@ impl KnowledgeBase {
                                 ))
                             }
                         }
+                        // Rule type specializer is a host predicate, which values can't be
+                        // checked against until the rule is called.
+                        Pattern::Predicate(name) => RuleParamMatch::False(format!(
+                            "Invalid parameter {}. Rule type expected a value matching @{}, got {}.",
+                            index, name, rule_value
+                        )),
                     }
                 }
 
@@ -563,16 +1301,154 @@ impl KnowledgeBase {
         self.rules.get(name)
     }
 
+    /// Return whether a warning about calling the deprecated rule `name` should be emitted at
+    /// `now_ms`, recording it if so. Each rule is warned about at most once per
+    /// `DEPRECATION_WARNING_INTERVAL_MS`.
+    pub fn should_warn_deprecated(&self, name: &Symbol, now_ms: u64) -> bool {
+        let mut warnings = self.deprecation_warnings.lock().unwrap();
+        match warnings.get(name) {
+            Some(last) if now_ms.saturating_sub(*last) < DEPRECATION_WARNING_INTERVAL_MS => false,
+            _ => {
+                warnings.insert(name.clone(), now_ms);
+                true
+            }
+        }
+    }
+
     pub fn add_rule_type(&mut self, rule_type: Rule) {
         self.rule_types.add(rule_type);
     }
 
+    /// Declare the type alias `name` for `members`, e.g., from `type Resource = Repo | Org;`.
+    /// Aliases are expanded by `expand_type_aliases` once the policy is loaded, so members may
+    /// be declared after the alias.
+    pub fn add_type_alias(&mut self, name: Term, members: Vec<Symbol>) -> PolarResult<()> {
+        let invalid = |msg: &str| -> PolarResult<()> {
+            Err(ValidationError::InvalidTypeAlias {
+                alias: name.clone(),
+                msg: msg.to_owned(),
+            }
+            .into())
+        };
+        let symbol = name.as_symbol()?.clone();
+        if symbol == ACTOR_UNION_NAME || symbol == RESOURCE_UNION_NAME {
+            return invalid("the name of a built-in union can't be an alias");
+        } else if self.is_constant(&symbol) {
+            return invalid("the name of a registered class or constant can't be an alias");
+        } else if self.type_aliases.contains_key(&symbol) {
+            return invalid("type aliases may only be declared once");
+        }
+        self.type_aliases
+            .insert(symbol, TypeAlias { name, members });
+        Ok(())
+    }
+
+    pub fn is_type_alias(&self, name: &Symbol) -> bool {
+        self.type_aliases.contains_key(name)
+    }
+
+    /// The classes that each type alias stands for, in the order they were declared, with the
+    /// members that are aliases replaced by their classes. Aliases that are members of
+    /// themselves are errors.
+    fn type_alias_classes(&self) -> (HashMap<Symbol, Vec<Symbol>>, Vec<PolarError>) {
+        fn resolve(
+            aliases: &HashMap<Symbol, TypeAlias>,
+            name: &Symbol,
+            visiting: &mut Vec<Symbol>,
+            classes: &mut Vec<Symbol>,
+        ) -> bool {
+            if visiting.contains(name) {
+                return false;
+            }
+            visiting.push(name.clone());
+            for member in &aliases[name].members {
+                if aliases.contains_key(member) {
+                    if !resolve(aliases, member, visiting, classes) {
+                        return false;
+                    }
+                } else if !classes.contains(member) {
+                    classes.push(member.clone());
+                }
+            }
+            visiting.pop();
+            true
+        }
+
+        let mut resolved = HashMap::new();
+        let mut errors = vec![];
+        for (name, alias) in &self.type_aliases {
+            let mut classes = vec![];
+            if resolve(&self.type_aliases, name, &mut vec![], &mut classes) {
+                resolved.insert(name.clone(), classes);
+            } else {
+                errors.push(
+                    ValidationError::InvalidTypeAlias {
+                        alias: alias.name.clone(),
+                        msg: "a type alias can't be a member of itself".to_owned(),
+                    }
+                    .into(),
+                );
+            }
+        }
+        (resolved, errors)
+    }
+
+    /// Replace the rules and rule types that use type aliases with the rules they stand for,
+    /// after a policy is loaded.
+    pub fn expand_type_aliases(&mut self) -> Vec<PolarError> {
+        if self.type_aliases.is_empty() {
+            return vec![];
+        }
+        let (classes, errors) = self.type_alias_classes();
+        if !errors.is_empty() {
+            return errors;
+        }
+
+        let mut expanded = vec![];
+        for (name, generic_rule) in &self.rules {
+            let mut ids = generic_rule.rules.keys().collect::<Vec<_>>();
+            ids.sort();
+            let rules = ids
+                .into_iter()
+                .map(|id| &generic_rule.rules[id])
+                .map(|rule| match expand_rule_type_aliases(rule, &classes) {
+                    Some(rules) => (true, rules.into_iter().map(Arc::new).collect()),
+                    None => (false, vec![rule.clone()]),
+                })
+                .collect::<Vec<_>>();
+            if rules.iter().any(|(expanded, _)| *expanded) {
+                let rules = rules.into_iter().flat_map(|(_, rules)| rules).collect();
+                expanded.push((name.clone(), rules));
+            }
+        }
+        for (name, rules) in expanded {
+            let mut generic_rule = GenericRule::new(name.clone(), rules);
+            if let Some(&(arity, param)) = self.hot_entrypoints.get(&name) {
+                generic_rule.specialize(arity, param);
+            }
+            self.rules.insert(name, generic_rule);
+        }
+        self.rule_types
+            .expand(|rule_type| expand_rule_type_aliases(rule_type, &classes));
+        self.rules_changed();
+        vec![]
+    }
+
+    /// Expand the type aliases in `matches` operations in `term`, e.g., a query.
+    pub fn expand_term_type_aliases(&self, term: Term) -> Term {
+        if self.type_aliases.is_empty() {
+            return term;
+        }
+        let (classes, _) = self.type_alias_classes();
+        expand_type_aliases(term, &classes)
+    }
+
     /// Define a constant variable.
     ///
     /// Error on attempts to register the "union" types (Actor & Resource) since those types have
     /// special meaning in policies that use resource blocks.
     pub fn register_constant(&mut self, name: Symbol, value: Term) -> PolarResult<()> {
-        if name.0 == ACTOR_UNION_NAME || name.0 == RESOURCE_UNION_NAME {
+        if name == ACTOR_UNION_NAME || name == RESOURCE_UNION_NAME {
             return Err(RuntimeError::InvalidRegistration {
                 msg: format!("'{}' is a built-in specializer.", name),
                 sym: name,
@@ -632,6 +1508,88 @@ impl KnowledgeBase {
         })
     }
 
+    /// Register `name` as a rule that the host can answer with facts from an external
+    /// data source.
+    ///
+    /// Error on attempts to register the "union" types (Actor & Resource) for the same reason
+    /// as `register_constant`.
+    pub fn register_fact_source(&mut self, name: Symbol) -> PolarResult<()> {
+        if name == ACTOR_UNION_NAME || name == RESOURCE_UNION_NAME {
+            return Err(RuntimeError::InvalidRegistration {
+                msg: format!("'{}' is a built-in specializer.", name),
+                sym: name,
+            }
+            .into());
+        }
+        self.fact_sources.insert(name);
+        Ok(())
+    }
+
+    /// Return true if a fact source has been registered for the rule `name`.
+    pub fn is_fact_source(&self, name: &Symbol) -> bool {
+        self.fact_sources.contains(name)
+    }
+
+    pub fn get_fact_sources(&self) -> &HashSet<Symbol> {
+        &self.fact_sources
+    }
+
+    /// Register `name` as a host predicate that may be used as a specializer, e.g.,
+    /// `allow(actor: @verified_email, "read", _)`. Queries ask the host whether each argument
+    /// matches with `QueryEvent::ExternalSpecializer`.
+    pub fn register_specializer_predicate(&mut self, name: Symbol) {
+        self.specializer_predicates.insert(name);
+    }
+
+    /// Return true if `name` has been registered as a specializer predicate.
+    pub fn is_specializer_predicate(&self, name: &Symbol) -> bool {
+        self.specializer_predicates.contains(name)
+    }
+
+    /// Register `name` as a method that the host can call on many instances at once. Queries
+    /// that call it on an element of a list of instances emit `QueryEvent::ExternalCallBatch`
+    /// for all the instances of the list instead of one `QueryEvent::ExternalCall` each.
+    pub fn register_batched_method(&mut self, name: Symbol) {
+        self.batched_methods.insert(name);
+    }
+
+    pub fn batched_methods(&self) -> &HashSet<Symbol> {
+        &self.batched_methods
+    }
+
+    /// Assert the ground fact `name(args)`. Returns `false` if the fact was already present.
+    pub fn insert_fact(&mut self, name: Symbol, args: TermList) -> PolarResult<bool> {
+        check_fact_name(&name)?;
+        self.facts.insert(name, args)
+    }
+
+    /// Assert many ground facts for `name` at once. Returns the number of facts that weren't
+    /// already present.
+    pub fn insert_facts(&mut self, name: Symbol, facts: Vec<TermList>) -> PolarResult<usize> {
+        check_fact_name(&name)?;
+        self.facts.insert_all(name, facts)
+    }
+
+    /// Retract the ground fact `name(args)`. Returns `false` if there was no such fact.
+    pub fn delete_fact(&mut self, name: &Symbol, args: &TermList) -> bool {
+        self.facts.delete(name, args)
+    }
+
+    /// Return true if facts have been asserted for the rule `name`.
+    pub fn has_facts(&self, name: &Symbol) -> bool {
+        self.facts.contains(name)
+    }
+
+    /// Return the facts for `name` that may unify with `args`, or `None` if no facts have been
+    /// asserted for `name`.
+    pub fn get_facts(&self, name: &Symbol, args: &[Term]) -> Option<Vec<TermList>> {
+        self.facts.get(name, args)
+    }
+
+    pub fn get_fact_names(&self) -> impl Iterator<Item = &Symbol> {
+        self.facts.names()
+    }
+
     /// Add the Method Resolution Order (MRO) list for a registered class.
     /// The `mro` argument is a list of the `instance_id` associated with a registered class.
     pub fn add_mro(&mut self, name: Symbol, mro: Vec<u64>) -> PolarResult<()> {
@@ -646,9 +1604,229 @@ impl KnowledgeBase {
     pub fn clear_rules(&mut self) {
         self.rules.clear();
         self.rule_types.reset();
+        self.templates.clear();
+        self.default_decision = None;
         self.inline_queries.clear();
         self.loaded_content.clear();
         self.resource_blocks.clear();
+        self.type_aliases.clear();
+        self.deprecation_warnings.lock().unwrap().clear();
+        self.rules_changed();
+    }
+
+    /// Replace the rules, and everything else that `clear_rules` clears, with those of
+    /// `staged`, a copy of this knowledge base without its rules that a policy was loaded into.
+    pub(crate) fn replace_rules(&mut self, staged: KnowledgeBase) {
+        self.rules = staged.rules;
+        self.rule_types = staged.rule_types;
+        self.templates = staged.templates;
+        self.default_decision = staged.default_decision;
+        self.inline_queries = staged.inline_queries;
+        self.loaded_content = staged.loaded_content;
+        self.resource_blocks = staged.resource_blocks;
+        self.type_aliases = staged.type_aliases;
+        self.deprecation_warnings.lock().unwrap().clear();
+        self.rules_changed();
+    }
+
+    /// Combine `other` into this knowledge base, e.g., to deploy policies compiled by separate
+    /// build steps together. The clauses of each rule and the rule types are combined as if
+    /// the policies had been loaded together, except that clauses that are the same as one
+    /// already here but for the names of their variables are skipped, so that a policy
+    /// compiled into both isn't duplicated. Scopes, templates, constants, type aliases, and the
+    /// default decision are taken from `other` unless this knowledge base also defines one of
+    /// the same name, in which case `conflicts` decides. Only scopes conflict even if they're
+    /// the same.
+    ///
+    /// Facts, quotas, and inline queries aren't merged. Constants naming application classes
+    /// or instances are only meaningful if both knowledge bases were built by the same host.
+    pub fn merge(&mut self, other: &KnowledgeBase, conflicts: MergeConflicts) -> PolarResult<()> {
+        // Whether to take `theirs` over `ours`, or fail the merge.
+        let take = |kind: &str, name: &dyn fmt::Display, differ: bool| -> PolarResult<bool> {
+            match conflicts {
+                _ if !differ => Ok(false),
+                MergeConflicts::Error => Err(RuntimeError::MergeConflict {
+                    kind: kind.to_owned(),
+                    name: name.to_string(),
+                }
+                .into()),
+                MergeConflicts::KeepOurs => Ok(false),
+                MergeConflicts::TakeTheirs => Ok(true),
+            }
+        };
+
+        // Decide everything before changing anything, so that a conflict leaves `self` as it
+        // was.
+        let mut scopes = vec![];
+        for (name, scope) in &other.scopes {
+            if !self.scopes.contains_key(name) || take("scope", name, true)? {
+                scopes.push((name.clone(), scope.clone()));
+            }
+        }
+        let mut templates = vec![];
+        for (name, template) in &other.templates {
+            let differ = matches!(self.templates.get(name), Some(ours) if ours != template);
+            if !self.templates.contains_key(name) || take("template", name, differ)? {
+                templates.push((name.clone(), template.clone()));
+            }
+        }
+        let mut constants = vec![];
+        for (name, value) in &other.constants.symbol_to_term {
+            let differ = matches!(self.constants.get(name), Some(ours) if ours != value);
+            if !self.constants.contains_key(name) || take("constant", name, differ)? {
+                constants.push((name.clone(), value.clone()));
+            }
+        }
+        let mut type_aliases = vec![];
+        for (name, alias) in &other.type_aliases {
+            let differ = matches!(
+                self.type_aliases.get(name),
+                Some(ours) if ours.members != alias.members
+            );
+            if !self.type_aliases.contains_key(name) || take("type alias", name, differ)? {
+                type_aliases.push((name.clone(), alias.clone()));
+            }
+        }
+        let default_decision = match (&self.default_decision, &other.default_decision) {
+            (_, None) => None,
+            (None, theirs) => theirs.clone(),
+            (Some((_, ours)), Some((term, theirs))) => {
+                let differ = ours != theirs;
+                take("default decision", term, differ)?.then(|| (term.clone(), theirs.clone()))
+            }
+        };
+
+        for (name, value) in constants {
+            self.register_constant(name, value)?;
+        }
+        for generic_rule in other.rules.values() {
+            let mut ids = generic_rule.rules.keys().collect::<Vec<_>>();
+            ids.sort();
+            for id in ids {
+                let rule = &generic_rule.rules[id];
+                if !self.is_duplicate_clause(rule) {
+                    self.insert_rule(rule.as_ref().clone());
+                }
+            }
+        }
+        let rule_types = other
+            .rule_types
+            .iter()
+            .filter(|theirs| {
+                !matches!(self.rule_types.get(&theirs.name), Some(ours) if ours.contains(theirs))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        for rule_type in rule_types {
+            self.rule_types.add(rule_type);
+        }
+        self.templates.extend(templates);
+        self.type_aliases.extend(type_aliases);
+        if default_decision.is_some() {
+            self.default_decision = default_decision;
+        }
+        self.scopes.extend(scopes);
+        self.rules_changed();
+        Ok(())
+    }
+
+    /// Forget what was computed from the rules.
+    fn rules_changed(&mut self) {
+        *self.fingerprint.get_mut().unwrap() = None;
+        *self.action_slices.get_mut().unwrap() = None;
+    }
+
+    /// The rules that a query `allow(actor, action, resource)` could use.
+    pub fn action_slice(&self, action: &Value) -> Arc<RuleSlice> {
+        let slices = self.action_slices();
+        slices
+            .get(&Some(action.clone()))
+            .unwrap_or(&slices[&None])
+            .clone()
+    }
+
+    /// The slices for every action that `allow` rules name, and for other actions by `None`,
+    /// computed the first time they're asked for after the rules change.
+    pub fn action_slices(&self) -> Arc<ActionSlices> {
+        self.action_slices
+            .lock()
+            .unwrap()
+            .get_or_insert_with(|| {
+                let allow = self.get_generic_rule(&Symbol::new(SLICED_RULE));
+                let actions = allow
+                    .into_iter()
+                    .flat_map(|generic_rule| generic_rule.rules.values())
+                    .filter(|rule| rule.params.len() == 3 && rule.params[1].is_ground())
+                    .map(|rule| Some(rule.params[1].parameter.value().clone()))
+                    .chain([None])
+                    .collect::<HashSet<_>>();
+                let slices = actions
+                    .into_iter()
+                    .map(|action| {
+                        let slice = Arc::new(slice(self, action.as_ref()));
+                        (action, slice)
+                    })
+                    .collect();
+                Arc::new(slices)
+            })
+            .clone()
+    }
+
+    pub(crate) fn hot_entrypoint(&self, name: &Symbol) -> Option<(usize, usize)> {
+        self.hot_entrypoints.get(name).copied()
+    }
+
+    /// A hash of the rules and templates, e.g., to tell which version of a policy made a
+    /// decision. Policies hash the same however their rules are ordered and their variables
+    /// are named, and in every process.
+    pub fn fingerprint(&self) -> u64 {
+        *self.fingerprint.lock().unwrap().get_or_insert_with(|| {
+            let canonical = |rule: &Rule| self.canonical_rule(rule);
+            let mut rules = self
+                .rules
+                .values()
+                .flat_map(|generic_rule| generic_rule.rules.values())
+                .map(|rule| canonical(rule))
+                .collect::<Vec<_>>();
+            rules.sort();
+            let mut templates = self
+                .templates
+                .iter()
+                .map(|(name, rule)| format!("{}: {}", name, canonical(rule)))
+                .collect::<Vec<_>>();
+            templates.sort();
+
+            let mut hasher = DefaultHasher::new();
+            rules.hash(&mut hasher);
+            templates.hash(&mut hasher);
+            hasher.finish()
+        })
+    }
+
+    /// The rules of each generic rule, in the order they're tried, with their variables named
+    /// as for `fingerprint`. Knowledge bases with the same resolution order answer every query
+    /// the same way, in the same order.
+    pub fn resolution_order(&self) -> BTreeMap<Symbol, Vec<String>> {
+        self.rules
+            .iter()
+            .map(|(name, generic_rule)| {
+                let mut ids = generic_rule.rules.keys().collect::<Vec<_>>();
+                ids.sort();
+                let rules = ids
+                    .into_iter()
+                    .map(|id| self.canonical_rule(&generic_rule.rules[id]))
+                    .collect();
+                (name.clone(), rules)
+            })
+            .collect()
+    }
+
+    fn canonical_rule(&self, rule: &Rule) -> String {
+        let mut names = CanonicalNames {
+            kb: self,
+            names: HashMap::new(),
+        };
+        names.fold_rule(rule.clone()).to_string()
     }
 
     // TODO(gj): Remove this fn & `FileLoading` error variant. These checks don't spark joy.
@@ -804,9 +1982,9 @@ impl KnowledgeBase {
         }
 
         let mut rule_types = rule_types_to_create.into_iter().map(|((subject, relation, object), required)| {
-            let subject_specializer = pattern!(instance!(&subject.as_symbol()?.0));
+            let subject_specializer = pattern!(instance!(subject.as_symbol()?.as_str()));
             let relation_name = relation.as_string()?;
-            let object_specializer = pattern!(instance!(&object.as_symbol()?.0));
+            let object_specializer = pattern!(instance!(object.as_symbol()?.as_str()));
 
             let name = sym!("has_relation");
             let mut params = args!("subject"; subject_specializer, relation_name, "object"; object_specializer);
@@ -814,7 +1992,7 @@ impl KnowledgeBase {
             let body = term!(op!(And));
             // Copy SourceInfo from implier or relation in shorthand rule.
             let source_info = relation.source_info().clone();
-            Ok(Rule { name, params, body, source_info, required })
+            Ok(Rule { name, params, body, source_info, required, metadata: Default::default() })
         }).collect::<PolarResult<Vec<_>>>()?;
 
         // If there are any Relation::Role declarations in *any* of our resource
@@ -852,6 +2030,97 @@ impl KnowledgeBase {
     pub fn has_rules(&self) -> bool {
         !self.rules.is_empty()
     }
+
+    /// Record the current rules as the policy in effect from `loaded_at` (in milliseconds since
+    /// the Unix epoch) onwards.
+    /// Older epochs are forgotten once there are more than `max_policy_epochs`.
+    pub fn record_epoch(&mut self, loaded_at: u64) {
+        if self.max_policy_epochs == 0 {
+            return;
+        }
+        if self.epochs.len() == self.max_policy_epochs {
+            self.epochs.pop_front();
+        }
+        self.epochs.push_back(PolicyEpoch {
+            loaded_at,
+            rules: self.rules.clone(),
+            resource_blocks: self.resource_blocks.clone(),
+            default_decision: self.default_decision.clone(),
+        });
+    }
+
+    /// The times at which each recorded policy epoch began, oldest first.
+    pub fn epoch_timestamps(&self) -> Vec<u64> {
+        self.epochs.iter().map(|epoch| epoch.loaded_at).collect()
+    }
+
+    /// Build a knowledge base for evaluating queries against the policy that was in effect at
+    /// `at`. Registered constants, MROs, and facts are the current ones; only rules and the
+    /// default decision are versioned. Returns `None` if `at` is before the oldest recorded
+    /// epoch.
+    pub fn snapshot_at(&self, at: u64) -> Option<KnowledgeBase> {
+        let epoch = self
+            .epochs
+            .iter()
+            .rev()
+            .find(|epoch| epoch.loaded_at <= at)?;
+        Some(KnowledgeBase {
+            rules: epoch.rules.clone(),
+            resource_blocks: epoch.resource_blocks.clone(),
+            default_decision: epoch.default_decision.clone(),
+            ..self.without_rules()
+        })
+    }
+
+    /// Build a knowledge base with the same constants, MROs, and facts as this one, but no
+    /// rules. IDs are shared with this knowledge base so that the two don't collide.
+    pub(crate) fn without_rules(&self) -> KnowledgeBase {
+        KnowledgeBase {
+            constants: self.constants.clone(),
+            mro: self.mro.clone(),
+            gensym_counter: self.gensym_counter.clone(),
+            id_counter: self.id_counter.clone(),
+            fact_sources: self.fact_sources.clone(),
+            specializer_predicates: self.specializer_predicates.clone(),
+            batched_methods: self.batched_methods.clone(),
+            facts: self.facts.clone(),
+            integer_overflow: self.integer_overflow,
+            duplicate_clauses: self.duplicate_clauses,
+            occurs_check: self.occurs_check,
+            limits: self.limits,
+            hot_entrypoints: self.hot_entrypoints.clone(),
+            heartbeat_interval: self.heartbeat_interval,
+            term_formatter: self.term_formatter.clone(),
+            scope_includes: self.scope_includes.clone(),
+            scope_quotas: self.scope_quotas.clone(),
+            default_scope_quota: self.default_scope_quota.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Rename the variables of a rule in the order they appear, so that rules that differ only in
+/// the names of their variables are the same.
+struct CanonicalNames<'kb> {
+    kb: &'kb KnowledgeBase,
+    names: HashMap<Symbol, Symbol>,
+}
+
+impl<'kb> Folder for CanonicalNames<'kb> {
+    fn fold_variable(&mut self, v: Symbol) -> Symbol {
+        if self.kb.is_constant(&v) {
+            return v;
+        }
+        let next = self.names.len();
+        self.names
+            .entry(v)
+            .or_insert_with(|| Symbol::new(&format!("_{}", next)))
+            .clone()
+    }
+
+    fn fold_rest_variable(&mut self, r: Symbol) -> Symbol {
+        self.fold_variable(r)
+    }
 }
 
 #[cfg(test)]
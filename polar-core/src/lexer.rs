@@ -29,6 +29,7 @@ pub fn loc_to_pos(src: &str, loc: usize) -> SrcPos {
 }
 
 pub struct Lexer<'input> {
+    input: &'input str,
     c: Option<(usize, char)>,
     chars: Peekable<CharIndices<'input>>,
     buf: String,
@@ -39,7 +40,12 @@ impl<'input> Lexer<'input> {
         let mut chars = input.char_indices().peekable();
         let c = chars.next();
         let buf = String::new();
-        Lexer { c, chars, buf }
+        Lexer {
+            input,
+            c,
+            chars,
+            buf,
+        }
     }
 }
 
@@ -52,6 +58,7 @@ pub enum Token {
     String(String),
     Boolean(bool),
     Symbol(Symbol),
+    Placeholder(Symbol),
     Colon,     // :
     Comma,     // ,
     LB,        // [
@@ -78,6 +85,7 @@ pub enum Token {
     Unify,     // =
     Assign,    // :=
     Pipe,      // |
+    SafeDot,   // .?
     SemiColon, // ;
     Query,     // ?=
     In,        // in
@@ -92,6 +100,9 @@ pub enum Token {
     Not,       // not
     Matches,   // matches
     Type,      // type
+    Try,       // try
+    Else,      // else
+    At,        // @
 }
 
 impl ToString for Token {
@@ -101,7 +112,8 @@ impl ToString for Token {
             Token::Float(f) => f.to_string(),
             Token::String(s) => s.clone(),
             Token::Boolean(b) => b.to_string(),
-            Token::Symbol(sym) => sym.0.clone(),
+            Token::Symbol(sym) => sym.to_string(),
+            Token::Placeholder(name) => format!(":{}", name),
             Token::Colon => ":".to_owned(),         // :
             Token::Comma => ",".to_owned(),         // ,
             Token::LB => "[".to_owned(),            // [
@@ -128,6 +140,7 @@ impl ToString for Token {
             Token::Unify => "=".to_owned(),         // =
             Token::Assign => ":=".to_owned(),       // :=
             Token::Pipe => "|".to_owned(),          // |
+            Token::SafeDot => ".?".to_owned(),      // .?
             Token::SemiColon => ";".to_owned(),     // ;
             Token::Query => "?=".to_owned(),        // ?=
             Token::In => "in".to_owned(),           // in
@@ -142,6 +155,9 @@ impl ToString for Token {
             Token::Not => "not".to_owned(),         // not
             Token::Matches => "matches".to_owned(), // matches
             Token::Type => "type".to_owned(),       // type
+            Token::Try => "try".to_owned(),         // try
+            Token::Else => "else".to_owned(),       // else
+            Token::At => "@".to_owned(),            // @
         }
     }
 }
@@ -239,6 +255,8 @@ impl<'input> Lexer<'input> {
             "not" => Token::Not,
             "matches" => Token::Matches,
             "type" => Token::Type,
+            "try" => Token::Try,
+            "else" => Token::Else,
             "mod" => Token::Mod,
             "rem" => Token::Rem,
             _ => Token::Symbol(Symbol::new(&self.buf)),
@@ -433,6 +451,32 @@ impl<'input> Lexer<'input> {
         }
     }
 
+    /// Scan `:`, `:=`, or a query parameter placeholder like `:actor`. A colon right after a
+    /// name or a closing bracket, as in `x:Foo` or `{a:1}`, doesn't start a placeholder.
+    fn scan_colon(&mut self, i: usize) -> Option<Spanned<Token, usize, ParseErrorKind>> {
+        let follows_term = self.input[..i]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c == '_' || c.is_alphanumeric() || "?\")]}".contains(c));
+        match self.chars.peek() {
+            Some((j, c)) if !follows_term && (*c == '_' || c.is_alphabetic()) => {
+                let (j, c) = (*j, *c);
+                self.c = self.chars.next();
+                match self.scan_symbol(j, c)? {
+                    Ok((_, Token::Symbol(name), end)) => {
+                        Some(Ok((i, Token::Placeholder(name), end)))
+                    }
+                    Ok((_, token, _)) => Some(Err(ParseErrorKind::ReservedWord {
+                        token: token.to_string(),
+                        loc: j,
+                    })),
+                    Err(e) => Some(Err(e)),
+                }
+            }
+            _ => self.scan_1c_or_2c_op(i, Token::Colon, '=', Token::Assign),
+        }
+    }
+
     /// Scan an operator to token unless next_char is the next char in which case scan to next_token.
     #[inline]
     #[allow(clippy::unnecessary_wraps)]
@@ -468,13 +512,14 @@ impl<'input> Iterator for Lexer<'input> {
                 }
                 '"' => self.scan_string(i),
                 '0'..='9' => self.scan_number(i, char),
-                ':' => self.scan_1c_or_2c_op(i, Token::Colon, '=', Token::Assign),
+                ':' => self.scan_colon(i),
                 '=' => self.scan_1c_or_2c_op(i, Token::Unify, '=', Token::Eq),
                 '<' => self.scan_1c_or_2c_op(i, Token::Lt, '=', Token::Leq),
                 '>' => self.scan_1c_or_2c_op(i, Token::Gt, '=', Token::Geq),
                 '!' => self.scan_1c_or_2c_op(i, Token::Bang, '=', Token::Neq),
                 '?' => self.scan_2c_op(i, '=', Token::Query),
                 '|' => self.scan_1c_op(i, Token::Pipe),
+                '@' => self.scan_1c_op(i, Token::At),
                 ',' => self.scan_1c_op(i, Token::Comma),
                 '[' => self.scan_1c_op(i, Token::LB),
                 ']' => self.scan_1c_op(i, Token::RB),
@@ -482,7 +527,7 @@ impl<'input> Iterator for Lexer<'input> {
                 '}' => self.scan_1c_op(i, Token::RCB),
                 '(' => self.scan_1c_op(i, Token::LP),
                 ')' => self.scan_1c_op(i, Token::RP),
-                '.' => self.scan_1c_op(i, Token::Dot),
+                '.' => self.scan_1c_or_2c_op(i, Token::Dot, '?', Token::SafeDot),
                 '+' => self.scan_1c_op(i, Token::Add),
                 '-' => self.scan_1c_op(i, Token::Sub),
                 '*' => self.scan_1c_op(i, Token::Mul),
@@ -616,6 +661,36 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_placeholders() {
+        let s = "f(:actor, {a: :b}, x:y)";
+        let mut lexer = Lexer::new(s);
+        assert!(matches!(lexer.next(), Some(Ok((0, Token::Symbol(_), 1)))));
+        assert!(matches!(lexer.next(), Some(Ok((1, Token::LP, 2)))));
+        assert!(
+            matches!(lexer.next(), Some(Ok((2, Token::Placeholder(x), 8))) if x == Symbol::new("actor"))
+        );
+        assert!(matches!(lexer.next(), Some(Ok((8, Token::Comma, 9)))));
+        assert!(matches!(lexer.next(), Some(Ok((10, Token::LCB, 11)))));
+        assert!(matches!(lexer.next(), Some(Ok((11, Token::Symbol(_), 12)))));
+        assert!(matches!(lexer.next(), Some(Ok((12, Token::Colon, 13)))));
+        assert!(
+            matches!(lexer.next(), Some(Ok((14, Token::Placeholder(x), 16))) if x == Symbol::new("b"))
+        );
+        assert!(matches!(lexer.next(), Some(Ok((16, Token::RCB, 17)))));
+        assert!(matches!(lexer.next(), Some(Ok((17, Token::Comma, 18)))));
+        assert!(matches!(lexer.next(), Some(Ok((19, Token::Symbol(_), 20)))));
+        assert!(matches!(lexer.next(), Some(Ok((20, Token::Colon, 21)))));
+
+        let mut lexer = Lexer::new("x = :type");
+        lexer.next();
+        lexer.next();
+        assert!(matches!(
+            lexer.next(),
+            Some(Err(ParseErrorKind::ReservedWord { token, loc: 5 })) if token == "type"
+        ));
+    }
+
     #[test]
     #[allow(clippy::cognitive_complexity)]
     fn test_lexer() {
@@ -8,31 +8,47 @@ extern crate maplit;
 pub mod macros;
 
 mod bindings;
+mod builtins;
+pub mod cedar;
+mod constant_folding;
 mod constants;
+pub mod constraints;
 mod counter;
 pub mod data_filtering;
 mod debugger;
 pub mod diagnostic;
 pub mod error;
 pub mod events;
+mod facts;
 pub mod filter;
+pub mod fixes;
 mod folder;
 mod formatting;
+mod interner;
 mod inverter;
 pub mod kb;
 mod lexer;
+pub mod limits;
+pub mod lint;
 pub mod messages;
 pub mod normalize;
 mod numerics;
 pub mod parser;
 mod partial;
 pub mod polar;
+pub mod profile;
 pub mod query;
+pub mod quota;
+pub mod rego;
 pub mod resource_block;
 mod rewrites;
 pub mod rules;
 mod runnable;
+pub mod sandbox;
+pub mod slices;
 pub mod sources;
+pub mod sql;
+pub mod suspend;
 pub mod terms;
 pub mod traces;
 mod validations;
@@ -40,4 +56,9 @@ mod visitor;
 mod vm;
 pub mod warning;
 
+pub use bindings::BindingStats;
+pub use facts::Precomputed;
+pub use formatting::TermFormatter;
 pub use lexer::loc_to_pos;
+pub use partial::residual_query;
+pub use rewrites::RewritePass;
@@ -0,0 +1,54 @@
+use crate::vm::MAX_STACK_SIZE;
+
+pub const MAX_RULE_DEPTH: usize = 10_000;
+pub const MAX_TERM_DEPTH: usize = 500;
+
+/// Limits on how deeply policies and queries may nest, so that pathological input fails with
+/// an error instead of exhausting memory or the native stack.
+///
+/// Set with `Polar::set_limits`. Term depth is checked when policies and queries are parsed;
+/// the other limits are checked by the VM as queries run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum number of rule calls a query may have in progress at once.
+    pub max_rule_depth: usize,
+    /// Maximum nesting of terms in a parsed policy or query, e.g., `[[1]]` nests 3 deep.
+    pub max_term_depth: usize,
+    /// Maximum number of goals and of choice points a query may have pending at once.
+    pub max_stack_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_rule_depth: MAX_RULE_DEPTH,
+            max_term_depth: MAX_TERM_DEPTH,
+            max_stack_size: MAX_STACK_SIZE,
+        }
+    }
+}
+
+impl Limits {
+    /// Create the default limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the number of rule calls a query may have in progress at once.
+    pub fn max_rule_depth(mut self, max: usize) -> Self {
+        self.max_rule_depth = max;
+        self
+    }
+
+    /// Limit the nesting of terms in parsed policies and queries.
+    pub fn max_term_depth(mut self, max: usize) -> Self {
+        self.max_term_depth = max;
+        self
+    }
+
+    /// Limit the number of goals and of choice points a query may have pending at once.
+    pub fn max_stack_size(mut self, max: usize) -> Self {
+        self.max_stack_size = max;
+        self
+    }
+}
@@ -0,0 +1,176 @@
+//! User-defined checks run against a policy after it's loaded, for house rules that the
+//! built-in validations don't cover.
+use super::diagnostic::Diagnostic;
+use super::error::ValidationError;
+use super::kb::KnowledgeBase;
+use super::terms::Term;
+use super::warning::ValidationWarning;
+
+/// A check run against the knowledge base once a policy has loaded successfully.
+///
+/// Register lint rules with `Polar::register_lint_rule` before loading the policy. Lints
+/// reported at [`LintLevel::Error`] fail the load like any other validation error.
+pub trait LintRule: Send + Sync {
+    /// A short identifier for the rule, included in its diagnostics.
+    fn name(&self) -> &str;
+
+    /// Return a [`Lint`] for each violation of the rule in `kb`.
+    fn check(&self, kb: &KnowledgeBase) -> Vec<Lint>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LintLevel {
+    Warning,
+    Error,
+}
+
+/// A violation reported by a [`LintRule`].
+#[derive(Clone, Debug)]
+pub struct Lint {
+    pub level: LintLevel,
+    pub message: String,
+    /// Term where the violation arose, tracked for lexical context.
+    pub term: Option<Term>,
+}
+
+impl Lint {
+    pub fn warning<T: Into<String>>(message: T, term: Option<Term>) -> Self {
+        Self {
+            level: LintLevel::Warning,
+            message: message.into(),
+            term,
+        }
+    }
+
+    pub fn error<T: Into<String>>(message: T, term: Option<Term>) -> Self {
+        Self {
+            level: LintLevel::Error,
+            message: message.into(),
+            term,
+        }
+    }
+}
+
+pub(crate) fn run_lint_rules(rules: &[Box<dyn LintRule>], kb: &KnowledgeBase) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for rule in rules {
+        for Lint {
+            level,
+            message,
+            term,
+        } in rule.check(kb)
+        {
+            let rule = rule.name().to_owned();
+            diagnostics.push(match level {
+                LintLevel::Warning => Diagnostic::Warning(
+                    ValidationWarning::Lint {
+                        rule,
+                        message,
+                        term,
+                    }
+                    .into(),
+                ),
+                LintLevel::Error => Diagnostic::Error(
+                    ValidationError::Lint {
+                        rule,
+                        message,
+                        term,
+                    }
+                    .into(),
+                ),
+            });
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polar::Polar;
+    use crate::resource_block::Declaration;
+    use crate::terms::{Symbol, Value};
+
+    /// Every resource block must declare a "read" permission.
+    struct RequireReadPermission;
+
+    impl LintRule for RequireReadPermission {
+        fn name(&self) -> &str {
+            "require-read-permission"
+        }
+
+        fn check(&self, kb: &KnowledgeBase) -> Vec<Lint> {
+            let blocks = &kb.resource_blocks;
+            let declarations = blocks.declarations();
+            let mut lints = vec![];
+            for resource in blocks.resources.difference(&blocks.actors) {
+                let has_read = declarations.get(resource).is_some_and(|declarations| {
+                    declarations.iter().any(|(name, declaration)| {
+                        matches!(declaration, Declaration::Permission)
+                            && matches!(name.value(), Value::String(s) if s == "read")
+                    })
+                });
+                if !has_read {
+                    lints.push(Lint::error(
+                        format!("resource {} must declare a \"read\" permission", resource),
+                        Some(resource.clone()),
+                    ));
+                }
+            }
+            lints
+        }
+    }
+
+    /// Warn about every policy that defines `allow` rules.
+    struct NoAllowRules;
+
+    impl LintRule for NoAllowRules {
+        fn name(&self) -> &str {
+            "no-allow"
+        }
+
+        fn check(&self, kb: &KnowledgeBase) -> Vec<Lint> {
+            kb.get_generic_rule(&Symbol::new("allow"))
+                .map(|_| vec![Lint::warning("allow rules are discouraged", None)])
+                .unwrap_or_default()
+        }
+    }
+
+    #[test]
+    fn test_lint_errors_fail_load() {
+        let polar = Polar::new();
+        polar.register_lint_rule(Box::new(RequireReadPermission));
+        polar.register_constant(sym!("User"), term!(true)).unwrap();
+        polar.register_constant(sym!("Repo"), term!(true)).unwrap();
+        let policy = r#"
+            allow(_actor, _action, _resource);
+            actor User {}
+            resource Repo { permissions = ["write"]; }
+        "#;
+        let err = polar.load_str(policy).unwrap_err();
+        assert_eq!(err.kind(), "ValidationError::Lint");
+        assert!(err.to_string().starts_with(
+            "require-read-permission: resource Repo must declare a \"read\" permission"
+        ));
+        assert!(err.get_context().is_some());
+        assert!(!polar.kb.read().unwrap().has_rules());
+
+        let policy = r#"
+            allow(_actor, _action, _resource);
+            actor User {}
+            resource Repo { permissions = ["read"]; }
+        "#;
+        polar.load_str(policy).unwrap();
+    }
+
+    #[test]
+    fn test_lint_warnings() {
+        let polar = Polar::new();
+        polar.register_lint_rule(Box::new(NoAllowRules));
+        polar.load_str("allow(_, _, _);").unwrap();
+        let messages = std::iter::from_fn(|| polar.next_message())
+            .map(|message| message.msg)
+            .collect::<Vec<_>>();
+        assert_eq!(messages, vec!["no-allow: allow rules are discouraged"]);
+    }
+}
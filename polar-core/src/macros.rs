@@ -189,6 +189,7 @@ macro_rules! rule {
             body: term!(op!(And, $(term!($body)),+)),
             source_info: $crate::sources::SourceInfo::Test,
             required: false,
+            metadata: Default::default(),
         }}
     };
     ($name:expr, [$($args:tt)*]) => {{
@@ -200,6 +201,7 @@ macro_rules! rule {
             body: term!(op!(And)),
             source_info: $crate::sources::SourceInfo::Test,
             required: false,
+            metadata: Default::default(),
         }
     }};
     // this macro variant is used exclusively to create rule *types*
@@ -213,6 +215,7 @@ macro_rules! rule {
             body: term!(op!(And)),
             source_info: $crate::sources::SourceInfo::Test,
             required: $required,
+            metadata: Default::default(),
         }
     }};
 }
@@ -257,6 +260,7 @@ impl From<(Symbol, Term)> for TestHelper<Parameter> {
         Self(Parameter {
             parameter: arg.1.clone_with_value(Value::Variable(arg.0)),
             specializer: Some(term!(specializer)),
+            guard: None,
         })
     }
 }
@@ -269,13 +273,14 @@ impl From<Value> for TestHelper<Parameter> {
         Self(Parameter {
             parameter: Term::from(name),
             specializer: None,
+            guard: None,
         })
     }
 }
 
 impl<S: AsRef<str>> From<S> for TestHelper<Symbol> {
     fn from(other: S) -> Self {
-        Self(Symbol(other.as_ref().to_string()))
+        Self(Symbol::new(other.as_ref()))
     }
 }
 
@@ -1,3 +1,16 @@
+//! Polar numbers and the arithmetic on them.
+//!
+//! - Integer `+`, `-`, and `*` that overflow an `i64` raise an arithmetic error, or saturate at
+//!   `i64::MIN`/`i64::MAX` if the knowledge base is configured with
+//!   [`IntegerOverflow::Saturate`].
+//! - `/` always produces a float, following IEEE 754, so `1 / 0` is infinity.
+//! - Integer `mod` and `rem` by zero raise an arithmetic error; float `mod` and `rem` by zero
+//!   produce NaN.
+//! - Mixing an integer and a float in arithmetic converts the integer to the nearest float.
+//! - Comparisons between integers and floats are exact: the integer is never rounded, so
+//!   `9007199254740993 > 9007199254740992.0` even though the two convert to the same float.
+//!   NaN is neither equal to, less than, nor greater than any number.
+//! - [`Numeric::total_cmp`] orders all numbers, including NaN, for sorting.
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 use std::cmp::Ordering;
@@ -7,6 +20,8 @@ use std::mem::discriminant;
 use std::num::FpCategory;
 use std::ops::{Add, Div, Mul, Rem, Sub};
 
+use crate::terms::Operator;
+
 #[derive(Copy, Clone, Debug, Deserialize, Serialize)]
 pub enum Numeric {
     Integer(i64),
@@ -18,6 +33,16 @@ pub enum Numeric {
     Float(f64),
 }
 
+/// What happens when integer arithmetic overflows an `i64`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum IntegerOverflow {
+    /// Raise an arithmetic error.
+    #[default]
+    Error,
+    /// Clamp the result to `i64::MIN` or `i64::MAX`.
+    Saturate,
+}
+
 /// Since JSON does not support ±∞ or NaN (RFC 8259 §6),
 /// we encode them as magic strings.
 fn serialize_float<S>(f: &f64, s: S) -> Result<S::Ok, S::Error>
@@ -119,14 +144,65 @@ impl Numeric {
         }
 
         match (self, modulus) {
+            (Numeric::Integer(_), Numeric::Integer(0)) => None,
             (Numeric::Integer(a), Numeric::Integer(b)) => {
-                a.checked_rem(b).map(|c| (c + b) % b).map(Numeric::Integer)
+                // The result takes the sign of the modulus. `c + b` can't overflow because `c`
+                // and `b` have opposite signs.
+                let c = a.wrapping_rem(b);
+                Some(Numeric::Integer(if c != 0 && (c < 0) != (b < 0) {
+                    c + b
+                } else {
+                    c
+                }))
             }
             (Numeric::Integer(a), Numeric::Float(b)) => Some(Numeric::Float(modulo(a as f64, b))),
             (Numeric::Float(a), Numeric::Integer(b)) => Some(Numeric::Float(modulo(a, b as f64))),
             (Numeric::Float(a), Numeric::Float(b)) => Some(Numeric::Float(modulo(a, b))),
         }
     }
+
+    /// Apply the arithmetic operation `op`, handling integer overflow according to `overflow`.
+    /// Returns `None` for unsupported operators, overflow in [`IntegerOverflow::Error`] mode, and
+    /// integer `mod` or `rem` by zero.
+    pub fn arithmetic(self, op: Operator, other: Self, overflow: IntegerOverflow) -> Option<Self> {
+        let checked = match op {
+            Operator::Add => self + other,
+            Operator::Sub => self - other,
+            Operator::Mul => self * other,
+            Operator::Div => self / other,
+            Operator::Mod => self.modulo(other),
+            Operator::Rem => self % other,
+            _ => return None,
+        };
+        match (checked, self, other) {
+            (None, Numeric::Integer(a), Numeric::Integer(b))
+                if overflow == IntegerOverflow::Saturate =>
+            {
+                match op {
+                    Operator::Add => Some(Numeric::Integer(a.saturating_add(b))),
+                    Operator::Sub => Some(Numeric::Integer(a.saturating_sub(b))),
+                    Operator::Mul => Some(Numeric::Integer(a.saturating_mul(b))),
+                    // Integer `mod` and `rem` by zero are errors in every mode.
+                    _ => None,
+                }
+            }
+            (result, _, _) => result,
+        }
+    }
+
+    /// Compare numbers for sorting. Unlike `partial_cmp`, this orders every pair of numbers:
+    /// NaN is greater than every other number and equal to itself. Integers and floats that
+    /// are numerically equal, including `0`, `0.0`, and `-0.0`, compare equal.
+    pub fn total_cmp(&self, other: &Self) -> Ordering {
+        let is_nan = |n: &Self| matches!(n, Numeric::Float(f) if f.is_nan());
+        match (is_nan(self), is_nan(other)) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            // Only NaN is unordered.
+            (false, false) => self.partial_cmp(other).unwrap_or(Ordering::Equal),
+        }
+    }
 }
 
 impl Rem for Numeric {
@@ -134,7 +210,9 @@ impl Rem for Numeric {
 
     fn rem(self, other: Self) -> Option<Self> {
         match (self, other) {
-            (Numeric::Integer(a), Numeric::Integer(b)) => a.checked_rem(b).map(Numeric::Integer),
+            // `i64::MIN rem -1` overflows in Rust, but its result, 0, doesn't.
+            (Numeric::Integer(_), Numeric::Integer(0)) => None,
+            (Numeric::Integer(a), Numeric::Integer(b)) => Some(Numeric::Integer(a.wrapping_rem(b))),
             (Numeric::Integer(a), Numeric::Float(b)) => Some(Numeric::Float((a as f64) % b)),
             (Numeric::Float(a), Numeric::Integer(b)) => Some(Numeric::Float(a % (b as f64))),
             (Numeric::Float(a), Numeric::Float(b)) => Some(Numeric::Float(a % b)),
@@ -229,6 +307,8 @@ impl Hash for Numeric {
     }
 }
 
+/// Integers and floats compare by their exact values: an integer is never rounded to the
+/// nearest float to compare it with one. NaN is unordered with respect to every number.
 impl PartialOrd for Numeric {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         // Compare the integer `i` with the float `f`.
@@ -535,4 +615,70 @@ mod tests {
             _ => panic!("expected a float"),
         });
     }
+
+    #[test]
+    fn integer_overflow() {
+        use IntegerOverflow::{Error, Saturate};
+        let (max, min) = (Numeric::Integer(i64::MAX), Numeric::Integer(i64::MIN));
+        let one = Numeric::Integer(1);
+
+        assert_eq!(max.arithmetic(Operator::Add, one, Error), None);
+        assert_eq!(max.arithmetic(Operator::Add, one, Saturate), Some(max));
+        assert_eq!(min.arithmetic(Operator::Sub, one, Saturate), Some(min));
+        assert_eq!(max.arithmetic(Operator::Mul, max, Saturate), Some(max));
+        assert_eq!(min.arithmetic(Operator::Mul, max, Saturate), Some(min));
+        assert_eq!(
+            one.arithmetic(Operator::Add, one, Error),
+            Some(Numeric::Integer(2))
+        );
+
+        // Division by zero is an error in every mode, but `i64::MIN mod -1` isn't.
+        let zero = Numeric::Integer(0);
+        assert_eq!(one.arithmetic(Operator::Mod, zero, Saturate), None);
+        assert_eq!(one.arithmetic(Operator::Rem, zero, Saturate), None);
+        let minus_one = Numeric::Integer(-1);
+        assert_eq!(min.arithmetic(Operator::Mod, minus_one, Error), Some(zero));
+        assert_eq!(min.arithmetic(Operator::Rem, minus_one, Error), Some(zero));
+        assert_eq!(
+            Numeric::Integer(1).modulo(Numeric::Integer(i64::MAX)),
+            Some(one)
+        );
+        assert_eq!(
+            Numeric::Integer(-1).modulo(Numeric::Integer(i64::MAX)),
+            Some(Numeric::Integer(i64::MAX - 1))
+        );
+    }
+
+    #[test]
+    fn total_ordering() {
+        let mut numbers = [
+            Numeric::Float(f64::NAN),
+            Numeric::Integer(2),
+            Numeric::Float(f64::INFINITY),
+            Numeric::Float(-0.5),
+            Numeric::Integer(i64::MIN),
+            Numeric::Float(f64::NEG_INFINITY),
+            Numeric::Float(1.5),
+        ];
+        numbers.sort_by(Numeric::total_cmp);
+        assert_eq!(
+            &numbers[..6],
+            &[
+                Numeric::Float(f64::NEG_INFINITY),
+                Numeric::Integer(i64::MIN),
+                Numeric::Float(-0.5),
+                Numeric::Float(1.5),
+                Numeric::Integer(2),
+                Numeric::Float(f64::INFINITY),
+            ]
+        );
+        assert!(matches!(numbers[6], Numeric::Float(f) if f.is_nan()));
+
+        let nan = Numeric::Float(f64::NAN);
+        assert_eq!(nan.total_cmp(&nan), Ordering::Equal);
+        assert_eq!(
+            Numeric::Integer(0).total_cmp(&Numeric::Float(-0.0)),
+            Ordering::Equal
+        );
+    }
 }
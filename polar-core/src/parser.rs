@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use lalrpop_util::{lalrpop_mod, ParseError};
+use serde::{Deserialize, Serialize};
 
 use super::{
     error::{self, PolarResult},
@@ -9,6 +10,7 @@ use super::{
     rules::*,
     sources::Source,
     terms::*,
+    visitor::{walk_term, Visitor},
 };
 
 /// Used to denote whether an enclosed value is a value or a logical operator
@@ -23,7 +25,35 @@ lalrpop_mod!(
     polar
 );
 
-#[derive(Clone, Debug, PartialEq)]
+/// A line of a policy: a rule, rule type, inline query, resource block, type alias, or default
+/// decision.
+///
+/// Lines serialize to JSON, e.g., with `serde_json`, so that tools can generate policies
+/// structurally and load them with `Polar::load_ast`. Each line is an object with one key, the
+/// kind of line, e.g., the lines of `allow(actor, "read", _: Repo) if actor.admin;` and
+/// `?= f(1);` are
+///
+/// ```text
+/// {"Rule": {
+///   "name": "allow",
+///   "params": [
+///     {"parameter": {"value": {"Variable": "actor"}}},
+///     {"parameter": {"value": {"String": "read"}}},
+///     {"parameter": {"value": {"Variable": "_"}},
+///      "specializer": {"value": {"Pattern": {"Instance": {"tag": "Repo", "fields": {"fields": {}}}}}}}
+///   ],
+///   "body": {"value": {"Expression": {"operator": "And", "args": [
+///     {"value": {"Expression": {"operator": "Dot", "args": [
+///       {"value": {"Variable": "actor"}}, {"value": {"String": "admin"}}]}}}]}}}
+/// }}
+/// {"Query": {"value": {"Call": {"name": "f", "args": [{"value": {"Number": {"Integer": 1}}}]}}}}
+/// ```
+///
+/// Terms are objects with the key `value`, in the same form as the terms the host exchanges
+/// with queries. A rule's body is always an `And` expression. `RuleType` lines are rules, and
+/// `ResourceBlock` and `TypeAlias` lines have the fields of the variants below. Source
+/// positions aren't serialized, so errors in loaded lines have no context.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Line {
     Rule(Rule),
     RuleType(Rule),
@@ -33,6 +63,41 @@ pub enum Line {
         resource: Term,
         productions: Vec<Production>,
     },
+    /// `type Name = A | B;`
+    TypeAlias {
+        name: Term,
+        members: Vec<Symbol>,
+    },
+    /// `default deny;`, `default allow;`, or `default allow rule;`. `keyword` is the term
+    /// `default`, tracked for lexical context.
+    Default {
+        keyword: Term,
+        decision: DefaultDecision,
+    },
+}
+
+/// Make the line `keyword decision rule;`, which must be a default decision.
+pub(crate) fn default_decision_line(
+    keyword: Term,
+    decision: Symbol,
+    rule: Option<Symbol>,
+) -> Result<Line, ParseError<usize, Token, error::ParseErrorKind>> {
+    let unrecognized = |term: &Term, token: String| {
+        let loc = term.parsed_context().map_or(0, |context| context.left);
+        Err(ParseError::User {
+            error: error::ParseErrorKind::UnrecognizedToken { token, loc },
+        })
+    };
+    if !matches!(keyword.value(), Value::Variable(name) if name.as_str() == "default") {
+        return unrecognized(&keyword, keyword.to_string());
+    }
+    let decision = match (decision.as_str(), rule) {
+        ("deny", None) => DefaultDecision::Deny,
+        ("allow", None) => DefaultDecision::Allow,
+        ("allow", Some(rule)) => DefaultDecision::AllowIf(rule),
+        _ => return unrecognized(&keyword, decision.to_string()),
+    };
+    Ok(Line::Default { keyword, decision })
 }
 
 fn lalrpop_error_to_polar_error(
@@ -81,6 +146,77 @@ pub fn parse_query(query: &str) -> PolarResult<Term> {
         .map_err(|e| lalrpop_error_to_polar_error(e, source))
 }
 
+/// Return an error for the first term in `lines` nested more than `max_depth` deep.
+///
+/// Checked after parsing so that deeply nested input is rejected before the recursive passes
+/// that rewrite and evaluate it.
+pub fn check_lines_depth(lines: &[Line], max_depth: usize) -> PolarResult<()> {
+    let mut checker = DepthChecker::new(max_depth);
+    for line in lines {
+        match line {
+            Line::Rule(rule) | Line::RuleType(rule) => checker.visit_rule(rule),
+            Line::Query(term) => checker.visit_term(term),
+            Line::ResourceBlock { .. } | Line::TypeAlias { .. } | Line::Default { .. } => {}
+        }
+    }
+    checker.result()
+}
+
+/// Return an error if `term` is nested more than `max_depth` deep.
+pub fn check_term_depth(term: &Term, max_depth: usize) -> PolarResult<()> {
+    let mut checker = DepthChecker::new(max_depth);
+    checker.visit_term(term);
+    checker.result()
+}
+
+struct DepthChecker {
+    depth: usize,
+    max_depth: usize,
+    too_deep: Option<Term>,
+}
+
+impl DepthChecker {
+    fn new(max_depth: usize) -> Self {
+        Self {
+            depth: 0,
+            max_depth,
+            too_deep: None,
+        }
+    }
+
+    fn result(self) -> PolarResult<()> {
+        match self.too_deep {
+            None => Ok(()),
+            Some(term) => {
+                let source = term.parsed_context().map_or_else(
+                    || Arc::new(Source::new(term.to_string())),
+                    |context| context.source.clone(),
+                );
+                let kind = error::ParseErrorKind::TermTooDeep {
+                    term,
+                    limit: self.max_depth,
+                };
+                Err(error::ParseError { source, kind }.into())
+            }
+        }
+    }
+}
+
+impl Visitor for DepthChecker {
+    fn visit_term(&mut self, term: &Term) {
+        if self.too_deep.is_some() {
+            return;
+        }
+        self.depth += 1;
+        if self.depth > self.max_depth {
+            self.too_deep = Some(term.clone());
+        } else {
+            walk_term(self, term);
+        }
+        self.depth -= 1;
+    }
+}
+
 #[cfg(test)]
 pub fn parse_rules(rules: &str) -> PolarResult<Vec<Rule>> {
     let source = Arc::new(Source::new(rules));
@@ -228,12 +364,86 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_type_alias() {
+        let line = parse_lines("type Resource = Repo | Org | Issue;");
+        assert_eq!(
+            line[0],
+            Line::TypeAlias {
+                name: term!(sym!("Resource")),
+                members: vec![sym!("Repo"), sym!("Org"), sym!("Issue")],
+            }
+        );
+        let line = parse_lines("type f(x: String); type Single = Repo;");
+        assert!(matches!(&line[0], Line::RuleType(_)));
+        assert!(matches!(&line[1], Line::TypeAlias { members, .. } if members.len() == 1));
+        super::parse_lines(Source::new("type Empty = ;")).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_default_decision() {
+        let decisions = parse_lines(
+            "default deny; default allow; default allow allow_readonly; allow(_, _, _);",
+        )
+        .into_iter()
+        .filter_map(|line| match line {
+            Line::Default { decision, .. } => Some(decision),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+        assert_eq!(
+            decisions,
+            vec![
+                DefaultDecision::Deny,
+                DefaultDecision::Allow,
+                DefaultDecision::AllowIf(sym!("allow_readonly")),
+            ]
+        );
+        for src in ["default maybe;", "default deny f;", "fallback deny;"] {
+            super::parse_lines(Source::new(src)).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_parse_specializer_predicates() {
+        let rule = parse_rule(
+            r#"allow(actor: @verified_email, "read", _) if actor.email matches @staff;"#,
+        );
+        assert_eq!(
+            rule.params[0].specializer,
+            Some(term!(value!(Pattern::Predicate(sym!("verified_email")))))
+        );
+        assert_eq!(
+            rule.to_string(),
+            r#"allow(actor: @verified_email, "read", _) if actor.email matches @staff;"#
+        );
+    }
+
     #[test]
     fn test_rule_type_error() {
         let rule_type = r#"type f(x: String) if x = "bad";"#;
         super::parse_lines(Source::new(rule_type)).unwrap_err();
     }
 
+    #[test]
+    fn test_parse_annotations() {
+        let rule = parse_rule(r#"@deprecated("use g") f(x) if x = 1;"#);
+        assert_eq!(rule.metadata.deprecated.as_deref(), Some("use g"));
+        assert_eq!(rule.to_string(), "f(x) if x = 1;");
+        let line = parse_lines(r#"@deprecated("use g") f(1);"#);
+        assert!(matches!(&line[0], Line::Rule(rule) if rule.metadata.deprecated.is_some()));
+
+        let rule = parse_rule(r#"@severity("high") @owner("secteam") @doc("x") f(1);"#);
+        assert_eq!(rule.metadata.doc.as_deref(), Some("x"));
+        assert_eq!(
+            rule.metadata.annotations,
+            btreemap! {
+                "severity".to_owned() => "high".to_owned(),
+                "owner".to_owned() => "secteam".to_owned(),
+            }
+        );
+    }
+
     #[test]
     fn test_parse_new() {
         let f = "a(x) if x = new Foo(a: 1);";
@@ -1,5 +1,5 @@
 use crate::counter::Counter;
-use crate::error::{invalid_state, PolarResult};
+use crate::error::{PolarResult, RuntimeError};
 use crate::events::QueryEvent;
 use crate::runnable::Runnable;
 use crate::terms::{Operation, Operator, Pattern, Symbol, Term, Value};
@@ -78,8 +78,8 @@ impl IsaConstraintCheck {
 
         let just_vars = constraint_path.len() == 1
             && proposed_path.len() == 1
-            && matches!(&constraint.args[0].as_symbol(), Ok(Symbol(_)))
-            && matches!(&self.proposed.args[0].as_symbol(), Ok(Symbol(_)));
+            && constraint.args[0].as_symbol().is_ok()
+            && self.proposed.args[0].as_symbol().is_ok();
 
         // FIXME(gw): this logic is hard to follow!
         if just_vars {
@@ -205,7 +205,8 @@ impl Runnable for IsaConstraintCheck {
 
     fn external_question_result(&mut self, call_id: u64, answer: bool) -> PolarResult<()> {
         if call_id != self.last_call_id {
-            return invalid_state("Unexpected call id");
+            let generation = Counter::generation_of(self.last_call_id);
+            return Err(RuntimeError::unknown_call_id(call_id, generation).into());
         }
         self.result = Some(answer);
         Ok(())
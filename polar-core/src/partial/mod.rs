@@ -4,4 +4,7 @@ mod partial;
 mod simplify;
 
 pub use isa_constraint_check::IsaConstraintCheck;
-pub use simplify::{simplify_bindings, simplify_bindings_opt, simplify_partial, sub_this};
+pub use simplify::{
+    canonicalize_temporaries, residual_query, simplify_bindings, simplify_bindings_opt,
+    simplify_partial, sub_this,
+};
@@ -79,6 +79,58 @@ pub fn sub_this(this: Symbol, term: Term) -> Term {
     fold_term(term, &mut VariableSubber::new(this))
 }
 
+/// Substitute a variable for `sym!("_this")` in a partial; the inverse of `sub_this`.
+struct ThisSubber {
+    var: Symbol,
+}
+
+impl Folder for ThisSubber {
+    fn fold_variable(&mut self, v: Symbol) -> Symbol {
+        if v == sym!("_this") {
+            self.var.clone()
+        } else {
+            v
+        }
+    }
+
+    fn fold_rest_variable(&mut self, v: Symbol) -> Symbol {
+        self.fold_variable(v)
+    }
+}
+
+/// A query that succeeds for values of `vars` exactly when they satisfy one of the partial
+/// `results`, e.g., to check values against the results of partially evaluating a rule once,
+/// without calling the rule again.
+pub fn residual_query(results: &[Bindings], vars: &[Symbol]) -> Term {
+    let expression =
+        |operator, args| Term::new_temporary(Value::Expression(Operation { operator, args }));
+    let disjuncts = results
+        .iter()
+        .map(|bindings| {
+            let conjuncts = vars
+                .iter()
+                .filter_map(|var| {
+                    let value = bindings.get(var)?;
+                    Some(match value.value() {
+                        Value::Expression(_) => {
+                            fold_term(value.clone(), &mut ThisSubber { var: var.clone() })
+                        }
+                        _ => expression(
+                            Operator::Unify,
+                            vec![
+                                Term::new_temporary(Value::Variable(var.clone())),
+                                value.clone(),
+                            ],
+                        ),
+                    })
+                })
+                .collect();
+            expression(Operator::And, conjuncts)
+        })
+        .collect();
+    expression(Operator::Or, disjuncts)
+}
+
 /// Turn `_this = x` into `x` when it's ground.
 fn simplify_trivial_constraint(this: Symbol, term: Term) -> Term {
     use {Operator::*, Value::*};
@@ -215,6 +267,52 @@ pub fn simplify_bindings_opt(bindings: Bindings, all: bool) -> Result<Option<Bin
     }
 }
 
+/// Whether `v` was made by `KnowledgeBase::gensym`, e.g., `_value_12` or `_3`.
+fn is_gensym(v: &Symbol) -> bool {
+    let counter = match v.as_str().strip_prefix('_') {
+        Some(rest) => rest.rsplit('_').next().unwrap_or(rest),
+        None => return false,
+    };
+    !counter.is_empty() && counter.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Rename gensym'd variables to `_0`, `_1`, ..., in the order they appear.
+#[derive(Default)]
+struct CanonicalTemporaries {
+    names: HashMap<Symbol, Symbol>,
+}
+
+impl Folder for CanonicalTemporaries {
+    fn fold_variable(&mut self, v: Symbol) -> Symbol {
+        if !is_gensym(&v) {
+            return v;
+        }
+        let next = self.names.len();
+        self.names
+            .entry(v)
+            .or_insert_with(|| Symbol::new(&format!("_{}", next)))
+            .clone()
+    }
+
+    fn fold_rest_variable(&mut self, r: Symbol) -> Symbol {
+        self.fold_variable(r)
+    }
+}
+
+/// Rename the gensym'd variables in the values of `bindings`, e.g., `_value_12` in a partial
+/// result, to `_0`, `_1`, ..., in the order they appear in the bindings sorted by name, so
+/// that the same result has the same names however many variables the query made before it.
+/// Since every gensym'd name is renamed, the new names can't clash with the others.
+pub fn canonicalize_temporaries(bindings: Bindings) -> Bindings {
+    let mut bindings = bindings.into_iter().collect::<Vec<_>>();
+    bindings.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+    let mut folder = CanonicalTemporaries::default();
+    bindings
+        .into_iter()
+        .map(|(var, value)| (folder.fold_variable(var), folder.fold_term(value)))
+        .collect()
+}
+
 #[derive(Clone, Default)]
 pub struct PerfCounters {
     enabled: bool,
@@ -1,26 +1,107 @@
+use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 
+use super::cedar;
 use super::data_filtering::{build_filter_plan, FilterPlan, PartialResults, Types};
 use super::diagnostic::Diagnostic;
-use super::error::{PolarResult, RuntimeError, ValidationError};
+use super::error::{PolarError, PolarResult, RuntimeError, ValidationError};
 use super::filter::Filter;
+use super::formatting::TermFormatter;
 use super::kb::*;
+use super::limits::Limits;
+use super::lint::{run_lint_rules, LintRule};
 use super::messages::*;
 use super::parser;
 use super::query::Query;
+use super::quota::ScopeQuota;
 use super::resource_block::resource_block_from_productions;
 use super::rewrites::*;
+use super::rules::{Rule, TemplateInfo};
 use super::sources::*;
+use super::suspend::SuspendedQuery;
 use super::terms::*;
 use super::validations::{
     check_ambiguous_precedence, check_no_allow_rule, check_resource_blocks_missing_has_permission,
     check_singletons,
 };
+use super::warning::{PolarWarning, ValidationWarning};
+
+/// Load the parsed `lines` of a policy into `kb`.
+fn load_lines(
+    mut lines: Vec<parser::Line>,
+    kb: &mut KnowledgeBase,
+    passes: &RewritePipeline,
+) -> PolarResult<Vec<Diagnostic>> {
+    parser::check_lines_depth(&lines, kb.limits().max_term_depth)?;
+    lines.reverse();
+    let mut diagnostics = vec![];
+    while let Some(line) = lines.pop() {
+        match line {
+            parser::Line::Rule(rule) if rule.metadata.template.is_some() => {
+                kb.add_template(rule)?;
+            }
+            parser::Line::Rule(rule) => {
+                diagnostics.append(&mut check_singletons(&rule, kb));
+                diagnostics.append(&mut check_ambiguous_precedence(&rule));
+                let rule = passes.run(rule, kb);
+                if let Some(warning) = kb.add_rule(rule) {
+                    diagnostics.push(Diagnostic::Warning(warning.into()));
+                }
+            }
+            parser::Line::Query(term) => {
+                kb.inline_queries.push(InlineQuery { term, scope: None });
+            }
+            parser::Line::RuleType(rule_type) => {
+                // make sure rule_type doesn't have anything that needs to be rewritten in the head
+                let rule_type = rewrite_rule(rule_type, kb);
+                if !matches!(
+                    rule_type.body.value(),
+                    Value::Expression(
+                        Operation {
+                            operator: Operator::And,
+                            args
+                        }
+                    ) if args.is_empty()
+                ) {
+                    diagnostics.push(Diagnostic::Error(
+                        ValidationError::InvalidRuleType {
+                            rule_type,
+                            msg: "Rule types cannot contain dot lookups.".into(),
+                        }
+                        .into(),
+                    ));
+                } else {
+                    kb.add_rule_type(rule_type);
+                }
+            }
+            parser::Line::TypeAlias { name, members } => {
+                kb.add_type_alias(name, members)?;
+            }
+            parser::Line::Default { keyword, decision } => {
+                kb.set_default_decision(keyword, decision)?;
+            }
+            parser::Line::ResourceBlock {
+                keyword,
+                resource,
+                productions,
+            } => {
+                let (block, mut errors) =
+                    resource_block_from_productions(keyword, resource, productions);
+                errors.append(&mut block.add_to_kb(kb));
+                diagnostics.extend(errors.into_iter().map(Into::into));
+            }
+        }
+    }
+    Ok(diagnostics)
+}
 
 pub struct Polar {
     pub kb: Arc<RwLock<KnowledgeBase>>,
     messages: MessageQueue,
     ignore_no_allow_warning: bool,
+    lint_rules: RwLock<Vec<Box<dyn LintRule>>>,
+    rewrite_passes: RwLock<RewritePipeline>,
+    shadow: RwLock<Option<Arc<RwLock<KnowledgeBase>>>>,
 }
 
 impl Default for Polar {
@@ -41,79 +122,60 @@ impl Polar {
             kb: Arc::new(RwLock::new(KnowledgeBase::new())),
             messages: MessageQueue::new(),
             ignore_no_allow_warning,
+            lint_rules: RwLock::new(vec![]),
+            rewrite_passes: RwLock::new(RewritePipeline::default()),
+            shadow: RwLock::new(None),
         }
     }
 
     /// Load `sources` into the KB, returning compile-time diagnostics accumulated during the load.
     pub fn diagnostic_load(&self, sources: Vec<Source>) -> Vec<Diagnostic> {
+        let mut kb = self.kb.write().unwrap();
+        self.load_into(&mut kb, sources)
+    }
+
+    fn load_into<I: IntoIterator<Item = Source>>(
+        &self,
+        kb: &mut KnowledgeBase,
+        sources: I,
+    ) -> Vec<Diagnostic> {
         // Separate function so that errors returned with `?` are captured.
-        fn load_source(source: Source, kb: &mut KnowledgeBase) -> PolarResult<Vec<Diagnostic>> {
-            if let Some(ref filename) = source.filename {
-                kb.add_source(filename, &source.src)?;
-            }
-            // TODO(gj): we still bomb out at the first ParseError.
-            let mut lines = parser::parse_lines(source)?;
-            lines.reverse();
-            let mut diagnostics = vec![];
-            while let Some(line) = lines.pop() {
-                match line {
-                    parser::Line::Rule(rule) => {
-                        diagnostics.append(&mut check_singletons(&rule, kb));
-                        diagnostics.append(&mut check_ambiguous_precedence(&rule));
-                        let rule = rewrite_rule(rule, kb);
-                        kb.add_rule(rule);
-                    }
-                    parser::Line::Query(term) => {
-                        kb.inline_queries.push(term);
-                    }
-                    parser::Line::RuleType(rule_type) => {
-                        // make sure rule_type doesn't have anything that needs to be rewritten in the head
-                        let rule_type = rewrite_rule(rule_type, kb);
-                        if !matches!(
-                            rule_type.body.value(),
-                            Value::Expression(
-                                Operation {
-                                    operator: Operator::And,
-                                    args
-                                }
-                            ) if args.is_empty()
-                        ) {
-                            diagnostics.push(Diagnostic::Error(
-                                ValidationError::InvalidRuleType {
-                                    rule_type,
-                                    msg: "Rule types cannot contain dot lookups.".into(),
-                                }
-                                .into(),
-                            ));
-                        } else {
-                            kb.add_rule_type(rule_type);
-                        }
-                    }
-                    parser::Line::ResourceBlock {
-                        keyword,
-                        resource,
-                        productions,
-                    } => {
-                        let (block, mut errors) =
-                            resource_block_from_productions(keyword, resource, productions);
-                        errors.append(&mut block.add_to_kb(kb));
-                        diagnostics.extend(errors.into_iter().map(Into::into));
-                    }
+        fn load_source(
+            source: Source,
+            kb: &mut KnowledgeBase,
+            passes: &RewritePipeline,
+        ) -> PolarResult<Vec<Diagnostic>> {
+            // Later chunks of a file read with a `SourceReader` are part of the same file.
+            match source.filename {
+                Some(ref filename) if source.first_line == 0 => {
+                    kb.add_source(filename, &source.src)?
                 }
+                _ => (),
             }
-            Ok(diagnostics)
+            // TODO(gj): we still bomb out at the first ParseError.
+            let lines = parser::parse_lines(source)?;
+            load_lines(lines, kb, passes)
         }
 
-        let mut kb = self.kb.write().unwrap();
         let mut diagnostics = vec![];
 
+        let passes = self.rewrite_passes.read().unwrap();
         for source in sources {
-            match load_source(source, &mut kb) {
+            match load_source(source, kb, &passes) {
                 Ok(mut ds) => diagnostics.append(&mut ds),
                 Err(e) => diagnostics.push(Diagnostic::Error(e)),
             }
         }
+        self.check_loaded(kb, diagnostics)
+    }
 
+    /// Check the policy loaded into `kb`, once every source has been loaded, adding to the
+    /// `diagnostics` of loading them.
+    fn check_loaded(
+        &self,
+        kb: &mut KnowledgeBase,
+        mut diagnostics: Vec<Diagnostic>,
+    ) -> Vec<Diagnostic> {
         // NOTE(gj): need to bomb out before rewriting shorthand rules to avoid emitting
         // correct-but-unhelpful errors, e.g., when there's an invalid `relations` declaration that
         // will result in a second error when rewriting a shorthand rule involving the relation
@@ -125,6 +187,17 @@ impl Polar {
         // Rewrite shorthand rules in resource blocks before validating rule types.
         diagnostics.extend(kb.rewrite_shorthand_rules().into_iter().map(Into::into));
 
+        // Expand type aliases now that every alias has been declared, and forget the warnings
+        // about aliases used as specializers before their declarations.
+        diagnostics.retain(|diagnostic| {
+            !matches!(
+                diagnostic,
+                Diagnostic::Warning(PolarWarning(ValidationWarning::UnknownSpecializer { sym, .. }))
+                    if kb.is_type_alias(sym)
+            )
+        });
+        diagnostics.extend(kb.expand_type_aliases().into_iter().map(Into::into));
+
         // NOTE(gj): need to bomb out before rule type validation in case additional rule types
         // were defined later on in the file that encountered the unrecoverable error. Those
         // additional rule types might extend the valid shapes for a rule type defined in a
@@ -146,29 +219,188 @@ impl Polar {
 
         // Perform validation checks against the whole policy
         if !self.ignore_no_allow_warning {
-            if let Some(w) = check_no_allow_rule(&kb) {
+            if let Some(w) = check_no_allow_rule(kb) {
                 diagnostics.push(w)
             }
         }
 
         // Check for has_permission calls alongside resource block definitions
-        if let Some(w) = check_resource_blocks_missing_has_permission(&kb) {
+        if let Some(w) = check_resource_blocks_missing_has_permission(kb) {
             diagnostics.push(Diagnostic::Warning(w.into()))
         };
 
+        // Run user-defined lint rules only against policies that are otherwise valid.
+        if !diagnostics.iter().any(Diagnostic::is_error) {
+            let lint_rules = self.lint_rules.read().unwrap();
+            diagnostics.append(&mut run_lint_rules(&lint_rules, kb));
+        }
+
         diagnostics
     }
 
     /// Load `Source`s into the KB.
     pub fn load(&self, sources: Vec<Source>) -> PolarResult<()> {
+        self.load_iter(sources)
+    }
+
+    /// Like `load`, but parsing and loading each source before taking the next from
+    /// `sources`, so that sources may be read lazily, e.g., with a
+    /// [`SourceReader`](crate::sources::SourceReader).
+    pub fn load_iter<I: IntoIterator<Item = Source>>(&self, sources: I) -> PolarResult<()> {
+        if let Ok(kb) = self.kb.read() {
+            if kb.has_rules() {
+                return Err(RuntimeError::MultipleLoadError.into());
+            }
+        }
+
+        let mut kb = self.kb.write().unwrap();
+        let diagnostics = self.load_into(&mut kb, sources);
+        self.finish_load(&mut kb, diagnostics)
+    }
+
+    /// Like `load`, but loading the lines of a policy parsed already, e.g., by
+    /// [`parser::parse_lines`] or deserialized from the JSON that describes them (see
+    /// [`parser::Line`]), rather than its source.
+    pub fn load_ast(&self, lines: Vec<parser::Line>) -> PolarResult<()> {
         if let Ok(kb) = self.kb.read() {
             if kb.has_rules() {
                 return Err(RuntimeError::MultipleLoadError.into());
             }
         }
 
+        let mut kb = self.kb.write().unwrap();
+        let diagnostics = {
+            let passes = self.rewrite_passes.read().unwrap();
+            load_lines(lines, &mut kb, &passes).unwrap_or_else(|e| vec![Diagnostic::Error(e)])
+        };
+        let diagnostics = self.check_loaded(&mut kb, diagnostics);
+        self.finish_load(&mut kb, diagnostics)
+    }
+
+    /// Report the `diagnostics` of loading a policy into `kb`, and either clear the policy if
+    /// any are errors, or record it as loaded.
+    fn finish_load(&self, kb: &mut KnowledgeBase, diagnostics: Vec<Diagnostic>) -> PolarResult<()> {
+        if let Some(e) = self.report_diagnostics(diagnostics) {
+            // If we've encountered any errors, clear the KB.
+            kb.clear_rules();
+            return Err(e);
+        }
+        kb.record_epoch(crate::vm::now_ms());
+        // Slice the rules now rather than in the first query.
+        kb.action_slices();
+        Ok(())
+    }
+
+    /// Load `sources` into the scope `name`, replacing the scope's rules if it was loaded
+    /// before. Scopes may only contain rules, templates, a default decision, and inline
+    /// queries, and their rules may call the rules loaded with `load`. The scope's inline
+    /// queries run in the scope.
+    /// Unlike `load`, this may be called any number of times.
+    pub fn load_scope(&self, name: &str, sources: Vec<Source>) -> PolarResult<()> {
+        let mut kb = self.kb.write().unwrap();
+        let quota = kb.scope_quota(name).clone();
+        let bytes = sources.iter().map(|source| source.src.len() as u64).sum();
+        check_scope_size(name, &quota, 0, bytes)?;
+
+        let mut scope = Scope::default();
+        let mut rules = 0;
+        let mut inline_queries = vec![];
+        let mut diagnostics = vec![];
+        for source in sources {
+            let lines = parser::parse_lines(source)?;
+            parser::check_lines_depth(&lines, kb.limits().max_term_depth)?;
+            for line in lines {
+                match line {
+                    parser::Line::Rule(rule) if rule.metadata.template.is_some() => {
+                        scope.add_template(rule)?;
+                    }
+                    parser::Line::Rule(rule) => {
+                        rules += 1;
+                        check_scope_size(name, &quota, rules, bytes)?;
+                        diagnostics.append(&mut check_singletons(&rule, &kb));
+                        diagnostics.append(&mut check_ambiguous_precedence(&rule));
+                        let passes = self.rewrite_passes.read().unwrap();
+                        scope.add_rule(passes.run(rule, &kb));
+                    }
+                    parser::Line::Default { keyword, decision } => {
+                        scope.set_default_decision(keyword, decision)?;
+                    }
+                    parser::Line::Query(term) => {
+                        inline_queries.push(InlineQuery {
+                            term,
+                            scope: Some(name.to_owned()),
+                        });
+                    }
+                    _ => return Err(RuntimeError::InvalidScope {
+                        scope: name.to_owned(),
+                        msg:
+                            "scopes may only contain rules, templates, defaults, and inline queries"
+                                .to_owned(),
+                    }
+                    .into()),
+                }
+            }
+        }
+        if !diagnostics.iter().any(Diagnostic::is_unrecoverable) {
+            diagnostics.append(&mut kb.validate_scope(name, &scope));
+        }
+        if let Some(e) = self.report_diagnostics(diagnostics) {
+            return Err(e);
+        }
+        kb.set_scope(name, scope);
+        // Inline queries left over from loading the scope before no longer apply.
+        kb.inline_queries
+            .retain(|query| query.scope.as_deref() != Some(name));
+        inline_queries.reverse();
+        kb.inline_queries.extend(inline_queries);
+        Ok(())
+    }
+
+    /// Make a rule from the template `name`, as for `KnowledgeBase::instantiate_template`,
+    /// using the templates loaded into `scope` as well as those loaded without a scope.
+    pub fn instantiate_template(
+        &self,
+        scope: Option<&str>,
+        name: &str,
+        bindings: HashMap<String, Term>,
+    ) -> PolarResult<Rule> {
+        let kb = self.kb.read().unwrap();
+        kb.instantiate_template_in(scope, name, bindings)
+    }
+
+    /// The templates that may be instantiated in `scope`, as for
+    /// `KnowledgeBase::list_templates`.
+    pub fn list_templates(&self, scope: Option<&str>) -> Vec<TemplateInfo> {
+        self.kb.read().unwrap().list_templates(scope)
+    }
+
+    /// Let queries in the scope `name` use the rules loaded into the scopes `includes` as well
+    /// as its own.
+    pub fn set_scope_includes(&self, name: &str, includes: Vec<String>) -> PolarResult<()> {
+        self.kb.write().unwrap().set_scope_includes(name, includes)
+    }
+
+    /// Remove the policy, quota, and includes of the scope `name`. Fails if other scopes
+    /// include it, unless `force` is set. Return `false` if it wasn't loaded.
+    pub fn remove_scope(&self, name: &str, force: bool) -> PolarResult<bool> {
+        self.kb.write().unwrap().remove_scope(name, force)
+    }
+
+    /// Limit the policy loaded into the scope `name` with `load_scope`, and the queries run in
+    /// the scope.
+    pub fn set_scope_quota(&self, name: &str, quota: ScopeQuota) {
+        self.kb.write().unwrap().set_scope_quota(name, quota);
+    }
+
+    /// Limit the scopes that don't have a quota of their own.
+    pub fn set_default_scope_quota(&self, quota: ScopeQuota) {
+        self.kb.write().unwrap().set_default_scope_quota(quota);
+    }
+
+    /// Queue warnings as messages, and return the first error.
+    fn report_diagnostics(&self, diagnostics: Vec<Diagnostic>) -> Option<PolarError> {
         let (mut errors, mut warnings) = (vec![], vec![]);
-        for diagnostic in self.diagnostic_load(sources) {
+        for diagnostic in diagnostics {
             match diagnostic {
                 Diagnostic::Error(e) => errors.push(e),
                 Diagnostic::Warning(w) => warnings.push(w),
@@ -177,12 +409,108 @@ impl Polar {
 
         self.messages
             .extend(warnings.into_iter().map(Message::warning));
+        errors.into_iter().next()
+    }
 
-        if let Some(e) = errors.into_iter().next() {
-            // If we've encountered any errors, clear the KB.
-            self.clear_rules();
+    /// Load `sources` into a separate shadow KB, replacing any previous shadow policy.
+    ///
+    /// The shadow KB starts with the constants, MROs, and facts of the main KB at the time of
+    /// the call, and is only queried through `new_query_from_term_in`. Inline queries in
+    /// shadow policies are not run.
+    pub fn shadow_load(&self, sources: Vec<Source>) -> PolarResult<()> {
+        let mut kb = self.kb.read().unwrap().without_rules();
+        let diagnostics = self.load_into(&mut kb, sources);
+        if let Some(e) = self.report_diagnostics(diagnostics) {
             return Err(e);
         }
+        kb.inline_queries.clear();
+        *self.shadow.write().unwrap() = Some(Arc::new(RwLock::new(kb)));
+        Ok(())
+    }
+
+    /// Load `sources` into a copy of the KB without its rules, to replace the loaded policy
+    /// with `commit_policy`, e.g., once the staged policy's inline queries pass. Unlike `load`,
+    /// this may be called while a policy is loaded, and leaves it loaded if `sources` fail to
+    /// load, so that queries never run against a partial or empty policy.
+    pub fn stage_policy(&self, sources: Vec<Source>) -> PolarResult<Arc<RwLock<KnowledgeBase>>> {
+        let mut kb = self.kb.read().unwrap().without_rules();
+        let diagnostics = self.load_into(&mut kb, sources);
+        if let Some(e) = self.report_diagnostics(diagnostics) {
+            return Err(e);
+        }
+        Ok(Arc::new(RwLock::new(kb)))
+    }
+
+    /// Replace the loaded policy with the one staged in `staged` by `stage_policy`. Facts,
+    /// scopes, and everything else that `clear_rules` keeps are those of the KB, not of
+    /// `staged`.
+    pub fn commit_policy(&self, staged: Arc<RwLock<KnowledgeBase>>) {
+        let staged = std::mem::take(&mut *staged.write().unwrap());
+        let mut kb = self.kb.write().unwrap();
+        kb.replace_rules(staged);
+        kb.record_epoch(crate::vm::now_ms());
+        kb.action_slices();
+    }
+
+    /// Discard the shadow KB, if any.
+    pub fn clear_shadow(&self) {
+        *self.shadow.write().unwrap() = None;
+    }
+
+    pub fn shadow_kb(&self) -> Option<Arc<RwLock<KnowledgeBase>>> {
+        self.shadow.read().unwrap().clone()
+    }
+
+    /// Load `sources` in several orders, each into a copy of the KB without its rules, and
+    /// check that every order tries the clauses of every rule in the same order, so that the
+    /// policy answers every query the same way. A few sources are loaded in every order; more
+    /// are loaded in each rotation of the given order and of its reverse. Doesn't change the
+    /// KB, e.g., to run in a policy's tests before loading it.
+    ///
+    /// Clauses from named files are tried by file name, and those from unnamed sources in the
+    /// order the sources are loaded, so the check fails for unnamed sources that define the
+    /// same rule.
+    pub fn verify_load_order(&self, sources: Vec<Source>) -> PolarResult<()> {
+        let names = sources
+            .iter()
+            .enumerate()
+            .map(|(i, source)| match &source.filename {
+                Some(filename) => filename.clone(),
+                None => format!("<source {}>", i + 1),
+            })
+            .collect::<Vec<_>>();
+        let mut first: Option<(Vec<String>, _)> = None;
+        for order in load_orders(sources.len()) {
+            let mut kb = self.kb.read().unwrap().without_rules();
+            let ordered = order.iter().map(|&i| sources[i].clone());
+            let diagnostics = self.load_into(&mut kb, ordered);
+            if let Some(e) = diagnostics.into_iter().find_map(|d| match d {
+                Diagnostic::Error(e) => Some(e),
+                Diagnostic::Warning(_) => None,
+            }) {
+                return Err(e);
+            }
+
+            let order = order.iter().map(|&i| names[i].clone()).collect::<Vec<_>>();
+            let rules = kb.resolution_order();
+            match &first {
+                None => first = Some((order, rules)),
+                Some((first_order, first_rules)) => {
+                    let differs = first_rules
+                        .keys()
+                        .chain(rules.keys())
+                        .find(|name| first_rules.get(*name) != rules.get(*name));
+                    if let Some(rule) = differs {
+                        return Err(RuntimeError::LoadOrderDependent {
+                            rule: rule.to_string(),
+                            first: first_order.clone(),
+                            second: order,
+                        }
+                        .into());
+                    }
+                }
+            }
+        }
         Ok(())
     }
 
@@ -194,30 +522,112 @@ impl Polar {
     /// Clear rules from the knowledge base
     pub fn clear_rules(&self) {
         let mut kb = self.kb.write().unwrap();
-        kb.clear_rules();
+        if kb.has_rules() {
+            kb.clear_rules();
+            kb.record_epoch(crate::vm::now_ms());
+        }
     }
 
+    /// Take the next inline query waiting to be run, set to run in the scope it was loaded
+    /// into, if any.
     pub fn next_inline_query(&self, trace: bool) -> Option<Query> {
-        let term = { self.kb.write().unwrap().inline_queries.pop() };
-        term.map(|t| self.new_query_from_term(t, trace))
+        self.next_inline_query_in(&self.kb, trace)
+    }
+
+    /// Like `next_inline_query`, but taking the query from `kb` and running it against `kb`,
+    /// e.g., a policy staged with `stage_policy`.
+    pub fn next_inline_query_in(
+        &self,
+        kb: &Arc<RwLock<KnowledgeBase>>,
+        trace: bool,
+    ) -> Option<Query> {
+        let inline_query = { kb.write().unwrap().inline_queries.pop() };
+        inline_query.map(|InlineQuery { term, scope }| {
+            let mut query = self.new_query_from_term_in(kb.clone(), term, trace);
+            query.set_scope(scope);
+            query
+        })
     }
 
     pub fn new_query(&self, src: &str, trace: bool) -> PolarResult<Query> {
-        parser::parse_query(src).map(|term| self.new_query_from_term(term, trace))
+        self.parse_query(src)
+            .map(|term| self.new_query_from_term(term, trace))
+    }
+
+    fn parse_query(&self, src: &str) -> PolarResult<Term> {
+        let term = parser::parse_query(src)?;
+        parser::check_term_depth(&term, self.kb.read().unwrap().limits().max_term_depth)?;
+        Ok(term)
+    }
+
+    /// Create a query from `src` with its placeholders, like `:actor`, replaced by the terms in
+    /// `params` of the same name. Unlike interpolating values into `src`, this can't change the
+    /// structure of the query.
+    pub fn new_query_with_params(
+        &self,
+        src: &str,
+        params: HashMap<String, Term>,
+        trace: bool,
+    ) -> PolarResult<Query> {
+        let term = fill_placeholders(self.parse_query(src)?, params)?;
+        Ok(self.new_query_from_term(term, trace))
     }
 
-    pub fn new_query_from_term(&self, mut term: Term, trace: bool) -> Query {
+    pub fn new_query_from_term(&self, term: Term, trace: bool) -> Query {
+        self.new_query_from_term_in(self.kb.clone(), term, trace)
+    }
+
+    /// Create a query against `kb` instead of the main KB, e.g., the shadow KB.
+    pub fn new_query_from_term_in(
+        &self,
+        kb: Arc<RwLock<KnowledgeBase>>,
+        mut term: Term,
+        trace: bool,
+    ) -> Query {
         use crate::vm::{Goal, PolarVirtualMachine};
-        {
-            let kb = self.kb.read().unwrap();
-            term = rewrite_term(term, &kb);
-        }
+        term = rewrite_term(term, &kb.read().unwrap());
+        let slice = sliced_action(&term).map(|action| kb.read().unwrap().action_slice(action));
         let query = Goal::Query { term: term.clone() };
-        let vm =
-            PolarVirtualMachine::new(self.kb.clone(), trace, vec![query], self.messages.clone());
+        let mut vm = PolarVirtualMachine::new(kb, trace, vec![query], self.messages.clone());
+        vm.set_slice(slice);
         Query::new(vm, term)
     }
 
+    /// Continue the query suspended in `suspended` with `Query::suspend`, e.g., on another
+    /// worker. The policy must be the one the query was running against, as told by its
+    /// fingerprint. See `crate::suspend`.
+    pub fn resume_query(&self, suspended: SuspendedQuery) -> PolarResult<Query> {
+        use crate::vm::PolarVirtualMachine;
+        let fingerprint = self.kb.read().unwrap().fingerprint();
+        if fingerprint != suspended.policy_fingerprint {
+            return Err(RuntimeError::Suspension {
+                msg: format!(
+                    "the query was suspended with policy {:x}, but policy {:x} is loaded",
+                    suspended.policy_fingerprint, fingerprint
+                ),
+            }
+            .into());
+        }
+        let SuspendedQuery { term, vm, .. } = suspended;
+        let slice = sliced_action(&term).map(|action| self.kb.read().unwrap().action_slice(action));
+        let mut vm = PolarVirtualMachine::resume(self.kb.clone(), vm, self.messages.clone());
+        vm.set_slice(slice);
+        Ok(Query::new(vm, term))
+    }
+
+    /// Create a query against the rules that were loaded at `at`, in milliseconds since the
+    /// Unix epoch. The query runs against a snapshot of the knowledge base, so it doesn't see
+    /// rules loaded after it was created.
+    pub fn new_query_from_term_at(&self, term: Term, at: u64, trace: bool) -> PolarResult<Query> {
+        let kb = self
+            .kb
+            .read()
+            .unwrap()
+            .snapshot_at(at)
+            .ok_or(RuntimeError::NoPolicyEpoch { at })?;
+        Ok(self.new_query_from_term_in(Arc::new(RwLock::new(kb)), term, trace))
+    }
+
     // @TODO: Direct load_rules endpoint.
 
     pub fn get_external_id(&self) -> u64 {
@@ -228,6 +638,56 @@ impl Polar {
         self.kb.write().unwrap().register_constant(name, value)
     }
 
+    /// Register `name` as a rule answered by a host fact source. Queries for `name` that
+    /// find no clauses in the policy emit `QueryEvent::ExternalFacts` instead of failing.
+    pub fn register_fact_source(&self, name: Symbol) -> PolarResult<()> {
+        self.kb.write().unwrap().register_fact_source(name)
+    }
+
+    /// Register `name` as a host predicate that rules may use as the specializer `@name`.
+    /// Queries ask the host whether arguments satisfy it with
+    /// `QueryEvent::ExternalSpecializer`.
+    pub fn register_specializer_predicate(&self, name: Symbol) {
+        self.kb
+            .write()
+            .unwrap()
+            .register_specializer_predicate(name)
+    }
+
+    /// Register `name` as a method that the host can call on many instances at once, as for
+    /// `KnowledgeBase::register_batched_method`.
+    pub fn register_batched_method(&self, name: Symbol) {
+        self.kb.write().unwrap().register_batched_method(name)
+    }
+
+    /// Assert the ground fact `name(args)`. Queries for `name` unify with matching facts
+    /// before trying any rules. Returns `false` if the fact was already present.
+    pub fn insert_fact(&self, name: Symbol, args: TermList) -> PolarResult<bool> {
+        self.kb.write().unwrap().insert_fact(name, args)
+    }
+
+    /// Assert many ground facts for `name` while holding the knowledge base lock once.
+    /// Returns the number of facts that weren't already present.
+    pub fn insert_facts(&self, name: Symbol, facts: Vec<TermList>) -> PolarResult<usize> {
+        self.kb.write().unwrap().insert_facts(name, facts)
+    }
+
+    /// Retract the ground fact `name(args)`. Returns `false` if there was no such fact.
+    pub fn delete_fact(&self, name: Symbol, args: TermList) -> PolarResult<bool> {
+        Ok(self.kb.write().unwrap().delete_fact(&name, &args))
+    }
+
+    /// Register a lint rule to check policies loaded after this call.
+    pub fn register_lint_rule(&self, rule: Box<dyn LintRule>) {
+        self.lint_rules.write().unwrap().push(rule);
+    }
+
+    /// Register a rewrite pass to transform the rules of policies loaded after this call. Passes
+    /// run in the order they're registered.
+    pub fn register_rewrite_pass(&self, pass: Box<dyn RewritePass>) {
+        self.rewrite_passes.write().unwrap().register(pass);
+    }
+
     /// Register MRO for `name` with `mro`.
     ///
     /// Params:
@@ -242,6 +702,12 @@ impl Polar {
         self.messages.next()
     }
 
+    /// Export the loaded `allow` and `deny` rules as Cedar policies. See [`crate::cedar`] for
+    /// the subset of Polar that can be exported.
+    pub fn export_cedar(&self) -> PolarResult<String> {
+        cedar::export(&self.kb.read().unwrap())
+    }
+
     pub fn build_filter_plan(
         &self,
         types: Types,
@@ -259,7 +725,77 @@ impl Polar {
         variable: &str,
         class_tag: &str,
     ) -> PolarResult<Filter> {
-        Filter::build(types, partial_results, variable, class_tag)
+        let filter = Filter::build(types, partial_results, variable, class_tag)?;
+        Ok(filter.with_rules(&self.kb.read().unwrap()))
+    }
+
+    /// Set how queries handle integer arithmetic that overflows an `i64`.
+    pub fn set_integer_overflow(&self, mode: IntegerOverflow) {
+        self.kb.write().unwrap().set_integer_overflow(mode);
+    }
+
+    /// What loading a clause identical to one of its rule's clauses does.
+    pub fn duplicate_clauses(&self) -> DuplicateClauses {
+        self.kb.read().unwrap().duplicate_clauses()
+    }
+
+    /// Set what loading a clause identical to one of its rule's clauses does, for policies
+    /// loaded after this call.
+    pub fn set_duplicate_clauses(&self, mode: DuplicateClauses) {
+        self.kb.write().unwrap().set_duplicate_clauses(mode);
+    }
+
+    /// Set whether queries started after this call check, when unifying a variable with a
+    /// term, that the term doesn't contain the variable, failing with
+    /// `RuntimeError::OccursCheck` rather than making a cyclic term like `x = [x]` does. Off by
+    /// default, since the check walks the term at every binding.
+    pub fn set_occurs_check(&self, enabled: bool) {
+        self.kb.write().unwrap().set_occurs_check(enabled);
+    }
+
+    /// Set limits on the nesting of policies and queries. Term depth applies to policies and
+    /// queries parsed after this call; the other limits to queries started after it.
+    pub fn set_limits(&self, limits: Limits) {
+        self.kb.write().unwrap().set_limits(limits);
+    }
+
+    /// Declare the rule `name` of `arity` parameters a hot entrypoint, whose clauses are grouped
+    /// at load time by the constant value of parameter `param`, e.g., `allow/3` by its action.
+    /// Calls with a constant argument in that position then skip filtering the other clauses.
+    pub fn add_hot_entrypoint(&self, name: &str, arity: usize, param: usize) -> PolarResult<()> {
+        self.kb
+            .write()
+            .unwrap()
+            .add_hot_entrypoint(Symbol::new(name), arity, param)
+    }
+
+    /// Format terms in traces, stack traces, and logs of queries started after this call with
+    /// `formatter`, e.g., to keep them short or redact secrets.
+    pub fn set_term_formatter(&self, formatter: TermFormatter) {
+        self.kb.write().unwrap().set_term_formatter(formatter);
+    }
+
+    /// Issue IDs with `partition` in their high bits, as for `KnowledgeBase::set_id_partition`.
+    /// IDs issued before this call are not affected.
+    pub fn set_id_partition(&self, partition: u64, bits: u32) -> PolarResult<()> {
+        self.kb.write().unwrap().set_id_partition(partition, bits)
+    }
+
+    /// How many times IDs ran out, and a new generation of IDs was started.
+    pub fn id_wraps(&self) -> u64 {
+        self.kb.read().unwrap().id_wraps()
+    }
+
+    /// Have queries started after this call emit a `QueryEvent::Heartbeat` every `interval`
+    /// goals run without another event, or no heartbeats if `interval` is `None`.
+    pub fn set_heartbeat_interval(&self, interval: Option<u64>) {
+        self.kb.write().unwrap().set_heartbeat_interval(interval);
+    }
+
+    /// Keep the rules of the last `max` policy epochs for `new_query_from_term_at`, forgetting
+    /// older ones. None are kept by default.
+    pub fn set_max_policy_epochs(&self, max: usize) {
+        self.kb.write().unwrap().set_max_policy_epochs(max);
     }
 
     // TODO(@gkaemmer): this is a hack and should not be used for similar cases.
@@ -270,10 +806,70 @@ impl Polar {
     }
 }
 
+/// Return an error if a policy of `rules` rules from sources totalling `bytes` bytes exceeds the
+/// size limits of `quota`, the quota of the scope `name`.
+fn check_scope_size(name: &str, quota: &ScopeQuota, rules: u64, bytes: u64) -> PolarResult<()> {
+    match quota.check_size(rules, bytes) {
+        Some((quota, limit)) => Err(RuntimeError::QuotaExceeded {
+            scope: name.to_owned(),
+            quota,
+            limit,
+            term: None,
+        }
+        .into()),
+        None => Ok(()),
+    }
+}
+
+/// The action of a query `allow(actor, action, resource)` for a constant action.
+fn sliced_action(term: &Term) -> Option<&Value> {
+    let call = term.as_call().ok()?;
+    if call.name.as_str() != crate::slices::SLICED_RULE || call.args.len() != 3 {
+        return None;
+    }
+    let action = call.args[1].value();
+    action.is_ground().then_some(action)
+}
+
+/// Most sources `Polar::verify_load_order` loads in every order.
+const MAX_PERMUTED_SOURCES: usize = 4;
+
+/// The orders `Polar::verify_load_order` loads `n` sources in, starting with the given order.
+fn load_orders(n: usize) -> Vec<Vec<usize>> {
+    if n <= MAX_PERMUTED_SOURCES {
+        let mut orders = vec![vec![]];
+        for _ in 0..n {
+            orders = orders
+                .into_iter()
+                .flat_map(|order: Vec<usize>| {
+                    (0..n)
+                        .filter(|i| !order.contains(i))
+                        .map(|i| {
+                            let mut order = order.clone();
+                            order.push(i);
+                            order
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect();
+        }
+        orders
+    } else {
+        (0..n)
+            .flat_map(|r| {
+                let rotation = (0..n).map(|i| (i + r) % n).collect::<Vec<_>>();
+                let reverse = rotation.iter().rev().copied().collect();
+                [rotation, reverse]
+            })
+            .collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::error::{RuntimeError::MultipleLoadError, ValidationError::FileLoading};
+    use crate::events::QueryEvent;
 
     #[test]
     fn can_load_and_query() {
@@ -321,6 +917,270 @@ mod tests {
         assert!(!polar.kb.read().unwrap().has_rules());
     }
 
+    #[test]
+    fn fingerprints_ignore_rule_order_and_variable_names() {
+        let fingerprint = |src: &str| {
+            let polar = Polar::new();
+            polar.load_str(src).unwrap();
+            let fingerprint = polar.kb.read().unwrap().fingerprint();
+            fingerprint
+        };
+        let policy = "f(x) if x.a = 1 and g(x); g(_); h(1, [y, *z]) if y in z;";
+        let first = fingerprint(policy);
+        assert_eq!(
+            first,
+            fingerprint("h(1, [b, *c]) if b in c; g(_y); f(y) if y.a = 1 and g(y);")
+        );
+        assert_ne!(
+            first,
+            fingerprint("f(x) if x.a = 2 and g(x); g(_); h(1, [y, *z]) if y in z;")
+        );
+        assert_ne!(first, fingerprint("f(x) if x.a = 1 and g(x); g(_);"));
+
+        // Fingerprints are cached until the rules change.
+        let polar = Polar::new();
+        polar.load_str(policy).unwrap();
+        assert_eq!(polar.kb.read().unwrap().fingerprint(), first);
+        polar.clear_rules();
+        assert_ne!(polar.kb.read().unwrap().fingerprint(), first);
+        polar.load_str(policy).unwrap();
+        assert_eq!(polar.kb.read().unwrap().fingerprint(), first);
+    }
+
+    #[test]
+    fn results_dont_depend_on_file_load_order() {
+        let sources = || {
+            vec![
+                Source::new_with_name("b.polar", "f(2); f(3);"),
+                Source::new_with_name("a.polar", "f(1); g(x) if f(x);"),
+                Source::new_with_name("c.polar", "f(4);"),
+            ]
+        };
+        let results = |sources: Vec<Source>| {
+            let polar = Polar::new();
+            polar.load(sources).unwrap();
+            polar
+                .new_query_from_term(term!(call!("g", [sym!("x")])), false)
+                .filter_map(|event| match event.unwrap() {
+                    QueryEvent::Result { bindings, .. } => Some(bindings[&sym!("x")].clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let expected = vec![term!(1), term!(2), term!(3), term!(4)];
+        assert_eq!(results(sources()), expected);
+        let mut reversed = sources();
+        reversed.reverse();
+        assert_eq!(results(reversed), expected);
+
+        let polar = Polar::new();
+        polar.verify_load_order(sources()).unwrap();
+        // Sources that name their files are loaded in more orders.
+        let many = (0..6)
+            .map(|i| Source::new_with_name(format!("{}.polar", i), format!("f({});", i)))
+            .collect();
+        polar.verify_load_order(many).unwrap();
+        assert!(!polar.kb.read().unwrap().has_rules());
+
+        // Unnamed sources are tried in the order they're loaded.
+        let err = polar
+            .verify_load_order(vec![Source::new("f(1);"), Source::new("f(2);")])
+            .unwrap_err();
+        assert!(matches!(
+            err.unwrap_runtime(),
+            RuntimeError::LoadOrderDependent { rule, .. } if rule == "f"
+        ));
+    }
+
+    #[test]
+    fn allow_queries_use_the_rules_for_their_action() {
+        let polar = Polar::new();
+        polar
+            .load_str(
+                r#"allow(actor, "read", doc) if reader(actor, doc);
+                   allow(actor, "write", doc) if writer(actor, doc);
+                   allow(actor, action, _) if admin(actor, action);
+                   reader(actor, doc) if member(actor, doc, "reader");
+                   reader(actor, doc) if writer(actor, doc);
+                   writer(actor, doc) if member(actor, doc, "writer");
+                   member("alice", "doc", "reader");
+                   member("bob", "doc", "writer");
+                   admin("carol", "delete");"#,
+            )
+            .unwrap();
+
+        let kb = polar.kb.read().unwrap();
+        let len = |action: &str, rule: &str| {
+            kb.action_slice(&Value::String(action.to_owned()))
+                .get_generic_rule(&sym!(rule))
+                .map_or(0, |generic_rule| generic_rule.rules.len())
+        };
+        assert_eq!(len("read", "allow"), 2);
+        assert_eq!(len("read", "reader"), 2);
+        assert_eq!(len("read", "member"), 2);
+        assert_eq!(len("write", "reader"), 0);
+        assert_eq!(len("write", "writer"), 1);
+        // The member rule for "reader" can't match calls for writers.
+        assert_eq!(len("write", "member"), 1);
+        // Actions that no rule names get the rules for any action.
+        assert_eq!(len("delete", "allow"), 1);
+        assert_eq!(len("delete", "admin"), 1);
+        assert_eq!(
+            kb.action_slice(&Value::String("delete".to_owned())).len(),
+            2
+        );
+        drop(kb);
+
+        let allowed = |actor: &str, action: &str| {
+            polar
+                .new_query_from_term(term!(call!("allow", [actor, action, "doc"])), false)
+                .any(|event| matches!(event.unwrap(), QueryEvent::Result { .. }))
+        };
+        assert!(allowed("alice", "read"));
+        assert!(!allowed("alice", "write"));
+        assert!(allowed("bob", "read"));
+        assert!(allowed("bob", "write"));
+        assert!(allowed("carol", "delete"));
+        assert!(!allowed("carol", "read"));
+    }
+
+    #[test]
+    fn queries_count_choices_and_backtracks_by_clause() {
+        let polar = Polar::new();
+        polar
+            .load(vec![Source::new_with_name(
+                "app.polar",
+                "f(x) if g(x) and x > 1;\ng(1);\ng(2);\ng(3);\nh(x) if g(x) and cut;",
+            )])
+            .unwrap();
+
+        let run = |name: &str| {
+            let mut query = polar.new_query_from_term(term!(call!(name, [sym!("x")])), false);
+            query.by_ref().collect::<PolarResult<Vec<_>>>().unwrap();
+            query.choice_stats()
+        };
+        let stats = run("f");
+        let counts = stats
+            .clauses
+            .iter()
+            .map(|c| (c.line.unwrap(), c.tried, c.backtracks))
+            .collect::<Vec<_>>();
+        // g(1) fails, and g(2) succeeds but is left to find another result.
+        assert_eq!(counts, vec![(1, 1, 0), (2, 1, 1), (3, 1, 1), (4, 1, 0)]);
+        assert_eq!(stats.rule_backtracks()[&sym!("g")], 2);
+        assert_eq!((stats.created, stats.pruned), (1, 0));
+
+        let heat_map = stats.heat_map();
+        assert!(heat_map.starts_with("app.polar\n"), "{}", heat_map);
+        assert!(heat_map.contains(&format!(
+            "    2 | {} | tried 1, backtracked 1: g(1)",
+            "#".repeat(24)
+        )));
+
+        let stats = run("h");
+        assert_eq!((stats.created, stats.pruned), (1, 1));
+        assert_eq!(stats.rule_backtracks()[&sym!("g")], 0);
+    }
+
+    #[test]
+    fn queries_at_a_time_use_the_policy_loaded_then() {
+        let polar = Polar::new();
+        polar.set_max_policy_epochs(10);
+        polar.load_str("f(1);").unwrap();
+        let first = polar.kb.read().unwrap().epoch_timestamps()[0];
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        polar.clear_rules();
+        polar.load_str("f(2);").unwrap();
+
+        let results = |query: Query| {
+            query
+                .filter_map(|event| match event.unwrap() {
+                    QueryEvent::Result { bindings, .. } => Some(bindings[&sym!("x")].clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let query = polar.new_query_from_term_at(term!(call!("f", [sym!("x")])), first, false);
+        assert_eq!(results(query.unwrap()), vec![term!(1)]);
+
+        let query = polar.new_query_from_term_at(term!(call!("f", [sym!("x")])), u64::MAX, false);
+        assert_eq!(results(query.unwrap()), vec![term!(2)]);
+
+        let err = polar
+            .new_query_from_term_at(term!(call!("f", [sym!("x")])), 0, false)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.unwrap_runtime(),
+            RuntimeError::NoPolicyEpoch { at: 0 }
+        ));
+    }
+
+    #[test]
+    fn shadow_policies_are_loaded_separately() {
+        let polar = Polar::new();
+        polar.load_str("f(1);").unwrap();
+        polar.shadow_load(vec![Source::new("f(2);")]).unwrap();
+
+        let results = |query: Query| {
+            query
+                .filter_map(|event| match event.unwrap() {
+                    QueryEvent::Result { bindings, .. } => Some(bindings[&sym!("x")].clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let query = term!(call!("f", [sym!("x")]));
+        assert_eq!(
+            results(polar.new_query_from_term(query.clone(), false)),
+            vec![term!(1)]
+        );
+        let shadow = polar.shadow_kb().unwrap();
+        assert_eq!(
+            results(polar.new_query_from_term_in(shadow, query, false)),
+            vec![term!(2)]
+        );
+
+        assert!(polar
+            .shadow_load(vec![Source::new("f(x) if g(x);")])
+            .is_err());
+        assert!(polar.shadow_kb().is_some());
+        polar.clear_shadow();
+        assert!(polar.shadow_kb().is_none());
+    }
+
+    #[test]
+    fn staged_policies_replace_the_loaded_policy_when_committed() {
+        let polar = Polar::new();
+        polar.load_str("f(1);").unwrap();
+        polar.insert_fact(sym!("g"), vec![term!(1)]).unwrap();
+
+        let results = |polar: &Polar, name: &str| {
+            polar
+                .new_query_from_term(term!(call!(name, [sym!("x")])), false)
+                .filter_map(|event| match event.unwrap() {
+                    QueryEvent::Result { bindings, .. } => Some(bindings[&sym!("x")].clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        // A policy that fails to load leaves the loaded policy as it was.
+        assert!(polar.stage_policy(vec![Source::new("f(")]).is_err());
+        assert_eq!(results(&polar, "f"), vec![term!(1)]);
+
+        let staged = polar
+            .stage_policy(vec![Source::new("f(2); ?= f(2);")])
+            .unwrap();
+        assert_eq!(results(&polar, "f"), vec![term!(1)]);
+        let inline_query = polar.next_inline_query_in(&staged, false).unwrap();
+        assert!(inline_query
+            .map(Result::unwrap)
+            .any(|event| matches!(event, QueryEvent::Result { .. })));
+        polar.commit_policy(staged);
+        assert_eq!(results(&polar, "f"), vec![term!(2)]);
+        assert_eq!(results(&polar, "g"), vec![term!(1)]);
+    }
+
     #[test]
     fn diagnostic_load_returns_multiple_diagnostics() {
         let polar = Polar::new();
@@ -388,7 +1248,7 @@ mod tests {
         let kb = polar.kb.read().unwrap();
         let rules = kb.get_rules().values().flat_map(|g| g.rules.values());
         let has_permission_rules = rules
-            .filter(|r| r.name.0 == "has_permission")
+            .filter(|r| r.name == "has_permission")
             .collect::<Vec<_>>();
         assert_eq!(has_permission_rules.len(), 1, "{:#?}", has_permission_rules);
         let has_permission_rule = has_permission_rules.into_iter().next().unwrap();
@@ -0,0 +1,163 @@
+//! Counts of the choice points between rule clauses that a query creates and prunes, and of
+//! how often its search backtracked out of each clause, for finding the clauses that cause
+//! wasted search.
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use super::lexer::loc_to_pos;
+use super::rules::Rule;
+use super::terms::Symbol;
+
+/// Counts of the choice points of a query, and of the search in each rule clause it tried.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ChoiceStats {
+    /// Choice points between the clauses of a rule, made by each call to a rule with more
+    /// than one applicable clause.
+    pub created: u64,
+    /// Choice points between the clauses of a rule discarded by `cut` before every clause was
+    /// tried.
+    pub pruned: u64,
+    /// The clauses tried, by source file and position.
+    pub clauses: Vec<ClauseStats>,
+}
+
+/// Counts of the search in one rule clause.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ClauseStats {
+    pub rule: Symbol,
+    /// The head of the clause, e.g., `allow(actor, "read", resource)`.
+    pub head: String,
+    /// The file the clause was loaded from, if it has a name.
+    pub filename: Option<String>,
+    /// The line the clause starts on, from 1, if it was parsed.
+    pub line: Option<usize>,
+    /// Times the clause was tried.
+    pub tried: u64,
+    /// Times the search backtracked out of the clause to try the next clause of its rule,
+    /// either because the clause failed or to find another result.
+    pub backtracks: u64,
+}
+
+impl ChoiceStats {
+    /// Backtracks out of the clauses of each rule.
+    pub fn rule_backtracks(&self) -> BTreeMap<Symbol, u64> {
+        let mut backtracks = BTreeMap::new();
+        for clause in &self.clauses {
+            *backtracks.entry(clause.rule.clone()).or_default() += clause.backtracks;
+        }
+        backtracks
+    }
+
+    /// A heat map of the clauses tried, by source file: each clause with its counts and a bar
+    /// as long as its share of the most backtracks out of any clause, e.g.,
+    ///
+    /// ```text
+    /// policy.polar
+    ///    3 | ######################## | tried 10, backtracked 9: allow(actor, "read", doc)
+    ///    7 |                          | tried 1, backtracked 0: has_role(actor, role, doc)
+    /// ```
+    pub fn heat_map(&self) -> String {
+        self.to_string()
+    }
+}
+
+const HEAT_MAP_WIDTH: u64 = 24;
+
+impl fmt::Display for ChoiceStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hottest = self.clauses.iter().map(|c| c.backtracks).max().unwrap_or(0);
+        let mut file = None;
+        for clause in &self.clauses {
+            if file != Some(&clause.filename) {
+                file = Some(&clause.filename);
+                writeln!(
+                    f,
+                    "{}",
+                    clause.filename.as_deref().unwrap_or("<unnamed source>")
+                )?;
+            }
+            let bar = match hottest {
+                0 => 0,
+                hottest => (clause.backtracks * HEAT_MAP_WIDTH).div_ceil(hottest),
+            };
+            let line = clause
+                .line
+                .map_or_else(|| "?".to_owned(), |l| l.to_string());
+            writeln!(
+                f,
+                "{:>5} | {:<width$} | tried {}, backtracked {}: {}",
+                line,
+                "#".repeat(bar as usize),
+                clause.tried,
+                clause.backtracks,
+                clause.head,
+                width = HEAT_MAP_WIDTH as usize,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Counts choice points and clause searches as a query runs. Shared by a VM and its children.
+#[derive(Default)]
+pub(crate) struct ChoiceProfiler {
+    created: u64,
+    pruned: u64,
+    /// Counts by clause. The clause is kept so that its address isn't reused.
+    clauses: HashMap<*const Rule, (Arc<Rule>, u64, u64)>,
+}
+
+impl ChoiceProfiler {
+    pub(crate) fn created(&mut self) {
+        self.created += 1;
+    }
+
+    pub(crate) fn pruned(&mut self, choices: usize) {
+        self.pruned += choices as u64;
+    }
+
+    pub(crate) fn tried(&mut self, rule: &Arc<Rule>) {
+        self.clause(rule).1 += 1;
+    }
+
+    pub(crate) fn backtracked(&mut self, rule: &Arc<Rule>) {
+        self.clause(rule).2 += 1;
+    }
+
+    fn clause(&mut self, rule: &Arc<Rule>) -> &mut (Arc<Rule>, u64, u64) {
+        self.clauses
+            .entry(Arc::as_ptr(rule))
+            .or_insert_with(|| (rule.clone(), 0, 0))
+    }
+
+    pub(crate) fn stats(&self) -> ChoiceStats {
+        let mut clauses = self
+            .clauses
+            .values()
+            .map(|(rule, tried, backtracks)| {
+                let context = rule.parsed_context();
+                let line = context.map(|context| {
+                    let (row, _) = loc_to_pos(&context.source.src, context.left);
+                    context.source.first_line + row + 1
+                });
+                ClauseStats {
+                    rule: rule.name.clone(),
+                    head: rule.head_as_string(),
+                    filename: context.and_then(|context| context.source.filename.clone()),
+                    line,
+                    tried: *tried,
+                    backtracks: *backtracks,
+                }
+            })
+            .collect::<Vec<_>>();
+        clauses.sort_by(|a, b| (&a.filename, a.line, &a.head).cmp(&(&b.filename, b.line, &b.head)));
+        ChoiceStats {
+            created: self.created,
+            pruned: self.pruned,
+            clauses,
+        }
+    }
+}
@@ -1,8 +1,16 @@
-use super::error::PolarResult;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+
+use super::bindings::BindingStats;
+use super::error::{PolarResult, RuntimeError};
 use super::events::*;
+use super::facts::Precomputed;
 use super::messages::*;
+use super::profile::ChoiceStats;
 use super::runnable::Runnable;
+use super::suspend::SuspendedQuery;
 use super::terms::*;
+use super::traces::{Backtrace, Counterexample};
 use super::vm::*;
 
 pub struct Query {
@@ -10,6 +18,7 @@ pub struct Query {
     vm: PolarVirtualMachine,
     term: Term,
     done: bool,
+    panicked: bool,
 }
 
 impl Query {
@@ -19,6 +28,7 @@ impl Query {
             vm,
             term,
             done: false,
+            panicked: false,
         }
     }
 
@@ -34,7 +44,29 @@ impl Query {
     /// 3. Immediately request the next event, which will execute Runnable B.
     /// 4. When Runnable B emits a Done event, pop Runnable B off the stack and return its result as
     ///    an answer to Runnable A.
+    ///
+    /// A panic while running the query is returned as an `EnginePanic` error, after which the
+    /// query is done.
     pub fn next_event(&mut self) -> PolarResult<QueryEvent> {
+        if self.panicked {
+            return Err(RuntimeError::EnginePanic {
+                msg: "query was resumed after it panicked".to_owned(),
+            }
+            .into());
+        }
+        // The query may be left in an inconsistent state by a panic, so it's never resumed
+        // afterwards. The VM only takes read locks on the knowledge base, which aren't poisoned
+        // by a panic, so other queries are unaffected.
+        std::panic::catch_unwind(AssertUnwindSafe(|| self.run_next_event())).unwrap_or_else(
+            |payload| {
+                self.panicked = true;
+                self.done = true;
+                Err(RuntimeError::engine_panic(payload).into())
+            },
+        )
+    }
+
+    fn run_next_event(&mut self) -> PolarResult<QueryEvent> {
         let mut counter = self.vm.id_counter();
         let qe = match self.top_runnable().run(Some(&mut counter)) {
             Ok(e) => e,
@@ -45,16 +77,16 @@ impl Query {
 
     fn recv_event(&mut self, qe: QueryEvent) -> PolarResult<QueryEvent> {
         match qe {
-            QueryEvent::None => self.next_event(),
+            QueryEvent::None => self.run_next_event(),
             QueryEvent::Run { runnable, call_id } => {
                 self.push_runnable(runnable, call_id);
-                self.next_event()
+                self.run_next_event()
             }
             QueryEvent::Done { result } => {
                 if let Some((_, result_call_id)) = self.pop_runnable() {
                     self.top_runnable()
                         .external_question_result(result_call_id, result)?;
-                    self.next_event()
+                    self.run_next_event()
                 } else {
                     // VM is done.
                     assert!(self.runnable_stack.is_empty());
@@ -80,10 +112,23 @@ impl Query {
         self.runnable_stack.pop()
     }
 
+    /// The rule calls in progress when an error last aborted the query, if one has.
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        match self.runnable_stack.last() {
+            Some((runnable, _)) => runnable.error_backtrace(),
+            None => self.vm.error_backtrace(),
+        }
+    }
+
     pub fn call_result(&mut self, call_id: u64, value: Option<Term>) -> PolarResult<()> {
         self.top_runnable().external_call_result(call_id, value)
     }
 
+    /// Answer a `QueryEvent::ExternalCallBatch` with the result for each of its instances.
+    pub fn call_results(&mut self, call_id: u64, values: Vec<Option<Term>>) -> PolarResult<()> {
+        self.top_runnable().external_call_results(call_id, values)
+    }
+
     pub fn question_result(&mut self, call_id: u64, result: bool) -> PolarResult<()> {
         self.top_runnable()
             .external_question_result(call_id, result)
@@ -93,10 +138,90 @@ impl Query {
         self.vm.external_error(message)
     }
 
+    /// Return true if an application error would be caught by a `try` in progress.
+    pub fn catches_errors(&self) -> bool {
+        self.vm.catches_errors()
+    }
+
+    /// Query the rules loaded into `scope` with `Polar::load_scope`, along with the rules
+    /// loaded without a scope.
+    pub fn set_scope(&mut self, scope: Option<String>) {
+        self.vm.set_scope(scope);
+    }
+
+    /// The scope set with `set_scope`, if any.
+    pub fn scope(&self) -> Option<&str> {
+        self.vm.scope()
+    }
+
+    /// Fail the query once it has run for `timeout_ms` milliseconds, or never if `timeout_ms`
+    /// is 0. Overrides the `POLAR_TIMEOUT_MS` environment variable.
+    pub fn set_query_timeout(&mut self, timeout_ms: u64) {
+        self.vm.set_query_timeout(timeout_ms);
+    }
+
+    /// Answer calls from `precomputed` rather than from the rules & facts of the KB.
+    pub fn set_precomputed(&mut self, precomputed: Option<Arc<Precomputed>>) {
+        self.vm.set_precomputed(precomputed);
+    }
+
+    /// Emit a `QueryEvent::Heartbeat` every `interval` goals run without another event, or no
+    /// heartbeats if `interval` is `None`. Overrides `Polar::set_heartbeat_interval`.
+    pub fn set_heartbeat_interval(&mut self, interval: Option<u64>) {
+        self.vm.heartbeat_interval = interval;
+    }
+
+    /// Check that variables aren't unified with terms that contain them, as for
+    /// `Polar::set_occurs_check`, which this overrides.
+    pub fn set_occurs_check(&mut self, enabled: bool) {
+        self.vm.occurs_check = enabled;
+    }
+
+    /// The state of the query, to resume with `Polar::resume_query`, e.g., on another worker.
+    /// See `crate::suspend`. Queries that are running a subquery for the application, like
+    /// data filtering, or that have finished, can't be suspended.
+    pub fn suspend(&self) -> PolarResult<SuspendedQuery> {
+        let msg = if self.done {
+            Some("the query has finished")
+        } else if !self.runnable_stack.is_empty() {
+            Some("the query is running a subquery")
+        } else {
+            None
+        };
+        if let Some(msg) = msg {
+            return Err(RuntimeError::Suspension {
+                msg: msg.to_owned(),
+            }
+            .into());
+        }
+        Ok(SuspendedQuery {
+            policy_fingerprint: self.vm.kb.read().unwrap().fingerprint(),
+            term: self.term.clone(),
+            vm: self.vm.suspend()?,
+        })
+    }
+
     pub fn debug_command(&mut self, command: &str) -> PolarResult<()> {
         self.top_runnable().debug_command(command)
     }
 
+    /// Counts of the binding operations of this query, e.g., to measure how much backtracking
+    /// costs.
+    pub fn binding_stats(&self) -> BindingStats {
+        self.vm.binding_stats()
+    }
+
+    /// Counts of the choice points this query created and pruned, and of the backtracking
+    /// out of each rule clause it tried, e.g., to render with [`ChoiceStats::heat_map`].
+    pub fn choice_stats(&self) -> ChoiceStats {
+        self.vm.choice_stats()
+    }
+
+    /// Return the counterexamples to `forall` operations that failed so far, oldest first.
+    pub fn counterexamples(&self) -> Vec<Counterexample> {
+        self.vm.counterexamples.borrow().clone()
+    }
+
     pub fn next_message(&self) -> Option<Message> {
         self.vm.messages.next()
     }
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::terms::Symbol;
+
+/// A limit enforced by a [`ScopeQuota`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum Quota {
+    /// Number of rules loaded into the scope.
+    Rules,
+    /// Total size in bytes of the sources loaded into the scope.
+    Bytes,
+    /// Number of goals run by one query in the scope.
+    QueryGoals,
+    /// Number of clauses of a rule entered by one query in the scope.
+    RuleCalls(Symbol),
+    /// Number of goals run by one query in the scope inside calls of a rule.
+    RuleGoals(Symbol),
+}
+
+impl fmt::Display for Quota {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Rules => write!(f, "rules"),
+            Self::Bytes => write!(f, "bytes"),
+            Self::QueryGoals => write!(f, "query goals"),
+            Self::RuleCalls(rule) => write!(f, "calls of rule {}", rule),
+            Self::RuleGoals(rule) => write!(f, "goals in calls of rule {}", rule),
+        }
+    }
+}
+
+/// Limits the size of the policy loaded into a scope, like one tenant's policy, and the cost
+/// of the queries run in it, so that one scope can't degrade an engine shared by many.
+///
+/// Size limits are checked by `Polar::load_scope`, which rejects the whole policy if it's too
+/// big. The goal budget is checked by the VM as a query in the scope runs; each query has its
+/// own budget. All limits are unset by default.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScopeQuota {
+    pub max_rules: Option<u64>,
+    pub max_bytes: Option<u64>,
+    pub max_query_goals: Option<u64>,
+    /// Limits of individual rules, by name.
+    pub rules: HashMap<Symbol, RuleQuota>,
+}
+
+impl ScopeQuota {
+    /// Create a quota without limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the number of rules loaded into the scope.
+    pub fn max_rules(mut self, max: u64) -> Self {
+        self.max_rules = Some(max);
+        self
+    }
+
+    /// Limit the total size in bytes of the sources loaded into the scope.
+    pub fn max_bytes(mut self, max: u64) -> Self {
+        self.max_bytes = Some(max);
+        self
+    }
+
+    /// Limit the number of goals each query in the scope may run.
+    pub fn max_query_goals(mut self, max: u64) -> Self {
+        self.max_query_goals = Some(max);
+        self
+    }
+
+    /// Limit the share of each query in the scope that calls of the rule `name` may use, so
+    /// that a helper rule, e.g., one loaded by a tenant, can't use up the whole goal budget of
+    /// the query.
+    pub fn rule(mut self, name: &str, quota: RuleQuota) -> Self {
+        self.rules.insert(Symbol::new(name), quota);
+        self
+    }
+
+    /// Return the first size limit exceeded by a policy of `rules` rules from sources totalling
+    /// `bytes` bytes, along with its limit.
+    pub fn check_size(&self, rules: u64, bytes: u64) -> Option<(Quota, u64)> {
+        match (self.max_rules, self.max_bytes) {
+            (Some(max), _) if rules > max => Some((Quota::Rules, max)),
+            (_, Some(max)) if bytes > max => Some((Quota::Bytes, max)),
+            _ => None,
+        }
+    }
+}
+
+/// Limits one rule's share of a query in a scope. Each query counts its calls of the rule, and
+/// the goals it runs inside them, including in the rules they call; exceeding a limit is an
+/// error, like exceeding the goal budget of the scope. All limits are unset by default.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleQuota {
+    pub max_calls: Option<u64>,
+    pub max_goals: Option<u64>,
+}
+
+impl RuleQuota {
+    /// Create a quota without limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the number of clauses of the rule each query may enter. A call counts once for
+    /// each applicable clause it tries.
+    pub fn max_calls(mut self, max: u64) -> Self {
+        self.max_calls = Some(max);
+        self
+    }
+
+    /// Limit the number of goals each query may run inside calls of the rule.
+    pub fn max_goals(mut self, max: u64) -> Self {
+        self.max_goals = Some(max);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_size() {
+        let quota = ScopeQuota::new().max_rules(2).max_bytes(100);
+        assert_eq!(quota.check_size(2, 100), None);
+        assert_eq!(quota.check_size(3, 100), Some((Quota::Rules, 2)));
+        assert_eq!(quota.check_size(1, 101), Some((Quota::Bytes, 100)));
+        assert_eq!(quota.check_size(3, 101), Some((Quota::Rules, 2)));
+        assert_eq!(ScopeQuota::new().check_size(u64::MAX, u64::MAX), None);
+    }
+}
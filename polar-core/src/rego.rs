@@ -0,0 +1,1315 @@
+//! A best-effort translation of policies in Rego, the language of Open Policy Agent, to Polar,
+//! for migrating them to oso.
+//!
+//! The translation covers rules over the `input` and `data` documents: boolean rules, partial
+//! set rules, and functions, whose bodies compare, assign, iterate with `some ... in` and `[_]`,
+//! and negate with `not`. Rules are named after their path in `data`, and take the input
+//! document as their first argument, so in the package `authz`, the Rego rule `allow` becomes
+//! the Polar rule `authz_allow(input)`, the set rule `roles[r]` becomes `authz_roles(input, r)`,
+//! and the function `f(x)` becomes `authz_f(input, x)`. The data document is the Polar constant
+//! `data`, which the host registers.
+//!
+//! Rules that use anything else, like built-in functions, comprehensions, `every`, `with`, or
+//! `else`, are left out of the translation whole, along with the rules that depend on them,
+//! and reported as [`RegoIssue`]s. Leaving out some of a rule's conditions could allow more than
+//! the Rego policy does.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// A construct that couldn't be translated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegoIssue {
+    /// The line of the construct in the Rego source, counting from 1.
+    pub line: usize,
+    /// The rule left out of the translation because of the construct, if any.
+    pub rule: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for RegoIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)?;
+        if let Some(rule) = &self.rule {
+            write!(f, " (left out rule `{}`)", rule)?;
+        }
+        Ok(())
+    }
+}
+
+/// The Polar translation of a Rego policy.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RegoTranslation {
+    pub polar: String,
+    pub issues: Vec<RegoIssue>,
+}
+
+/// Translate the Rego policy `src` to Polar.
+pub fn translate(src: &str) -> RegoTranslation {
+    let tokens = match tokenize(src) {
+        Ok(tokens) => tokens,
+        Err(issue) => {
+            return RegoTranslation {
+                polar: String::new(),
+                issues: vec![issue],
+            }
+        }
+    };
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        depth: 0,
+    };
+    let mut module = parser.module();
+    let issues = std::mem::take(&mut module.issues);
+    Translator::new(&module).translate(issues)
+}
+
+/// Words that Polar reserves, which Rego names are suffixed with `_` to avoid.
+const POLAR_KEYWORDS: &[&str] = &[
+    "true", "false", "inf", "nan", "new", "in", "cut", "debug", "print", "isa", "forall", "if",
+    "and", "or", "not", "matches", "type", "where", "try", "else", "mod", "rem",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Ident(String),
+    Str(String),
+    Num(String),
+    Punct(&'static str),
+    Newline,
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    tok: Tok,
+    line: usize,
+}
+
+const PUNCTUATION: &[&str] = &[
+    ":=", "==", "!=", "<=", ">=", "{", "}", "[", "]", "(", ")", ".", ",", ";", ":", "=", "<", ">",
+    "+", "-", "*", "/", "%", "|", "&",
+];
+
+fn tokenize(src: &str) -> Result<Vec<Token>, RegoIssue> {
+    let chars = src.chars().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    let mut line = 1;
+    let mut i = 0;
+    let error = |line, message: String| RegoIssue {
+        line,
+        rule: None,
+        message,
+    };
+    while i < chars.len() {
+        let c = chars[i];
+        let (start, start_line) = (i, line);
+        let tok = match c {
+            '\n' => {
+                i += 1;
+                Tok::Newline
+            }
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                Tok::Ident(chars[start..i].iter().collect())
+            }
+            c if c.is_ascii_digit() => {
+                while i < chars.len()
+                    && (chars[i].is_ascii_digit()
+                        || chars[i] == '.' && chars.get(i + 1).is_some_and(char::is_ascii_digit))
+                {
+                    i += 1;
+                }
+                Tok::Num(chars[start..i].iter().collect())
+            }
+            '`' => {
+                i += 1;
+                while i < chars.len() && chars[i] != '`' {
+                    if chars[i] == '\n' {
+                        line += 1;
+                    }
+                    i += 1;
+                }
+                if i == chars.len() {
+                    return Err(error(line, "unterminated raw string".to_owned()));
+                }
+                i += 1;
+                Tok::Str(chars[start + 1..i - 1].iter().collect())
+            }
+            '"' => {
+                i += 1;
+                let mut s = String::new();
+                loop {
+                    match chars.get(i) {
+                        None | Some('\n') => {
+                            return Err(error(line, "unterminated string".to_owned()))
+                        }
+                        Some('"') => break,
+                        Some('\\') => {
+                            let escaped = match chars.get(i + 1) {
+                                Some('n') => '\n',
+                                Some('r') => '\r',
+                                Some('t') => '\t',
+                                Some('b') => '\u{8}',
+                                Some('f') => '\u{c}',
+                                Some('u') => chars
+                                    .get(i + 2..i + 6)
+                                    .map(|hex| hex.iter().collect::<String>())
+                                    .and_then(|hex| u32::from_str_radix(&hex, 16).ok())
+                                    .and_then(char::from_u32)
+                                    .ok_or_else(|| {
+                                        error(line, "invalid unicode escape".to_owned())
+                                    })?,
+                                Some(&c) => c,
+                                None => return Err(error(line, "unterminated string".to_owned())),
+                            };
+                            i += if chars[i + 1] == 'u' { 6 } else { 2 };
+                            s.push(escaped);
+                        }
+                        Some(&c) => {
+                            s.push(c);
+                            i += 1;
+                        }
+                    }
+                }
+                i += 1;
+                Tok::Str(s)
+            }
+            _ => {
+                let rest = chars[i..].iter().take(2).collect::<String>();
+                match PUNCTUATION.iter().find(|p| rest.starts_with(**p)) {
+                    Some(p) => {
+                        i += p.len();
+                        Tok::Punct(p)
+                    }
+                    None => return Err(error(line, format!("unexpected character `{}`", c))),
+                }
+            }
+        };
+        tokens.push(Token {
+            tok,
+            line: start_line,
+        });
+        if tokens.last().is_some_and(|t| t.tok == Tok::Newline) {
+            line += 1;
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Var(String),
+    Str(String),
+    Num(String),
+    Bool(bool),
+    Null,
+    Array(Vec<Expr>),
+    Object(Vec<(Expr, Expr)>),
+    Dot(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Neg(Box<Expr>),
+}
+
+#[derive(Clone, Debug)]
+enum Stmt {
+    Expr(Expr),
+    Not(Box<Stmt>),
+    /// `some x` declares `x`; `some x in coll` also iterates `coll`.
+    Some(Vec<String>, Option<Expr>),
+}
+
+#[derive(Clone, Debug)]
+enum Head {
+    Boolean,
+    Set(Expr),
+    Function(Vec<Expr>),
+}
+
+#[derive(Clone, Debug)]
+struct Rule {
+    line: usize,
+    name: String,
+    head: Head,
+    body: Vec<Stmt>,
+}
+
+#[derive(Debug, Default)]
+struct Module {
+    package: Vec<String>,
+    /// Names bound by imports, which aren't translated.
+    imports: HashSet<String>,
+    rules: Vec<Rule>,
+    issues: Vec<RegoIssue>,
+}
+
+/// A syntax error or an unsupported construct, at a line.
+type ParseResult<T> = Result<T, (usize, String)>;
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// How many brackets the parser is in, where newlines don't separate statements.
+    depth: usize,
+}
+
+impl Parser {
+    fn module(&mut self) -> Module {
+        let mut module = Module::default();
+        loop {
+            while self.eat_newline() || self.eat(";") {}
+            if self.pos >= self.tokens.len() {
+                return module;
+            }
+            let start = self.pos;
+            let name = match &self.tokens[start].tok {
+                Tok::Ident(name) => Some(name.clone()),
+                _ => None,
+            };
+            let result = match name.as_deref() {
+                Some("package") => self.package().map(|package| module.package = package),
+                Some("import") => self.import(&mut module),
+                Some("default") => self.default(&mut module),
+                _ => self.rule().map(|rule| module.rules.push(rule)),
+            };
+            if let Err((line, message)) = result {
+                let rule = name.filter(|name| !["package", "import", "default"].contains(&&**name));
+                module.issues.push(RegoIssue {
+                    line,
+                    rule,
+                    message,
+                });
+                self.recover(start);
+            }
+        }
+    }
+
+    /// Skip the statement starting at `start`, up to the end of its last line.
+    fn recover(&mut self, start: usize) {
+        self.pos = start;
+        self.depth = 0;
+        let mut balance = 0i64;
+        while let Some(token) = self.tokens.get(self.pos) {
+            self.pos += 1;
+            match token.tok {
+                Tok::Punct("{" | "[" | "(") => balance += 1,
+                Tok::Punct("}" | "]" | ")") => balance -= 1,
+                Tok::Newline if balance <= 0 => return,
+                _ => (),
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Option<&Tok> {
+        if self.depth > 0 {
+            while self.eat_newline() {}
+        }
+        self.tokens.get(self.pos).map(|t| &t.tok)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or_else(|| self.tokens.last())
+            .map_or(1, |t| t.line)
+    }
+
+    fn error<T>(&self, message: String) -> ParseResult<T> {
+        Err((self.line(), message))
+    }
+
+    fn eat_newline(&mut self) -> bool {
+        let newline = matches!(
+            self.tokens.get(self.pos),
+            Some(Token {
+                tok: Tok::Newline,
+                ..
+            })
+        );
+        if newline {
+            self.pos += 1;
+        }
+        newline
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        let found = matches!(self.peek(), Some(Tok::Punct(p)) if *p == punct);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_ident(&mut self, ident: &str) -> bool {
+        let found = matches!(self.peek(), Some(Tok::Ident(i)) if i == ident);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, punct: &str) -> ParseResult<()> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            self.error(format!("expected `{}`", punct))
+        }
+    }
+
+    fn ident(&mut self) -> ParseResult<String> {
+        match self.peek().cloned() {
+            Some(Tok::Ident(ident)) => {
+                self.pos += 1;
+                Ok(ident)
+            }
+            _ => self.error("expected a name".to_owned()),
+        }
+    }
+
+    /// Parse a dotted path, e.g., of a package.
+    fn path(&mut self) -> ParseResult<Vec<String>> {
+        let mut path = vec![self.ident()?];
+        while self.eat(".") {
+            path.push(self.ident()?);
+        }
+        Ok(path)
+    }
+
+    fn end_of_statement(&mut self) -> ParseResult<()> {
+        if self.pos >= self.tokens.len() || self.eat_newline() || self.eat(";") {
+            Ok(())
+        } else {
+            self.error("expected the end of the line".to_owned())
+        }
+    }
+
+    fn package(&mut self) -> ParseResult<Vec<String>> {
+        self.eat_ident("package");
+        let path = self.path()?;
+        self.end_of_statement()?;
+        Ok(path)
+    }
+
+    fn import(&mut self, module: &mut Module) -> ParseResult<()> {
+        let line = self.line();
+        self.eat_ident("import");
+        let path = self.path()?;
+        let alias = if self.eat_ident("as") {
+            Some(self.ident()?)
+        } else {
+            None
+        };
+        self.end_of_statement()?;
+        if path == ["rego", "v1"] || path.starts_with(&["future".to_owned(), "keywords".to_owned()])
+        {
+            return Ok(());
+        }
+        module
+            .imports
+            .insert(alias.unwrap_or_else(|| path.last().cloned().unwrap_or_default()));
+        module.issues.push(RegoIssue {
+            line,
+            rule: None,
+            message: format!("the import of `{}` isn't translated", path.join(".")),
+        });
+        Ok(())
+    }
+
+    fn default(&mut self, module: &mut Module) -> ParseResult<()> {
+        let line = self.line();
+        self.eat_ident("default");
+        let name = self.ident()?;
+        if !(self.eat(":=") || self.eat("=")) {
+            return self.error("expected `:=` or `=`".to_owned());
+        }
+        let value = self.expr()?;
+        self.end_of_statement()?;
+        // Polar rules fail without a result, like a default of false.
+        if !matches!(value, Expr::Bool(false)) {
+            module.issues.push(RegoIssue {
+                line,
+                rule: None,
+                message: format!(
+                    "the default value of `{}` isn't translated; only `false` is",
+                    name
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    fn rule(&mut self) -> ParseResult<Rule> {
+        let line = self.line();
+        let name = self.ident()?;
+        let head = if self.eat("(") {
+            self.depth += 1;
+            let args = self.items(")")?;
+            self.depth -= 1;
+            Head::Function(args)
+        } else if self.eat("[") {
+            self.depth += 1;
+            let key = self.expr()?;
+            self.expect("]")?;
+            self.depth -= 1;
+            Head::Set(key)
+        } else if self.eat_ident("contains") {
+            Head::Set(self.expr()?)
+        } else if matches!(self.peek(), Some(Tok::Punct("."))) {
+            return self.error("rules with dotted names aren't translated".to_owned());
+        } else {
+            Head::Boolean
+        };
+        if self.eat(":=") || self.eat("=") {
+            match (&head, self.expr()?) {
+                (Head::Set(_), _) => {
+                    return self.error("object rules aren't translated".to_owned());
+                }
+                (_, Expr::Bool(true)) => (),
+                _ => {
+                    return self
+                        .error("rules with values other than `true` aren't translated".to_owned());
+                }
+            }
+        }
+
+        let body = if self.eat_ident("if") && !matches!(self.peek(), Some(Tok::Punct("{"))) {
+            vec![self.stmt()?]
+        } else if matches!(self.peek(), Some(Tok::Punct("{"))) {
+            self.block()?
+        } else {
+            vec![]
+        };
+        if self.eat_ident("else") {
+            return self.error("`else` isn't translated".to_owned());
+        }
+        if matches!(self.peek(), Some(Tok::Punct("{"))) {
+            return self.error("rules with more than one body aren't translated".to_owned());
+        }
+        self.end_of_statement()?;
+        Ok(Rule {
+            line,
+            name,
+            head,
+            body,
+        })
+    }
+
+    fn block(&mut self) -> ParseResult<Vec<Stmt>> {
+        self.expect("{")?;
+        let mut body = vec![];
+        loop {
+            while self.eat_newline() || self.eat(";") {}
+            if self.eat("}") {
+                return Ok(body);
+            }
+            body.push(self.stmt()?);
+            if !(self.eat_newline()
+                || self.eat(";")
+                || matches!(self.peek(), Some(Tok::Punct("}"))))
+            {
+                if self.eat_ident("with") {
+                    return self.error("`with` isn't translated".to_owned());
+                }
+                return self.error("expected the end of the line".to_owned());
+            }
+        }
+    }
+
+    fn stmt(&mut self) -> ParseResult<Stmt> {
+        if self.eat_ident("some") {
+            let mut vars = vec![self.ident()?];
+            while self.eat(",") {
+                vars.push(self.ident()?);
+            }
+            let collection = if self.eat_ident("in") {
+                Some(self.expr()?)
+            } else {
+                None
+            };
+            Ok(Stmt::Some(vars, collection))
+        } else if self.eat_ident("not") {
+            Ok(Stmt::Not(Box::new(self.stmt()?)))
+        } else if self.eat_ident("every") {
+            self.error("`every` isn't translated".to_owned())
+        } else {
+            Ok(Stmt::Expr(self.assignment()?))
+        }
+    }
+
+    fn assignment(&mut self) -> ParseResult<Expr> {
+        let left = self.expr()?;
+        for op in [":=", "="] {
+            if self.eat(op) {
+                return Ok(Expr::Binary(op, Box::new(left), Box::new(self.expr()?)));
+            }
+        }
+        Ok(left)
+    }
+
+    /// Parse an expression, not including assignments.
+    fn expr(&mut self) -> ParseResult<Expr> {
+        let mut left = self.comparison()?;
+        while self.eat_ident("in") {
+            left = Expr::Binary("in", Box::new(left), Box::new(self.comparison()?));
+        }
+        Ok(left)
+    }
+
+    fn comparison(&mut self) -> ParseResult<Expr> {
+        let left = self.set_operation()?;
+        for op in ["==", "!=", "<=", ">=", "<", ">"] {
+            if self.eat(op) {
+                return Ok(Expr::Binary(
+                    op,
+                    Box::new(left),
+                    Box::new(self.set_operation()?),
+                ));
+            }
+        }
+        Ok(left)
+    }
+
+    fn set_operation(&mut self) -> ParseResult<Expr> {
+        let left = self.sum()?;
+        if matches!(self.peek(), Some(Tok::Punct("|" | "&"))) {
+            return self.error("set operations aren't translated".to_owned());
+        }
+        Ok(left)
+    }
+
+    fn sum(&mut self) -> ParseResult<Expr> {
+        let mut left = self.product()?;
+        loop {
+            let op = if self.eat("+") {
+                "+"
+            } else if self.eat("-") {
+                "-"
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.product()?));
+        }
+    }
+
+    fn product(&mut self) -> ParseResult<Expr> {
+        let mut left = self.unary()?;
+        loop {
+            let op = if self.eat("*") {
+                "*"
+            } else if self.eat("/") {
+                "/"
+            } else if self.eat("%") {
+                "%"
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> ParseResult<Expr> {
+        if self.eat("-") {
+            Ok(Expr::Neg(Box::new(self.unary()?)))
+        } else {
+            self.postfix()
+        }
+    }
+
+    fn postfix(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.primary()?;
+        loop {
+            // Lookups and calls continue on the same line only.
+            let next = self.tokens.get(self.pos).map(|t| &t.tok);
+            if next == Some(&Tok::Punct(".")) {
+                self.pos += 1;
+                expr = Expr::Dot(Box::new(expr), self.ident()?);
+            } else if next == Some(&Tok::Punct("[")) {
+                self.pos += 1;
+                self.depth += 1;
+                let index = self.expr()?;
+                self.expect("]")?;
+                self.depth -= 1;
+                expr = Expr::Index(Box::new(expr), Box::new(index));
+            } else if next == Some(&Tok::Punct("(")) {
+                let name = match path_name(&expr) {
+                    Some(name) => name,
+                    None => return self.error("expected a function name".to_owned()),
+                };
+                self.pos += 1;
+                self.depth += 1;
+                let args = self.items(")")?;
+                self.depth -= 1;
+                expr = Expr::Call(name, args);
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    /// Parse comma-separated expressions up to `close`.
+    fn items(&mut self, close: &str) -> ParseResult<Vec<Expr>> {
+        let mut items = vec![];
+        while !self.eat(close) {
+            items.push(self.expr()?);
+            if self.eat("|") {
+                return self.error("comprehensions aren't translated".to_owned());
+            }
+            if !self.eat(",") {
+                self.expect(close)?;
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    fn primary(&mut self) -> ParseResult<Expr> {
+        let tok = match self.peek().cloned() {
+            Some(tok) => tok,
+            None => return self.error("unexpected end of the policy".to_owned()),
+        };
+        self.pos += 1;
+        match tok {
+            Tok::Ident(ident) => Ok(match ident.as_str() {
+                "true" => Expr::Bool(true),
+                "false" => Expr::Bool(false),
+                "null" => Expr::Null,
+                _ => Expr::Var(ident),
+            }),
+            Tok::Str(s) => Ok(Expr::Str(s)),
+            Tok::Num(n) => Ok(Expr::Num(n)),
+            Tok::Punct("(") => {
+                self.depth += 1;
+                let expr = self.expr()?;
+                self.expect(")")?;
+                self.depth -= 1;
+                Ok(expr)
+            }
+            Tok::Punct("[") => {
+                self.depth += 1;
+                let items = self.items("]")?;
+                self.depth -= 1;
+                Ok(Expr::Array(items))
+            }
+            Tok::Punct("{") => {
+                self.depth += 1;
+                let mut fields = vec![];
+                while !self.eat("}") {
+                    let key = self.expr()?;
+                    if self.eat("|") {
+                        return self.error("comprehensions aren't translated".to_owned());
+                    }
+                    if !self.eat(":") {
+                        return self.error("sets aren't translated".to_owned());
+                    }
+                    fields.push((key, self.expr()?));
+                    if !self.eat(",") {
+                        self.expect("}")?;
+                        break;
+                    }
+                }
+                self.depth -= 1;
+                Ok(Expr::Object(fields))
+            }
+            tok => {
+                self.pos -= 1;
+                self.error(format!("unexpected {}", describe(&tok)))
+            }
+        }
+    }
+}
+
+fn describe(tok: &Tok) -> String {
+    match tok {
+        Tok::Ident(i) => format!("`{}`", i),
+        Tok::Str(s) => format!("string {:?}", s),
+        Tok::Num(n) => format!("number {}", n),
+        Tok::Punct(p) => format!("`{}`", p),
+        Tok::Newline => "end of the line".to_owned(),
+    }
+}
+
+/// The dotted name of `expr` if it's a path of names, e.g., `time.now_ns`.
+fn path_name(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Var(name) => Some(name.clone()),
+        Expr::Dot(base, field) => path_name(base).map(|base| format!("{}.{}", base, field)),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RuleKind {
+    Boolean,
+    Set,
+    Function,
+}
+
+struct Translator<'a> {
+    rules: &'a [Rule],
+    package: &'a [String],
+    imports: &'a HashSet<String>,
+    kinds: HashMap<&'a str, RuleKind>,
+}
+
+/// The state of the translation of one rule.
+#[derive(Default)]
+struct Body {
+    /// Variables bound so far.
+    bound: HashSet<String>,
+    uses_input: bool,
+    /// The rules called.
+    deps: HashSet<String>,
+    fresh: usize,
+}
+
+impl<'a> Translator<'a> {
+    fn new(module: &'a Module) -> Self {
+        let kinds = module
+            .rules
+            .iter()
+            .map(|rule| {
+                let kind = match rule.head {
+                    Head::Boolean => RuleKind::Boolean,
+                    Head::Set(_) => RuleKind::Set,
+                    Head::Function(_) => RuleKind::Function,
+                };
+                (rule.name.as_str(), kind)
+            })
+            .collect();
+        Self {
+            rules: &module.rules,
+            package: &module.package,
+            imports: &module.imports,
+            kinds,
+        }
+    }
+
+    fn translate(&self, mut issues: Vec<RegoIssue>) -> RegoTranslation {
+        let mut failed = issues
+            .iter()
+            .filter_map(|issue| issue.rule.clone())
+            .collect::<HashSet<_>>();
+        let mut translated = vec![];
+        for rule in self.rules {
+            match self.rule(rule) {
+                Ok((polar, deps)) => translated.push((rule, polar, deps)),
+                Err(message) => {
+                    failed.insert(rule.name.clone());
+                    issues.push(RegoIssue {
+                        line: rule.line,
+                        rule: Some(rule.name.clone()),
+                        message,
+                    });
+                }
+            }
+        }
+
+        // Leave out every definition of a rule that's partly left out, and the rules that
+        // depend on it, until no more are.
+        loop {
+            let before = failed.len();
+            for (rule, _, deps) in &translated {
+                if failed.contains(&rule.name) {
+                    continue;
+                }
+                let mut missing = deps
+                    .iter()
+                    .filter(|dep| failed.contains(*dep))
+                    .collect::<Vec<_>>();
+                missing.sort();
+                if let Some(dep) = missing.first() {
+                    failed.insert(rule.name.clone());
+                    issues.push(RegoIssue {
+                        line: rule.line,
+                        rule: Some(rule.name.clone()),
+                        message: format!("it depends on the rule `{}`, which is left out", dep),
+                    });
+                }
+            }
+            if failed.len() == before {
+                break;
+            }
+        }
+        issues.sort_by_key(|issue| issue.line);
+
+        let polar = translated
+            .into_iter()
+            .filter(|(rule, _, _)| !failed.contains(&rule.name))
+            .map(|(_, polar, _)| polar + "\n")
+            .collect::<Vec<_>>()
+            .join("\n");
+        RegoTranslation { polar, issues }
+    }
+
+    /// Translate a rule to Polar, returning it and the rules it calls.
+    fn rule(&self, rule: &Rule) -> Result<(String, HashSet<String>), String> {
+        let mut body = Body::default();
+        let mut args = vec![];
+        match &rule.head {
+            Head::Boolean | Head::Set(_) => (),
+            Head::Function(params) => {
+                for param in params {
+                    bind(&mut body, param);
+                    args.push(self.value(param, &mut body, &mut vec![])?);
+                }
+            }
+        }
+        let mut conjuncts = vec![];
+        for stmt in &rule.body {
+            self.stmt(stmt, &mut body, &mut conjuncts)?;
+        }
+        if let Head::Set(key) = &rule.head {
+            let mut prelude = vec![];
+            args.push(self.value(key, &mut body, &mut prelude)?);
+            if !prelude.is_empty() {
+                return Err("iterating in the key of a set rule isn't translated".to_owned());
+            }
+        }
+
+        let input = if body.uses_input { "input" } else { "_input" };
+        let head = format!(
+            "{}({})",
+            self.rule_name(&rule.name),
+            std::iter::once(input.to_owned())
+                .chain(args)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let polar = if conjuncts.is_empty() {
+            format!("{};", head)
+        } else {
+            format!("{} if\n    {};", head, conjuncts.join(" and\n    "))
+        };
+        Ok((polar, body.deps))
+    }
+
+    /// Translate a statement to the conjuncts it adds to `out`.
+    fn stmt(&self, stmt: &Stmt, body: &mut Body, out: &mut Vec<String>) -> Result<(), String> {
+        match stmt {
+            Stmt::Some(_, None) => Ok(()),
+            Stmt::Some(vars, Some(collection)) => {
+                if vars.len() != 1 {
+                    return Err("iterating keys or indices with `some` isn't translated".to_owned());
+                }
+                let item = Expr::Var(vars[0].clone());
+                self.stmt(
+                    &Stmt::Expr(Expr::Binary(
+                        "in",
+                        Box::new(item),
+                        Box::new(collection.clone()),
+                    )),
+                    body,
+                    out,
+                )
+            }
+            Stmt::Not(stmt) => {
+                // Bindings don't escape negation.
+                let bound = body.bound.clone();
+                let mut negated = vec![];
+                self.stmt(stmt, body, &mut negated)?;
+                body.bound = bound;
+                out.push(format!("not ({})", negated.join(" and ")));
+                Ok(())
+            }
+            Stmt::Expr(expr) => {
+                let conjunct = self.check(expr, body, out)?;
+                out.push(conjunct);
+                Ok(())
+            }
+        }
+    }
+
+    /// Translate an expression that's checked, e.g., a comparison or a call of a rule.
+    fn check(&self, expr: &Expr, body: &mut Body, out: &mut Vec<String>) -> Result<String, String> {
+        match expr {
+            Expr::Binary(
+                op @ (":=" | "=" | "==" | "!=" | "<" | "<=" | ">" | ">="),
+                left,
+                right,
+            ) => {
+                let (left, right) = if matches!(*op, ":=" | "=") {
+                    // Only the right side of an assignment may be bound before it.
+                    let right = self.value(right, body, out)?;
+                    bind(body, left);
+                    (self.value(left, body, out)?, right)
+                } else {
+                    (self.value(left, body, out)?, self.value(right, body, out)?)
+                };
+                let op = if *op == ":=" { "=" } else { op };
+                Ok(format!("{} {} {}", left, op, right))
+            }
+            Expr::Binary("in", item, collection) => {
+                if let Some((name, RuleKind::Set)) = self.rule_ref(collection) {
+                    return self.call(&name, &[item], body, out);
+                }
+                let collection = self.value(collection, body, out)?;
+                bind(body, item);
+                Ok(format!(
+                    "{} in {}",
+                    self.value(item, body, out)?,
+                    collection
+                ))
+            }
+            Expr::Index(base, key) if matches!(self.rule_ref(base), Some((_, RuleKind::Set))) => {
+                let (name, _) = self.rule_ref(base).expect("the base is a set rule");
+                self.call(&name, &[key], body, out)
+            }
+            Expr::Call(name, args)
+                if self.kinds.get(name.as_str()) == Some(&RuleKind::Function) =>
+            {
+                self.call(name, &args.iter().collect::<Vec<_>>(), body, out)
+            }
+            expr => match self.rule_ref(expr) {
+                Some((name, RuleKind::Boolean)) => self.call(&name, &[], body, out),
+                Some((name, _)) => Err(format!(
+                    "checking the rule `{}` without arguments isn't translated",
+                    name
+                )),
+                // Rego checks that other expressions are defined and not false.
+                None => Ok(format!("{} != false", self.value(expr, body, out)?)),
+            },
+        }
+    }
+
+    fn call(
+        &self,
+        name: &str,
+        args: &[&Expr],
+        body: &mut Body,
+        out: &mut Vec<String>,
+    ) -> Result<String, String> {
+        body.uses_input = true;
+        body.deps.insert(name.to_owned());
+        let mut polar_args = vec!["input".to_owned()];
+        for arg in args {
+            bind(body, arg);
+            polar_args.push(self.value(arg, body, out)?);
+        }
+        Ok(format!(
+            "{}({})",
+            self.rule_name(name),
+            polar_args.join(", ")
+        ))
+    }
+
+    /// The Polar name of a rule: its name in Rego, prefixed with its package, like its path in
+    /// `data`, which also keeps it apart from Polar's rule types, e.g., `allow`.
+    fn rule_name(&self, name: &str) -> String {
+        polar_name(
+            &self
+                .package
+                .iter()
+                .map(String::as_str)
+                .chain(std::iter::once(name))
+                .collect::<Vec<_>>()
+                .join("_"),
+        )
+    }
+
+    /// The rule that `expr` refers to, by its name or by its path in `data`.
+    fn rule_ref(&self, expr: &Expr) -> Option<(String, RuleKind)> {
+        let path = path_name(expr)?;
+        let name = match path.strip_prefix("data.") {
+            Some(rest) => {
+                let package = self.package.join(".") + ".";
+                rest.strip_prefix(&package)?.to_owned()
+            }
+            None => path,
+        };
+        let kind = *self.kinds.get(name.as_str())?;
+        Some((name, kind))
+    }
+
+    /// Translate an expression that's used as a value, adding the conjuncts that bind the
+    /// elements it iterates to `out`.
+    fn value(&self, expr: &Expr, body: &mut Body, out: &mut Vec<String>) -> Result<String, String> {
+        if let Some((name, _)) = self.rule_ref(expr) {
+            return Err(format!(
+                "using the rule `{}` as a value isn't translated",
+                name
+            ));
+        }
+        match expr {
+            Expr::Var(name) if self.imports.contains(name) => {
+                Err(format!("`{}` refers to an import", name))
+            }
+            Expr::Var(name) if name == "input" => {
+                body.uses_input = true;
+                Ok(name.clone())
+            }
+            Expr::Var(name) => Ok(polar_name(name)),
+            Expr::Str(s) => Ok(polar_string(s)),
+            Expr::Num(n) => Ok(n.clone()),
+            Expr::Bool(b) => Ok(b.to_string()),
+            Expr::Null => Err("`null` isn't translated".to_owned()),
+            Expr::Array(items) => Ok(format!(
+                "[{}]",
+                items
+                    .iter()
+                    .map(|item| self.value(item, body, out))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .join(", ")
+            )),
+            Expr::Object(fields) => {
+                let mut polar_fields = vec![];
+                for (key, value) in fields {
+                    match key {
+                        Expr::Str(key) if is_identifier(key) => {
+                            polar_fields.push(format!("{}: {}", key, self.value(value, body, out)?))
+                        }
+                        _ => {
+                            return Err("object keys other than names aren't translated".to_owned())
+                        }
+                    }
+                }
+                Ok(format!("{{{}}}", polar_fields.join(", ")))
+            }
+            Expr::Dot(base, field) => Ok(format!(
+                "{}{}",
+                self.value(base, body, out)?,
+                polar_field(field)
+            )),
+            Expr::Index(base, key) => {
+                let base = self.value(base, body, out)?;
+                match &**key {
+                    Expr::Str(key) => Ok(format!("{}{}", base, polar_field(key))),
+                    Expr::Var(var) if var == "_" => {
+                        body.fresh += 1;
+                        let item = format!("_item_{}", body.fresh);
+                        out.push(format!("{} in {}", item, base));
+                        Ok(item)
+                    }
+                    Expr::Var(var) if body.bound.contains(var) => {
+                        Ok(format!("{}.({})", base, polar_name(var)))
+                    }
+                    Expr::Var(var) => Err(format!(
+                        "iterating the keys or indices of `{}` with `{}` isn't translated",
+                        base, var
+                    )),
+                    Expr::Num(_) => Err("indexing arrays by position isn't translated".to_owned()),
+                    _ => Err("indexing by an expression isn't translated".to_owned()),
+                }
+            }
+            Expr::Call(name, _) if self.kinds.contains_key(name.as_str()) => Err(format!(
+                "using the result of the function `{}` isn't translated",
+                name
+            )),
+            Expr::Call(name, _) => {
+                Err(format!("the built-in function `{}` isn't translated", name))
+            }
+            Expr::Binary(op @ ("+" | "-" | "*" | "/" | "%"), left, right) => Ok(format!(
+                "({} {} {})",
+                self.value(left, body, out)?,
+                if *op == "%" { "rem" } else { op },
+                self.value(right, body, out)?
+            )),
+            Expr::Binary(..) => {
+                Err("using the result of a comparison as a value isn't translated".to_owned())
+            }
+            Expr::Neg(expr) => match &**expr {
+                Expr::Num(n) => Ok(format!("-{}", n)),
+                expr => Ok(format!("(0 - {})", self.value(expr, body, out)?)),
+            },
+        }
+    }
+}
+
+/// Mark the variables of `expr` as bound.
+fn bind(body: &mut Body, expr: &Expr) {
+    match expr {
+        Expr::Var(name) => {
+            body.bound.insert(name.clone());
+        }
+        Expr::Array(items) => items.iter().for_each(|item| bind(body, item)),
+        Expr::Object(fields) => fields.iter().for_each(|(_, value)| bind(body, value)),
+        _ => (),
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn polar_name(name: &str) -> String {
+    if POLAR_KEYWORDS.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_owned()
+    }
+}
+
+fn polar_field(field: &str) -> String {
+    if is_identifier(field) && !POLAR_KEYWORDS.contains(&field) {
+        format!(".{}", field)
+    } else {
+        format!(".({})", polar_string(field))
+    }
+}
+
+fn polar_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_translate() {
+        let translation = translate(
+            r#"
+            package app.rbac
+
+            import rego.v1
+
+            default allow := false
+
+            # Admins may do anything.
+            allow if is_admin
+
+            allow if {
+                some grant in user_grants
+                input.action == grant.action
+                not input.resource["read-only"]
+            }
+
+            is_admin if data.admins[_] == input.user
+
+            user_grants contains grant if {
+                some role in input.roles
+                grant := data.grants[role][_]
+            }
+
+            owner(resource) if resource.owner == input.user
+            "#,
+        );
+        assert_eq!(translation.issues, vec![]);
+        assert_eq!(
+            translation.polar,
+            r#"app_rbac_allow(input) if
+    app_rbac_is_admin(input);
+
+app_rbac_allow(input) if
+    app_rbac_user_grants(input, grant) and
+    input.action == grant.action and
+    not (input.resource.("read-only") != false);
+
+app_rbac_is_admin(input) if
+    _item_1 in data.admins and
+    _item_1 == input.user;
+
+app_rbac_user_grants(input, grant) if
+    role in input.roles and
+    _item_1 in data.grants.(role) and
+    grant = _item_1;
+
+app_rbac_owner(input, resource) if
+    resource.owner == input.user;
+"#
+        );
+    }
+
+    #[test]
+    fn test_translate_issues() {
+        let translation = translate(
+            r#"
+            package authz
+
+            import data.roles as r
+
+            default allow := true
+
+            allow {
+                count(input.groups) > 0
+            }
+
+            allow {
+                is_member
+            }
+
+            allow {
+                input.public == true
+            }
+
+            is_member {
+                input.group == r.members[_]
+            }
+
+            deny[msg] {
+                msg := sprintf("denied: %v", [input.user])
+            }
+
+            limit = 10
+            "#,
+        );
+        let issues = translation
+            .issues
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            issues,
+            vec![
+                "line 4: the import of `data.roles` isn't translated",
+                "line 6: the default value of `allow` isn't translated; only `false` is",
+                "line 8: the built-in function `count` isn't translated (left out rule `allow`)",
+                "line 20: `r` refers to an import (left out rule `is_member`)",
+                "line 24: the built-in function `sprintf` isn't translated (left out rule `deny`)",
+                "line 28: rules with values other than `true` aren't translated (left out rule `limit`)",
+            ]
+        );
+        // Every definition of `allow` is left out, including the one without issues.
+        assert_eq!(translation.polar, "");
+
+        let translation = translate(
+            "package p
+
+allow { input.x[0] == 1 }
+ok { allow }
+",
+        );
+        let issues = translation
+            .issues
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            issues,
+            vec![
+                "line 3: indexing arrays by position isn't translated (left out rule `allow`)",
+                "line 4: it depends on the rule `allow`, which is left out (left out rule `ok`)",
+            ]
+        );
+    }
+}
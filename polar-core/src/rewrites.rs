@@ -1,5 +1,7 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+use super::constant_folding::fold_constants;
+use super::error::{PolarResult, RuntimeError};
 use super::folder::*;
 use super::kb::*;
 use super::rules::*;
@@ -27,7 +29,7 @@ impl<'kb> Folder for Renamer<'kb> {
         } else if let Some(w) = self.renames.get(&v) {
             w.clone()
         } else {
-            let w = self.kb.gensym(&v.0);
+            let w = self.kb.gensym(&v);
             self.renames.insert(v, w.clone());
             w
         }
@@ -37,7 +39,7 @@ impl<'kb> Folder for Renamer<'kb> {
         if let Some(s) = self.renames.get(&r) {
             s.clone()
         } else {
-            let s = self.kb.gensym(&r.0);
+            let s = self.kb.gensym(&r);
             self.renames.insert(r, s.clone());
             s
         }
@@ -60,6 +62,7 @@ impl<'kb> Rewriter<'kb> {
         match o.operator {
             Operator::Add
             | Operator::Dot
+            | Operator::SafeDot
             | Operator::Div
             | Operator::Mul
             | Operator::Sub
@@ -78,7 +81,7 @@ impl<'kb> Rewriter<'kb> {
 fn temp_name(o: &Operator) -> &'static str {
     match o {
         Operator::Add | Operator::Div | Operator::Mul | Operator::Sub => "op",
-        Operator::Dot => "value",
+        Operator::Dot | Operator::SafeDot => "value",
         Operator::New => "instance",
         _ => "temp",
     }
@@ -97,8 +100,26 @@ impl<'kb> Folder for Rewriter<'kb> {
             params,
             source_info,
             required,
+            metadata,
         }: Rule,
     ) -> Rule {
+        // Parameter guards are checked first, in the order of the parameters.
+        let (params, guards): (Vec<_>, Vec<_>) = params
+            .into_iter()
+            .map(|mut p| {
+                let guard = p.guard.take();
+                (p, guard)
+            })
+            .unzip();
+        let guards = guards.into_iter().flatten().collect::<TermList>();
+        let body = if guards.is_empty() {
+            body
+        } else {
+            body.clone_with_value(Value::Expression(Operation {
+                operator: Operator::And,
+                args: guards.into_iter().chain(unwrap_and(&body)).collect(),
+            }))
+        };
         let mut body = self.fold_term(body);
 
         self.stack.push(vec![]);
@@ -117,6 +138,7 @@ impl<'kb> Folder for Rewriter<'kb> {
             body,
             source_info,
             required,
+            metadata,
         }
     }
 
@@ -127,15 +149,16 @@ impl<'kb> Folder for Rewriter<'kb> {
             _ if self.stack.is_empty() => {
                 // If there is no containing conjunction, make one.
                 self.stack.push(vec![]);
-                let new = self.fold_term(t);
+                let new = self.fold_term(t.clone());
                 let rewrites = self.stack.pop().unwrap();
-                rewrites.into_iter().rfold(new, and_op_)
+                t.clone_with_value(rewrites.into_iter().rfold(new, and_op_).value().clone())
             }
             Value::Expression(o) if self.needs_rewrite(o) => {
                 // Rewrite sub-expressions, then push a temp onto the args.
                 let mut new = fold_operation(o.clone(), self);
+                // The temp stands for the expression, so it takes the expression's source.
                 let temp = Value::Variable(self.kb.gensym(temp_name(&o.operator)));
-                new.args.push(Term::from(temp.clone()));
+                new.args.push(t.clone_with_value(temp.clone()));
 
                 // Push the rewritten expression into the top stack frame.
                 self.stack
@@ -153,16 +176,16 @@ impl<'kb> Folder for Rewriter<'kb> {
     fn fold_operation(&mut self, o: Operation) -> Operation {
         use Operator::*;
         match o.operator {
-            And | Or | Not => Operation {
+            And | Or | Not | Try => Operation {
                 operator: fold_operator(o.operator, self),
                 args: o
                     .args
                     .into_iter()
-                    .map(|arg| {
-                        let arg_operator = arg.as_expression().map(|e| e.operator).ok();
+                    .map(|original| {
+                        let arg_operator = original.as_expression().map(|e| e.operator).ok();
 
                         self.stack.push(vec![]);
-                        let arg = self.fold_term(arg);
+                        let arg = self.fold_term(original.clone());
                         let rewrites = self.stack.pop().unwrap();
                         // Decide whether to prepend, or append
 
@@ -181,11 +204,14 @@ impl<'kb> Folder for Rewriter<'kb> {
                         //
                         // We prepend when the rewritten variable needs to be bound before it is
                         // used.
-                        if only_pure(&rewrites) && arg_operator == Some(Operator::Unify) {
+                        let arg = if only_pure(&rewrites) && arg_operator == Some(Operator::Unify) {
                             rewrites.into_iter().fold(arg, and_)
                         } else {
                             rewrites.into_iter().rfold(arg, and_op_)
-                        }
+                        };
+                        // The conjunction of the argument and its rewrites stands for the
+                        // argument as written.
+                        original.clone_with_value(arg.value().clone())
                     })
                     .collect(),
             },
@@ -213,8 +239,23 @@ impl<'kb> Folder for Rewriter<'kb> {
         }
     }
 
+    // Temporary variables from inside a lambda stay in its body.
+    fn fold_lambda(&mut self, Lambda { params, body }: Lambda) -> Lambda {
+        let params = self.fold_list(params);
+        let operator = body.as_expression().map(|e| e.operator).ok();
+        self.stack.push(vec![]);
+        let body = self.fold_term(body);
+        let rewrites = self.stack.pop().unwrap();
+        let body = if only_pure(&rewrites) && operator == Some(Operator::Unify) {
+            rewrites.into_iter().fold(body, and_)
+        } else {
+            rewrites.into_iter().rfold(body, and_op_)
+        };
+        Lambda { params, body }
+    }
+
     fn fold_rest_variable(&mut self, v: Symbol) -> Symbol {
-        if v.0 == "_" {
+        if v == "_" {
             self.kb.gensym("_")
         } else {
             v
@@ -222,7 +263,7 @@ impl<'kb> Folder for Rewriter<'kb> {
     }
 
     fn fold_variable(&mut self, v: Symbol) -> Symbol {
-        if v.0 == "_" {
+        if v == "_" {
             self.kb.gensym("_")
         } else {
             v
@@ -234,7 +275,7 @@ fn only_pure(rewrites: &[Term]) -> bool {
     use Operator::*;
     rewrites.iter().all(|t| {
         t.as_expression().map_or(false, |op| {
-            matches!(op.operator, Dot | Add | Sub | Mul | Div | Rem)
+            matches!(op.operator, Dot | SafeDot | Add | Sub | Mul | Div | Rem)
         })
     })
 }
@@ -263,9 +304,65 @@ pub fn unwrap_and(term: &Term) -> TermList {
 }
 
 /// Rewrite a term.
+/// Replace each query parameter placeholder in `term`, like `:actor`, with the parameter of the
+/// same name. Every placeholder must have a parameter, and every parameter a placeholder.
+pub fn fill_placeholders(term: Term, params: HashMap<String, Term>) -> PolarResult<Term> {
+    struct Filler {
+        params: HashMap<String, Term>,
+        used: HashSet<String>,
+        missing: Option<String>,
+    }
+
+    impl Folder for Filler {
+        fn fold_term(&mut self, t: Term) -> Term {
+            match t.value() {
+                Value::Variable(name) if name.starts_with(':') => {
+                    let name = &name[1..];
+                    match self.params.get(name) {
+                        Some(param) => {
+                            self.used.insert(name.to_owned());
+                            // Point at the placeholder the parameter was written as.
+                            t.clone_with_value(param.value().clone())
+                        }
+                        None => {
+                            self.missing.get_or_insert_with(|| name.to_owned());
+                            t
+                        }
+                    }
+                }
+                _ => fold_term(t, self),
+            }
+        }
+    }
+
+    let mut filler = Filler {
+        params,
+        used: HashSet::new(),
+        missing: None,
+    };
+    let term = filler.fold_term(term);
+    if let Some(name) = filler.missing {
+        let msg = format!("No parameter for the placeholder :{}", name);
+        return Err(RuntimeError::QueryParameter { name, msg }.into());
+    }
+    let mut unused = filler
+        .params
+        .into_keys()
+        .filter(|name| !filler.used.contains(name))
+        .collect::<Vec<_>>();
+    unused.sort();
+    match unused.into_iter().next() {
+        Some(name) => {
+            let msg = format!("No placeholder for the parameter {}", name);
+            Err(RuntimeError::QueryParameter { name, msg }.into())
+        }
+        None => Ok(term),
+    }
+}
+
 pub fn rewrite_term(term: Term, kb: &KnowledgeBase) -> Term {
     let mut fld = Rewriter::new(kb);
-    fld.fold_term(term)
+    kb.expand_term_type_aliases(fld.fold_term(term))
 }
 
 /// Rewrite a rule.
@@ -274,6 +371,202 @@ pub fn rewrite_rule(rule: Rule, kb: &KnowledgeBase) -> Rule {
     fld.fold_rule(rule)
 }
 
+/// A type alias, `type Name = A | B;`. In specializers and `matches`, an alias stands for any
+/// of its members, which may be classes or other aliases.
+#[derive(Clone, Debug)]
+pub struct TypeAlias {
+    /// The name of the alias, as declared.
+    pub name: Term,
+    pub members: Vec<Symbol>,
+}
+
+/// Expand the aliases in `matches` operations, given the classes each alias stands for.
+struct AliasExpander<'a> {
+    classes: &'a HashMap<Symbol, Vec<Symbol>>,
+    expanded: bool,
+}
+
+impl<'a> AliasExpander<'a> {
+    /// The patterns for the classes an alias stands for, if `pattern` is an alias.
+    fn expand(&self, pattern: &Term) -> Option<Vec<Term>> {
+        match pattern.value() {
+            Value::Pattern(Pattern::Instance(InstanceLiteral { tag, fields })) => {
+                let classes = self.classes.get(tag)?;
+                let patterns = classes
+                    .iter()
+                    .map(|class| {
+                        pattern.clone_with_value(Value::Pattern(Pattern::Instance(
+                            InstanceLiteral {
+                                tag: class.clone(),
+                                fields: fields.clone(),
+                            },
+                        )))
+                    })
+                    .collect();
+                Some(patterns)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<'a> Folder for AliasExpander<'a> {
+    fn fold_term(&mut self, t: Term) -> Term {
+        let t = fold_term(t, self);
+        let alternatives = match t.value() {
+            Value::Expression(Operation {
+                operator: Operator::Isa,
+                args,
+            }) if args.len() == 2 => match self.expand(&args[1]) {
+                Some(patterns) => patterns
+                    .into_iter()
+                    .map(|pattern| {
+                        t.clone_with_value(Value::Expression(Operation {
+                            operator: Operator::Isa,
+                            args: vec![args[0].clone(), pattern],
+                        }))
+                    })
+                    .collect(),
+                None => return t,
+            },
+            _ => return t,
+        };
+        self.expanded = true;
+        t.clone_with_value(Value::Expression(Operation {
+            operator: Operator::Or,
+            args: alternatives,
+        }))
+    }
+}
+
+/// Expand the type aliases in `term`, given the classes each alias stands for: `x matches A`,
+/// where `A` is an alias, becomes a disjunction of `x matches` each class.
+pub fn expand_type_aliases(term: Term, classes: &HashMap<Symbol, Vec<Symbol>>) -> Term {
+    let mut expander = AliasExpander {
+        classes,
+        expanded: false,
+    };
+    expander.fold_term(term)
+}
+
+/// The rules that `rule` stands for with its type aliases expanded, or `None` if it uses none:
+/// one rule for each combination of the classes of the aliases that specialize its parameters,
+/// with `matches` expanded in its body and guards.
+pub fn expand_rule_type_aliases(
+    rule: &Rule,
+    classes: &HashMap<Symbol, Vec<Symbol>>,
+) -> Option<Vec<Rule>> {
+    let mut expander = AliasExpander {
+        classes,
+        expanded: false,
+    };
+    let body = expander.fold_term(rule.body.clone());
+    let mut heads: Vec<Vec<Parameter>> = vec![vec![]];
+    for param in &rule.params {
+        let guard = param.guard.clone().map(|guard| expander.fold_term(guard));
+        let specializers = match &param.specializer {
+            Some(specializer) => match expander.expand(specializer) {
+                Some(patterns) => {
+                    expander.expanded = true;
+                    patterns.into_iter().map(Some).collect()
+                }
+                None => vec![Some(specializer.clone())],
+            },
+            None => vec![None],
+        };
+        let mut expanded_heads = vec![];
+        for head in &heads {
+            for specializer in &specializers {
+                let mut head = head.clone();
+                head.push(Parameter {
+                    parameter: param.parameter.clone(),
+                    specializer: specializer.clone(),
+                    guard: guard.clone(),
+                });
+                expanded_heads.push(head);
+            }
+        }
+        heads = expanded_heads;
+    }
+    if !expander.expanded {
+        return None;
+    }
+    let rules = heads
+        .into_iter()
+        .map(|params| Rule {
+            params,
+            body: body.clone(),
+            ..rule.clone()
+        })
+        .collect();
+    Some(rules)
+}
+
+/// A transform of the rules in a policy, run as the policy is loaded, before the rules are
+/// added to the knowledge base.
+///
+/// Register passes with `Polar::register_rewrite_pass` before loading the policy, e.g., to add
+/// a tenant check to the body of every `allow` rule. Passes see rules after constant folding
+/// and before the built-in rewrites, so they may use dot lookups and other sugar.
+pub trait RewritePass: Send + Sync {
+    /// A short identifier for the pass.
+    fn name(&self) -> &str;
+
+    /// Transform `rule`, e.g., by adding to its body.
+    fn rewrite_rule(&self, rule: Rule, kb: &KnowledgeBase) -> Rule;
+}
+
+struct FoldConstants;
+
+impl RewritePass for FoldConstants {
+    fn name(&self) -> &str {
+        "fold_constants"
+    }
+
+    fn rewrite_rule(&self, rule: Rule, _: &KnowledgeBase) -> Rule {
+        fold_constants(rule)
+    }
+}
+
+struct Desugar;
+
+impl RewritePass for Desugar {
+    fn name(&self) -> &str {
+        "desugar"
+    }
+
+    fn rewrite_rule(&self, rule: Rule, kb: &KnowledgeBase) -> Rule {
+        rewrite_rule(rule, kb)
+    }
+}
+
+/// The passes run over each rule loaded, in order: constant folding, the passes registered
+/// with `register`, and the built-in rewrites of dot lookups, anonymous variables, etc.
+pub struct RewritePipeline {
+    passes: Vec<Box<dyn RewritePass>>,
+}
+
+impl Default for RewritePipeline {
+    fn default() -> Self {
+        Self {
+            passes: vec![Box::new(FoldConstants), Box::new(Desugar)],
+        }
+    }
+}
+
+impl RewritePipeline {
+    /// Run `pass` after the passes registered before it, and before the built-in rewrites.
+    pub fn register(&mut self, pass: Box<dyn RewritePass>) {
+        self.passes.insert(self.passes.len() - 1, pass);
+    }
+
+    pub fn run(&self, rule: Rule, kb: &KnowledgeBase) -> Rule {
+        self.passes
+            .iter()
+            .fold(rule, |rule, pass| pass.rewrite_rule(rule, kb))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -287,6 +580,46 @@ mod tests {
         crate::parser::parse_rules(src).unwrap()
     }
 
+    #[test]
+    fn rewrites_keep_source() {
+        use crate::visitor::{walk_term, Visitor};
+
+        struct SourceVisitor(Vec<(String, String)>);
+
+        impl Visitor for SourceVisitor {
+            fn visit_term(&mut self, t: &Term) {
+                let context = t.parsed_context().expect("rewritten term has no source");
+                let src = context.source.src[context.left..context.right].to_owned();
+                self.0.push((t.to_string(), src));
+                walk_term(self, t)
+            }
+        }
+
+        let kb = KnowledgeBase::new();
+        let rule = rewrite_rule(
+            parse_rules("f(x) if x.foo.bar = 1 and g(x.baz + 1);").remove(0),
+            &kb,
+        );
+        let mut visitor = SourceVisitor(vec![]);
+        visitor.visit_term(&rule.body);
+        let temps = visitor
+            .0
+            .into_iter()
+            .filter(|(term, _)| term.starts_with("_value") || term.starts_with("_op"))
+            .collect::<HashSet<_>>();
+        assert!(temps.contains(&("_value_1".to_owned(), "x.foo".to_owned())));
+        assert!(temps.contains(&("_value_2".to_owned(), "x.foo.bar".to_owned())));
+        assert!(temps.contains(&("_value_3".to_owned(), "x.baz".to_owned())));
+        assert!(temps.contains(&("_op_4".to_owned(), "x.baz + 1".to_owned())));
+
+        let query = parse_query("x = :y");
+        let params = HashMap::from([("y".to_owned(), Term::from(1))]);
+        let query = fill_placeholders(query, params).unwrap();
+        let mut visitor = SourceVisitor(vec![]);
+        visitor.visit_term(&query);
+        assert!(visitor.0.contains(&("1".to_owned(), ":y".to_owned())));
+    }
+
     #[test]
     fn rewrite_anonymous_vars() {
         let kb = KnowledgeBase::new();
@@ -341,6 +674,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn rewrite_parameter_guards() {
+        let kb = KnowledgeBase::new();
+        let rules = parse_rules("f(x: Foo where x.active, y where y > 1) if x.owner = y;");
+        let rule = rules[0].clone();
+        assert_eq!(
+            rule.to_string(),
+            "f(x: Foo{} where x.active, y where y > 1) if x.owner = y;"
+        );
+        let rule = rewrite_rule(rule, &kb);
+        assert_eq!(
+            rule.to_string(),
+            "f(x: Foo{}, y) if x.active = _value_1 and _value_1 and y > 1 and _value_2 = y and x.owner = _value_2;"
+        );
+    }
+
+    #[test]
+    fn rewrite_lambda_bodies() {
+        let kb = KnowledgeBase::new();
+        let rules = parse_rules("f(xs, a) if all(xs, |x| x.owner = a.id);");
+        let rule = rewrite_rule(rules[0].clone(), &kb);
+        assert_eq!(
+            rule.to_string(),
+            "f(xs, a) if all(xs, |x| _value_1 = _value_2 and x.owner = _value_1 and a.id = _value_2);"
+        );
+    }
+
     #[test]
     fn rewrite_nested_lookups() {
         let kb = KnowledgeBase::new();
@@ -1,23 +1,70 @@
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 
 use super::sources::{Context, Source, SourceInfo};
 use super::terms::*;
+use super::visitor::{walk_rule, Visitor};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Parameter {
     pub parameter: Term,
     pub specializer: Option<Term>,
+    /// A condition on the parameter written after `where`, which the rewriter moves to the
+    /// start of the rule's body.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guard: Option<Term>,
 }
 
 impl Parameter {
     pub fn is_ground(&self) -> bool {
-        self.specializer.is_none() && self.parameter.value().is_ground()
+        self.specializer.is_none() && self.guard.is_none() && self.parameter.value().is_ground()
+    }
+
+    /// Whether the specializer may match `arg`: false only if it's a dictionary pattern that
+    /// `arg`, a dictionary, doesn't match.
+    pub(crate) fn may_match(&self, arg: &Term) -> bool {
+        match &self.specializer {
+            Some(specializer) => match_ground_pattern(arg, specializer) != Some(false),
+            None => true,
+        }
+    }
+}
+
+/// Whether `value matches pattern`, if that can be decided without running a query: when the
+/// pattern is a dictionary pattern, possibly nested, of strings, integers and booleans, and
+/// `value` is a dictionary without variables where the pattern needs it. `None` otherwise,
+/// e.g., for instances, whose fields are looked up by the host.
+pub(crate) fn match_ground_pattern(value: &Term, pattern: &Term) -> Option<bool> {
+    match (value.value(), pattern.value()) {
+        (Value::Dictionary(dict), Value::Pattern(Pattern::Dictionary(fields))) => {
+            let mut decided = Some(true);
+            for (field, pattern) in &fields.fields {
+                match dict.fields.get(field) {
+                    None => return Some(false),
+                    Some(value) => match match_ground_pattern(value, pattern) {
+                        Some(false) => return Some(false),
+                        Some(true) => (),
+                        None => decided = None,
+                    },
+                }
+            }
+            decided
+        }
+        // Integers and floats may be equal, so leave floats to unification.
+        (left, right) if is_scalar(left) && is_scalar(right) => Some(left == right),
+        _ => None,
     }
 }
 
+fn is_scalar(value: &Value) -> bool {
+    matches!(
+        value,
+        Value::String(_) | Value::Boolean(_) | Value::Number(Numeric::Integer(_))
+    )
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Rule {
     pub name: Symbol,
@@ -27,7 +74,61 @@ pub struct Rule {
     pub source_info: SourceInfo,
     // TODO @patrickod: refactor Rule into Rule & RuleType structs
     // `required` is used exclusively with rule *types* and not normal rules.
+    #[serde(default)]
     pub required: bool,
+    /// Boxed to keep rules, and the errors that hold them, small.
+    #[serde(default)]
+    pub metadata: Box<RuleMetadata>,
+}
+
+/// Information about a rule from annotations preceding it, e.g., `@deprecated("...")`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct RuleMetadata {
+    /// Set by `@deprecated(message)`. Calling a deprecated rule emits a warning.
+    pub deprecated: Option<String>,
+    /// Set by `@template(name)`. A template isn't loaded as a rule: its placeholders, like
+    /// `:role`, are filled in by `KnowledgeBase::instantiate_template` to make rules.
+    pub template: Option<String>,
+    /// Set by `@doc(text)`.
+    pub doc: Option<String>,
+    /// Set by `@calls(names)` on a template, e.g., `@calls("has_role, has_relation")`. Rules
+    /// with the name and arity of the template may only call these rules, besides built-ins.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub calls: Option<Vec<Symbol>>,
+    /// Set by any other annotation, e.g., `@owner("secteam")`, by name. Decisions made by the
+    /// rule can be attributed with them, e.g., to the team that owns it.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+/// The decision made when no `allow` rule matches a request, declared in a policy or scope with
+/// `default deny;`, `default allow;`, or `default allow rule;`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum DefaultDecision {
+    Deny,
+    Allow,
+    /// Allow the request if `rule(actor, action, resource)` matches it, e.g.,
+    /// `default allow allow_readonly;`.
+    AllowIf(Symbol),
+}
+
+/// A rule template, as listed by `KnowledgeBase::list_templates`.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct TemplateInfo {
+    /// Set by `@template(name)`.
+    pub name: String,
+    /// The scope the template was loaded into, or `None` for templates loaded without a scope.
+    pub scope: Option<String>,
+    /// The head of the rules made from the template, e.g., `allow(actor, :action, _resource)`.
+    pub head: String,
+    /// The names of the template's placeholders without the leading `:`, in the order they
+    /// first appear.
+    pub placeholders: Vec<String>,
+    /// Set by `@doc(text)`.
+    pub doc: Option<String>,
+    /// Set by `@calls(names)`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub calls: Option<Vec<Symbol>>,
 }
 
 impl PartialEq for Rule {
@@ -44,6 +145,26 @@ impl Rule {
         self.params.iter().all(|p| p.is_ground())
     }
 
+    /// The names of the placeholders, like `:role`, in the rule without the leading `:`, in the
+    /// order they first appear.
+    pub fn placeholders(&self) -> Vec<String> {
+        struct PlaceholderVisitor(Vec<String>);
+
+        impl Visitor for PlaceholderVisitor {
+            fn visit_variable(&mut self, v: &Symbol) {
+                if let Some(name) = v.as_str().strip_prefix(':') {
+                    if !self.0.iter().any(|seen| seen == name) {
+                        self.0.push(name.to_owned());
+                    }
+                }
+            }
+        }
+
+        let mut visitor = PlaceholderVisitor(vec![]);
+        walk_rule(&mut visitor, self);
+        visitor.0
+    }
+
     pub(crate) fn parsed_context(&self) -> Option<&Context> {
         if let SourceInfo::Parser(context) = &self.source_info {
             Some(context)
@@ -59,6 +180,7 @@ impl Rule {
             body,
             source_info: SourceInfo::Test,
             required: false,
+            metadata: Default::default(),
         }
     }
 
@@ -77,6 +199,7 @@ impl Rule {
             body,
             source_info: SourceInfo::parser(source, left, right),
             required: false,
+            metadata: Default::default(),
         }
     }
 }
@@ -131,6 +254,23 @@ impl RuleTypes {
         self.add_default_rule_types()
     }
 
+    /// Replace each rule type for which `f` returns rules with those rules.
+    pub fn expand<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&Rule) -> Option<Vec<Rule>>,
+    {
+        for rule_types in self.0.values_mut() {
+            *rule_types = rule_types
+                .iter()
+                .flat_map(|rule_type| f(rule_type).unwrap_or_else(|| vec![rule_type.clone()]))
+                .collect();
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Rule> {
+        self.0.values().flatten()
+    }
+
     pub fn required_rule_types(&self) -> Vec<&Rule> {
         self.0
             .values()
@@ -205,12 +345,95 @@ impl RuleIndex {
     }
 }
 
+/// The rules of one arity, grouped ahead of time by the constant value of one of their
+/// parameters, so that calls with a constant argument in that position don't walk the index.
+#[derive(Clone, Debug)]
+struct Specialization {
+    arity: usize,
+    param: usize,
+    /// For each constant, the rules whose parameter is that constant or not a constant, in the
+    /// order they were added.
+    clauses: HashMap<Value, Rules>,
+    /// The rules whose parameter is not a constant, in the order they were added.
+    general: Rules,
+}
+
+impl Specialization {
+    fn new(arity: usize, param: usize) -> Self {
+        Self {
+            arity,
+            param,
+            clauses: HashMap::new(),
+            general: vec![],
+        }
+    }
+
+    fn add_rule(&mut self, rule: &Arc<Rule>) {
+        if rule.params.len() != self.arity {
+            return;
+        }
+        let param = &rule.params[self.param];
+        if param.is_ground() {
+            let general = &self.general;
+            self.clauses
+                .entry(param.parameter.value().clone())
+                .or_insert_with(|| general.clone())
+                .push(rule.clone());
+        } else {
+            self.general.push(rule.clone());
+            for rules in self.clauses.values_mut() {
+                rules.push(rule.clone());
+            }
+        }
+    }
+
+    /// The rules applicable to `args`, as `RuleIndex` would find them, if the call matches this
+    /// specialization.
+    fn get_applicable_rules(&self, args: &[Term]) -> Option<Rules> {
+        if args.len() != self.arity || !args[self.param].is_ground() {
+            return None;
+        }
+        let rules = self
+            .clauses
+            .get(args[self.param].value())
+            .unwrap_or(&self.general);
+        // Other constant parameters still have to match their arguments.
+        let matches = |rule: &&Arc<Rule>| {
+            rule.params
+                .iter()
+                .zip(args)
+                .enumerate()
+                .all(|(i, (param, arg))| {
+                    i == self.param
+                        || !param.is_ground()
+                        || !arg.is_ground()
+                        || param.parameter.value() == arg.value()
+                })
+        };
+        Some(rules.iter().filter(matches).cloned().collect())
+    }
+}
+
+/// Where a rule goes among the rules of other sources, so that loading the same files in a
+/// different order doesn't change which rule is tried first: rules from named files come first,
+/// by file name, then rules from unnamed sources and the application, in the order they were
+/// added. The rules of a file keep the order they're written in.
+fn source_order(rule: &Rule) -> (bool, Option<&str>) {
+    let filename = rule
+        .parsed_context()
+        .and_then(|context| context.source.filename.as_deref());
+    (filename.is_none(), filename)
+}
+
 #[derive(Clone)]
 pub struct GenericRule {
     pub name: Symbol,
     pub rules: HashMap<u64, Arc<Rule>>,
     index: RuleIndex,
+    specialization: Option<Specialization>,
     next_rule_id: u64,
+    /// The `source_order` of the last rule added.
+    last_source: Option<(bool, Option<String>)>,
 }
 
 impl GenericRule {
@@ -219,7 +442,9 @@ impl GenericRule {
             name,
             rules: Default::default(),
             index: Default::default(),
+            specialization: None,
             next_rule_id: 0,
+            last_source: None,
         };
 
         for rule in rules {
@@ -230,6 +455,14 @@ impl GenericRule {
     }
 
     pub fn add_rule(&mut self, rule: Arc<Rule>) {
+        let (unnamed, filename) = source_order(&rule);
+        if let Some((last_unnamed, last_filename)) = &self.last_source {
+            if (unnamed, filename) < (*last_unnamed, last_filename.as_deref()) {
+                return self.insert_before_later_sources(rule);
+            }
+        }
+        self.last_source = Some((unnamed, filename.map(str::to_owned)));
+
         let rule_id = self.next_rule_id();
 
         assert!(
@@ -237,10 +470,59 @@ impl GenericRule {
             "Rule id already used."
         );
         self.index.index_rule(rule_id, &rule.params[..], 0);
+        if let Some(specialization) = &mut self.specialization {
+            specialization.add_rule(&rule);
+        }
+    }
+
+    /// Add a rule from a source that goes before the sources of rules already added, by adding
+    /// all the rules again in `source_order`.
+    fn insert_before_later_sources(&mut self, rule: Arc<Rule>) {
+        let mut ids: Vec<_> = self.rules.keys().copied().collect();
+        ids.sort_unstable();
+        let mut rules: Rules = ids.iter().map(|id| self.rules[id].clone()).collect();
+        rules.push(rule);
+        // Stable, so rules from the same source keep their order.
+        rules.sort_by(|a, b| source_order(a).cmp(&source_order(b)));
+
+        let specialization = self
+            .specialization
+            .take()
+            .map(|specialization| (specialization.arity, specialization.param));
+        self.rules.clear();
+        self.index = RuleIndex::default();
+        self.next_rule_id = 0;
+        self.last_source = None;
+        if let Some((arity, param)) = specialization {
+            self.specialize(arity, param);
+        }
+        for rule in rules {
+            self.add_rule(rule);
+        }
+    }
+
+    /// Group the rules with `arity` parameters by the constant value of parameter `param`, so
+    /// that finding the rules applicable to a call with a constant in that position is a single
+    /// lookup. Replaces any previous specialization.
+    pub fn specialize(&mut self, arity: usize, param: usize) {
+        let mut specialization = Specialization::new(arity, param);
+        let mut ids: Vec<_> = self.rules.keys().collect();
+        ids.sort();
+        for id in ids {
+            specialization.add_rule(&self.rules[id]);
+        }
+        self.specialization = Some(specialization);
     }
 
     #[allow(clippy::ptr_arg)]
     pub fn get_applicable_rules(&self, args: &TermList) -> Rules {
+        if let Some(rules) = self
+            .specialization
+            .as_ref()
+            .and_then(|specialization| specialization.get_applicable_rules(args))
+        {
+            return rules;
+        }
         self.index
             .get_applicable_rules(args, 0)
             .iter()
@@ -321,4 +603,43 @@ mod tests {
         let index13 = index1.index.get(&Some(value!(3))).unwrap();
         assert_eq!(args, keys(index13));
     }
+
+    #[test]
+    fn test_specialization() {
+        let polar = Polar::new();
+        polar.add_hot_entrypoint("f", 3, 1).unwrap();
+        assert!(polar.add_hot_entrypoint("f", 3, 3).is_err());
+        polar
+            .load_str(
+                r#"
+            f(_x, "read", 1);
+            f(_x, _y, 1);
+            f(_x, "read", 2);
+            f(_x, "write", 1);
+            f(_x, _y);
+        "#,
+            )
+            .unwrap();
+
+        let kb = polar.kb.read().unwrap();
+        let generic_rule = kb.get_generic_rule(&sym!("f")).unwrap();
+        assert!(generic_rule.specialization.is_some());
+        let calls = [
+            vec![term!(sym!("a")), term!("read"), term!(1)],
+            vec![term!(sym!("a")), term!("read"), term!(sym!("z"))],
+            vec![term!(sym!("a")), term!("write"), term!(2)],
+            vec![term!(sym!("a")), term!("delete"), term!(1)],
+            vec![term!(sym!("a")), term!(sym!("b")), term!(1)],
+            vec![term!(sym!("a")), term!("read")],
+        ];
+        for args in calls {
+            let rules = generic_rule.get_applicable_rules(&args);
+            let ids: RuleSet = generic_rule.index.get_applicable_rules(&args, 0);
+            let expected: Rules = ids
+                .iter()
+                .map(|id| generic_rule.rules[id].clone())
+                .collect();
+            assert_eq!(rules, expected, "args: {:?}", args);
+        }
+    }
 }
@@ -2,6 +2,7 @@ use crate::counter::Counter;
 use crate::error::{invalid_state, PolarError, PolarResult};
 use crate::events::QueryEvent;
 use crate::terms::Term;
+use crate::traces::Backtrace;
 
 /// Trait for something that produces query events and accepts answers.
 ///
@@ -22,6 +23,14 @@ pub trait Runnable {
         invalid_state("Unexpected external call")
     }
 
+    fn external_call_results(
+        &mut self,
+        _call_id: u64,
+        _terms: Vec<Option<Term>>,
+    ) -> PolarResult<()> {
+        invalid_state("Unexpected batched external call")
+    }
+
     fn debug_command(&mut self, _command: &str) -> PolarResult<()> {
         invalid_state("Unexpected debug command")
     }
@@ -30,6 +39,11 @@ pub trait Runnable {
         Err(err)
     }
 
+    /// The rule calls in progress when an error last aborted the Runnable, if any.
+    fn error_backtrace(&self) -> Option<&Backtrace> {
+        None
+    }
+
     // TODO Alternative?: Goal::Run takes a Runnable constructor function.
     /// Create a new runnable that when run will perform the same operation as
     /// this one.
@@ -0,0 +1,92 @@
+use std::collections::{HashMap, HashSet};
+
+/// Built-in types are implemented by the host library rather than by the application, so
+/// sandboxed policies may always use their methods.
+const BUILTIN_CLASSES: &[&str] = &[
+    "Boolean",
+    "Dictionary",
+    "Float",
+    "Integer",
+    "List",
+    "String",
+];
+
+/// Limits the registered classes, and the attributes & methods of those classes, that rules
+/// loaded from a particular source may use.
+///
+/// A sandbox is attached to a [`Source`](crate::sources::Source) and checked by the VM each
+/// time a term parsed from that source looks up an attribute on, or calls a method of, an
+/// application instance or class. Rules from the sandboxed source may still call rules from
+/// other sources, which aren't restricted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Sandbox {
+    /// Class name -> allowed attributes & methods, or `None` if all are allowed.
+    classes: HashMap<String, Option<HashSet<String>>>,
+}
+
+impl Sandbox {
+    /// Create a sandbox that allows only built-in types.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow every attribute & method of the class registered as `class`.
+    pub fn allow_class(mut self, class: &str) -> Self {
+        self.classes.insert(class.to_owned(), None);
+        self
+    }
+
+    /// Allow the attribute or method `name` of the class registered as `class`.
+    pub fn allow_method(mut self, class: &str, name: &str) -> Self {
+        if let Some(names) = self
+            .classes
+            .entry(class.to_owned())
+            .or_insert_with(|| Some(HashSet::new()))
+        {
+            names.insert(name.to_owned());
+        }
+        self
+    }
+
+    /// Return `true` if `name` may be used on instances of `class`. Instances whose class isn't
+    /// known are never allowed.
+    pub fn allows(&self, class: Option<&str>, name: &str) -> bool {
+        match class {
+            Some(class) if BUILTIN_CLASSES.contains(&class) => true,
+            Some(class) => match self.classes.get(class) {
+                Some(None) => true,
+                Some(Some(names)) => names.contains(name),
+                None => false,
+            },
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sandbox_allows() {
+        let sandbox = Sandbox::new()
+            .allow_class("User")
+            .allow_method("Repo", "name")
+            .allow_method("Repo", "owner");
+        assert!(sandbox.allows(Some("User"), "delete"));
+        assert!(sandbox.allows(Some("Repo"), "name"));
+        assert!(sandbox.allows(Some("Repo"), "owner"));
+        assert!(!sandbox.allows(Some("Repo"), "delete"));
+        assert!(!sandbox.allows(Some("Org"), "name"));
+        assert!(!sandbox.allows(None, "name"));
+        assert!(sandbox.allows(Some("String"), "len"));
+
+        // Allowing a whole class overrides earlier method allowances and isn't narrowed by
+        // later ones.
+        let sandbox = Sandbox::new()
+            .allow_method("Repo", "name")
+            .allow_class("Repo")
+            .allow_method("Repo", "owner");
+        assert!(sandbox.allows(Some("Repo"), "delete"));
+    }
+}
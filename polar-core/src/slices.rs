@@ -0,0 +1,173 @@
+//! Slices of the knowledge base: the rules that a query for one action could use.
+//!
+//! Queries like `allow(actor, "read", resource)` only use the `allow` rules for `"read"` or
+//! for any action, the rules those call, and so on. Propagating constant arguments from each
+//! call to the rules it calls finds those rules ahead of time, so that a query for `"read"` in
+//! a large policy doesn't have to filter out the rules for unrelated actions and resources at
+//! every call.
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+
+use super::kb::KnowledgeBase;
+use super::rules::{GenericRule, Rule};
+use super::terms::*;
+use super::visitor::{walk_term, Visitor};
+
+/// The rule that queries are sliced for, by action.
+pub const SLICED_RULE: &str = "allow";
+
+/// The rules that queries for one action could use.
+#[derive(Clone, Default)]
+pub struct RuleSlice {
+    rules: HashMap<Symbol, GenericRule>,
+}
+
+impl RuleSlice {
+    /// The clauses of the rule `name` that queries for the slice's action could use.
+    pub fn get_generic_rule(&self, name: &Symbol) -> Option<&GenericRule> {
+        self.rules.get(name)
+    }
+
+    /// The number of clauses in the slice, of every rule.
+    pub fn len(&self) -> usize {
+        self.rules
+            .values()
+            .map(|generic_rule| generic_rule.rules.len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The slice for each action that `SLICED_RULE` clauses name, and by `None`, for the rest.
+pub type ActionSlices = HashMap<Option<Value>, Arc<RuleSlice>>;
+
+/// What's known of an argument ahead of time: its value, if it's a constant.
+type Abstract = Option<Value>;
+
+/// Collects the rule calls in a rule body, with what's known of their arguments.
+struct CallVisitor<'a> {
+    known: &'a HashMap<Symbol, Value>,
+    calls: Vec<(Symbol, Vec<Abstract>)>,
+}
+
+impl<'a> Visitor for CallVisitor<'a> {
+    fn visit_term(&mut self, term: &Term) {
+        match term.value() {
+            // Method calls aren't rule calls.
+            Value::Expression(op) if op.operator == Operator::Dot => return,
+            Value::Call(call) => {
+                let args = call
+                    .args
+                    .iter()
+                    .map(|arg| match arg.value() {
+                        Value::Variable(var) => self.known.get(var).cloned(),
+                        value if value.is_ground() => Some(value.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                self.calls.push((call.name.clone(), args));
+            }
+            _ => (),
+        }
+        walk_term(self, term)
+    }
+}
+
+/// Whether a clause could match a call with arguments `args`.
+fn may_match(rule: &Rule, args: &[Abstract]) -> bool {
+    rule.params.len() == args.len()
+        && rule.params.iter().zip(args).all(|(param, arg)| match arg {
+            Some(arg) if param.is_ground() => param.parameter.value() == arg,
+            _ => true,
+        })
+}
+
+/// The clauses of each rule in a slice, and the calls they make.
+#[derive(Default)]
+struct Slicer {
+    clauses: HashMap<Symbol, BTreeSet<u64>>,
+    calls: Vec<(Symbol, Vec<Abstract>)>,
+}
+
+impl Slicer {
+    /// Add the clause `id` of the rule `name`, called with arguments `args`.
+    fn add_clause(&mut self, name: &Symbol, id: u64, rule: &Rule, args: &[Abstract]) {
+        self.clauses.entry(name.clone()).or_default().insert(id);
+        // Parameters bound to constants by the call are constants in the body.
+        let known = rule
+            .params
+            .iter()
+            .zip(args)
+            .filter_map(|(param, arg)| match (param.parameter.value(), arg) {
+                (Value::Variable(var), Some(value)) => Some((var.clone(), value.clone())),
+                _ => None,
+            })
+            .collect();
+        let mut visitor = CallVisitor {
+            known: &known,
+            calls: vec![],
+        };
+        visitor.visit_term(&rule.body);
+        for param in &rule.params {
+            if let Some(guard) = &param.guard {
+                visitor.visit_term(guard);
+            }
+        }
+        self.calls.extend(visitor.calls);
+    }
+}
+
+/// The rules that a query for `SLICED_RULE(_, action, _)` could use, or, if `action` is
+/// `None`, for an action that no `SLICED_RULE` clause names.
+pub fn slice(kb: &KnowledgeBase, action: Option<&Value>) -> RuleSlice {
+    let mut slicer = Slicer::default();
+    let sliced_rule = Symbol::new(SLICED_RULE);
+    let args = vec![None, action.cloned(), None];
+    for (id, rule) in kb
+        .get_generic_rule(&sliced_rule)
+        .into_iter()
+        .flat_map(|generic_rule| &generic_rule.rules)
+    {
+        let matches = match action {
+            Some(_) => may_match(rule, &args),
+            None => rule.params.len() == 3 && !rule.params[1].is_ground(),
+        };
+        if matches {
+            slicer.add_clause(&sliced_rule, *id, rule, &args);
+        }
+    }
+
+    let mut seen = HashSet::new();
+    while let Some((name, args)) = slicer.calls.pop() {
+        let generic_rule = match kb.get_generic_rule(&name) {
+            Some(generic_rule) if seen.insert((name.clone(), args.clone())) => generic_rule,
+            _ => continue,
+        };
+        for (id, rule) in &generic_rule.rules {
+            if may_match(rule, &args) {
+                slicer.add_clause(&name, *id, rule, &args);
+            }
+        }
+    }
+
+    let rules = slicer
+        .clauses
+        .into_iter()
+        .map(|(name, ids)| {
+            let generic_rule = kb.get_generic_rule(&name).unwrap();
+            let rules = ids
+                .iter()
+                .map(|id| generic_rule.rules[id].clone())
+                .collect();
+            let mut sliced = GenericRule::new(name.clone(), rules);
+            if let Some((arity, param)) = kb.hot_entrypoint(&name) {
+                sliced.specialize(arity, param);
+            }
+            (name, sliced)
+        })
+        .collect();
+    RuleSlice { rules }
+}
@@ -1,9 +1,10 @@
 use std::fmt::Write;
+use std::io::{self, BufRead, BufReader, Read};
 use std::{fmt, sync::Arc};
 
 use serde::{Deserialize, Serialize};
 
-use crate::{formatting::source_lines, lexer::loc_to_pos};
+use crate::{formatting::source_lines, lexer::loc_to_pos, sandbox::Sandbox};
 
 /// Parsed source context.
 #[derive(Clone)]
@@ -36,6 +37,7 @@ impl Context {
     pub(crate) fn source_position(&self) -> String {
         let mut f = String::new();
         let (row, column) = loc_to_pos(&self.source.src, self.left);
+        let row = self.source.first_line + row;
         write!(f, " at line {}, column {}", row + 1, column + 1).unwrap();
         if let Some(ref filename) = self.source.filename {
             write!(f, " of file {}", filename).unwrap();
@@ -57,6 +59,10 @@ pub enum SourceInfo {
 
     /// Created for a test
     Test,
+
+    /// A value the application marked as sensitive, e.g., a secret, which is redacted
+    /// wherever it's displayed
+    Sensitive,
 }
 
 impl fmt::Debug for SourceInfo {
@@ -67,6 +73,7 @@ impl fmt::Debug for SourceInfo {
             Self::TemporaryVariable => f.write_str("SourceInfo::TemporaryVariable"),
             Self::Ffi => f.write_str("SourceInfo::Ffi"),
             Self::Test => f.write_str("SourceInfo::Test"),
+            Self::Sensitive => f.write_str("SourceInfo::Sensitive"),
         }
     }
 }
@@ -83,10 +90,17 @@ impl SourceInfo {
 
 // TODO(gj): `Serialize` makes some `polar-wasm-api` tests easier to write. We could look into
 // https://serde.rs/remote-derive.html if we cared to preserve that while removing this impl.
-#[derive(PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Source {
     pub filename: Option<String>,
     pub src: String,
+    /// Restricts the application calls made by rules in this source.
+    #[serde(skip)]
+    pub sandbox: Option<Sandbox>,
+    /// Line of the file that `src` starts on, if `src` is a chunk of a larger file read with
+    /// a [`SourceReader`].
+    #[serde(skip)]
+    pub first_line: usize,
 }
 
 impl Source {
@@ -94,6 +108,8 @@ impl Source {
         Self {
             filename: None,
             src: src.as_ref().into(),
+            sandbox: None,
+            first_line: 0,
         }
     }
 
@@ -101,6 +117,212 @@ impl Source {
         Self {
             filename: Some(filename.as_ref().into()),
             src: src.as_ref().into(),
+            sandbox: None,
+            first_line: 0,
         }
     }
+
+    /// Restrict the application classes & methods that rules in this source may use.
+    pub fn with_sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+}
+
+/// Default minimum size in bytes of the chunks read by a [`SourceReader`].
+pub const CHUNK_SIZE: usize = 1 << 16;
+
+/// Reads Polar source in chunks of whole statements, so that large policies can be parsed and
+/// loaded piece by piece instead of reading the whole source into memory first.
+///
+/// Each chunk is a [`Source`] of at least [`CHUNK_SIZE`] bytes, except for the last, and
+/// records the line it starts on so that errors report lines of the whole source. Only the
+/// first chunk is checked against sources loaded before it for duplicate files.
+pub struct SourceReader<R> {
+    reader: BufReader<R>,
+    filename: Option<String>,
+    chunk_size: usize,
+    scanner: StatementScanner,
+    /// Text read but not yet returned in a chunk.
+    buf: String,
+    /// End of the last whole statement in `buf`.
+    boundary: usize,
+    /// Line of the file that `buf` starts on.
+    line: usize,
+}
+
+impl<R: Read> SourceReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            filename: None,
+            chunk_size: CHUNK_SIZE,
+            scanner: StatementScanner::default(),
+            buf: String::new(),
+            boundary: 0,
+            line: 0,
+        }
+    }
+
+    /// Name the chunks after `filename`.
+    pub fn with_filename<T: AsRef<str>>(mut self, filename: T) -> Self {
+        self.filename = Some(filename.as_ref().into());
+        self
+    }
+
+    /// Read chunks of at least `chunk_size` bytes instead of [`CHUNK_SIZE`].
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    fn chunk(&mut self, end: usize) -> Source {
+        let src: String = self.buf.drain(..end).collect();
+        let first_line = self.line;
+        self.line += src.matches('\n').count();
+        self.boundary = self.boundary.saturating_sub(end);
+        Source {
+            filename: self.filename.clone(),
+            src,
+            sandbox: None,
+            first_line,
+        }
+    }
+}
+
+impl<R: Read> Iterator for SourceReader<R> {
+    type Item = io::Result<Source>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            while self.boundary < self.chunk_size.max(1) {
+                let start = self.buf.len();
+                match self.reader.read_line(&mut self.buf) {
+                    Ok(0) if self.buf.trim().is_empty() => return None,
+                    Ok(0) => return Some(Ok(self.chunk(self.buf.len()))),
+                    Ok(_) => {
+                        if let Some(end) = self.scanner.scan(&self.buf[start..]) {
+                            self.boundary = start + end;
+                        }
+                    }
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+            // Skip chunks of blank lines.
+            let source = self.chunk(self.boundary);
+            if !source.src.trim().is_empty() {
+                return Some(Ok(source));
+            }
+        }
+    }
+}
+
+/// Enough of the start of a statement to tell whether it's a resource block.
+const HEAD_LEN: usize = "resource ".len();
+
+/// Finds the ends of lines that end statements, without parsing them.
+#[derive(Default)]
+struct StatementScanner {
+    /// Nesting of parentheses, brackets, and braces.
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    in_comment: bool,
+    /// The start of the statement in progress, if any.
+    head: Option<String>,
+}
+
+impl StatementScanner {
+    /// Scan the next piece of text, returning the end of the last line in it that ends a
+    /// statement without starting another one.
+    fn scan(&mut self, text: &str) -> Option<usize> {
+        let mut boundary = None;
+        for (i, c) in text.char_indices() {
+            let mut ended = false;
+            if self.in_comment {
+                self.in_comment = c != '\n';
+            } else if self.in_string {
+                match c {
+                    _ if self.escaped => self.escaped = false,
+                    '\\' => self.escaped = true,
+                    '"' => self.in_string = false,
+                    _ => (),
+                }
+            } else {
+                match c {
+                    '#' => self.in_comment = true,
+                    '"' => self.in_string = true,
+                    '(' | '[' | '{' => self.depth += 1,
+                    ')' | ']' => self.depth = self.depth.saturating_sub(1),
+                    '}' => {
+                        self.depth = self.depth.saturating_sub(1);
+                        // Resource blocks end with their closing brace.
+                        ended = self.depth == 0 && self.in_block();
+                    }
+                    ';' => ended = self.depth == 0,
+                    _ => (),
+                }
+            }
+
+            if ended {
+                self.head = None;
+            } else if c == '\n' && self.head.is_none() {
+                boundary = Some(i + 1);
+            } else if !self.in_comment && (self.head.is_some() || !c.is_whitespace()) {
+                let head = self.head.get_or_insert_with(String::new);
+                if head.len() < HEAD_LEN {
+                    head.push(c);
+                }
+            }
+        }
+        boundary
+    }
+
+    fn in_block(&self) -> bool {
+        let keyword = self
+            .head
+            .as_deref()
+            .and_then(|h| h.split_whitespace().next());
+        matches!(keyword, Some("resource" | "actor"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks(src: &str) -> Vec<(usize, String)> {
+        SourceReader::new(src.as_bytes())
+            .with_chunk_size(1)
+            .map(|source| source.map(|s| (s.first_line, s.src)).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_source_reader() {
+        let src = r#"f(1); # not; a statement
+g(x) if
+  x = "a;
+b";
+resource Repo {
+  roles = ["reader"];
+}
+h({a: 1}); i(1)
+  ;
+"#;
+        assert_eq!(
+            chunks(src),
+            vec![
+                (0, "f(1); # not; a statement\n".to_owned()),
+                (1, "g(x) if\n  x = \"a;\nb\";\n".to_owned()),
+                (
+                    4,
+                    "resource Repo {\n  roles = [\"reader\"];\n}\n".to_owned()
+                ),
+                (7, "h({a: 1}); i(1)\n  ;\n".to_owned()),
+            ]
+        );
+        assert_eq!(chunks("f(1);\n\n"), vec![(0, "f(1);\n".to_owned())]);
+        assert_eq!(chunks("f(1)"), vec![(0, "f(1)".to_owned())]);
+    }
 }
@@ -0,0 +1,344 @@
+//! Compilation of data filters to SQL predicates, e.g., for row-level security policies that
+//! let a database enforce the same rules as the application.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::data_filtering::{Type, Types};
+use crate::error::{PolarResult, RuntimeError};
+use crate::filter::{Comparison, Condition, Datum, Filter, Projection, Relation};
+use crate::numerics::Numeric;
+use crate::terms::Value;
+
+/// A SQL predicate over the rows of a filter's root table, with numbered placeholders (`$1`,
+/// `$2`, ...) for the filter's parameters and the values it compares fields with, e.g., for a
+/// PostgreSQL row-level security policy. Values are never written into the predicate itself.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SqlTemplate {
+    pub predicate: String,
+    /// What each placeholder stands for, in order, e.g., `actor.id` for `$1`.
+    pub parameters: Vec<SqlParameter>,
+}
+
+/// What a placeholder of a [`SqlTemplate`] stands for.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SqlParameter {
+    /// A parameter of the filter, e.g., `actor.id`, for the caller to bind.
+    Path(String),
+    /// A value from the policy: an integer, a float, a string, or a boolean.
+    Value(Value),
+}
+
+impl Filter {
+    /// Compile the filter to a PostgreSQL predicate over the rows of its root type's table.
+    /// Relations become `EXISTS` subqueries joined on the fields of the relations in `types`.
+    /// `tables` maps type names to table names; types without one use their own name.
+    pub fn to_sql(
+        &self,
+        types: &Types,
+        tables: &HashMap<String, String>,
+    ) -> PolarResult<SqlTemplate> {
+        let mut compiler = SqlCompiler {
+            filter: self,
+            types,
+            tables,
+            parameters: vec![],
+        };
+        let predicate = compiler.predicate()?;
+        Ok(SqlTemplate {
+            predicate,
+            parameters: compiler.parameters,
+        })
+    }
+}
+
+struct SqlCompiler<'a> {
+    filter: &'a Filter,
+    types: &'a Types,
+    tables: &'a HashMap<String, String>,
+    parameters: Vec<SqlParameter>,
+}
+
+impl<'a> SqlCompiler<'a> {
+    fn predicate(&mut self) -> PolarResult<String> {
+        let filter = self.filter;
+        // Filters say "false" with `TRUE = FALSE`, so leave out the conjunctions that do.
+        let mut disjuncts = filter
+            .conditions
+            .iter()
+            .filter(|conditions| !conditions.iter().any(is_contradiction))
+            .map(|conditions| self.conjunction(conditions))
+            .collect::<PolarResult<Vec<_>>>()?;
+        Ok(match disjuncts.len() {
+            0 => "FALSE".to_owned(),
+            1 => disjuncts.remove(0),
+            _ => disjuncts
+                .iter()
+                .map(|disjunct| format!("({})", disjunct))
+                .collect::<Vec<_>>()
+                .join(" OR "),
+        })
+    }
+
+    fn conjunction(&mut self, conditions: &'a HashSet<Condition>) -> PolarResult<String> {
+        // Conditions are a set, so sort them for the same predicate every time.
+        let mut conditions = conditions.iter().collect::<Vec<_>>();
+        conditions.sort_by_cached_key(|condition| condition.to_string());
+
+        let mut related = HashSet::new();
+        let mut clauses = vec![];
+        for condition in conditions {
+            let Condition(left, _, right) = condition;
+            for datum in [left, right] {
+                if let Datum::Field(Projection(typ, _)) = datum {
+                    if typ != &self.filter.root {
+                        related.insert(typ.as_str());
+                    }
+                }
+            }
+            clauses.push(self.condition(condition)?);
+        }
+        if clauses.is_empty() {
+            return Ok("TRUE".to_owned());
+        }
+
+        let joins = self.joins(related)?;
+        if joins.is_empty() {
+            return Ok(clauses.join(" AND "));
+        }
+        let from = joins
+            .iter()
+            .map(|Relation(_, _, to)| self.table(to))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let on = joins
+            .iter()
+            .map(|relation| self.join_condition(relation))
+            .collect::<PolarResult<Vec<_>>>()?;
+        Ok(format!(
+            "EXISTS (SELECT 1 FROM {} WHERE {})",
+            from,
+            on.into_iter()
+                .chain(clauses)
+                .collect::<Vec<_>>()
+                .join(" AND ")
+        ))
+    }
+
+    /// The relations traversed from the root to reach the types `related`, in the order the
+    /// filter traverses them.
+    fn joins(&self, mut related: HashSet<&'a str>) -> PolarResult<Vec<&'a Relation>> {
+        let filter = self.filter;
+        let mut needed = HashSet::new();
+        while let Some(&typ) = related.iter().next() {
+            related.remove(typ);
+            let relation = filter
+                .relations
+                .iter()
+                .find(|Relation(_, _, to)| to == typ)
+                .ok_or_else(|| sql_unsupported(format!("no relation leads to `{}`", typ)))?;
+            if needed.insert(relation) && relation.0 != self.filter.root {
+                related.insert(&relation.0);
+            }
+        }
+        Ok(filter
+            .relations
+            .iter()
+            .filter(|relation| needed.contains(relation))
+            .collect())
+    }
+
+    fn join_condition(&self, relation: &Relation) -> PolarResult<String> {
+        let Relation(from, field, to) = relation;
+        match self.types.get(from).and_then(|fields| fields.get(field)) {
+            Some(Type::Relation {
+                my_field,
+                other_field,
+                ..
+            }) => Ok(format!(
+                "{}.{} = {}.{}",
+                self.table(from),
+                quote_identifier(my_field),
+                self.table(to),
+                quote_identifier(other_field)
+            )),
+            _ => Err(sql_unsupported(format!(
+                "`{}.{}` is not a relation",
+                from, field
+            ))),
+        }
+    }
+
+    fn condition(&mut self, condition: &Condition) -> PolarResult<String> {
+        use Comparison::*;
+        let Condition(left, op, right) = condition;
+        let left = self.datum(left)?;
+        let list = match right {
+            Datum::Immediate(Value::List(terms)) => Some(
+                terms
+                    .iter()
+                    .map(|term| self.value(term.value()))
+                    .collect::<PolarResult<Vec<_>>>()?,
+            ),
+            _ => None,
+        };
+        Ok(match (op, list) {
+            (In, Some(items)) if items.is_empty() => "FALSE".to_owned(),
+            (Nin, Some(items)) if items.is_empty() => "TRUE".to_owned(),
+            (In, Some(items)) => format!("{} IN ({})", left, items.join(", ")),
+            (Nin, Some(items)) => format!("{} NOT IN ({})", left, items.join(", ")),
+            (In, None) => format!("{} = ANY({})", left, self.datum(right)?),
+            (Nin, None) => format!("{} <> ALL({})", left, self.datum(right)?),
+            (op, _) => {
+                let op = match op {
+                    Eq => "=",
+                    Neq => "<>",
+                    Lt => "<",
+                    Leq => "<=",
+                    Gt => ">",
+                    Geq => ">=",
+                    In | Nin => unreachable!("handled above"),
+                };
+                format!("{} {} {}", left, op, self.datum(right)?)
+            }
+        })
+    }
+
+    fn datum(&mut self, datum: &Datum) -> PolarResult<String> {
+        match datum {
+            Datum::Field(Projection(typ, Some(field))) => {
+                Ok(format!("{}.{}", self.table(typ), quote_identifier(field)))
+            }
+            Datum::Field(Projection(typ, None)) => Err(sql_unsupported(format!(
+                "comparing whole `{}` records; compare one of their fields instead",
+                typ
+            ))),
+            Datum::Immediate(value) => self.value(value),
+            Datum::Parameter(path) => Ok(self.placeholder(SqlParameter::Path(path.clone()))),
+        }
+    }
+
+    /// The placeholder for `value`, if SQL can compare fields with it.
+    fn value(&mut self, value: &Value) -> PolarResult<String> {
+        match value {
+            Value::Number(Numeric::Integer(_)) | Value::String(_) | Value::Boolean(_) => (),
+            Value::Number(Numeric::Float(f)) if f.is_finite() => (),
+            value => return Err(sql_unsupported(format!("the value {}", value))),
+        }
+        Ok(self.placeholder(SqlParameter::Value(value.clone())))
+    }
+
+    /// The placeholder for `parameter`, the same one each time it's used.
+    fn placeholder(&mut self, parameter: SqlParameter) -> String {
+        let index = match self.parameters.iter().position(|p| p == &parameter) {
+            Some(index) => index,
+            None => {
+                self.parameters.push(parameter);
+                self.parameters.len() - 1
+            }
+        };
+        format!("${}", index + 1)
+    }
+
+    fn table(&self, typ: &str) -> String {
+        quote_identifier(self.tables.get(typ).map_or(typ, String::as_str))
+    }
+}
+
+fn is_contradiction(condition: &Condition) -> bool {
+    matches!(
+        condition,
+        Condition(Datum::Immediate(left), Comparison::Eq, Datum::Immediate(right)) if left != right
+    )
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn sql_unsupported(msg: String) -> crate::error::PolarError {
+    RuntimeError::SqlUnsupported { msg }.into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::events::ResultEvent;
+    use crate::terms::*;
+
+    fn types() -> Types {
+        let s = String::from;
+        hashmap! {
+            s("Repo") => hashmap! {
+                s("org") => Type::Relation {
+                    kind: s("one"),
+                    my_field: s("org_id"),
+                    other_field: s("id"),
+                    other_class_tag: s("Org"),
+                },
+                s("public") => Type::Base { class_tag: s("Boolean") },
+                s("owner_id") => Type::Base { class_tag: s("Integer") },
+            },
+            s("Org") => hashmap! {
+                s("name") => Type::Base { class_tag: s("String") },
+            },
+        }
+    }
+
+    #[test]
+    fn test_to_sql() -> PolarResult<()> {
+        let isa = term!(op!(Isa, var!("_this"), term!(pattern!(instance!("Repo")))));
+        let dot = |term: Term, field: &str| term!(op!(Dot, term, str!(field)));
+        let ors = vec![
+            ResultEvent::new(hashmap! {
+                sym!("resource") => term!(op!(And,
+                    isa.clone(),
+                    term!(op!(Unify, dot(var!("actor"), "id"), dot(var!("_this"), "owner_id")))
+                ))
+            }),
+            ResultEvent::new(hashmap! {
+                sym!("resource") => term!(op!(And,
+                    isa,
+                    term!(op!(Unify, term!(true), dot(var!("_this"), "public"))),
+                    term!(op!(Unify, str!("o'neil"), dot(dot(var!("_this"), "org"), "name")))
+                ))
+            }),
+        ];
+        let filter =
+            Filter::build_with_parameters(types(), ors, "resource", "Repo", &[("actor", "User")])?;
+        let tables = hashmap! { "Repo".to_owned() => "repos".to_owned() };
+        let template = filter.to_sql(&types(), &tables)?;
+        assert_eq!(
+            template.predicate,
+            r#"($1 = "repos"."owner_id") OR (EXISTS (SELECT 1 FROM "Org" WHERE "repos"."org_id" = "Org"."id" AND $2 = "repos"."public" AND $3 = "Org"."name"))"#
+        );
+        assert_eq!(
+            template.parameters,
+            vec![
+                SqlParameter::Path("actor.id".to_owned()),
+                SqlParameter::Value(Value::Boolean(true)),
+                SqlParameter::Value(Value::String("o'neil".to_owned())),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_sql_unsupported() {
+        let filter = Filter::build(
+            types(),
+            vec![ResultEvent::new(hashmap! {
+                sym!("resource") => term!(op!(And,
+                    term!(op!(Isa, var!("_this"), term!(pattern!(instance!("Repo"))))),
+                    term!(op!(Unify, var!("_this"), term!(Value::ExternalInstance(ExternalInstance::from(1)))))
+                ))
+            })],
+            "resource",
+            "Repo",
+        )
+        .unwrap();
+        let error = filter.to_sql(&types(), &HashMap::new()).unwrap_err();
+        assert!(error.to_string().contains("whole `Repo` records"));
+    }
+}
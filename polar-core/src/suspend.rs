@@ -0,0 +1,32 @@
+//! Queries suspended to be resumed later, e.g., by another worker once an asynchronous
+//! application call it's waiting on is answered.
+//!
+//! [`Query::suspend`](crate::query::Query::suspend) captures the state of a query between
+//! events: its goals, bindings, and choice points. [`SuspendedQuery`] can be serialized with
+//! serde and persisted, and [`Polar::resume_query`](crate::polar::Polar::resume_query)
+//! continues it, e.g., by answering the call it was waiting on with
+//! [`Query::call_result`](crate::query::Query::call_result).
+//!
+//! Application instances are referred to by their instance IDs, so the application must be
+//! able to find the instances of a resumed query by ID. Workers that resume each other's
+//! queries should issue IDs from different partitions (see `Polar::set_id_partition`) so that
+//! the IDs a resumed query issues don't collide with those in its state.
+//!
+//! A suspended query's state is trusted as-is, including the scope it runs in and its goal
+//! budget, so it must be stored where callers can't tamper with it. Since where terms came
+//! from isn't serialized, queries with pending sensitive terms or terms from sandboxed
+//! sources can't be suspended.
+use serde::{Deserialize, Serialize};
+
+use super::terms::Term;
+use super::vm::VmState;
+
+/// The state of a suspended query.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SuspendedQuery {
+    /// The [fingerprint](crate::kb::KnowledgeBase::fingerprint) of the policy the query was
+    /// running against. It may only be resumed against the same policy.
+    pub policy_fingerprint: u64,
+    pub(crate) term: Term,
+    pub(crate) vm: VmState,
+}
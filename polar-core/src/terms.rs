@@ -1,14 +1,18 @@
 use std::{
+    cmp::Ordering,
     collections::{hash_map::DefaultHasher, BTreeMap, HashSet},
     fmt,
     hash::{Hash, Hasher},
-    sync::Arc,
+    ops::Deref,
+    sync::{Arc, OnceLock},
 };
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use super::error::{unexpected_value, PolarResult};
-pub use super::numerics::Numeric;
+use super::folder::{fold_value, Folder};
+use super::interner;
+pub use super::numerics::{IntegerOverflow, Numeric};
 use super::resource_block::{ACTOR_UNION_NAME, RESOURCE_UNION_NAME};
 use super::sources::{Context, Source, SourceInfo};
 use super::visitor::{walk_operation, walk_term, Visitor};
@@ -53,24 +57,128 @@ pub fn has_rest_var(list: &TermList) -> bool {
     !list.is_empty() && matches!(list.last().unwrap().value(), Value::RestVariable(_))
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Hash)]
-pub struct Symbol(pub String);
+/// A name, like a variable, rule, class, or field name.
+///
+/// Names are interned, so symbols compare and hash by an integer id rather than by their
+/// text. They still order by their text, so that dictionaries keep their fields sorted.
+#[derive(Clone)]
+pub struct Symbol {
+    id: u32,
+    name: Arc<str>,
+}
 
 impl Symbol {
     pub fn new(name: &str) -> Self {
-        Self(name.to_string())
+        let (id, name) = interner::intern(name);
+        Self { id, name }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.name
     }
 
     pub fn is_temporary_var(&self) -> bool {
-        self.0.starts_with('_')
+        self.name.starts_with('_')
     }
 
     pub fn is_namespaced_var(&self) -> bool {
-        self.0.contains("::")
+        self.name.contains("::")
+    }
+
+    /// Split a path like `a::b::f` into the path of its scope, `a::b`, and its last name, `f`.
+    pub fn split_path(&self) -> Option<(&str, &str)> {
+        self.name.rsplit_once("::")
     }
 
     pub fn is_this_var(&self) -> bool {
-        self.0 == "_this"
+        self.as_str() == "_this"
+    }
+}
+
+/// Make a symbol as the tuple struct `Symbol(String)` did before names were interned, for
+/// code written against it. Prefer `Symbol::new`.
+#[allow(non_snake_case)]
+pub fn Symbol(name: String) -> Symbol {
+    Symbol::new(&name)
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Self {
+        Self::new(&name)
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.name
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Symbol").field(&self.as_str()).finish()
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Symbol {}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state)
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self.id == other.id {
+            Ordering::Equal
+        } else {
+            self.name.cmp(&other.name)
+        }
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct("Symbol", self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self::from)
     }
 }
 
@@ -81,6 +189,14 @@ pub struct Call {
     pub kwargs: Option<BTreeMap<Symbol, Term>>,
 }
 
+/// An anonymous predicate, e.g., `|x| x.owner = actor`, passed to higher-order builtins like
+/// `filter`. Variables other than the parameters refer to the enclosing scope.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
+pub struct Lambda {
+    pub params: TermList,
+    pub body: Term,
+}
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
 pub enum Operator {
     Debug,
@@ -90,7 +206,9 @@ pub enum Operator {
     Isa,
     New,
     Dot,
+    SafeDot,
     Not,
+    Try,
     Mul,
     Div,
     Mod,
@@ -121,6 +239,8 @@ pub struct Operation {
 pub enum Pattern {
     Dictionary(Dictionary),
     Instance(InstanceLiteral),
+    /// A predicate registered by the host as a specializer, written `@name`.
+    Predicate(Symbol),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
@@ -136,6 +256,7 @@ pub enum Value {
     Variable(Symbol),
     RestVariable(Symbol),
     Expression(Operation),
+    Lambda(Lambda),
 }
 
 impl Value {
@@ -144,7 +265,8 @@ impl Value {
             Value::Call(_)
             | Value::ExternalInstance(_)
             | Value::Variable(_)
-            | Value::RestVariable(_) => false,
+            | Value::RestVariable(_)
+            | Value::Lambda(_) => false,
             Value::Number(_) | Value::String(_) | Value::Boolean(_) => true,
             Value::Pattern(_) => panic!("unexpected value type"),
             Value::Dictionary(Dictionary { fields }) => fields.values().all(|t| t.is_ground()),
@@ -162,8 +284,11 @@ impl fmt::Display for Value {
     }
 }
 
+/// What sensitive terms are shown as in traces, logs, and error messages.
+pub const REDACTED: &str = "[REDACTED]";
+
 /// Represents a concrete instance of a Polar value
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Term {
     /// Information about where the term was created from
     #[serde(skip, default = "SourceInfo::ffi")]
@@ -181,6 +306,19 @@ impl PartialEq for Term {
 
 impl Eq for Term {}
 
+impl fmt::Debug for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut term = f.debug_struct("Term");
+        term.field("source_info", &self.source_info);
+        if self.is_sensitive() {
+            term.field("value", &format_args!("{}", REDACTED));
+        } else {
+            term.field("value", &self.value);
+        }
+        term.finish()
+    }
+}
+
 impl Hash for Term {
     /// Hash just the value, not source information.
     fn hash<H>(&self, state: &mut H)
@@ -269,12 +407,45 @@ impl From<Call> for Value {
     }
 }
 
+/// Integers in this range share one preallocated value per integer.
+const SHARED_INTEGERS: std::ops::RangeInclusive<i64> = -128..=1023;
+
+/// Preallocated values for booleans and small integers, which queries create constantly.
+struct SharedValues {
+    booleans: [Arc<Value>; 2],
+    integers: Vec<Arc<Value>>,
+}
+
+/// Wrap `value` for a term, reusing a preallocated copy if it's a boolean or small integer
+/// instead of allocating a new one.
+fn share(value: Value) -> Arc<Value> {
+    static SHARED: OnceLock<SharedValues> = OnceLock::new();
+    let shared = || {
+        SHARED.get_or_init(|| SharedValues {
+            booleans: [
+                Arc::new(Value::Boolean(false)),
+                Arc::new(Value::Boolean(true)),
+            ],
+            integers: SHARED_INTEGERS
+                .map(|i| Arc::new(Value::Number(Numeric::Integer(i))))
+                .collect(),
+        })
+    };
+    match value {
+        Value::Boolean(b) => shared().booleans[b as usize].clone(),
+        Value::Number(Numeric::Integer(i)) if SHARED_INTEGERS.contains(&i) => {
+            shared().integers[(i - SHARED_INTEGERS.start()) as usize].clone()
+        }
+        value => Arc::new(value),
+    }
+}
+
 impl Term {
     /// Creates a new term for a temporary variable
     pub fn new_temporary(value: Value) -> Self {
         Self {
             source_info: SourceInfo::TemporaryVariable,
-            value: Arc::new(value),
+            value: share(value),
         }
     }
 
@@ -282,7 +453,7 @@ impl Term {
     pub fn new_from_ffi(value: Value) -> Self {
         Self {
             source_info: SourceInfo::Ffi,
-            value: Arc::new(value),
+            value: share(value),
         }
     }
 
@@ -290,7 +461,7 @@ impl Term {
     pub fn new_from_parser(source: Arc<Source>, left: usize, right: usize, value: Value) -> Self {
         Self {
             source_info: SourceInfo::parser(source, left, right),
-            value: Arc::new(value),
+            value: share(value),
         }
     }
 
@@ -298,22 +469,34 @@ impl Term {
     pub fn new_from_test(value: Value) -> Self {
         Self {
             source_info: SourceInfo::Test,
-            value: Arc::new(value),
+            value: share(value),
         }
     }
 
+    /// Creates a new term for a value the application marked as sensitive, which is shown as
+    /// [`REDACTED`] instead. The terms nested in `value` are marked too, so that its parts
+    /// stay redacted when they're taken apart.
+    pub fn new_sensitive(value: Value) -> Self {
+        Sensitize.fold_term(Self::new_temporary(value))
+    }
+
+    /// Return true if the term was marked sensitive.
+    pub fn is_sensitive(&self) -> bool {
+        matches!(self.source_info, SourceInfo::Sensitive)
+    }
+
     /// Create a new Term, cloning the source info of `self`
     /// but with the new `value`
     pub fn clone_with_value(&self, value: Value) -> Self {
         Self {
             source_info: self.source_info.clone(),
-            value: Arc::new(value),
+            value: share(value),
         }
     }
 
     /// Replace the `value` of self
     pub fn replace_value(&mut self, value: Value) {
-        self.value = Arc::new(value);
+        self.value = share(value);
     }
 
     pub(crate) fn source_info(&self) -> &SourceInfo {
@@ -461,12 +644,90 @@ impl Term {
         hasher.finish()
     }
 
+    /// Hash the value of this term canonically, for keying caches and deduplicating terms by
+    /// value. Terms that are equal hash the same: dictionaries regardless of the order of
+    /// their fields, and numbers by value, so `1` and `1.0` hash the same. Lists hash in order,
+    /// and source information isn't hashed.
+    ///
+    /// Unlike `hash_value`, which hashes symbols by their interned ids, this hashes symbols by
+    /// name, so the hash of a term doesn't change while it's cached, and is the same in every
+    /// process.
+    pub fn canonical_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        hash_canonical(self.value(), &mut hasher);
+        hasher.finish()
+    }
+
     pub fn is_actor_union(&self) -> bool {
-        matches!(self.value(), Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) | Value::Variable(tag) if tag.0 == ACTOR_UNION_NAME)
+        matches!(self.value(), Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) | Value::Variable(tag) if tag == ACTOR_UNION_NAME)
     }
 
     pub fn is_resource_union(&self) -> bool {
-        matches!(self.value(), Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) | Value::Variable(tag) if tag.0 == RESOURCE_UNION_NAME)
+        matches!(self.value(), Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) | Value::Variable(tag) if tag == RESOURCE_UNION_NAME)
+    }
+}
+
+fn hash_canonical<H: Hasher>(value: &Value, state: &mut H) {
+    let terms = |terms: &[Term], state: &mut H| {
+        terms.len().hash(state);
+        for term in terms {
+            hash_canonical(term.value(), state);
+        }
+    };
+    // Dictionaries iterate over their fields in order of name, whatever order they were
+    // written in.
+    let fields = |fields: &BTreeMap<Symbol, Term>, state: &mut H| {
+        fields.len().hash(state);
+        for (name, term) in fields {
+            name.as_str().hash(state);
+            hash_canonical(term.value(), state);
+        }
+    };
+
+    std::mem::discriminant(value).hash(state);
+    match value {
+        Value::Number(n) => n.hash(state),
+        Value::String(s) => s.hash(state),
+        Value::Boolean(b) => b.hash(state),
+        Value::ExternalInstance(instance) => instance.instance_id.hash(state),
+        Value::Dictionary(dict) | Value::Pattern(Pattern::Dictionary(dict)) => {
+            fields(&dict.fields, state)
+        }
+        Value::Pattern(Pattern::Instance(InstanceLiteral { tag, fields: dict })) => {
+            tag.as_str().hash(state);
+            fields(&dict.fields, state);
+        }
+        Value::Pattern(Pattern::Predicate(name)) => name.as_str().hash(state),
+        Value::Call(call) => {
+            call.name.as_str().hash(state);
+            terms(&call.args, state);
+            call.kwargs.is_some().hash(state);
+            if let Some(kwargs) = &call.kwargs {
+                fields(kwargs, state);
+            }
+        }
+        Value::List(list) => terms(list, state),
+        Value::Variable(sym) | Value::RestVariable(sym) => sym.as_str().hash(state),
+        Value::Expression(op) => {
+            op.operator.hash(state);
+            terms(&op.args, state);
+        }
+        Value::Lambda(lambda) => {
+            terms(&lambda.params, state);
+            hash_canonical(lambda.body.value(), state);
+        }
+    }
+}
+
+/// Marks every term in a value sensitive.
+struct Sensitize;
+
+impl Folder for Sensitize {
+    fn fold_term(&mut self, t: Term) -> Term {
+        Term {
+            source_info: SourceInfo::Sensitive,
+            value: share(fold_value(t.value().clone(), self)),
+        }
     }
 }
 
@@ -501,4 +762,75 @@ mod tests {
             "b:2"
         );
     }
+
+    #[test]
+    fn test_canonical_hash() {
+        let hash = |t: Term| t.canonical_hash();
+        let dict = |fields: &[(&str, Term)]| {
+            let mut dict = Dictionary::new();
+            for (name, term) in fields {
+                dict.fields.insert(sym!(name), term.clone());
+            }
+            term!(Value::Dictionary(dict))
+        };
+
+        // Dictionaries hash the same in any order, lists don't.
+        assert_eq!(
+            hash(dict(&[("a", term!(1)), ("b", term!("two"))])),
+            hash(dict(&[("b", term!("two")), ("a", term!(1))]))
+        );
+        assert_ne!(
+            hash(dict(&[("a", term!(1))])),
+            hash(dict(&[("a", term!(2))]))
+        );
+        assert_ne!(hash(term!([1, 2])), hash(term!([2, 1])));
+        let list = |terms: Vec<Term>| term!(Value::List(terms));
+        assert_ne!(
+            hash(list(vec![term!(1), term!([2])])),
+            hash(list(vec![term!([1]), term!(2)]))
+        );
+        assert_eq!(hash(term!([1, 2])), hash(term!([1.0, 2])));
+
+        // Values of different types don't collide.
+        assert_ne!(hash(term!("x")), hash(term!(sym!("x"))));
+        assert_ne!(hash(term!([])), hash(dict(&[])));
+    }
+
+    #[test]
+    fn test_shared_values() {
+        assert!(Arc::ptr_eq(&term!(1).value, &term!(1).value));
+        assert!(Arc::ptr_eq(&term!(true).value, &term!(true).value));
+        assert!(!Arc::ptr_eq(&term!(1).value, &term!(2).value));
+        assert!(!Arc::ptr_eq(&term!(5000).value, &term!(5000).value));
+
+        // Mutating a shared value copies it first.
+        let mut one = term!(1);
+        *one.mut_value() = value!(2);
+        assert_eq!(term!(1).value(), &value!(1));
+        assert_eq!(one.value(), &value!(2));
+    }
+
+    #[test]
+    fn test_sensitive_terms() {
+        let secret = Term::new_sensitive(Value::List(vec![term!("hunter2"), term!(1)]));
+        assert!(secret.is_sensitive());
+        assert_eq!(secret.to_string(), REDACTED);
+        assert!(!format!("{:?}", secret).contains("hunter2"));
+
+        // Its parts stay redacted, and so do the terms made from it.
+        let parts = match secret.value() {
+            Value::List(parts) => parts,
+            _ => panic!("expected a list"),
+        };
+        assert_eq!(parts[0].to_string(), REDACTED);
+        assert_eq!(
+            parts[0].clone_with_value(value!("other")).to_string(),
+            REDACTED
+        );
+        assert_eq!(parts[1].value(), &value!(1));
+
+        // Sensitive terms are still equal to the values they hold.
+        assert_eq!(parts[0], term!("hunter2"));
+        assert_eq!(term!("hunter2").to_string(), "\"hunter2\"");
+    }
 }
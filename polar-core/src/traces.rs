@@ -1,6 +1,8 @@
 use super::rules::*;
 use super::terms::*;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::rc::Rc;
 use std::sync::Arc;
 
@@ -24,6 +26,29 @@ impl Trace {
             None
         }
     }
+
+    /// The rules applied in the trace, outermost first.
+    pub fn rules(&self) -> Vec<Arc<Rule>> {
+        fn collect(trace: &Trace, rules: &mut Vec<Arc<Rule>>) {
+            if let Node::Rule(rule) = &trace.node {
+                rules.push(rule.clone());
+            }
+            for child in &trace.children {
+                collect(child, rules);
+            }
+        }
+        let mut rules = vec![];
+        collect(self, &mut rules);
+        rules
+    }
+}
+
+/// Values of the quantified variables of a `forall` for which its condition failed, e.g.,
+/// `x = 3` for `forall(x in [1, 2, 3], x < 3)`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Counterexample {
+    pub forall: Term,
+    pub bindings: BTreeMap<Symbol, Term>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -31,3 +56,38 @@ pub struct TraceResult {
     pub trace: Rc<Trace>,
     pub formatted: String,
 }
+
+/// The rule calls in progress when an error aborted a query, outermost first. Kept by the
+/// query, and only formatted when displayed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Backtrace {
+    pub frames: Vec<BacktraceFrame>,
+}
+
+/// A call of a rule in a [`Backtrace`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct BacktraceFrame {
+    /// The rule that was called.
+    pub rule: Arc<Rule>,
+    /// The call, with its arguments replaced by their values when the error occurred.
+    pub call: Term,
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "backtrace (most recent call last):")?;
+        for (i, frame) in self.frames.iter().enumerate() {
+            write!(f, "  {:03}: {}", i, frame.call)?;
+            if let Some(context) = frame.call.parsed_context() {
+                write!(f, "{}", context.source_position())?;
+            }
+            writeln!(f)?;
+            write!(f, "    in rule {}", frame.rule.name)?;
+            if let Some(context) = frame.rule.parsed_context() {
+                write!(f, "{}", context.source_position())?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 use std::collections::{HashMap, HashSet};
 
+use super::builtins::is_builtin;
 use super::diagnostic::Diagnostic;
 use super::error::{PolarError, ValidationError};
 use super::kb::*;
@@ -58,6 +59,12 @@ impl<'kb> Visitor for SingletonVisitor<'kb> {
                     .and_modify(|o| *o = None)
                     .or_insert_with(|| Some(t.clone()));
             }
+            Value::Pattern(Pattern::Predicate(name)) if !self.kb.is_specializer_predicate(name) => {
+                self.singletons
+                    .entry(name.clone())
+                    .and_modify(|o| *o = None)
+                    .or_insert_with(|| Some(t.clone()));
+            }
             _ => (),
         }
         walk_term(self, t);
@@ -125,7 +132,9 @@ pub fn check_no_allow_rule(kb: &KnowledgeBase) -> Option<Diagnostic> {
     let has_allow = kb.get_rules().contains_key(&sym!("allow"));
     let has_allow_field = kb.get_rules().contains_key(&sym!("allow_field"));
     let has_allow_request = kb.get_rules().contains_key(&sym!("allow_request"));
-    if has_allow || has_allow_field || has_allow_request {
+    // A default decision decides the requests that no `allow` rule does.
+    let has_default = kb.default_decision(None).is_some();
+    if has_allow || has_allow_field || has_allow_request || has_default {
         None
     } else {
         Some(Diagnostic::Warning(
@@ -140,7 +149,7 @@ struct ResourceBlocksMissingHasPermissionVisitor {
 
 impl Visitor for ResourceBlocksMissingHasPermissionVisitor {
     fn visit_call(&mut self, call: &Call) {
-        if call.name.0 == "has_permission" {
+        if call.name == "has_permission" {
             self.calls_has_permission = true;
         }
         walk_call(self, call)
@@ -176,32 +185,13 @@ pub fn check_resource_blocks_missing_has_permission(
     visitor.warnings()
 }
 
-struct UndefinedRuleCallVisitor<'kb> {
+/// Collects the calls to rules, but not to methods, in the terms it visits.
+#[derive(Default)]
+struct RuleCallVisitor {
     call_terms: Vec<Term>,
-    defined_rules: HashSet<&'kb Symbol>,
-}
-
-impl<'kb> UndefinedRuleCallVisitor<'kb> {
-    fn new(defined_rules: HashSet<&'kb Symbol>) -> Self {
-        Self {
-            defined_rules,
-            call_terms: Vec::new(),
-        }
-    }
-
-    fn errors(self) -> Vec<Diagnostic> {
-        self.call_terms
-            .into_iter()
-            .filter(|term| {
-                term.as_call()
-                    .map_or(false, |call| !self.defined_rules.contains(&call.name))
-            })
-            .map(|term| PolarError::from(ValidationError::UndefinedRuleCall { term }).into())
-            .collect()
-    }
 }
 
-impl<'kb> Visitor for UndefinedRuleCallVisitor<'kb> {
+impl Visitor for RuleCallVisitor {
     fn visit_term(&mut self, term: &Term) {
         match term.value() {
             Value::Expression(op) => {
@@ -216,12 +206,90 @@ impl<'kb> Visitor for UndefinedRuleCallVisitor<'kb> {
     }
 }
 
+fn undefined_rule_call_errors(
+    call_terms: Vec<Term>,
+    defined_rules: HashSet<&Symbol>,
+) -> Vec<Diagnostic> {
+    call_terms
+        .into_iter()
+        .filter(|term| {
+            // Calls of paths like `a::f` may be of rules in scopes that aren't loaded yet.
+            term.as_call().map_or(false, |call| {
+                !defined_rules.contains(&call.name)
+                    && !is_builtin(call)
+                    && call.name.split_path().is_none()
+            })
+        })
+        .map(|term| PolarError::from(ValidationError::UndefinedRuleCall { term }).into())
+        .collect()
+}
+
 pub fn check_undefined_rule_calls(kb: &KnowledgeBase) -> Vec<Diagnostic> {
-    let mut visitor = UndefinedRuleCallVisitor::new(kb.get_rules().keys().collect());
+    let defined_rules = kb
+        .get_rules()
+        .keys()
+        .chain(kb.get_fact_sources().iter())
+        .chain(kb.get_fact_names())
+        .collect();
+    let mut visitor = RuleCallVisitor::default();
     for rule in kb.get_rules().values() {
         visitor.visit_generic_rule(rule);
     }
-    visitor.errors()
+    undefined_rule_call_errors(visitor.call_terms, defined_rules)
+}
+
+/// Like `check_undefined_rule_calls`, for the rules of `scope`, which may also call rules
+/// defined in `kb`.
+pub fn check_undefined_scope_rule_calls(
+    kb: &KnowledgeBase,
+    name: &str,
+    scope: &Scope,
+) -> Vec<Diagnostic> {
+    let defined_rules = kb
+        .get_rules()
+        .keys()
+        .chain(scope.get_rules().keys())
+        .chain(
+            kb.enclosing_scopes(name)
+                .into_iter()
+                .flat_map(|s| s.get_rules().keys()),
+        )
+        .chain(kb.get_fact_sources().iter())
+        .chain(kb.get_fact_names())
+        .collect();
+    let mut visitor = RuleCallVisitor::default();
+    for rule in scope.get_rules().values() {
+        visitor.visit_generic_rule(rule);
+    }
+    undefined_rule_call_errors(visitor.call_terms, defined_rules)
+}
+
+/// Check that the body of `rule` only calls the rules in `allowed` and built-ins, as the
+/// `@calls` annotations of `templates` require.
+pub fn check_template_rule_calls(
+    rule: &Rule,
+    allowed: &HashSet<&Symbol>,
+    templates: &[String],
+) -> Vec<Diagnostic> {
+    let mut visitor = RuleCallVisitor::default();
+    visitor.visit_term(&rule.body);
+    visitor
+        .call_terms
+        .into_iter()
+        .filter(|term| {
+            matches!(term.as_call(), Ok(call) if !allowed.contains(&call.name) && !is_builtin(call))
+        })
+        .map(|term| {
+            let mut allowed = allowed.iter().map(ToString::to_string).collect::<Vec<_>>();
+            allowed.sort();
+            PolarError::from(ValidationError::DisallowedRuleCall {
+                term,
+                templates: templates.to_vec(),
+                allowed,
+            })
+            .into()
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -275,6 +343,15 @@ mod tests {
         assert!(check_no_allow_rule(&kb).is_none());
     }
 
+    #[test]
+    fn test_check_no_allow_rule_with_default_decision() {
+        let mut kb = KnowledgeBase::new();
+        kb.add_rule(rule!("f", [sym!("x")]));
+        kb.set_default_decision(var!("default"), DefaultDecision::Allow)
+            .unwrap();
+        assert!(check_no_allow_rule(&kb).is_none());
+    }
+
     #[test]
     fn test_check_resource_blocks_missing_has_permission_warning() {
         let mut kb = KnowledgeBase::new();
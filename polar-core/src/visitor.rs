@@ -68,6 +68,9 @@ pub trait Visitor: Sized {
     fn visit_operation(&mut self, o: &Operation) {
         walk_operation(self, o)
     }
+    fn visit_lambda(&mut self, l: &Lambda) {
+        walk_lambda(self, l)
+    }
     fn visit_param(&mut self, p: &Parameter) {
         walk_param(self, p)
     }
@@ -114,6 +117,7 @@ pub fn walk_term<V: Visitor>(visitor: &mut V, term: &Term) {
         Value::Variable(v) => visitor.visit_variable(v),
         Value::RestVariable(r) => visitor.visit_rest_variable(r),
         Value::Expression(o) => visitor.visit_operation(o),
+        Value::Lambda(l) => visitor.visit_lambda(l),
     }
 }
 
@@ -139,6 +143,7 @@ pub fn walk_pattern<V: Visitor>(visitor: &mut V, pattern: &Pattern) {
     match pattern {
         Pattern::Dictionary(dict) => visitor.visit_dictionary(dict),
         Pattern::Instance(instance) => visitor.visit_instance_literal(instance),
+        Pattern::Predicate(name) => visitor.visit_symbol(name),
     }
 }
 
@@ -160,11 +165,19 @@ pub fn walk_operation<V: Visitor>(visitor: &mut V, expr: &Operation) {
     walk_elements!(visitor, visit_term, &expr.args);
 }
 
+pub fn walk_lambda<V: Visitor>(visitor: &mut V, lambda: &Lambda) {
+    walk_elements!(visitor, visit_term, &lambda.params);
+    visitor.visit_term(&lambda.body);
+}
+
 pub fn walk_param<V: Visitor>(visitor: &mut V, param: &Parameter) {
     visitor.visit_term(&param.parameter);
     if let Some(ref specializer) = param.specializer {
         visitor.visit_term(specializer);
     }
+    if let Some(ref guard) = param.guard {
+        visitor.visit_term(guard);
+    }
 }
 
 #[cfg(test)]
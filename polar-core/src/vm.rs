@@ -1,4 +1,4 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::BTreeMap;
 use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
@@ -6,34 +6,47 @@ use std::rc::Rc;
 use std::string::ToString;
 use std::sync::{Arc, RwLock, RwLockReadGuard};
 
+use serde::{Deserialize, Serialize};
+
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
 use crate::bindings::{
-    Binding, BindingManager, BindingStack, Bindings, Bsp, FollowerId, VariableState,
+    Binding, BindingManager, BindingStack, BindingStats, Bindings, Bsp, FollowerId, VariableState,
 };
+use crate::builtins;
 use crate::counter::Counter;
 use crate::data_filtering::partition_equivs;
 use crate::debugger::{get_binding_for_var, DebugEvent, Debugger};
 use crate::error::{invalid_state, unsupported, PolarError, PolarResult, RuntimeError};
 use crate::events::*;
+use crate::facts::Precomputed;
 use crate::folder::Folder;
 use crate::inverter::Inverter;
 use crate::kb::*;
 use crate::messages::*;
 use crate::numerics::*;
-use crate::partial::{simplify_bindings_opt, simplify_partial, sub_this, IsaConstraintCheck};
+use crate::partial::{
+    canonicalize_temporaries, simplify_bindings_opt, simplify_partial, sub_this, IsaConstraintCheck,
+};
+use crate::profile::{ChoiceProfiler, ChoiceStats};
+use crate::quota::{Quota, RuleQuota};
 use crate::rewrites::Renamer;
 use crate::rules::*;
 use crate::runnable::Runnable;
+use crate::slices::RuleSlice;
 use crate::sources::Context;
 use crate::terms::*;
 use crate::traces::*;
 use crate::visitor::{walk_term, Visitor};
+use crate::warning::{PolarWarning, ValidationWarning};
 
 pub const MAX_STACK_SIZE: usize = 10_000;
 pub const DEFAULT_TIMEOUT_MS: u64 = 30_000;
 
+/// A call of a batched method: the instance ID, method name, and arguments.
+type BatchedCall = (u64, Symbol, TermList);
+
 #[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Eq)]
 pub enum LogLevel {
     Trace,
@@ -47,7 +60,9 @@ impl LogLevel {
     }
 }
 
-#[derive(Debug, Clone)]
+/// Goals that can't be serialized, `Error`, `Run`, and `AddConstraintsBatch`, are skipped; a
+/// query with them can't be suspended.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[must_use = "ignored goals are never accomplished"]
 #[allow(clippy::large_enum_variant)]
 pub enum Goal {
@@ -58,6 +73,7 @@ pub enum Goal {
     Debug {
         message: String,
     },
+    #[serde(skip)]
     Error {
         error: PolarError,
     },
@@ -91,6 +107,10 @@ pub enum Goal {
         instance: Term,
         literal: InstanceLiteral,
     },
+    IsaPredicate {
+        instance: Term,
+        name: Symbol,
+    },
     MakeExternal {
         constructor: Term,
         instance_id: u64,
@@ -99,6 +119,11 @@ pub enum Goal {
         call_id: u64,
         iterable: Term,
     },
+    NextFact {
+        call_id: u64,
+        name: Symbol,
+        args: TermList,
+    },
     CheckError,
     Noop,
     Query {
@@ -123,12 +148,27 @@ pub enum Goal {
     },
     TraceStackPush,
     TraceStackPop,
+    /// Leave the body of a rule, which `TraceRule` entered.
+    PopRule,
+    /// Look up `fields` in turn starting from `value`, as for the `get` builtin.
+    SafeLookup {
+        value: Term,
+        fields: TermList,
+        default: Option<Term>,
+        result: Term,
+    },
+    /// Record the values of `variables` as a counterexample to `forall`.
+    RecordCounterexample {
+        forall: Term,
+        variables: Vec<Symbol>,
+    },
     Unify {
         left: Term,
         right: Term,
     },
 
     /// Run the `runnable`.
+    #[serde(skip)]
     Run {
         runnable: Box<dyn Runnable>,
     },
@@ -140,12 +180,137 @@ pub enum Goal {
 
     /// TODO hack.
     /// Add a new constraint
+    #[serde(skip)]
     AddConstraintsBatch {
         add_constraints: Rc<RefCell<Bindings>>,
     },
 }
 
-#[derive(Clone, Debug)]
+impl Goal {
+    /// Whether a query with this goal pending can be suspended.
+    fn is_suspendable(&self) -> bool {
+        !matches!(
+            self,
+            Goal::Error { .. } | Goal::Run { .. } | Goal::AddConstraintsBatch { .. }
+        )
+    }
+
+    /// Visit the terms & rules of the goal.
+    fn visit<V: Visitor>(&self, visitor: &mut V) {
+        use Goal::*;
+        match self {
+            Isa { left, right } | Unify { left, right } => {
+                visitor.visit_term(left);
+                visitor.visit_term(right);
+            }
+            IsMoreSpecific { left, right, args } => {
+                visitor.visit_rule(left);
+                visitor.visit_rule(right);
+                visitor.visit_list(args);
+            }
+            IsSubspecializer {
+                left, right, arg, ..
+            } => {
+                visitor.visit_term(left);
+                visitor.visit_term(right);
+                visitor.visit_term(arg);
+            }
+            Lookup { dict, field, value } => {
+                visitor.visit_dictionary(dict);
+                visitor.visit_term(field);
+                visitor.visit_term(value);
+            }
+            LookupExternal {
+                instance, field, ..
+            } => {
+                visitor.visit_term(instance);
+                visitor.visit_term(field);
+            }
+            IsaExternal { instance, literal } => {
+                visitor.visit_term(instance);
+                visitor.visit_instance_literal(literal);
+            }
+            IsaPredicate { instance, .. } => visitor.visit_term(instance),
+            MakeExternal { constructor, .. } => visitor.visit_term(constructor),
+            NextExternal { iterable, .. } => visitor.visit_term(iterable),
+            NextFact { args, .. } => visitor.visit_list(args),
+            Query { term } | PopQuery { term } | AddConstraint { term } => visitor.visit_term(term),
+            FilterRules {
+                args,
+                applicable_rules,
+                unfiltered_rules,
+            } => {
+                visitor.visit_list(args);
+                for rule in applicable_rules.iter().chain(unfiltered_rules) {
+                    visitor.visit_rule(rule);
+                }
+            }
+            SortRules { args, rules, .. } => {
+                visitor.visit_list(args);
+                for rule in rules {
+                    visitor.visit_rule(rule);
+                }
+            }
+            TraceRule { trace } => visit_trace(visitor, trace),
+            SafeLookup {
+                value,
+                fields,
+                default,
+                result,
+            } => {
+                visitor.visit_term(value);
+                visitor.visit_list(fields);
+                if let Some(default) = default {
+                    visitor.visit_term(default);
+                }
+                visitor.visit_term(result);
+            }
+            RecordCounterexample { forall, .. } => visitor.visit_term(forall),
+            Backtrack
+            | Cut { .. }
+            | Debug { .. }
+            | Error { .. }
+            | Halt
+            | CheckError
+            | Noop
+            | TraceStackPush
+            | TraceStackPop
+            | PopRule
+            | Run { .. }
+            | AddConstraintsBatch { .. } => (),
+        }
+    }
+}
+
+fn visit_trace<V: Visitor>(visitor: &mut V, trace: &Trace) {
+    match &trace.node {
+        Node::Rule(rule) => visitor.visit_rule(rule),
+        Node::Term(term) => visitor.visit_term(term),
+    }
+    for child in &trace.children {
+        visit_trace(visitor, child);
+    }
+}
+
+/// Finds terms that are sensitive or from a sandboxed source. A suspended query forgets both,
+/// since the source info of terms isn't serialized.
+#[derive(Default)]
+struct Unsuspendable(bool);
+
+impl Visitor for Unsuspendable {
+    fn visit_term(&mut self, t: &Term) {
+        let sandboxed = t
+            .parsed_context()
+            .is_some_and(|context| context.source.sandbox.is_some());
+        if t.is_sensitive() || sandboxed {
+            self.0 = true;
+        } else if !self.0 {
+            walk_term(self, t);
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Choice {
     pub alternatives: Vec<GoalStack>,
     bsp: Bsp,              // binding stack pointer
@@ -153,14 +318,23 @@ pub struct Choice {
     queries: Queries,      // query stack snapshot
     trace: Vec<Rc<Trace>>, // trace snapshot
     trace_stack: TraceStack,
+    rule_depth: usize, // rule depth snapshot
+    rule_frames: RuleFrames,
+    /// The fallback of a `try`, queried instead of the rest of its body if an application error
+    /// occurs before this choice is cut.
+    fallback: Option<Term>,
+    /// The clauses of the rule whose alternatives these are, if any, in the order they're tried.
+    rules: Option<Rules>,
 }
 
 pub type Choices = Vec<Choice>;
 /// Shortcut type alias for a list of goals
 pub type Goals = Vec<Goal>;
 pub type TraceStack = Vec<Rc<Vec<Rc<Trace>>>>;
+/// Calls in progress of rules with a quota, with the rule depth each was made at.
+pub type RuleFrames = Vec<(Symbol, usize)>;
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct GoalStack(Vec<Rc<Goal>>);
 
 impl GoalStack {
@@ -185,6 +359,39 @@ impl std::ops::DerefMut for GoalStack {
 
 pub type Queries = TermList;
 
+/// The state of a suspended VM, from which `PolarVirtualMachine::resume` continues the query.
+/// See `crate::suspend`.
+///
+/// The state is trusted as-is when resumed, including the scope the query runs in and its goal
+/// budget, so it must only be resumed from storage that callers can't tamper with.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VmState {
+    goals: GoalStack,
+    bindings: BindingStack,
+    choices: Choices,
+    queries: Queries,
+    tracing: bool,
+    trace_stack: TraceStack,
+    trace: Vec<Rc<Trace>>,
+    counterexamples: Vec<Counterexample>,
+    external_error: Option<String>,
+    rule_depth: usize,
+    heartbeat_interval: Option<u64>,
+    occurs_check: bool,
+    csp: Bsp,
+    scope: Option<String>,
+    goal_budget: Option<u64>,
+    goals_run: u64,
+    rule_quotas: HashMap<Symbol, RuleQuota>,
+    rule_usage: HashMap<Symbol, (u64, u64)>,
+    rule_frames: RuleFrames,
+    call_id_symbols: HashMap<u64, Symbol>,
+    batch_siblings: HashMap<u64, Rc<TermList>>,
+    batch_results: Vec<(BatchedCall, Option<Term>)>,
+    pending_batches: HashMap<u64, (Vec<BatchedCall>, usize)>,
+    query_contains_partial: bool,
+}
+
 pub fn compare(
     op: Operator,
     left: &Term,
@@ -234,6 +441,10 @@ pub struct PolarVirtualMachine {
     pub tracing: bool,
     pub trace_stack: TraceStack, // Stack of traces higher up the tree.
     pub trace: Vec<Rc<Trace>>,   // Traces for the current level of the trace tree.
+    /// Counterexamples to failed `forall` operations, shared with child VMs.
+    pub counterexamples: Rc<RefCell<Vec<Counterexample>>>,
+    /// Counts of choice points and clause searches, shared with child VMs.
+    choice_profiler: Rc<RefCell<ChoiceProfiler>>,
 
     // Errors from outside the vm.
     pub external_error: Option<String>,
@@ -247,6 +458,18 @@ pub struct PolarVirtualMachine {
     /// Maximum size of goal stack
     stack_limit: usize,
 
+    /// Number of rule calls in progress, and the maximum allowed.
+    rule_depth: usize,
+    max_rule_depth: usize,
+
+    /// Number of goals between heartbeat events, if any, and the number run since the last
+    /// event.
+    pub heartbeat_interval: Option<u64>,
+    goals_since_event: u64,
+
+    /// Whether unifying a variable with a term that contains it is an error.
+    pub occurs_check: bool,
+
     /// Binding stack constant below here.
     csp: Bsp,
 
@@ -256,9 +479,41 @@ pub struct PolarVirtualMachine {
     /// Rules and types.
     pub kb: Arc<RwLock<KnowledgeBase>>,
 
+    /// Scope whose rules are queried along with the rules loaded without a scope.
+    scope: Option<String>,
+
+    /// The rules the query could use, if it's for one action. See `crate::slices`.
+    slice: Option<Arc<RuleSlice>>,
+
+    /// Answers of calls evaluated before the query, which replace the rules & facts for them.
+    precomputed: Option<Arc<Precomputed>>,
+
+    /// The rule calls in progress when an error last aborted the query.
+    error_backtrace: Option<Backtrace>,
+
+    /// Number of goals the query may run, from the quota of its scope, and the number it has
+    /// run so far, shared with the VMs it spawns.
+    goal_budget: Option<u64>,
+    goals_run: Rc<Cell<u64>>,
+
+    /// Quotas of individual rules, from the quota of the query's scope, the calls of each made
+    /// and the goals run inside them so far, shared with the VMs the query spawns, and the
+    /// calls of those rules in progress.
+    rule_quotas: Arc<HashMap<Symbol, RuleQuota>>,
+    rule_usage: Rc<RefCell<HashMap<Symbol, (u64, u64)>>>,
+    rule_frames: RuleFrames,
+
     /// Call ID -> result variable name table.
     call_id_symbols: HashMap<u64, Symbol>,
 
+    /// Methods the host calls on many instances at once, the instances of the lists the query
+    /// iterates by instance ID, results of batched calls, and batched calls waiting for results
+    /// by call ID.
+    batched_methods: HashSet<Symbol>,
+    batch_siblings: HashMap<u64, Rc<TermList>>,
+    batch_results: HashMap<BatchedCall, Option<Term>>,
+    pending_batches: HashMap<u64, (Vec<BatchedCall>, usize)>,
+
     /// Logging flag.
     log_level: Option<LogLevel>,
 
@@ -306,28 +561,53 @@ impl PolarVirtualMachine {
             .ok()
             .and_then(|timeout_str| timeout_str.parse::<u64>().ok())
             .unwrap_or(DEFAULT_TIMEOUT_MS);
-        let constants = kb
-            .read()
-            .expect("cannot acquire KB read lock")
-            .get_registered_constants()
-            .clone();
+        let (constants, limits, heartbeat_interval, occurs_check, batched_methods) = {
+            let kb = kb.read().expect("cannot acquire KB read lock");
+            (
+                kb.get_registered_constants().clone(),
+                kb.limits(),
+                kb.heartbeat_interval(),
+                kb.occurs_check(),
+                kb.batched_methods().clone(),
+            )
+        };
 
         let mut vm = Self {
             goals: GoalStack::new_reversed(goals),
             binding_manager: BindingManager::new(),
             query_start_time: None,
             query_timeout_ms,
-            stack_limit: MAX_STACK_SIZE,
+            stack_limit: limits.max_stack_size,
+            rule_depth: 0,
+            max_rule_depth: limits.max_rule_depth,
+            heartbeat_interval,
+            goals_since_event: 0,
+            occurs_check,
             csp: Bsp::default(),
             choices: vec![],
             queries: vec![],
             tracing,
             trace_stack: vec![],
             trace: vec![],
+            counterexamples: Rc::new(RefCell::new(vec![])),
+            choice_profiler: Rc::default(),
             external_error: None,
             debugger: Debugger::default(),
             kb,
+            scope: None,
+            slice: None,
+            precomputed: None,
+            error_backtrace: None,
+            goal_budget: None,
+            goals_run: Rc::new(Cell::new(0)),
+            rule_quotas: Arc::default(),
+            rule_usage: Rc::default(),
+            rule_frames: vec![],
             call_id_symbols: HashMap::new(),
+            batched_methods,
+            batch_siblings: HashMap::new(),
+            batch_results: HashMap::new(),
+            pending_batches: HashMap::new(),
             // `log` controls internal VM logging
             log_level: None,
             // `polar_log_stderr` prints things immediately to stderr
@@ -412,14 +692,206 @@ impl PolarVirtualMachine {
     }
 
     /// Clone self, replacing the goal stack and retaining only the current bindings.
-    pub fn clone_with_goals(&self, goals: Goals) -> Self {
+    pub fn clone_with_goals(&mut self, goals: Goals) -> Self {
         let mut vm = Self::new(self.kb.clone(), self.tracing, goals, self.messages.clone());
-        vm.binding_manager.clone_from(&self.binding_manager);
+        vm.binding_manager = self.binding_manager.snapshot();
         vm.query_contains_partial = self.query_contains_partial;
         vm.debugger = self.debugger.clone();
+        vm.counterexamples = self.counterexamples.clone();
+        vm.choice_profiler = self.choice_profiler.clone();
+        vm.scope.clone_from(&self.scope);
+        vm.slice.clone_from(&self.slice);
+        vm.precomputed.clone_from(&self.precomputed);
+        vm.query_timeout_ms = self.query_timeout_ms;
+        vm.goal_budget = self.goal_budget;
+        vm.goals_run = self.goals_run.clone();
+        vm.rule_quotas = self.rule_quotas.clone();
+        vm.rule_usage = self.rule_usage.clone();
+        vm.rule_frames.clone_from(&self.rule_frames);
+        vm.rule_depth = self.rule_depth;
+        vm.heartbeat_interval = self.heartbeat_interval;
+        vm.occurs_check = self.occurs_check;
+        vm
+    }
+
+    /// Query the rules of `scope` along with the rules loaded without a scope.
+    /// The query is limited to the number of goals, and the calls of individual rules, allowed
+    /// by the quota of `scope`.
+    pub fn set_scope(&mut self, scope: Option<String>) {
+        let quota = scope
+            .as_ref()
+            .map(|scope| self.kb().scope_quota(scope).clone())
+            .unwrap_or_default();
+        self.goal_budget = quota.max_query_goals;
+        self.rule_quotas = Arc::new(quota.rules);
+        self.scope = scope;
+    }
+
+    pub fn scope(&self) -> Option<&str> {
+        self.scope.as_deref()
+    }
+
+    /// The state of the query, to continue it with `resume`, e.g., in another process. Fails if
+    /// the query is inverting a negation, has goals that can't be serialized, or has pending
+    /// goals or bindings with sensitive terms or terms from sandboxed sources, which would be
+    /// redacted or sandboxed no longer once resumed.
+    pub fn suspend(&self) -> PolarResult<VmState> {
+        let unsuspendable = |msg: &str| {
+            Err(RuntimeError::Suspension {
+                msg: msg.to_owned(),
+            }
+            .into())
+        };
+        if self.inverting {
+            return unsuspendable("the query is evaluating a negation");
+        }
+        let mut goals = self
+            .goals
+            .iter()
+            .chain(self.choices.iter().flat_map(|choice| {
+                choice
+                    .goals
+                    .iter()
+                    .chain(choice.alternatives.iter().flat_map(|goals| goals.iter()))
+            }));
+        if !goals.all(|goal| goal.is_suspendable()) {
+            return unsuspendable("the query has a pending error or subquery");
+        }
+
+        // Constants are bound again from the KB when the query is resumed, so only the other
+        // bindings count.
+        let mut unsuspendable_terms = Unsuspendable::default();
+        let goals = self
+            .goals
+            .iter()
+            .chain(self.choices.iter().flat_map(|choice| {
+                choice
+                    .goals
+                    .iter()
+                    .chain(choice.alternatives.iter().flat_map(|goals| goals.iter()))
+            }));
+        for goal in goals {
+            goal.visit(&mut unsuspendable_terms);
+        }
+        let queries = self
+            .queries
+            .iter()
+            .chain(self.choices.iter().flat_map(|choice| choice.queries.iter()));
+        let bindings = self.binding_manager.bindings_after(true, &self.csp);
+        for term in queries.chain(bindings.values()) {
+            unsuspendable_terms.visit_term(term);
+        }
+        for trace in &self.trace {
+            visit_trace(&mut unsuspendable_terms, trace);
+        }
+        if unsuspendable_terms.0 {
+            return unsuspendable(
+                "the query has pending sensitive terms or terms from a sandboxed source",
+            );
+        }
+        Ok(VmState {
+            goals: self.goals.clone(),
+            bindings: self.binding_manager.suspend()?,
+            choices: self.choices.clone(),
+            queries: self.queries.clone(),
+            tracing: self.tracing,
+            trace_stack: self.trace_stack.clone(),
+            trace: self.trace.clone(),
+            counterexamples: self.counterexamples.borrow().clone(),
+            external_error: self.external_error.clone(),
+            rule_depth: self.rule_depth,
+            heartbeat_interval: self.heartbeat_interval,
+            occurs_check: self.occurs_check,
+            csp: self.csp.clone(),
+            scope: self.scope.clone(),
+            goal_budget: self.goal_budget,
+            goals_run: self.goals_run.get(),
+            rule_quotas: self.rule_quotas.as_ref().clone(),
+            rule_usage: self.rule_usage.borrow().clone(),
+            rule_frames: self.rule_frames.clone(),
+            call_id_symbols: self.call_id_symbols.clone(),
+            batch_siblings: self.batch_siblings.clone(),
+            batch_results: self
+                .batch_results
+                .iter()
+                .map(|(call, result)| (call.clone(), result.clone()))
+                .collect(),
+            pending_batches: self.pending_batches.clone(),
+            query_contains_partial: self.query_contains_partial,
+        })
+    }
+
+    /// A VM that continues the query suspended in `state` against `kb`. Its timeout starts
+    /// over, and its choice statistics only count what it does after resuming.
+    pub fn resume(kb: Arc<RwLock<KnowledgeBase>>, state: VmState, messages: MessageQueue) -> Self {
+        let constants = kb.read().unwrap().get_registered_constants().clone();
+        let mut vm = Self::new(kb, state.tracing, vec![], messages);
+        vm.goals = state.goals;
+        // Bind constants to the KB's terms for them, which may be sensitive, rather than to
+        // their deserialized values.
+        let bindings = state
+            .bindings
+            .into_iter()
+            .map(|Binding(var, value)| match constants.get(&var) {
+                Some(constant) if *constant == value => Binding(var, constant.clone()),
+                _ => Binding(var, value),
+            })
+            .collect();
+        vm.binding_manager = BindingManager::from_bindings(bindings);
+        vm.choices = state.choices;
+        vm.queries = state.queries;
+        vm.trace_stack = state.trace_stack;
+        vm.trace = state.trace;
+        vm.counterexamples = Rc::new(RefCell::new(state.counterexamples));
+        vm.external_error = state.external_error;
+        vm.rule_depth = state.rule_depth;
+        vm.heartbeat_interval = state.heartbeat_interval;
+        vm.occurs_check = state.occurs_check;
+        vm.csp = state.csp;
+        vm.scope = state.scope;
+        vm.goal_budget = state.goal_budget;
+        vm.goals_run = Rc::new(Cell::new(state.goals_run));
+        vm.rule_quotas = Arc::new(state.rule_quotas);
+        vm.rule_usage = Rc::new(RefCell::new(state.rule_usage));
+        vm.rule_frames = state.rule_frames;
+        vm.call_id_symbols = state.call_id_symbols;
+        vm.batch_siblings = state.batch_siblings;
+        vm.batch_results = state.batch_results.into_iter().collect();
+        vm.pending_batches = state.pending_batches;
+        vm.query_contains_partial = state.query_contains_partial;
         vm
     }
 
+    /// Look up rules in `slice` rather than in all of the KB. Ignored by queries with a
+    /// scope, whose rules may call rules outside the slice.
+    pub fn set_slice(&mut self, slice: Option<Arc<RuleSlice>>) {
+        self.slice = slice;
+    }
+
+    /// Fail the query once it has run for `timeout_ms` milliseconds, or never if `timeout_ms`
+    /// is 0. Overrides the `POLAR_TIMEOUT_MS` environment variable.
+    pub fn set_query_timeout(&mut self, timeout_ms: u64) {
+        self.query_timeout_ms = timeout_ms;
+    }
+
+    /// Answer calls from `precomputed` rather than from the rules & facts of the KB.
+    pub fn set_precomputed(&mut self, precomputed: Option<Arc<Precomputed>>) {
+        self.precomputed = precomputed;
+    }
+
+    /// The rule `name`, from the query's slice if it has one.
+    fn generic_rule<'kb>(
+        &'kb self,
+        kb: &'kb KnowledgeBase,
+        name: &Symbol,
+    ) -> Option<&'kb GenericRule> {
+        self.slice
+            .as_ref()
+            .filter(|_| self.scope.is_none())
+            .and_then(|slice| slice.get_generic_rule(name))
+            .or_else(|| kb.get_generic_rule(name))
+    }
+
     #[cfg(test)]
     fn set_stack_limit(&mut self, limit: usize) {
         self.stack_limit = limit;
@@ -456,12 +928,22 @@ impl PolarVirtualMachine {
             .expect("unregistered external call ID")
     }
 
+    /// The symbol of a call the application answered, or an error if the query isn't
+    /// waiting on the call.
+    fn pending_call_sym(&self, call_id: u64) -> PolarResult<&Symbol> {
+        self.call_id_symbols.get(&call_id).ok_or_else(|| {
+            RuntimeError::unknown_call_id(call_id, self.id_counter().generation()).into()
+        })
+    }
+
     /// Try to achieve one goal. Return `Some(QueryEvent)` if an external
     /// result is needed to achieve it, or `None` if it can run internally.
     fn next(&mut self, goal: Rc<Goal>) -> PolarResult<QueryEvent> {
         self.log(LogLevel::Trace, || goal.to_string(), &[]);
 
         self.check_timeout()?;
+        self.check_goal_budget()?;
+        self.check_rule_goals()?;
 
         match goal.as_ref() {
             Goal::Backtrack => self.backtrack()?,
@@ -486,6 +968,7 @@ impl PolarVirtualMachine {
                 field,
             } => return self.lookup_external(*call_id, instance, field),
             Goal::IsaExternal { instance, literal } => return self.isa_external(instance, literal),
+            Goal::IsaPredicate { instance, name } => return self.isa_predicate(instance, name),
             Goal::MakeExternal {
                 constructor,
                 instance_id,
@@ -493,6 +976,11 @@ impl PolarVirtualMachine {
             Goal::NextExternal { call_id, iterable } => {
                 return self.next_external(*call_id, iterable)
             }
+            Goal::NextFact {
+                call_id,
+                name,
+                args,
+            } => return self.next_fact(*call_id, name, args),
             Goal::CheckError => return self.check_error(),
             Goal::Noop => {}
             Goal::Query { term } => {
@@ -512,6 +1000,15 @@ impl PolarVirtualMachine {
                 inner,
                 args,
             } => self.sort_rules(rules, args, *outer, *inner)?,
+            Goal::SafeLookup {
+                value,
+                fields,
+                default,
+                result,
+            } => self.safe_lookup(value, fields, default.as_ref(), result)?,
+            Goal::RecordCounterexample { forall, variables } => {
+                self.record_counterexample(forall, variables)
+            }
             Goal::TraceStackPush => {
                 self.trace_stack.push(Rc::new(self.trace.clone()));
                 self.trace = vec![];
@@ -525,9 +1022,25 @@ impl PolarVirtualMachine {
                 self.trace.push(Rc::new(trace.clone()));
                 self.maybe_break(DebugEvent::Pop)?;
             }
+            Goal::PopRule => {
+                self.rule_depth -= 1;
+                if matches!(self.rule_frames.last(), Some((_, depth)) if *depth == self.rule_depth)
+                {
+                    self.rule_frames.pop();
+                }
+            }
             Goal::TraceRule { trace } => {
                 if let Node::Rule(rule) = &trace.node {
                     self.log(LogLevel::Info, || format!("RULE: {}", rule), &[]);
+                    if self.rule_depth >= self.max_rule_depth {
+                        return Err(RuntimeError::RuleDepthExceeded {
+                            limit: self.max_rule_depth,
+                            term: None,
+                        }
+                        .into());
+                    }
+                    self.enter_rule(&rule.name)?;
+                    self.rule_depth += 1;
                 }
                 self.trace.push(trace.clone());
                 self.maybe_break(DebugEvent::Rule)?;
@@ -550,8 +1063,8 @@ impl PolarVirtualMachine {
         use {Goal::*, VariableState::Unbound};
         if self.goals.len() >= self.stack_limit {
             let msg = format!("Goal stack overflow! MAX_GOALS = {}", self.stack_limit);
-            Err(RuntimeError::StackOverflow { msg }.into())
-        } else if matches!(goal, LookupExternal { call_id, ..} | NextExternal { call_id, .. } if self.variable_state(self.get_call_sym(call_id)) != Unbound)
+            Err(RuntimeError::StackOverflow { msg, term: None }.into())
+        } else if matches!(goal, LookupExternal { call_id, ..} | NextExternal { call_id, .. } | NextFact { call_id, .. } if self.variable_state(self.get_call_sym(call_id)) != Unbound)
         {
             invalid_state("The call_id result variables for LookupExternal and NextExternal goals must be unbound.")
         } else {
@@ -583,7 +1096,7 @@ impl PolarVirtualMachine {
             .collect();
         if self.choices.len() >= self.stack_limit {
             let msg = "Too many choices.".to_owned();
-            Err(RuntimeError::StackOverflow { msg }.into())
+            Err(RuntimeError::StackOverflow { msg, term: None }.into())
         } else {
             self.choices.push(Choice {
                 alternatives,
@@ -592,6 +1105,10 @@ impl PolarVirtualMachine {
                 queries: self.queries.clone(),
                 trace: self.trace.clone(),
                 trace_stack: self.trace_stack.clone(),
+                rule_depth: self.rule_depth,
+                rule_frames: self.rule_frames.clone(),
+                fallback: None,
+                rules: None,
             });
             Ok(())
         }
@@ -692,7 +1209,19 @@ impl PolarVirtualMachine {
             || format!("⇒ add_constraint: {}", term),
             &[],
         );
-        self.binding_manager.add_constraint(term)
+        // A constraint made while querying a policy's expression comes from that expression,
+        // so that conditions built from partial results can be traced back to the policy.
+        let source = self
+            .queries
+            .iter()
+            .rev()
+            .find(|query| query.parsed_context().is_some());
+        match source {
+            Some(query) if term.parsed_context().is_none() => self
+                .binding_manager
+                .add_constraint(&query.clone_with_value(term.value().clone())),
+            _ => self.binding_manager.add_constraint(term),
+        }
     }
 
     /// Augment the bindings stack with constants from a hash map.
@@ -712,10 +1241,19 @@ impl PolarVirtualMachine {
     }
 
     /// Retrieve internal binding stack for debugger.
-    pub fn bindings_debug(&self) -> &BindingStack {
+    pub fn bindings_debug(&self) -> BindingStack {
         self.binding_manager.bindings_debug()
     }
 
+    /// Counts of the binding operations of this VM and the VMs it spawned.
+    pub fn binding_stats(&self) -> BindingStats {
+        self.binding_manager.stats()
+    }
+
+    pub fn choice_stats(&self) -> ChoiceStats {
+        self.choice_profiler.borrow().stats()
+    }
+
     /// Returns bindings for all vars used by terms in terms.
     pub fn relevant_bindings(&self, terms: &[&Term]) -> Bindings {
         let mut variables = HashSet::new();
@@ -799,12 +1337,14 @@ impl PolarVirtualMachine {
                     // print BINDINGS: { .. } only for TRACE logs
                     if !terms.is_empty() && configured_log_level == LogLevel::Trace {
                         let relevant_bindings = self.relevant_bindings(terms);
+                        let kb = self.kb();
+                        let formatter = kb.term_formatter();
                         write!(
                             msg,
                             ", BINDINGS: {{{}}}",
                             relevant_bindings
                                 .iter()
-                                .map(|(var, val)| format!("{} => {}", var.0, val))
+                                .map(|(var, val)| format!("{} => {}", var, formatter.format(val)))
                                 .collect::<Vec<String>>()
                                 .join(", ")
                         )
@@ -820,12 +1360,12 @@ impl PolarVirtualMachine {
     }
 
     /// Get the query stack as a string for printing in error messages.
-    pub(crate) fn stack_trace(&self) -> String {
+    /// Build a linear stack from the trace tree, outermost first. Not just using the query
+    /// stack because it doesn't know about rules; the query stack should really use this too.
+    fn trace_path(&self) -> Vec<Rc<Trace>> {
         let mut trace_stack = self.trace_stack.clone();
         let mut trace = self.trace.clone();
 
-        // Build linear stack from trace tree. Not just using query stack because it doesn't
-        // know about rules, query stack should really use this too.
         let mut stack = vec![];
         while let Some(t) = trace.last() {
             stack.push(t.clone());
@@ -836,6 +1376,31 @@ impl PolarVirtualMachine {
         }
 
         stack.reverse();
+        stack
+    }
+
+    /// The rule calls in progress, outermost first, with the current values of their arguments.
+    fn backtrace(&self) -> Backtrace {
+        let mut frames = vec![];
+        let mut call = None;
+        for t in self.trace_path() {
+            match &t.node {
+                Node::Term(term) => call = Some(term.clone()),
+                Node::Rule(rule) => {
+                    if let Some(call) = call.take() {
+                        frames.push(BacktraceFrame {
+                            rule: rule.clone(),
+                            call: self.deref(&call),
+                        });
+                    }
+                }
+            }
+        }
+        Backtrace { frames }
+    }
+
+    pub(crate) fn stack_trace(&self) -> String {
+        let stack = self.trace_path();
 
         // Only index queries, not rules. Rule nodes are just used as context for where the query
         // comes from.
@@ -898,7 +1463,87 @@ impl PolarVirtualMachine {
         let elapsed = self.query_duration();
         let timeout = self.query_timeout_ms;
         if elapsed > timeout {
-            return Err(RuntimeError::QueryTimeout { elapsed, timeout }.into());
+            return Err(RuntimeError::QueryTimeout {
+                elapsed,
+                timeout,
+                term: None,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Point an error without context at the innermost query being run that was parsed from a
+    /// policy, so that errors raised outside of any particular term still have a location.
+    fn add_error_context(&self, error: PolarError) -> PolarError {
+        match self
+            .queries
+            .iter()
+            .rev()
+            .find(|term| term.parsed_context().is_some())
+        {
+            Some(term) => error.with_context_term(term),
+            None => error,
+        }
+    }
+
+    fn check_goal_budget(&self) -> PolarResult<()> {
+        let goals_run = self.goals_run.get() + 1;
+        self.goals_run.set(goals_run);
+        match (self.goal_budget, &self.scope) {
+            (Some(limit), Some(scope)) if goals_run > limit => Err(RuntimeError::QuotaExceeded {
+                scope: scope.clone(),
+                quota: Quota::QueryGoals,
+                limit,
+                term: None,
+            }
+            .into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn rule_quota_exceeded(&self, quota: Quota, limit: u64) -> PolarError {
+        RuntimeError::QuotaExceeded {
+            scope: self.scope.clone().unwrap_or_default(),
+            quota,
+            limit,
+            term: None,
+        }
+        .into()
+    }
+
+    /// Count a call of the rule `name` against its quota, if it has one.
+    fn enter_rule(&mut self, name: &Symbol) -> PolarResult<()> {
+        let quota = match self.rule_quotas.get(name) {
+            Some(quota) => quota,
+            None => return Ok(()),
+        };
+        let mut usage = self.rule_usage.borrow_mut();
+        let (calls, _) = usage.entry(name.clone()).or_default();
+        *calls += 1;
+        if let Some(limit) = quota.max_calls.filter(|limit| *calls > *limit) {
+            return Err(self.rule_quota_exceeded(Quota::RuleCalls(name.clone()), limit));
+        }
+        self.rule_frames.push((name.clone(), self.rule_depth));
+        Ok(())
+    }
+
+    /// Count a goal against the quota of each rule with a call in progress.
+    fn check_rule_goals(&self) -> PolarResult<()> {
+        let mut usage = self.rule_usage.borrow_mut();
+        for (i, (name, _)) in self.rule_frames.iter().enumerate() {
+            // Goals in recursive calls only count once.
+            if self.rule_frames[..i].iter().any(|(outer, _)| outer == name) {
+                continue;
+            }
+            let (_, goals) = usage.entry(name.clone()).or_default();
+            *goals += 1;
+            if let Some(limit) = self.rule_quotas[name]
+                .max_goals
+                .filter(|limit| *goals > *limit)
+            {
+                return Err(self.rule_quota_exceeded(Quota::RuleGoals(name.clone()), limit));
+            }
         }
         Ok(())
     }
@@ -921,9 +1566,22 @@ impl PolarVirtualMachine {
                     queries,
                     trace,
                     trace_stack,
+                    rule_depth,
+                    rule_frames,
+                    fallback,
+                    rules,
                 }) => {
                     self.binding_manager.backtrack(&bsp);
+                    self.rule_depth = rule_depth;
+                    self.rule_frames.clone_from(&rule_frames);
                     if let Some(mut alternative) = alternatives.pop() {
+                        if let Some(rules) = &rules {
+                            // Leave the clause before the one that's tried next.
+                            let next = rules.len() - alternatives.len() - 1;
+                            let mut profiler = self.choice_profiler.borrow_mut();
+                            profiler.backtracked(&rules[next - 1]);
+                            profiler.tried(&rules[next]);
+                        }
                         if alternatives.is_empty() {
                             self.goals = goals;
                             self.queries = queries;
@@ -941,6 +1599,10 @@ impl PolarVirtualMachine {
                                 queries,
                                 trace,
                                 trace_stack,
+                                rule_depth,
+                                rule_frames,
+                                fallback,
+                                rules,
                             })
                         }
                         self.goals.append(&mut alternative);
@@ -954,6 +1616,11 @@ impl PolarVirtualMachine {
 
     /// Commit to the current choice.
     fn cut(&mut self, index: usize) {
+        let pruned = self.choices[index.min(self.choices.len())..]
+            .iter()
+            .filter(|choice| choice.rules.is_some() && !choice.alternatives.is_empty())
+            .count();
+        self.choice_profiler.borrow_mut().pruned(pruned);
         self.choices.truncate(index);
     }
 
@@ -989,6 +1656,7 @@ impl PolarVirtualMachine {
             &[left, right],
         );
 
+        let ground_match = match_ground_pattern(left, right);
         match (left.value(), right.value()) {
             (_, Value::Dictionary(_)) => todo!("make this case unreachable"),
             (Value::Expression(_), _) | (_, Value::Expression(_)) => {
@@ -1052,6 +1720,15 @@ impl PolarVirtualMachine {
                 })?;
             }
 
+            (Value::Dictionary(_), Value::Pattern(Pattern::Dictionary(_)))
+                if ground_match.is_some() =>
+            {
+                // Ground dictionaries match in one step, however deeply the pattern is nested.
+                if ground_match == Some(false) {
+                    self.push_goal(Goal::Backtrack)?;
+                }
+            }
+
             (Value::Dictionary(left), Value::Pattern(Pattern::Dictionary(right))) => {
                 // Check that the left is more specific than the right.
                 let left_fields: HashSet<&Symbol> = left.fields.keys().collect();
@@ -1087,7 +1764,7 @@ impl PolarVirtualMachine {
                     let lookup = Goal::LookupExternal {
                         instance: left.clone(),
                         call_id,
-                        field: right_value.clone_with_value(Value::String(field.0.clone())),
+                        field: right_value.clone_with_value(Value::String(field.to_string())),
                     };
                     let isa = Goal::Isa {
                         left: Term::from(answer),
@@ -1097,6 +1774,20 @@ impl PolarVirtualMachine {
                 }
             }
 
+            (_, Value::Pattern(Pattern::Predicate(name))) => {
+                if !self.kb.read().unwrap().is_specializer_predicate(name) {
+                    return Err(RuntimeError::UnregisteredSpecializer {
+                        name: name.clone(),
+                        term: right.clone(),
+                    }
+                    .into());
+                }
+                self.push_goal(Goal::IsaPredicate {
+                    instance: left.clone(),
+                    name: name.clone(),
+                })?;
+            }
+
             (_, Value::Pattern(Pattern::Instance(right_literal))) => {
                 // Check fields
                 self.push_goal(Goal::Isa {
@@ -1182,7 +1873,7 @@ impl PolarVirtualMachine {
                 // Produce a constraint like left.field = value
                 let to_unify = |(field, value): (&Symbol, &Term)| -> Term {
                     let value = self.deref(value);
-                    let field = right.clone_with_value(value!(field.0.as_ref()));
+                    let field = right.clone_with_value(value!(field.as_str()));
                     let left = left.clone_with_value(value!(op!(Dot, left.clone(), field)));
                     term!(op!(Unify, left, value))
                 };
@@ -1255,7 +1946,7 @@ impl PolarVirtualMachine {
                 // Construct field constraints.
                 let field_constraints = fields.fields.iter().rev().map(|(f, v)| {
                     let v = self.deref(v);
-                    let field = right.clone_with_value(value!(f.0.as_ref()));
+                    let field = right.clone_with_value(value!(f.as_str()));
                     let left = left.clone_with_value(value!(op!(Dot, left.clone(), field)));
                     op!(Unify, left, v)
                 });
@@ -1273,6 +1964,12 @@ impl PolarVirtualMachine {
                     vec![Goal::CheckError, Goal::Backtrack],
                 )?;
             }
+            Value::Pattern(Pattern::Predicate(name)) => {
+                return unsupported(
+                    format!("cannot partially evaluate a match against @{}", name),
+                    right,
+                );
+            }
             // if the RHS isn't a pattern or a dictionary, we'll fall back to unifying
             // this is not the _best_ behaviour, but it's what we've been doing
             // previously
@@ -1292,7 +1989,7 @@ impl PolarVirtualMachine {
             let members = kb.get_union_members(union).iter();
             members
                 .map(|member| {
-                    let tag = member.as_symbol().unwrap().0.as_str();
+                    let tag = member.as_symbol().unwrap().as_str();
                     member.clone_with_value(value!(pattern!(instance!(tag))))
                 })
                 .map(|pattern| {
@@ -1317,7 +2014,7 @@ impl PolarVirtualMachine {
                     // if `field` is bound, unification will only succeed for the matching key
                     // if `field` is unbound, unification will succeed for all keys
                     goals.push(Goal::Unify {
-                        left: field.clone_with_value(Value::String(k.clone().0)),
+                        left: field.clone_with_value(Value::String(k.to_string())),
                         right: field.clone(),
                     });
                     // attempt to unify dict value with result
@@ -1330,7 +2027,7 @@ impl PolarVirtualMachine {
                 self.choose(alternatives)
             }
             Value::String(field) => {
-                if let Some(retrieved) = dict.fields.get(&Symbol(field.clone())) {
+                if let Some(retrieved) = dict.fields.get(&Symbol::new(field)) {
                     self.push_goal(Goal::Unify {
                         left: retrieved.clone(),
                         right: value.clone(),
@@ -1370,7 +2067,7 @@ impl PolarVirtualMachine {
                         .collect()
                 }),
             ),
-            Value::String(field) => (Symbol(field.clone()), None, None),
+            Value::String(field) => (Symbol::new(field), None, None),
             v => {
                 return self.type_error(
                     field,
@@ -1379,6 +2076,9 @@ impl PolarVirtualMachine {
             }
         };
 
+        let context = field.parsed_context().or_else(|| instance.parsed_context());
+        self.check_sandbox(context, &self.deref(instance), &field_name, field)?;
+
         // add an empty choice point; lookups return only one value
         // but we'll want to cut if we get back nothing
         self.push_choice(vec![])?;
@@ -1405,6 +2105,12 @@ impl PolarVirtualMachine {
             &[],
         );
 
+        if let (Some(args), None) = (&args, &kwargs) {
+            if let Some(event) = self.batch_call(call_id, instance, &field_name, args)? {
+                return Ok(event);
+            }
+        }
+
         Ok(QueryEvent::ExternalCall {
             call_id,
             instance: self.deref(instance),
@@ -1414,6 +2120,139 @@ impl PolarVirtualMachine {
         })
     }
 
+    /// Return the event for a call of a batched method on an instance of a list that the query
+    /// iterates: none if an earlier batch already answered it, or else a batch of the call on
+    /// every instance of the list. Return `None` for calls that aren't batched.
+    fn batch_call(
+        &mut self,
+        call_id: u64,
+        instance: &Term,
+        name: &Symbol,
+        args: &[Term],
+    ) -> PolarResult<Option<QueryEvent>> {
+        if !self.batched_methods.contains(name) {
+            return Ok(None);
+        }
+        let instance_id = match self.deref(instance).value() {
+            Value::ExternalInstance(ExternalInstance { instance_id, .. }) => *instance_id,
+            _ => return Ok(None),
+        };
+        let call = (instance_id, name.clone(), args.to_vec());
+        if let Some(result) = self.batch_results.get(&call).cloned() {
+            self.external_call_result(call_id, result)?;
+            return Ok(Some(QueryEvent::None));
+        }
+        let instances = match self.batch_siblings.get(&instance_id) {
+            Some(instances) => instances.clone(),
+            None => return Ok(None),
+        };
+
+        let calls = instances
+            .iter()
+            .map(|instance| match instance.value() {
+                Value::ExternalInstance(ExternalInstance { instance_id, .. }) => {
+                    (*instance_id, name.clone(), args.to_vec())
+                }
+                _ => unreachable!("batch siblings are external instances"),
+            })
+            .collect::<Vec<_>>();
+        let index = calls
+            .iter()
+            .position(|sibling| sibling == &call)
+            .expect("an instance is one of its batch siblings");
+        self.pending_batches.insert(call_id, (calls, index));
+        Ok(Some(QueryEvent::ExternalCallBatch {
+            call_id,
+            instances: instances.to_vec(),
+            attribute: name.clone(),
+            args: args.to_vec(),
+        }))
+    }
+
+    /// Remember the instances of a list that the query iterates, so that calls of batched
+    /// methods on each of them are made on all of them at once.
+    fn note_batch_siblings(&mut self, terms: &[Term]) {
+        if self.batched_methods.is_empty() {
+            return;
+        }
+        let mut ids = HashSet::new();
+        let instances = terms
+            .iter()
+            .map(|term| self.deref(term))
+            .filter(|term| match term.value() {
+                Value::ExternalInstance(ExternalInstance { instance_id, .. }) => {
+                    ids.insert(*instance_id)
+                }
+                _ => false,
+            })
+            .collect::<TermList>();
+        if instances.len() < 2 {
+            return;
+        }
+        let instances = Rc::new(instances);
+        for id in ids {
+            self.batch_siblings.insert(id, instances.clone());
+        }
+    }
+
+    /// Return an error if `context` is in a sandboxed source whose sandbox doesn't allow `name`
+    /// to be used on `instance`.
+    fn check_sandbox(
+        &self,
+        context: Option<&Context>,
+        instance: &Term,
+        name: &Symbol,
+        term: &Term,
+    ) -> PolarResult<()> {
+        let sandbox = match context.and_then(|context| context.source.sandbox.as_ref()) {
+            Some(sandbox) => sandbox,
+            None => return Ok(()),
+        };
+        let class = self.class_name(instance);
+        if sandbox.allows(class.as_deref(), name) {
+            Ok(())
+        } else {
+            Err(RuntimeError::SandboxViolation {
+                class: class.unwrap_or_else(|| "UNKNOWN".to_owned()),
+                name: name.to_string(),
+                term: term.clone(),
+            }
+            .into())
+        }
+    }
+
+    /// The name of `instance`'s class, as the policy refers to it. Registered classes and
+    /// other registered constants are named by the name they were registered under.
+    fn class_name(&self, instance: &Term) -> Option<String> {
+        let name = match instance.value() {
+            Value::ExternalInstance(ExternalInstance {
+                instance_id,
+                class_id,
+                class_repr,
+                ..
+            }) => {
+                let kb = self.kb();
+                if let Some(class) = class_id.and_then(|id| kb.get_symbol_for_class_id(&id)) {
+                    return Some(class.to_string());
+                }
+                let constant = kb.get_registered_constants().iter().find(|(_, term)| {
+                    matches!(term.value(), Value::ExternalInstance(e) if e.instance_id == *instance_id)
+                });
+                return constant
+                    .map(|(name, _)| name.to_string())
+                    .or_else(|| class_repr.clone());
+            }
+            Value::Boolean(_) => "Boolean",
+            Value::Dictionary(_) => "Dictionary",
+            Value::List(_) => "List",
+            Value::Number(Numeric::Integer(_)) => "Integer",
+            Value::Number(Numeric::Float(_)) => "Float",
+            Value::String(_) => "String",
+            _ => return None,
+        };
+        Some(name.to_owned())
+    }
+
     fn isa_external(
         &mut self,
         instance: &Term,
@@ -1432,6 +2271,21 @@ impl PolarVirtualMachine {
         })
     }
 
+    /// Ask the host whether `instance` satisfies the specializer predicate `name`.
+    fn isa_predicate(&mut self, instance: &Term, name: &Symbol) -> PolarResult<QueryEvent> {
+        let (call_id, answer) = self.new_call_var("isa", false.into());
+        self.push_goal(Goal::Unify {
+            left: answer,
+            right: Term::from(true),
+        })?;
+
+        Ok(QueryEvent::ExternalSpecializer {
+            call_id,
+            name: name.clone(),
+            instance: self.deref(instance),
+        })
+    }
+
     fn next_external(&mut self, call_id: u64, iterable: &Term) -> PolarResult<QueryEvent> {
         // add another choice point for the next result
         self.push_choice(vec![vec![Goal::NextExternal {
@@ -1445,6 +2299,26 @@ impl PolarVirtualMachine {
         })
     }
 
+    fn next_fact(
+        &mut self,
+        call_id: u64,
+        name: &Symbol,
+        args: &TermList,
+    ) -> PolarResult<QueryEvent> {
+        // add another choice point for the next answer
+        self.push_choice(vec![vec![Goal::NextFact {
+            call_id,
+            name: name.clone(),
+            args: args.clone(),
+        }]])?;
+
+        Ok(QueryEvent::ExternalFacts {
+            call_id,
+            name: name.clone(),
+            args: args.iter().map(|arg| self.deref(arg)).collect(),
+        })
+    }
+
     fn make_external(&self, constructor: &Term, instance_id: u64) -> QueryEvent {
         QueryEvent::MakeExternal {
             instance_id,
@@ -1454,6 +2328,9 @@ impl PolarVirtualMachine {
 
     fn check_error(&mut self) -> PolarResult<QueryEvent> {
         if let Some(msg) = self.external_error.take() {
+            if self.catch_error(&msg)? {
+                return Ok(QueryEvent::None);
+            }
             let term = match self.trace.last().map(|t| t.node.clone()) {
                 Some(Node::Term(t)) => Some(t),
                 _ => None,
@@ -1470,6 +2347,43 @@ impl PolarVirtualMachine {
         }
     }
 
+    /// Recover from an application error inside the body of a `try` by restoring the state from
+    /// before the body and querying the fallback. Return false if no `try` is in progress.
+    fn catch_error(&mut self, msg: &str) -> PolarResult<bool> {
+        let index = match self.choices.iter().rposition(|c| c.fallback.is_some()) {
+            Some(index) => index,
+            None => return Ok(false),
+        };
+        self.log(
+            LogLevel::Info,
+            || format!("CAUGHT APPLICATION ERROR: {}", msg),
+            &[],
+        );
+        self.choices.truncate(index + 1);
+        let Choice {
+            bsp,
+            goals,
+            queries,
+            trace,
+            trace_stack,
+            rule_depth,
+            rule_frames,
+            fallback,
+            ..
+        } = self.choices.pop().unwrap();
+        self.binding_manager.backtrack(&bsp);
+        self.rule_depth = rule_depth;
+        self.rule_frames = rule_frames;
+        self.goals = goals;
+        self.queries = queries;
+        self.trace = trace;
+        self.trace_stack = trace_stack;
+        self.push_goal(Goal::Query {
+            term: fallback.unwrap(),
+        })?;
+        Ok(true)
+    }
+
     /// Query for the provided term.
     ///
     /// Uses the knowledge base to get an ordered list of rules.
@@ -1507,7 +2421,7 @@ impl PolarVirtualMachine {
 
         match &term.value() {
             Value::Call(predicate) => {
-                self.query_for_predicate(predicate.clone())?;
+                self.query_for_predicate(term, predicate.clone())?;
             }
             Value::Expression(_) => {
                 return self.query_for_operation(term);
@@ -1551,47 +2465,362 @@ impl PolarVirtualMachine {
     /// Select applicable rules for predicate.
     /// Sort applicable rules by specificity.
     /// Create a choice over the applicable rules.
-    fn query_for_predicate(&mut self, predicate: Call) -> PolarResult<()> {
+    fn query_for_predicate(&mut self, term: &Term, predicate: Call) -> PolarResult<()> {
         if predicate.kwargs.is_some() {
             return invalid_state(format!(
                 "query_for_predicate: unexpected kwargs: {}",
                 predicate
             ));
         }
-        let goals = match self.kb.read().unwrap().get_generic_rule(&predicate.name) {
+        if let Some(precomputed) = self.precomputed.clone() {
+            let args: TermList = predicate.args.iter().map(|t| self.deref(t)).collect();
+            if let Some(answers) = precomputed.get(&predicate.name, &args) {
+                let args = Term::from(predicate.args);
+                return self.choose(answers.iter().map(|answer| {
+                    vec![Goal::Unify {
+                        left: args.clone(),
+                        right: Term::from(answer.clone()),
+                    }]
+                }));
+            }
+        }
+        let (has_rules, is_fact_source, facts) = {
+            let kb = self.kb();
+            let args: TermList = predicate.args.iter().map(|t| self.deref(t)).collect();
+            (
+                kb.get_generic_rule(&predicate.name).is_some()
+                    || !self.scoped_rules(&kb, &predicate.name).is_empty(),
+                kb.is_fact_source(&predicate.name),
+                kb.get_facts(&predicate.name, &args),
+            )
+        };
+        let goals = if has_rules {
+            Some(self.query_for_rules(term, &predicate)?)
+        } else if is_fact_source {
+            Some(self.query_for_facts(&predicate))
+        } else if facts.is_some() {
+            None
+        } else if builtins::is_nil_check(&predicate) {
+            return self.query_for_nil_check(&predicate);
+        } else if builtins::is_path_lookup(&predicate) {
+            return self.query_for_path_lookup(term, &predicate);
+        } else if builtins::is_higher_order(&predicate) {
+            return self.query_for_higher_order_builtin(term, &predicate);
+        } else if builtins::is_builtin(&predicate) {
+            return self.query_for_builtin(term, &predicate);
+        } else {
+            return Err(RuntimeError::QueryForUndefinedRule {
+                name: predicate.name.to_string(),
+                term: Some(term.clone()),
+            }
+            .into());
+        };
+
+        match facts {
+            None => self.append_goals(goals.unwrap_or_default()),
+            Some(facts) => {
+                // Try facts asserted at runtime, in insertion order, before any rules.
+                let args = Term::from(predicate.args);
+                let alternatives = facts
+                    .into_iter()
+                    .map(|fact| {
+                        vec![Goal::Unify {
+                            left: args.clone(),
+                            right: Term::from(fact),
+                        }]
+                    })
+                    .chain(goals)
+                    .collect::<Vec<_>>();
+                self.choose(alternatives)
+            }
+        }
+    }
+
+    /// Evaluate a call to a builtin predicate and unify its result with the last argument.
+    fn query_for_builtin(&mut self, term: &Term, predicate: &Call) -> PolarResult<()> {
+        let args: TermList = predicate.args.iter().map(|t| self.deref(t)).collect();
+        let (result, inputs) = args.split_last().unwrap();
+        match builtins::evaluate(term, &predicate.name, inputs) {
+            Ok(Some(value)) => self.push_goal(Goal::Unify {
+                left: value,
+                right: result.clone(),
+            }),
+            Ok(None) => self.push_goal(Goal::Backtrack),
+            Err(msg) => self.type_error(term, msg),
+        }
+    }
+
+    fn query_for_path_lookup(&mut self, term: &Term, predicate: &Call) -> PolarResult<()> {
+        let path = self.deref(&predicate.args[1]);
+        match builtins::path_fields(&path) {
+            Ok(fields) => self.push_goal(Goal::SafeLookup {
+                value: predicate.args[0].clone(),
+                fields,
+                default: Some(predicate.args[2].clone()),
+                result: predicate.args[3].clone(),
+            }),
+            Err(msg) => self.type_error(term, msg),
+        }
+    }
+
+    /// The value of the `nil` constant, if one is registered.
+    fn nil(&self) -> Option<Term> {
+        self.kb()
+            .get_registered_constants()
+            .get(&Symbol::new("nil"))
+            .cloned()
+    }
+
+    /// Look up the first of `fields` on `value`, then the rest on the result. If `value` is
+    /// `nil`, or a dictionary without the field, unify `result` with `default` instead, or fail
+    /// if there is no default.
+    fn safe_lookup(
+        &mut self,
+        value: &Term,
+        fields: &[Term],
+        default: Option<&Term>,
+        result: &Term,
+    ) -> PolarResult<()> {
+        let value = self.deref(value);
+        let (field, rest) = match fields.split_first() {
+            Some(split) => split,
             None => {
-                return Err(RuntimeError::QueryForUndefinedRule {
-                    name: predicate.name.0.clone(),
-                }
-                .into())
+                return self.push_goal(Goal::Unify {
+                    left: value,
+                    right: result.clone(),
+                })
             }
-            Some(generic_rule) => {
-                if generic_rule.name != predicate.name {
-                    return invalid_state(format!(
-                        "query_for_predicate: different rule names: {} != {}",
-                        generic_rule.name, predicate.name
-                    ));
+        };
+        let use_default = match default {
+            Some(default) => vec![Goal::Unify {
+                left: default.clone(),
+                right: result.clone(),
+            }],
+            None => vec![Goal::Backtrack],
+        };
+        let next = Term::from(self.kb().gensym("value"));
+        let lookup = vec![
+            Goal::Query {
+                term: Term::from(op!(Dot, value.clone(), field.clone(), next.clone())),
+            },
+            Goal::SafeLookup {
+                value: next,
+                fields: rest.to_vec(),
+                default: default.cloned(),
+                result: result.clone(),
+            },
+        ];
+        match value.value() {
+            Value::Dictionary(dict) => match field.value() {
+                Value::String(name) if !dict.fields.contains_key(&Symbol::new(name)) => {
+                    self.append_goals(use_default)
                 }
+                _ => self.append_goals(lookup),
+            },
+            _ => self.choose_nil(&value, use_default, lookup),
+        }
+    }
 
-                // Pre-filter rules.
-                let args = predicate.args.iter().map(|t| self.deref(t)).collect();
-                let pre_filter = generic_rule.get_applicable_rules(&args);
+    /// Run `if_nil` if the (dereferenced) `value` is `nil`, and `otherwise` if it isn't. Host
+    /// instances other than `nil` itself, like an empty `Option`, are compared to `nil` by the
+    /// host. Without a registered `nil` constant, nothing is `nil`.
+    fn choose_nil(&mut self, value: &Term, if_nil: Goals, otherwise: Goals) -> PolarResult<()> {
+        match (value.value(), self.nil()) {
+            (Value::ExternalInstance(instance), Some(nil)) => match nil.value() {
+                Value::ExternalInstance(nil) if nil.instance_id == instance.instance_id => {
+                    self.append_goals(if_nil)
+                }
+                _ => self.choose_conditional(
+                    vec![Goal::Query {
+                        term: Term::from(op!(Eq, value.clone(), nil)),
+                    }],
+                    if_nil,
+                    otherwise,
+                ),
+            },
+            (value, Some(nil)) if value == nil.value() => self.append_goals(if_nil),
+            _ => self.append_goals(otherwise),
+        }
+    }
 
-                self.polar_trace_mute = true;
+    /// Query for `is_nil(value)` or `is_defined(value)`. Unbound variables are neither.
+    fn query_for_nil_check(&mut self, predicate: &Call) -> PolarResult<()> {
+        let value = self.deref(&predicate.args[0]);
+        if matches!(value.value(), Value::Variable(_) | Value::RestVariable(_)) {
+            return self.push_goal(Goal::Backtrack);
+        }
+        let (if_nil, otherwise) = if predicate.name == "is_nil" {
+            (vec![], vec![Goal::Backtrack])
+        } else {
+            (vec![Goal::Backtrack], vec![])
+        };
+        self.choose_nil(&value, if_nil, otherwise)
+    }
 
-                // Filter rules by applicability.
-                vec![
-                    Goal::TraceStackPush,
-                    Goal::FilterRules {
-                        applicable_rules: vec![],
-                        unfiltered_rules: pre_filter,
-                        args: predicate.args,
-                    },
-                    Goal::TraceStackPop,
-                ]
+    /// Query for a builtin that applies a lambda to each element of a list. The goal built by
+    /// the builtin is cut after its first success.
+    fn query_for_higher_order_builtin(&mut self, term: &Term, predicate: &Call) -> PolarResult<()> {
+        let args: TermList = predicate.args.iter().map(|t| self.deref(t)).collect();
+        let (list, lambda) = match (args[0].value(), args[1].value()) {
+            (Value::List(list), Value::Lambda(lambda))
+                if !has_rest_var(list) && lambda.params.len() == 1 =>
+            {
+                (list, lambda)
+            }
+            _ => {
+                let msg = format!(
+                    "{} expects a list and a lambda with one parameter, got: {}, {}",
+                    predicate.name, args[0], args[1]
+                );
+                return self.type_error(term, msg);
+            }
+        };
+        let applications = list
+            .iter()
+            .map(|element| self.apply_lambda(lambda, element))
+            .collect();
+        let body = builtins::higher_order_body(
+            term,
+            &predicate.name,
+            list,
+            applications,
+            args.get(2),
+            || self.kb().gensym("rest"),
+        );
+        let choice_index = self.choices.len();
+        self.append_goals(vec![Goal::Query { term: body }, Goal::Cut { choice_index }])
+    }
+
+    /// Apply `lambda` to `arg`. Variables from the enclosing scope that are bound (or
+    /// constrained) are captured; unbound ones are replaced by fresh variables, so that
+    /// applications don't affect one another.
+    fn apply_lambda(&self, lambda: &Lambda, arg: &Term) -> Term {
+        let mut substitutions = HashMap::new();
+        if let Value::Variable(param) = lambda.params[0].value() {
+            substitutions.insert(param.clone(), arg.clone());
+        }
+        let mut variables = HashSet::new();
+        lambda.body.variables(&mut variables);
+        for variable in variables {
+            if !substitutions.contains_key(&variable)
+                && matches!(self.variable_state(&variable), VariableState::Unbound)
+            {
+                let fresh = Term::from(self.kb().gensym(&variable));
+                substitutions.insert(variable, fresh);
+            }
+        }
+        builtins::apply(lambda, substitutions)
+    }
+
+    /// Return goals that filter & run the rules that are applicable to `predicate`.
+    fn query_for_rules(&mut self, term: &Term, predicate: &Call) -> PolarResult<Goals> {
+        let kb = self.kb.read().unwrap();
+        let generic_rules = self
+            .generic_rule(&kb, &predicate.name)
+            .into_iter()
+            .chain(self.scoped_rules(&kb, &predicate.name))
+            .collect::<Vec<_>>();
+        if generic_rules.is_empty() {
+            return Err(RuntimeError::QueryForUndefinedRule {
+                name: predicate.name.to_string(),
+                term: Some(term.clone()),
             }
+            .into());
+        }
+
+        // Pre-filter rules.
+        let args = predicate.args.iter().map(|t| self.deref(t)).collect();
+        let mut pre_filter = vec![];
+        let resolved = predicate.name.split_path().map(|(_, rule)| rule);
+        for generic_rule in generic_rules {
+            if generic_rule.name != predicate.name && Some(generic_rule.name.as_str()) != resolved {
+                return invalid_state(format!(
+                    "query_for_predicate: different rule names: {} != {}",
+                    generic_rule.name, predicate.name
+                ));
+            }
+            pre_filter.extend(generic_rule.get_applicable_rules(&args));
+        }
+        // Skip the rules whose dictionary specializers don't match the arguments.
+        pre_filter.retain(|rule| {
+            rule.params
+                .iter()
+                .zip(args.iter())
+                .all(|(param, arg)| param.may_match(arg))
+        });
+
+        let deprecated = pre_filter
+            .iter()
+            .find_map(|rule| rule.metadata.deprecated.as_ref());
+        if let Some(message) = deprecated {
+            if kb.should_warn_deprecated(&predicate.name, now_ms()) {
+                let warning = ValidationWarning::DeprecatedRule {
+                    rule: predicate.name.clone(),
+                    message: message.clone(),
+                    term: term.clone(),
+                };
+                let warning = PolarWarning::from(warning).to_string();
+                self.messages.push(MessageKind::Warning, warning);
+            }
+        }
+
+        self.polar_trace_mute = true;
+
+        // Filter rules by applicability.
+        Ok(vec![
+            Goal::TraceStackPush,
+            Goal::FilterRules {
+                applicable_rules: vec![],
+                unfiltered_rules: pre_filter,
+                args: predicate.args.clone(),
+            },
+            Goal::TraceStackPop,
+        ])
+    }
+
+    /// The rules `name` in this query's scope and the scopes it's nested in or includes, if
+    /// any. If there are none, and no rules `name` were loaded without a scope, a path like
+    /// `a::b::f` names the rules `f` in the scope `a::b` and the scopes that one encloses.
+    fn scoped_rules<'kb>(&self, kb: &'kb KnowledgeBase, name: &Symbol) -> Vec<&'kb GenericRule> {
+        fn rules<'kb>(kb: &'kb KnowledgeBase, scope: &str, name: &Symbol) -> Vec<&'kb GenericRule> {
+            kb.get_scope(scope)
+                .into_iter()
+                .chain(kb.enclosing_scopes(scope))
+                .filter_map(|scope| scope.get_generic_rule(name))
+                .collect()
+        }
+
+        let scoped = match &self.scope {
+            Some(scope) => rules(kb, scope, name),
+            None => vec![],
         };
-        self.append_goals(goals)
+        match name.split_path() {
+            Some((scope, rule)) if scoped.is_empty() && kb.get_generic_rule(name).is_none() => {
+                rules(kb, scope, &Symbol::new(rule))
+            }
+            _ => scoped,
+        }
+    }
+
+    /// Return goals that ask the host fact source registered for `predicate` for answers, and
+    /// unify each answer with the predicate's arguments.
+    fn query_for_facts(&mut self, predicate: &Call) -> Goals {
+        // Generate symbol for the answer and leave the variable unbound, so that unification
+        // with the result does not fail. Unification of the answer with the result happens in
+        // `fn external_call_result()`.
+        let answer = self.kb().gensym("fact");
+        let call_id = self.new_call_id(&answer);
+        vec![
+            Goal::NextFact {
+                call_id,
+                name: predicate.name.clone(),
+                args: predicate.args.clone(),
+            },
+            Goal::Unify {
+                left: Term::from(predicate.args.clone()),
+                right: Term::from(answer),
+            },
+        ]
     }
 
     fn query_for_operation(&mut self, term: &Term) -> PolarResult<QueryEvent> {
@@ -1616,18 +2845,19 @@ impl PolarVirtualMachine {
                 }
 
                 let term = args.pop().unwrap();
-                let add_constraints = Rc::new(RefCell::new(Bindings::new()));
-                let inverter = Box::new(Inverter::new(
-                    self,
-                    vec![Goal::Query { term }],
-                    add_constraints.clone(),
-                    self.bsp(),
-                ));
-                self.choose_conditional(
-                    vec![Goal::Run { runnable: inverter }],
-                    vec![Goal::AddConstraintsBatch { add_constraints }],
-                    vec![Goal::Backtrack],
-                )?;
+                self.query_for_negation(vec![Goal::Query { term }])?;
+            }
+            Operator::Try => {
+                // Query the body up to its first success, catching application errors.
+                if args.len() != 2 {
+                    return wrong_arity();
+                }
+                let fallback = args.pop().unwrap();
+                let body = args.pop().unwrap();
+                let choice_index = self.choices.len();
+                self.push_choice(vec![])?;
+                self.choices[choice_index].fallback = Some(fallback);
+                self.append_goals(vec![Goal::Query { term: body }, Goal::Cut { choice_index }])?;
             }
             Operator::Assign => {
                 if args.len() != 2 {
@@ -1666,6 +2896,20 @@ impl PolarVirtualMachine {
             Operator::Dot => {
                 return self.query_op_helper(term, Self::dot_op_helper, false, false);
             }
+            Operator::SafeDot => {
+                if args.len() != 3 {
+                    return wrong_arity();
+                }
+                let result = args.pop().unwrap();
+                let field = args.pop().unwrap();
+                let value = args.pop().unwrap();
+                self.push_goal(Goal::SafeLookup {
+                    value,
+                    fields: vec![field],
+                    default: self.nil(),
+                    result,
+                })?;
+            }
 
             Operator::Lt
             | Operator::Gt
@@ -1720,8 +2964,21 @@ impl PolarVirtualMachine {
                 let instance_id = self.new_id();
 
                 let class = &constructor.as_call()?.name;
+                if let Some(sandbox) = constructor
+                    .parsed_context()
+                    .and_then(|context| context.source.sandbox.as_ref())
+                {
+                    if !sandbox.allows(Some(class.as_str()), "new") {
+                        return Err(RuntimeError::SandboxViolation {
+                            class: class.to_string(),
+                            name: "new".to_owned(),
+                            term: constructor,
+                        }
+                        .into());
+                    }
+                }
                 let class_repr = if self.kb().is_constant(class) {
-                    Some(class.0.clone())
+                    Some(class.to_string())
                 } else {
                     None
                 };
@@ -1784,29 +3041,74 @@ impl PolarVirtualMachine {
                 }
                 let action = args.pop().unwrap();
                 let condition = args.pop().unwrap();
-                // For all is implemented as !(condition, !action).
-                let op = Operation {
+                // The quantified variables are those bound by the condition and used by the
+                // action, which leaves out temporaries from rewriting the condition.
+                let (mut bound, mut used) = (HashSet::new(), HashSet::new());
+                condition.variables(&mut bound);
+                action.variables(&mut used);
+                bound.retain(|v| matches!(self.variable_state(v), VariableState::Unbound));
+                let mut variables = bound
+                    .iter()
+                    .filter(|v| used.contains(v))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                if variables.is_empty() {
+                    variables = bound.into_iter().collect();
+                }
+                variables.sort();
+
+                // For all is implemented as !(condition, !action). Each solution of the
+                // negated query is a counterexample.
+                let not_action = term.clone_with_value(Value::Expression(Operation {
                     operator: Operator::Not,
-                    args: vec![term.clone_with_value(Value::Expression(Operation {
-                        operator: Operator::And,
-                        args: vec![
-                            condition,
-                            term.clone_with_value(Value::Expression(Operation {
-                                operator: Operator::Not,
-                                args: vec![action],
-                            })),
-                        ],
-                    }))],
-                };
-                let double_negation = term.clone_with_value(Value::Expression(op));
-                self.push_goal(Goal::Query {
-                    term: double_negation,
-                })?;
+                    args: vec![action],
+                }));
+                self.query_for_negation(vec![
+                    Goal::Query { term: condition },
+                    Goal::Query { term: not_action },
+                    Goal::RecordCounterexample {
+                        forall: term.clone(),
+                        variables,
+                    },
+                ])?;
             }
         }
         Ok(QueryEvent::None)
     }
 
+    /// Succeed if `goals` fail, by running them in an inverter.
+    fn query_for_negation(&mut self, goals: Goals) -> PolarResult<()> {
+        let add_constraints = Rc::new(RefCell::new(Bindings::new()));
+        let bsp = self.bsp();
+        let inverter = Box::new(Inverter::new(self, goals, add_constraints.clone(), bsp));
+        self.choose_conditional(
+            vec![Goal::Run { runnable: inverter }],
+            vec![Goal::AddConstraintsBatch { add_constraints }],
+            vec![Goal::Backtrack],
+        )
+    }
+
+    fn record_counterexample(&mut self, forall: &Term, variables: &[Symbol]) {
+        let bindings = variables
+            .iter()
+            .map(|v| (v.clone(), self.deref(&Term::from(v.clone()))))
+            .collect::<BTreeMap<_, _>>();
+        let counterexample = Counterexample {
+            forall: forall.clone(),
+            bindings,
+        };
+        // The same counterexample may be found more than once, e.g., for duplicate elements.
+        let mut counterexamples = self.counterexamples.borrow_mut();
+        if !counterexamples.contains(&counterexample) {
+            self.log(
+                LogLevel::Info,
+                || format!("FORALL FAILED: {}", counterexample),
+                &[],
+            );
+            counterexamples.push(counterexample);
+        }
+    }
+
     /// Handle variables & constraints as arguments to various operations.
     /// Calls the `eval` method to handle ground terms.
     ///
@@ -1990,15 +3292,19 @@ impl PolarVirtualMachine {
 
         match (left.value(), right.value()) {
             (Value::Number(left), Value::Number(right)) => {
-                if let Some(answer) = match op {
-                    Operator::Add => *left + *right,
-                    Operator::Sub => *left - *right,
-                    Operator::Mul => *left * *right,
-                    Operator::Div => *left / *right,
-                    Operator::Mod => (*left).modulo(*right),
-                    Operator::Rem => *left % *right,
-                    _ => return unsupported(format!("numeric operation {}", op), term),
-                } {
+                if !matches!(
+                    op,
+                    Operator::Add
+                        | Operator::Sub
+                        | Operator::Mul
+                        | Operator::Div
+                        | Operator::Mod
+                        | Operator::Rem
+                ) {
+                    return unsupported(format!("numeric operation {}", op), term);
+                }
+                let overflow = self.kb.read().unwrap().integer_overflow();
+                if let Some(answer) = left.arithmetic(*op, *right, overflow) {
                     self.push_goal(Goal::Unify {
                         left: term.clone_with_value(Value::Number(answer)),
                         right: result.clone(),
@@ -2095,31 +3401,34 @@ impl PolarVirtualMachine {
 
         match iterable.value() {
             // Unify item with each element of the list, skipping non-matching ground terms.
-            Value::List(terms) => self.choose(
-                terms
-                    .iter()
-                    .filter(|term| {
-                        !item_is_ground || !term.is_ground() || term.value() == item.value()
-                    })
-                    .map(|term| match term.value() {
-                        Value::RestVariable(v) => {
-                            let term = op!(In, item.clone(), Term::from(v.clone())).into();
-                            vec![Goal::Query { term }]
-                        }
-                        _ => vec![Goal::Unify {
-                            left: item.clone(),
-                            right: term.clone(),
-                        }],
-                    })
-                    .collect::<Vec<Goals>>(),
-            )?,
+            Value::List(terms) => {
+                self.note_batch_siblings(terms);
+                self.choose(
+                    terms
+                        .iter()
+                        .filter(|term| {
+                            !item_is_ground || !term.is_ground() || term.value() == item.value()
+                        })
+                        .map(|term| match term.value() {
+                            Value::RestVariable(v) => {
+                                let term = op!(In, item.clone(), Term::from(v.clone())).into();
+                                vec![Goal::Query { term }]
+                            }
+                            _ => vec![Goal::Unify {
+                                left: item.clone(),
+                                right: term.clone(),
+                            }],
+                        })
+                        .collect::<Vec<Goals>>(),
+                )?
+            }
             // Unify item with each (k, v) pair of the dict, skipping non-matching ground terms.
             Value::Dictionary(dict) => self.choose(
                 dict.fields
                     .iter()
                     .map(|(k, v)| {
                         iterable.clone_with_value(Value::List(vec![
-                            v.clone_with_value(Value::String(k.0.clone())),
+                            v.clone_with_value(Value::String(k.to_string())),
                             v.clone(),
                         ]))
                     })
@@ -2243,6 +3552,18 @@ impl PolarVirtualMachine {
                             // Both variables are bound. Unify their values.
                             self.push_goal(Goal::Unify { left: x, right: y })?;
                         }
+                        (VariableState::Bound(_), _) => {
+                            self.check_occurs(r, left)?;
+                            if self.bind(l, right.clone()).is_err() {
+                                self.push_goal(Goal::Backtrack)?;
+                            }
+                        }
+                        (_, VariableState::Bound(_)) => {
+                            self.check_occurs(l, right)?;
+                            if self.bind(l, right.clone()).is_err() {
+                                self.push_goal(Goal::Backtrack)?;
+                            }
+                        }
                         _ => {
                             // At least one variable is unbound. Bind it.
                             if self.bind(l, right.clone()).is_err() {
@@ -2266,11 +3587,7 @@ impl PolarVirtualMachine {
                     VariableState::Bound(value) => {
                         self.push_goal(Goal::Unify { left: value, right })?;
                     }
-                    _ => {
-                        if self.bind(var, right).is_err() {
-                            self.push_goal(Goal::Backtrack)?;
-                        }
-                    }
+                    _ => self.unify_bind(var, right)?,
                 }
             }
 
@@ -2281,11 +3598,7 @@ impl PolarVirtualMachine {
                     VariableState::Bound(value) => {
                         self.push_goal(Goal::Unify { left, right: value })?;
                     }
-                    _ => {
-                        if self.bind(var, left).is_err() {
-                            self.push_goal(Goal::Backtrack)?;
-                        }
-                    }
+                    _ => self.unify_bind(var, left)?,
                 }
             }
 
@@ -2380,6 +3693,28 @@ impl PolarVirtualMachine {
         Ok(())
     }
 
+    /// Bind the variable `var`, which isn't bound to a value, to `val` in a unification,
+    /// backtracking if its constraints don't allow `val`.
+    fn unify_bind(&mut self, var: &Symbol, val: Term) -> PolarResult<()> {
+        self.check_occurs(var, &val)?;
+        if self.bind(var, val).is_err() {
+            self.push_goal(Goal::Backtrack)?;
+        }
+        Ok(())
+    }
+
+    /// With the occurs check on, fail if binding `var` to `val` would make a cyclic term.
+    fn check_occurs(&self, var: &Symbol, val: &Term) -> PolarResult<()> {
+        if self.occurs_check && self.binding_manager.occurs(var, val) {
+            return Err(RuntimeError::OccursCheck {
+                var: var.clone(),
+                term: self.binding_manager.deep_deref(val),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
     /// "Unify" two lists element-wise, respecting rest-variables.
     /// Used by both `unify` and `isa`; hence the third argument,
     /// a closure that builds sub-goals.
@@ -2654,12 +3989,25 @@ impl PolarVirtualMachine {
                 // Query for the body clauses.
                 goals.push(Goal::Query { term: body.clone() });
                 goals.push(Goal::TraceStackPop);
+                goals.push(Goal::PopRule);
 
                 alternatives.push(goals)
             }
 
-            // Choose the first alternative, and push a choice for the rest.
-            self.choose(alternatives)?;
+            // Choose the first alternative, and push a choice for the rest, which knows their
+            // rules for profiling.
+            let mut alternatives = alternatives.into_iter();
+            let first = alternatives.next().unwrap();
+            self.push_choice(alternatives)?;
+            self.choices.last_mut().unwrap().rules = Some(rules.clone());
+            {
+                let mut profiler = self.choice_profiler.borrow_mut();
+                if rules.len() > 1 {
+                    profiler.created();
+                }
+                profiler.tried(&rules[0]);
+            }
+            self.append_goals(first)?;
         }
         Ok(())
     }
@@ -2808,7 +4156,7 @@ impl PolarVirtualMachine {
             let chars = context.source.src.chars();
             chars.take(context.right).skip(context.left).collect()
         } else {
-            term.to_string()
+            self.kb().term_formatter().format(term)
         };
 
         if include_info {
@@ -2839,6 +4187,11 @@ impl PolarVirtualMachine {
         Ok(QueryEvent::Run { runnable, call_id })
     }
 
+    /// Return true if an application error would be caught by a `try` in progress.
+    pub fn catches_errors(&self) -> bool {
+        self.choices.iter().any(|c| c.fallback.is_some())
+    }
+
     /// Handle an error coming from outside the vm.
     pub fn external_error(&mut self, message: String) -> PolarResult<()> {
         self.external_error = Some(message);
@@ -2869,14 +4222,29 @@ impl Runnable for PolarVirtualMachine {
         }
 
         while let Some(goal) = self.goals.pop() {
-            match self.next(goal.clone())? {
+            let event = self.next(goal.clone()).map_err(|e| {
+                self.error_backtrace = Some(self.backtrace());
+                self.add_error_context(e)
+            })?;
+            match event {
                 QueryEvent::None => (),
                 event => {
                     self.external_error = None;
+                    self.goals_since_event = 0;
                     return Ok(event);
                 }
             }
             self.maybe_break(DebugEvent::Goal(goal.clone()))?;
+
+            if let Some(interval) = self.heartbeat_interval {
+                self.goals_since_event += 1;
+                // Only pause with goals left, since an empty goal stack on resumption means
+                // the query is done.
+                if self.goals_since_event >= interval && !self.goals.is_empty() {
+                    let goals = std::mem::take(&mut self.goals_since_event);
+                    return Ok(QueryEvent::Heartbeat { goals });
+                }
+            }
         }
 
         if self.tracing {
@@ -2900,7 +4268,7 @@ impl Runnable for PolarVirtualMachine {
             match simplify_bindings_opt(bindings, false) {
                 Ok(Some(bs)) => {
                     // simplification succeeds
-                    bindings = bs;
+                    bindings = canonicalize_temporaries(bs);
                 }
                 Ok(None) => {
                     // incompatible bindings; simplification fails
@@ -2910,7 +4278,7 @@ impl Runnable for PolarVirtualMachine {
 
                 Err(RuntimeError::UnhandledPartial { term, ref var }) => {
                     // use the debugger to get the nicest possible version of this binding
-                    let Binding(original_var_name, simplified) = get_binding_for_var(&var.0, self);
+                    let Binding(original_var_name, simplified) = get_binding_for_var(var, self);
 
                     // TODO(gj): `t` is a partial constructed in the VM, so we don't have any
                     // source context for it. We make a best effort to track down some relevant
@@ -2987,6 +4355,10 @@ impl Runnable for PolarVirtualMachine {
         Ok(QueryEvent::Result { bindings, trace })
     }
 
+    fn error_backtrace(&self) -> Option<&Backtrace> {
+        self.error_backtrace.as_ref()
+    }
+
     fn handle_error(&mut self, error: PolarError) -> PolarResult<QueryEvent> {
         // if we pushed a debug goal, push an error goal underneath it.
         if self.maybe_break(DebugEvent::Error(error.clone()))? {
@@ -3001,7 +4373,8 @@ impl Runnable for PolarVirtualMachine {
 
     /// Handle response to a predicate posed to the application, e.g., `ExternalIsa`.
     fn external_question_result(&mut self, call_id: u64, answer: bool) -> PolarResult<()> {
-        let var = self.call_id_symbols.remove(&call_id).expect("bad call id");
+        let var = self.pending_call_sym(call_id)?.clone();
+        self.call_id_symbols.remove(&call_id);
         self.rebind_external_answer(&var, Term::from(answer));
         Ok(())
     }
@@ -3016,12 +4389,12 @@ impl Runnable for PolarVirtualMachine {
         // TODO: Open question if we need to pass errors back down to rust.
         // For example what happens if the call asked for a field that doesn't exist?
 
+        let sym = self.pending_call_sym(call_id)?.clone();
+        self.pending_batches.remove(&call_id);
         if let Some(value) = term {
             self.log(LogLevel::Trace, || format!("=> {}", value), &[]);
 
-            // Fetch variable to unify with call result.
-            let sym = self.get_call_sym(call_id).to_owned();
-
+            // Unify the call's variable with the result.
             self.push_goal(Goal::Unify {
                 left: Term::from(sym),
                 right: value,
@@ -3031,7 +4404,7 @@ impl Runnable for PolarVirtualMachine {
 
             // No more results. Clean up, cut out the retry alternative,
             // and backtrack.
-            self.call_id_symbols.remove(&call_id).expect("bad call ID");
+            self.call_id_symbols.remove(&call_id);
 
             let check_error = if let Some(goal) = self.goals.last() {
                 matches!(*(*goal), Goal::CheckError)
@@ -3051,6 +4424,27 @@ impl Runnable for PolarVirtualMachine {
         Ok(())
     }
 
+    /// Handle the results of a batched call provided by the application, one for each instance
+    /// of the batch, in order. The result for the instance the call was made on is handled as
+    /// by `external_call_result`, and the rest answer the calls on the other instances later.
+    fn external_call_results(&mut self, call_id: u64, terms: Vec<Option<Term>>) -> PolarResult<()> {
+        let (calls, index) = match self.pending_batches.remove(&call_id) {
+            Some(batch) => batch,
+            None => return invalid_state(format!("no batched call with ID {}", call_id)),
+        };
+        if terms.len() != calls.len() {
+            return invalid_state(format!(
+                "batched call {} has {} instances, but got {} results",
+                call_id,
+                calls.len(),
+                terms.len()
+            ));
+        }
+        let result = terms[index].clone();
+        self.batch_results.extend(calls.into_iter().zip(terms));
+        self.external_call_result(call_id, result)
+    }
+
     /// Drive debugger.
     fn debug_command(&mut self, command: &str) -> PolarResult<()> {
         let mut debugger = self.debugger.clone();
@@ -3067,6 +4461,20 @@ impl Runnable for PolarVirtualMachine {
     }
 }
 
+/// Milliseconds since the Unix epoch.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+/// Milliseconds since the Unix epoch.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn now_ms() -> u64 {
+    js_sys::Date::now() as u64
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -3642,10 +5050,10 @@ mod tests {
                 operator: Operator::And,
                 args: vec![
                     term!(1),
-                    Term::new_from_test(Value::Variable(Symbol("x".to_string()))),
-                    Term::new_from_test(Value::Variable(Symbol("x".to_string()))),
+                    Term::new_from_test(Value::Variable(Symbol::new("x"))),
+                    Term::new_from_test(Value::Variable(Symbol::new("x"))),
                     Term::new_from_test(Value::List(vec![Term::new_from_test(Value::Variable(
-                        Symbol("y".to_string()),
+                        Symbol::new("y"),
                     ))])),
                 ],
             })),
@@ -3655,14 +5063,14 @@ mod tests {
         let renamed_terms = unwrap_and(&renamed_rule.body);
         assert_eq!(renamed_terms[1].value(), renamed_terms[2].value());
         let x_value = match &renamed_terms[1].value() {
-            Value::Variable(sym) => Some(sym.0.clone()),
+            Value::Variable(sym) => Some(sym.clone()),
             _ => None,
         };
         assert_eq!(x_value.unwrap(), "_x_1");
 
         let y_value = match &renamed_terms[3].value() {
             Value::List(terms) => match &terms[0].value() {
-                Value::Variable(sym) => Some(sym.0.clone()),
+                Value::Variable(sym) => Some(sym.clone()),
                 _ => None,
             },
             _ => None,
@@ -3705,7 +5113,7 @@ mod tests {
                 } => {
                     external_isas.push(class_tag.clone());
                     // Return `true` if the specified `class_tag` is `"a"`.
-                    vm.external_question_result(call_id, class_tag.0 == "a")
+                    vm.external_question_result(call_id, class_tag == "a")
                         .unwrap()
                 }
                 QueryEvent::ExternalOp { .. }
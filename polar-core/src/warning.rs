@@ -3,6 +3,7 @@ use std::fmt;
 use indoc::indoc;
 use strum_macros::AsRefStr;
 
+use super::rules::Rule;
 use super::sources::Context;
 use super::terms::{InstanceLiteral, Pattern, Symbol, Term, Value};
 
@@ -21,6 +22,9 @@ impl PolarWarning {
             AmbiguousPrecedence { term } | UnknownSpecializer { term, .. } => {
                 term.parsed_context().cloned()
             }
+            Lint { term, .. } => term.as_ref().and_then(Term::parsed_context).cloned(),
+            DeprecatedRule { term, .. } => term.parsed_context().cloned(),
+            DuplicateClause { rule } => rule.parsed_context().cloned(),
             MissingAllowRule | MissingHasPermissionRule => None,
         }
     }
@@ -48,6 +52,23 @@ pub enum ValidationWarning {
     // TODO(gj): won't need `sym` once we have an easier, infallible way of going from `Term` ->
     // `Pattern` -> `InstanceLiteral` -> `tag` (`Symbol`).
     UnknownSpecializer { term: Term, sym: Symbol },
+    // Category: runtime
+    DeprecatedRule {
+        rule: Symbol,
+        message: String,
+        /// Term<Call> of the deprecated rule.
+        term: Term,
+    },
+    // Category: general
+    DuplicateClause {
+        rule: Rule,
+    },
+    // Category: user-defined
+    Lint {
+        rule: String,
+        message: String,
+        term: Option<Term>,
+    },
 }
 
 impl From<ValidationWarning> for PolarWarning {
@@ -89,7 +110,7 @@ const MISSING_HAS_PERMISSION_RULE_MSG: &str = indoc! {"
 
 fn common_specializer_misspellings(term: &Term) -> Option<&str> {
     if let Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) = term.value() {
-        let misspelled_type = match tag.0.as_ref() {
+        let misspelled_type = match tag.as_str() {
             "integer" => "Integer",
             "int" => "Integer",
             "i32" => "Integer",
@@ -139,6 +160,16 @@ impl fmt::Display for ValidationWarning {
                     write!(f, ", did you mean {}?", suggestion)?;
                 }
             }
+            DeprecatedRule { rule, message, .. } => {
+                write!(f, "Call to deprecated rule {}: {}", rule, message)?
+            }
+            DuplicateClause { rule } => write!(
+                f,
+                "Clause {} duplicates a clause of rule {} already loaded",
+                rule.head_as_string(),
+                rule.name
+            )?,
+            Lint { rule, message, .. } => write!(f, "{}: {}", rule, message)?,
         }
 
         Ok(())
@@ -1,7 +1,7 @@
 mod mock_externals;
 
 use indoc::indoc;
-use maplit::btreemap;
+use maplit::{btreemap, hashmap};
 use permutohedron::Heap;
 
 use std::cell::RefCell;
@@ -11,9 +11,16 @@ use mock_externals::MockExternal;
 use polar_core::{
     error::{ParseErrorKind::*, RuntimeError::*, ValidationError::*, *},
     events::*,
+    fixes::ParamEdit,
+    kb::{DuplicateClauses, MergeConflicts},
+    limits::Limits,
     messages::*,
     polar::Polar,
     query::Query,
+    quota::{Quota, RuleQuota, ScopeQuota},
+    rules::TemplateInfo,
+    sandbox::Sandbox,
+    sources::Source,
     sym, term,
     terms::*,
     traces::*,
@@ -467,6 +474,819 @@ fn test_forall_with_dot_lookup() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn test_forall_counterexamples() -> TestResult {
+    let p = polar();
+    let mut q = p.new_query("forall(x in [1, 2, 3], x < 3)", false)?;
+    assert!(matches!(q.next_event()?, QueryEvent::Done { .. }));
+    let counterexamples = q.counterexamples();
+    assert_eq!(counterexamples.len(), 1);
+    assert_eq!(counterexamples[0].bindings[&sym!("x")], term!(3));
+    assert_eq!(
+        counterexamples[0].to_string(),
+        "forall(x in [1, 2, 3], x < 3) for x = 3"
+    );
+
+    // Temporaries from rewriting the condition aren't reported, and each distinct
+    // counterexample is reported once.
+    p.load_str("f(x) if forall(y in x.ys, y != 0 and y != 1);")?;
+    let mut q = p.new_query("f({ys: [1, 0, 2, 0]})", false)?;
+    assert!(matches!(q.next_event()?, QueryEvent::Done { .. }));
+    let values = q
+        .counterexamples()
+        .into_iter()
+        .map(|c| c.bindings.into_values().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(values, vec![vec![term!(1)], vec![term!(0)]]);
+
+    let mut q = p.new_query("f({ys: [2, 3]})", false)?;
+    assert!(matches!(q.next_event()?, QueryEvent::Result { .. }));
+    assert!(q.counterexamples().is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_query_parameters() -> TestResult {
+    let p = polar();
+    p.load_str(r#"allow("alice", "read", {id: 1});"#)?;
+    let params = hashmap! {
+        "actor".to_owned() => term!("alice"),
+        "resource".to_owned() => term!(btreemap! {sym!("id") => term!(1)}),
+    };
+    let q = p.new_query_with_params(r#"allow(:actor, "read", :resource)"#, params, false)?;
+    assert_eq!(query_results!(q).len(), 1);
+
+    // A parameter is a value, even if it looks like Polar.
+    let params = hashmap! {"actor".to_owned() => term!(r#""alice" or true"#)};
+    let q = p.new_query_with_params(r#"allow(:actor, "read", {id: 1})"#, params, false)?;
+    assert!(query_results!(q).is_empty());
+
+    let missing = p.new_query_with_params("x = :y", HashMap::new(), false);
+    assert!(matches!(
+        missing.map_err(|e| e.0),
+        Err(ErrorKind::Runtime(RuntimeError::QueryParameter { name, .. })) if name == "y"
+    ));
+    let params = hashmap! {"y".to_owned() => term!(1), "z".to_owned() => term!(2)};
+    let unused = p.new_query_with_params("x = :y", params, false);
+    assert!(matches!(
+        unused.map_err(|e| e.0),
+        Err(ErrorKind::Runtime(RuntimeError::QueryParameter { name, .. })) if name == "z"
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_rule_templates() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"f(1);
+           @template("allow_role")
+           allow(actor, :action, _resource) if :role in actor.roles;"#,
+    )?;
+    // Templates aren't rules.
+    let mut q = p.new_query(r#"allow({roles: ["admin"]}, "read", 1)"#, false)?;
+    assert!(matches!(
+        q.next_event().map_err(|e| e.0),
+        Err(ErrorKind::Runtime(
+            RuntimeError::QueryForUndefinedRule { .. }
+        ))
+    ));
+
+    let bindings = hashmap! {
+        "action".to_owned() => term!("read"),
+        "role".to_owned() => term!("admin"),
+    };
+    let rule = p.instantiate_template(None, "allow_role", bindings)?;
+    assert_eq!(
+        rule.to_string(),
+        r#"allow(actor, "read", _resource) if "admin" in actor.roles;"#
+    );
+    assert!(rule.metadata.template.is_none());
+    p.load_scope("a", vec![Source::new(rule.to_string())])?;
+    let mut q = p.new_query(r#"allow({roles: ["admin"]}, "read", 1)"#, false)?;
+    q.set_scope(Some("a".to_owned()));
+    assert_eq!(query_results!(q).len(), 1);
+
+    let unknown = p.instantiate_template(None, "allow_any", HashMap::new());
+    assert!(matches!(
+        unknown.map_err(|e| e.0),
+        Err(ErrorKind::Runtime(RuntimeError::InvalidTemplate { template, .. })) if template == "allow_any"
+    ));
+    // Bindings are plain values, which can't change the structure of the rule.
+    let bindings = hashmap! {
+        "action".to_owned() => term!("read"),
+        "role".to_owned() => term!(sym!("x")),
+    };
+    let not_ground = p.instantiate_template(None, "allow_role", bindings);
+    assert!(matches!(
+        not_ground.map_err(|e| e.0),
+        Err(ErrorKind::Runtime(RuntimeError::InvalidTemplate { .. }))
+    ));
+    let bindings = hashmap! {"action".to_owned() => term!("read")};
+    let missing = p.instantiate_template(None, "allow_role", bindings);
+    assert!(matches!(
+        missing.map_err(|e| e.0),
+        Err(ErrorKind::Runtime(RuntimeError::QueryParameter { name, .. })) if name == "role"
+    ));
+
+    let duplicate = polar().load_str(
+        r#"@template("t") f(:x);
+           @template("t") g(:x);"#,
+    );
+    assert!(matches!(
+        duplicate.map_err(|e| e.0),
+        Err(ErrorKind::Runtime(RuntimeError::InvalidTemplate { template, .. })) if template == "t"
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_list_templates() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"f(1);
+           @doc("Allow actors with a role to take an action.")
+           @template("allow_role")
+           allow(actor, :action, _resource) if :role in actor.roles;
+           @template("allow_user")
+           allow(actor, :action, _resource) if actor.name = :name;"#,
+    )?;
+    p.load_scope(
+        "a",
+        vec![Source::new(
+            r#"@doc("Allow one user any action.")
+               @template("allow_user")
+               allow(actor, _action, _resource) if actor.name = :name;"#,
+        )],
+    )?;
+
+    let allow_role = TemplateInfo {
+        name: "allow_role".to_owned(),
+        scope: None,
+        head: "allow(actor, :action, _resource)".to_owned(),
+        placeholders: vec!["action".to_owned(), "role".to_owned()],
+        doc: Some("Allow actors with a role to take an action.".to_owned()),
+        calls: None,
+    };
+    let allow_user = TemplateInfo {
+        name: "allow_user".to_owned(),
+        scope: None,
+        head: "allow(actor, :action, _resource)".to_owned(),
+        placeholders: vec!["action".to_owned(), "name".to_owned()],
+        doc: None,
+        calls: None,
+    };
+    assert_eq!(
+        p.list_templates(None),
+        vec![allow_role.clone(), allow_user.clone()]
+    );
+    assert_eq!(
+        p.list_templates(Some("b")),
+        vec![allow_role.clone(), allow_user]
+    );
+
+    // A scope's templates hide the templates of the same name loaded without a scope.
+    let scoped_allow_user = TemplateInfo {
+        name: "allow_user".to_owned(),
+        scope: Some("a".to_owned()),
+        head: "allow(actor, _action, _resource)".to_owned(),
+        placeholders: vec!["name".to_owned()],
+        doc: Some("Allow one user any action.".to_owned()),
+        calls: None,
+    };
+    assert_eq!(
+        p.list_templates(Some("a")),
+        vec![allow_role, scoped_allow_user]
+    );
+    let bindings = hashmap! {"name".to_owned() => term!("alice")};
+    let rule = p.instantiate_template(Some("a"), "allow_user", bindings)?;
+    assert_eq!(
+        rule.to_string(),
+        r#"allow(actor, _action, _resource) if actor.name = "alice";"#
+    );
+    Ok(())
+}
+
+#[test]
+fn test_template_allowed_calls() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"has_role(actor, role, _resource) if role in actor.roles;
+           has_relation(_subject, _relation, _object);
+           secret(_actor);
+           @calls("has_role, has_relation")
+           @template("allow_role")
+           allow(actor, _action, resource) if has_role(actor, :role, resource);"#,
+    )?;
+    let calls = p.list_templates(None).remove(0).calls;
+    assert_eq!(calls, Some(vec![sym!("has_role"), sym!("has_relation")]));
+
+    // Rules with the template's name and arity may call the allowed rules, built-ins, and
+    // methods.
+    p.load_scope(
+        "a",
+        vec![Source::new(
+            r#"allow(actor, "read", resource) if
+                   has_relation(resource, "parent", parent) and
+                   has_role(actor, "member", parent) and
+                   actor.is_active() and cut;
+               f(actor) if secret(actor);"#,
+        )],
+    )?;
+
+    let disallowed = |policy: &str| {
+        let e = p.load_scope("a", vec![Source::new(policy)]).unwrap_err();
+        match e.0 {
+            ErrorKind::Validation(ValidationError::DisallowedRuleCall {
+                term,
+                templates,
+                allowed,
+            }) => {
+                assert_eq!(templates, vec!["allow_role"]);
+                assert_eq!(allowed, vec!["has_relation", "has_role"]);
+                term.to_string()
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+    };
+    assert_eq!(
+        disallowed("allow(actor, _action, _resource) if secret(actor);"),
+        "secret(actor)"
+    );
+    assert_eq!(
+        disallowed("allow(_actor, _action, _resource) if forall(x in [1], secret(x));"),
+        "secret(x)"
+    );
+    // Rules loaded without a scope are checked too.
+    let p = polar();
+    let e = p
+        .load_str(
+            r#"secret(_actor);
+               @calls("")
+               @template("allow_any")
+               allow(_actor, _action, _resource);
+               allow(actor, _action, _resource) if secret(actor);"#,
+        )
+        .unwrap_err();
+    assert!(matches!(
+        e.0,
+        ErrorKind::Validation(ValidationError::DisallowedRuleCall { .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_sandbox() -> TestResult {
+    let p = polar();
+    let profile = term!(Value::ExternalInstance(ExternalInstance {
+        instance_id: 1,
+        constructor: None,
+        repr: None,
+        class_repr: None,
+        class_id: Some(1),
+    }));
+    p.register_constant(sym!("Profile"), profile)?;
+    p.load(vec![
+        Source::new_with_name("app.polar", "app(x) if x = Profile.secret();"),
+        Source::new_with_name(
+            "tenant.polar",
+            r#"name(x) if x = Profile.name();
+               secret(x) if x = Profile.secret();
+               trusted(x) if app(x);
+               length(x) if x = "abc".len();
+               make(x) if x = new Profile();"#,
+        )
+        .with_sandbox(Sandbox::new().allow_method("Profile", "name")),
+    ])?;
+
+    for allowed in ["name(x)", "trusted(x)", "length(x)", "app(x)"] {
+        let mut q = p.new_query(allowed, false)?;
+        assert!(matches!(q.next_event()?, QueryEvent::ExternalCall { .. }));
+    }
+    for denied in ["secret(x)", "make(x)"] {
+        let mut q = p.new_query(denied, false)?;
+        assert!(matches!(
+            q.next_event().map_err(|e| e.0),
+            Err(ErrorKind::Runtime(RuntimeError::SandboxViolation { class, .. })) if class == "Profile"
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_scopes() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"f(1);
+           g(x) if f(x);"#,
+    )?;
+    p.load_scope("a", vec![Source::new("f(2); h(x) if g(x);")])?;
+    p.load_scope("b", vec![Source::new("f(3);")])?;
+
+    let scoped = |scope: &str, src: &str| -> PolarResult<Vec<Value>> {
+        let mut q = p.new_query(src, false)?;
+        q.set_scope(Some(scope.to_owned()));
+        Ok(query_results!(q)
+            .into_iter()
+            .map(|(bindings, _)| bindings[&sym!("x")].clone())
+            .collect())
+    };
+    assert_eq!(scoped("a", "g(x)")?, values![1, 2]);
+    assert_eq!(scoped("a", "h(x)")?, values![1, 2]);
+    assert_eq!(scoped("b", "g(x)")?, values![1, 3]);
+    assert_eq!(scoped("c", "g(x)")?, values![1]);
+    qvar(&p, "g(x)", "x", values![1]);
+    let mut q = p.new_query("h(x)", false)?;
+    q.set_scope(Some("b".to_owned()));
+    assert!(matches!(
+        q.next_event().map_err(|e| e.0),
+        Err(ErrorKind::Runtime(
+            RuntimeError::QueryForUndefinedRule { .. }
+        ))
+    ));
+
+    // Reloading a scope replaces its rules.
+    p.load_scope("a", vec![Source::new("f(4);")])?;
+    assert_eq!(scoped("a", "g(x)")?, values![1, 4]);
+
+    // Scopes are validated against the rules loaded without a scope.
+    let undefined = p.load_scope("a", vec![Source::new("f(x) if k(x);")]);
+    assert!(matches!(
+        undefined.map_err(|e| e.0),
+        Err(ErrorKind::Validation(
+            ValidationError::UndefinedRuleCall { .. }
+        ))
+    ));
+    let not_rules = p.load_scope("a", vec![Source::new("type f(x);")]);
+    assert!(matches!(
+        not_rules.map_err(|e| e.0),
+        Err(ErrorKind::Runtime(RuntimeError::InvalidScope { .. }))
+    ));
+    assert_eq!(scoped("a", "g(x)")?, values![1, 4]);
+    Ok(())
+}
+
+#[test]
+fn test_merge_knowledge_bases() -> TestResult {
+    let build = |policy: &str, scope: &str| -> PolarResult<Polar> {
+        let p = polar();
+        p.register_constant(sym!("Limit"), term!(1))?;
+        p.load_str(policy)?;
+        p.load_scope(scope, vec![Source::new("f(10);")])?;
+        Ok(p)
+    };
+    let ours = r#"f(1);
+                  g(x) if f(x);
+                  @template("t")
+                  f(:x);"#;
+    let theirs = r#"f(1);
+                    f(2);
+                    @template("t")
+                    f(x) if x = :x;"#;
+    let merged = |conflicts| -> PolarResult<Polar> {
+        let p = build(ours, "a")?;
+        let other = build(theirs, "b")?;
+        other
+            .kb
+            .write()
+            .unwrap()
+            .register_constant(sym!("Other"), term!(2))?;
+        let other = other.kb.read().unwrap();
+        p.kb.write().unwrap().merge(&other, conflicts)?;
+        Ok(p)
+    };
+
+    // Clauses are combined, skipping the clause loaded into both.
+    let p = merged(MergeConflicts::KeepOurs)?;
+    qvar(&p, "g(x)", "x", values![1, 2]);
+    qvar(&p, "x = Other", "x", values![2]);
+    let scopes = |p: &Polar| {
+        p.list_templates(Some("b"))
+            .into_iter()
+            .map(|template| (template.scope, template.head))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(scopes(&p), vec![(None, "f(:x)".to_owned())]);
+    let mut q = p.new_query("f(x)", false)?;
+    q.set_scope(Some("b".to_owned()));
+    assert_eq!(query_results!(q).len(), 3);
+
+    let p = merged(MergeConflicts::TakeTheirs)?;
+    assert_eq!(scopes(&p), vec![(None, "f(x)".to_owned())]);
+
+    // Conflicts fail the merge, and leave the knowledge base as it was.
+    let e = merged(MergeConflicts::Error).map(|_| ()).unwrap_err();
+    assert!(matches!(
+        e.0,
+        ErrorKind::Runtime(RuntimeError::MergeConflict { kind, name })
+            if kind == "template" && name == "t"
+    ));
+    let p = build(ours, "a")?;
+    let other = build(theirs.replace("f(x) if x = :x", "f(:x)").as_str(), "a")?;
+    let e =
+        p.kb.write()
+            .unwrap()
+            .merge(&other.kb.read().unwrap(), MergeConflicts::Error)
+            .unwrap_err();
+    assert!(matches!(
+        e.0,
+        ErrorKind::Runtime(RuntimeError::MergeConflict { kind, .. }) if kind == "scope"
+    ));
+    qvar(&p, "g(x)", "x", values![1]);
+    Ok(())
+}
+
+#[test]
+fn test_remove_scope() -> TestResult {
+    let p = polar();
+    p.load_str("f(1);")?;
+    p.set_scope_quota("a", ScopeQuota::new().max_rules(1));
+    p.load_scope("a", vec![Source::new("f(2);")])?;
+    p.load_scope("b", vec![Source::new("f(3);")])?;
+
+    assert!(p.remove_scope("a", false)?);
+    assert!(!p.remove_scope("a", false)?);
+    let mut q = p.new_query("f(x)", false)?;
+    q.set_scope(Some("a".to_owned()));
+    assert_eq!(query_results!(q).len(), 1);
+    let mut q = p.new_query("f(x)", false)?;
+    q.set_scope(Some("b".to_owned()));
+    assert_eq!(query_results!(q).len(), 2);
+
+    // The scope's quota is removed along with its rules.
+    p.load_scope("a", vec![Source::new("f(2); f(4);")])?;
+
+    // And so are its templates.
+    p.load_scope("c", vec![Source::new(r#"@template("g") g(x) if x = :y;"#)])?;
+    assert_eq!(p.list_templates(Some("c")).len(), 1);
+    assert!(p.remove_scope("c", false)?);
+    assert!(p.list_templates(Some("c")).is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_scope_includes() -> TestResult {
+    let p = polar();
+    p.load_str("f(1);")?;
+    p.load_scope("parent", vec![Source::new("f(2);")])?;
+    p.set_scope_includes("child", vec!["parent".to_owned()])?;
+    p.load_scope("child", vec![Source::new("f(3); g(x) if f(x);")])?;
+    assert!(p
+        .set_scope_includes("parent", vec!["parent".to_owned()])
+        .is_err());
+
+    let mut q = p.new_query("g(x)", false)?;
+    q.set_scope(Some("child".to_owned()));
+    assert_eq!(query_results!(q).len(), 3);
+    let mut q = p.new_query("f(x)", false)?;
+    q.set_scope(Some("parent".to_owned()));
+    assert_eq!(query_results!(q).len(), 2);
+
+    // Included scopes can only be removed by force.
+    assert!(p.remove_scope("parent", false).is_err());
+    assert!(p.remove_scope("parent", true)?);
+    let mut q = p.new_query("g(x)", false)?;
+    q.set_scope(Some("child".to_owned()));
+    assert_eq!(query_results!(q).len(), 2);
+
+    // Once a scope that includes another is removed, the other can be.
+    p.load_scope("parent", vec![Source::new("f(2);")])?;
+    assert!(p.remove_scope("child", false)?);
+    assert!(p.remove_scope("parent", false)?);
+    Ok(())
+}
+
+#[test]
+fn test_nested_scopes() -> TestResult {
+    let p = polar();
+    p.load_scope("org", vec![Source::new("f(1); h(1);")])?;
+    p.load_scope("org::team", vec![Source::new("f(2); g(x) if f(x);")])?;
+    p.load_scope("org::team::squad", vec![Source::new("f(3);")])?;
+
+    // Queries in a scope use the rules of the scopes it's nested in.
+    let mut q = p.new_query("g(x)", false)?;
+    q.set_scope(Some("org::team".to_owned()));
+    assert_eq!(query_results!(q).len(), 2);
+    let mut q = p.new_query("h(x)", false)?;
+    q.set_scope(Some("org::team::squad".to_owned()));
+    assert_eq!(query_results!(q).len(), 1);
+
+    // Paths name the rules of a scope and the scopes it's nested in, from any scope.
+    assert_eq!(
+        query_results!(p.new_query("org::team::squad::f(x)", false)?).len(),
+        3
+    );
+    assert_eq!(query_results!(p.new_query("org::f(x)", false)?).len(), 1);
+    let mut q = p.new_query("x = 2 and org::team::f(x)", false)?;
+    q.set_scope(Some("org".to_owned()));
+    assert_eq!(query_results!(q).len(), 1);
+
+    // Calls of paths in a policy are resolved when they're queried.
+    p.load_str("k(x) if a::b::k(x);")?;
+    p.load_scope("a::b", vec![Source::new("k(1);")])?;
+    assert_eq!(query_results!(p.new_query("k(x)", false)?).len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_scope_quotas() -> TestResult {
+    let p = polar();
+    p.load_str("f(0); f(x) if x > 0 and f(x - 1);")?;
+    p.set_default_scope_quota(ScopeQuota::new().max_rules(2));
+    p.set_scope_quota("a", ScopeQuota::new().max_bytes(10).max_query_goals(200));
+
+    let exceeded = |result: PolarResult<()>, expected: Quota| {
+        matches!(
+            result.map_err(|e| e.0),
+            Err(ErrorKind::Runtime(RuntimeError::QuotaExceeded { quota, .. })) if quota == expected
+        )
+    };
+    assert!(exceeded(
+        p.load_scope("b", vec![Source::new("g(1); g(2); g(3);")]),
+        Quota::Rules
+    ));
+    p.load_scope("b", vec![Source::new("g(1); g(2);")])?;
+    assert!(exceeded(
+        p.load_scope("a", vec![Source::new("g(1); g(2);")]),
+        Quota::Bytes
+    ));
+    p.load_scope("a", vec![Source::new("g(1);")])?;
+
+    // Each query in a scope has its own goal budget.
+    for _ in 0..2 {
+        let mut q = p.new_query("f(1)", false)?;
+        q.set_scope(Some("a".to_owned()));
+        assert_eq!(query_results!(q).len(), 1);
+    }
+    let mut q = p.new_query("f(100)", false)?;
+    q.set_scope(Some("a".to_owned()));
+    assert!(exceeded(q.next_event().map(|_| ()), Quota::QueryGoals));
+    let mut q = p.new_query("f(100)", false)?;
+    q.set_scope(Some("b".to_owned()));
+    assert!(matches!(q.next_event()?, QueryEvent::Result { .. }));
+    Ok(())
+}
+
+#[test]
+fn test_rule_quotas() -> TestResult {
+    let p = polar();
+    p.load_str("f(0); f(x) if x > 0 and f(x - 1); g(x) if f(x);")?;
+    p.load_scope("a", vec![Source::new("h(x) if f(x);")])?;
+    p.load_scope("b", vec![Source::new("h(x) if f(x);")])?;
+    p.set_scope_quota(
+        "a",
+        ScopeQuota::new().rule("h", RuleQuota::new().max_goals(1000)),
+    );
+    p.set_scope_quota(
+        "b",
+        ScopeQuota::new().rule("f", RuleQuota::new().max_calls(20)),
+    );
+
+    let run = |query: &str, scope: &str| -> PolarResult<usize> {
+        let mut q = p.new_query(query, false)?;
+        q.set_scope(Some(scope.to_owned()));
+        let mut results = 0;
+        loop {
+            match q.next_event()? {
+                QueryEvent::Result { .. } => results += 1,
+                QueryEvent::Done { .. } => return Ok(results),
+                event => panic!("unexpected event: {:?}", event),
+            }
+        }
+    };
+    let exceeded = |result: PolarResult<usize>, expected: Quota| {
+        matches!(
+            result.map_err(|e| e.0),
+            Err(ErrorKind::Runtime(RuntimeError::QuotaExceeded { quota, .. })) if quota == expected
+        )
+    };
+
+    // Each query has its own quota for each rule.
+    for _ in 0..2 {
+        assert_eq!(run("h(2)", "a")?, 1);
+    }
+    assert!(exceeded(run("h(50)", "a"), Quota::RuleGoals(sym!("h"))));
+    // Goals outside calls of the rule don't count against its quota.
+    assert_eq!(run("g(50)", "a")?, 1);
+
+    assert_eq!(run("h(3)", "b")?, 1);
+    assert!(exceeded(run("g(50)", "b"), Quota::RuleCalls(sym!("f"))));
+    Ok(())
+}
+
+#[test]
+fn test_limits() -> TestResult {
+    let p = polar();
+    let limits = Limits::new().max_rule_depth(10).max_term_depth(5);
+    p.set_limits(limits);
+    p.load_str(
+        "f(0); f(x) if x > 0 and f(x - 1);
+         g(x) if f(x) and false; g(x) if f(x);",
+    )?;
+
+    assert_eq!(query_results!(p.new_query("f(9)", false)?).len(), 1);
+    let err = p.new_query("f(10)", false)?.next_event().unwrap_err();
+    assert!(matches!(
+        err.0,
+        ErrorKind::Runtime(RuleDepthExceeded { limit: 10, .. })
+    ));
+    assert!(err.get_context().is_some());
+
+    // Backtracking out of a rule call restores the depth of its caller.
+    assert_eq!(query_results!(p.new_query("g(8)", false)?).len(), 1);
+
+    assert_eq!(query_results!(p.new_query("x = [[[1]]]", false)?).len(), 1);
+    let err = p.new_query("x = [[[[1]]]]", false).err().unwrap();
+    assert!(matches!(
+        err.0,
+        ErrorKind::Parse(ParseError {
+            kind: TermTooDeep { limit: 5, .. },
+            ..
+        })
+    ));
+    assert!(err.get_context().is_some());
+    let q = polar();
+    q.set_limits(limits);
+    let err = q.load_str("h(x) if x = [[[[1]]]];").unwrap_err();
+    assert!(matches!(
+        err.0,
+        ErrorKind::Parse(ParseError {
+            kind: TermTooDeep { .. },
+            ..
+        })
+    ));
+
+    p.set_limits(Limits::new().max_stack_size(10));
+    let err = p.new_query("f(10)", false)?.next_event().unwrap_err();
+    assert!(matches!(err.0, ErrorKind::Runtime(StackOverflow { .. })));
+    Ok(())
+}
+
+#[test]
+fn test_heartbeats() -> TestResult {
+    let p = polar();
+    p.load_str("f(0); f(x) if x > 0 and f(x - 1);")?;
+    let mut q = p.new_query("f(50)", false)?;
+    assert!(matches!(q.next_event()?, QueryEvent::Result { .. }));
+
+    p.set_heartbeat_interval(Some(10));
+    let mut q = p.new_query("f(50)", false)?;
+    let mut heartbeats = 0;
+    loop {
+        match q.next_event()? {
+            QueryEvent::Heartbeat { goals } => {
+                assert_eq!(goals, 10);
+                heartbeats += 1;
+            }
+            QueryEvent::Result { .. } => break,
+            event => panic!("unexpected event: {:?}", event),
+        }
+    }
+    assert!(heartbeats > 10);
+
+    let mut q = p.new_query("f(50)", false)?;
+    q.set_heartbeat_interval(None);
+    assert!(matches!(q.next_event()?, QueryEvent::Result { .. }));
+    Ok(())
+}
+
+#[test]
+fn test_occurs_check() -> TestResult {
+    let p = polar();
+    p.load_str("f(x, [x]);")?;
+    qeval(&p, "x = [x]");
+    qeval(&p, "f(y, y)");
+
+    p.set_occurs_check(true);
+    qruntime!(&p, "x = [x]", OccursCheck { var, .. }, var == sym!("x"));
+    qruntime!(&p, "x = {a: [1, x]}", OccursCheck { .. });
+    qruntime!(&p, "x = [y] and y = x", OccursCheck { .. });
+    qruntime!(&p, "x = y and y = [x]", OccursCheck { .. });
+    qruntime!(&p, "f(y, y)", OccursCheck { .. });
+    qeval(&p, "x = y and y = x and x = [1]");
+    qvar(&p, "x = [1] and y = x", "y", vec![value!([1])]);
+
+    let mut q = p.new_query("x = [x]", false)?;
+    q.set_occurs_check(false);
+    assert!(matches!(q.next_event()?, QueryEvent::Result { .. }));
+    Ok(())
+}
+
+#[test]
+fn test_suspend_and_resume() -> TestResult {
+    let policy = r#"f(x, y) if y = x.name; f(_, "default");"#;
+    let profile = term!(Value::ExternalInstance(ExternalInstance {
+        instance_id: 1,
+        constructor: None,
+        repr: None,
+        class_repr: None,
+        class_id: None,
+    }));
+    let p = polar();
+    p.load_str(policy)?;
+    let mut q = p.new_query("f(profile, y)", false)?;
+    q.bind(sym!("profile"), profile.clone())?;
+    let call_id = match q.next_event()? {
+        QueryEvent::ExternalCall {
+            call_id, attribute, ..
+        } if attribute == sym!("name") => call_id,
+        event => panic!("unexpected event: {:?}", event),
+    };
+    let suspended = serde_json::to_string(&q.suspend()?).unwrap();
+
+    // Resume the query with the same policy, e.g., on another worker.
+    let p = polar();
+    p.load_str(policy)?;
+    let mut q = p.resume_query(serde_json::from_str(&suspended).unwrap())?;
+    q.call_result(call_id, Some(term!("alice")))?;
+    for expected in ["alice", "default"] {
+        match q.next_event()? {
+            QueryEvent::Result { bindings, .. } => {
+                assert_eq!(bindings[&sym!("y")], term!(expected))
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+    }
+    assert!(matches!(q.next_event()?, QueryEvent::Done { .. }));
+
+    // Queries may only be resumed with the policy they were suspended with.
+    let p = polar();
+    p.load_str("f(_, 1);")?;
+    let resumed = p.resume_query(serde_json::from_str(&suspended).unwrap());
+    assert!(matches!(
+        resumed.map(|_| ()).unwrap_err().0,
+        ErrorKind::Runtime(RuntimeError::Suspension { .. })
+    ));
+
+    // Resumed queries bind constants to the KB's terms for them, which may be sensitive.
+    let p = polar();
+    p.register_constant(
+        sym!("Key"),
+        Term::new_sensitive(Value::String("hunter2".to_owned())),
+    )?;
+    p.load_str(policy)?;
+    let mut q = p.new_query("f(profile, y) and z = Key", false)?;
+    q.bind(sym!("profile"), profile.clone())?;
+    let call_id = match q.next_event()? {
+        QueryEvent::ExternalCall { call_id, .. } => call_id,
+        event => panic!("unexpected event: {:?}", event),
+    };
+    let suspended = serde_json::to_string(&q.suspend()?).unwrap();
+    let mut q = p.resume_query(serde_json::from_str(&suspended).unwrap())?;
+    q.call_result(call_id, Some(term!("alice")))?;
+    match q.next_event()? {
+        QueryEvent::Result { bindings, .. } => assert!(bindings[&sym!("z")].is_sensitive()),
+        event => panic!("unexpected event: {:?}", event),
+    }
+
+    // Queries with pending goals from a sandboxed source can't be suspended, since they
+    // wouldn't be sandboxed once resumed.
+    let p = polar();
+    p.register_constant(sym!("Profile"), profile)?;
+    p.load(vec![
+        Source::new(policy).with_sandbox(Sandbox::new().allow_method("Profile", "name"))
+    ])?;
+    let mut q = p.new_query("f(Profile, y)", false)?;
+    assert!(matches!(q.next_event()?, QueryEvent::ExternalCall { .. }));
+    assert!(matches!(
+        q.suspend().map(|_| ()).unwrap_err().0,
+        ErrorKind::Runtime(RuntimeError::Suspension { .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_constant_folding() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"f(x) if x = 2 * 3 + 1;
+           f(x) if x = 1 and 2 < 1;
+           f(x) if "a" < "b" and x = 2 or 1 > 2;
+           g(_x) if cut and 1 > 2;
+           g(1);
+           h(x) if x = 9223372036854775807 + 1;"#,
+    )?;
+    qvar(&p, "f(x)", "x", values![7, 2]);
+    qnull(&p, "g(x)");
+    qruntime!(&p, "h(x)", ArithmeticError { .. });
+    Ok(())
+}
+
+#[test]
+fn test_binding_stats() -> TestResult {
+    let p = polar();
+    p.load_str("f(x) if not x = 1 and not x = 2;")?;
+    let mut q = p.new_query("f(3)", false)?;
+    assert!(matches!(q.next_event()?, QueryEvent::Result { .. }));
+    let stats = q.binding_stats();
+    assert_eq!(stats.snapshots, 2);
+    assert!(stats.bindings > 0);
+    assert!(stats.backtracks > 0);
+    // Negations share the bindings of their parent query instead of copying them.
+    assert_eq!(stats.copied, 0);
+    Ok(())
+}
+
 #[test]
 fn test_trace() -> TestResult {
     let p = polar();
@@ -634,13 +1454,62 @@ fn test_multi_arg_method_ordering() -> TestResult {
 fn test_no_applicable_rules() -> TestResult {
     let p = polar();
 
-    qruntime!("f()", QueryForUndefinedRule { name }, name == "f");
+    qruntime!("f()", QueryForUndefinedRule { name, .. }, name == "f");
 
     p.load_str("f(_);")?;
     qnull(&p, "f()");
     Ok(())
 }
 
+#[test]
+fn test_runtime_errors_have_context() -> TestResult {
+    let p = polar();
+    p.load_str("f(x) if\n  x = 1 and\n  f(x);")?;
+    let err = p.new_query("f(1)", false)?.next_event().unwrap_err();
+    assert!(matches!(err.0, ErrorKind::Runtime(StackOverflow { .. })));
+    assert!(err.get_context().is_some());
+    assert!(err.to_string().contains("at line 3, column 3"));
+
+    p.set_scope_quota("a", ScopeQuota::new().max_query_goals(5));
+    let mut q = p.new_query("f(1)", false)?;
+    q.set_scope(Some("a".to_owned()));
+    let err = q.next_event().unwrap_err();
+    assert!(matches!(err.0, ErrorKind::Runtime(QuotaExceeded { .. })));
+    assert!(err.get_context().is_some());
+    Ok(())
+}
+
+#[test]
+fn test_runtime_errors_have_backtraces() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"f(x) if g(x, "a");
+           g(x, y) if h([x, y]);
+           h(xs) if xs + 1 > 0;"#,
+    )?;
+    let mut query = p.new_query("f(1)", false)?;
+    assert!(query.backtrace().is_none());
+    query.next_event().unwrap_err();
+    let backtrace = query.backtrace().expect("query should have a backtrace");
+    let calls: Vec<_> = backtrace
+        .frames
+        .iter()
+        .map(|frame| (frame.rule.name.as_str(), frame.call.to_string()))
+        .collect();
+    assert_eq!(
+        calls,
+        vec![
+            ("f", "f(1)".to_owned()),
+            ("g", r#"g(1, "a")"#.to_owned()),
+            ("h", r#"h([1, "a"])"#.to_owned()),
+        ]
+    );
+    let formatted = backtrace.to_string();
+    assert!(formatted.contains(r#"002: h([1, "a"]) at line 2, column 23"#));
+    assert!(formatted.contains("in rule h at line 3, column 12"));
+    Ok(())
+}
+
 /// From Aït-Kaci's WAM tutorial (1999), page 34.
 #[test]
 fn test_ait_kaci_34() -> TestResult {
@@ -904,6 +1773,47 @@ fn test_dict_specializers() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn test_nested_dict_specializers() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"tier(_: {metadata: {tier: "prod"}}, "prod");
+           tier(_: {metadata: {tier: "dev"}}, "dev");
+           tier(_: {metadata: {owner: {team: "infra"}, tier: t}}, t);"#,
+    )?;
+    qvar(
+        &p,
+        r#"tier({metadata: {tier: "prod", x: 1}, y: 2}, t)"#,
+        "t",
+        values!["prod"],
+    );
+    qvar(
+        &p,
+        r#"tier({metadata: {tier: "dev"}}, t)"#,
+        "t",
+        values!["dev"],
+    );
+    qvar(
+        &p,
+        r#"tier({metadata: {tier: "dev", owner: {team: "infra"}}}, t)"#,
+        "t",
+        values!["dev", "dev"],
+    );
+    qnull(&p, r#"tier({metadata: {tier: "test"}}, _)"#);
+    qnull(&p, r#"tier({metadata: {tier: 1}}, _)"#);
+    qnull(&p, r#"tier({metadata: 1}, _)"#);
+    qnull(&p, r#"tier({}, _)"#);
+
+    // Clauses whose specializers don't match aren't tried.
+    let mut query = p.new_query(r#"tier({metadata: {tier: "dev"}}, t)"#, false)?;
+    query.by_ref().collect::<PolarResult<Vec<_>>>()?;
+    let stats = query.choice_stats();
+    assert_eq!(stats.clauses.len(), 1);
+    assert_eq!(stats.created, 0);
+
+    Ok(())
+}
+
 #[test]
 fn test_non_instance_specializers() -> TestResult {
     let p = polar();
@@ -925,6 +1835,31 @@ fn test_non_instance_specializers() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn test_parameter_guards() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"f(x: Integer where x > 0, y where y = x + 1);
+           g(x: {active: true} where x.level >= 2 and x.level < 5);"#,
+    )?;
+    qeval(&p, "f(1, 2)");
+    qnull(&p, "f(0, 1)");
+    qnull(&p, "f(1, 3)");
+    qvar(&p, "f(2, y)", "y", values![3]);
+
+    qeval(&p, "g({active: true, level: 2})");
+    qnull(&p, "g({active: true, level: 5})");
+    qnull(&p, "g({active: false, level: 3})");
+
+    // `where` is only a keyword after a parameter.
+    let p = polar();
+    p.load_str("where(where) if where = 1; h(x) if where(x);")?;
+    qeval(&p, "h(1)");
+    qvar(&p, "where = 2", "where", values![2]);
+    assert!(p.load_str("k(x when x > 0);").is_err());
+    Ok(())
+}
+
 #[test]
 #[allow(clippy::unnecessary_wraps)]
 fn test_bindings() -> TestResult {
@@ -1075,6 +2010,40 @@ fn test_load_str_with_query() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn test_scoped_inline_queries() -> TestResult {
+    let p = polar();
+    p.load_str("f(1); ?= f(1);")?;
+    p.load_scope("a", vec![Source::new("f(2); ?= f(2); ?= f(3);")])?;
+    p.load_scope("b", vec![Source::new("f(3); ?= f(3);")])?;
+
+    let mut results = vec![];
+    while let Some(q) = p.next_inline_query(false) {
+        let scope = q.scope().map(str::to_owned);
+        results.push((scope, query_results!(q).len()));
+    }
+    assert_eq!(
+        results,
+        vec![
+            (Some("b".to_owned()), 1),
+            (Some("a".to_owned()), 1),
+            (Some("a".to_owned()), 0),
+            (None, 1),
+        ]
+    );
+
+    // Reloading or removing a scope drops the inline queries it had waiting.
+    p.load_scope("a", vec![Source::new("?= f(4);")])?;
+    p.load_scope("a", vec![Source::new("?= f(1);")])?;
+    p.load_scope("b", vec![Source::new("?= f(4);")])?;
+    assert!(p.remove_scope("b", false)?);
+    let q = p.next_inline_query(false).unwrap();
+    assert_eq!(q.scope(), Some("a"));
+    assert_eq!(query_results!(q).len(), 1);
+    assert!(p.next_inline_query(false).is_none());
+    Ok(())
+}
+
 /// Test using a constructor with positional + kwargs.
 #[test]
 fn test_make_external() -> TestResult {
@@ -1237,6 +2206,272 @@ fn test_comparisons() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn test_list_and_dict_builtins() -> TestResult {
+    let p = polar();
+    qeval(&p, "append([1, 2], 3, [1, 2, 3])");
+    qeval(&p, "concat([1], [2, 3], [1, 2, 3])");
+    qeval(&p, "concat([], [], [])");
+    qeval(&p, "slice([1, 2, 3, 4], 1, 3, [2, 3])");
+    qeval(&p, "slice([1, 2, 3, 4], -2, 10, [3, 4])");
+    qeval(&p, "slice([1, 2, 3, 4], 3, 1, [])");
+    qeval(&p, r#"keys({b: 1, a: 2}, ["a", "b"])"#);
+    qeval(&p, "values({b: 1, a: 2}, [2, 1])");
+    qeval(&p, "get_or({a: 1}, \"a\", 0, 1)");
+    qeval(&p, "get_or({a: 1}, \"b\", 0, 0)");
+    qeval(&p, "merge({a: 1, b: 2}, {b: 3, c: 4}, {a: 1, b: 3, c: 4})");
+    qnull(&p, "append([1], 2, [1])");
+
+    qruntime!(
+        "append(1, 2, x)",
+        TypeError { msg, .. },
+        msg.starts_with("append expects a list")
+    );
+    qruntime!("keys(x, y)", TypeError { .. });
+    qruntime!(r#"get_or({}, 1, 0, x)"#, TypeError { .. });
+
+    // Builtins may be called from policies, and rules with the same name take precedence.
+    p.load_str(
+        r#"roles(user, roles) if get_or(user, "roles", [], roles);
+           keys(_, "overridden");"#,
+    )?;
+    qeval(&p, r#"roles({roles: ["admin"]}, ["admin"])"#);
+    qeval(&p, "roles({}, [])");
+    qvar(&p, "keys({a: 1}, x)", "x", values!["overridden"]);
+    Ok(())
+}
+
+#[test]
+fn test_conversion_builtins() -> TestResult {
+    let p = polar();
+    qeval(
+        &p,
+        r#"to_string(12, "12") and to_string(1.5, "1.5") and to_string(true, "true")"#,
+    );
+    qeval(&p, r#"to_string("a", "a")"#);
+    qvar(&p, r#"to_int("42", x)"#, "x", values![42]);
+    qvar(&p, "to_int(3.0, x)", "x", values![3]);
+    qvar(&p, r#"to_float("2.5", x)"#, "x", values![2.5]);
+    qvar(&p, "to_float(2, x)", "x", values![2.0]);
+    qnull(&p, r#"to_int("4.2", _x)"#);
+    qnull(&p, r#"to_float("abc", _x)"#);
+    qvar(&p, r#"to_int("abc", x) or x = 0"#, "x", values![0]);
+    qruntime!("to_int(4.2, x)", TypeError { .. });
+    qruntime!("to_string([1], x)", TypeError { .. });
+    qruntime!("to_float(x, y)", TypeError { .. });
+
+    qvar(
+        &p,
+        r#"parse_datetime("1970-01-01T00:00:00Z", x)"#,
+        "x",
+        values![0],
+    );
+    qvar(
+        &p,
+        r#"parse_datetime("2021-06-01", x)"#,
+        "x",
+        values![1622505600],
+    );
+    qvar(
+        &p,
+        r#"parse_datetime("2021-06-01T14:30:00+02:00", x)"#,
+        "x",
+        values![1622550600],
+    );
+    qvar(
+        &p,
+        r#"parse_datetime("2000-02-29T00:00:00.25Z", x)"#,
+        "x",
+        values![951782400.25],
+    );
+    qeval(
+        &p,
+        r#"parse_datetime("2021-06-01T00:00:00Z", a) and parse_datetime("2021-05-31T23:00:00-02:00", b) and a < b"#,
+    );
+    qnull(&p, r#"parse_datetime("2021-02-29", _x)"#);
+    qnull(&p, r#"parse_datetime("2021-06-01T25:00:00Z", _x)"#);
+    qnull(&p, r#"parse_datetime("2021-06-01T12:00:00", _x)"#);
+    qruntime!("parse_datetime(1, x)", TypeError { .. });
+
+    // Conversions make values of different types comparable.
+    p.load_str(r#"owns(actor, resource) if to_int(resource.owner_id, actor.id);"#)?;
+    qeval(&p, r#"owns({id: 7}, {owner_id: "7"})"#);
+    qnull(&p, r#"owns({id: 7}, {owner_id: "8"})"#);
+    Ok(())
+}
+
+#[test]
+fn test_safe_lookups() -> TestResult {
+    let p = polar();
+    qeval(&p, r#"get({a: {b: 1}}, "a.b", 0, 1)"#);
+    qeval(&p, r#"get({a: {b: 1}}, ["a", "b"], 0, 1)"#);
+    qeval(&p, r#"get({a: {}}, "a.b.c", 0, 0)"#);
+    qeval(&p, r#"get({}, "a", 0, 0)"#);
+    qeval(&p, r#"get({a: 1}, [], 0, {a: 1})"#);
+    qruntime!(r#"get({}, 1, 0, x)"#, TypeError { .. });
+
+    // Without a `nil` constant, safe navigation on missing fields fails.
+    qeval(&p, "{a: {b: 1}}.?a.?b = 1");
+    qnull(&p, "{a: {}}.?a.?b = _x");
+
+    let nil = term!(Value::ExternalInstance(ExternalInstance {
+        instance_id: 1,
+        constructor: None,
+        repr: None,
+        class_repr: None,
+        class_id: None,
+    }));
+    p.kb.write()
+        .unwrap()
+        .register_constant(sym!("nil"), nil.clone())?;
+    qvar(&p, "{a: {}}.?a.?b.?c = x", "x", vec![nil.value().clone()]);
+    qvar(&p, r#"get({a: nil}, "a.b", 0, x)"#, "x", values![0]);
+
+    p.load_str(r#"owner_id(resource, id) if id = resource.?owner.?id;"#)?;
+    qvar(&p, "owner_id({owner: {id: 1}}, id)", "id", values![1]);
+    qvar(&p, "owner_id({}, id)", "id", vec![nil.value().clone()]);
+    Ok(())
+}
+
+#[test]
+fn test_nil_checks() -> TestResult {
+    let p = polar();
+    // Without a `nil` constant, every bound value is defined.
+    qeval(&p, "is_defined(1)");
+    qnull(&p, "is_nil({})");
+    qnull(&p, "is_nil(_x)");
+    qnull(&p, "is_defined(_x)");
+
+    let nil = term!(Value::ExternalInstance(ExternalInstance {
+        instance_id: 1,
+        constructor: None,
+        repr: None,
+        class_repr: None,
+        class_id: None,
+    }));
+    p.kb.write().unwrap().register_constant(sym!("nil"), nil)?;
+    qeval(&p, "is_nil(nil)");
+    qnull(&p, "is_defined(nil)");
+    qeval(&p, "x = nil and is_nil(x)");
+    qeval(&p, "is_defined(0) and is_defined([]) and is_defined(false)");
+    qnull(&p, "is_nil(false)");
+    qeval(&p, "is_nil({a: {}}.?a.?b)");
+
+    p.load_str(
+        "owner(resource, owner) if is_defined(resource.?owner) and owner = resource.owner;",
+    )?;
+    qvar(&p, r#"owner({owner: "alice"}, x)"#, "x", values!["alice"]);
+    qnull(&p, "owner({}, _x)");
+    qnull(&p, "owner({owner: nil}, _x)");
+    Ok(())
+}
+
+#[test]
+fn test_try() -> TestResult {
+    let p = polar();
+    qvar(&p, "try x = 1 else x = 2", "x", values![1]);
+    qnull(&p, "try 1 = 2 else true");
+    // Only the first solution of the body is used.
+    qvar(&p, "try x in [1, 2] else x = 0", "x", values![1]);
+    // Hard failures aren't caught.
+    qruntime!("try keys(1, x) else x = []", TypeError { .. });
+
+    let profile = term!(Value::ExternalInstance(ExternalInstance {
+        instance_id: 1,
+        constructor: None,
+        repr: None,
+        class_repr: None,
+        class_id: None,
+    }));
+    p.kb.write()
+        .unwrap()
+        .register_constant(sym!("profile"), profile)?;
+    let mut q = p.new_query(
+        r#"y = 1 and try (y = 2 or x = profile.name) else x = "anonymous""#,
+        false,
+    )?;
+    match q.next_event()? {
+        QueryEvent::ExternalCall { call_id, .. } => {
+            q.application_error("no name".to_owned())?;
+            q.call_result(call_id, None)?;
+        }
+        event => panic!("unexpected event: {:?}", event),
+    }
+    match q.next_event()? {
+        QueryEvent::Result { bindings, .. } => {
+            assert_eq!(bindings[&sym!("x")], term!("anonymous"));
+            assert_eq!(bindings[&sym!("y")], term!(1));
+        }
+        event => panic!("unexpected event: {:?}", event),
+    }
+    assert!(matches!(q.next_event()?, QueryEvent::Done { .. }));
+
+    // Errors after the body aren't caught.
+    let mut q = p.new_query("try true else true and x = profile.name", false)?;
+    match q.next_event()? {
+        QueryEvent::ExternalCall { call_id, .. } => {
+            q.application_error("no name".to_owned())?;
+            q.call_result(call_id, None)?;
+        }
+        event => panic!("unexpected event: {:?}", event),
+    }
+    assert!(matches!(
+        q.next_event().map_err(|e| e.0),
+        Err(ErrorKind::Runtime(RuntimeError::Application { .. }))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_lambdas() -> TestResult {
+    let p = polar();
+    qvar(
+        &p,
+        "filter([1, 2, 3, 4], |x| x > 2, result)",
+        "result",
+        vec![value!([3, 4])],
+    );
+    qvar(
+        &p,
+        "filter([1, 2], |x| x > 2, result)",
+        "result",
+        vec![value!([])],
+    );
+    qeval(&p, "any([1, 2, 3], |x| x = 2)");
+    qnull(&p, "any([], |_x| true)");
+    qeval(&p, "all([1, 2, 3], |x| x > 0)");
+    qnull(&p, "all([1, 2, 3], |x| x > 1)");
+    qeval(&p, "all([], |_x| false)");
+
+    // Unbound variables from the enclosing scope are fresh in each application.
+    qeval(&p, "all([{a: 1}, {a: 2}], |x| x.a = y) and y = 3");
+
+    // Lambdas capture bound variables, and may be passed to rules.
+    p.load_str(
+        r#"owned(actor, docs, owned) if filter(docs, |doc| doc.owner = actor, owned);
+           owns_all(actor, docs) if all(docs, |doc| doc.owner = actor);"#,
+    )?;
+    qvar(
+        &p,
+        r#"owned("alice", [{owner: "alice", id: 1}, {owner: "bob", id: 2}], owned)"#,
+        "owned",
+        vec![value!([value!(btreemap! {
+            sym!("owner") => term!("alice"),
+            sym!("id") => term!(1),
+        })])],
+    );
+    qeval(&p, r#"owns_all("bob", [{owner: "bob"}, {owner: "bob"}])"#);
+    qnull(&p, r#"owns_all("bob", [{owner: "bob"}, {owner: "alice"}])"#);
+
+    qruntime!(
+        "filter(1, |x| x, result)",
+        TypeError { msg, .. },
+        msg.starts_with("filter expects a list and a lambda")
+    );
+    qruntime!("any([1], |x, y| x = y)", TypeError { .. });
+    Ok(())
+}
+
 #[test]
 fn test_modulo_and_remainder() {
     let p = polar();
@@ -1311,6 +2546,23 @@ fn test_arithmetic() -> TestResult {
 
     qruntime!("9223372036854775807 + 1 > 0", ArithmeticError { .. });
     qruntime!("-9223372036854775807 - 2 < 0", ArithmeticError { .. });
+    qruntime!("1 mod 0 = x", ArithmeticError { .. });
+
+    let saturating = polar();
+    saturating.set_integer_overflow(polar_core::terms::IntegerOverflow::Saturate);
+    qvar(
+        &saturating,
+        "x = 9223372036854775807 + 1",
+        "x",
+        values![i64::MAX],
+    );
+    qvar(
+        &saturating,
+        "x = -9223372036854775807 * 2",
+        "x",
+        values![i64::MIN],
+    );
+    qruntime!(&saturating, "1 rem 0 = x", ArithmeticError { .. });
 
     // x / 0 = ∞
     qvar(&p, "x=1/0", "x", values![f64::INFINITY]);
@@ -1731,6 +2983,57 @@ has_role(user: User, "owner", repository: Repository) if
     Ok(())
 }
 
+#[test]
+fn test_invalid_rule_suggested_fix() -> TestResult {
+    let p = polar();
+    let policy = r#"type f(x: Integer, y: {kind: "a"});
+                    type f(x: String, y: {kind: "b"});
+                    f(x, y: {kind: "a", n: 1}) if x = y.n;"#;
+    let fix = match p.load_str(policy).unwrap_err().0 {
+        ErrorKind::Validation(InvalidRule {
+            fix: Some(fix),
+            msg,
+            ..
+        }) => {
+            assert!(msg.contains("Perhaps you meant:"), "{}", msg);
+            fix
+        }
+        e => panic!("unexpected error: {}", e),
+    };
+
+    // Of the rule types, the first takes the fewest edits.
+    assert_eq!(fix.rule_type, r#"f(x: Integer{}, y: {kind: "a"})"#);
+    assert_eq!(fix.head, r#"f(x: Integer{}, y: {kind: "a", n: 1})"#);
+    assert_eq!(fix.params.len(), 1);
+    let param = &fix.params[0];
+    assert_eq!(param.index, 1);
+    assert_eq!(
+        param.edit,
+        ParamEdit::Specializer {
+            from: None,
+            to: "Integer{}".to_owned()
+        }
+    );
+    assert_eq!(param.replacement, "x: Integer{}");
+    let (left, right) = param.span.unwrap();
+    assert_eq!(&policy[left..right], "x");
+
+    let policy = r#"type f(x: {kind: "a", id: 1});
+                    f(_x: {kind: "b"});"#;
+    let fix = match polar().load_str(policy).unwrap_err().0 {
+        ErrorKind::Validation(InvalidRule { fix: Some(fix), .. }) => fix,
+        e => panic!("unexpected error: {}", e),
+    };
+    assert_eq!(fix.head, r#"f(_x: {id: 1, kind: "a"})"#);
+    assert_eq!(
+        fix.params[0].edit,
+        ParamEdit::Fields {
+            fields: vec!["id".to_owned(), "kind".to_owned()]
+        }
+    );
+    Ok(())
+}
+
 #[test]
 fn test_and_or_warning() -> TestResult {
     let p = polar();
@@ -2765,3 +4068,415 @@ fn test_internal_isa_check() -> TestResult {
     assert_eq!(results.len(), 1);
     Ok(())
 }
+
+#[test]
+fn test_fact_source() -> TestResult {
+    let p = polar();
+    p.register_fact_source(sym!("has_role"))?;
+    p.load_str(r#"allow(actor, "read", resource) if has_role(actor, "member", resource);"#)?;
+
+    // Answer `has_role` from a fixed set of tuples, filtering on ground arguments.
+    let tuples = [
+        values!["alice", "member", "repo1"],
+        values!["alice", "owner", "repo2"],
+        values!["bob", "member", "repo2"],
+    ];
+    let answers = |query: &str| {
+        let mut query = p.new_query(query, false).unwrap();
+        let mut streams: HashMap<u64, std::vec::IntoIter<Vec<Value>>> = HashMap::new();
+        let mut results = vec![];
+        loop {
+            match query.next_event().unwrap() {
+                QueryEvent::Done { .. } => break,
+                QueryEvent::Result { bindings, .. } => results.push(bindings),
+                QueryEvent::ExternalFacts {
+                    call_id,
+                    name,
+                    args,
+                } => {
+                    assert_eq!(name, sym!("has_role"));
+                    let stream = streams.entry(call_id).or_insert_with(|| {
+                        tuples
+                            .iter()
+                            .filter(|tuple| {
+                                tuple.iter().zip(args.iter()).all(|(v, arg)| {
+                                    matches!(arg.value(), Value::Variable(_)) || arg.value() == v
+                                })
+                            })
+                            .cloned()
+                            .collect::<Vec<_>>()
+                            .into_iter()
+                    });
+                    let answer = stream.next().map(|tuple| {
+                        Term::new_from_test(Value::List(
+                            tuple.into_iter().map(Term::new_from_test).collect(),
+                        ))
+                    });
+                    query.call_result(call_id, answer).unwrap();
+                }
+                e => panic!("unexpected event: {:?}", e),
+            }
+        }
+        results
+    };
+
+    assert_eq!(answers(r#"allow("alice", "read", "repo1")"#).len(), 1);
+    assert!(answers(r#"allow("alice", "read", "repo2")"#).is_empty());
+    let results = answers(r#"has_role(who, "member", resource)"#);
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0][&sym!("who")], term!("alice"));
+    assert_eq!(results[1][&sym!("resource")], term!("repo2"));
+    Ok(())
+}
+
+#[test]
+fn test_fact_source_satisfies_validation() -> TestResult {
+    let p = polar();
+    qvalidation!(p, "f() if g();", UndefinedRuleCall { .. });
+    p.clear_rules();
+    p.register_fact_source(sym!("g"))?;
+    p.load_str("f() if g();")?;
+    Ok(())
+}
+
+#[test]
+fn test_facts() -> TestResult {
+    let p = polar();
+    p.insert_fact(sym!("f"), vec![term!(1), term!("a")])?;
+    p.insert_fact(sym!("f"), vec![term!(2), term!("b")])?;
+    assert!(!p.insert_fact(sym!("f"), vec![term!(2), term!("b")])?);
+    assert!(p.insert_fact(sym!("f"), vec![term!(sym!("x"))]).is_err());
+
+    qvar(&p, "f(x, _)", "x", values![1, 2]);
+    qvar(&p, r#"f(x, "b")"#, "x", values![2]);
+    qnull(&p, "f(3, _)");
+    qnull(&p, "f(1)");
+
+    // Facts are tried before rules, and survive reloading policies.
+    p.load_str("f(3, \"c\"); g(x) if f(x, _);")?;
+    qvar(&p, "g(x)", "x", values![1, 2, 3]);
+    p.clear_rules();
+    p.load_str("g(x) if f(x, _);")?;
+    qvar(&p, "g(x)", "x", values![1, 2]);
+
+    assert!(p.delete_fact(sym!("f"), vec![term!(1), term!("a")])?);
+    assert!(!p.delete_fact(sym!("f"), vec![term!(1), term!("a")])?);
+    qvar(&p, "g(x)", "x", values![2]);
+    Ok(())
+}
+
+#[test]
+fn test_deprecated_rules() -> TestResult {
+    let p = polar();
+    p.load_str(indoc! {r#"
+        @deprecated("use g")
+        f(1);
+        f(2);
+        g(1);
+        h(x) if f(x);
+    "#})?;
+
+    let warnings = |query: &str| -> PolarResult<Vec<String>> {
+        let mut query = p.new_query(query, false)?;
+        let mut warnings = vec![];
+        loop {
+            let event = query.next_event()?;
+            while let Some(msg) = query.next_message() {
+                if let MessageKind::Warning = msg.kind {
+                    warnings.push(msg.msg);
+                }
+            }
+            if let QueryEvent::Done { .. } = event {
+                return Ok(warnings);
+            }
+        }
+    };
+
+    // Calls to other rules, or that only match the non-deprecated clause, don't warn.
+    assert!(warnings("g(1)")?.is_empty());
+    assert!(warnings("f(2)")?.is_empty());
+
+    let expected = indoc! {"
+        Call to deprecated rule f: use g at line 5, column 9:
+        \t005: h(x) if f(x);
+        \t             ^\n"};
+    assert_eq!(warnings("h(_)")?, vec![expected]);
+    // Warnings are rate-limited.
+    assert!(warnings("f(1)")?.is_empty());
+
+    // Reloading resets the limit.
+    p.clear_rules();
+    p.load_str(r#"@deprecated("no longer used") f(1);"#)?;
+    assert_eq!(warnings("f(1)")?.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_unknown_call_ids() -> TestResult {
+    let p = Polar::new();
+    let profile = term!(Value::ExternalInstance(ExternalInstance {
+        instance_id: 1,
+        constructor: None,
+        repr: None,
+        class_repr: None,
+        class_id: None,
+    }));
+    p.kb.write()
+        .unwrap()
+        .register_constant(sym!("profile"), profile)?;
+
+    let call_id = |q: &mut Query| match q.next_event() {
+        Ok(QueryEvent::ExternalCall { call_id, .. }) => call_id,
+        event => panic!("unexpected event: {:?}", event),
+    };
+    let unknown = |result: PolarResult<()>| match result.map_err(|e| e.0) {
+        Err(ErrorKind::Runtime(RuntimeError::UnknownCallId { msg, .. })) => msg,
+        result => panic!("unexpected result: {:?}", result),
+    };
+
+    // An ID from a finished query is stale.
+    let mut finished = p.new_query("x = profile.name", false)?;
+    let stale = call_id(&mut finished);
+    finished.call_result(stale, None)?;
+    assert!(matches!(finished.next_event()?, QueryEvent::Done { .. }));
+
+    let mut q = p.new_query("x = profile.name", false)?;
+    let pending = call_id(&mut q);
+    let msg = unknown(q.call_result(stale, Some(term!("alice"))));
+    assert!(msg.contains("may be from a query that has finished"));
+    assert!(unknown(q.question_result(stale, true)).contains("finished"));
+
+    // So is an ID from another Polar instance.
+    let foreign = Polar::new().get_external_id();
+    assert_ne!(foreign, pending);
+    let msg = unknown(q.call_result(foreign, None));
+    assert!(msg.contains("another Polar instance"));
+
+    // The query still takes answers to its own calls.
+    q.call_result(pending, Some(term!("alice")))?;
+    match q.next_event()? {
+        QueryEvent::Result { bindings, .. } => assert_eq!(bindings[&sym!("x")], term!("alice")),
+        event => panic!("unexpected event: {:?}", event),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_id_partitions() -> TestResult {
+    let p = Polar::new();
+    p.set_id_partition(5, 3)?;
+    let a = p.get_external_id();
+    let b = p.get_external_id();
+    assert_eq!(b, a + 1);
+    // Partitions take the high bits of IDs, which fit in 53 bits.
+    assert_eq!(a >> 50, 5);
+
+    let other = Polar::new();
+    other.set_id_partition(6, 3)?;
+    assert_eq!(other.get_external_id() >> 50, 6);
+    assert_eq!(p.id_wraps(), 0);
+
+    for (partition, bits) in [(8, 3), (0, 17)] {
+        assert!(matches!(
+            p.set_id_partition(partition, bits).map_err(|e| e.0),
+            Err(ErrorKind::Runtime(RuntimeError::InvalidIdPartition { .. }))
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_rewrite_passes() -> TestResult {
+    use polar_core::{kb::KnowledgeBase, op, rules::Rule, RewritePass};
+
+    /// Require the actor of every `allow` rule to belong to the tenant "acme".
+    struct TenantCheck;
+
+    impl RewritePass for TenantCheck {
+        fn name(&self) -> &str {
+            "tenant_check"
+        }
+
+        fn rewrite_rule(&self, mut rule: Rule, _: &KnowledgeBase) -> Rule {
+            if rule.name.as_str() != "allow" {
+                return rule;
+            }
+            let actor = rule.params[0].parameter.clone();
+            let tenant = term!(op!(Dot, actor, term!("tenant")));
+            let check = term!(op!(Unify, tenant, term!("acme")));
+            if let Value::Expression(Operation { operator, args }) = rule.body.value() {
+                let mut args = args.clone();
+                args.insert(0, check);
+                let body = Value::Expression(Operation {
+                    operator: *operator,
+                    args,
+                });
+                rule.body = rule.body.clone_with_value(body);
+            }
+            rule
+        }
+    }
+
+    let p = polar();
+    p.register_rewrite_pass(Box::new(TenantCheck));
+    p.load_str(
+        r#"allow(_actor, "read", _resource);
+           f(x) if x = 1;"#,
+    )?;
+    qeval(&p, r#"allow({tenant: "acme"}, "read", 1)"#);
+    qnull(&p, r#"allow({tenant: "globex"}, "read", 1)"#);
+    qvar(&p, "f(x)", "x", values![1]);
+
+    p.load_scope("a", vec![Source::new(r#"allow(_actor, "write", _);"#)])?;
+    let mut q = p.new_query(r#"allow({tenant: "globex"}, "write", 1)"#, false)?;
+    q.set_scope(Some("a".to_owned()));
+    assert!(query_results!(q).is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_external_call_batch() -> TestResult {
+    let p = polar();
+    for (name, id) in [("A", 1), ("B", 2), ("C", 3)] {
+        let instance = term!(Value::ExternalInstance(ExternalInstance {
+            instance_id: id,
+            constructor: None,
+            repr: None,
+            class_repr: None,
+            class_id: None,
+        }));
+        p.register_constant(sym!(name), instance)?;
+    }
+    p.register_batched_method(sym!("score"));
+    p.load_str(
+        r#"high(x) if x in [A, B, C] and x.score(1) > 1;
+           named(x) if x in [A, B] and x.name() = "b";"#,
+    )?;
+
+    // Answer every call of `score` in one batch, with no score for `C`.
+    let mut query = p.new_query("high(x)", false)?;
+    let mut batches = 0;
+    let mut results = vec![];
+    loop {
+        match query.next_event()? {
+            QueryEvent::Done { .. } => break,
+            QueryEvent::Result { bindings, .. } => results.push(bindings[&sym!("x")].clone()),
+            QueryEvent::ExternalCallBatch {
+                call_id,
+                instances,
+                attribute,
+                args,
+            } => {
+                batches += 1;
+                assert_eq!(attribute, sym!("score"));
+                assert_eq!(args, vec![term!(1)]);
+                assert_eq!(instances.len(), 3);
+                query.call_results(call_id, vec![Some(term!(1)), Some(term!(2)), None])?;
+            }
+            QueryEvent::ExternalOp {
+                call_id,
+                operator: Operator::Gt,
+                args,
+            } => query.question_result(call_id, args[0] == term!(2))?,
+            e => panic!("unexpected event: {:?}", e),
+        }
+    }
+    assert_eq!(batches, 1);
+    assert_eq!(results.len(), 1);
+    assert!(matches!(
+        results[0].value(),
+        Value::ExternalInstance(ExternalInstance { instance_id: 2, .. })
+    ));
+
+    // Methods that aren't batched are called on each instance.
+    let mut query = p.new_query("named(x)", false)?;
+    assert!(matches!(
+        query.next_event()?,
+        QueryEvent::ExternalCall { attribute, .. } if attribute == sym!("name")
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_load_ast() -> TestResult {
+    use polar_core::parser::{parse_lines, Line};
+
+    // Parsed lines survive a trip through JSON, and load like their source.
+    let src = r#"type f(x: Dictionary);
+                 f(x: Dictionary) if x.a > 1;
+                 g(x) if f(x) or x matches {a: 1} or x = "a";
+                 ?= g("a");"#;
+    let lines = parse_lines(Source::new(src))?;
+    let json = serde_json::to_string(&lines).unwrap();
+    let lines: Vec<Line> = serde_json::from_str(&json).unwrap();
+    let p = polar();
+    p.load_ast(lines)?;
+    qeval(&p, "f({a: 2})");
+    qnull(&p, "f({a: 1})");
+    qeval(&p, "g({a: 1})");
+
+    // Tools may write lines themselves, leaving out optional fields.
+    let json = r#"[
+        {"Rule": {
+            "name": "h",
+            "params": [{"parameter": {"value": {"Variable": "x"}}}],
+            "body": {"value": {"Expression": {"operator": "And", "args": [
+                {"value": {"Expression": {"operator": "Unify", "args": [
+                    {"value": {"Variable": "x"}},
+                    {"value": {"Number": {"Integer": 1}}}]}}}]}}}
+        }},
+        {"Query": {"value": {"Call": {"name": "h", "args": [{"value": {"Number": {"Integer": 1}}}]}}}}
+    ]"#;
+    let p = polar();
+    p.load_ast(serde_json::from_str(json).unwrap())?;
+    qvar(&p, "h(x)", "x", values![1]);
+
+    // Loaded lines are validated like source.
+    let json = r#"[{"Rule": {
+        "name": "h",
+        "params": [{"parameter": {"value": {"Variable": "x"}}}],
+        "body": {"value": {"Expression": {"operator": "And", "args": []}}}
+    }}]"#;
+    let p = polar();
+    let err = p.load_ast(serde_json::from_str(json).unwrap()).unwrap_err();
+    assert!(matches!(
+        err.0,
+        ErrorKind::Validation(SingletonVariable { .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_duplicate_clauses() -> TestResult {
+    let load = |p: &Polar| {
+        p.load(vec![
+            Source::new_with_name("a.polar", "f(x) if x = 1; f(x) if x = 2;"),
+            Source::new_with_name("b.polar", "f(y) if y = 1; f(x, y) if x = y;"),
+        ])
+    };
+
+    // By default, duplicates are kept, and succeed once more.
+    let p = polar();
+    load(&p)?;
+    assert!(p.next_message().is_none());
+    qvar(&p, "f(x)", "x", values![1, 2, 1]);
+
+    let p = polar();
+    p.set_duplicate_clauses(DuplicateClauses::Warn);
+    load(&p)?;
+    let message = p.next_message().unwrap();
+    assert!(matches!(message.kind, MessageKind::Warning));
+    assert!(message
+        .msg
+        .starts_with("Clause f(y) duplicates a clause of rule f already loaded"));
+    assert!(p.next_message().is_none());
+    qvar(&p, "f(x)", "x", values![1, 2, 1]);
+
+    let p = polar();
+    p.set_duplicate_clauses(DuplicateClauses::Skip);
+    load(&p)?;
+    assert!(p.next_message().is_none());
+    qvar(&p, "f(x)", "x", values![1, 2]);
+    qeval(&p, "f(1, 1)");
+    Ok(())
+}
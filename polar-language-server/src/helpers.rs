@@ -1,6 +1,6 @@
 use std::collections::{BTreeMap, HashSet};
 
-use lsp_types::{Position, PublishDiagnosticsParams, Range, TextDocumentItem, Url};
+use lsp_types::{Position, PublishDiagnosticsParams, Range, TextDocumentItem, TextEdit, Url};
 use polar_core::diagnostic::Diagnostic;
 use serde::Serialize;
 use wasm_bindgen::prelude::*;
@@ -40,6 +40,40 @@ pub(crate) fn range_from_polar_diagnostic_context(diagnostic: &Diagnostic) -> Ra
         .unwrap_or_default()
 }
 
+/// The suggested fix of an invalid rule as a quick fix: a title and the edits of the rule's
+/// document, e.g., `{"title": "specialize parameter 1 on `User{}`", "edits": [..]}`, for the
+/// client to offer as a code action.
+pub(crate) fn quick_fix_from_polar_diagnostic(
+    diagnostic: &Diagnostic,
+) -> Option<serde_json::Value> {
+    use polar_core::error::{ErrorKind::Validation, ValidationError::InvalidRule};
+    use polar_core::loc_to_pos;
+
+    let (fix, context) = match diagnostic {
+        Diagnostic::Error(e) => match (&e.0, diagnostic.get_context()) {
+            (Validation(InvalidRule { fix: Some(fix), .. }), Some(context)) => (fix, context),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let position = |loc| {
+        let (row, column) = loc_to_pos(&context.source.src, loc);
+        Position::new(row as _, column as _)
+    };
+    let edits = fix
+        .params
+        .iter()
+        .map(|param| {
+            let (left, right) = param.span?;
+            Some(TextEdit::new(
+                Range::new(position(left), position(right)),
+                param.replacement.clone(),
+            ))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(serde_json::json!({ "title": fix.describe(), "edits": edits }))
+}
+
 pub(crate) fn uri_from_polar_diagnostic_context(diagnostic: &Diagnostic) -> Option<Url> {
     if let Some(context) = diagnostic.get_context() {
         if let Some(filename) = context.source.filename.as_ref() {
@@ -17,8 +17,9 @@ use wasm_bindgen::prelude::*;
 
 mod helpers;
 use helpers::{
-    empty_diagnostics_for_doc, log, range_from_polar_diagnostic_context, unique_extensions,
-    uri_from_polar_diagnostic_context, Diagnostics, Documents, LspEvent,
+    empty_diagnostics_for_doc, log, quick_fix_from_polar_diagnostic,
+    range_from_polar_diagnostic_context, unique_extensions, uri_from_polar_diagnostic_context,
+    Diagnostics, Documents, LspEvent,
 };
 
 #[wasm_bindgen]
@@ -402,6 +403,7 @@ impl PolarLanguageServer {
                     }
                 }
                 Line::RuleType(_) => event.policy_stats.rule_types += 1,
+                Line::TypeAlias { .. } | Line::Default { .. } => (),
                 Line::Rule(_) => {
                     event.policy_stats.longhand_rules += 1;
                     event.policy_stats.total_rules += 1;
@@ -492,6 +494,7 @@ impl PolarLanguageServer {
                     severity: Some(severity),
                     source: Some("Polar Language Server".to_owned()),
                     message: message.clone(),
+                    data: quick_fix_from_polar_diagnostic(&diagnostic),
                     ..Default::default()
                 };
                 (doc, diagnostic)